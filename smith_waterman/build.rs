@@ -1,6 +1,27 @@
 use std::env;
 
 fn main() {
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+
+    // The wgpu backend (wgpu_align.rs) is always built on macOS, where wgpu
+    // is a plain dependency, and elsewhere only with --features wgpu
+    println!("cargo:rustc-check-cfg=cfg(wgpu_backend)");
+    if target_os == "macos" || env::var_os("CARGO_FEATURE_WGPU").is_some() {
+        println!("cargo:rustc-cfg=wgpu_backend");
+    }
+
+    // macOS ships OpenCL as a system framework (Apple Silicon included)
+    if target_os == "macos" {
+        println!("cargo:rustc-link-lib=framework=OpenCL");
+    } else {
+        link_opencl_library();
+    }
+    
+    // Re-run if OpenCL kernel source changes
+    println!("cargo:rerun-if-changed=src/smith_waterman.cl");
+}
+
+fn link_opencl_library() {
     // Check for OpenCL development libraries
     let opencl_paths = [
         "/usr/lib/x86_64-linux-gnu",
@@ -16,7 +37,4 @@ fn main() {
     
     // Link OpenCL library
     println!("cargo:rustc-link-lib=dylib=OpenCL");
-    
-    // Re-run if OpenCL kernel source changes
-    println!("cargo:rerun-if-changed=src/smith_waterman.cl");
-} 
\ No newline at end of file
+}
@@ -0,0 +1,74 @@
+// A ChunkProcessor of your own on the Pipeline, next to a built-in one: GC
+// content and read lengths per file, counted in the same pass as the k-mer
+// spectrum
+//   cargo run --release --example custom_processor
+//   cargo run --release --example custom_processor -- reads.fq.gz more.fq
+// The files default to the bundled paired reads. The spectrum is written to
+// kmer_spectrum.tsv in the system temp directory
+
+use rustseq_mini::chunking::ChunkSize;
+use rustseq_mini::exit_codes::{self, ExitKind};
+use rustseq_mini::kmer_hash::HashKind;
+use rustseq_mini::kmer_spectrum::{self, DEFAULT_KMER};
+use rustseq_mini::pipeline::{ChunkProcessor, Pipeline};
+use rustseq_mini::ReadChunk;
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+
+// Totals of the current file, and the files finished so far
+#[derive(Default)]
+struct GcContent {
+    reads: usize,
+    bases: usize,
+    gc: usize,
+    longest: usize,
+    files: usize,
+}
+
+impl ChunkProcessor for GcContent {
+    fn name(&self) -> &str {
+        "GC content"
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        (self.reads, self.bases, self.gc, self.longest) = (0, 0, 0, 0);
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        for read in chunk.iter() {
+            self.reads += 1;
+            self.bases += read.len();
+            self.gc += read.iter().filter(|&&base| matches!(base, b'G' | b'C' | b'g' | b'c')).count();
+            self.longest = self.longest.max(read.len());
+        }
+        Ok(())
+    }
+
+    fn finish_file(&mut self, _file: &str) -> Result<String, String> {
+        self.files += 1;
+        Ok(format!("{} reads, {} bases, {:.1}% GC, longest read {}",
+                   self.reads, self.bases, self.gc as f64 / self.bases.max(1) as f64 * 100.0, self.longest))
+    }
+
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        Ok(Some(format!("{} files", self.files)))
+    }
+}
+
+fn main() {
+    let mut files: Vec<String> = std::env::args().skip(1).collect();
+    if files.is_empty() {
+        files = vec![format!("{}/reads_R1.fastq", DATA), format!("{}/reads_R2.fastq", DATA)];
+    }
+    let output = std::env::temp_dir().join("kmer_spectrum.tsv");
+    let spectrum = kmer_spectrum::processor(HashKind::default(), DEFAULT_KMER, 1, output)
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+
+    // Processors see each chunk in registration order
+    let mut pipeline = Pipeline::new(ChunkSize::Reads(10_000));
+    pipeline.register(Box::new(GcContent::default())).register(spectrum);
+    if let Err(e) = pipeline.run(&files) {
+        exit_codes::fail(ExitKind::DataError, &e);
+    }
+}
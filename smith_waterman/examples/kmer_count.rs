@@ -0,0 +1,37 @@
+// K-mer counting through the library: the k-mer spectrum of `analyze
+// --kmer-spectrum` on a Pipeline, which reads each file once
+//   cargo run --release --example kmer_count
+//   cargo run --release --example kmer_count -- 15 spectrum.tsv reads.fq.gz
+// Arguments, all optional: k (21), the output TSV (kmer_spectrum.tsv in the
+// system temp directory) and the files (the bundled paired reads). Every k-mer
+// is counted (scale 1); large inputs want a scale as in the CLI's --kmer-scale
+
+use rustseq_mini::chunking::ChunkSize;
+use rustseq_mini::exit_codes::{self, ExitKind};
+use rustseq_mini::kmer_hash::HashKind;
+use rustseq_mini::kmer_spectrum::{self, DEFAULT_KMER};
+use rustseq_mini::pipeline::Pipeline;
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let k = match args.next().map(|k| k.parse::<usize>()) {
+        None => DEFAULT_KMER,
+        Some(Ok(k)) => k,
+        Some(Err(_)) => exit_codes::fail(ExitKind::ConfigError, "k must be a whole number: kmer_count [K [OUTPUT [FILE...]]]"),
+    };
+    let output = args.next().unwrap_or_else(|| std::env::temp_dir().join("kmer_spectrum.tsv").to_string_lossy().into_owned());
+    let mut files: Vec<String> = args.collect();
+    if files.is_empty() {
+        files = vec![format!("{}/reads_R1.fastq", DATA), format!("{}/reads_R2.fastq", DATA)];
+    }
+
+    let processor = kmer_spectrum::processor(HashKind::default(), k, 1, &output)
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    let mut pipeline = Pipeline::new(ChunkSize::Reads(10_000));
+    pipeline.register(processor);
+    if let Err(e) = pipeline.run(&files) {
+        exit_codes::fail(ExitKind::DataError, &e);
+    }
+}
@@ -0,0 +1,47 @@
+// Pairwise alignment through the library: two sequences, then a FASTQ file
+// against a FASTA target, with a score per contig
+//   cargo run --release --example pairwise_align
+//   cargo run --release --example pairwise_align -- ACGTTGCAAC ACGTAGCAAC
+//   cargo run --release --example pairwise_align -- --cpu
+// The aligner runs on the first GPU when there is one and on the SIMD CPU path
+// otherwise (or with --cpu), with the default scores. The files are the
+// bundled dataset in examples/data
+
+use rustseq_mini::chunking::ChunkSize;
+use rustseq_mini::exit_codes::{self, ExitKind};
+use rustseq_mini::{gpu, Aligner, Backend};
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let cpu = args.iter().any(|arg| arg == "--cpu");
+    args.retain(|arg| arg != "--cpu");
+    let backend = if cpu || !gpu::is_gpu_available() { Backend::Cpu } else { Backend::Gpu };
+    let (seq1, seq2) = match args.as_slice() {
+        [] => ("ACGTTGCAACGGTACCTTAG".to_string(), "ACGTAGCAACGGTTCCTTAG".to_string()),
+        [seq1, seq2] => (seq1.clone(), seq2.clone()),
+        _ => exit_codes::fail(ExitKind::ConfigError, "expected no sequences or two: pairwise_align [--cpu] [SEQ1 SEQ2]"),
+    };
+    if let Err(e) = run(backend, &seq1, &seq2) {
+        exit_codes::fail(ExitKind::DataError, &e);
+    }
+}
+
+fn run(backend: Backend, seq1: &str, seq2: &str) -> Result<(), String> {
+    // 8 reads per chunk, so the bundled reads are a few chunks against each contig window
+    let aligner = Aligner::builder().backend(backend).chunking(ChunkSize::Reads(8)).build()?;
+    println!("Aligning on {}", aligner.device_name());
+
+    let score = aligner.align(seq1.as_bytes(), seq2.as_bytes())?;
+    println!("{} vs {}: score {}", seq1, seq2, score);
+
+    let (reads, reference) = (format!("{}/reads_R1.fastq", DATA), format!("{}/reference.fa", DATA));
+    let result = aligner.align_files(&reads, &reference)?;
+    println!("{} vs {}: score {} in {:.1} ms", reads, reference, result.score, result.processing_time_ms);
+    for contig in &result.contigs {
+        println!("  {} ({} bases): score {}, best {} at {}-{} (query chunk {})",
+                 contig.contig, contig.length, contig.score, contig.best_score, contig.best_start, contig.best_end, contig.best_query_chunk);
+    }
+    Ok(())
+}
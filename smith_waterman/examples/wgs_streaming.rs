@@ -0,0 +1,53 @@
+// A WGS run through the library on the bundled paired reads, streamed in
+// chunks with progress as files and chunks finish
+//   cargo run --release --example wgs_streaming
+//   cargo run --release --example wgs_streaming -- /tmp/wgs_example
+// Results, checkpoints and logs go under the given directory (by default
+// wgs_example in the system temp directory), as with --output-dir. WGS runs
+// need the GPU backend, so without a GPU this exits 3 like --full-wgs
+
+use rustseq_mini::aggregation;
+use rustseq_mini::chunking::ChunkSize;
+use rustseq_mini::exit_codes::{self, ExitKind};
+use rustseq_mini::output_layout;
+use rustseq_mini::wgs_config::WgsConfig;
+use rustseq_mini::{gpu, Aligner, Backend, CancellationToken, Progress, ProgressStage};
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+
+fn main() {
+    let output_dir = std::env::args().nth(1)
+        .unwrap_or_else(|| std::env::temp_dir().join("wgs_example").to_string_lossy().into_owned());
+    if !gpu::is_gpu_available() {
+        exit_codes::fail(ExitKind::NoDevice, "WGS runs need a GPU and no compatible gpu was found");
+    }
+    if let Err(e) = output_layout::install(&output_dir) {
+        exit_codes::fail(ExitKind::ConfigError, &e);
+    }
+    if let Err(e) = run() {
+        exit_codes::fail(ExitKind::DataError, &e);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let aligner = Aligner::builder().backend(Backend::Gpu).chunking(ChunkSize::Reads(8)).build()?;
+    let mut config = WgsConfig::new(DATA, "example");
+    // Bare names are resolved against the data directory
+    config.input_files = Some(vec!["reads_R1.fastq".to_string(), "reads_R2.fastq".to_string()]);
+    config.aggregates = vec!["max".to_string(), "mean".to_string()];
+    aggregation::validate(&config.aggregates)?;
+
+    let report = aligner.run_wgs_with(&config, &CancellationToken::new(), &mut |progress: Progress| match progress.stage {
+        ProgressStage::FileFinished => println!("[progress] file {} of {}: {} reads, score {} after {:.2} s",
+                                                progress.file_index + 1, progress.total_files, progress.reads, progress.score,
+                                                progress.elapsed.as_secs_f64()),
+        ProgressStage::Chunk => println!("[progress] chunk {} of file {}: score {} so far", progress.chunks, progress.file_index + 1, progress.score),
+        _ => {},
+    })?;
+
+    for file in &report.files {
+        println!("{}: {:?}, {} reads, {} bases, score {}, aggregates {}",
+                 file.name(), file.status, file.total_reads, file.total_bases, file.score, aggregation::summary(&file.aggregates));
+    }
+    Ok(())
+}
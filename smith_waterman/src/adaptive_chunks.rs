@@ -0,0 +1,273 @@
+// --chunk-size auto: chunk size adapted during a WGS run instead of hand-tuned
+// Each file starts at a conservative size (chunking::ADAPTIVE_START_*). Every
+// WINDOW launches, the slowest kernel of the window is compared with the
+// latency target (ADAPTIVE_CHUNK_TARGET_MS, default 250 ms): the size doubles
+// while that stays under half the target, so the next size should still meet
+// it, and halves once it's over. Host RAM or GPU memory above MEMORY_PRESSURE
+// of the total (from the metrics sampler) halves it too. The size stays between
+// a floor and the largest launch the device takes
+// The controller and the size it sets are the run's (run_context.rs): another
+// run in the process adapts, or stops adapting, on its own. Readers pick the
+// size up through ChunkSize::is_full on the run's threads, in the unit the run
+// adapts in, so chunks already read keep the size they were cut at. Starting every file from the same size
+// keeps its first chunk identical between runs, which --resume re-verifies;
+// later chunk boundaries follow the timing, so scores of auto runs aren't
+// comparable run to run. Every change is kept for the benchmark results
+
+use crate::chunking::ChunkSize;
+use crate::run_context;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const DEFAULT_TARGET_MS: f64 = 250.0;
+// Launches between decisions
+const WINDOW: usize = 4;
+const MEMORY_PRESSURE: f64 = 0.9;
+// Changes kept for the results
+const MAX_STEPS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkAdaptation {
+    // 1-based file and launch within the file the change was decided after
+    pub file: usize,
+    pub launch: usize,
+    pub from: usize,
+    pub to: usize,
+    // Slowest kernel of the window
+    pub kernel_ms: f64,
+    // "latency" or "memory"
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkAdaptationSummary {
+    pub enabled: bool,
+    pub unit: String,
+    pub start: usize,
+    pub min: usize,
+    pub max: usize,
+    pub target_ms: f64,
+    // Largest size reached, and the size at the end of the run
+    pub largest: usize,
+    pub last: usize,
+    pub steps: Vec<ChunkAdaptation>,
+}
+
+impl ChunkAdaptationSummary {
+    pub fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+        println!("Adaptive chunk size ({} ms target): {} to {} {}, reached {}, ended at {}, {} changes",
+                 self.target_ms, self.min, self.max, self.unit, self.largest, self.last, self.steps.len());
+    }
+}
+
+struct Controller {
+    // The size each file starts at, and the unit the run adapts in
+    start: ChunkSize,
+    summary: ChunkAdaptationSummary,
+    file: usize,
+    launches: usize,
+    window_max_ms: f64,
+    window_launches: usize,
+}
+
+// One run's adaptation
+#[derive(Default)]
+pub struct Adaptation {
+    // Size readers cut chunks at, shifted left one with the low bit set for
+    // bases; 0 when not adapting
+    limit: AtomicUsize,
+    controller: Mutex<Option<Controller>>,
+}
+
+impl Adaptation {
+    fn set_limit(&self, size: Option<ChunkSize>) {
+        let encoded = match size {
+            Some(ChunkSize::Reads(n)) => n << 1,
+            Some(ChunkSize::Bases(n)) => n << 1 | 1,
+            None => 0,
+        };
+        self.limit.store(encoded, Ordering::SeqCst);
+    }
+
+    fn limit(&self) -> Option<ChunkSize> {
+        match self.limit.load(Ordering::Relaxed) {
+            0 => None,
+            encoded if encoded & 1 == 1 => Some(ChunkSize::Bases(encoded >> 1)),
+            encoded => Some(ChunkSize::Reads(encoded >> 1)),
+        }
+    }
+}
+
+// ADAPTIVE_CHUNK_TARGET_MS from .env
+pub fn target_ms_from_env() -> Result<f64, String> {
+    match std::env::var("ADAPTIVE_CHUNK_TARGET_MS") {
+        Ok(value) => match value.trim().parse::<f64>() {
+            Ok(ms) if ms > 0.0 => Ok(ms),
+            _ => Err(format!("Invalid ADAPTIVE_CHUNK_TARGET_MS '{}': expected milliseconds > 0", value)),
+        },
+        Err(_) => Ok(DEFAULT_TARGET_MS),
+    }
+}
+
+// Start adapting the current run's chunks from `start`, up to
+// `max_launch_bases` per launch
+pub fn start(start: ChunkSize, max_launch_bases: usize, target_ms: f64) {
+    let (min, max) = match start {
+        ChunkSize::Bases(n) => ((n / 4).max(1), max_launch_bases.max(n)),
+        ChunkSize::Reads(n) => ((n / 4).max(1), (max_launch_bases / crate::chunking::TYPICAL_READ_LENGTH).max(n)),
+    };
+    let summary = ChunkAdaptationSummary {
+        enabled: true, unit: start.unit().to_string(), start: start.value(), min, max, target_ms,
+        largest: start.value(), last: start.value(), steps: Vec::new(),
+    };
+    run_context::with_state(|state| {
+        if let Ok(mut controller) = state.chunks.controller.lock() {
+            *controller = Some(Controller { start, summary, file: 0, launches: 0, window_max_ms: 0.0, window_launches: 0 });
+        }
+        state.chunks.set_limit(Some(start));
+    });
+}
+
+// Back to the starting size for file `file_index`
+pub fn start_file(file_index: usize) {
+    run_context::with_state(|state| {
+        let Ok(mut controller) = state.chunks.controller.lock() else { return };
+        let Some(controller) = controller.as_mut() else { return };
+        (controller.file, controller.launches, controller.window_max_ms, controller.window_launches) = (file_index + 1, 0, 0.0, 0);
+        controller.summary.last = controller.summary.start;
+        state.chunks.set_limit(Some(controller.start));
+    });
+}
+
+// Stop adapting the current run's chunks; its readers go back to their
+// configured sizes
+pub fn stop() {
+    run_context::with_state(|state| state.chunks.set_limit(None));
+}
+
+// The size the current run cuts chunks at while it adapts them
+pub fn limit() -> Option<ChunkSize> {
+    run_context::with_state(|state| state.chunks.limit())
+}
+
+// Kernel time of a finished launch of the current run
+pub fn observe(kernel_ns: u64) {
+    run_context::with_state(|state| observe_in(&state.chunks, kernel_ns));
+}
+
+fn observe_in(adaptation: &Adaptation, kernel_ns: u64) {
+    if adaptation.limit().is_none() {
+        return;
+    }
+    let Ok(mut controller) = adaptation.controller.lock() else { return };
+    let Some(controller) = controller.as_mut() else { return };
+    controller.launches += 1;
+    controller.window_launches += 1;
+    controller.window_max_ms = controller.window_max_ms.max(kernel_ns as f64 / 1e6);
+    if controller.window_launches < WINDOW {
+        return;
+    }
+
+    let summary = &controller.summary;
+    let current = summary.last;
+    let kernel_ms = controller.window_max_ms;
+    let (next, reason) = if memory_pressure() {
+        (current / 2, "memory")
+    } else if kernel_ms > summary.target_ms {
+        (current / 2, "latency")
+    } else if kernel_ms * 2.0 < summary.target_ms {
+        (current.saturating_mul(2), "latency")
+    } else {
+        (current, "")
+    };
+    let next = next.clamp(summary.min, summary.max);
+    (controller.window_max_ms, controller.window_launches) = (0.0, 0);
+    if next == current {
+        return;
+    }
+    let step = ChunkAdaptation {
+        file: controller.file, launch: controller.launches, from: current, to: next, kernel_ms, reason: reason.to_string(),
+    };
+    let summary = &mut controller.summary;
+    if summary.steps.len() < MAX_STEPS {
+        summary.steps.push(step);
+    }
+    summary.last = next;
+    summary.largest = summary.largest.max(next);
+    adaptation.set_limit(Some(match controller.start {
+        ChunkSize::Reads(_) => ChunkSize::Reads(next),
+        ChunkSize::Bases(_) => ChunkSize::Bases(next),
+    }));
+}
+
+// Host RAM or GPU memory in use above MEMORY_PRESSURE of the total, per the latest sample
+fn memory_pressure() -> bool {
+    let Some(sample) = crate::metrics_sampler::latest() else { return false };
+    let Ok(system) = crate::system_info::get_system_info() else { return false };
+    let ram = system.total_ram_gb > 0.0 && sample.ram_used_gb > system.total_ram_gb * MEMORY_PRESSURE;
+    let gpu = sample.gpu_memory_used_mb
+        .is_some_and(|used| system.gpu_memory_gb > 0.0 && used / 1024.0 > system.gpu_memory_gb * MEMORY_PRESSURE);
+    ram || gpu
+}
+
+// The current run's adaptation so far
+pub fn summary() -> ChunkAdaptationSummary {
+    run_context::with_state(|state| {
+        state.chunks.controller.lock().ok()
+            .and_then(|controller| controller.as_ref().map(|c| c.summary.clone()))
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_context::RunState;
+    use std::sync::Arc;
+
+    #[test]
+    fn each_run_adapts_on_its_own() {
+        let (reads, bases) = (Arc::new(RunState::default()), Arc::new(RunState::default()));
+        {
+            let _entered = reads.enter();
+            start(ChunkSize::Reads(1000), 1 << 30, 250.0);
+            start_file(0);
+        }
+        {
+            let _entered = bases.enter();
+            start(ChunkSize::Bases(4096), 1 << 30, 250.0);
+            start_file(0);
+            // A kernel well under half the target doubles the size after a window
+            for _ in 0..WINDOW {
+                observe(1_000_000);
+            }
+            assert_eq!(limit(), Some(ChunkSize::Bases(8192)));
+            assert!(ChunkSize::Bases(1 << 20).is_full(1, 8192));
+            assert!(!ChunkSize::Bases(1 << 20).is_full(1, 8191));
+            // Sizes in another unit than the run adapts in keep their own
+            assert!(!ChunkSize::Reads(100).is_full(99, 1 << 30));
+            assert!(ChunkSize::Reads(100).is_full(100, 0));
+        }
+
+        let _entered = reads.enter();
+        assert_eq!(limit(), Some(ChunkSize::Reads(1000)));
+        assert!(ChunkSize::Reads(10).is_full(1000, 0));
+        assert!(!ChunkSize::Reads(10).is_full(999, 0));
+        assert!(summary().steps.is_empty());
+        {
+            // The other run stopping leaves this one adapting
+            let _entered = bases.enter();
+            stop();
+            assert_eq!(limit(), None);
+            assert!(ChunkSize::Bases(100).is_full(0, 100));
+            assert_eq!(summary().steps.len(), 1);
+        }
+        assert_eq!(limit(), Some(ChunkSize::Reads(1000)));
+        // A thread outside both runs doesn't adapt
+        std::thread::spawn(|| assert_eq!(limit(), None)).join().unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+// Thread pinning and NUMA-aware placement for the input pipeline
+// Buffers follow Linux first-touch policy, so pinning the threads that fill
+// them to the GPU's NUMA node also places the staging memory on that node
+
+use crate::warnings::{self, Category};
+use once_cell::sync::OnceCell;
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumaMode {
+    Off,
+    Auto,
+    Node(usize),
+}
+
+impl NumaMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "off" => Ok(NumaMode::Off),
+            "auto" => Ok(NumaMode::Auto),
+            node => node.parse::<usize>()
+                .map(NumaMode::Node)
+                .map_err(|_| format!("Invalid --numa value '{}': expected off, auto, or a node number", node)),
+        }
+    }
+}
+
+// Cores assigned to each pipeline stage (None = let the scheduler decide)
+#[derive(Debug, Clone, Default)]
+pub struct AffinityConfig {
+    pub reader_cores: Option<Vec<usize>>,
+    pub decompressor_cores: Option<Vec<usize>>,
+    pub gpu_submit_core: Option<usize>,
+    pub numa_node: Option<usize>,
+}
+
+static AFFINITY: OnceCell<AffinityConfig> = OnceCell::new();
+
+// Parse a Linux-style cpu list such as "0-3,8,10-11"
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cores = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.trim().parse().map_err(|_| format!("Invalid cpu range '{}'", part))?;
+                let end: usize = end.trim().parse().map_err(|_| format!("Invalid cpu range '{}'", part))?;
+                if end < start {
+                    return Err(format!("Invalid cpu range '{}'", part));
+                }
+                cores.extend(start..=end);
+            },
+            None => cores.push(part.trim().parse().map_err(|_| format!("Invalid cpu '{}'", part))?),
+        }
+    }
+    if cores.is_empty() {
+        return Err(format!("Empty cpu list '{}'", list));
+    }
+    Ok(cores)
+}
+
+// NUMA node of the first NVIDIA GPU, from its PCI bus id
+pub fn detect_gpu_numa_node() -> Option<usize> {
+    let output = Command::new("nvidia-smi")
+        .args(["--query-gpu=pci.bus_id", "--format=csv,noheader"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // nvidia-smi reports 00000000:01:00.0, sysfs uses 0000:01:00.0
+    let bus_id = String::from_utf8_lossy(&output.stdout).lines().next()?.trim().to_lowercase();
+    let bus_id = if bus_id.len() > 12 { bus_id[bus_id.len() - 12..].to_string() } else { bus_id };
+    let node = std::fs::read_to_string(format!("/sys/bus/pci/devices/{}/numa_node", bus_id)).ok()?;
+    // -1 means the platform has no NUMA information for this device
+    node.trim().parse::<i64>().ok().filter(|&n| n >= 0).map(|n| n as usize)
+}
+
+pub fn numa_node_cpus(node: usize) -> Option<Vec<usize>> {
+    let list = std::fs::read_to_string(format!("/sys/devices/system/node/node{}/cpulist", node)).ok()?;
+    parse_cpu_list(&list).ok()
+}
+
+// Build the stage assignment from CLI options
+// Explicit core lists win; NUMA mode fills in any stage left unset
+pub fn configure(numa: NumaMode, reader: Option<&str>, decompressor: Option<&str>, gpu_submit: Option<usize>) -> Result<AffinityConfig, String> {
+    let mut config = AffinityConfig {
+        reader_cores: reader.map(parse_cpu_list).transpose()?,
+        decompressor_cores: decompressor.map(parse_cpu_list).transpose()?,
+        gpu_submit_core: gpu_submit,
+        numa_node: None,
+    };
+
+    let node = match numa {
+        NumaMode::Off => None,
+        NumaMode::Node(n) => Some(n),
+        NumaMode::Auto => {
+            let detected = detect_gpu_numa_node();
+            if detected.is_none() {
+                warnings::warn(Category::Device, "could not detect the GPU's NUMA node, leaving placement to the OS");
+            }
+            detected
+        },
+    };
+
+    if let Some(node) = node {
+        let cpus = numa_node_cpus(node)
+            .ok_or_else(|| format!("NUMA node {} not found in /sys/devices/system/node", node))?;
+        config.numa_node = Some(node);
+        config.reader_cores.get_or_insert_with(|| cpus.clone());
+        config.decompressor_cores.get_or_insert_with(|| cpus.clone());
+        if config.gpu_submit_core.is_none() {
+            config.gpu_submit_core = cpus.first().copied();
+        }
+    }
+
+    Ok(config)
+}
+
+// Install the process-wide assignment (first call wins)
+pub fn install(config: AffinityConfig) {
+    if let Some(node) = config.numa_node {
+        println!("NUMA placement: node {}", node);
+    }
+    if let Some(cores) = &config.reader_cores {
+        println!("Reader threads pinned to cores {:?}", cores);
+    }
+    if let Some(cores) = &config.decompressor_cores {
+        println!("Decompressor pinned to cores {:?}", cores);
+    }
+    if let Some(core) = config.gpu_submit_core {
+        println!("GPU submit thread pinned to core {}", core);
+    }
+    let _ = AFFINITY.set(config);
+}
+
+pub fn get() -> Option<&'static AffinityConfig> {
+    AFFINITY.get()
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(pid: libc::pid_t, cores: &[usize]) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &core in cores {
+            libc::CPU_SET(core, &mut set);
+        }
+        if libc::sched_setaffinity(pid, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(format!("sched_setaffinity failed: {}", std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_pid: i32, _cores: &[usize]) -> Result<(), String> {
+    Err("Thread pinning is only supported on Linux".to_string())
+}
+
+fn pin(pid: i32, cores: &[usize], stage: &str) {
+    if let Err(e) = set_affinity(pid, cores) {
+        warnings::warn(Category::Device, format!("failed to pin {} to cores {:?}: {}", stage, cores, e));
+    }
+}
+
+// Pin the calling thread as a reader thread (used by the async runtime)
+pub fn pin_reader_thread() {
+    if let Some(cores) = get().and_then(|c| c.reader_cores.as_ref()) {
+        pin(0, cores, "reader thread");
+    }
+}
+
+// Pin the calling thread as the GPU submit thread
+pub fn pin_gpu_submit_thread() {
+    if let Some(core) = get().and_then(|c| c.gpu_submit_core) {
+        pin(0, &[core], "GPU submit thread");
+    }
+}
+
+// Pin the calling thread as a decompressor thread (the parallel gzip pool)
+pub fn pin_decompressor_thread() {
+    if let Some(cores) = get().and_then(|c| c.decompressor_cores.as_ref()) {
+        pin(0, cores, "decompressor thread");
+    }
+}
+
+// Pin an already spawned decompressor process (zcat)
+pub fn pin_decompressor(pid: Option<u32>) {
+    if let (Some(cores), Some(pid)) = (get().and_then(|c| c.decompressor_cores.as_ref()), pid) {
+        pin(pid as i32, cores, "decompressor");
+    }
+}
@@ -0,0 +1,188 @@
+// Aggregation: what a WGS run reports per file besides the sum of its chunk scores
+// A file's score is every chunk score added into one i32, which is what the
+// checkpoints, resume checks and the run report are built on, and stays so.
+// --aggregate (WgsConfig::aggregates) names further roll-ups; each gets every
+// scored chunk of a file in turn (chunk index, reads, bases, score) and gives
+// one number for it, reported with the file and kept in its checkpoint:
+//   sum            the file score again, as a float, for tables of aggregates
+//   max, min       the best and worst chunk score; with --long-reads a chunk is
+//                  one read, so these are per read
+//   mean           chunk scores averaged over the chunks
+//   mean-identity  each chunk's score over the best it could have scored
+//                  (every base a match), averaged over the chunks; the
+//                  normalized identity_weighted weights them by bases instead
+// Chunks that fail to align are left out, as they are from the sum. Per-contig
+// sums are the pair mode's per-contig scores (contig_chunks.rs)
+// Library callers register an aggregator of their own under a name and list it
+// in WgsConfig::aggregates, as a CLI built on the crate would take it from
+// --aggregate:
+//   aggregation::register("p90", Arc::new(|| Box::new(P90::default()) as Box<dyn Aggregator>))?;
+//   config.aggregates = vec!["p90".to_string(), "max".to_string()];
+
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+const BUILT_IN: [&str; 5] = ["sum", "max", "min", "mean", "mean-identity"];
+
+// One chunk's score, as it arrives
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkScore {
+    // 1-based, in file order
+    pub chunk_index: usize,
+    pub reads: usize,
+    pub bases: usize,
+    pub score: i32,
+}
+
+// A roll-up of one file's chunk scores; a fresh one is made for every file
+pub trait Aggregator: Send {
+    fn add(&mut self, chunk: &ChunkScore);
+
+    // The file's value so far; 0 before any chunk
+    fn value(&self) -> f64;
+}
+
+// Makes the aggregator for each file
+pub type AggregatorFactory = Arc<dyn Fn() -> Box<dyn Aggregator> + Send + Sync>;
+
+#[derive(Default)]
+struct Sum(f64);
+
+impl Aggregator for Sum {
+    fn add(&mut self, chunk: &ChunkScore) {
+        self.0 += f64::from(chunk.score);
+    }
+
+    fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+// The highest (or, with `lowest`, the lowest) chunk score
+#[derive(Default)]
+struct Extreme {
+    lowest: bool,
+    value: Option<i32>,
+}
+
+impl Aggregator for Extreme {
+    fn add(&mut self, chunk: &ChunkScore) {
+        self.value = Some(match self.value {
+            Some(value) if self.lowest => value.min(chunk.score),
+            Some(value) => value.max(chunk.score),
+            None => chunk.score,
+        });
+    }
+
+    fn value(&self) -> f64 {
+        self.value.map_or(0.0, f64::from)
+    }
+}
+
+// The mean of a value per chunk
+#[derive(Default)]
+struct Mean {
+    identity: bool,
+    total: f64,
+    chunks: usize,
+}
+
+impl Aggregator for Mean {
+    fn add(&mut self, chunk: &ChunkScore) {
+        self.total += if self.identity {
+            let best = chunk.bases as f64 * f64::from(crate::scoring::selected().match_score);
+            if best > 0.0 { f64::from(chunk.score) / best } else { 0.0 }
+        } else {
+            f64::from(chunk.score)
+        };
+        self.chunks += 1;
+    }
+
+    fn value(&self) -> f64 {
+        if self.chunks > 0 { self.total / self.chunks as f64 } else { 0.0 }
+    }
+}
+
+static REGISTERED: Lazy<Mutex<Vec<(String, AggregatorFactory)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn registered() -> std::sync::MutexGuard<'static, Vec<(String, AggregatorFactory)>> {
+    REGISTERED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Make an aggregator available to --aggregate and WgsConfig::aggregates by `name`
+pub fn register(name: &str, factory: AggregatorFactory) -> Result<(), String> {
+    if name.is_empty() || name.contains(',') || BUILT_IN.contains(&name) {
+        return Err(format!("Can't register an aggregator named '{}': names must be non-empty, without commas, and not a built-in ({})",
+                           name, BUILT_IN.join(", ")));
+    }
+    let mut aggregators = registered();
+    if aggregators.iter().any(|(registered, _)| registered == name) {
+        return Err(format!("An aggregator named '{}' is already registered", name));
+    }
+    aggregators.push((name.to_string(), factory));
+    Ok(())
+}
+
+// Built-ins, then registered aggregators in the order they were registered
+pub fn names() -> Vec<String> {
+    BUILT_IN.iter().map(|name| name.to_string()).chain(registered().iter().map(|(name, _)| name.clone())).collect()
+}
+
+pub fn create(name: &str) -> Result<Box<dyn Aggregator>, String> {
+    match name {
+        "sum" => Ok(Box::<Sum>::default()),
+        "max" => Ok(Box::<Extreme>::default()),
+        "min" => Ok(Box::new(Extreme { lowest: true, value: None })),
+        "mean" => Ok(Box::<Mean>::default()),
+        "mean-identity" => Ok(Box::new(Mean { identity: true, ..Default::default() })),
+        _ => {
+            let factory = registered().iter().find(|(registered, _)| registered == name).map(|(_, factory)| factory.clone());
+            factory.map(|factory| factory())
+                .ok_or_else(|| format!("Unknown aggregate '{}': expected one of {}", name, names().join(", ")))
+        },
+    }
+}
+
+// Every name must be known, and none given twice
+pub fn validate(names: &[String]) -> Result<(), String> {
+    for (i, name) in names.iter().enumerate() {
+        create(name)?;
+        if names[..i].contains(name) {
+            return Err(format!("Aggregate '{}' is given twice", name));
+        }
+    }
+    Ok(())
+}
+
+// The aggregators of one file, by name
+pub struct FileAggregates {
+    aggregators: Vec<(String, Box<dyn Aggregator>)>,
+}
+
+impl FileAggregates {
+    pub fn start(names: &[String]) -> Result<Self, String> {
+        let aggregators = names.iter().map(|name| create(name).map(|aggregator| (name.clone(), aggregator))).collect::<Result<_, _>>()?;
+        Ok(Self { aggregators })
+    }
+
+    pub fn add(&mut self, chunk: &ChunkScore) {
+        for (_, aggregator) in &mut self.aggregators {
+            aggregator.add(chunk);
+        }
+    }
+
+    pub fn values(&self) -> BTreeMap<String, f64> {
+        self.aggregators.iter().map(|(name, aggregator)| (name.clone(), aggregator.value())).collect()
+    }
+}
+
+// "max 1234, mean-identity 0.8731", in name order
+pub fn summary(values: &BTreeMap<String, f64>) -> String {
+    values.iter().map(|(name, value)| format!("{} {}", name, format_value(*value))).collect::<Vec<_>>().join(", ")
+}
+
+// Whole numbers without a fraction, the rest to 4 places
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 { format!("{}", value as i64) } else { format!("{:.4}", value) }
+}
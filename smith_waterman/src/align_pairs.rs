@@ -0,0 +1,178 @@
+// `align pairs`: one score per line of a pairs file, for "align this list of
+// pairs" workloads
+// Each line is seq1<TAB>seq2, optionally followed by key=value fields:
+//   id=NAME      the pair's name in the output (default pair_N, N counting pairs from 1)
+//   match=S      match score for this pair (default --match-score or the preset's)
+//   mismatch=S   mismatch penalty for this pair (default --mismatch-penalty)
+// A sequence is a FASTA file (plain or .gz; its first record), path:record for
+// another record of it, or the bases themselves. Files are read once however
+// many pairs name them. Blank lines and lines starting with '#' are skipped
+// Pairs are scored like db search, as the best ungapped local run over every
+// diagonal (ungapped.rs). They are gathered into batches of up to BATCH_PAIRS
+// pairs or BATCH_BASES bases, and each batch is one launch on the GPU
+// (smith_waterman_paired_diagonals) with every distinct pair scoring in it, or
+// a parallel pass on the CPU. Rows are written in input order as batches finish:
+// id, seq1, seq2, their lengths, match, mismatch and score
+
+use crate::scoring::Scoring;
+use crate::ungapped::{self, ScoreMatrix, SequenceSet};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+const BATCH_PAIRS: usize = 4096;
+const BATCH_BASES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct PairsSummary {
+    pub pairs: usize,
+    pub bases: usize,
+    pub batches: usize,
+    // Distinct files the sequences were read from
+    pub files: usize,
+}
+
+struct Pair {
+    id: String,
+    seq1: String,
+    seq2: String,
+    scoring: Scoring,
+}
+
+struct Batch {
+    pairs: Vec<Pair>,
+    queries: SequenceSet,
+    targets: SequenceSet,
+}
+
+impl Batch {
+    fn new() -> Self {
+        Self { pairs: Vec::new(), queries: SequenceSet::new(), targets: SequenceSet::new() }
+    }
+
+    fn bases(&self) -> usize {
+        self.queries.residues.len() + self.targets.residues.len()
+    }
+}
+
+// FASTA files named in the pairs file, by path
+#[derive(Default)]
+struct Sources {
+    files: HashMap<String, Vec<(String, Vec<u8>)>>,
+}
+
+impl Sources {
+    // Label and bases of one sequence field
+    fn resolve<'a>(&'a mut self, field: &'a str) -> Result<(String, &'a [u8]), String> {
+        let (path, record) = match field.rsplit_once(':') {
+            Some((path, record)) if !std::path::Path::new(field).is_file() && std::path::Path::new(path).is_file() => (path, Some(record)),
+            _ if std::path::Path::new(field).is_file() => (field, None),
+            _ if !field.is_empty() && field.bytes().all(|b| b.is_ascii_alphabetic()) => return Ok((field.to_string(), field.as_bytes())),
+            _ => return Err(format!("'{}' is neither a file nor a sequence of letters", field)),
+        };
+        if !self.files.contains_key(path) {
+            let records = crate::simulate::load_reference(path)?;
+            self.files.insert(path.to_string(), records);
+        }
+        let records = &self.files[path];
+        let (name, bases) = match record {
+            Some(record) => records.iter().find(|(name, _)| name == record)
+                .ok_or_else(|| format!("{} has no record '{}'", path, record))?,
+            None => &records[0],
+        };
+        Ok((format!("{}:{}", path, name), bases))
+    }
+}
+
+fn parse_score(key: &str, value: &str) -> Result<i32, String> {
+    value.parse().map_err(|_| format!("invalid {} '{}': expected a whole number", key, value))
+}
+
+// Optional key=value fields after the two sequences
+fn parse_options(fields: &[&str], number: usize, defaults: Scoring) -> Result<(String, Scoring), String> {
+    let mut id = format!("pair_{}", number);
+    let mut scoring = defaults;
+    for field in fields.iter().filter(|field| !field.is_empty()) {
+        match field.split_once('=') {
+            Some(("id", value)) if !value.is_empty() => id = value.to_string(),
+            Some(("match", value)) => scoring.match_score = parse_score("match", value)?,
+            Some(("mismatch", value)) => scoring.mismatch_penalty = parse_score("mismatch", value)?,
+            _ => return Err(format!("unknown field '{}': expected id=, match= or mismatch=", field)),
+        }
+    }
+    if scoring.match_score <= 0 {
+        return Err(format!("match must be positive, got {}", scoring.match_score));
+    }
+    if scoring.mismatch_penalty > 0 {
+        return Err(format!("mismatch is added to the score, so it can't be positive (got {})", scoring.mismatch_penalty));
+    }
+    Ok((id, scoring))
+}
+
+// Score every pair of `pairs_path` and write one TSV row per pair
+pub fn align(pairs_path: &str, use_gpu: bool, out: &mut dyn Write) -> Result<PairsSummary, String> {
+    let file = File::open(pairs_path).map_err(|e| format!("Failed to open pairs file {}: {}", pairs_path, e))?;
+    let defaults = crate::scoring::selected();
+    let mut sources = Sources::default();
+    let mut summary = PairsSummary::default();
+    let mut batch = Batch::new();
+    // Nucleotide matrices share an encoding, so any of them encodes the bases
+    let encoder = ScoreMatrix::nucleotide();
+    writeln!(out, "#id\tseq1\tseq2\tseq1_length\tseq2_length\tmatch\tmismatch\tscore")
+        .map_err(|e| format!("Failed to write pair scores: {}", e))?;
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read pairs file {}: {}", pairs_path, e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let in_line = |e: String| format!("Pairs line {}: {}", i + 1, e);
+        let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+        if fields.len() < 2 {
+            return Err(in_line("expected seq1<TAB>seq2[<TAB>key=value...]".to_string()));
+        }
+        let (id, scoring) = parse_options(&fields[2..], summary.pairs + batch.pairs.len() + 1, defaults).map_err(in_line)?;
+        let (seq1, bases) = sources.resolve(fields[0]).map_err(in_line)?;
+        batch.queries.push(bases, &encoder);
+        let (seq2, bases) = sources.resolve(fields[1]).map_err(in_line)?;
+        batch.targets.push(bases, &encoder);
+        batch.pairs.push(Pair { id, seq1, seq2, scoring });
+        if batch.pairs.len() >= BATCH_PAIRS || batch.bases() >= BATCH_BASES {
+            score_batch(std::mem::replace(&mut batch, Batch::new()), use_gpu, &mut summary, out)?;
+        }
+    }
+    if !batch.pairs.is_empty() {
+        score_batch(batch, use_gpu, &mut summary, out)?;
+    }
+    if summary.pairs == 0 {
+        return Err(format!("Pairs file {} lists no pairs", pairs_path));
+    }
+    summary.files = sources.files.len();
+    Ok(summary)
+}
+
+fn score_batch(batch: Batch, use_gpu: bool, summary: &mut PairsSummary, out: &mut dyn Write) -> Result<(), String> {
+    let mut scorings: Vec<Scoring> = Vec::new();
+    let matrix_of: Vec<u32> = batch.pairs.iter()
+        .map(|pair| match scorings.iter().position(|scoring| *scoring == pair.scoring) {
+            Some(index) => index as u32,
+            None => {
+                scorings.push(pair.scoring);
+                (scorings.len() - 1) as u32
+            },
+        })
+        .collect();
+    let matrices: Vec<ScoreMatrix> = scorings.iter().map(|scoring| ScoreMatrix::nucleotide_with(*scoring)).collect();
+    let scores = ungapped::paired_scores(&batch.queries, &batch.targets, &matrices, &matrix_of, use_gpu)?;
+    for (index, (pair, score)) in batch.pairs.iter().zip(scores).enumerate() {
+        let (len1, len2) = (batch.queries.get(index).len(), batch.targets.get(index).len());
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", pair.id, pair.seq1, pair.seq2, len1, len2,
+                 pair.scoring.match_score, pair.scoring.mismatch_penalty, score)
+            .map_err(|e| format!("Failed to write pair scores: {}", e))?;
+    }
+    summary.pairs += batch.pairs.len();
+    summary.bases += batch.bases();
+    summary.batches += 1;
+    Ok(())
+}
@@ -1,22 +1,47 @@
 // GPU-accelerated sequence aligner using OpenCL
 // Handles the main alignment logic and orchestrates GPU operations
 
-use crate::gpu::{GpuAlignmentResult, GpuDevice, GPU_WORK_GROUP_SIZE, GPU_MAX_WORK_GROUPS, get_opencl_context};
-use ocl::{Buffer, Program, Kernel, MemFlags};
-use crate::tools::benchmark::{start_benchmark, update_benchmark_progress, finish_benchmark};
+use crate::aggregation::{ChunkScore, FileAggregates};
+use crate::gpu::{GpuAlignmentResult, GpuContext, GpuDevice, GPU_WORK_GROUP_SIZE, GPU_MAX_WORK_GROUPS};
+use ocl::{Buffer, Event, Program, Kernel, MemFlags};
+use crate::alignment_input::{process_alignment_file_in_chunks, AlignmentInputOptions};
+use crate::chunk_audit::{ChunkAudit, ChunkRecord};
+use crate::async_input::{stream_input_chunks, ChunkPool};
+use crate::chunk_latency;
+use crate::chunking::{self, ChunkSize};
+use crate::contig_chunks::{ContigScore, ContigTally};
+use crate::gpu_queues::{LaunchPool, QueueCount};
+use crate::gpu_timing::{self, CommandTimes};
+use crate::input_format::{detect_path, RecordAssembler};
+use crate::kernels::{KernelVariant, PACKED_LANES};
+use crate::mmap_input;
+use crate::planner::format_bytes;
+use crate::contamination::{Panel, ScreenStats};
+use crate::low_complexity;
+use crate::memory_profile::{Reservation, Site};
+use crate::qc::{QcCollector, QcReport};
+use crate::quarantine::FileFailure;
+use crate::read_binning;
+use crate::long_read_trim::{TrimStats, Trimmer};
+use crate::read_trim::{ReadTrimStats, ReadTrimmer};
+use crate::sanitize::{SanitizeStats, Sanitizer};
+use crate::read_chunk::{ReadChunk, ReadNameMode};
+use crate::read_output::ReadResultWriter;
+use crate::resume::RunProvenance;
+use crate::run_control::{CancellationToken, Progress, ProgressStage};
+use crate::run_context::RunContext;
+use crate::score_metrics::NormalizedScore;
+use crate::scorer::ScorePair;
+use crate::stages::{self, Stage, StageClock, StageReceiver, StageSender};
+use crate::tui;
+use crate::wgs_config::WgsConfig;
+use crate::wgs_report::{FileReport, FileStatus, WgsRunReport};
+use crate::warnings::{self, Category};
 
-// Centralized chunk size configuration - .env is the ONLY source of truth
-fn get_chunk_size_reads() -> Result<usize, String> {
-    let chunk_size_str = std::env::var("GPU_CHUNK_SIZE_READS")
-        .map_err(|_| "GPU_CHUNK_SIZE_READS not set in .env file".to_string())?;
-    
-    chunk_size_str.parse::<usize>()
-        .map_err(|e| format!("Invalid GPU_CHUNK_SIZE_READS value '{}': {}", chunk_size_str, e))
-}
-
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
-use std::process::{Command, Stdio};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
 
 use serde::{Serialize, Deserialize};
 
@@ -29,60 +54,123 @@ pub struct FileCheckpoint {
     pub total_bases: usize,
     pub total_reads: usize,
     pub completed: bool,
+    #[serde(default)]
+    pub normalized: NormalizedScore,
+    // Bases masked by --mask-low-complexity
+    #[serde(default)]
+    pub masked_bases: usize,
+    // Ambiguous (N) bases in the input, however --n-policy scored them
+    #[serde(default)]
+    pub n_bases: usize,
+    // --screen: share of the file's sampled k-mers found in each panel reference
+    #[serde(default)]
+    pub contamination: Vec<(String, f64)>,
+    // Score of the file's first chunk, re-checked when resuming on another device
+    #[serde(default)]
+    pub first_chunk_score: Option<i32>,
+    // Reads and bases of the chunks skipped after failing to align
+    #[serde(default)]
+    pub excluded_reads: usize,
+    #[serde(default)]
+    pub excluded_bases: usize,
+    // --aggregate: each named aggregate of the file's chunk scores (aggregation.rs)
+    #[serde(default)]
+    pub aggregates: BTreeMap<String, f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CheckpointState {
+    // See schema.rs; 0 in checkpoints saved before it was recorded
+    #[serde(default)]
+    pub schema_version: u32,
     pub run_id: String,
     pub files: Vec<FileCheckpoint>,
     pub total_files: usize,
     pub completed_files: usize,
+    // What the scores depend on; None in checkpoints saved before it was recorded
+    #[serde(default)]
+    pub provenance: Option<RunProvenance>,
+    // Devices the files were aligned on, in order of first use
+    #[serde(default)]
+    pub devices: Vec<String>,
+    // Files that failed, with their attempts across resumes (quarantine.rs)
+    #[serde(default)]
+    pub failures: Vec<FileFailure>,
+    // Write large checkpoints zstd-compressed (WgsConfig::compress_checkpoints)
+    #[serde(skip)]
+    pub compress: bool,
 }
 
 impl CheckpointState {
     pub fn new(run_id: String, total_files: usize) -> Self {
         Self {
+            schema_version: crate::schema::CHECKPOINT.version(),
             run_id,
             files: Vec::new(),
             total_files,
             completed_files: 0,
+            provenance: None,
+            devices: Vec::new(),
+            failures: Vec::new(),
+            compress: false,
         }
     }
 
-    pub fn save(&self) -> Result<(), String> {
-        // Get the current run number from benchmark system
-        let run_number = crate::tools::benchmark::get_next_run_number();
-        let filename = format!("checkpoint_run_{}.json", run_number);
-        
+    // One checkpoint file per run, rewritten after every file
+    pub fn path_for(run_number: u64) -> std::path::PathBuf {
+        crate::output_layout::checkpoint_file(&format!("checkpoint_run_{}.json", run_number))
+    }
+
+    // The checkpoint with the highest run number in `dir` (each save rewrites the full state)
+    pub fn latest_in(dir: &std::path::Path) -> Result<Option<Self>, String> {
+        let Ok(entries) = std::fs::read_dir(dir) else { return Ok(None) };
+        let latest = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                let (number, suffix) = name.strip_prefix("checkpoint_run_")?.split_once('.')?;
+                let number = number.parse::<u64>().ok()?;
+                ["json", "json.zst", "json.bak", "json.zst.bak"].contains(&suffix)
+                    .then(|| (number, Self::path_for(number)))
+            })
+            .max_by_key(|(number, _)| *number);
+        match latest {
+            Some((_, path)) => Self::load(&path),
+            None => Ok(None),
+        }
+    }
+
+    // Saved as run `run_number`'s checkpoint (checkpoint_store.rs)
+    pub fn save(&self, run_number: u64) -> Result<(), String> {
         let json = serde_json::to_string_pretty(self)
             .map_err(|e| format!("Failed to serialize checkpoint: {}", e))?;
-        
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&filename)
-            .map_err(|e| format!("Failed to create checkpoint file: {}", e))?;
-        
-        file.write_all(json.as_bytes())
-            .map_err(|e| format!("Failed to write checkpoint: {}", e))?;
-        
+        let written = crate::checkpoint_store::write(&Self::path_for(run_number), &json, self.compress)?;
+        crate::remote_state::mirror(&written);
         Ok(())
     }
 
-    pub fn load(run_id: &str) -> Result<Option<Self>, String> {
-        let filename = format!("checkpoint_{}.json", run_id);
-        match File::open(&filename) {
-            Ok(file) => {
-                let checkpoint: CheckpointState = serde_json::from_reader(file)
-                    .map_err(|e| format!("Failed to parse checkpoint: {}", e))?;
-                Ok(Some(checkpoint))
-            },
-            Err(_) => Ok(None), // No checkpoint file exists
+    // The newest readable generation of the checkpoint at `filename`; None if there is none
+    pub fn load(filename: &std::path::Path) -> Result<Option<Self>, String> {
+        let mut unreadable: Option<String> = None;
+        for path in crate::checkpoint_store::generations(filename) {
+            let parsed = crate::checkpoint_store::read(&path)
+                .and_then(|json| crate::schema::parse(&crate::schema::CHECKPOINT, &json, &path.display().to_string()));
+            match parsed {
+                Ok(state) => {
+                    if let Some(e) = &unreadable {
+                        warnings::warn(Category::Checkpoints, format!("{}; using the previous checkpoint {}", e, path.display()));
+                    }
+                    return Ok(Some(state));
+                },
+                Err(e) => {
+                    unreadable.get_or_insert(e);
+                },
+            }
         }
+        unreadable.map_or(Ok(None), Err)
     }
 
-    pub fn add_file_result(&mut self, checkpoint: FileCheckpoint) -> Result<(), String> {
+    pub fn add_file_result(&mut self, run_number: u64, checkpoint: FileCheckpoint) -> Result<(), String> {
         // Remove any existing entry for this file
         self.files.retain(|f| f.file_index != checkpoint.file_index);
         
@@ -93,7 +181,7 @@ impl CheckpointState {
         self.completed_files = self.files.iter().filter(|f| f.completed).count();
         
         // Save checkpoint
-        self.save()?;
+        self.save(run_number)?;
         
         Ok(())
     }
@@ -101,48 +189,85 @@ impl CheckpointState {
     pub fn is_file_completed(&self, file_index: usize) -> bool {
         self.files.iter().any(|f| f.file_index == file_index && f.completed)
     }
+
+    // Count a failed attempt at a file; saved with the next add_file_result
+    pub fn record_failure(&mut self, file_index: usize, file_path: &str, error: &str, failed_chunks: &[String], quarantine_after: u32) -> &FileFailure {
+        let position = match self.failures.iter().position(|f| f.file_index == file_index) {
+            Some(position) => position,
+            None => {
+                self.failures.push(FileFailure::new(file_index, file_path));
+                self.failures.len() - 1
+            },
+        };
+        let failure = &mut self.failures[position];
+        failure.record(error, failed_chunks, quarantine_after);
+        failure
+    }
+
+    // The file's failures, if it has been quarantined
+    pub fn quarantined(&self, file_index: usize) -> Option<&FileFailure> {
+        self.failures.iter().find(|f| f.file_index == file_index && f.quarantined)
+    }
 }
 
-// Simple streaming FASTQ processor that yields chunks as they're read
-pub fn process_fastq_file_in_chunks<F>(filepath: &str, chunk_size_reads: usize, mut processor: F) -> Result<(), String> 
-where F: FnMut(&[String]) -> Result<(), String> {
-    let reader: Box<dyn BufRead> = if filepath.ends_with(".gz") {
-        // Use system zcat for gzipped files (fixes flate2 issues with large files)
-        let child = Command::new("zcat")
+// Simple streaming FASTA/FASTQ processor that yields chunks as they're read
+// Lines are read into one reused buffer and sequences land in a reused arena
+// To feed several analyses from one pass, register them on a pipeline.rs Pipeline
+pub fn process_fastq_file_in_chunks<F>(filepath: &str, chunk_size: ChunkSize, mut processor: F) -> Result<(), String> 
+where F: FnMut(&ReadChunk) -> Result<(), String> {
+    // Compression is sniffed from the content, not the extension
+    let detected = detect_path(filepath)?;
+    let mut reader: Box<dyn BufRead> = if let Some(mut command) = detected.compression.decompress_command() {
+        // Use system zcat/zstd for compressed files (fixes flate2 issues with large files)
+        let child = command
             .arg(filepath)
             .stdout(Stdio::piped())
             .spawn()
-            .map_err(|e| format!("Failed to spawn zcat for {}: {}", filepath, e))?;
+            .map_err(|e| format!("Failed to spawn {} decompressor for {}: {}", detected.compression.name(), filepath, e))?;
+        crate::affinity::pin_decompressor(Some(child.id()));
         
         let stdout = child.stdout
-            .ok_or_else(|| format!("Failed to get stdout from zcat for {}", filepath))?;
+            .ok_or_else(|| format!("Failed to get stdout from decompressor for {}", filepath))?;
         
         Box::new(BufReader::new(stdout))
     } else {
-        // For non-gzipped files, use regular file reader
+        // Uncompressed, or decoded in-process (DECOMPRESSOR=native, the default off Linux)
         let file = File::open(filepath)
             .map_err(|e| format!("Failed to open file {}: {}", filepath, e))?;
-        Box::new(BufReader::new(file))
+        Box::new(BufReader::new(detected.compression.native_decoder(file)?))
     };
     
-    let mut chunk = Vec::with_capacity(chunk_size_reads);
+    let mut chunk = ReadChunk::with_capacity(chunk_size.capacity_reads());
+    let mut records = RecordAssembler::new();
+    let mut line = Vec::new();
     let mut line_count = 0;
     let mut total_reads = 0;
+    let mut chunks = 0;
     let mut error_count = 0;
+    let terminator = reader.fill_buf().map(crate::input_format::line_terminator)
+        .map_err(|e| format!("Failed to read {}: {}", filepath, e))?;
     
-    for line_result in reader.lines() {
-        match line_result {
-            Ok(line) => {
+    loop {
+        line.clear();
+        let skip = records.skips_next_line(&chunk);
+        match if skip { reader.skip_until(terminator) } else { reader.read_until(terminator, &mut line) } {
+            Ok(0) => break,
+            Ok(_) => {
                 line_count += 1;
                 
-                if line_count % 4 == 2 {
-                    // This is a sequence line
-                    chunk.push(line);
+                let completed = if skip {
+                    records.skip_line()
+                } else {
+                    records.push_line(&line, &mut chunk).map_err(|e| format!("{}: {}", filepath, e))?
+                };
+                if completed {
+                    // A read is complete
                     total_reads += 1;
                     
-                    if chunk.len() >= chunk_size_reads {
+                    if chunk_size.is_full(chunk.len(), chunk.bases().len()) {
                         // Process this chunk
                         processor(&chunk)?;
+                        chunks += 1;
                         chunk.clear();
                     }
                 }
@@ -155,7 +280,7 @@ where F: FnMut(&[String]) -> Result<(), String> {
             Err(e) => {
                 error_count += 1;
                 if error_count <= 5 {
-                    println!("    Warning: Error reading line {}: {}", line_count, e);
+                    warnings::warn(Category::Input, format!("Error reading line {}: {}", line_count, e));
                 }
                 if error_count > 10 {
                     return Err(format!("Too many read errors (>10), stopping at line {}", line_count));
@@ -165,11 +290,15 @@ where F: FnMut(&[String]) -> Result<(), String> {
     }
     
     // Process any remaining reads in the final chunk
+    if records.finish(&mut chunk) {
+        total_reads += 1;
+    }
     if !chunk.is_empty() {
         processor(&chunk)?;
+        chunks += 1;
     }
     
-    println!("    Processed {} total reads in {} chunks", total_reads, (total_reads + chunk_size_reads - 1) / chunk_size_reads);
+    println!("    Processed {} total reads in {} chunks", total_reads, chunks);
     println!("    Total lines read: {}", line_count);
     if error_count > 0 {
         println!("    Total read errors: {}", error_count);
@@ -178,121 +307,619 @@ where F: FnMut(&[String]) -> Result<(), String> {
 }
 
 
+// A ChunkAligner launch's chunk index, reads and bases
+type ChunkTag = (usize, usize, usize);
+
+// Self-alignment of every chunk on the GPU, as in the WGS pass, as a pipeline
+// processor (`analyze --align`); no sanitizing, masking or per-read output
+pub struct ChunkAligner {
+    device: GpuDevice,
+    launches: LaunchPool<ChunkTag>,
+    chunks: usize,
+    score: i64,
+    failed: usize,
+    total: i64,
+}
 
-// Process full WGS dataset from all 16 files
-pub fn process_full_wgs_dataset(device: &GpuDevice) -> Result<Vec<GpuAlignmentResult>, String> {
-    let wgs_path = std::env::var("WGS_DATA_DIR")
-        .unwrap_or_else(|_| "/path/to/wgs/data".to_string());
-    let sample_id = std::env::var("WGS_SAMPLE_ID")
-        .unwrap_or_else(|_| "SAMPLE_ID".to_string());
-    let lanes: usize = std::env::var("WGS_LANES")
-        .unwrap_or_else(|_| "8".to_string())
-        .parse()
-        .unwrap_or(8);
-    let reads_per_lane: usize = std::env::var("WGS_READS_PER_LANE")
-        .unwrap_or_else(|_| "2".to_string())
-        .parse()
-        .unwrap_or(2);
-    
-    // Generate file paths dynamically based on configuration
-    let mut files = Vec::new();
-    for lane in 1..=lanes {
-        for read in 1..=reads_per_lane {
-            let filename = format!("{}_L{:03}_R{}_001.fastq.gz", sample_id, lane, read);
-            files.push(format!("{}/{}", wgs_path, filename));
+impl ChunkAligner {
+    pub fn new(gpu: &GpuContext, queues: QueueCount) -> Result<Self, String> {
+        Ok(Self { device: gpu.info().clone(), launches: LaunchPool::on(gpu, queues)?, chunks: 0, score: 0, failed: 0, total: 0 })
+    }
+
+    fn complete(&mut self, finished: Vec<(ChunkTag, Result<i32, String>)>) {
+        for ((chunk_index, reads, bases), result) in finished {
+            match result {
+                Ok(score) => self.score += score as i64,
+                Err(e) => {
+                    warnings::warn(Category::Chunks, format!("Failed to align chunk {}: {}", chunk_index, e));
+                    crate::exit_codes::record_skipped_chunk(reads, bases);
+                    self.failed += 1;
+                },
+            }
         }
     }
+}
+
+impl crate::pipeline::ChunkProcessor for ChunkAligner {
+    fn name(&self) -> &str {
+        "Alignment"
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        (self.chunks, self.score, self.failed) = (0, 0, 0);
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        self.chunks += 1;
+        if chunk.bases().len() >= MIN_CHUNK_BASES {
+            let finished = self.launches.submit((self.chunks, chunk.len(), chunk.bases().len()), chunk.bases(), chunk.bases(), &self.device);
+            self.complete(finished);
+        }
+        Ok(())
+    }
+
+    fn finish_file(&mut self, _file: &str) -> Result<String, String> {
+        let finished = self.launches.drain();
+        self.complete(finished);
+        self.total += self.score;
+        Ok(format!("score {} over {} chunks{}", self.score, self.chunks,
+                   if self.failed > 0 { format!(" ({} failed)", self.failed) } else { String::new() }))
+    }
+
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        Ok(Some(format!("total score {} on {}", self.total, self.device.name)))
+    }
+}
+
+
+
+// Process the configured WGS input files, as the CLI's run (run_context::cli)
+pub fn process_full_wgs_dataset(device: &GpuDevice, config: &WgsConfig) -> Result<WgsRunReport, String> {
+    wgs_on(crate::run_context::cli(), &GpuContext::shared()?, device, config, &CancellationToken::new(), &mut |_| {})
+}
+
+// process_full_wgs_dataset on a given context's device
+pub fn process_full_wgs_dataset_on(gpu: &GpuContext, config: &WgsConfig) -> Result<WgsRunReport, String> {
+    wgs_on(crate::run_context::cli(), gpu, gpu.info(), config, &CancellationToken::new(), &mut |_| {})
+}
+
+// A WGS run on `gpu`'s device recording into `run`, stopping at the next
+// chunk once `cancel` is cancelled and reporting each file and chunk to `progress`
+pub fn process_full_wgs_dataset_in(run: &RunContext, gpu: &GpuContext, config: &WgsConfig, cancel: &CancellationToken,
+                                   progress: &mut dyn FnMut(Progress)) -> Result<WgsRunReport, String> {
+    wgs_on(run, gpu, gpu.info(), config, cancel, progress)
+}
+
+fn wgs_on(run: &RunContext, gpu: &GpuContext, device: &GpuDevice, config: &WgsConfig, cancel: &CancellationToken,
+          progress: &mut dyn FnMut(Progress)) -> Result<WgsRunReport, String> {
+    // This thread and the stage threads record into the run
+    let _entered = run.enter();
+    let run_number = run.run().number;
+    let run_start = std::time::Instant::now();
+    let files = config.order.apply(config.selection.apply(config.discover_files())?);
+    crate::aggregation::validate(&config.aggregates)?;
+    if config.deterministic && config.adaptive_chunks.is_some() {
+        return Err("An adaptive chunk size can't be combined with deterministic mode; set a fixed chunk size".to_string());
+    }
+    crate::determinism::set_enabled(config.deterministic);
+    let memory = run.memory();
+    memory.start(config.memory_budget);
+    if let Some(budget) = config.memory_budget {
+        let planned = memory.check_plan(config, budget)?;
+        println!("Memory profile: strict, {} budget ({} planned for chunks and launches)", format_bytes(budget), format_bytes(planned));
+    }
     
     let total_files = files.len();
-    let chunk_size_reads: usize = get_chunk_size_reads()?;
+    let chunk_size = config.launch_chunk_size();
+    let alignment_input = &config.alignment_input;
+    let read_output = config.read_output.as_ref();
     
     // Display chunk size info (no confirmation required)
     println!("==========================================");
     println!("🚀 GPU PROCESSING STARTING 🚀");
     println!("==========================================");
-    println!("GPU_CHUNK_SIZE_READS: {} (from .env)", chunk_size_reads);
-    println!("Kernel launches per file: ~{}", (51858562 + chunk_size_reads - 1) / chunk_size_reads);
-    println!("Total kernel launches: ~{}", ((51858562 + chunk_size_reads - 1) / chunk_size_reads) * total_files);
+    // Long-read mode has one read per chunk whatever the size
+    let adaptive_target = config.adaptive_chunks.filter(|_| config.long_reads.is_none());
+    match adaptive_target {
+        Some(target) => println!("Chunk size: adaptive, starting at {} per file ({} ms kernel target)", chunk_size, target),
+        None => println!("Chunk size: {}", chunk_size),
+    }
+    if let Some(long_reads) = &config.long_reads {
+        println!("Long-read mode: {}", long_reads.name());
+    }
+    if !config.aggregates.is_empty() {
+        println!("Aggregates: {}", config.aggregates.join(", "));
+    }
+    let expectations = &config.expectations;
+    let expected_launches = match chunk_size {
+        ChunkSize::Reads(_) => expectations.reads.map(|reads| chunk_size.estimated_chunks(reads, 0)),
+        ChunkSize::Bases(_) => expectations.bases.map(|bases| chunk_size.estimated_chunks(0, bases)),
+    };
+    if let Some(launches) = expected_launches {
+        println!("Kernel launches per file: ~{}", launches / total_files.max(1));
+        println!("Total kernel launches: ~{}", launches);
+    }
     println!("==========================================");
     
     // Generate run ID for checkpointing
     let run_id = format!("wgs_{}", chrono::Utc::now().timestamp());
     
-    // Check for existing checkpoint
-    let mut checkpoint_state = match CheckpointState::load(&run_id)? {
-        Some(state) => {
-            println!("Found existing checkpoint: {} files completed", state.completed_files);
-            state
+    // Continue from the latest checkpoint with --resume, after checking it against this run and device
+    let mut checkpoint_state = if config.resume {
+        crate::resume::resume(run_number, gpu, device, config, &files)?
+    } else {
+        println!("Starting fresh run (--resume continues from the latest checkpoint)");
+        let mut state = CheckpointState::new(run_id.clone(), total_files);
+        state.provenance = Some(RunProvenance::current(config));
+        state.devices.push(device.name.clone());
+        crate::output_layout::record_device(run_number, &device.name)?;
+        state
+    };
+    checkpoint_state.compress = config.compress_checkpoints;
+    if let Some(read_group) = &config.read_group {
+        println!("Read group: {} (sample {})", read_group.id, read_group.sample);
+        crate::output_layout::record_read_group(run_number, read_group)?;
+    }
+    crate::output_layout::record_file_order(run_number, config.order.name(), &files)?;
+    
+    // --qc reads flow cell positions from the names, so they are kept
+    let name_mode = match read_output {
+        Some(options) => options.name_mode,
+        None if config.qc.is_some() => ReadNameMode::Keep,
+        None => ReadNameMode::Discard,
+    };
+    let mut read_writer = match read_output {
+        Some(options) => {
+            println!("Per-read results: {} ({})", options.path, options.format.name());
+            let mut writer = ReadResultWriter::create(options, config.read_group.as_ref(), config.long_reads.is_some())?;
+            writer.hold_against(memory);
+            Some(writer)
         },
-        None => {
-            println!("No existing checkpoint found, starting fresh run ");
-            CheckpointState::new(run_id.clone(), total_files)
-        }
+        None => None,
     };
     
+    // This thread drives the GPU for the rest of the run
+    crate::affinity::pin_gpu_submit_thread();
+    
+    let queue_count = if read_writer.is_some() { QueueCount::Fixed(1) } else { config.gpu_queues };
+    let mut launches = LaunchPool::<ChunkLaunch>::on(gpu, queue_count)?.scheduled(run.gpu_job().cloned()).held_against(memory);
+    match queue_count {
+        QueueCount::Auto => println!("GPU queues: auto (tuning 1 to {} on the first chunks)", crate::gpu_queues::MAX_QUEUES),
+        QueueCount::Fixed(_) => println!("GPU queues: {}", launches.queues()),
+    }
+    
     // Start benchmarking
-    start_benchmark("full_wgs", chunk_size_reads, true);
+    run.start_benchmark("full_wgs", chunk_size, true);
+    read_binning::start(config.binning);
+    if let Some(target) = adaptive_target {
+        crate::adaptive_chunks::start(chunk_size, max_launch_bases(device), target);
+    }
+    crate::read_chunk::keep_qualities(config.qc.is_some() || config.read_trim.is_some_and(|trim| trim.window.is_some()));
+    // The alphabet picked on the first chunk holds for the rest of the run
+    let mut sanitizer = config.sanitize.map(Sanitizer::new);
+    let mut sanitize_total = SanitizeStats::default();
+    let mut trimmer = config.trim.clone().map(Trimmer::new);
+    let mut trim_total = TrimStats::default();
+    if let Some(trim) = &config.trim {
+        println!("Long-read trimming: {}", trim.name());
+    }
+    let read_trimmer = config.read_trim.map(ReadTrimmer::new);
+    let mut read_trim_total = ReadTrimStats::default();
+    if let Some(read_trim) = &config.read_trim {
+        println!("Read trimming: {}", read_trim.name());
+    }
+    let mut panel = config.screen.as_ref().map(Panel::build).transpose()?;
+    if let Some(panel) = panel.as_mut() {
+        panel.hold_against(memory)?;
+    }
+    let mut screen_total = ScreenStats::new(panel.as_ref().map_or(0, |p| p.names.len()));
+    if let Some(panel) = &panel {
+        println!("Contamination screen: {} ({} sampled k-mers)", panel.names.join(", "), panel.sketch_size());
+    }
+    let mut qc_report = QcReport::default();
+    let mut audit = match ChunkAudit::create(&run.run().logs_dir, alignment_input) {
+        Ok(audit) => {
+            println!("Chunk audit log: {}", audit.path().display());
+            Some(audit)
+        },
+        Err(e) => {
+            warnings::warn(Category::Storage, format!("{}; chunks aren't audited", e));
+            None
+        },
+    };
     
-    println!("Processing {} files (your complete genome)...", total_files);
-    println!("Estimated total reads: ~415 million ");
-    println!("Estimated total base pairs: ~62 billion ");
-    println!("Estimated genome coverage: ~19x");
-    println!("Checkpoint file: checkpoint_{}.json ", run_id);
+    println!("Processing {} files...", total_files);
+    expectations.print_expected();
+    println!("Checkpoint file: {} ", CheckpointState::path_for(run_number).display());
     println!("==========================================");
     
     // Process files sequentially to maintain checkpointing
-    let mut results = Vec::new();
+    let mut report = WgsRunReport::new(run_number);
+    tui::set_files(&files, expectations.reads);
     
-    for (i, file) in files.iter().enumerate() {
+    // Files left to process; a file whose stalled reader was killed goes back to
+    // the front (watchdog.rs)
+    let mut queue: std::collections::VecDeque<usize> = (0..files.len()).collect();
+    let mut stall_retries = vec![0; files.len()];
+    while let Some(i) = queue.pop_front() {
+        let file = &files[i];
         // Skip if already completed
         if checkpoint_state.is_file_completed(i) {
-            println!("Skipping file {}/{} (already completed): {}", i+1, total_files, file.split('/').last().unwrap());
+            println!("Skipping file {}/{} (already completed): {}", i+1, total_files, crate::paths::file_name(file));
             // Find the existing result
             if let Some(existing) = checkpoint_state.files.iter().find(|f| f.file_index == i) {
-                results.push(GpuAlignmentResult {
+                let mut file_report = FileReport {
+                    index: i,
+                    path: file.clone(),
+                    status: FileStatus::Resumed,
                     score: existing.score,
+                    total_reads: existing.total_reads,
+                    total_bases: existing.total_bases,
+                    chunks: 0,
+                    skipped_chunks: 0,
                     processing_time_ms: existing.processing_time_ms,
                     gpu_device: device.name.clone(),
+                    normalized: existing.normalized,
+                    error: None,
+                    excluded_reads: 0,
+                    excluded_bases: 0,
+                    excluded_fraction: 0.0,
+                    is_partial: false,
+                    aggregates: existing.aggregates.clone(),
+                };
+                file_report.exclude(existing.excluded_reads, existing.excluded_bases);
+                if let Some(writer) = read_writer.as_mut() {
+                    writer.write_file_summary(&file_report)?;
+                }
+                report.add_file(file_report);
+                tui::file_skipped(i, existing.total_reads, existing.total_bases);
+                progress(Progress {
+                    stage: ProgressStage::FileSkipped, file: file.clone(), file_index: i, total_files, chunks: 0,
+                    reads: existing.total_reads, bases: existing.total_bases, score: existing.score as i64, elapsed: run_start.elapsed(),
                 });
             }
             continue;
         }
+        if let Some(failure) = checkpoint_state.quarantined(i) {
+            println!("Skipping file {}/{} (quarantined after {} failed attempts): {}", i+1, total_files, failure.attempts, crate::paths::file_name(file));
+            let mut file_report = FileReport {
+                index: i,
+                path: file.clone(),
+                status: FileStatus::Quarantined,
+                score: 0,
+                total_reads: 0,
+                total_bases: 0,
+                chunks: 0,
+                skipped_chunks: 0,
+                processing_time_ms: 0.0,
+                gpu_device: device.name.clone(),
+                normalized: NormalizedScore::default(),
+                error: failure.errors.last().cloned(),
+                excluded_reads: 0,
+                excluded_bases: 0,
+                excluded_fraction: 0.0,
+                is_partial: false,
+                aggregates: BTreeMap::new(),
+            };
+            file_report.exclude(0, 0);
+            if let Some(writer) = read_writer.as_mut() {
+                writer.write_file_summary(&file_report)?;
+            }
+            report.add_file(file_report);
+            tui::file_finished(i, false);
+            crate::exit_codes::record_quarantined_file();
+            continue;
+        }
         
-        println!("Processing file {}/{}: {}", i+1, total_files, file.split('/').last().unwrap());
+        println!("Processing file {}/{}: {}", i+1, total_files, crate::paths::file_name(file));
+        let _file_scope = crate::profiler::scope("process_file");
+        tui::file_started(i);
+        crate::file_timing::start_file();
+        crate::watchdog::start_file(i, file);
         let start_time = std::time::Instant::now();
         let mut total_score = 0;
+        let mut aggregates = FileAggregates::start(&config.aggregates)?;
         let mut processed_chunks = 0;
         let mut total_bases = 0;
         let mut total_reads = 0;
-        println!("    Using chunk size: {} reads ", chunk_size_reads);
+        println!("    Using chunk size: {}", chunk_size);
         
-        let process_result = process_fastq_file_in_chunks(file, chunk_size_reads, |chunk| {
-            let seq = chunk.concat();
-            total_bases += seq.len();
-            total_reads += chunk.len();
-            
-            match gpu_align_chunk_self(&seq, device) {
+        let mut chunk_index = 0;
+        crate::adaptive_chunks::start_file(i);
+        let mut masked_bases = 0;
+        let mut n_bases = 0;
+        let mut sanitize_stats = SanitizeStats::default();
+        let mut trim_stats = TrimStats::default();
+        let mut read_trim_stats = ReadTrimStats::default();
+        let mut screen_stats = ScreenStats::new(screen_total.kmer_hits.len());
+        let mut qc = config.qc.clone().map(|options| {
+            let mut qc = QcCollector::new(options);
+            qc.hold_against(memory);
+            qc
+        });
+        // Scores that have arrived, for progress while `complete` holds total_score
+        let mut arrived_score = 0i64;
+        let mut first_chunk_score = None;
+        let mut chunk_errors = Vec::new();
+        // Reads and bases of the chunks skipped after failing to align
+        let (mut excluded_reads, mut excluded_bases) = (0, 0);
+        progress(Progress {
+            stage: ProgressStage::FileStarted, file: file.clone(), file_index: i, total_files, chunks: 0,
+            reads: 0, bases: 0, score: 0, elapsed: run_start.elapsed(),
+        });
+        
+        // Scores arrive when their launch finishes, which can be a few chunks later
+        let mut complete = |launch: ChunkLaunch, result: Result<i32, String>| -> Result<Option<i32>, String> {
+            if let Some(log) = audit.as_mut() {
+                let record = ChunkRecord {
+                    file_index: i, file, chunk_index: launch.chunk_index, first_read: launch.first_read, reads: launch.file_reads,
+                    bases: launch.bases, score: result.as_ref().copied().map_err(|e| e.as_str()), launch: launch.submitted.elapsed(),
+                };
+                if let Err(e) = log.record(&record) {
+                    warnings::warn(Category::Storage, format!("{}; no more chunks are audited", e));
+                    audit = None;
+                }
+            }
+            let outcome = match result {
                 Ok(score) => {
                     total_score += score;
+                    aggregates.add(&ChunkScore { chunk_index: launch.chunk_index, reads: launch.reads, bases: launch.bases, score });
                     processed_chunks += 1;
+                    if launch.chunk_index == 1 {
+                        first_chunk_score = Some(score);
+                    }
                     if processed_chunks % 10 == 0 {
-                        println!("    Processed {} chunks ({} reads), current score: {}", processed_chunks, chunk.len(), total_score);
+                        println!("    Processed {} chunks ({} reads), current score: {}", processed_chunks, launch.reads, total_score);
                         // Update benchmark progress
-                        update_benchmark_progress(i+1, total_reads, total_bases, total_score);
+                        run.update_benchmark_progress(i+1, launch.total_reads, launch.total_bases, total_score);
                     }
+                    Ok(Some(score))
                 },
                 Err(e) => {
-                    println!("    Warning: Failed to align chunk {}: {}", processed_chunks, e);
+                    // Skipping chunks wouldn't bring memory back under the budget
+                    if config.deterministic || memory.exceeded() {
+                        return Err(format!("chunk {} failed to align: {}", launch.chunk_index, e));
+                    }
+                    warnings::warn(Category::Chunks, format!("Failed to align chunk {}: {}", processed_chunks, e));
+                    crate::exit_codes::record_skipped_chunk(launch.reads, launch.bases);
+                    excluded_reads += launch.reads;
+                    excluded_bases += launch.bases;
+                    chunk_errors.push(format!("file {} chunk {}: {}", i+1, launch.chunk_index, e));
+                    tui::error(format!("file {} chunk {}: {}", i+1, launch.chunk_index, e));
+                    Ok(None)
+                }
+            };
+            run.benchmark_chunk_done();
+            outcome
+        };
+        
+        // GPU stage: launches each encoded chunk and passes it on with the scores
+        // that arrived; stops when the encoder is done or the writer has stopped
+        let mut score_chunks = |encoded: StageReceiver<EncodedChunk>, scored_tx: StageSender<ScoredChunk>| -> Result<(), String> {
+            let _stage = StageClock::start(Stage::Gpu);
+            let mut last_chunk_done = std::time::Instant::now();
+            while let Some(item) = encoded.recv() {
+                let _scope = crate::profiler::scope("align_chunk");
+                cancel.check()?;
+                let EncodedChunk { launch, first_read, chunk, masked, .. } = &item;
+                let launch = ChunkLaunch { submitted: std::time::Instant::now(), ..*launch };
+                // Self-alignment of the chunk: a measure of its internal sequence similarity
+                let query = masked.as_deref().unwrap_or(chunk.bases());
+                let mut read_score = None;
+                let finished = if let Some(long_reads) = &config.long_reads {
+                    // The chunk is one read; its tiles are launched and waited for here
+                    let scored = crate::long_reads::score_read(gpu, device, query, chunk.bases(), long_reads);
+                    let result = scored.as_ref().map(|read| read.score).map_err(|e| e.clone());
+                    read_score = scored.ok();
+                    vec![(launch, result)]
+                } else if chunk.bases().len() < MIN_CHUNK_BASES {
+                    vec![(launch, Ok(0))]
+                } else {
+                    launches.submit(launch, query, chunk.bases(), device)
+                };
+                let mut scores = Vec::new();
+                for (launch, result) in finished {
+                    let Some(score) = complete(launch, result)? else { continue };
+                    arrived_score += score as i64;
+                    scores.push(score);
+                }
+                tui::chunk_done(i, chunk.len(), chunk.bases().len());
+                progress(Progress {
+                    stage: ProgressStage::Chunk, file: file.clone(), file_index: i, total_files, chunks: launch.chunk_index,
+                    reads: launch.total_reads, bases: launch.total_bases, score: arrived_score, elapsed: run_start.elapsed(),
+                });
+                // Includes the wait for this chunk, so reader stalls show up too
+                chunk_latency::record(file, launch.chunk_index, *first_read, last_chunk_done.elapsed());
+                last_chunk_done = std::time::Instant::now();
+                let bases = chunk.bases().len();
+                if scored_tx.send(ScoredChunk { encoded: item, scores, read_score }, bases).is_err() {
+                    break;
                 }
             }
             Ok(())
+        };
+        
+        // The file's stages (see stages.rs): this thread drives the GPU while the
+        // reader, encoder and writer run on threads of their own
+        let process_result = std::thread::scope(|scope| {
+            let (parsed_tx, parsed) = stages::channel(Stage::Parser, Stage::Encoder, Stage::Parser.channel_depth());
+            let (encoded_tx, encoded) = stages::channel(Stage::Encoder, Stage::Gpu, Stage::Encoder.channel_depth());
+            let (scored_tx, scored) = stages::channel(Stage::Gpu, Stage::Writer, Stage::Gpu.channel_depth());
+            let (recycle, recycled) = std::sync::mpsc::channel();
+            
+            let reader = scope.spawn(move || {
+                let _entered = run.enter();
+                crate::affinity::pin_reader_thread();
+                read_file_chunks(file, alignment_input, chunk_size, name_mode, parsed_tx, ChunkPool::new(recycled, memory.clone()))
+            });
+            
+            // Encoder stage: the chunk as the GPU will see it
+            let (sanitizer, sanitize_stats, trimmer, trim_stats) = (&mut sanitizer, &mut sanitize_stats, &mut trimmer, &mut trim_stats);
+            let (read_trimmer, read_trim_stats) = (&read_trimmer, &mut read_trim_stats);
+            let (panel, screen_stats, qc) = (&panel, &mut screen_stats, &mut qc);
+            let (chunk_index, total_reads, total_bases, n_bases, masked_bases) = (&mut chunk_index, &mut total_reads, &mut total_bases, &mut n_bases, &mut masked_bases);
+            let encoder = scope.spawn(move || -> Result<(), String> {
+                let _entered = run.enter();
+                let _stage = StageClock::start(Stage::Encoder);
+                // Sanitizing, trimming and binning copy into the spare and swap it in
+                let mut spare = ReadChunk::with_names(chunk_size.capacity_reads(), name_mode);
+                spare.hold_against(memory);
+                while let Some(mut chunk) = parsed.recv() {
+                    tui::set_queue_depth(parsed.len(), parsed.capacity());
+                    let (first_read, file_reads) = (*total_reads, chunk.len());
+                    *total_bases += chunk.bases().len();
+                    *total_reads += chunk.len();
+                    *chunk_index += 1;
+                    if let Some(sanitizer) = sanitizer.as_mut() {
+                        sanitizer.sanitize_into(&chunk, &mut spare, sanitize_stats);
+                        std::mem::swap(&mut chunk, &mut spare);
+                    }
+                    if let Some(trimmer) = trimmer.as_mut() {
+                        trimmer.trim_into(&chunk, &mut spare, trim_stats);
+                        std::mem::swap(&mut chunk, &mut spare);
+                    }
+                    if let Some(read_trimmer) = read_trimmer {
+                        read_trimmer.trim_into(&chunk, &mut spare, read_trim_stats);
+                        std::mem::swap(&mut chunk, &mut spare);
+                    }
+                    if let Some(panel) = panel {
+                        panel.screen_chunk(&chunk, screen_stats);
+                    }
+                    if let Some(qc) = qc.as_mut() {
+                        qc.add_chunk(&chunk);
+                        qc.account()?;
+                    }
+                    if read_binning::reorder_into(&chunk, config.binning, &mut spare) {
+                        std::mem::swap(&mut chunk, &mut spare);
+                    }
+                    chunk.account()?;
+                    spare.account()?;
+                    
+                    let launch = ChunkLaunch { chunk_index: *chunk_index, reads: chunk.len(), bases: chunk.bases().len(), total_reads: *total_reads, total_bases: *total_bases,
+                                               first_read, file_reads, submitted: std::time::Instant::now() };
+                    *n_bases += crate::n_policy::count_n(chunk.bases());
+                    // Launch copy of the bases with low-complexity runs set to N
+                    let masked = config.mask_low_complexity.then(|| {
+                        let mut masked = Vec::new();
+                        *masked_bases += low_complexity::mask_chunk(&chunk, &mut masked);
+                        masked
+                    });
+                    let masked_budget = Reservation::reserve(memory, Site::Chunks, masked.as_ref().map_or(0, |m| m.capacity()))?;
+                    let bases = chunk.bases().len();
+                    if encoded_tx.send(EncodedChunk { launch, first_read, chunk, masked, _masked_budget: masked_budget }, bases).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+            
+            // Writer stage: per-read output, then the chunk goes back to be refilled
+            let read_writer = &mut read_writer;
+            let writer = scope.spawn(move || -> Result<(), String> {
+                let _entered = run.enter();
+                let _stage = StageClock::start(Stage::Writer);
+                while let Some(ScoredChunk { encoded, scores, read_score }) = scored.recv() {
+                    let EncodedChunk { launch, chunk, .. } = encoded;
+                    if let Some(writer) = read_writer.as_mut() {
+                        // Per-read output runs with one queue, so the scores are this chunk's
+                        for score in scores {
+                            match &read_score {
+                                Some(read) if !chunk.is_empty() => writer.write_long_read(&chunk, i, launch.chunk_index, read)?,
+                                _ => writer.write_chunk(&chunk, i, launch.chunk_index, score)?,
+                            }
+                        }
+                    }
+                    stages::add_item(Stage::Writer, chunk.bases().len());
+                    let _ = recycle.send(chunk);
+                }
+                Ok(())
+            });
+            
+            // Returning drops this end of the channels, so a stage that stopped
+            // early stops the ones on either side of it
+            let scored = score_chunks(encoded, scored_tx);
+            let joined = |stage: Stage, result: std::thread::Result<Result<(), String>>| {
+                result.unwrap_or_else(|_| Err(format!("{} stage panicked", stage.name())))
+            };
+            let written = joined(Stage::Writer, writer.join());
+            let encoded = joined(Stage::Encoder, encoder.join());
+            let read = joined(Stage::Reader, reader.join().map(|read| read.map(|_| ())));
+            scored.and(written).and(encoded).and(read)
         });
+        println!("    Processed {} total reads in {} chunks", total_reads, chunk_index);
+        
+        // Collect the launches still in flight at the end of the file
+        let process_result = launches.drain().into_iter()
+            .try_fold((), |_, (launch, result)| complete(launch, result).map(|_| ()))
+            .and(process_result);
+        let stall = crate::watchdog::finish_file();
+        let process_result = match &stall {
+            Some(stall) => Err(stall.message.clone()),
+            None => process_result,
+        };
         
         let processing_time = start_time.elapsed();
+        crate::file_timing::finish_file(i, file, process_result.is_ok(), total_reads, total_bases, processing_time);
+        println!("    Stages: {}", stages::summary(&stages::snapshot()));
+        
+        let skipped_chunks = chunk_errors.len();
+        let failed_chunks = chunk_errors.clone();
+        report.errors.append(&mut chunk_errors);
+        let mut file_report = FileReport {
+            index: i,
+            path: file.clone(),
+            status: FileStatus::Completed,
+            score: total_score,
+            total_reads,
+            total_bases,
+            chunks: chunk_index,
+            skipped_chunks,
+            processing_time_ms: processing_time.as_millis() as f64,
+            gpu_device: device.name.clone(),
+            normalized: NormalizedScore::from_totals(total_score as i64, total_reads, total_bases),
+            error: None,
+            excluded_reads: 0,
+            excluded_bases: 0,
+            excluded_fraction: 0.0,
+            is_partial: false,
+            aggregates: aggregates.values(),
+        };
         
         match process_result {
             Ok(_) => {
-                println!("  File {} complete: Score={}, Bases={}, Time: {:.2} s ", i+1, total_score, total_bases, processing_time.as_secs_f64());
+                tui::file_finished(i, true);
+                file_report.exclude(excluded_reads, excluded_bases);
+                println!("  File {} complete: Score={}, Bases={}, Time: {:.2} s{}", i+1, total_score, total_bases, processing_time.as_secs_f64(), file_report.partial_note());
+                println!("    Normalized: {}", NormalizedScore::from_totals(total_score as i64, total_reads, total_bases).summary());
+                if !file_report.aggregates.is_empty() {
+                    println!("    Aggregates: {}", crate::aggregation::summary(&file_report.aggregates));
+                }
+                println!("    N bases: {} ({:.3}%, scored as {})", n_bases, n_bases as f64 / total_bases.max(1) as f64 * 100.0, crate::n_policy::selected().name());
+                if sanitizer.is_some() {
+                    println!("    Sanitized {}", sanitize_stats.summary());
+                    sanitize_total.add(&sanitize_stats);
+                }
+                if trimmer.is_some() {
+                    println!("    Trimmed {}", trim_stats.summary());
+                    trim_total.add(&trim_stats);
+                }
+                if read_trimmer.is_some() {
+                    println!("    Read trimming: {}", read_trim_stats.summary());
+                    read_trim_total.add(&read_trim_stats);
+                }
+                if config.mask_low_complexity {
+                    println!("    Masked: {} bases ({:.2}%) low-complexity", masked_bases, masked_bases as f64 / total_bases.max(1) as f64 * 100.0);
+                }
+                if let Some(panel) = &panel {
+                    println!("    Contamination: {}", screen_stats.summary(panel));
+                    screen_total.add(&screen_stats);
+                }
+                if let Some(qc) = qc.take() {
+                    let file_qc = qc.finish(file);
+                    println!("    QC: {}", file_qc.summary());
+                    qc_report.files.push(file_qc);
+                }
                 
                 // Save checkpoint for this file
                 let file_checkpoint = FileCheckpoint {
@@ -303,20 +930,50 @@ pub fn process_full_wgs_dataset(device: &GpuDevice) -> Result<Vec<GpuAlignmentRe
                     total_bases,
                     total_reads,
                     completed: true,
+                    normalized: NormalizedScore::from_totals(total_score as i64, total_reads, total_bases),
+                    masked_bases,
+                    n_bases,
+                    contamination: contamination(panel.as_ref(), &screen_stats),
+                    first_chunk_score,
+                    excluded_reads,
+                    excluded_bases,
+                    aggregates: file_report.aggregates.clone(),
                 };
                 
-                if let Err(e) = checkpoint_state.add_file_result(file_checkpoint) {
-                    println!("Warning: Failed to save checkpoint: {}", e);
+                if let Err(e) = checkpoint_state.add_file_result(run_number, file_checkpoint) {
+                    warnings::warn(Category::Checkpoints, format!("Failed to save checkpoint: {}", e));
                 }
-                
-                results.push(GpuAlignmentResult {
-                    score: total_score,
-                    processing_time_ms: processing_time.as_millis() as f64,
-                    gpu_device: device.name.clone(),
+                progress(Progress {
+                    stage: ProgressStage::FileFinished, file: file.clone(), file_index: i, total_files, chunks: chunk_index,
+                    reads: total_reads, bases: total_bases, score: total_score as i64, elapsed: run_start.elapsed(),
                 });
+                
+                if let Some(writer) = read_writer.as_mut() {
+                    writer.write_file_summary(&file_report)?;
+                }
+                report.add_file(file_report);
             },
             Err(e) => {
                 println!("  File {} failed: {}", i+1, e);
+                tui::file_finished(i, false);
+                tui::error(format!("file {} failed: {}", i+1, e));
+                
+                // Retried from the start, unless per-read rows of it were written already
+                if stall.as_ref().is_some_and(|stall| stall.retry) && stall_retries[i] < crate::watchdog::STALL_RETRIES && read_writer.is_none() {
+                    stall_retries[i] += 1;
+                    println!("  Retrying file {} after its reader stalled (retry {} of {})", i+1, stall_retries[i], crate::watchdog::STALL_RETRIES);
+                    queue.push_front(i);
+                    continue;
+                }
+                
+                // A cancelled or preempted run didn't fail the file
+                // Nor did going over the --memory-profile strict budget
+                let quarantined = !cancel.is_cancelled() && !memory.exceeded() && {
+                    let failure = checkpoint_state.record_failure(i, file, &e, &failed_chunks, config.quarantine_after);
+                    println!("    Failed attempt {} at this file{}", failure.attempts,
+                             if config.quarantine_after > 0 { format!(" (quarantined at {})", config.quarantine_after) } else { String::new() });
+                    failure.quarantined
+                };
                 
                 // Save partial checkpoint for failed file
                 let file_checkpoint = FileCheckpoint {
@@ -327,49 +984,305 @@ pub fn process_full_wgs_dataset(device: &GpuDevice) -> Result<Vec<GpuAlignmentRe
                     total_bases,
                     total_reads,
                     completed: false,
+                    normalized: NormalizedScore::from_totals(total_score as i64, total_reads, total_bases),
+                    masked_bases,
+                    n_bases,
+                    contamination: contamination(panel.as_ref(), &screen_stats),
+                    first_chunk_score,
+                    excluded_reads,
+                    excluded_bases,
+                    aggregates: file_report.aggregates.clone(),
                 };
                 
-                if let Err(e) = checkpoint_state.add_file_result(file_checkpoint) {
-                    println!("Warning: Failed to save checkpoint: {}", e);
+                if let Err(e) = checkpoint_state.add_file_result(run_number, file_checkpoint) {
+                    warnings::warn(Category::Checkpoints, format!("Failed to save checkpoint: {}", e));
+                }
+                
+                if !checkpoint_state.failures.is_empty() {
+                    match crate::quarantine::save_report(run_number, config.quarantine_after, &checkpoint_state.failures) {
+                        Ok(path) => println!("    Failures: {}", path.display()),
+                        Err(e) => warnings::warn(Category::Storage, format!("Failed to save the failure report: {}", e)),
+                    }
+                }
+                file_report.error = Some(e.clone());
+                if quarantined {
+                    println!("  File {} quarantined; continuing with the other files", i+1);
+                    file_report.status = FileStatus::Quarantined;
+                    file_report.exclude(excluded_reads, excluded_bases);
+                    if let Some(writer) = read_writer.as_mut() {
+                        writer.write_file_summary(&file_report)?;
+                    }
+                    report.add_file(file_report);
+                    save_report(&report);
+                    crate::exit_codes::record_quarantined_file();
+                    continue;
+                }
+                file_report.status = FileStatus::Failed;
+                file_report.exclude(excluded_reads, excluded_bases);
+                // Finished so the results so far are readable (Parquet needs its footer)
+                if let Some(mut writer) = read_writer.take() {
+                    let finished = writer.write_file_summary(&file_report).and_then(|_| writer.finish());
+                    if let Err(e) = finished {
+                        warnings::warn(Category::Storage, format!("Failed to finish per-read results: {}", e));
+                    }
                 }
+                report.add_file(file_report);
+                save_report(&report);
                 
+                if cancel.is_cancelled() && stall.is_none() {
+                    return Err(format!("Run cancelled during file {}; completed files are checkpointed", i+1));
+                }
                 return Err(format!("File {} failed: {}", i+1, e));
             }
         }
     }
+    crate::adaptive_chunks::stop();
     
     // Finish benchmarking with final totals from checkpoint
-    let final_totals = checkpoint_state.files.iter().fold((0, 0, 0, 0), |(files, reads, bases, score), file| {
+    let final_totals = checkpoint_state.files.iter().filter(|file| file.completed).fold((0, 0, 0, 0), |(files, reads, bases, score), file| {
         (files + 1, reads + file.total_reads, bases + file.total_bases, score + file.score)
     });
     
     // Update benchmark with final totals before finishing
-    update_benchmark_progress(final_totals.0, final_totals.1, final_totals.2, final_totals.3);
+    run.update_benchmark_progress(final_totals.0, final_totals.1, final_totals.2, final_totals.3);
+    run.set_benchmark_excluded(report.totals.excluded_bases, report.totals.is_partial);
     
-    if let Some(benchmark_result) = finish_benchmark() {
+    if let Some(benchmark_result) = run.finish_benchmark() {
         println!("BENCHMARK RESULTS:");
         println!("=====================");
         println!("Total time: {:.2} s ", benchmark_result.total_time_seconds);
-        println!("Throughput: {:.0} reads/s, {:.0} bases/s ", 
-                benchmark_result.throughput_reads_per_second, benchmark_result.throughput_bases_per_second);
+        println!("Throughput: {:.0} reads/s, {:.0} bases/s{}", 
+                benchmark_result.throughput_reads_per_second, benchmark_result.throughput_bases_per_second,
+                crate::wgs_report::partial_note(benchmark_result.is_partial, benchmark_result.excluded_fraction));
         println!("GPU utilization: {:.1} %", benchmark_result.gpu_utilization_avg);
-        println!("Results saved to: benchmark_results.json ");
+        benchmark_result.gpu_timing.print();
+        benchmark_result.chunk_latency.print();
+        benchmark_result.binning.print();
+        benchmark_result.chunk_adaptation.print();
+        if let Some(sanitizer) = &sanitizer {
+            println!("Sanitizer ({} alphabet): {}", sanitizer.alphabet().map_or("undetected", |a| a.name()), sanitize_total.summary());
+        }
+        if trimmer.is_some() {
+            println!("Long-read trimming: {}", trim_total.summary());
+        }
+        if read_trimmer.is_some() {
+            println!("Read trimming: {}", read_trim_total.summary());
+        }
+        if let Some(panel) = &panel {
+            println!("Contamination: {}", screen_total.summary(panel));
+        }
+        println!("Normalized score: {}", benchmark_result.normalized_score.summary());
+        benchmark_result.cost.print();
+        if config.memory_budget.is_some() {
+            println!("Memory: {}", memory.summary());
+        }
+        println!("Results saved to: {} ", crate::output_layout::result_file("benchmark_results.json").display());
     }
     
-    println!("All files completed! Checkpoint saved to: checkpoint_{}.json ", run_id);
+    if let Some(writer) = read_writer {
+        let rows = writer.finish()?;
+        println!("Wrote {} per-read results", rows);
+    }
+    if config.qc.is_some() {
+        let path = qc_report.save()?;
+        println!("QC report ({} files): {}", qc_report.files.len(), path.display());
+    }
     
-    Ok(results)
+    save_report(&report);
+    println!("All files completed! Checkpoint saved to: {} ", crate::checkpoint_store::current(&CheckpointState::path_for(run_number)).display());
+    
+    Ok(report)
 }
 
-// Self-alignment of a single chunk (for full WGS processing)
-fn gpu_align_chunk_self(chunk: &str, device: &GpuDevice) -> Result<i32, String> {
-    if chunk.len() < 1000 {
-        return Ok(0); // Skip very small chunks (less than 1000 bases)
+// A report that can't be written is a warning; the checkpoint has the scores
+fn save_report(report: &WgsRunReport) {
+    match report.save() {
+        Ok(path) => println!("Run report: {}", path.display()),
+        Err(e) => warnings::warn(Category::Checkpoints, format!("Failed to save the run report: {}", e)),
     }
-    
-    // For self-alignment, we'll align the chunk against itself
-    // This gives us a measure of internal sequence similarity
-    gpu_align(chunk, chunk, device)
+}
+
+// Per-reference fractions for the checkpoint; empty without --screen
+fn contamination(panel: Option<&Panel>, stats: &ScreenStats) -> Vec<(String, f64)> {
+    panel.map_or_else(Vec::new, |panel| panel.names.iter().cloned().zip(stats.fractions()).collect())
+}
+
+// Skip very small chunks (less than 1000 bases); they score 0
+pub(crate) const MIN_CHUNK_BASES: usize = 1000;
+
+// A chunk's launch, with the running file totals at the time it was submitted
+#[derive(Debug, Clone, Copy)]
+struct ChunkLaunch {
+    chunk_index: usize,
+    reads: usize,
+    bases: usize,
+    total_reads: usize,
+    total_bases: usize,
+    // The file's reads in the chunk as read, for the audit log
+    first_read: usize,
+    file_reads: usize,
+    submitted: std::time::Instant,
+}
+
+// Encoder to GPU: the chunk to launch, and its bases with low-complexity runs
+// masked when that's on (with what the copy holds of the memory budget)
+struct EncodedChunk {
+    launch: ChunkLaunch,
+    first_read: usize,
+    chunk: ReadChunk,
+    masked: Option<Vec<u8>>,
+    _masked_budget: Reservation,
+}
+
+// GPU to writer: the scores that arrived while the chunk was launched
+struct ScoredChunk {
+    encoded: EncodedChunk,
+    scores: Vec<i32>,
+    read_score: Option<crate::long_reads::ReadScore>,
+}
+
+// What a chunk callback returns to end a read early once the encoder has stopped
+const DOWNSTREAM_STOPPED: &str = "encoder stopped";
+
+// Reader and parser stages of one WGS file, sending chunks into `tx`; BAM/CRAM
+// and memory-mapped inputs parse as they read, on this thread, while everything
+// else goes through the async input layer. Returns the reads sent
+fn read_file_chunks(file: &str, alignment_input: &AlignmentInputOptions, chunk_size: ChunkSize, name_mode: ReadNameMode,
+                    tx: StageSender<ReadChunk>, pool: ChunkPool) -> Result<usize, String> {
+    let mut reads = 0;
+    let result = if let Some(format) = alignment_input.alignment_format(file)? {
+        // BAM/CRAM: reads come straight out of the alignment file
+        let _stage = StageClock::start(Stage::Reader);
+        let mut clock = crate::file_timing::ReaderClock::decoding();
+        process_alignment_file_in_chunks(file, format, alignment_input, chunk_size, name_mode, |chunk| {
+            reads += chunk.len();
+            let mut copy = chunk.clone();
+            pool.hold(&mut copy);
+            copy.account()?;
+            let waited = std::time::Instant::now();
+            let sent = tx.send(copy, chunk.bases().len());
+            clock.add_blocked(waited.elapsed());
+            sent.map_err(|_| DOWNSTREAM_STOPPED.to_string())
+        })
+    } else if let Some(mut packed) = crate::packed_reads::PackedReader::for_file(file, name_mode)? {
+        // Prepared input: reads are unpacked from the cache, with nothing to decompress or parse
+        println!("    Reading {} ({} reads, {} bases)", packed.path(), packed.reads(), packed.bases());
+        let _stage = StageClock::start(Stage::Reader);
+        let mut clock = crate::file_timing::ReaderClock::new();
+        let mut more = true;
+        let mut sent = Ok(());
+        while more && sent.is_ok() {
+            let mut chunk = pool.next_empty(chunk_size, name_mode);
+            while !chunk_size.is_full(chunk.len(), chunk.bases().len()) {
+                more = packed.next_read(&mut chunk)?;
+                if !more {
+                    break;
+                }
+            }
+            if chunk.is_empty() {
+                break;
+            }
+            chunk.account()?;
+            reads += chunk.len();
+            let bases = chunk.bases().len();
+            let waited = std::time::Instant::now();
+            sent = tx.send(chunk, bases).map_err(|_| DOWNSTREAM_STOPPED.to_string());
+            clock.add_blocked(waited.elapsed());
+        }
+        sent
+    } else if mmap_input::is_enabled() && mmap_input::can_map(file) {
+        // Uncompressed local input: reads are slices into the map, copied once into a chunk
+        // Page faults aren't separated out, so all reader time counts as parsing
+        let _stage = StageClock::start(Stage::Reader);
+        let mut clock = crate::file_timing::ReaderClock::new();
+        mmap_input::process_mapped_file_in_chunks(file, chunk_size, |records| {
+            let mut chunk = pool.next_empty(chunk_size, name_mode);
+            for record in records {
+                chunk.push_name(record.id);
+                chunk.push_read_lines(record.seq_lines());
+                if let Some(qual) = record.qual {
+                    chunk.push_quality(qual);
+                }
+            }
+            chunk.account()?;
+            reads += chunk.len();
+            let bases = chunk.bases().len();
+            let waited = std::time::Instant::now();
+            let sent = tx.send(chunk, bases);
+            clock.add_blocked(waited.elapsed());
+            sent.map_err(|_| DOWNSTREAM_STOPPED.to_string())
+        })
+    } else {
+        // Reads and decompression run on the async input layer
+        return stream_input_chunks(file, chunk_size, name_mode, tx, pool);
+    };
+    match result {
+        Err(e) if e != DOWNSTREAM_STOPPED => Err(e),
+        _ => Ok(reads),
+    }
+}
+
+// The reader and parser stages of a WGS file on their own (bench io, bench
+// compute): each chunk is handed to `each` on this thread and nothing is
+// aligned; `each` returns false to stop reading. Returns the reads read
+pub fn read_file_only(file: &str, alignment_input: &AlignmentInputOptions, chunk_size: ChunkSize, name_mode: ReadNameMode,
+                      mut each: impl FnMut(&ReadChunk) -> bool) -> Result<usize, String> {
+    std::thread::scope(|scope| {
+        let (parsed_tx, parsed) = stages::channel(Stage::Parser, Stage::Encoder, Stage::Parser.channel_depth());
+        let (recycle, recycled) = std::sync::mpsc::channel();
+        let pool = ChunkPool::new(recycled, Default::default());
+        let run = crate::run_context::current();
+        let reader = scope.spawn(move || {
+            let _entered = run.enter();
+            read_file_chunks(file, alignment_input, chunk_size, name_mode, parsed_tx, pool)
+        });
+        while let Some(chunk) = parsed.recv() {
+            if !each(&chunk) {
+                break;
+            }
+            let _ = recycle.send(chunk);
+        }
+        // Dropping the receiver stops a reader that is still going
+        drop(parsed);
+        reader.join().unwrap_or_else(|_| Err(format!("{} stage panicked", Stage::Reader.name())))
+    })
+}
+
+// Every chunk of file1 against every chunk of file2, summed
+pub(crate) fn align_pair_chunks(file1: &str, file2: &str, chunk_size: ChunkSize,
+                                mut score: impl FnMut(&[u8], &[u8]) -> Result<i32, String>) -> Result<(i32, Vec<ContigScore>), String> {
+    align_pair_chunks_with(file1, file2, chunk_size, |pair| score(pair.seq1, pair.seq2))
+}
+
+// align_pair_chunks, passing each chunk's qualities too where it has them (a
+// Scorer's); contig windows have none
+pub(crate) fn align_pair_chunks_with(file1: &str, file2: &str, chunk_size: ChunkSize,
+                                     mut score: impl FnMut(&ScorePair) -> Result<i32, String>) -> Result<(i32, Vec<ContigScore>), String> {
+    let mut total_score = 0;
+    if !crate::contig_chunks::is_contig_target(file2)? {
+        process_fastq_file_in_chunks(file1, chunk_size, |chunk1| {
+            process_fastq_file_in_chunks(file2, chunk_size, |chunk2| {
+                let pair = ScorePair { seq1: chunk1.bases(), seq2: chunk2.bases(), quality1: chunk1.qualities(), quality2: chunk2.qualities() };
+                total_score += score(&pair)?;
+                Ok(())
+            })
+        })?;
+        return Ok((total_score, Vec::new()));
+    }
+    // A FASTA target is aligned window by window, never across contigs
+    let mut tally = ContigTally::default();
+    let mut query_chunk = 0;
+    process_fastq_file_in_chunks(file1, chunk_size, |chunk1| {
+        query_chunk += 1;
+        crate::contig_chunks::for_each_window(file2, chunk_size, |window| {
+            let pair = ScorePair { quality1: chunk1.qualities(), ..ScorePair::new(chunk1.bases(), window.bases) };
+            let window_score = score(&pair)?;
+            total_score += window_score;
+            tally.add(window, query_chunk, window_score);
+            Ok(())
+        })
+    })?;
+    Ok((total_score, tally.into_scores()))
 }
 
 // GPU alignment for a single pair of files
@@ -379,50 +1292,140 @@ pub fn gpu_align_pair(file1: &str, file2: &str, device: &GpuDevice) -> Result<Gp
     let bases2 = count_bases_in_fastq(file2)?;
     println!("Loaded {} bases from {}", bases1, file1);
     println!("Loaded {} bases from {}", bases2, file2);
-    // For actual alignment, just use the chunked logic (no need to load all into memory)
-    // Use chunked alignment for large sequences
+    // Chunked alignment, so neither file is loaded into memory whole
     let start_time = std::time::Instant::now();
-    let score = {
-        // We'll just align the first chunk of each file for demonstration (or you can implement full pairwise chunked alignment)
-        // For now, use the same chunked logic as before
-        let mut total_score = 0;
-        let chunk_size = get_chunk_size_reads()?;
-        process_fastq_file_in_chunks(file1, chunk_size, |chunk1| {
-            process_fastq_file_in_chunks(file2, chunk_size, |chunk2| {
-                let seq1 = chunk1.concat();
-                let seq2 = chunk2.concat();
-                total_score += gpu_align(&seq1, &seq2, device)?;
-                Ok(())
-            })?;
-            Ok(())
-        })?;
-        total_score
-    };
+    let (score, contigs) = align_pair_chunks(file1, file2, chunking::get()?, |bytes1, bytes2| gpu_align_bytes(bytes1, bytes2, device))?;
     let processing_time = start_time.elapsed();
     Ok(GpuAlignmentResult {
         score,
         processing_time_ms: processing_time.as_millis() as f64,
         gpu_device: device.name.clone(),
+        total_reads: 0,
+        total_bases: 0,
+        contigs,
+    })
+}
+
+// CPU counterpart of gpu_align_pair using the SIMD CPU path
+pub fn cpu_align_pair(file1: &str, file2: &str) -> Result<GpuAlignmentResult, String> {
+    let start_time = std::time::Instant::now();
+    let (score, contigs) = align_pair_chunks(file1, file2, chunking::get()?, |bytes1, bytes2| Ok(crate::cpu_align::cpu_align_bytes(bytes1, bytes2)))?;
+    Ok(GpuAlignmentResult {
+        score,
+        processing_time_ms: start_time.elapsed().as_millis() as f64,
+        gpu_device: format!("CPU ({})", crate::cpu_align::selected_level().name()),
+        total_reads: 0,
+        total_bases: 0,
+        contigs,
     })
 }
 
 // Main GPU alignment function for two sequences using OpenCL
 pub fn gpu_align(seq1: &str, seq2: &str, device: &GpuDevice) -> Result<i32, String> {
-    let bytes1 = seq1.as_bytes();
-    let bytes2 = seq2.as_bytes();
-    let len = bytes1.len().min(bytes2.len());
-    if len == 0 {
+    gpu_align_bytes(seq1.as_bytes(), seq2.as_bytes(), device)
+}
+
+// (work groups, work group size) for a launch over `len` positions
+pub fn launch_geometry(len: usize, device: &GpuDevice) -> (usize, usize) {
+    let work_group_size = crate::determinism::work_group_size(device.max_work_group_size, GPU_WORK_GROUP_SIZE);
+    (len.div_ceil(work_group_size).min(GPU_MAX_WORK_GROUPS), work_group_size)
+}
+
+// Byte-level alignment entry point, used by readers that never build Strings
+pub fn gpu_align_bytes(bytes1: &[u8], bytes2: &[u8], device: &GpuDevice) -> Result<i32, String> {
+    gpu_align_bytes_with(bytes1, bytes2, device, crate::kernels::selected())
+}
+
+// gpu_align_bytes with an explicit kernel variant
+pub fn gpu_align_bytes_with(bytes1: &[u8], bytes2: &[u8], device: &GpuDevice, variant: KernelVariant) -> Result<i32, String> {
+    if bytes1.is_empty() || bytes2.is_empty() {
         return Ok(0);
     }
     
     // Use shared OpenCL context to prevent resource exhaustion
-    let (context, queue, _ocl_device) = get_opencl_context()
-        .map_err(|e| format!("Failed to get OpenCL context: {}", e))?;
-    // Calculate optimal OpenCL work group configuration
-    let work_group_size = device.max_work_group_size.min(GPU_WORK_GROUP_SIZE);
-    let work_groups_needed = (len + work_group_size - 1) / work_group_size;
-    let work_groups = work_groups_needed.min(GPU_MAX_WORK_GROUPS);
+    let gpu = GpuContext::shared()?;
     
+    // Note: Async memory operations would require more complex OpenCL setup
+    // For now, we use the standard synchronous approach which is still very efficient
+    enqueue_alignment(gpu.queue(), gpu.program()?, bytes1, bytes2, device, variant)?.wait()
+}
+
+// gpu_align_bytes on a given context, e.g. one per device from library threads
+pub fn gpu_align_on(gpu: &GpuContext, bytes1: &[u8], bytes2: &[u8]) -> Result<i32, String> {
+    if bytes1.is_empty() || bytes2.is_empty() {
+        return Ok(0);
+    }
+    enqueue_alignment(gpu.queue(), gpu.program()?, bytes1, bytes2, gpu.info(), crate::kernels::selected())?.wait()
+}
+
+pub const KERNEL_SOURCE: &str = include_str!("smith_waterman.cl");
+
+// Create and build the OpenCL program
+pub fn build_program(context: &ocl::Context) -> Result<Program, String> {
+    build_program_scored(context, crate::scoring::selected())
+}
+
+// The OpenCL program built with other scores than the process-wide ones
+pub fn build_program_scored(context: &ocl::Context, scoring: crate::scoring::Scoring) -> Result<Program, String> {
+    Program::builder()
+        .src(KERNEL_SOURCE)
+        .cmplr_def("N_POLICY", crate::n_policy::selected().kernel_define())
+        .cmplr_def("MATCH_SCORE", scoring.match_score)
+        .cmplr_def("MISMATCH_PENALTY", scoring.mismatch_penalty)
+        .cmplr_def("GAP_PENALTY", scoring.gap_penalty)
+        .build(context)
+        .map_err(|e| format!("Failed to build OpenCL program: {}", e))
+}
+
+// A launch whose uploads are done and whose kernel may still be running
+pub struct PendingAlignment {
+    result_buffer: Buffer<i32>,
+    // Kept alive until the kernel has finished with them
+    _seq_buffers: (Buffer<u8>, Buffer<u8>),
+    // The uploads this launch is charged with: both sequences, or none, one or
+    // both of them against a resident target (resident_target.rs)
+    seq_writes: Vec<Event>,
+    // Sources of non-blocking uploads (launch_reuse.rs), kept until they finish
+    host_copies: Vec<Vec<u8>>,
+    kernel_event: Event,
+    variant: KernelVariant,
+    launch_start: std::time::Instant,
+}
+
+impl PendingAlignment {
+    // Wait for the kernel, read its score, and record the launch's timing
+    pub fn wait(mut self) -> Result<i32, String> {
+        // Read result after the kernel; the only time the host waits on the launch
+        let mut result = vec![0i32];
+        let mut result_read = Event::empty();
+        self.result_buffer.read(&mut result).ewait(&self.kernel_event).enew(&mut result_read).enq()
+            .map_err(|e| format!("Failed to read {} kernel result: {}", self.variant.name(), e))?;
+        self.host_copies.clear();
+        
+        let mut transfers = Vec::with_capacity(self.seq_writes.len() + 1);
+        for write in &self.seq_writes {
+            transfers.push(CommandTimes::from_event(write)?);
+        }
+        transfers.push(CommandTimes::from_event(&result_read)?);
+        gpu_timing::record_launch(&transfers, &CommandTimes::from_event(&self.kernel_event)?, self.launch_start.elapsed().as_nanos() as u64);
+        Ok(result[0])
+    }
+}
+
+impl Drop for PendingAlignment {
+    // A launch dropped unwaited (e.g. on an error) mustn't free what its
+    // uploads are still reading
+    fn drop(&mut self) {
+        if !self.host_copies.is_empty() {
+            for write in &self.seq_writes {
+                let _ = write.wait_for();
+            }
+        }
+    }
+}
+
+// GPU memory this process may use, after the safety margin and --gpu-fraction
+pub fn memory_budget_bytes(device: &GpuDevice) -> usize {
     // Use centralized system information for memory calculations
     let available_memory_bytes = if let Ok(system_info) = crate::system_info::get_system_info() {
         system_info.available_gpu_memory_bytes()
@@ -431,17 +1434,64 @@ pub fn gpu_align(seq1: &str, seq2: &str, device: &GpuDevice) -> Result<i32, Stri
         let gpu_memory_gb = device.memory_gb;
         (gpu_memory_gb * 0.8) as usize * 1024 * 1024 * 1024
     };
-    
+    crate::gpu_share::memory_budget(available_memory_bytes)
+}
+
+// Longest sequence one launch accepts
+pub fn max_launch_bases(device: &GpuDevice) -> usize {
     // OpenCL work group limits
     let max_work_items = GPU_MAX_WORK_GROUPS * GPU_WORK_GROUP_SIZE; // 16,777,216
     let max_sequence_by_work_groups = max_work_items;
     
     // Memory-based limit (accounting for both sequences + overhead)
-    let max_sequence_by_memory = available_memory_bytes / 3; // Balanced: 3x overhead for stability
+    let max_sequence_by_memory = memory_budget_bytes(device) / 3; // Balanced: 3x overhead for stability
     
     // Use the smaller of the two limits
-    let max_sequence_size = max_sequence_by_work_groups.min(max_sequence_by_memory);
+    max_sequence_by_work_groups.min(max_sequence_by_memory)
+}
+
+// Upload both sequences on `queue` and enqueue the kernel without waiting for it
+// The uploads copy `bytes1`/`bytes2` (or block, with launch reuse off), so the
+// caller can reuse them on return
+pub fn enqueue_alignment(queue: &ocl::Queue, program: &Program, bytes1: &[u8], bytes2: &[u8], device: &GpuDevice, variant: KernelVariant) -> Result<PendingAlignment, String> {
+    let (bytes1, bytes2) = (crate::n_policy::prepare(bytes1), crate::n_policy::prepare(bytes2));
+    let len = bytes1.len().min(bytes2.len());
+    check_launch_len(len, device)?;
+    
+    // Log memory usage for optimization
+    let buffer_size_mb = (bytes1.len() + bytes2.len()) / (1024 * 1024);
+    println!("Buffer size: {} MB (seq1: {} MB, seq2: {} MB)", 
+             buffer_size_mb, bytes1.len() / (1024 * 1024), bytes2.len() / (1024 * 1024));
     
+    let launch_start = std::time::Instant::now();
+    if !crate::launch_reuse::enabled() {
+        let (seq1_buffer, seq1_write) = upload_sequence(queue, &bytes1, "seq1")?;
+        let (seq2_buffer, seq2_write) = upload_sequence(queue, &bytes2, "seq2")?;
+        let launch = SequenceLaunch { seq1: &seq1_buffer, seq2: &seq2_buffer, len, seq_writes: vec![seq1_write, seq2_write], launch_start };
+        return enqueue_kernel(queue, program, launch, device, variant);
+    }
+    let host_copies = vec![bytes1.into_owned(), bytes2.into_owned()];
+    let seq1_buffer = sequence_buffer(queue, host_copies[0].len(), "seq1")?;
+    let seq2_buffer = sequence_buffer(queue, host_copies[1].len(), "seq2")?;
+    let mut seq_writes = vec![Event::empty(), Event::empty()];
+    let written = write_unblocked(&seq1_buffer, &host_copies[0], &mut seq_writes[0], "seq1")
+        .and_then(|_| write_unblocked(&seq2_buffer, &host_copies[1], &mut seq_writes[1], "seq2"));
+    let launch = SequenceLaunch { seq1: &seq1_buffer, seq2: &seq2_buffer, len, seq_writes, launch_start };
+    match written.and_then(|_| enqueue_kernel(queue, program, launch, device, variant)) {
+        Ok(mut pending) => {
+            pending.host_copies = host_copies;
+            Ok(pending)
+        },
+        Err(e) => {
+            // Nothing may still be reading the host copies once they're dropped
+            let _ = queue.finish();
+            Err(e)
+        },
+    }
+}
+
+fn check_launch_len(len: usize, device: &GpuDevice) -> Result<(), String> {
+    let max_sequence_size = max_launch_bases(device);
     if len > max_sequence_size {
         let gpu_memory_gb = if let Ok(system_info) = crate::system_info::get_system_info() {
             system_info.gpu_memory_gb
@@ -451,17 +1501,23 @@ pub fn gpu_align(seq1: &str, seq2: &str, device: &GpuDevice) -> Result<i32, Stri
         
         return Err(format!(
             "Sequence too large ({} bytes), max allowed: {} bytes ({} MB). GPU: {:.1}GB, Work groups: {}",
-            len, max_sequence_size, max_sequence_size / (1024*1024), gpu_memory_gb, max_work_items
+            len, max_sequence_size, max_sequence_size / (1024*1024), gpu_memory_gb, GPU_MAX_WORK_GROUPS * GPU_WORK_GROUP_SIZE
         ));
     }
-    
-    println!("OpenCL Grid: {} work groups x {} work items = {} total work items ", 
-             work_groups, work_group_size, work_groups * work_group_size);
-    
-    // Log memory usage for optimization
-    let buffer_size_mb = (bytes1.len() + bytes2.len()) / (1024 * 1024);
-    println!("Buffer size: {} MB (seq1: {} MB, seq2: {} MB)", 
-             buffer_size_mb, bytes1.len() / (1024 * 1024), bytes2.len() / (1024 * 1024));
+    Ok(())
+}
+
+// A read-only device buffer holding `bytes` (already through the N policy),
+// written with an explicit, blocking write so its profiling event can be timed
+pub fn upload_sequence(queue: &ocl::Queue, bytes: &[u8], what: &str) -> Result<(Buffer<u8>, Event), String> {
+    let buffer = sequence_buffer(queue, bytes.len(), what)?;
+    let mut write = Event::empty();
+    buffer.write(bytes).enew(&mut write).enq()
+        .map_err(|e| format!("Failed to write {} buffer: {}", what, e))?;
+    Ok((buffer, write))
+}
+
+fn sequence_buffer(queue: &ocl::Queue, len: usize, what: &str) -> Result<Buffer<u8>, String> {
     // Check if pinned memory should be used
     let use_pinned = std::env::var("USE_PINNED_MEMORY")
         .unwrap_or_else(|_| "false".to_string())
@@ -471,73 +1527,127 @@ pub fn gpu_align(seq1: &str, seq2: &str, device: &GpuDevice) -> Result<i32, Stri
     let buffer_flags = if use_pinned {
         MemFlags::new().read_only().alloc_host_ptr()
     } else {
-        MemFlags::new().read_only().copy_host_ptr()
+        MemFlags::new().read_only()
     };
     
-    // Create OpenCL buffers with async memory operations for better bandwidth utilization
-    let seq1_buffer = Buffer::<u8>::builder()
+    Buffer::<u8>::builder()
         .queue(queue.clone())
         .flags(buffer_flags)
-        .len(bytes1.len())
-        .copy_host_slice(bytes1)
+        .len(len)
         .build()
-        .map_err(|e| format!("Failed to create seq1 buffer: {}", e))?;
+        .map_err(|e| format!("Failed to create {} buffer: {}", what, e))
+}
+
+// Enqueue a write of `bytes` without waiting for it; the caller keeps `bytes`
+// alive and unchanged until `write` has finished
+fn write_unblocked(buffer: &Buffer<u8>, bytes: &[u8], write: &mut Event, what: &str) -> Result<(), String> {
+    // SAFETY: enqueue_alignment moves the bytes into the PendingAlignment, which
+    // waits for the write before they are dropped (wait, Drop, or queue.finish)
+    unsafe { buffer.write(bytes).block(false).enew(write).enq() }
+        .map_err(|e| format!("Failed to write {} buffer: {}", what, e))
+}
+
+// Two uploaded sequences, aligned over their first `len` positions
+pub struct SequenceLaunch<'a> {
+    pub seq1: &'a Buffer<u8>,
+    pub seq2: &'a Buffer<u8>,
+    pub len: usize,
+    pub seq_writes: Vec<Event>,
+    pub launch_start: std::time::Instant,
+}
+
+// Enqueue the kernel on sequences already on the device
+pub fn enqueue_kernel(queue: &ocl::Queue, program: &Program, launch: SequenceLaunch, device: &GpuDevice, variant: KernelVariant) -> Result<PendingAlignment, String> {
+    let len = launch.len;
+    check_launch_len(len, device)?;
     
-    let seq2_buffer = Buffer::<u8>::builder()
-        .queue(queue.clone())
-        .flags(buffer_flags)
-        .len(bytes2.len())
-        .copy_host_slice(bytes2)
-        .build()
-        .map_err(|e| format!("Failed to create seq2 buffer: {}", e))?;
+    // Calculate optimal OpenCL work group configuration
+    let (work_groups, work_group_size) = launch_geometry(len, device);
+    
+    // The packed kernel runs one work item per four lanes of the same geometry
+    let local_size = match variant {
+        KernelVariant::Packed if work_group_size % PACKED_LANES != 0 => {
+            return Err(format!("packed kernel needs a work group size divisible by {}, got {}", PACKED_LANES, work_group_size));
+        },
+        KernelVariant::Packed => work_group_size / PACKED_LANES,
+        _ => work_group_size,
+    };
     
+    println!("OpenCL Grid: {} work groups x {} work items = {} total work items ", 
+             work_groups, local_size, work_groups * local_size);
+    
+    // The kernel folds into this with atomic_max, so it has to start at zero
     let result_buffer = Buffer::<i32>::builder()
         .queue(queue.clone())
         .flags(MemFlags::new().write_only())
         .len(1)
+        .fill_val(0)
         .build()
         .map_err(|e| format!("Failed to create result buffer: {}", e))?;
     
-    // Note: Async memory operations would require more complex OpenCL setup
-    // For now, we use the standard synchronous approach which is still very efficient
-    // Create and build OpenCL program
-    let program_src = include_str!("smith_waterman.cl");
-    let program = Program::builder()
-        .src(program_src)
-        .build(&context)
-        .map_err(|e| format!("Failed to build OpenCL program: {}", e))?;
-    // Create kernel
-    let kernel = Kernel::builder()
-        .program(&program)
-        .name("smith_waterman_align")
-        .queue(queue.clone())
-        .global_work_size(work_groups * work_group_size)
-        .local_work_size(work_group_size)
-        .arg(&seq1_buffer)
-        .arg(&seq2_buffer)
-        .arg(&result_buffer)
-        .arg(&(len as u32))
-        .build()
-        .map_err(|e| format!("Failed to create kernel: {}", e))?;
-    // Execute kernel
-    unsafe {
-        kernel.enq().map_err(|e| format!("Failed to execute kernel: {}", e))?;
+    let length = len as u32;
+    let build = || {
+        let mut builder = Kernel::builder();
+        builder
+            .program(program)
+            .name(variant.entry_point())
+            .queue(queue.clone())
+            .global_work_size(work_groups * local_size)
+            .local_work_size(local_size)
+            .arg(launch.seq1)
+            .arg(launch.seq2)
+            .arg(&result_buffer)
+            .arg(&length);
+        if variant == KernelVariant::Tiled {
+            // Two tiles of 4 bases per work item, plus the reduction scratch
+            builder
+                .arg_local::<u8>(work_group_size * 4)
+                .arg_local::<u8>(work_group_size * 4)
+                .arg_local::<i32>(work_group_size);
+        }
+        if variant == KernelVariant::Packed {
+            builder.arg_local::<i32>(local_size);
+        }
+        builder.build()
+            .map_err(|e| format!("Failed to create {} kernel: {}", variant.name(), e))
+    };
+    let mut kernel_event = Event::empty();
+    if crate::launch_reuse::enabled() {
+        // The kept kernel, with this launch's arguments and geometry
+        let kernel = crate::launch_reuse::kernel(program, variant, work_group_size, build)?;
+        let kernel = kernel.lock().map_err(|e| format!("Failed to acquire {} kernel lock: {}", variant.name(), e))?;
+        kernel.set_arg(0, launch.seq1).and_then(|_| kernel.set_arg(1, launch.seq2))
+            .and_then(|_| kernel.set_arg(2, &result_buffer)).and_then(|_| kernel.set_arg(3, length))
+            .map_err(|e| format!("Failed to set {} kernel arguments: {}", variant.name(), e))?;
+        let cmd = kernel.cmd().queue(queue).global_work_size(work_groups * local_size).local_work_size(local_size);
+        let cmd = if launch.seq_writes.is_empty() { cmd } else { cmd.ewait(&launch.seq_writes[..]) };
+        unsafe {
+            cmd.enew(&mut kernel_event).enq().map_err(|e| format!("Failed to execute kernel: {}", e))?;
+        }
+    } else {
+        let kernel = build()?;
+        unsafe {
+            kernel.cmd().enew(&mut kernel_event).enq().map_err(|e| format!("Failed to execute kernel: {}", e))?;
+        }
     }
-    // Wait for completion
-    queue.finish().map_err(|e| format!("Failed to wait for kernel completion: {}", e))?;
-    // Read result
-    let mut result = vec![0i32];
-    result_buffer.read(&mut result).enq().map_err(|e| format!("Failed to read result: {}", e))?;
-    Ok(result[0])
+    Ok(PendingAlignment {
+        result_buffer,
+        _seq_buffers: (launch.seq1.clone(), launch.seq2.clone()),
+        seq_writes: launch.seq_writes,
+        host_copies: Vec::new(),
+        kernel_event,
+        variant,
+        launch_start: launch.launch_start,
+    })
 }
 
 /// Count total bases in a FASTQ file (compressed or uncompressed), streaming and chunked
 pub fn count_bases_in_fastq(filepath: &str) -> Result<usize, String> {
     let mut total_bases = 0usize;
     // Use centralized chunk size for efficiency
-    let chunk_size = get_chunk_size_reads()?;
+    let chunk_size = chunking::get()?;
     process_fastq_file_in_chunks(filepath, chunk_size, |chunk| {
-        total_bases += chunk.iter().map(|seq| seq.len()).sum::<usize>();
+        total_bases += chunk.bases().len();
         Ok(())
     })?;
     Ok(total_bases)
@@ -0,0 +1,222 @@
+// BAM/CRAM input: reads already stored in alignment files are fed to the
+// aligner directly, without converting to FASTQ first
+// Secondary and supplementary records are skipped and reverse-strand reads are
+// flipped back to their sequenced orientation, as `samtools fastq` does
+
+use crate::chunking::ChunkSize;
+use crate::input_format::{detect_path, SeqFormat};
+use crate::read_chunk::{ReadChunk, ReadNameMode};
+use noodles::sam::alignment::Record;
+use noodles::{bam, cram, fasta, sam};
+
+// Input format requested on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    // Sniffed from the content
+    #[default]
+    Auto,
+    Fastx,
+    Bam,
+    Cram,
+}
+
+impl InputFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "auto" => Ok(InputFormat::Auto),
+            "fastq" | "fasta" => Ok(InputFormat::Fastx),
+            "bam" => Ok(InputFormat::Bam),
+            "cram" => Ok(InputFormat::Cram),
+            other => Err(format!("Invalid --input-format '{}': expected auto, fastq, fasta, bam, or cram", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InputFormat::Auto => "auto",
+            InputFormat::Fastx => "fastq",
+            InputFormat::Bam => "bam",
+            InputFormat::Cram => "cram",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AlignmentInputOptions {
+    pub format: InputFormat,
+    // samtools-style region such as chr1:10000-20000 (needs a .bai/.crai index)
+    pub region: Option<String>,
+    // FASTA (with .fai) for CRAMs that reference external sequences
+    pub reference: Option<String>,
+}
+
+impl AlignmentInputOptions {
+    // Alignment format to read `path` with, or None for FASTA/FASTQ
+    pub fn alignment_format(&self, path: &str) -> Result<Option<SeqFormat>, String> {
+        match self.format {
+            InputFormat::Bam => Ok(Some(SeqFormat::Bam)),
+            InputFormat::Cram => Ok(Some(SeqFormat::Cram)),
+            InputFormat::Fastx => Ok(None),
+            InputFormat::Auto => {
+                if path.contains("://") {
+                    return Ok(None);
+                }
+                Ok(detect_path(path)?.format.filter(|f| f.is_alignment()))
+            },
+        }
+    }
+}
+
+pub(crate) fn complement_base(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' | b'U' => b'A',
+        b'M' => b'K',
+        b'K' => b'M',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        other => other,
+    }
+}
+
+// Collects records into chunks and hands full chunks to the processor
+struct AlignmentChunker<'a, F> {
+    chunk: ReadChunk,
+    chunk_size: ChunkSize,
+    processor: &'a mut F,
+    // Reused buffer for the read in sequenced orientation
+    seq: Vec<u8>,
+    qual: Vec<u8>,
+    total_reads: usize,
+    skipped: usize,
+}
+
+impl<F> AlignmentChunker<'_, F>
+where F: FnMut(&ReadChunk) -> Result<(), String> {
+    fn push<R: Record>(&mut self, record: &R) -> Result<(), String> {
+        let flags = record.flags()
+            .map_err(|e| format!("Invalid flags in record {}: {}", self.total_reads + 1, e))?;
+        if flags.is_secondary() || flags.is_supplementary() {
+            self.skipped += 1;
+            return Ok(());
+        }
+
+        let name = record.name().map(|n| -> &[u8] { n.as_ref() }).unwrap_or(b"*");
+        self.chunk.push_name(name);
+        self.seq.clear();
+        self.seq.extend(record.sequence().iter());
+        if flags.is_reverse_complemented() {
+            self.seq.reverse();
+            for base in self.seq.iter_mut() {
+                *base = complement_base(*base);
+            }
+        }
+        self.chunk.push_read(&self.seq);
+        // Raw Phred scores as Phred+33, in sequenced orientation; 0xff means missing
+        self.qual.clear();
+        self.qual.extend(record.quality_scores().iter().filter_map(Result::ok).map(|q| q.saturating_add(33)));
+        // Kept empty when missing, so qualities stay in step with reads
+        if self.qual.contains(&0xff) {
+            self.qual.clear();
+        }
+        if flags.is_reverse_complemented() {
+            self.qual.reverse();
+        }
+        self.chunk.push_quality(&self.qual);
+        self.total_reads += 1;
+
+        if self.chunk_size.is_full(self.chunk.len(), self.chunk.bases().len()) {
+            (self.processor)(&self.chunk)?;
+            self.chunk.clear();
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(usize, usize), String> {
+        if !self.chunk.is_empty() {
+            (self.processor)(&self.chunk)?;
+        }
+        Ok((self.total_reads, self.skipped))
+    }
+}
+
+fn reference_repository(reference: Option<&str>) -> Result<fasta::Repository, String> {
+    match reference {
+        Some(path) => {
+            let reader = fasta::io::indexed_reader::Builder::default()
+                .build_from_path(path)
+                .map_err(|e| format!("Failed to open CRAM reference {} (needs a .fai index): {}", path, e))?;
+            Ok(fasta::Repository::new(fasta::repository::adapters::IndexedReader::new(reader)))
+        },
+        None => Ok(fasta::Repository::default()),
+    }
+}
+
+// Stream the reads of a BAM or CRAM file in chunks, optionally limited to a region
+pub fn process_alignment_file_in_chunks<F>(filepath: &str, format: SeqFormat, options: &AlignmentInputOptions,
+                                           chunk_size: ChunkSize, name_mode: ReadNameMode, mut processor: F) -> Result<(), String>
+where F: FnMut(&ReadChunk) -> Result<(), String> {
+    let region = options.region.as_deref()
+        .map(|r| r.parse::<noodles::core::Region>().map_err(|e| format!("Invalid region '{}': {}", r, e)))
+        .transpose()?;
+    let mut chunker = AlignmentChunker {
+        chunk: ReadChunk::with_names(chunk_size.capacity_reads(), name_mode),
+        chunk_size,
+        processor: &mut processor,
+        seq: Vec::new(),
+        qual: Vec::new(),
+        total_reads: 0,
+        skipped: 0,
+    };
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", filepath, e);
+
+    match (format, &region) {
+        (SeqFormat::Bam, None) => {
+            let mut reader = bam::io::reader::Builder.build_from_path(filepath).map_err(read_error)?;
+            reader.read_header().map_err(read_error)?;
+            for record in reader.records() {
+                chunker.push(&record.map_err(read_error)?)?;
+            }
+        },
+        (SeqFormat::Bam, Some(region)) => {
+            let mut reader = bam::io::indexed_reader::Builder::default().build_from_path(filepath)
+                .map_err(|e| format!("Failed to open {} with its .bai index: {}", filepath, e))?;
+            let header = reader.read_header().map_err(read_error)?;
+            for record in reader.query(&header, region).map_err(read_error)? {
+                chunker.push(&record.map_err(read_error)?)?;
+            }
+        },
+        (SeqFormat::Cram, None) => {
+            let mut reader = cram::io::reader::Builder::default()
+                .set_reference_sequence_repository(reference_repository(options.reference.as_deref())?)
+                .build_from_path(filepath)
+                .map_err(read_error)?;
+            let header: sam::Header = reader.read_header().map_err(read_error)?;
+            for record in reader.records(&header) {
+                chunker.push(&record.map_err(read_error)?)?;
+            }
+        },
+        (SeqFormat::Cram, Some(region)) => {
+            let mut reader = cram::io::indexed_reader::Builder::default()
+                .set_reference_sequence_repository(reference_repository(options.reference.as_deref())?)
+                .build_from_path(filepath)
+                .map_err(|e| format!("Failed to open {} with its .crai index: {}", filepath, e))?;
+            let header: sam::Header = reader.read_header().map_err(read_error)?;
+            for record in reader.query(&header, region).map_err(read_error)? {
+                chunker.push(&record.map_err(read_error)?)?;
+            }
+        },
+        (other, _) => return Err(format!("{} is not an alignment format", other.name())),
+    }
+
+    let (total_reads, skipped) = chunker.finish()?;
+    println!("    Processed {} reads from {} ({} secondary/supplementary skipped)",
+             total_reads, format.name(), skipped);
+    Ok(())
+}
@@ -0,0 +1,181 @@
+// ani: alignment-free average nucleotide identity between two genomes or
+// assemblies, in seconds where compare-assemblies aligns them end to end
+// Both are sketched with sketch.rs. B's whole sketch is kept; A is cut into
+// WINDOW-base windows (FastANI's fragments), each sketched on its own. A
+// window sharing a fraction c of its sampled k-mers with B is estimated at
+// c^(1/k) identity, since a k-mer survives only when all k of its bases match.
+// Windows at MIN_IDENTITY or above count as aligned: A -> B ANI is the mean
+// identity of the aligned windows, and the aligned fraction is their share of
+// A's windows. Both directions are reported, with their mean as the ANI.
+// Windows with fewer than MIN_WINDOW_KMERS sampled k-mers (mostly N runs)
+// are left out. Whole-genome containment and Jaccard are reported as well
+
+use crate::sketch::{for_each_sampled, Sketch, MAX_KMER};
+use rayon::prelude::*;
+use serde::Serialize;
+
+pub const DEFAULT_KMER: usize = 17;
+pub const DEFAULT_SCALE: u64 = 10;
+pub const DEFAULT_WINDOW: usize = 3000;
+// FastANI's cutoff: below it the estimate is mostly chance k-mers
+const MIN_IDENTITY: f64 = 0.8;
+const MIN_WINDOW_KMERS: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AniOptions {
+    pub k: usize,
+    pub scale: u64,
+    pub window: usize,
+}
+
+impl AniOptions {
+    pub fn new(k: usize, scale: u64, window: usize) -> Result<Self, String> {
+        if k == 0 || k > MAX_KMER {
+            return Err(format!("Invalid --kmer {}: expected 1 to {}", k, MAX_KMER));
+        }
+        if scale == 0 {
+            return Err("Invalid --scale 0: expected at least 1".to_string());
+        }
+        if window < k * MIN_WINDOW_KMERS {
+            return Err(format!("Invalid --window {}: expected at least {} bases for k = {}", window, k * MIN_WINDOW_KMERS, k));
+        }
+        Ok(Self { k, scale, window })
+    }
+}
+
+// One direction: A's windows against B's sketch
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AniDirection {
+    pub ani: f64,
+    pub aligned_fraction: f64,
+    pub windows: usize,
+    pub aligned_windows: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AniReport {
+    pub a: String,
+    pub b: String,
+    pub k: usize,
+    pub scale: u64,
+    pub window: usize,
+    pub a_bases: usize,
+    pub b_bases: usize,
+    pub a_sketch: usize,
+    pub b_sketch: usize,
+    // Mean of the two directions
+    pub ani: f64,
+    pub a_to_b: AniDirection,
+    pub b_to_a: AniDirection,
+    // Whole-genome shares of sampled k-mers
+    pub a_in_b: f64,
+    pub b_in_a: f64,
+    pub jaccard: f64,
+}
+
+fn sketch_of(sequences: &[(String, Vec<u8>)], options: &AniOptions) -> Sketch {
+    sequences.par_iter()
+        .map(|(_, sequence)| {
+            let mut sketch = Sketch::new(options.k, options.scale);
+            sketch.add(sequence);
+            sketch
+        })
+        .reduce(|| Sketch::new(options.k, options.scale), |mut all, sketch| {
+            all.hashes.extend(sketch.hashes);
+            all
+        })
+}
+
+fn direction(query: &[(String, Vec<u8>)], target: &Sketch, options: &AniOptions) -> AniDirection {
+    let max_hash = crate::sketch::max_hash(options.scale);
+    let windows: Vec<Option<f64>> = query.par_iter()
+        .flat_map_iter(|(_, sequence)| sequence.chunks(options.window))
+        .filter_map(|window| {
+            let (mut kmers, mut shared) = (0, 0);
+            for_each_sampled(window, options.k, max_hash, |hash| {
+                kmers += 1;
+                shared += target.hashes.contains(&hash) as usize;
+            });
+            if kmers < MIN_WINDOW_KMERS {
+                return None;
+            }
+            let identity = (shared as f64 / kmers as f64).powf(1.0 / options.k as f64);
+            Some((identity >= MIN_IDENTITY).then_some(identity))
+        })
+        .collect();
+    let aligned: Vec<f64> = windows.iter().flatten().copied().collect();
+    AniDirection {
+        ani: if aligned.is_empty() { 0.0 } else { aligned.iter().sum::<f64>() / aligned.len() as f64 },
+        aligned_fraction: if windows.is_empty() { 0.0 } else { aligned.len() as f64 / windows.len() as f64 },
+        windows: windows.len(),
+        aligned_windows: aligned.len(),
+    }
+}
+
+// ANI between two FASTAs (plain or .gz)
+pub fn estimate(a_path: &str, b_path: &str, options: &AniOptions) -> Result<AniReport, String> {
+    let a = crate::simulate::load_reference(a_path)?;
+    let b = crate::simulate::load_reference(b_path)?;
+    let (a_sketch, b_sketch) = rayon::join(|| sketch_of(&a, options), || sketch_of(&b, options));
+    if a_sketch.is_empty() || b_sketch.is_empty() {
+        return Err(format!("No k-mers sampled from {}; is it a nucleotide FASTA?", if a_sketch.is_empty() { a_path } else { b_path }));
+    }
+    let a_to_b = direction(&a, &b_sketch, options);
+    let b_to_a = direction(&b, &a_sketch, options);
+    Ok(AniReport {
+        a: a_path.to_string(),
+        b: b_path.to_string(),
+        k: options.k,
+        scale: options.scale,
+        window: options.window,
+        a_bases: a.iter().map(|(_, s)| s.len()).sum(),
+        b_bases: b.iter().map(|(_, s)| s.len()).sum(),
+        a_sketch: a_sketch.len(),
+        b_sketch: b_sketch.len(),
+        ani: (a_to_b.ani + b_to_a.ani) / 2.0,
+        a_in_b: a_sketch.containment(&b_sketch),
+        b_in_a: b_sketch.containment(&a_sketch),
+        jaccard: a_sketch.jaccard(&b_sketch),
+        a_to_b,
+        b_to_a,
+    })
+}
+
+// 1 - ANI between every pair of `sequences` (both directions averaged), for
+// distance matrices; pairs with no aligned window are 1
+pub fn distance_matrix(sequences: &[(String, Vec<u8>)], options: &AniOptions) -> Vec<Vec<f64>> {
+    let sketches: Vec<Sketch> = sequences.iter().map(|sequence| sketch_of(std::slice::from_ref(sequence), options)).collect();
+    let n = sequences.len();
+    let pairs: Vec<(usize, usize)> = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).collect();
+    let anis: Vec<f64> = pairs.par_iter()
+        .map(|&(i, j)| {
+            let forward = direction(std::slice::from_ref(&sequences[i]), &sketches[j], options);
+            let backward = direction(std::slice::from_ref(&sequences[j]), &sketches[i], options);
+            (forward.ani + backward.ani) / 2.0
+        })
+        .collect();
+    let mut distances = vec![vec![0.0; n]; n];
+    for (&(i, j), ani) in pairs.iter().zip(anis) {
+        distances[i][j] = 1.0 - ani;
+        distances[j][i] = 1.0 - ani;
+    }
+    distances
+}
+
+impl AniReport {
+    pub fn print(&self) {
+        println!("ANI (k = {}, 1 in {} k-mers, {}-base windows):", self.k, self.scale, self.window);
+        println!("   A: {} ({} bases, {} sampled k-mers)", self.a, self.a_bases, self.a_sketch);
+        println!("   B: {} ({} bases, {} sampled k-mers)", self.b, self.b_bases, self.b_sketch);
+        println!("   ANI: {:.2}%", self.ani * 100.0);
+        for (name, direction) in [("A -> B", &self.a_to_b), ("B -> A", &self.b_to_a)] {
+            println!("   {}: {:.2}% ANI, {:.2}% aligned ({} of {} windows)", name, direction.ani * 100.0,
+                     direction.aligned_fraction * 100.0, direction.aligned_windows, direction.windows);
+        }
+        println!("   Shared k-mers: {:.2}% of A in B, {:.2}% of B in A, Jaccard {:.4}",
+                 self.a_in_b * 100.0, self.b_in_a * 100.0, self.jaccard);
+        if self.a_to_b.aligned_windows == 0 && self.b_to_a.aligned_windows == 0 {
+            println!("   No window reached {:.0}% identity; the genomes are too distant for this estimate", MIN_IDENTITY * 100.0);
+        }
+    }
+}
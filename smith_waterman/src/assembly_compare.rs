@@ -0,0 +1,568 @@
+// compare-assemblies: end-to-end identity between two assemblies of one genome
+// Contigs are paired by name first; the rest of A are paired one to one with
+// the B contig sharing the most sampled k-mers (FracMinHash, sampled as
+// --screen samples reads). A pair's orientation is the strand most of A's
+// k-mers are found on in B. The pair is then aligned end to end in blocks of
+// BLOCK bases of A: an affine-gap global alignment within --band diagonals,
+// anchored where the previous block ended and free to end anywhere in B, so
+// long contigs never need a full DP matrix. Identical blocks skip the DP, and
+// a block keeps only its best-scoring prefix, so the next one starts where the
+// alignment was still good. A block under BREAK_IDENTITY (an indel wider than the band, a rearrangement
+// or a divergent region) is a breakpoint: alignment resumes at the next
+// k-mer A and B share exactly, and the spans skipped on either side are
+// reported. Insertions are bases in B missing from A, deletions the reverse
+// There is no banded GPU kernel in this tree, so pairs are aligned on the CPU,
+// in parallel
+
+use crate::alignment_input::complement_base;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+// Bases of A per banded alignment
+const BLOCK: usize = 4096;
+// Diagonals either side of the block's start diagonal, unless --band or a
+// --preset sets it
+pub const DEFAULT_BAND: usize = 32;
+const MISMATCH_COST: u32 = 4;
+const GAP_OPEN_COST: u32 = 6;
+const GAP_EXTEND_COST: u32 = 1;
+const BREAK_IDENTITY: f64 = 0.75;
+// Exact k-mers resume an alignment after a breakpoint; B is indexed every ANCHOR_STEP bases
+pub(crate) const ANCHOR_K: usize = 21;
+const ANCHOR_STEP: usize = 16;
+// Bases after an anchor that must mostly agree, so a chance k-mer isn't taken
+const ANCHOR_CHECK: usize = 64;
+// Pairing by shared k-mers: about one k-mer in PAIR_SCALE is sampled
+const PAIR_K: usize = 21;
+const PAIR_SCALE: u64 = 50;
+const MIN_SHARED_KMERS: usize = 3;
+// Pairs listed in the printed report; the TSV has all of them
+const REPORTED_PAIRS: usize = 20;
+
+// A span of A, 0-based half-open, where the alignment broke, and the span of B
+// skipped to where it resumed (on B's forward strand)
+#[derive(Debug, Clone, Serialize)]
+pub struct Breakpoint {
+    pub a_start: usize,
+    pub a_end: usize,
+    pub b_start: usize,
+    pub b_end: usize,
+    // b_insertion / b_deletion (the diagonal moved further than the band),
+    // rearrangement (B jumped backwards), or divergent (same diagonal)
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContigComparison {
+    pub a_contig: String,
+    pub b_contig: String,
+    // "name" or "kmer"
+    pub paired_by: String,
+    pub reverse: bool,
+    pub a_length: usize,
+    pub b_length: usize,
+    pub aligned_a: usize,
+    pub aligned_b: usize,
+    pub matches: usize,
+    pub mismatches: usize,
+    pub insertions: usize,
+    pub inserted_bases: usize,
+    pub deletions: usize,
+    pub deleted_bases: usize,
+    // Matches over aligned columns
+    pub identity: f64,
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ComparisonReport {
+    pub a_contigs: usize,
+    pub b_contigs: usize,
+    pub a_bases: usize,
+    pub b_bases: usize,
+    pub paired_by_name: usize,
+    pub paired_by_kmer: usize,
+    pub unpaired_a: Vec<String>,
+    pub unpaired_b: Vec<String>,
+    pub matches: usize,
+    pub mismatches: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub breakpoints: usize,
+    // Matches over aligned columns of every pair
+    pub identity: f64,
+    // Share of A's bases aligned, and of A's bases matched exactly in B
+    pub a_aligned_fraction: f64,
+    pub agreement: f64,
+    pub pairs: Vec<ContigComparison>,
+}
+
+// Counts of one block's alignment, or of a whole pair
+#[derive(Debug, Default, Clone, Copy)]
+struct Tally {
+    matches: usize,
+    mismatches: usize,
+    insertions: usize,
+    inserted_bases: usize,
+    deletions: usize,
+    deleted_bases: usize,
+    consumed_a: usize,
+    consumed_b: usize,
+}
+
+impl Tally {
+    fn columns(&self) -> usize {
+        self.matches + self.mismatches + self.inserted_bases + self.deleted_bases
+    }
+
+    fn identity(&self) -> f64 {
+        if self.columns() == 0 { 0.0 } else { self.matches as f64 / self.columns() as f64 }
+    }
+
+    fn add(&mut self, other: &Tally) {
+        self.matches += other.matches;
+        self.mismatches += other.mismatches;
+        self.insertions += other.insertions;
+        self.inserted_bases += other.inserted_bases;
+        self.deletions += other.deletions;
+        self.deleted_bases += other.deleted_bases;
+        self.consumed_a += other.consumed_a;
+        self.consumed_b += other.consumed_b;
+    }
+
+    // The best-scoring prefix of a block's alignment, so a block running into
+    // an indel wider than the band or a rearrangement stops where the good part
+    // ends and the next block finds the break
+    fn of_best_prefix(ops: &[Op]) -> Self {
+        let (mut score, mut best, mut best_length) = (0i64, 0i64, 0usize);
+        for (n, op) in ops.iter().enumerate() {
+            let extends = n > 0 && ops[n - 1] == *op;
+            score += match op {
+                Op::Match => 1,
+                Op::Mismatch => -3,
+                Op::Insertion | Op::Deletion if extends => -1,
+                Op::Insertion | Op::Deletion => -4,
+            };
+            if score > best {
+                (best, best_length) = (score, n + 1);
+            }
+        }
+        let mut tally = Tally::default();
+        for (n, op) in ops[..best_length].iter().enumerate() {
+            let opens = n == 0 || ops[n - 1] != *op;
+            match op {
+                Op::Match => tally.matches += 1,
+                Op::Mismatch => tally.mismatches += 1,
+                Op::Insertion => (tally.inserted_bases, tally.insertions) = (tally.inserted_bases + 1, tally.insertions + opens as usize),
+                Op::Deletion => (tally.deleted_bases, tally.deletions) = (tally.deleted_bases + 1, tally.deletions + opens as usize),
+            }
+            tally.consumed_a += (*op != Op::Insertion) as usize;
+            tally.consumed_b += (*op != Op::Deletion) as usize;
+        }
+        tally
+    }
+}
+
+// One alignment column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Match,
+    Mismatch,
+    // Base of B only
+    Insertion,
+    // Base of A only
+    Deletion,
+}
+
+// Calls `f` with the start, forward hash and reverse complement hash of
+// every ANCHOR_K-mer of A/C/G/T bases (rolling_hash.rs), until it returns false
+pub(crate) fn for_each_kmer(sequence: &[u8], f: impl FnMut(usize, u64, u64) -> bool) {
+    crate::rolling_hash::for_each_kmer(sequence, ANCHOR_K, f);
+}
+
+// Positions of B's k-mers that occur once among the sampled ones
+struct AnchorIndex {
+    positions: HashMap<u64, u32>,
+}
+
+impl AnchorIndex {
+    const REPEATED: u32 = u32::MAX;
+
+    fn build(b: &[u8]) -> Self {
+        let mut positions = HashMap::new();
+        for_each_kmer(b, |start, kmer, _| {
+            if start % ANCHOR_STEP == 0 {
+                positions.entry(kmer)
+                    .and_modify(|p| *p = Self::REPEATED)
+                    .or_insert(start as u32);
+            }
+            true
+        });
+        Self { positions }
+    }
+
+    fn get(&self, kmer: u64) -> Option<usize> {
+        self.positions.get(&kmer).filter(|&&p| p != Self::REPEATED).map(|&p| p as usize)
+    }
+
+    // Whether more of A's k-mers are found reverse complemented in B than as they are
+    fn reversed(&self, a: &[u8]) -> bool {
+        let (mut forward, mut reverse) = (0usize, 0usize);
+        for_each_kmer(a, |_, kmer, revcomp| {
+            forward += self.get(kmer).is_some() as usize;
+            reverse += self.get(revcomp).is_some() as usize;
+            true
+        });
+        reverse > forward
+    }
+
+    // First (a, b) at or after `from` in A where an exact k-mer match starts
+    // and the following bases mostly agree; `skip` rejects candidates
+    fn next_anchor(&self, a: &[u8], b: &[u8], from: usize, skip: impl Fn(usize, usize) -> bool) -> Option<(usize, usize)> {
+        let mut found = None;
+        for_each_kmer(&a[from.min(a.len())..], |start, kmer, _| {
+            let p = from + start;
+            let Some(q) = self.get(kmer) else { return true };
+            let check = ANCHOR_CHECK.min(a.len() - p).min(b.len() - q);
+            let agree = a[p..p + check].iter().zip(&b[q..q + check]).filter(|(x, y)| x == y).count();
+            if agree * 10 >= check * 9 && !skip(p, q) {
+                found = Some((p, q));
+            }
+            found.is_none()
+        });
+        found
+    }
+}
+
+const STATE_M: u8 = 0;
+const STATE_DEL: u8 = 1;
+const STATE_INS: u8 = 2;
+const INF: u32 = u32::MAX / 4;
+
+// (cost, state) of the cheapest of `costs` after adding each state's step cost
+fn cheapest(costs: [u32; 3], step: [u32; 3]) -> (u32, u8) {
+    (0..3).map(|s| (costs[s].saturating_add(step[s]), s as u8))
+        .min_by_key(|&(cost, _)| cost)
+        .unwrap_or((INF, STATE_M))
+}
+
+// Columns of all of `a` against a prefix of `b` (b may end anywhere),
+// starting at (0, 0), within `band` diagonals of the main one
+fn align_block(a: &[u8], b: &[u8], band: usize) -> Vec<Op> {
+    let width = 2 * band + 1;
+    let (m, n) = (a.len(), b.len());
+    // Per cell: predecessor state of M (bits 0-1), of a deletion (2-3), of an insertion (4-5)
+    let mut trace = vec![0u8; (m + 1) * width];
+    let mut prev = vec![[INF; 3]; width];
+    let mut cur = vec![[INF; 3]; width];
+    let open = GAP_OPEN_COST + GAP_EXTEND_COST;
+    for (d, cell) in prev.iter_mut().enumerate().skip(band) {
+        let j = d - band;
+        if j > n {
+            break;
+        }
+        *cell = if j == 0 { [0, INF, INF] } else { [INF, INF, GAP_OPEN_COST + j as u32 * GAP_EXTEND_COST] };
+        trace[d] = if j <= 1 { STATE_M << 4 } else { STATE_INS << 4 };
+    }
+    for i in 1..=m {
+        for d in 0..width {
+            let j = i as isize + d as isize - band as isize;
+            if j < 0 || j as usize > n {
+                cur[d] = [INF; 3];
+                continue;
+            }
+            let j = j as usize;
+            let mut cell = [INF; 3];
+            let mut t = 0u8;
+            if j > 0 {
+                let substitution = if a[i - 1] == b[j - 1] { 0 } else { MISMATCH_COST };
+                let (cost, from) = cheapest(prev[d], [substitution; 3]);
+                cell[STATE_M as usize] = cost;
+                t |= from;
+            }
+            if d + 1 < width {
+                let (cost, from) = cheapest(prev[d + 1], [open, GAP_EXTEND_COST, open]);
+                cell[STATE_DEL as usize] = cost;
+                t |= from << 2;
+            }
+            if d > 0 && j > 0 {
+                let (cost, from) = cheapest(cur[d - 1], [open, open, GAP_EXTEND_COST]);
+                cell[STATE_INS as usize] = cost;
+                t |= from << 4;
+            }
+            cur[d] = cell;
+            trace[i * width + d] = t;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    // Cheapest end in the last row, nearest the main diagonal on ties
+    let end = (0..width)
+        .filter(|&d| (m + d).checked_sub(band).is_some_and(|j| j <= n))
+        .flat_map(|d| (0..3u8).map(move |s| (d, s)))
+        .min_by_key(|&(d, s)| (prev[d][s as usize], d.abs_diff(band)));
+    let Some((mut d, mut state)) = end.filter(|&(d, s)| prev[d][s as usize] < INF) else {
+        return Vec::new();
+    };
+
+    let mut ops = Vec::with_capacity(m + band);
+    let mut i = m;
+    while i > 0 || d != band {
+        let t = trace[i * width + d];
+        match state {
+            STATE_M => {
+                let j = i + d - band;
+                ops.push(if a[i - 1] == b[j - 1] { Op::Match } else { Op::Mismatch });
+                state = t & 3;
+                i -= 1;
+            },
+            STATE_DEL => {
+                ops.push(Op::Deletion);
+                state = (t >> 2) & 3;
+                i -= 1;
+                d += 1;
+            },
+            _ => {
+                ops.push(Op::Insertion);
+                state = (t >> 4) & 3;
+                d -= 1;
+            },
+        }
+    }
+    ops.reverse();
+    ops
+}
+
+pub(crate) fn reverse_complement(sequence: &[u8]) -> Vec<u8> {
+    sequence.iter().rev().map(|&base| complement_base(base)).collect()
+}
+
+// Block by block alignment of a pair, with breakpoints where it had to resume elsewhere
+fn compare_pair(a: &[u8], b: &[u8], band: usize, comparison: &mut ContigComparison) {
+    let mut index = AnchorIndex::build(b);
+    let reversed_b;
+    let b = if index.reversed(a) {
+        reversed_b = reverse_complement(b);
+        index = AnchorIndex::build(&reversed_b);
+        comparison.reverse = true;
+        &reversed_b[..]
+    } else {
+        b
+    };
+    // B spans back on its forward strand
+    let reverse = comparison.reverse;
+    let forward_span = |start: usize, end: usize| if reverse { (b.len() - end, b.len() - start) } else { (start, end) };
+
+    // Start at the first anchor, or at both starts when it is near them
+    let Some((mut i, mut j)) = index.next_anchor(a, b, 0, |_, _| false) else { return };
+    if i <= band && j <= band {
+        (i, j) = (0, 0);
+    }
+    let mut total = Tally::default();
+    let mut breakpoints = Vec::new();
+    while i < a.len() && j < b.len() {
+        let length = BLOCK.min(a.len() - i).min(b.len() - j + band);
+        let block = if b.len() - j >= length && a[i..i + length] == b[j..j + length] {
+            Tally { matches: length, consumed_a: length, consumed_b: length, ..Default::default() }
+        } else {
+            Tally::of_best_prefix(&align_block(&a[i..i + length], &b[j..b.len().min(j + length + band)], band))
+        };
+        if block.consumed_a > 0 && block.identity() >= BREAK_IDENTITY {
+            total.add(&block);
+            (i, j) = (i + block.consumed_a, j + block.consumed_b);
+            continue;
+        }
+        // Resume past this block, or inside it on another diagonal
+        let diagonal = j as isize - i as isize;
+        let same_diagonal = |p: usize, q: usize| p < i + length && (q as isize - p as isize).abs_diff(diagonal) <= band;
+        let Some((p, q)) = index.next_anchor(a, b, i + 1, same_diagonal) else { break };
+        let jump = (q as isize - j as isize) - (p as isize - i as isize);
+        let kind = if q < j {
+            "rearrangement"
+        } else if jump > band as isize {
+            "b_insertion"
+        } else if jump < -(band as isize) {
+            "b_deletion"
+        } else {
+            "divergent"
+        };
+        let (b_start, b_end) = if q >= j { forward_span(j, q) } else { forward_span(q, j) };
+        breakpoints.push(Breakpoint { a_start: i, a_end: p, b_start, b_end, kind: kind.to_string() });
+        (i, j) = (p, q);
+    }
+
+    comparison.aligned_a = total.consumed_a;
+    comparison.aligned_b = total.consumed_b;
+    comparison.matches = total.matches;
+    comparison.mismatches = total.mismatches;
+    comparison.insertions = total.insertions;
+    comparison.inserted_bases = total.inserted_bases;
+    comparison.deletions = total.deletions;
+    comparison.deleted_bases = total.deleted_bases;
+    comparison.identity = total.identity();
+    comparison.breakpoints = breakpoints;
+}
+
+// Contig pairs (index in A, index in B, paired by): names first, then shared k-mers
+fn pair_contigs(a: &[(String, Vec<u8>)], b: &[(String, Vec<u8>)]) -> Vec<(usize, usize, &'static str)> {
+    let b_by_name: HashMap<&str, usize> = b.iter().enumerate().map(|(i, (name, _))| (name.as_str(), i)).collect();
+    let mut pairs: Vec<(usize, usize, &'static str)> = a.iter().enumerate()
+        .filter_map(|(i, (name, _))| b_by_name.get(name.as_str()).map(|&j| (i, j, "name")))
+        .collect();
+    let paired_a: HashSet<usize> = pairs.iter().map(|p| p.0).collect();
+    let mut paired_b: HashSet<usize> = pairs.iter().map(|p| p.1).collect();
+
+    let max_hash = crate::sketch::max_hash(PAIR_SCALE);
+    let mut b_sketch: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (j, (_, sequence)) in b.iter().enumerate().filter(|(j, _)| !paired_b.contains(j)) {
+        crate::sketch::for_each_sampled(sequence, PAIR_K, max_hash, |hash| {
+            let contigs = b_sketch.entry(hash).or_default();
+            if contigs.last() != Some(&j) {
+                contigs.push(j);
+            }
+        });
+    }
+    let mut candidates: Vec<(usize, usize, usize)> = a.par_iter().enumerate()
+        .filter(|(i, _)| !paired_a.contains(i))
+        .flat_map_iter(|(i, (_, sequence))| {
+            let mut seen = HashSet::new();
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            crate::sketch::for_each_sampled(sequence, PAIR_K, max_hash, |hash| {
+                if seen.insert(hash) {
+                    for &j in b_sketch.get(&hash).map(Vec::as_slice).unwrap_or(&[]) {
+                        *shared.entry(j).or_default() += 1;
+                    }
+                }
+            });
+            shared.into_iter().filter(|&(_, n)| n >= MIN_SHARED_KMERS).map(move |(j, n)| (n, i, j))
+        })
+        .collect();
+    // Most shared first, one B contig per A contig
+    candidates.sort_unstable_by(|x, y| y.0.cmp(&x.0).then(x.1.cmp(&y.1)).then(x.2.cmp(&y.2)));
+    let mut kmer_paired_a = HashSet::new();
+    for (_, i, j) in candidates {
+        if !kmer_paired_a.contains(&i) && !paired_b.contains(&j) {
+            kmer_paired_a.insert(i);
+            paired_b.insert(j);
+            pairs.push((i, j, "kmer"));
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+// Pair and align the contigs of two assembly FASTAs (plain or .gz)
+pub fn compare(a_path: &str, b_path: &str, band: usize) -> Result<ComparisonReport, String> {
+    let a = crate::simulate::load_reference(a_path)?;
+    let b = crate::simulate::load_reference(b_path)?;
+    let pairs = pair_contigs(&a, &b);
+    println!("Paired {} of {} contigs in {} with {} contigs in {}", pairs.len(), a.len(), a_path, b.len(), b_path);
+
+    let comparisons: Vec<ContigComparison> = pairs.par_iter()
+        .map(|&(i, j, paired_by)| {
+            let mut comparison = ContigComparison {
+                a_contig: a[i].0.clone(),
+                b_contig: b[j].0.clone(),
+                paired_by: paired_by.to_string(),
+                a_length: a[i].1.len(),
+                b_length: b[j].1.len(),
+                ..Default::default()
+            };
+            compare_pair(&a[i].1, &b[j].1, band, &mut comparison);
+            comparison
+        })
+        .collect();
+
+    let paired_a: HashSet<usize> = pairs.iter().map(|p| p.0).collect();
+    let paired_b: HashSet<usize> = pairs.iter().map(|p| p.1).collect();
+    let mut report = ComparisonReport {
+        a_contigs: a.len(),
+        b_contigs: b.len(),
+        a_bases: a.iter().map(|(_, s)| s.len()).sum(),
+        b_bases: b.iter().map(|(_, s)| s.len()).sum(),
+        paired_by_name: pairs.iter().filter(|p| p.2 == "name").count(),
+        paired_by_kmer: pairs.iter().filter(|p| p.2 == "kmer").count(),
+        unpaired_a: a.iter().enumerate().filter(|(i, _)| !paired_a.contains(i)).map(|(_, (name, _))| name.clone()).collect(),
+        unpaired_b: b.iter().enumerate().filter(|(j, _)| !paired_b.contains(j)).map(|(_, (name, _))| name.clone()).collect(),
+        ..Default::default()
+    };
+    let mut aligned_a = 0;
+    let mut columns = 0;
+    for c in &comparisons {
+        report.matches += c.matches;
+        report.mismatches += c.mismatches;
+        report.insertions += c.insertions;
+        report.deletions += c.deletions;
+        report.breakpoints += c.breakpoints.len();
+        aligned_a += c.aligned_a;
+        columns += c.matches + c.mismatches + c.inserted_bases + c.deleted_bases;
+    }
+    let fraction = |n: usize, d: usize| if d == 0 { 0.0 } else { n as f64 / d as f64 };
+    report.identity = fraction(report.matches, columns);
+    report.a_aligned_fraction = fraction(aligned_a, report.a_bases);
+    report.agreement = fraction(report.matches, report.a_bases);
+    report.pairs = comparisons;
+    Ok(report)
+}
+
+impl ComparisonReport {
+    pub fn print(&self) {
+        println!("Assembly comparison (A: {} contigs, {} bases; B: {} contigs, {} bases):",
+                 self.a_contigs, self.a_bases, self.b_contigs, self.b_bases);
+        println!("   Pairs: {} by name, {} by shared k-mers; unpaired: {} in A, {} in B",
+                 self.paired_by_name, self.paired_by_kmer, self.unpaired_a.len(), self.unpaired_b.len());
+        println!("   Identity: {:.4}% over aligned columns", self.identity * 100.0);
+        println!("   A aligned: {:.2}%, matched exactly in B: {:.2}%", self.a_aligned_fraction * 100.0, self.agreement * 100.0);
+        println!("   Mismatches: {}, insertions in B: {}, deletions in B: {}, breakpoints: {}",
+                 self.mismatches, self.insertions, self.deletions, self.breakpoints);
+        if self.pairs.is_empty() {
+            return;
+        }
+        let mut ranked: Vec<&ContigComparison> = self.pairs.iter().collect();
+        ranked.sort_by(|x, y| x.identity.total_cmp(&y.identity));
+        println!("   Pairs{}:", if self.pairs.len() > REPORTED_PAIRS { format!(" (lowest {} by identity)", REPORTED_PAIRS) } else { String::new() });
+        for c in ranked.iter().take(REPORTED_PAIRS) {
+            println!("     {} ~ {}{} ({}): {:.4}% identity, {:.1}% of A aligned, {} mismatches, {} ins, {} del, {} breakpoints",
+                     c.a_contig, c.b_contig, if c.reverse { " (-)" } else { "" }, c.paired_by, c.identity * 100.0,
+                     if c.a_length == 0 { 0.0 } else { c.aligned_a as f64 * 100.0 / c.a_length as f64 },
+                     c.mismatches, c.insertions, c.deletions, c.breakpoints.len());
+        }
+    }
+
+    // One row per pair
+    pub fn write_tsv(&self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut out = std::io::BufWriter::new(file);
+        let mut write = || -> std::io::Result<()> {
+            writeln!(out, "a_contig\tb_contig\tpaired_by\tstrand\ta_length\tb_length\taligned_a\taligned_b\tmatches\tmismatches\tinsertions\tinserted_bases\tdeletions\tdeleted_bases\tidentity\tbreakpoints")?;
+            for c in &self.pairs {
+                writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{}",
+                         c.a_contig, c.b_contig, c.paired_by, if c.reverse { '-' } else { '+' }, c.a_length, c.b_length,
+                         c.aligned_a, c.aligned_b, c.matches, c.mismatches, c.insertions, c.inserted_bases,
+                         c.deletions, c.deleted_bases, c.identity, c.breakpoints.len())?;
+            }
+            out.flush()
+        };
+        write().map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    // One row per breakpoint, 0-based half-open spans
+    pub fn write_breakpoints(&self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut out = std::io::BufWriter::new(file);
+        let mut write = || -> std::io::Result<()> {
+            writeln!(out, "a_contig\ta_start\ta_end\tb_contig\tb_start\tb_end\tkind")?;
+            for c in &self.pairs {
+                for bp in &c.breakpoints {
+                    writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}", c.a_contig, bp.a_start, bp.a_end, c.b_contig, bp.b_start, bp.b_end, bp.kind)?;
+                }
+            }
+            out.flush()
+        };
+        write().map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+}
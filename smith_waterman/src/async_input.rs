@@ -0,0 +1,588 @@
+// Async input layer for streaming FASTQ data
+// Overlaps remote reads, decompression, and chunk dispatch with GPU work
+// The reader stage (fetch, read, decompress) hands decoded bytes to the parser
+// stage in blocks over a bounded channel, and the parser cuts them into chunks
+// for the next stage (stages.rs)
+
+use std::process::Stdio;
+use crate::chunking::ChunkSize;
+use crate::input_format::{detect_input, line_terminator, Compression, Decompressor, DetectedInput, RecordAssembler};
+use crate::memory_profile::MemoryBudget;
+use crate::read_chunk::{ReadChunk, ReadNameMode};
+use crate::stages::{self, Activity, Stage, StageClock, StageReceiver, StageSender, RAW_BLOCK_BYTES};
+use crate::watchdog::WatchedChild;
+use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// Number of chunks buffered between the parser and the next stage
+pub const DEFAULT_CHANNEL_DEPTH: usize = 4;
+
+// Where input bytes come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputSource {
+    LocalFile(String),
+    Http(String),
+    ObjectStore(String),
+}
+
+impl InputSource {
+    pub fn from_path(path: &str) -> Self {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            InputSource::Http(path.to_string())
+        } else if path.starts_with("s3://") || path.starts_with("gs://") {
+            InputSource::ObjectStore(path.to_string())
+        } else {
+            InputSource::LocalFile(path.to_string())
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            InputSource::LocalFile(p) | InputSource::Http(p) | InputSource::ObjectStore(p) => p,
+        }
+    }
+
+    // First `limit` bytes of the raw input, for format detection
+    // Remote inputs are fetched separately and the fetch is dropped after the prefix
+    pub fn read_prefix(&self, limit: usize) -> Result<Vec<u8>, String> {
+        use std::io::Read;
+        let mut prefix = Vec::with_capacity(limit);
+        match self.fetch_command() {
+            None => {
+                let file = std::fs::File::open(self.path())
+                    .map_err(|e| format!("Failed to open file {}: {}", self.path(), e))?;
+                file.take(limit as u64).read_to_end(&mut prefix)
+                    .map_err(|e| format!("Failed to read {}: {}", self.path(), e))?;
+            },
+            Some(mut cmd) => {
+                let mut child = cmd
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("Failed to spawn fetcher for {}: {}", self.path(), e))?;
+                let read_result = match child.stdout.take() {
+                    Some(stdout) => stdout.take(limit as u64).read_to_end(&mut prefix)
+                        .map_err(|e| format!("Failed to read {}: {}", self.path(), e)),
+                    None => Err(format!("Failed to get stdout from fetcher for {}", self.path())),
+                };
+                drop(FetcherGuard(Some(child)));
+                read_result?;
+            },
+        }
+        Ok(prefix)
+    }
+
+    // Command that writes the raw (possibly compressed) bytes to stdout
+    fn fetch_command(&self) -> Option<std::process::Command> {
+        match self {
+            InputSource::LocalFile(_) => None,
+            InputSource::Http(url) => {
+                let mut cmd = std::process::Command::new("curl");
+                cmd.args(["-sSfL", url]);
+                Some(cmd)
+            },
+            InputSource::ObjectStore(url) if url.starts_with("gs://") => {
+                let mut cmd = std::process::Command::new("gsutil");
+                cmd.args(["cat", url]);
+                Some(cmd)
+            },
+            InputSource::ObjectStore(url) => {
+                let mut cmd = std::process::Command::new("aws");
+                cmd.args(["s3", "cp", url, "-"]);
+                Some(cmd)
+            },
+        }
+    }
+}
+
+// Kills the remote fetch process when the reader goes away
+struct FetcherGuard(Option<std::process::Child>);
+
+impl Drop for FetcherGuard {
+    fn drop(&mut self) {
+        if let Some(fetcher) = self.0.as_mut() {
+            let _ = fetcher.kill();
+            let _ = fetcher.wait();
+        }
+    }
+}
+
+// Open reader plus the child processes feeding it (killed when dropped), which
+// the stall watchdog may kill sooner
+struct OpenedInput {
+    reader: Box<dyn AsyncRead + Unpin + Send>,
+    fetcher: FetcherGuard,
+    decompressor: Option<Child>,
+    watched: Vec<WatchedChild>,
+}
+
+fn watch_fetcher(fetcher: &Option<std::process::Child>, path: &str) -> Vec<WatchedChild> {
+    fetcher.iter().map(|child| crate::watchdog::watch_child(Stage::Reader, child.id(), &format!("fetcher for {}", path))).collect()
+}
+
+fn reject_alignment(path: &str, detected: &DetectedInput) -> Result<(), String> {
+    match detected.format.filter(|f| f.is_alignment()) {
+        Some(format) => Err(format!("{}: {} input is read with --input-format {}, not the FASTA/FASTQ stream",
+                                    path, format.name(), format.name().to_lowercase())),
+        None => Ok(()),
+    }
+}
+
+async fn open_input(source: &InputSource) -> Result<OpenedInput, String> {
+    let path = source.path();
+
+    // Sniff compression and format from the content before committing to a pipeline
+    let detect_source = source.clone();
+    let detected = tokio::task::spawn_blocking(move || detect_input(&detect_source)).await
+        .map_err(|e| format!("Input detection task failed for {}: {}", path, e))??;
+    println!("    Input: {}", detected.describe());
+    reject_alignment(path, &detected)?;
+
+    // Remote inputs are fetched by an external tool, same as zcat for local gzip
+    let mut fetcher = match source.fetch_command() {
+        Some(mut cmd) => Some(cmd
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to spawn fetcher for {}: {}", path, e))?),
+        None => None,
+    };
+
+    if let Some(command) = detected.compression.decompress_command() {
+        let mut zcat = Command::from(command);
+        zcat.kill_on_drop(true).stdout(Stdio::piped());
+        match fetcher.as_mut() {
+            Some(child) => {
+                let stdout = child.stdout.take()
+                    .ok_or_else(|| format!("Failed to get stdout from fetcher for {}", path))?;
+                zcat.stdin(Stdio::from(stdout));
+            },
+            None => {
+                zcat.arg(path);
+            },
+        }
+        let mut child = zcat.spawn()
+            .map_err(|e| format!("Failed to spawn {} decompressor for {}: {}", detected.compression.name(), path, e))?;
+        crate::affinity::pin_decompressor(child.id());
+        let mut watched = watch_fetcher(&fetcher, path);
+        if let Some(pid) = child.id() {
+            watched.push(crate::watchdog::watch_child(Stage::Reader, pid, &format!("{} decompressor for {}", detected.compression.name(), path)));
+        }
+        let stdout = child.stdout.take()
+            .ok_or_else(|| format!("Failed to get stdout from decompressor for {}", path))?;
+        return Ok(OpenedInput {
+            reader: Box::new(stdout),
+            fetcher: FetcherGuard(fetcher),
+            decompressor: Some(child),
+            watched,
+        });
+    }
+
+    match fetcher.as_mut() {
+        Some(child) => {
+            let stdout = child.stdout.take()
+                .ok_or_else(|| format!("Failed to get stdout from fetcher for {}", path))?;
+            let stdout = tokio::process::ChildStdout::from_std(stdout)
+                .map_err(|e| format!("Failed to register fetcher output for {}: {}", path, e))?;
+            Ok(OpenedInput {
+                reader: Box::new(stdout),
+                watched: watch_fetcher(&fetcher, path),
+                fetcher: FetcherGuard(fetcher),
+                decompressor: None,
+            })
+        },
+        None => {
+            let file = tokio::fs::File::open(path).await
+                .map_err(|e| format!("Failed to open file {}: {}", path, e))?;
+            Ok(OpenedInput {
+                reader: Box::new(file),
+                fetcher: FetcherGuard(None),
+                decompressor: None,
+                watched: Vec::new(),
+            })
+        },
+    }
+}
+
+// Filled chunks travel to the consumer; spent chunks come back for reuse
+pub struct ChunkStream {
+    pub chunks: StageReceiver<ReadChunk>,
+    pub recycle: std::sync::mpsc::Sender<ReadChunk>,
+    // The parser, which finishes with the reader's result
+    pub reader: JoinHandle<Result<usize, String>>,
+}
+
+// Where a reader's empty chunks come from: spent ones the consumer sent back,
+// or new ones, held against the run's memory budget either way
+pub struct ChunkPool {
+    recycled: std::sync::mpsc::Receiver<ReadChunk>,
+    memory: Arc<MemoryBudget>,
+}
+
+impl ChunkPool {
+    pub fn new(recycled: std::sync::mpsc::Receiver<ReadChunk>, memory: Arc<MemoryBudget>) -> Self {
+        Self { recycled, memory }
+    }
+
+    // Reuse a returned chunk if one is waiting, otherwise allocate a new one
+    pub fn next_empty(&self, chunk_size: ChunkSize, name_mode: ReadNameMode) -> ReadChunk {
+        let mut chunk = match self.recycled.try_recv() {
+            Ok(mut chunk) if chunk.name_mode() == name_mode => {
+                chunk.clear();
+                chunk
+            },
+            _ => ReadChunk::with_names(chunk_size.capacity_reads(), name_mode),
+        };
+        self.hold(&mut chunk);
+        chunk
+    }
+
+    // Hold a chunk from elsewhere against the pool's budget
+    pub fn hold(&self, chunk: &mut ReadChunk) {
+        chunk.hold_against(&self.memory);
+    }
+}
+
+// Reader stage for async inputs (an external decompressor, a remote fetch or a
+// plain local file): decoded bytes go to the parser in blocks, and awaiting the
+// bounded channel holds the reader back when parsing falls behind
+async fn pump_async(source: InputSource, raw: mpsc::Sender<Vec<u8>>) -> Result<(), String> {
+    use tokio::io::AsyncReadExt;
+    let _clock = StageClock::start(Stage::Reader);
+    // Child processes stay alive for as long as the guards are in scope
+    let OpenedInput { mut reader, fetcher: _fetcher, decompressor: _decompressor, watched: _watched } = open_input(&source).await?;
+    loop {
+        let mut block = vec![0u8; RAW_BLOCK_BYTES];
+        let mut filled = 0;
+        while filled < block.len() {
+            match reader.read(&mut block[filled..]).await {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) => return Err(format!("Read failed for {} at byte {}: {}", source.path(), filled, e)),
+            }
+        }
+        if filled == 0 {
+            return Ok(());
+        }
+        block.truncate(filled);
+        let waited = std::time::Instant::now();
+        let sent = {
+            let _waiting = stages::waiting(Stage::Reader, Activity::Blocked);
+            raw.send(block).await
+        };
+        stages::add_blocked(Stage::Reader, waited.elapsed());
+        if sent.is_err() {
+            // Parser stopped early, nothing left to do
+            return Ok(());
+        }
+        stages::add_item(Stage::Reader, filled);
+    }
+}
+
+// Reader stage for readers that aren't async (direct I/O, in-process decompression)
+fn pump_blocking<R: std::io::Read>(mut reader: R, via: &str, raw: mpsc::Sender<Vec<u8>>) -> Result<(), String> {
+    let _clock = StageClock::start(Stage::Reader);
+    loop {
+        let mut block = vec![0u8; RAW_BLOCK_BYTES];
+        let mut filled = 0;
+        while filled < block.len() {
+            match reader.read(&mut block[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(format!("Read failed ({}): {}", via, e)),
+            }
+        }
+        if filled == 0 {
+            return Ok(());
+        }
+        block.truncate(filled);
+        let waited = std::time::Instant::now();
+        let sent = {
+            let _waiting = stages::waiting(Stage::Reader, Activity::Blocked);
+            raw.blocking_send(block)
+        };
+        stages::add_blocked(Stage::Reader, waited.elapsed());
+        if sent.is_err() {
+            return Ok(());
+        }
+        stages::add_item(Stage::Reader, filled);
+    }
+}
+
+// The parser's view of the reader's blocks
+struct RawBlocks {
+    rx: mpsc::Receiver<Vec<u8>>,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl std::io::Read for RawBlocks {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.block.len() {
+            let waited = std::time::Instant::now();
+            let next = {
+                let _waiting = stages::waiting(Stage::Parser, Activity::Starved);
+                self.rx.blocking_recv()
+            };
+            stages::add_starved(Stage::Parser, waited.elapsed());
+            match next {
+                Some(block) => (self.block, self.pos) = (block, 0),
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Parser stage: the reader's blocks into chunks of FASTA/FASTQ reads (and names,
+// if requested); `via` names the reader in messages. Finishes with the reader's
+// error, if it had one, once the blocks are dropped and the reader has stopped
+fn parse_blocks(raw: mpsc::Receiver<Vec<u8>>, reader: JoinHandle<Result<(), String>>, via: &'static str, chunk_size: ChunkSize, name_mode: ReadNameMode,
+                tx: StageSender<ReadChunk>, pool: ChunkPool) -> Result<usize, String> {
+    let blocks = std::io::BufReader::with_capacity(RAW_BLOCK_BYTES, RawBlocks { rx: raw, block: Vec::new(), pos: 0 });
+    let parsed = {
+        let _clock = StageClock::start(Stage::Parser);
+        read_chunks_blocking(blocks, via, chunk_size, name_mode, &tx, &pool)
+    };
+    let read = tokio::runtime::Handle::current().block_on(reader)
+        .map_err(|e| format!("Input reader task failed: {}", e))?;
+    let total_reads = parsed?;
+    read?;
+    Ok(total_reads)
+}
+
+// Chunk-cutting loop of the parser; the bounded channel to the consumer holds
+// it back when the GPU side falls behind
+fn read_chunks_blocking<R: std::io::BufRead>(mut reader: R, via: &str, chunk_size: ChunkSize, name_mode: ReadNameMode,
+                                             tx: &StageSender<ReadChunk>, pool: &ChunkPool) -> Result<usize, String> {
+    let _scope = crate::profiler::scope("read_chunks");
+    let mut chunk = pool.next_empty(chunk_size, name_mode);
+    let mut records = RecordAssembler::new();
+    let mut line = Vec::new();
+    let mut line_count = 0;
+    let mut total_reads = 0;
+    let mut clock = crate::file_timing::ReaderClock::new();
+    let waited = std::time::Instant::now();
+    let terminator = reader.fill_buf().map(line_terminator)
+        .map_err(|e| format!("Read failed at line 0 ({}): {}", via, e))?;
+    clock.add_input(waited.elapsed());
+
+    loop {
+        line.clear();
+        // Refills of the read buffer are the time spent waiting on the reader
+        let waited = std::time::Instant::now();
+        let _ = reader.fill_buf();
+        clock.add_input(waited.elapsed());
+        // Lines the chunk won't store are skipped in the buffer, not copied out
+        let skip = records.skips_next_line(&chunk);
+        let n = if skip { reader.skip_until(terminator) } else { reader.read_until(terminator, &mut line) }
+            .map_err(|e| format!("Read failed at line {} ({}): {}", line_count, via, e))?;
+        if n == 0 {
+            break;
+        }
+        line_count += 1;
+
+        let completed = if skip { records.skip_line() } else { records.push_line(&line, &mut chunk)? };
+        if completed {
+            total_reads += 1;
+
+            if chunk_size.is_full(chunk.len(), chunk.bases().len()) {
+                let mut full = std::mem::replace(&mut chunk, pool.next_empty(chunk_size, name_mode));
+                full.account()?;
+                let bases = full.bases().len();
+                let waited = std::time::Instant::now();
+                let sent = tx.send(full, bases);
+                clock.add_blocked(waited.elapsed());
+                if sent.is_err() {
+                    // Consumer stopped early, nothing left to do
+                    return Ok(total_reads);
+                }
+            }
+        }
+    }
+
+    if records.finish(&mut chunk) {
+        total_reads += 1;
+    }
+    if !chunk.is_empty() {
+        chunk.account()?;
+        let bases = chunk.bases().len();
+        let _ = tx.send(chunk, bases);
+    }
+
+    println!("    Total lines read: {} ({}, {})", line_count, records.format().map_or("empty", |f| f.name()), via);
+    Ok(total_reads)
+}
+
+// Direct I/O path: aligned O_DIRECT reads on a blocking thread
+#[cfg(target_os = "linux")]
+fn spawn_direct_reader(path: String, raw: mpsc::Sender<Vec<u8>>) -> JoinHandle<Result<(), String>> {
+    use crate::direct_io::{DirectReader, DIRECT_IO_BLOCK_SIZE};
+    use crate::input_format::detect_path;
+    use std::io::BufReader as StdBufReader;
+
+    let run = crate::run_context::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = run.enter();
+        let detected = detect_path(&path)?;
+        println!("    Input: {}", detected.describe());
+        let reader = DirectReader::open(&path)
+            .map_err(|e| format!("Failed to open {} for direct I/O: {}", path, e))?;
+        let decoded = detected.compression.native_decoder(reader)?;
+        pump_blocking(StdBufReader::with_capacity(DIRECT_IO_BLOCK_SIZE, decoded), "direct I/O", raw)
+    })
+}
+
+// In-process decompression path (DECOMPRESSOR=native): the raw bytes of a local
+// file or remote fetch are decoded with flate2/zstd on a blocking thread, so no
+// zcat or zstd binary is needed. Local bgzf files and gzip files with an index
+// take this path whatever DECOMPRESSOR is, to be decoded in parallel
+fn spawn_native_reader(source: InputSource, raw: mpsc::Sender<Vec<u8>>) -> JoinHandle<Result<(), String>> {
+    use std::io::Read;
+
+    let run = crate::run_context::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = run.enter();
+        let path = source.path();
+        let detected = detect_input(&source)?;
+        println!("    Input: {}", detected.describe());
+        reject_alignment(path, &detected)?;
+        if let (InputSource::LocalFile(_), Compression::Gzip, true) = (&source, detected.compression, crate::parallel_gzip::enabled()) {
+            if let Some(index) = crate::parallel_gzip::GzipIndex::for_file(path)? {
+                println!("    Gzip index: {} access points, {} decompression threads", index.points.len(), crate::parallel_gzip::threads());
+                return pump_blocking(crate::parallel_gzip::IndexedGzipReader::new(path, index), "indexed gzip", raw);
+            }
+        }
+        // The fetcher stays alive for as long as the guard is in scope
+        let (raw_input, _fetcher, _watched): (Box<dyn Read + Send>, FetcherGuard, Vec<WatchedChild>) = match source.fetch_command() {
+            Some(mut cmd) => {
+                let mut child = cmd
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("Failed to spawn fetcher for {}: {}", path, e))?;
+                let stdout = child.stdout.take()
+                    .ok_or_else(|| format!("Failed to get stdout from fetcher for {}", path))?;
+                let fetcher = Some(child);
+                let watched = watch_fetcher(&fetcher, path);
+                (Box::new(stdout), FetcherGuard(fetcher), watched)
+            },
+            None => {
+                let file = std::fs::File::open(path)
+                    .map_err(|e| format!("Failed to open file {}: {}", path, e))?;
+                (Box::new(file), FetcherGuard(None), Vec::new())
+            },
+        };
+        let decoded = detected.compression.native_decoder(raw_input)?;
+        pump_blocking(decoded, "in-process decoding", raw)
+    })
+}
+
+// Reader and parser stages for one input on the current runtime, sending chunks
+// into `tx`; the handle is the parser's
+fn spawn_stages(source: InputSource, chunk_size: ChunkSize, name_mode: ReadNameMode, tx: StageSender<ReadChunk>,
+                pool: ChunkPool) -> JoinHandle<Result<usize, String>> {
+    let (raw, blocks) = mpsc::channel(Stage::Reader.channel_depth());
+    let (reader, via) = spawn_reader(source, raw);
+    // The blocking threads record into the run of the thread starting them
+    let run = crate::run_context::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = run.enter();
+        parse_blocks(blocks, reader, via, chunk_size, name_mode, tx, pool)
+    })
+}
+
+fn spawn_reader(source: InputSource, raw: mpsc::Sender<Vec<u8>>) -> (JoinHandle<Result<(), String>>, &'static str) {
+    #[cfg(target_os = "linux")]
+    {
+        if let InputSource::LocalFile(path) = &source {
+            if crate::direct_io::is_enabled() {
+                return (spawn_direct_reader(path.clone(), raw), "direct I/O");
+            }
+        }
+    }
+
+    let parallel_local = matches!(&source, InputSource::LocalFile(path) if crate::parallel_gzip::takes_local(path));
+    if crate::input_format::decompressor() == Decompressor::Native || parallel_local {
+        return (spawn_native_reader(source, raw), "in-process decoding");
+    }
+
+    (tokio::spawn(pump_async(source, raw)), "async input")
+}
+
+// Spawn the reader and parser on the current runtime and return the consuming end
+pub fn spawn_chunk_stream(source: InputSource, chunk_size: ChunkSize, name_mode: ReadNameMode, depth: usize) -> ChunkStream {
+    let (tx, chunks) = stages::channel(Stage::Parser, Stage::Encoder, depth);
+    let (recycle, recycled) = std::sync::mpsc::channel();
+    let reader = spawn_stages(source, chunk_size, name_mode, tx, ChunkPool::new(recycled, Arc::default()));
+    ChunkStream { chunks, recycle, reader }
+}
+
+// Channel depth from .env, falling back to the default, then scaled by --gpu-fraction
+pub fn get_channel_depth() -> usize {
+    let depth = std::env::var("INPUT_CHANNEL_DEPTH")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&v| v > 0)
+        .unwrap_or(DEFAULT_CHANNEL_DEPTH);
+    crate::gpu_share::channel_depth(depth)
+}
+
+fn input_runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .on_thread_start(crate::affinity::pin_reader_thread)
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Failed to start async I/O runtime: {}", e))
+}
+
+// Reader and parser stages of a WGS file pass: chunks go to the caller's
+// channel until the input ends or its receiver is dropped, and spent chunks
+// come back through `pool`. Returns the reads parsed
+pub fn stream_input_chunks(path: &str, chunk_size: ChunkSize, name_mode: ReadNameMode, tx: StageSender<ReadChunk>,
+                           pool: ChunkPool) -> Result<usize, String> {
+    let runtime = input_runtime()?;
+    let parser = {
+        let _guard = runtime.enter();
+        spawn_stages(InputSource::from_path(path), chunk_size, name_mode, tx, pool)
+    };
+    runtime.block_on(parser).map_err(|e| format!("Async reader task failed: {}", e))?
+}
+
+// Sync facade: runs the reader and parser on a private runtime while the caller
+// consumes chunks on the current thread (no runtime needed by the caller)
+pub fn process_input_in_chunks<F>(path: &str, chunk_size: ChunkSize, name_mode: ReadNameMode, mut processor: F) -> Result<(), String>
+where F: FnMut(&ReadChunk) -> Result<(), String> {
+    let runtime = input_runtime()?;
+
+    let source = InputSource::from_path(path);
+    let ChunkStream { chunks, recycle, reader } = {
+        let _guard = runtime.enter();
+        spawn_chunk_stream(source, chunk_size, name_mode, get_channel_depth())
+    };
+
+    let mut chunk_count = 0;
+    while let Some(chunk) = chunks.recv() {
+        chunk_count += 1;
+        crate::tui::set_queue_depth(chunks.len(), chunks.capacity());
+        if let Err(e) = processor(&chunk) {
+            // Dropping the receiver stops the parser at its next send
+            drop(chunks);
+            let _ = runtime.block_on(reader);
+            return Err(e);
+        }
+        // Hand the arena back so the parser doesn't allocate a new one
+        let _ = recycle.send(chunk);
+    }
+
+    let total_reads = runtime.block_on(reader)
+        .map_err(|e| format!("Async reader task failed: {}", e))??;
+    println!("    Processed {} total reads in {} chunks", total_reads, chunk_count);
+    Ok(())
+}
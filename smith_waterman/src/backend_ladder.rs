@@ -0,0 +1,108 @@
+// Backend fallback ladder (--fallback): when the backend asked for can't be
+// opened, try the next one down instead of exiting, so one binary and one
+// command line run on every machine of a mixed lab
+// The ladder is CUDA, then OpenCL, then wgpu, then the SIMD CPU path, starting
+// at the backend asked for (--gpu: CUDA; --wgpu: wgpu). Each rung that fails is
+// kept with why, so the caller can log it:
+//   CUDA    this build has no CUDA kernels, so the rung always fails; the probe
+//           says whether a CUDA driver is installed, in which case the NVIDIA
+//           GPU is reached through its OpenCL driver on the next rung
+//   OpenCL  no driver (no OpenCL platform installed), no GPU device on any
+//           platform, or the context or kernel build failing
+//   wgpu    not in this build (macOS, or --features wgpu), only the basic
+//           kernel, or no adapter / device request failing
+// The CPU rung always opens, so descending only fails when the caller's
+// backends are all above a rung it can't use (WGS runs need OpenCL)
+
+use crate::chunking::ChunkSize;
+use crate::engine::{AlignParams, Aligner, Backend};
+use std::path::PathBuf;
+
+pub const LADDER: [Backend; 4] = [Backend::Cuda, Backend::Gpu, Backend::Wgpu, Backend::Cpu];
+
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub backend: Backend,
+    pub reason: String,
+}
+
+pub fn name(backend: Backend) -> &'static str {
+    match backend {
+        Backend::Cuda => "CUDA",
+        Backend::Gpu => "OpenCL",
+        Backend::Wgpu => "wgpu",
+        Backend::Cpu => "CPU",
+    }
+}
+
+// The CUDA driver library, where an installed driver puts it
+fn cuda_driver() -> Option<PathBuf> {
+    let candidates: &[&str] = if cfg!(windows) {
+        &["C:\\Windows\\System32\\nvcuda.dll"]
+    } else {
+        &["/usr/lib/x86_64-linux-gnu/libcuda.so.1", "/usr/lib/aarch64-linux-gnu/libcuda.so.1", "/usr/lib64/libcuda.so.1",
+          "/usr/lib/libcuda.so.1", "/usr/lib/wsl/lib/libcuda.so.1"]
+    };
+    candidates.iter().map(PathBuf::from).find(|path| path.is_file())
+}
+
+// Why the CUDA rung can't run: there are no CUDA kernels in this build
+pub fn cuda_unavailable() -> String {
+    match cuda_driver() {
+        Some(driver) => format!("no CUDA backend in this build (driver found at {}; its GPU runs through OpenCL)", driver.display()),
+        None => "no CUDA backend in this build, and no CUDA driver installed".to_string(),
+    }
+}
+
+// Why OpenCL can't run here, before building a context
+fn opencl_unavailable() -> Option<String> {
+    let platforms = ocl::Platform::list();
+    if platforms.is_empty() {
+        return Some("no OpenCL driver: no platform is installed".to_string());
+    }
+    if !crate::gpu::is_gpu_available() {
+        return Some(format!("no OpenCL GPU device on {} platform(s)", platforms.len()));
+    }
+    None
+}
+
+// The first backend from `preferred` down that opens, and why each one above
+// it didn't; backends not in `usable` are passed over without a word
+pub fn descend(preferred: Backend, usable: &[Backend], params: AlignParams, chunk_size: ChunkSize)
+    -> Result<(Aligner, Vec<Rejection>), Vec<Rejection>> {
+    let start = LADDER.iter().position(|&backend| backend == preferred).unwrap_or(0);
+    let mut rejected = Vec::new();
+    for &backend in &LADDER[start..] {
+        if !usable.contains(&backend) {
+            continue;
+        }
+        if backend == Backend::Gpu {
+            if let Some(reason) = opencl_unavailable() {
+                rejected.push(Rejection { backend, reason });
+                continue;
+            }
+        }
+        match Aligner::builder().backend(backend).params(params).chunking(chunk_size).build() {
+            Ok(aligner) => return Ok((aligner, rejected)),
+            Err(e) => rejected.push(Rejection {
+                backend,
+                reason: if backend == Backend::Gpu { format!("context or kernel build failed: {}", e) } else { e },
+            }),
+        }
+    }
+    Err(rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuda_rung_falls_through() {
+        let (aligner, rejected) = descend(Backend::Cuda, &[Backend::Cuda, Backend::Cpu], AlignParams::default(), ChunkSize::Reads(8)).unwrap();
+        assert_eq!(aligner.backend(), Backend::Cpu);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].backend, Backend::Cuda);
+        assert!(rejected[0].reason.starts_with("no CUDA backend in this build"), "{}", rejected[0].reason);
+    }
+}
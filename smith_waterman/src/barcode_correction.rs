@@ -0,0 +1,145 @@
+// `correct-barcodes`: clustering observed cell barcodes/UMIs into corrected ones
+// The barcode of a read is `length` bases from `offset` (e.g. 16 from 0 on a
+// 10x R1). Distinct barcodes are counted, and every pair's global edit distance
+// is computed in batches with edit_distance.rs, keeping only the pairs within
+// max_edits. Clustering is greedy by abundance, in the spirit of UMI-tools'
+// directional method: the most frequent unassigned barcode becomes a cluster
+// and absorbs each unassigned neighbour with count c when its own count is at
+// least 2c - 1. Ties in count go to the lexicographically smaller barcode
+// Bases other than A/C/G/T never match, so barcodes with N join a cluster
+// only through edits
+
+use crate::edit_distance::{self, Mode};
+use crate::fastq::{FastqBatch, FastqChunks};
+use crate::ungapped::SequenceSet;
+use std::collections::HashMap;
+use std::io::Write;
+
+const READS_PER_BATCH: usize = 65536;
+pub const MAX_EDITS: u32 = 2;
+pub const DEFAULT_MAX_EDITS: u32 = 1;
+// Barcode pairs scored together
+const PAIRS_PER_BATCH: usize = 1 << 24;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BarcodeOptions {
+    pub offset: usize,
+    pub length: usize,
+    pub max_edits: u32,
+    pub use_gpu: bool,
+}
+
+impl BarcodeOptions {
+    pub fn new(offset: usize, length: usize, max_edits: u32, use_gpu: bool) -> Result<Self, String> {
+        if length == 0 || length > edit_distance::MAX_PATTERN {
+            return Err(format!("Invalid barcode length {}: expected 1 to {}", length, edit_distance::MAX_PATTERN));
+        }
+        if max_edits == 0 || max_edits > MAX_EDITS {
+            return Err(format!("Invalid --max-edits {}: expected 1 to {}", max_edits, MAX_EDITS));
+        }
+        Ok(Self { offset, length, max_edits, use_gpu })
+    }
+}
+
+// One line of the correction map
+#[derive(Debug, Clone)]
+pub struct Correction {
+    pub observed: Vec<u8>,
+    pub corrected: usize,
+    pub count: u64,
+    pub edits: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CorrectionSummary {
+    pub reads: usize,
+    // Reads too short to hold the barcode
+    pub skipped: usize,
+    pub distinct: usize,
+    pub clusters: usize,
+    pub reads_corrected: u64,
+}
+
+// Distinct barcodes with their read counts, most frequent first
+fn count_barcodes(reads: &str, options: &BarcodeOptions, summary: &mut CorrectionSummary) -> Result<Vec<(Vec<u8>, u64)>, String> {
+    let mut counts: HashMap<Vec<u8>, u64> = HashMap::new();
+    let mut chunks = FastqChunks::open(reads, READS_PER_BATCH)?;
+    let mut batch = FastqBatch::with_capacity(READS_PER_BATCH);
+    while chunks.next_into(&mut batch)? {
+        for record in batch.iter() {
+            summary.reads += 1;
+            match record.seq.get(options.offset..options.offset + options.length) {
+                Some(barcode) => *counts.entry(barcode.to_ascii_uppercase()).or_insert(0) += 1,
+                None => summary.skipped += 1,
+            }
+        }
+    }
+    let mut barcodes: Vec<(Vec<u8>, u64)> = counts.into_iter().collect();
+    barcodes.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(barcodes)
+}
+
+// Neighbours of every barcode within max_edits, as (barcode, edits)
+fn neighbours(barcodes: &[(Vec<u8>, u64)], max_edits: u32, use_gpu: bool) -> Result<Vec<Vec<(usize, u32)>>, String> {
+    let mut all = SequenceSet::new();
+    for (barcode, _) in barcodes {
+        all.push_encoded(&barcode.iter().map(|&b| edit_distance::encode_text(b)).collect::<Vec<u8>>());
+    }
+    let mut neighbours = vec![Vec::new(); barcodes.len()];
+    let per_batch = (PAIRS_PER_BATCH / barcodes.len().max(1)).max(1);
+    let mut first = 0;
+    while first < barcodes.len() {
+        let last = (first + per_batch).min(barcodes.len());
+        let patterns = all.slice(first, last);
+        let hits = edit_distance::distances(&patterns, &all, Mode::Global, use_gpu)?;
+        for (pair, hit) in hits.iter().enumerate() {
+            let (text, pattern) = (pair / patterns.len(), first + pair % patterns.len());
+            if hit.distance <= max_edits && text != pattern {
+                neighbours[pattern].push((text, hit.distance));
+            }
+        }
+        first = last;
+    }
+    Ok(neighbours)
+}
+
+// Greedy directional clustering; barcodes are in count order, so the first
+// unassigned one is always the most frequent
+fn cluster(barcodes: &[(Vec<u8>, u64)], neighbours: &[Vec<(usize, u32)>]) -> Vec<(usize, u32)> {
+    let mut assigned: Vec<Option<(usize, u32)>> = vec![None; barcodes.len()];
+    for head in 0..barcodes.len() {
+        if assigned[head].is_some() {
+            continue;
+        }
+        assigned[head] = Some((head, 0));
+        for &(member, edits) in &neighbours[head] {
+            if assigned[member].is_none() && barcodes[head].1 + 1 >= 2 * barcodes[member].1 {
+                assigned[member] = Some((head, edits));
+            }
+        }
+    }
+    assigned.into_iter().enumerate().map(|(i, a)| a.unwrap_or((i, 0))).collect()
+}
+
+// Count, cluster and write the correction map of every barcode in `reads` (FASTQ) as TSV
+pub fn correct(reads: &str, options: BarcodeOptions, out: &mut dyn Write) -> Result<CorrectionSummary, String> {
+    let mut summary = CorrectionSummary::default();
+    let barcodes = count_barcodes(reads, &options, &mut summary)?;
+    let neighbours = neighbours(&barcodes, options.max_edits, options.use_gpu)?;
+    let corrections: Vec<Correction> = cluster(&barcodes, &neighbours).into_iter().zip(&barcodes)
+        .map(|((corrected, edits), (observed, count))| Correction { observed: observed.clone(), corrected, count: *count, edits })
+        .collect();
+    summary.distinct = barcodes.len();
+    let write_error = |e: std::io::Error| format!("Failed to write correction map: {}", e);
+    writeln!(out, "#observed\tcorrected\tcount\tedits").map_err(write_error)?;
+    for (i, correction) in corrections.iter().enumerate() {
+        if correction.corrected == i {
+            summary.clusters += 1;
+        } else {
+            summary.reads_corrected += correction.count;
+        }
+        writeln!(out, "{}\t{}\t{}\t{}", String::from_utf8_lossy(&correction.observed),
+                 String::from_utf8_lossy(&barcodes[correction.corrected].0), correction.count, correction.edits).map_err(write_error)?;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,239 @@
+// Multi-sample batch orchestration
+// Each sample runs as its own rustseq_mini --full-wgs process with a
+// per-sample --output-dir, so checkpoints, benchmark results, and logs never mix
+// between samples and one failing sample doesn't take down the others
+
+use crate::aligner::CheckpointState;
+use crate::exit_codes::ExitKind;
+use crate::score_metrics::NormalizedScore;
+use std::fs::{create_dir_all, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// One manifest line: sample_id <TAB> data_dir [<TAB> comma-separated input files]
+#[derive(Debug, Clone)]
+pub struct SampleEntry {
+    pub sample_id: String,
+    pub data_dir: String,
+    pub input_files: Option<String>,
+}
+
+pub fn read_manifest(path: &str) -> Result<Vec<SampleEntry>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open manifest {}: {}", path, e))?;
+    let mut samples: Vec<SampleEntry> = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read manifest {}: {}", path, e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+        if fields.len() < 2 || fields[0].is_empty() || fields[1].is_empty() {
+            return Err(format!("Manifest line {}: expected sample_id<TAB>data_dir[<TAB>input_files]", i + 1));
+        }
+        if samples.iter().any(|s| s.sample_id == fields[0]) {
+            return Err(format!("Manifest line {}: duplicate sample id '{}'", i + 1, fields[0]));
+        }
+        samples.push(SampleEntry {
+            sample_id: fields[0].to_string(),
+            data_dir: fields[1].to_string(),
+            input_files: fields.get(2).filter(|f| !f.is_empty()).map(|f| f.to_string()),
+        });
+    }
+    if samples.is_empty() {
+        return Err(format!("Manifest {} lists no samples", path));
+    }
+    Ok(samples)
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleOutcome {
+    pub sample_id: String,
+    pub status: ExitKind,
+    // Finished with results, possibly with skipped chunks
+    pub succeeded: bool,
+    pub files_completed: usize,
+    pub total_files: usize,
+    pub total_reads: usize,
+    pub total_bases: usize,
+    pub total_score: i64,
+    pub processing_time_s: f64,
+    pub output_dir: PathBuf,
+}
+
+impl SampleOutcome {
+    pub fn normalized(&self) -> NormalizedScore {
+        NormalizedScore::from_totals(self.total_score, self.total_reads, self.total_bases)
+    }
+}
+
+// The forwarded options, with a --remote-state prefix narrowed to the sample
+// so samples don't overwrite each other's state
+fn sample_args(forwarded_args: &[String], sample_id: &str) -> Vec<String> {
+    let narrow = |url: &str| format!("{}/{}", url.trim_end_matches('/'), sample_id);
+    let mut args = Vec::with_capacity(forwarded_args.len());
+    let mut iter = forwarded_args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(url) = arg.strip_prefix("--remote-state=") {
+            args.push(format!("--remote-state={}", narrow(url)));
+        } else if arg == "--remote-state" {
+            args.push(arg.clone());
+            args.extend(iter.next().map(|url| narrow(url)));
+        } else {
+            args.push(arg.clone());
+        }
+    }
+    args
+}
+
+fn run_sample(sample: &SampleEntry, output_root: &Path, forwarded_args: &[String]) -> Result<SampleOutcome, String> {
+    let output_dir = output_root.join(&sample.sample_id);
+    create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+    let log_path = output_dir.join("run.log");
+    let log = File::create(&log_path)
+        .map_err(|e| format!("Failed to create {}: {}", log_path.display(), e))?;
+    let log_err = log.try_clone()
+        .map_err(|e| format!("Failed to open {}: {}", log_path.display(), e))?;
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Failed to locate rustseq_mini executable: {}", e))?;
+
+    let mut command = Command::new(exe);
+    command
+        // The child runs inside the sample directory, so its layout root is "."
+        .args(["--full-wgs", "--gpu", "--output-dir", "."])
+        .args(sample_args(forwarded_args, &sample.sample_id))
+        .current_dir(&output_dir)
+        .env("WGS_SAMPLE_ID", &sample.sample_id)
+        .env("WGS_DATA_DIR", &sample.data_dir)
+        .stdout(Stdio::from(log))
+        .stderr(Stdio::from(log_err));
+    match &sample.input_files {
+        Some(files) => command.env("WGS_INPUT_FILES", files),
+        None => command.env_remove("WGS_INPUT_FILES"),
+    };
+
+    println!("[{}] started (log: {})", sample.sample_id, log_path.display());
+    let started = std::time::Instant::now();
+    let status = command.status()
+        .map_err(|e| format!("Failed to start run for sample {}: {}", sample.sample_id, e))?;
+
+    let kind = ExitKind::from_code(status.code());
+    let checkpoint = CheckpointState::latest_in(&output_dir.join("checkpoints")).unwrap_or_else(|e| {
+        println!("[{}] Warning: {}", sample.sample_id, e);
+        None
+    });
+    let files = checkpoint.as_ref().map(|c| c.files.as_slice()).unwrap_or(&[]);
+    let outcome = SampleOutcome {
+        sample_id: sample.sample_id.clone(),
+        status: kind,
+        succeeded: kind.is_success(),
+        files_completed: files.iter().filter(|f| f.completed).count(),
+        total_files: checkpoint.as_ref().map(|c| c.total_files).unwrap_or(0),
+        total_reads: files.iter().map(|f| f.total_reads).sum(),
+        total_bases: files.iter().map(|f| f.total_bases).sum(),
+        total_score: files.iter().map(|f| f.score as i64).sum(),
+        processing_time_s: started.elapsed().as_secs_f64(),
+        output_dir,
+    };
+    println!("[{}] {} in {:.1} s", sample.sample_id,
+             match outcome.status {
+                 ExitKind::Success => "finished".to_string(),
+                 ExitKind::PartialSuccess => "finished with skipped chunks".to_string(),
+                 kind => format!("FAILED ({})", kind.name()),
+             }, outcome.processing_time_s);
+    Ok(outcome)
+}
+
+// Run every sample in the manifest, at most `parallel` at a time
+pub fn run_batch(manifest: &str, output_root: &str, parallel: usize, forwarded_args: &[String]) -> Result<Vec<SampleOutcome>, String> {
+    let samples = read_manifest(manifest)?;
+    let output_root = PathBuf::from(output_root);
+    create_dir_all(&output_root)
+        .map_err(|e| format!("Failed to create {}: {}", output_root.display(), e))?;
+    let workers = parallel.clamp(1, samples.len());
+    println!("Batch: {} samples, {} at a time, output in {}", samples.len(), workers, output_root.display());
+
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<(usize, SampleOutcome)>> = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(sample) = samples.get(index) else { break };
+                    let outcome = run_sample(sample, &output_root, forwarded_args).unwrap_or_else(|e| {
+                        println!("[{}] FAILED: {}", sample.sample_id, e);
+                        SampleOutcome {
+                            sample_id: sample.sample_id.clone(),
+                            status: ExitKind::Failure,
+                            succeeded: false,
+                            files_completed: 0,
+                            total_files: 0,
+                            total_reads: 0,
+                            total_bases: 0,
+                            total_score: 0,
+                            processing_time_s: 0.0,
+                            output_dir: output_root.join(&sample.sample_id),
+                        }
+                    });
+                    if let Ok(mut outcomes) = outcomes.lock() {
+                        outcomes.push((index, outcome));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().map_err(|_| "Batch results lock poisoned".to_string())?;
+    outcomes.sort_by_key(|(index, _)| *index);
+    let outcomes: Vec<SampleOutcome> = outcomes.into_iter().map(|(_, o)| o).collect();
+    write_summary(&output_root.join("batch_summary.tsv"), &outcomes)?;
+    Ok(outcomes)
+}
+
+const SUMMARY_HEADER: &str = "sample_id\tstatus\tfiles\treads\tbases\tscore\tscore_per_base\tscore_per_read\tidentity_weighted\ttime_s";
+
+fn summary_row(o: &SampleOutcome) -> String {
+    let n = o.normalized();
+    format!("{}\t{}\t{}/{}\t{}\t{}\t{}\t{:.4}\t{:.2}\t{:.4}\t{:.1}",
+            o.sample_id, o.status.name(), o.files_completed, o.total_files,
+            o.total_reads, o.total_bases, o.total_score,
+            n.score_per_base, n.score_per_read, n.identity_weighted, o.processing_time_s)
+}
+
+fn write_summary(path: &Path, outcomes: &[SampleOutcome]) -> Result<(), String> {
+    let mut file = File::create(path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    writeln!(file, "{}", SUMMARY_HEADER)
+        .and_then(|_| outcomes.iter().try_for_each(|o| writeln!(file, "{}", summary_row(o))))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Cross-sample table for the terminal
+pub fn print_summary(outcomes: &[SampleOutcome]) {
+    println!("\nBATCH SUMMARY");
+    println!("=============");
+    println!("{}", SUMMARY_HEADER.replace('\t', "  "));
+    for outcome in outcomes {
+        println!("{}", summary_row(outcome).replace('\t', "  "));
+    }
+    let failed = outcomes.iter().filter(|o| !o.succeeded).count();
+    println!("{} of {} samples succeeded", outcomes.len() - failed, outcomes.len());
+}
+
+// Exit status for the whole batch: success only if every sample fully succeeded,
+// a data error if none did
+pub fn exit_kind(outcomes: &[SampleOutcome]) -> ExitKind {
+    if outcomes.iter().all(|o| o.status == ExitKind::Success) {
+        ExitKind::Success
+    } else if outcomes.iter().any(|o| o.succeeded) {
+        ExitKind::PartialSuccess
+    } else {
+        ExitKind::DataError
+    }
+}
@@ -0,0 +1,628 @@
+// A minimal BigWig writer (UCSC's indexed binary wiggle, version 4), for
+// coverage tracks too large to load as bedGraph text in a genome browser
+// The file holds, in order: the 64-byte header, MAX_ZOOM_LEVELS zoom headers
+// (the unused ones zeroed, as UCSC's writer reserves them), the whole-file
+// summary, a B+ tree from chromosome names to ids and sizes, the data as
+// zlib-compressed sections of up to ITEMS_PER_SECTION bedGraph items (start,
+// end, value) each, all of one chromosome, an R tree over the sections' spans
+// for range lookups, then each zoom level's data and R tree
+// Chromosome ids are the order of `chroms`, and runs must come in that order
+// and by start within a chromosome, which is how a track sweeps a reference;
+// the name tree is sorted by name, as readers binary-search it. Both trees have
+// BLOCK_SIZE items a node, every node padded to full size as UCSC's writer does
+// Zoom levels are what a browser draws from when a window holds more bases than
+// pixels. Each summarizes the data in records of up to `reduction` bases (bases
+// covered, min, max, sum and sum of squares); the first reduction is four times
+// the mean run length and each next one ZOOM_INCREMENT times the last, as in
+// bedGraphToBigWig. The mean is only known once all runs are in, so the levels
+// are built by reading the written sections back, one pass a level, rather
+// than by holding the runs. Levels stop at MAX_ZOOM_LEVELS, at the largest
+// chromosome's size, or once a level is no longer half the size of the one
+// below it
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const BIGWIG_MAGIC: u32 = 0x888F_FC26;
+const CHROM_TREE_MAGIC: u32 = 0x78CA_8C91;
+const R_TREE_MAGIC: u32 = 0x2468_ACE0;
+const VERSION: u16 = 4;
+const BLOCK_SIZE: usize = 256;
+const ITEMS_PER_SECTION: usize = 1024;
+const HEADER_BYTES: u64 = 64;
+const SUMMARY_BYTES: u64 = 40;
+const ZOOM_HEADER_BYTES: u64 = 24;
+const MAX_ZOOM_LEVELS: usize = 10;
+const ZOOM_INCREMENT: u32 = 4;
+const ZOOM_RECORD_BYTES: usize = 32;
+const BEDGRAPH_SECTION: u8 = 1;
+
+// `value` over [start, end) of chromosome `chrom` (an index into the chroms)
+#[derive(Debug, Clone, Copy)]
+pub struct BigWigRun {
+    pub chrom: u32,
+    pub start: u32,
+    pub end: u32,
+    pub value: f32,
+}
+
+// A written data section, for the index
+struct Section {
+    chrom: u32,
+    start: u32,
+    end: u32,
+    offset: u64,
+    size: u64,
+}
+
+#[derive(Default)]
+struct Summary {
+    bases: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Summary {
+    fn add(&mut self, run: &BigWigRun) {
+        let (bases, value) = (f64::from(run.end - run.start), f64::from(run.value));
+        if self.bases == 0 {
+            (self.min, self.max) = (value, value);
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.bases += u64::from(run.end - run.start);
+        self.sum += bases * value;
+        self.sum_squares += bases * value * value;
+    }
+}
+
+// Writes `runs` over chromosomes given as (name, size)
+pub fn write(path: &str, chroms: &[(&str, usize)], runs: impl IntoIterator<Item = BigWigRun>) -> Result<(), String> {
+    // Opened for reading too: the zoom levels are built from the written data
+    let file = std::fs::File::options().read(true).write(true).create(true).truncate(true).open(path)
+        .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    write_to(file, chroms, runs).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+fn write_to<W: Read + Write + Seek>(mut out: W, chroms: &[(&str, usize)], runs: impl IntoIterator<Item = BigWigRun>) -> std::io::Result<()> {
+    for &(name, size) in chroms {
+        if size > u32::MAX as usize {
+            return Err(invalid(format!("chromosome {} is {} bases, more than BigWig's limit of {}", name, size, u32::MAX)));
+        }
+    }
+    // Header, zoom headers and summary are written last, once the offsets are known
+    let summary_offset = HEADER_BYTES + MAX_ZOOM_LEVELS as u64 * ZOOM_HEADER_BYTES;
+    out.write_all(&vec![0; (summary_offset + SUMMARY_BYTES) as usize])?;
+    let chrom_tree_offset = out.stream_position()?;
+    write_chrom_tree(&mut out, chroms)?;
+
+    let data_offset = out.stream_position()?;
+    out.write_all(&0u64.to_le_bytes())?;
+    let (mut sections, mut summary, mut largest_section) = (Vec::new(), Summary::default(), 0usize);
+    let mut pending: Vec<BigWigRun> = Vec::with_capacity(ITEMS_PER_SECTION);
+    let (mut last, mut items): (Option<(u32, u32)>, u64) = (None, 0);
+    for run in runs {
+        if run.chrom as usize >= chroms.len() || run.start >= run.end || run.end as usize > chroms[run.chrom as usize].1 {
+            return Err(invalid(format!("run {}:{}-{} is outside its chromosome", run.chrom, run.start, run.end)));
+        }
+        if last.is_some_and(|(chrom, end)| (run.chrom, run.start) < (chrom, end)) {
+            return Err(invalid(format!("run {}:{}-{} is out of order", run.chrom, run.start, run.end)));
+        }
+        last = Some((run.chrom, run.end));
+        summary.add(&run);
+        items += 1;
+        if pending.len() == ITEMS_PER_SECTION || pending.first().is_some_and(|first| first.chrom != run.chrom) {
+            largest_section = largest_section.max(write_section(&mut out, &pending, &mut sections)?);
+            pending.clear();
+        }
+        pending.push(run);
+    }
+    if !pending.is_empty() {
+        largest_section = largest_section.max(write_section(&mut out, &pending, &mut sections)?);
+    }
+
+    let index_offset = out.stream_position()?;
+    write_index(&mut out, &sections, index_offset)?;
+
+    let mut zooms = Vec::new();
+    let mut end = out.stream_position()?;
+    let largest_chrom = chroms.iter().map(|&(_, size)| size).max().unwrap_or(0);
+    let mut reduction = (summary.bases / items.max(1) * 4).clamp(1, u64::from(u32::MAX)) as u32;
+    let mut previous = items;
+    while !sections.is_empty() && zooms.len() < MAX_ZOOM_LEVELS && (reduction as usize) < largest_chrom {
+        let level = write_zoom_level(&mut out, chroms, &sections, reduction, end)?;
+        end = out.stream_position()?;
+        largest_section = largest_section.max(level.largest_block);
+        let records = level.records;
+        zooms.push(level);
+        if records * 2 > previous {
+            break;
+        }
+        previous = records;
+        reduction = reduction.saturating_mul(ZOOM_INCREMENT);
+    }
+    out.seek(SeekFrom::Start(end))?;
+    out.write_all(&BIGWIG_MAGIC.to_le_bytes())?;
+
+    out.seek(SeekFrom::Start(data_offset))?;
+    out.write_all(&(sections.len() as u64).to_le_bytes())?;
+    out.seek(SeekFrom::Start(0))?;
+    let mut header = Vec::with_capacity((summary_offset + SUMMARY_BYTES) as usize);
+    header.extend_from_slice(&BIGWIG_MAGIC.to_le_bytes());
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(zooms.len() as u16).to_le_bytes());
+    header.extend_from_slice(&chrom_tree_offset.to_le_bytes());
+    header.extend_from_slice(&data_offset.to_le_bytes());
+    header.extend_from_slice(&index_offset.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes()); // field count (bigBed only)
+    header.extend_from_slice(&0u16.to_le_bytes()); // defined field count
+    header.extend_from_slice(&0u64.to_le_bytes()); // autoSql offset
+    header.extend_from_slice(&summary_offset.to_le_bytes()); // total summary offset
+    header.extend_from_slice(&(largest_section as u32).to_le_bytes()); // uncompressed buffer size
+    header.extend_from_slice(&0u64.to_le_bytes()); // extension offset
+    for zoom in &zooms {
+        header.extend_from_slice(&zoom.reduction.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&zoom.data_offset.to_le_bytes());
+        header.extend_from_slice(&zoom.index_offset.to_le_bytes());
+    }
+    header.resize(summary_offset as usize, 0);
+    header.extend_from_slice(&summary.bases.to_le_bytes());
+    for value in [summary.min, summary.max, summary.sum, summary.sum_squares] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    out.write_all(&header)?;
+    out.flush()
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+// Compresses and writes one section; returns its uncompressed size
+fn write_section<W: Write + Seek>(out: &mut W, runs: &[BigWigRun], sections: &mut Vec<Section>) -> std::io::Result<usize> {
+    let (first, last) = (runs[0], runs[runs.len() - 1]);
+    let mut raw = Vec::with_capacity(24 + runs.len() * 12);
+    raw.extend_from_slice(&first.chrom.to_le_bytes());
+    raw.extend_from_slice(&first.start.to_le_bytes());
+    raw.extend_from_slice(&last.end.to_le_bytes());
+    raw.extend_from_slice(&0u32.to_le_bytes()); // item step (fixed-step only)
+    raw.extend_from_slice(&0u32.to_le_bytes()); // item span
+    raw.push(BEDGRAPH_SECTION);
+    raw.push(0);
+    raw.extend_from_slice(&(runs.len() as u16).to_le_bytes());
+    for run in runs {
+        raw.extend_from_slice(&run.start.to_le_bytes());
+        raw.extend_from_slice(&run.end.to_le_bytes());
+        raw.extend_from_slice(&run.value.to_le_bytes());
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&raw)?;
+    let compressed = encoder.finish()?;
+    let offset = out.stream_position()?;
+    out.write_all(&compressed)?;
+    sections.push(Section { chrom: first.chrom, start: first.start, end: last.end, offset, size: compressed.len() as u64 });
+    Ok(raw.len())
+}
+
+// Reads a written section's runs back
+fn read_section<R: Read + Seek>(input: &mut R, section: &Section) -> std::io::Result<Vec<BigWigRun>> {
+    input.seek(SeekFrom::Start(section.offset))?;
+    let mut compressed = vec![0; section.size as usize];
+    input.read_exact(&mut compressed)?;
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut raw)?;
+    let word = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+    let count = usize::from(u16::from_le_bytes([raw[22], raw[23]]));
+    Ok((0..count).map(|i| {
+        let at = 24 + i * 12;
+        BigWigRun { chrom: section.chrom, start: word(at), end: word(at + 4), value: f32::from_le_bytes(raw[at + 8..at + 12].try_into().unwrap()) }
+    }).collect())
+}
+
+// The data over up to a zoom level's reduction of one chromosome
+#[derive(Clone, Copy)]
+struct ZoomRecord {
+    chrom: u32,
+    start: u32,
+    end: u32,
+    bases: u32,
+    min: f32,
+    max: f32,
+    sum: f32,
+    sum_squares: f32,
+}
+
+// One zoom level as it is written: records go to blocks of up to
+// ITEMS_PER_SECTION, all of one chromosome like the data sections, so the
+// level's R tree bounds each block by one chromosome's span
+struct ZoomLevel {
+    reduction: u32,
+    data_offset: u64,
+    index_offset: u64,
+    // Where the current record's window ends
+    window_end: u32,
+    current: Option<ZoomRecord>,
+    block: Vec<ZoomRecord>,
+    blocks: Vec<Section>,
+    records: u64,
+    largest_block: usize,
+    // Where the next block goes
+    end: u64,
+}
+
+impl ZoomLevel {
+    fn add<W: Write + Seek>(&mut self, out: &mut W, run: &BigWigRun, chrom_size: u32) -> std::io::Result<()> {
+        let mut start = run.start;
+        while start < run.end {
+            if !self.current.is_some_and(|record| record.chrom == run.chrom && start < self.window_end) {
+                self.close(out)?;
+                self.window_end = start.saturating_add(self.reduction).min(chrom_size);
+                self.current = Some(ZoomRecord { chrom: run.chrom, start, end: start, bases: 0, min: run.value, max: run.value, sum: 0.0, sum_squares: 0.0 });
+            }
+            let end = run.end.min(self.window_end);
+            if let Some(record) = self.current.as_mut() {
+                let bases = (end - start) as f32;
+                record.end = end;
+                record.bases += end - start;
+                record.min = record.min.min(run.value);
+                record.max = record.max.max(run.value);
+                record.sum += bases * run.value;
+                record.sum_squares += bases * run.value * run.value;
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    // Moves the current record to the block, writing the block out first when
+    // it is full or of another chromosome
+    fn close<W: Write + Seek>(&mut self, out: &mut W) -> std::io::Result<()> {
+        let Some(record) = self.current.take() else { return Ok(()) };
+        if self.block.len() == ITEMS_PER_SECTION || self.block.first().is_some_and(|first| first.chrom != record.chrom) {
+            self.write_block(out)?;
+        }
+        self.block.push(record);
+        self.records += 1;
+        Ok(())
+    }
+
+    fn write_block<W: Write + Seek>(&mut self, out: &mut W) -> std::io::Result<()> {
+        let (Some(&first), Some(&last)) = (self.block.first(), self.block.last()) else { return Ok(()) };
+        let mut raw = Vec::with_capacity(self.block.len() * ZOOM_RECORD_BYTES);
+        for record in self.block.drain(..) {
+            for value in [record.chrom, record.start, record.end, record.bases] {
+                raw.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [record.min, record.max, record.sum, record.sum_squares] {
+                raw.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw)?;
+        let compressed = encoder.finish()?;
+        out.seek(SeekFrom::Start(self.end))?;
+        out.write_all(&compressed)?;
+        self.blocks.push(Section { chrom: first.chrom, start: first.start, end: last.end, offset: self.end, size: compressed.len() as u64 });
+        self.end += compressed.len() as u64;
+        self.largest_block = self.largest_block.max(raw.len());
+        Ok(())
+    }
+}
+
+// Writes a zoom level at `at` from the data `sections`: its record count, its
+// blocks and then its R tree
+fn write_zoom_level<W: Read + Write + Seek>(out: &mut W, chroms: &[(&str, usize)], sections: &[Section], reduction: u32, at: u64) -> std::io::Result<ZoomLevel> {
+    let mut level = ZoomLevel {
+        reduction, data_offset: at, index_offset: 0, window_end: 0, current: None,
+        block: Vec::with_capacity(ITEMS_PER_SECTION), blocks: Vec::new(), records: 0, largest_block: 0, end: at + 4,
+    };
+    for section in sections {
+        for run in read_section(out, section)? {
+            level.add(out, &run, chroms[run.chrom as usize].1 as u32)?;
+        }
+    }
+    level.close(out)?;
+    level.write_block(out)?;
+    out.seek(SeekFrom::Start(at))?;
+    out.write_all(&(level.records as u32).to_le_bytes())?;
+    level.index_offset = level.end;
+    out.seek(SeekFrom::Start(level.index_offset))?;
+    write_index(out, &level.blocks, level.index_offset)?;
+    Ok(level)
+}
+
+// Nodes a level of `items` items needs, from the leaves up to the root
+fn level_widths(items: usize, block: usize) -> Vec<usize> {
+    let mut widths = vec![items.div_ceil(block).max(1)];
+    while widths[widths.len() - 1] > 1 {
+        widths.push(widths[widths.len() - 1].div_ceil(block));
+    }
+    widths
+}
+
+// Writes a tree over `count` items, root first and each level's nodes in item
+// order. `node_bytes` is a padded node's size; `leaf` writes item i and
+// `branch` writes the entry for items [first, last] and the child at an offset
+fn write_tree<W: Write + Seek>(out: &mut W, count: usize, block: usize, node_bytes: u64,
+                               mut leaf: impl FnMut(&mut Vec<u8>, usize), mut branch: impl FnMut(&mut Vec<u8>, usize, usize, u64),
+                               node_header: impl Fn(bool, usize) -> Vec<u8>) -> std::io::Result<()> {
+    let widths = level_widths(count, block);
+    let base = out.stream_position()?;
+    // Offset of each level's first node, root level first
+    let mut level_offsets = Vec::with_capacity(widths.len());
+    let mut offset = base;
+    for &width in widths.iter().rev() {
+        level_offsets.push(offset);
+        offset += width as u64 * node_bytes;
+    }
+    for (depth, &width) in widths.iter().rev().enumerate() {
+        let height = widths.len() - 1 - depth;
+        // Items under one node of this level
+        let span = block.pow(height as u32 + 1);
+        for node in 0..width {
+            let first = node * span;
+            let children = if height == 0 {
+                count.saturating_sub(first).min(block)
+            } else {
+                count.saturating_sub(first).div_ceil(span / block).min(block)
+            };
+            let mut bytes = node_header(height == 0, children);
+            for child in 0..children {
+                if height == 0 {
+                    leaf(&mut bytes, first + child);
+                } else {
+                    let child_span = span / block;
+                    let child_first = first + child * child_span;
+                    let child_last = (child_first + child_span).min(count) - 1;
+                    let child_offset = level_offsets[depth + 1] + (node * block + child) as u64 * node_bytes;
+                    branch(&mut bytes, child_first, child_last, child_offset);
+                }
+            }
+            bytes.resize(node_bytes as usize, 0);
+            out.write_all(&bytes)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_chrom_tree<W: Write + Seek>(out: &mut W, chroms: &[(&str, usize)]) -> std::io::Result<()> {
+    let mut sorted: Vec<usize> = (0..chroms.len()).collect();
+    sorted.sort_by_key(|&i| chroms[i].0);
+    let key_size = chroms.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(1);
+    let block = BLOCK_SIZE.min(chroms.len()).max(1);
+    let item_bytes = key_size + 8;
+    let mut header = Vec::with_capacity(32);
+    header.extend_from_slice(&CHROM_TREE_MAGIC.to_le_bytes());
+    header.extend_from_slice(&(block as u32).to_le_bytes());
+    header.extend_from_slice(&(key_size as u32).to_le_bytes());
+    header.extend_from_slice(&8u32.to_le_bytes()); // value size: id and size
+    header.extend_from_slice(&(chroms.len() as u64).to_le_bytes());
+    header.extend_from_slice(&0u64.to_le_bytes());
+    out.write_all(&header)?;
+    let key = |bytes: &mut Vec<u8>, i: usize| {
+        let name = chroms[sorted[i]].0.as_bytes();
+        bytes.extend_from_slice(name);
+        bytes.resize(bytes.len() + key_size - name.len(), 0);
+    };
+    write_tree(out, chroms.len(), block, 4 + (block * item_bytes) as u64,
+               |bytes, i| {
+                   key(bytes, i);
+                   bytes.extend_from_slice(&(sorted[i] as u32).to_le_bytes());
+                   bytes.extend_from_slice(&(chroms[sorted[i]].1 as u32).to_le_bytes());
+               },
+               |bytes, first, _, child_offset| {
+                   key(bytes, first);
+                   bytes.extend_from_slice(&child_offset.to_le_bytes());
+               },
+               |is_leaf, count| vec![u8::from(is_leaf), 0, count as u8, (count >> 8) as u8])
+}
+
+fn write_index<W: Write + Seek>(out: &mut W, sections: &[Section], data_end: u64) -> std::io::Result<()> {
+    let (first, last) = (sections.first(), sections.last());
+    let mut header = Vec::with_capacity(48);
+    header.extend_from_slice(&R_TREE_MAGIC.to_le_bytes());
+    header.extend_from_slice(&(BLOCK_SIZE as u32).to_le_bytes());
+    header.extend_from_slice(&(sections.len() as u64).to_le_bytes());
+    header.extend_from_slice(&first.map_or(0, |s| s.chrom).to_le_bytes());
+    header.extend_from_slice(&first.map_or(0, |s| s.start).to_le_bytes());
+    header.extend_from_slice(&last.map_or(0, |s| s.chrom).to_le_bytes());
+    header.extend_from_slice(&last.map_or(0, |s| s.end).to_le_bytes());
+    header.extend_from_slice(&data_end.to_le_bytes());
+    header.extend_from_slice(&(ITEMS_PER_SECTION as u32).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    out.write_all(&header)?;
+    // Leaf items (bounds, data offset and size) are 32 bytes, branch items
+    // (bounds and child offset) 24; nodes are padded to the larger
+    let bounds = |bytes: &mut Vec<u8>, first: &Section, last: &Section| {
+        for value in [first.chrom, first.start, last.chrom, last.end] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    };
+    write_tree(out, sections.len(), BLOCK_SIZE, 4 + (BLOCK_SIZE * 32) as u64,
+               |bytes, i| {
+                   bounds(bytes, &sections[i], &sections[i]);
+                   bytes.extend_from_slice(&sections[i].offset.to_le_bytes());
+                   bytes.extend_from_slice(&sections[i].size.to_le_bytes());
+               },
+               |bytes, first, last, child_offset| {
+                   bounds(bytes, &sections[first], &sections[last]);
+                   bytes.extend_from_slice(&child_offset.to_le_bytes());
+               },
+               |is_leaf, count| vec![u8::from(is_leaf), 0, count as u8, (count >> 8) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn inflate(bytes: &[u8], offset: u64, size: u64) -> Vec<u8> {
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&bytes[offset as usize..(offset + size) as usize]).read_to_end(&mut raw).unwrap();
+        raw
+    }
+
+    // A name's (id, size) from the chrom tree at `offset`, descending from the
+    // root through the last branch key not past the name
+    fn lookup(bytes: &[u8], offset: u64, name: &str) -> Option<(u32, u32)> {
+        let at = offset as usize;
+        assert_eq!(u32_at(bytes, at), CHROM_TREE_MAGIC);
+        let key_size = u32_at(bytes, at + 8) as usize;
+        assert_eq!(u32_at(bytes, at + 12), 8);
+        let mut wanted = name.as_bytes().to_vec();
+        wanted.resize(key_size, 0);
+        let mut node = at + 32;
+        loop {
+            let (is_leaf, count) = (bytes[node] == 1, usize::from(u16_at(bytes, node + 2)));
+            let item = |i: usize| node + 4 + i * (key_size + 8);
+            let key = |i: usize| &bytes[item(i)..item(i) + key_size];
+            if is_leaf {
+                return (0..count).find(|&i| key(i) == wanted.as_slice())
+                    .map(|i| (u32_at(bytes, item(i) + key_size), u32_at(bytes, item(i) + key_size + 4)));
+            }
+            let child = (0..count).rev().find(|&i| key(i) <= wanted.as_slice())?;
+            node = u64_at(bytes, item(child) + key_size) as usize;
+        }
+    }
+
+    // The (offset, size) of every leaf of the R tree at `offset` whose bounds
+    // overlap [start, end) of `chrom`, in file order
+    fn overlapping(bytes: &[u8], offset: u64, chrom: u32, start: u32, end: u32) -> Vec<(u64, u64)> {
+        let at = offset as usize;
+        assert_eq!(u32_at(bytes, at), R_TREE_MAGIC);
+        let mut found = Vec::new();
+        let mut stack = vec![at + 48];
+        while let Some(node) = stack.pop() {
+            let (is_leaf, count) = (bytes[node] == 1, usize::from(u16_at(bytes, node + 2)));
+            let item_bytes = if is_leaf { 32 } else { 24 };
+            let mut children = Vec::new();
+            for i in 0..count {
+                let item = node + 4 + i * item_bytes;
+                let (first_chrom, first_start) = (u32_at(bytes, item), u32_at(bytes, item + 4));
+                let (last_chrom, last_end) = (u32_at(bytes, item + 8), u32_at(bytes, item + 12));
+                if (first_chrom, first_start) >= (chrom, end) || (last_chrom, last_end) <= (chrom, start) {
+                    continue;
+                }
+                if is_leaf {
+                    found.push((u64_at(bytes, item + 16), u64_at(bytes, item + 24)));
+                } else {
+                    children.push(u64_at(bytes, item + 16) as usize);
+                }
+            }
+            stack.extend(children.into_iter().rev());
+        }
+        found
+    }
+
+    #[test]
+    fn reads_back_through_the_trees() {
+        // Enough chromosomes for a branch level in the name tree (their names
+        // sort apart from their ids), and enough runs on one for a branch level
+        // in the R tree
+        let names: Vec<String> = (0..300).map(|i| format!("chr{}", i)).collect();
+        let chroms: Vec<(&str, usize)> = names.iter().enumerate()
+            .map(|(i, name)| (name.as_str(), if i == 0 { 4_000_000 } else { 10_000 + i }))
+            .collect();
+        let mut runs: Vec<BigWigRun> = (0..300_000)
+            .map(|i| BigWigRun { chrom: 0, start: i * 12, end: i * 12 + 10, value: (i % 7 + 1) as f32 })
+            .collect();
+        for chrom in [5, 150, 299] {
+            runs.push(BigWigRun { chrom, start: 100, end: 250, value: 2.0 });
+            runs.push(BigWigRun { chrom, start: 9_000, end: 9_500, value: 0.5 });
+        }
+        let mut out = Cursor::new(Vec::new());
+        write_to(&mut out, &chroms, runs.iter().copied()).unwrap();
+        let bytes = out.into_inner();
+
+        assert_eq!(u32_at(&bytes, 0), BIGWIG_MAGIC);
+        assert_eq!(u32_at(&bytes, bytes.len() - 4), BIGWIG_MAGIC);
+        assert_eq!(u16_at(&bytes, 4), VERSION);
+        let zoom_levels = usize::from(u16_at(&bytes, 6));
+        let (chrom_tree, data, index) = (u64_at(&bytes, 8), u64_at(&bytes, 16), u64_at(&bytes, 24));
+        let summary = u64_at(&bytes, 44);
+        assert_eq!(summary, HEADER_BYTES + MAX_ZOOM_LEVELS as u64 * ZOOM_HEADER_BYTES);
+        assert_eq!(chrom_tree, summary + SUMMARY_BYTES);
+        assert!(chrom_tree < data && data < index && (index as usize) < bytes.len());
+        let bases: u64 = runs.iter().map(|run| u64::from(run.end - run.start)).sum();
+        let sum: f64 = runs.iter().map(|run| f64::from(run.end - run.start) * f64::from(run.value)).sum();
+        assert_eq!(u64_at(&bytes, summary as usize), bases);
+        assert_eq!(f64::from_le_bytes(bytes[summary as usize + 24..summary as usize + 32].try_into().unwrap()), sum);
+
+        for (id, &(name, size)) in chroms.iter().enumerate() {
+            assert_eq!(lookup(&bytes, chrom_tree, name), Some((id as u32, size as u32)), "{}", name);
+        }
+        assert_eq!(lookup(&bytes, chrom_tree, "chrUn"), None);
+
+        // Every section, through the R tree, gives back the runs in order
+        let sections: Vec<_> = (0..chroms.len() as u32).flat_map(|chrom| overlapping(&bytes, index, chrom, 0, u32::MAX)).collect();
+        assert!(overlapping(&bytes, index, 0, 0, u32::MAX).len() > BLOCK_SIZE);
+        assert_eq!(u64_at(&bytes, data as usize), sections.len() as u64);
+        assert_eq!(u64_at(&bytes, index as usize + 8), sections.len() as u64);
+        let mut read = Vec::new();
+        for &(offset, size) in &sections {
+            let raw = inflate(&bytes, offset, size);
+            assert_eq!(raw[20], BEDGRAPH_SECTION);
+            let chrom = u32_at(&raw, 0);
+            let count = usize::from(u16_at(&raw, 22));
+            assert_eq!(raw.len(), 24 + count * 12);
+            for i in 0..count {
+                let at = 24 + i * 12;
+                read.push((chrom, u32_at(&raw, at), u32_at(&raw, at + 4), f32_at(&raw, at + 8)));
+            }
+        }
+        let expected: Vec<_> = runs.iter().map(|run| (run.chrom, run.start, run.end, run.value)).collect();
+        assert_eq!(read, expected);
+
+        // A range query reaches only the sections over it
+        let found = overlapping(&bytes, index, 150, 0, 1_000);
+        assert_eq!(found.len(), 1);
+        assert_eq!(u32_at(&inflate(&bytes, found[0].0, found[0].1), 0), 150);
+
+        // Each zoom level summarizes all the data, in records no wider than its
+        // reduction, and each level is coarser than the last
+        assert!(zoom_levels >= 2, "{} zoom levels", zoom_levels);
+        let mut last_reduction = 0;
+        for level in 0..zoom_levels {
+            let at = (HEADER_BYTES + level as u64 * ZOOM_HEADER_BYTES) as usize;
+            let (reduction, zoom_data, zoom_index) = (u32_at(&bytes, at), u64_at(&bytes, at + 8), u64_at(&bytes, at + 16));
+            assert!(reduction > last_reduction);
+            last_reduction = reduction;
+            let (mut records, mut zoom_bases, mut zoom_sum) = (0u32, 0u64, 0f64);
+            for (offset, size) in (0..chroms.len() as u32).flat_map(|chrom| overlapping(&bytes, zoom_index, chrom, 0, u32::MAX)) {
+                let raw = inflate(&bytes, offset, size);
+                for record in raw.chunks(ZOOM_RECORD_BYTES) {
+                    let (start, end) = (u32_at(record, 4), u32_at(record, 8));
+                    assert!(start < end && end - start <= reduction);
+                    assert!(u32_at(record, 12) <= end - start);
+                    assert!(f32_at(record, 16) <= f32_at(record, 20));
+                    zoom_bases += u64::from(u32_at(record, 12));
+                    zoom_sum += f64::from(f32_at(record, 24));
+                    records += 1;
+                }
+            }
+            assert_eq!(u32_at(&bytes, zoom_data as usize), records);
+            assert_eq!(zoom_bases, bases);
+            assert!((zoom_sum - sum).abs() < sum * 1e-4, "{} against {}", zoom_sum, sum);
+        }
+    }
+}
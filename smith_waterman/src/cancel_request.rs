@@ -0,0 +1,102 @@
+// `run cancel`: stop a full WGS run from outside its process, for runs started
+// by a scheduler or in the background, where there is no terminal to Ctrl-C
+// and SIGTERM would be taken for a preemption notice (preemption.rs)
+// The request is a file, `cancel`, in the run's logs directory (logs/run_N,
+// under --output-dir when there is one), so it can be made from wherever the
+// output directory is visible, another host on a shared filesystem included.
+// The run polls for it every second from the start; on a request it cancels its
+// token, so the current chunk finishes, the interrupted file is checkpointed as
+// incomplete and the run returns. It then saves its benchmark result, waits for
+// the uploads to --remote-state, removes the request and exits 130 as for
+// Ctrl-C, ready for --resume. `run cancel --wait` waits for that removal
+// Unlike a preemption there is no deadline: the chunk in flight finishes
+// however long it takes, since nothing is about to take the machine away
+
+use crate::exit_codes::ExitKind;
+use crate::run_control::CancellationToken;
+use crate::run_registry::{RunRecord, RunRegistry};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub const REQUEST_FILE: &str = "cancel";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+fn request_file(logs_dir: &Path) -> PathBuf {
+    logs_dir.join(REQUEST_FILE)
+}
+
+// Watch for a cancel request to `run` for the rest of the process, cancelling
+// `cancel` when one arrives
+pub fn watch(run: &RunRecord, cancel: &CancellationToken) -> Result<(), String> {
+    let path = request_file(&run.logs_dir);
+    let cancel = cancel.clone();
+    std::thread::Builder::new()
+        .name("cancel-watch".to_string())
+        .spawn(move || {
+            while !path.exists() {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            REQUESTED.store(true, Ordering::SeqCst);
+            crate::tui::stop();
+            println!("\nCancel requested ({}): stopping after the current chunk", path.display());
+            cancel.cancel();
+        })
+        .map_err(|e| format!("Failed to start cancel watcher: {}", e))?;
+    Ok(())
+}
+
+// Flush, upload, acknowledge the request and exit resumable, once the run has
+// returned
+pub fn shutdown(run: &RunRecord) -> ! {
+    crate::run_context::interrupt_cli();
+    crate::remote_state::flush();
+    crate::notify::send(&crate::notify::RunNotification::new(crate::notify::RunEvent::Interrupted));
+    let _ = std::fs::remove_file(request_file(&run.logs_dir));
+    println!("Run cancelled; completed files are checkpointed and the run can be resumed with --resume");
+    ExitKind::Interrupted.exit()
+}
+
+// Run `run` (its number, or its run id) in the registry of output
+// directory `dir`
+pub fn find_run(dir: &Path, run: &str) -> Result<RunRecord, String> {
+    let path = dir.join("run_registry.json");
+    let registry = RunRegistry::load(&path)?;
+    if registry.runs.is_empty() {
+        return Err(format!("No runs registered in {}", path.display()));
+    }
+    let by_number = run.trim_start_matches("run_").parse::<u64>().ok().and_then(|number| registry.find(number));
+    by_number.or_else(|| registry.runs.iter().find(|record| record.run_id == run))
+        .cloned()
+        .ok_or_else(|| format!("No run {} in {} (last run: {})", run, path.display(), registry.last_run))
+}
+
+// Ask `run` of output directory `dir` to stop; returns the request file
+pub fn request(dir: &Path, run: &RunRecord) -> Result<PathBuf, String> {
+    let logs_dir = dir.join("logs").join(format!("run_{}", run.number));
+    std::fs::create_dir_all(&logs_dir)
+        .map_err(|e| format!("Failed to create {}: {}", logs_dir.display(), e))?;
+    let path = request_file(&logs_dir);
+    let note = format!("requested {} by pid {}\n", chrono::Utc::now().to_rfc3339(), std::process::id());
+    std::fs::write(&path, note).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+// Wait until the run has removed request `path`, i.e. has checkpointed and is
+// exiting
+pub fn wait(path: &Path, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    while path.exists() {
+        if started.elapsed() >= timeout {
+            return Err(format!("{} still there after {} s: the run hasn't stopped yet, or isn't running", path.display(), timeout.as_secs()));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+// Checkpoint files on disk
+// A checkpoint is never rewritten in place: it is written in full to
+// <name>.tmp and synced, the current one is renamed to <name>.bak, and the new
+// one is renamed over it. A crash mid-write leaves the previous checkpoint
+// intact, and one that does get cut short (a full disk, a filesystem without
+// atomic rename) is passed over for the .bak when loading, with a warning
+// Checkpoints and the run registry restored from --remote-state are written
+// the same way (write_file)
+// With CHECKPOINT_COMPRESSION=zstd in .env, checkpoints over COMPRESS_ABOVE
+// (ones with many files, or failures listing many failed chunks) are written
+// zstd-compressed as <name>.zst instead. Loading takes either form, so runs
+// can switch between them and resume each other's checkpoints
+
+use std::path::{Path, PathBuf};
+
+pub const COMPRESS_ABOVE: usize = 64 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+// CHECKPOINT_COMPRESSION from .env: off (the default) or zstd
+pub fn compression_from_env() -> Result<bool, String> {
+    match std::env::var("CHECKPOINT_COMPRESSION") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "" | "off" | "none" => Ok(false),
+            "zstd" => Ok(true),
+            _ => Err(format!("Invalid CHECKPOINT_COMPRESSION '{}': expected off or zstd", value)),
+        },
+        Err(_) => Ok(false),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// `path` and its compressed form
+pub fn forms(path: &Path) -> [PathBuf; 2] {
+    [path.to_path_buf(), with_suffix(path, ".zst")]
+}
+
+// The checkpoint files of `path` that exist, newest generation first
+pub fn generations(path: &Path) -> Vec<PathBuf> {
+    let [plain, compressed] = forms(path);
+    [plain.clone(), compressed.clone(), with_suffix(&plain, ".bak"), with_suffix(&compressed, ".bak")]
+        .into_iter()
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+// The current checkpoint of `path` as written, compressed or not
+pub fn current(path: &Path) -> PathBuf {
+    forms(path).into_iter().find(|path| path.is_file()).unwrap_or_else(|| path.to_path_buf())
+}
+
+// Contents of one checkpoint file, decompressed if it is a .zst
+pub fn read(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let compressed = path.to_string_lossy().trim_end_matches(".bak").ends_with(".zst");
+    let bytes = if compressed {
+        zstd::decode_all(&bytes[..]).map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?
+    } else {
+        bytes
+    };
+    String::from_utf8(bytes).map_err(|_| format!("{} is not UTF-8 text", path.display()))
+}
+
+// Replace the checkpoint at `path` with `json`, keeping the current one as the
+// .bak; returns the file written
+pub fn write(path: &Path, json: &str, compress: bool) -> Result<PathBuf, String> {
+    let [plain, compressed] = forms(path);
+    let (target, other) = if compress && json.len() > COMPRESS_ABOVE { (compressed, plain) } else { (plain, compressed) };
+    let bytes = if target.extension().is_some_and(|extension| extension == "zst") {
+        zstd::encode_all(json.as_bytes(), ZSTD_LEVEL).map_err(|e| format!("Failed to compress checkpoint: {}", e))?
+    } else {
+        json.as_bytes().to_vec()
+    };
+    replace(&target, &other, &bytes)?;
+    Ok(target)
+}
+
+// Replace the file at `target` with `bytes` as they are, in the form `target`
+// names (a .zst is taken as already compressed), keeping the current one as the
+// .bak: a checkpoint or run registry fetched from the remote state
+pub fn write_file(target: &Path, bytes: &[u8]) -> Result<(), String> {
+    let plain = if target.extension().is_some_and(|extension| extension == "zst") { target.with_extension("") } else { target.to_path_buf() };
+    let [plain, compressed] = forms(&plain);
+    let other = if target == compressed { plain } else { compressed };
+    replace(target, &other, bytes)
+}
+
+// Write `bytes` to <target>.tmp and sync it, keep the current file (or its
+// `other` form) as the .bak, and rename the new one over it
+fn replace(target: &Path, other: &Path, bytes: &[u8]) -> Result<(), String> {
+    let temp = with_suffix(target, ".tmp");
+    let written = std::fs::File::create(&temp).and_then(|mut file| {
+        std::io::Write::write_all(&mut file, bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(format!("Failed to write {}: {}", temp.display(), e));
+    }
+    // One previous generation is kept, in whichever form it was written
+    if let Some(previous) = [target, other].into_iter().find(|path| path.is_file()) {
+        for stale in [with_suffix(target, ".bak"), with_suffix(other, ".bak")] {
+            let _ = std::fs::remove_file(stale);
+        }
+        std::fs::rename(previous, with_suffix(previous, ".bak"))
+            .map_err(|e| format!("Failed to keep the previous {}: {}", previous.display(), e))?;
+        let _ = std::fs::remove_file(other);
+    }
+    std::fs::rename(&temp, target)
+        .map_err(|e| format!("Failed to replace {}: {}", target.display(), e))?;
+    sync_dir(target);
+    Ok(())
+}
+
+// Persist the renames; not possible (or needed) everywhere, so failures are ignored
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        if let Ok(dir) = std::fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_file_keeps_the_previous_generation() {
+        let dir = std::env::temp_dir().join(format!("rustseq_mini_checkpoint_store_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wgs_checkpoint_run_1.json");
+
+        write_file(&path, b"{\"first\": 1}").unwrap();
+        write_file(&path, b"{\"second\": 2}").unwrap();
+        assert_eq!(read(&path).unwrap(), "{\"second\": 2}");
+        assert_eq!(read(&with_suffix(&path, ".bak")).unwrap(), "{\"first\": 1}");
+        assert!(!with_suffix(&path, ".tmp").exists());
+
+        // A compressed one fetched as is replaces the plain one, which becomes the .bak
+        let [_, compressed] = forms(&path);
+        write_file(&compressed, &zstd::encode_all(&b"{\"third\": 3}"[..], ZSTD_LEVEL).unwrap()).unwrap();
+        assert_eq!(generations(&path), [compressed.clone(), with_suffix(&path, ".bak")]);
+        assert_eq!(read(&current(&path)).unwrap(), "{\"third\": 3}");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,333 @@
+// `walk`: a synteny-style score track of a query genome along a reference, for
+// whole-chromosome comparisons one total score can't describe
+// The reference is streamed one contig at a time and a --window base window is
+// slid along each contig in --step steps. Each window is first placed on the
+// query by its k-mers: the query's 21-mers are indexed every INDEX_STEP bases
+// (ones occurring more than once are left out), every k-mer of the window that
+// is in the index votes for the query contig, strand and diagonal it lands on,
+// and the diagonal bucket with the most votes wins. With fewer than MIN_VOTES
+// the window has no place on the query and scores 0. A placed window is scored
+// against the query span under its votes, padded by MARGIN bases either side,
+// as the best ungapped local run over every diagonal, like align pairs and db
+// search (ungapped.rs); a minus-strand window is scored as its reverse
+// complement. Windows are gathered into batches of up to BATCH_WINDOWS windows
+// or BATCH_BASES bases, each one launch on the GPU (smith_waterman_paired_diagonals)
+// or a parallel pass on the CPU
+// The track is bedGraph, one line per window in reference order. When windows
+// overlap (--step under --window), a line covers the first --step bases of its
+// window, so lines never overlap. --placements also writes where each window
+// landed on the query, which is where inversions and translocations show up
+// `profile` walks a genome against itself, for repeats and segmental
+// duplications. The index then keeps k-mers occurring up to MAX_COPIES times,
+// with all their positions, and votes on a window's own contig and strand
+// within a window's length of its own diagonal are left out, so a window lands
+// on its best copy elsewhere or nowhere. Bases of the scored span that are the
+// window itself are masked to N, so the trivial diagonal never scores; copies
+// of a tandem repeat shorter than the window then only score by the parts
+// beyond the window's ends
+
+use crate::assembly_compare::{for_each_kmer, reverse_complement, ANCHOR_K};
+use crate::chunking::ChunkSize;
+use crate::ungapped::{self, ScoreMatrix, SequenceSet};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Write;
+
+pub const DEFAULT_WINDOW: usize = 10_000;
+// Query k-mers are indexed this far apart
+const INDEX_STEP: usize = 16;
+// Votes within this many diagonals of each other count together
+const DIAGONAL_BUCKET: i64 = 256;
+const MIN_VOTES: u32 = 2;
+// Most times a k-mer may occur and still be indexed, profiling a genome against itself
+const MAX_COPIES: usize = 8;
+const MARGIN: usize = 256;
+const BATCH_WINDOWS: usize = 4096;
+const BATCH_BASES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkSummary {
+    pub query_contigs: usize,
+    pub query_bases: usize,
+    pub contigs: usize,
+    pub windows: usize,
+    pub placed: usize,
+    pub minus_strand: usize,
+    pub batches: usize,
+}
+
+// Where a window landed on the query
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Placement {
+    pub(crate) contig: usize,
+    pub(crate) minus: bool,
+    // Span to score the window against, padded by MARGIN
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    // Where the window's first base (of its reverse complement on the minus
+    // strand) lands, by the lowest diagonal voted for
+    pub(crate) offset: usize,
+    pub(crate) votes: u32,
+}
+
+struct Window {
+    contig: String,
+    start: usize,
+    end: usize,
+    // Line end in the track
+    track_end: usize,
+    placement: Option<Placement>,
+}
+
+// Query positions of the k-mers that occur once among the indexed ones; also
+// places read mates on a reference (pair_stats.rs)
+pub(crate) struct QueryIndex {
+    positions: HashMap<u64, (u32, u32)>,
+    // Every position of k-mers occurring 2 to MAX_COPIES times, when profiling
+    // a genome against itself
+    copies: HashMap<u64, Vec<(u32, u32)>>,
+}
+
+impl QueryIndex {
+    const REPEATED: (u32, u32) = (u32::MAX, u32::MAX);
+
+    pub(crate) fn new(query: &[(String, Vec<u8>)]) -> Self {
+        Self::with_copies(query, 1)
+    }
+
+    fn with_copies(query: &[(String, Vec<u8>)], max_copies: usize) -> Self {
+        let (mut positions, mut copies) = (HashMap::new(), HashMap::new());
+        for (contig, (_, bases)) in query.iter().enumerate() {
+            for_each_kmer(bases, |start, forward, _| {
+                if start % INDEX_STEP == 0 {
+                    let position = (contig as u32, start as u32);
+                    match positions.entry(forward) {
+                        Entry::Vacant(entry) => {
+                            entry.insert(position);
+                        },
+                        Entry::Occupied(mut entry) => {
+                            if max_copies > 1 {
+                                let seen: &mut Vec<(u32, u32)> = copies.entry(forward).or_insert_with(|| vec![*entry.get()]);
+                                if seen.len() <= max_copies {
+                                    seen.push(position);
+                                }
+                            }
+                            entry.insert(Self::REPEATED);
+                        },
+                    }
+                }
+                true
+            });
+        }
+        positions.retain(|_, position| *position != Self::REPEATED);
+        copies.retain(|_, seen| seen.len() <= max_copies);
+        Self { positions, copies }
+    }
+
+    fn hits(&self, kmer: u64) -> &[(u32, u32)] {
+        match self.positions.get(&kmer) {
+            Some(position) => std::slice::from_ref(position),
+            None => self.copies.get(&kmer).map_or(&[], Vec::as_slice),
+        }
+    }
+
+    // The query contig, strand and span the window's k-mers vote for
+    pub(crate) fn place(&self, window: &[u8], query: &[(String, Vec<u8>)]) -> Option<Placement> {
+        self.place_elsewhere(window, query, None)
+    }
+
+    // As place(), leaving out votes on the plus strand of contig `own.0` within
+    // a window's length of diagonal `own.1`: where a window of the query itself lies
+    fn place_elsewhere(&self, window: &[u8], query: &[(String, Vec<u8>)], own: Option<(usize, usize)>) -> Option<Placement> {
+        let is_own = |contig: u32, diagonal: i64| {
+            own.is_some_and(|(own_contig, own_start)| contig as usize == own_contig && (diagonal - own_start as i64).abs() < window.len() as i64)
+        };
+        // (contig, minus, bucket) -> votes and the lowest and highest diagonal voted for
+        let mut votes: HashMap<(u32, bool, i64), (u32, i64, i64)> = HashMap::new();
+        let mut vote = |key: (u32, bool, i64), diagonal: i64| {
+            let entry = votes.entry(key).or_insert((0, diagonal, diagonal));
+            *entry = (entry.0 + 1, entry.1.min(diagonal), entry.2.max(diagonal));
+        };
+        for_each_kmer(window, |start, forward, reverse| {
+            for &(contig, position) in self.hits(forward) {
+                let diagonal = position as i64 - start as i64;
+                if !is_own(contig, diagonal) {
+                    vote((contig, false, diagonal.div_euclid(DIAGONAL_BUCKET)), diagonal);
+                }
+            }
+            // On the minus strand, positions are in the window's reverse complement
+            for &(contig, position) in self.hits(reverse) {
+                let diagonal = position as i64 - (window.len() - ANCHOR_K - start) as i64;
+                vote((contig, true, diagonal.div_euclid(DIAGONAL_BUCKET)), diagonal);
+            }
+            true
+        });
+        let (&(contig, minus, _), &(count, low, high)) = votes.iter()
+            .max_by_key(|&(&(contig, minus, bucket), &(count, _, _))| (count, std::cmp::Reverse((contig, minus, bucket))))?;
+        if count < MIN_VOTES {
+            return None;
+        }
+        let length = query[contig as usize].1.len() as i64;
+        let start = (low - MARGIN as i64).clamp(0, length) as usize;
+        let end = (high + (window.len() + MARGIN) as i64).clamp(0, length) as usize;
+        let offset = low.clamp(0, length) as usize;
+        Some(Placement { contig: contig as usize, minus, start, end, offset, votes: count })
+    }
+}
+
+struct Batch {
+    windows: Vec<Window>,
+    // Placed windows only, oriented to the strand they were placed on
+    queries: SequenceSet,
+    targets: SequenceSet,
+}
+
+impl Batch {
+    fn new() -> Self {
+        Self { windows: Vec::new(), queries: SequenceSet::new(), targets: SequenceSet::new() }
+    }
+
+    fn bases(&self) -> usize {
+        self.queries.residues.len() + self.targets.residues.len()
+    }
+}
+
+// Walk `reference` against `query`, writing the bedGraph track to `track` and
+// each window's placement to `placements`
+pub fn walk(query_path: &str, reference_path: &str, window: usize, step: usize, use_gpu: bool,
+            track: &mut dyn Write, mut placements: Option<&mut dyn Write>) -> Result<WalkSummary, String> {
+    if !crate::contig_chunks::is_contig_target(reference_path)? {
+        return Err(format!("walk needs a FASTA reference, and {} is not one", reference_path));
+    }
+    let query = crate::simulate::load_reference(query_path)?;
+    let index = QueryIndex::new(&query);
+    let matrix = ScoreMatrix::nucleotide();
+    let mut summary = WalkSummary {
+        query_contigs: query.len(),
+        query_bases: query.iter().map(|(_, bases)| bases.len()).sum(),
+        ..Default::default()
+    };
+    write_headers(&format!("walk {}", crate::paths::file_name(query_path)), track, &mut placements)?;
+
+    let mut batch = Batch::new();
+    // One contig per chunk, so the reference is never loaded whole
+    crate::contig_chunks::for_each_window(reference_path, ChunkSize::Reads(1), |contig| {
+        summary.contigs += 1;
+        let mut start = 0;
+        while start < contig.bases.len() {
+            let end = contig.bases.len().min(start + window);
+            let bases = contig.bases[start..end].to_ascii_uppercase();
+            let placement = index.place(&bases, &query);
+            if let Some(placement) = placement {
+                let bases = if placement.minus { reverse_complement(&bases) } else { bases };
+                batch.queries.push(&bases, &matrix);
+                batch.targets.push(&query[placement.contig].1[placement.start..placement.end], &matrix);
+            }
+            batch.windows.push(Window { contig: contig.contig.to_string(), start, end, track_end: end.min(start + step), placement });
+            if batch.windows.len() >= BATCH_WINDOWS || batch.bases() >= BATCH_BASES {
+                score_batch(std::mem::replace(&mut batch, Batch::new()), &query, &matrix, use_gpu, &mut summary, track, &mut placements)?;
+            }
+            start += step;
+        }
+        Ok(())
+    })?;
+    if !batch.windows.is_empty() {
+        score_batch(batch, &query, &matrix, use_gpu, &mut summary, track, &mut placements)?;
+    }
+    if summary.windows == 0 {
+        return Err(format!("Reference {} has no sequence", reference_path));
+    }
+    Ok(summary)
+}
+
+// Walk `genome` against itself, each window scored against its best copy
+// elsewhere in the genome; outputs as walk()
+pub fn profile(genome_path: &str, window: usize, step: usize, use_gpu: bool,
+               track: &mut dyn Write, mut placements: Option<&mut dyn Write>) -> Result<WalkSummary, String> {
+    let genome = crate::simulate::load_reference(genome_path)?;
+    let index = QueryIndex::with_copies(&genome, MAX_COPIES);
+    let matrix = ScoreMatrix::nucleotide();
+    let mut summary = WalkSummary {
+        query_contigs: genome.len(),
+        query_bases: genome.iter().map(|(_, bases)| bases.len()).sum(),
+        contigs: genome.len(),
+        ..Default::default()
+    };
+    write_headers(&format!("profile {}", crate::paths::file_name(genome_path)), track, &mut placements)?;
+
+    let mut batch = Batch::new();
+    for (contig, (name, contig_bases)) in genome.iter().enumerate() {
+        let mut start = 0;
+        while start < contig_bases.len() {
+            let end = contig_bases.len().min(start + window);
+            let bases = contig_bases[start..end].to_ascii_uppercase();
+            let placement = index.place_elsewhere(&bases, &genome, Some((contig, start)));
+            if let Some(placement) = placement {
+                let mut target = genome[placement.contig].1[placement.start..placement.end].to_vec();
+                if placement.contig == contig && !placement.minus {
+                    for base in &mut target[start.clamp(placement.start, placement.end) - placement.start..end.clamp(placement.start, placement.end) - placement.start] {
+                        *base = b'N';
+                    }
+                }
+                let bases = if placement.minus { reverse_complement(&bases) } else { bases };
+                batch.queries.push(&bases, &matrix);
+                batch.targets.push(&target, &matrix);
+            }
+            batch.windows.push(Window { contig: name.clone(), start, end, track_end: end.min(start + step), placement });
+            if batch.windows.len() >= BATCH_WINDOWS || batch.bases() >= BATCH_BASES {
+                score_batch(std::mem::replace(&mut batch, Batch::new()), &genome, &matrix, use_gpu, &mut summary, track, &mut placements)?;
+            }
+            start += step;
+        }
+    }
+    if !batch.windows.is_empty() {
+        score_batch(batch, &genome, &matrix, use_gpu, &mut summary, track, &mut placements)?;
+    }
+    if summary.windows == 0 {
+        return Err(format!("Genome {} has no sequence", genome_path));
+    }
+    Ok(summary)
+}
+
+fn write_headers(track_name: &str, track: &mut dyn Write, placements: &mut Option<&mut dyn Write>) -> Result<(), String> {
+    writeln!(track, "track type=bedGraph name=\"{}\"", track_name).map_err(|e| format!("Failed to write the track: {}", e))?;
+    if let Some(out) = placements.as_mut() {
+        writeln!(out, "#contig\tstart\tend\tquery_contig\tquery_start\tquery_end\tstrand\tvotes\tscore")
+            .map_err(|e| format!("Failed to write placements: {}", e))?;
+    }
+    Ok(())
+}
+
+fn score_batch(batch: Batch, query: &[(String, Vec<u8>)], matrix: &ScoreMatrix, use_gpu: bool, summary: &mut WalkSummary,
+               track: &mut dyn Write, placements: &mut Option<&mut dyn Write>) -> Result<(), String> {
+    let matrix_of = vec![0; batch.queries.len()];
+    let scores = if batch.queries.is_empty() {
+        Vec::new()
+    } else {
+        ungapped::paired_scores(&batch.queries, &batch.targets, std::slice::from_ref(matrix), &matrix_of, use_gpu)?
+    };
+    let mut scores = scores.into_iter();
+    for window in &batch.windows {
+        let score = match window.placement {
+            Some(_) => scores.next().unwrap_or(0),
+            None => 0,
+        };
+        writeln!(track, "{}\t{}\t{}\t{}", window.contig, window.start, window.track_end, score)
+            .map_err(|e| format!("Failed to write the track: {}", e))?;
+        if let Some(out) = placements.as_mut() {
+            let written = match window.placement {
+                Some(p) => writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", window.contig, window.start, window.end,
+                                    query[p.contig].0, p.start, p.end, if p.minus { '-' } else { '+' }, p.votes, score),
+                None => writeln!(out, "{}\t{}\t{}\t.\t.\t.\t.\t0\t0", window.contig, window.start, window.end),
+            };
+            written.map_err(|e| format!("Failed to write placements: {}", e))?;
+        }
+        if let Some(placement) = window.placement {
+            summary.placed += 1;
+            summary.minus_strand += placement.minus as usize;
+        }
+    }
+    summary.windows += batch.windows.len();
+    summary.batches += 1;
+    Ok(())
+}
@@ -0,0 +1,214 @@
+// Per-chunk audit log of full WGS runs, and the reproducer that reads it
+// Every chunk a run aligns adds a line to chunks.tsv in the run's logs
+// directory (logs/run_N): its id (file:chunk, as in the run's chunk errors),
+// the file, which of the file's reads it holds, the bases launched, the score
+// or the error, the kernel and how long the launch took from submission to its
+// score. The reads are counted as the reader delivered them, before
+// sanitizing, trimming or binning, so `debug extract-chunk <run> <chunk>` can
+// read the file again and write exactly those reads to a FASTQ, to align on
+// their own when a chunk's score or time looks wrong. The header keeps the
+// input options the run read with (format, region, reference), so the reader
+// sees the same reads the second time
+// Each line is flushed as it is written: a crash leaves the log complete up to
+// the chunks that were in flight
+
+use crate::alignment_input::{AlignmentInputOptions, InputFormat};
+use crate::chunking::ChunkSize;
+use crate::read_chunk::{ReadName, ReadNameMode};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const AUDIT_FILE: &str = "chunks.tsv";
+const COLUMNS: &str = "chunk\tfile_index\tfile\tfirst_read\treads\tbases\tscore\tkernel\tlaunch_ms\terror";
+
+// One chunk's outcome, as the run saw it
+pub struct ChunkRecord<'a> {
+    pub file_index: usize,
+    pub file: &'a str,
+    pub chunk_index: usize,
+    // Reads of the file, from 0, before any of them were dropped or trimmed
+    pub first_read: usize,
+    pub reads: usize,
+    pub bases: usize,
+    pub score: Result<i32, &'a str>,
+    pub launch: Duration,
+}
+
+pub struct ChunkAudit {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+// "3:17": chunk 17 of file 3, both from 1
+pub fn chunk_id(file_index: usize, chunk_index: usize) -> String {
+    format!("{}:{}", file_index + 1, chunk_index)
+}
+
+impl ChunkAudit {
+    pub fn create(logs_dir: &Path, input: &AlignmentInputOptions) -> Result<Self, String> {
+        std::fs::create_dir_all(logs_dir)
+            .map_err(|e| format!("Failed to create {}: {}", logs_dir.display(), e))?;
+        let path = logs_dir.join(AUDIT_FILE);
+        let file = File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut audit = Self { path, out: BufWriter::new(file) };
+        let mut header = format!("# input_format\t{}\n", input.format.name());
+        if let Some(region) = &input.region {
+            header.push_str(&format!("# region\t{}\n", region));
+        }
+        if let Some(reference) = &input.reference {
+            header.push_str(&format!("# reference\t{}\n", reference));
+        }
+        header.push_str(COLUMNS);
+        audit.line(&header)?;
+        Ok(audit)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, chunk: &ChunkRecord) -> Result<(), String> {
+        let (score, error) = match chunk.score {
+            Ok(score) => (score.to_string(), String::new()),
+            Err(e) => (String::new(), e.replace(['\t', '\n'], " ")),
+        };
+        let line = format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.3}\t{}",
+                           chunk_id(chunk.file_index, chunk.chunk_index), chunk.file_index, chunk.file, chunk.first_read,
+                           chunk.reads, chunk.bases, score, crate::kernels::selected().name(),
+                           chunk.launch.as_secs_f64() * 1000.0, error);
+        self.line(&line)
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.out, "{}", line)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub chunk: String,
+    pub file: String,
+    pub first_read: usize,
+    pub reads: usize,
+    pub bases: usize,
+    pub score: Option<i32>,
+    pub kernel: String,
+    pub launch_ms: f64,
+    pub error: String,
+}
+
+pub struct AuditLog {
+    pub input: AlignmentInputOptions,
+    pub entries: Vec<AuditEntry>,
+}
+
+// The audit log of run `run` in output directory `dir`
+pub fn audit_file(dir: &Path, run: u64) -> PathBuf {
+    dir.join("logs").join(format!("run_{}", run)).join(AUDIT_FILE)
+}
+
+impl AuditLog {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut log = Self { input: AlignmentInputOptions::default(), entries: Vec::new() };
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if let Some(option) = line.strip_prefix("# ") {
+                match option.split_once('\t') {
+                    Some(("input_format", format)) => log.input.format = InputFormat::parse(format)?,
+                    Some(("region", region)) => log.input.region = Some(region.to_string()),
+                    Some(("reference", reference)) => log.input.reference = Some(reference.to_string()),
+                    _ => {},
+                }
+                continue;
+            }
+            if line.is_empty() || line == COLUMNS {
+                continue;
+            }
+            let bad = || format!("{} line {}: expected {} tab-separated columns", path.display(), number + 1, COLUMNS.split('\t').count());
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != COLUMNS.split('\t').count() {
+                return Err(bad());
+            }
+            let count = |field: &str| field.parse::<usize>().map_err(|_| bad());
+            log.entries.push(AuditEntry {
+                chunk: fields[0].to_string(),
+                file: fields[2].to_string(),
+                first_read: count(fields[3])?,
+                reads: count(fields[4])?,
+                bases: count(fields[5])?,
+                score: fields[6].parse().ok(),
+                kernel: fields[7].to_string(),
+                launch_ms: fields[8].parse().map_err(|_| bad())?,
+                error: fields[9].to_string(),
+            });
+        }
+        Ok(log)
+    }
+
+    // The chunk's last line: a file retried after a stall logs its chunks again
+    pub fn find(&self, chunk: &str) -> Result<&AuditEntry, String> {
+        self.entries.iter().rev().find(|entry| entry.chunk == chunk)
+            .ok_or_else(|| format!("No chunk {} in the audit log ({} chunks; ids are file:chunk, e.g. 1:1)", chunk, self.entries.len()))
+    }
+}
+
+impl AuditEntry {
+    pub fn print(&self) {
+        let outcome = match self.score {
+            Some(score) => format!("score {}", score),
+            None => format!("failed: {}", self.error),
+        };
+        println!("Chunk {}: reads {} to {} of {}, {} bases, {}, {} kernel, launch {:.1} ms",
+                 self.chunk, self.first_read, self.first_read + self.reads, self.file, self.bases, outcome, self.kernel, self.launch_ms);
+    }
+}
+
+// Read `entry`'s file again and write the chunk's reads as FASTQ; reads
+// without qualities (FASTA, BAM without them) get 'I' for every base
+pub fn extract(entry: &AuditEntry, input: &AlignmentInputOptions, out: &mut dyn Write) -> Result<usize, String> {
+    crate::read_chunk::keep_qualities(true);
+    let end = entry.first_read + entry.reads;
+    let mut position = 0;
+    let mut written = 0;
+    let mut failed = None;
+    crate::aligner::read_file_only(&entry.file, input, ChunkSize::Bases(crate::chunking::DEFAULT_CHUNK_BASES), ReadNameMode::Keep, |chunk| {
+        for index in 0..chunk.len() {
+            let read = position + index;
+            if read < entry.first_read || read >= end {
+                continue;
+            }
+            let name = match chunk.name(index) {
+                Some(ReadName::Name(name)) => String::from_utf8_lossy(name).to_string(),
+                _ => format!("chunk_{}_read_{}", entry.chunk.replace(':', "_"), read),
+            };
+            let bases = chunk.read(index);
+            let quality = chunk.quality(index).filter(|quality| quality.len() == bases.len())
+                .map_or_else(|| vec![b'I'; bases.len()], |quality| quality.to_vec());
+            let record = writeln!(out, "@{}", name)
+                .and_then(|_| out.write_all(bases))
+                .and_then(|_| out.write_all(b"\n+\n"))
+                .and_then(|_| out.write_all(&quality))
+                .and_then(|_| out.write_all(b"\n"));
+            if let Err(e) = record {
+                failed = Some(format!("Failed to write the chunk's reads: {}", e));
+                return false;
+            }
+            written += 1;
+        }
+        position += chunk.len();
+        position < end
+    })?;
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    if written < entry.reads {
+        return Err(format!("{} has only {} reads; chunk {} needs reads {} to {} (has the file changed since the run?)",
+                           entry.file, position, entry.chunk, entry.first_read, end));
+    }
+    Ok(written)
+}
@@ -0,0 +1,104 @@
+// Per-chunk latency histogram for WGS runs
+// A chunk's latency is the time from the previous chunk finishing (or the file
+// starting) to this chunk finishing, so it includes waiting on the reader as well
+// as alignment. Averages hide stragglers from I/O stalls or thermal throttling;
+// the percentiles and the slowest chunks (with their file and read offset) don't
+// The histogram is the run's (run_context::RunState)
+
+use crate::run_context::with_state;
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Slowest chunks kept for the report
+const SLOWEST_KEPT: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlowChunk {
+    pub file: String,
+    pub chunk_index: usize,
+    // Index of the chunk's first read within the file
+    pub first_read: usize,
+    pub latency_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkLatencySummary {
+    pub chunks: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub slowest: Vec<SlowChunk>,
+}
+
+impl ChunkLatencySummary {
+    pub fn print(&self) {
+        if self.chunks == 0 {
+            return;
+        }
+        println!("Chunk latency ({} chunks): mean {:.1} ms, p50 {:.1} ms, p95 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+                 self.chunks, self.mean_ms, self.p50_ms, self.p95_ms, self.p99_ms, self.max_ms);
+        for chunk in &self.slowest {
+            println!("   {:.1} ms  {} chunk {} (reads from {})",
+                     chunk.latency_ms, crate::paths::file_name(&chunk.file), chunk.chunk_index, chunk.first_read);
+        }
+    }
+}
+
+pub struct LatencyTracker {
+    // Microseconds, 1 us to 1 h at 3 significant digits
+    histogram: Histogram<u64>,
+    slowest: Vec<SlowChunk>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self {
+            histogram: Histogram::new_with_bounds(1, 3_600_000_000, 3).expect("valid histogram bounds"),
+            slowest: Vec::new(),
+        }
+    }
+}
+
+impl LatencyTracker {
+    fn record(&mut self, file: &str, chunk_index: usize, first_read: usize, latency: Duration) {
+        self.histogram.saturating_record(latency.as_micros().max(1) as u64);
+
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let is_slow = self.slowest.len() < SLOWEST_KEPT
+            || self.slowest.last().is_some_and(|s| latency_ms > s.latency_ms);
+        if is_slow {
+            self.slowest.push(SlowChunk { file: file.to_string(), chunk_index, first_read, latency_ms });
+            self.slowest.sort_by(|a, b| b.latency_ms.total_cmp(&a.latency_ms));
+            self.slowest.truncate(SLOWEST_KEPT);
+        }
+    }
+
+    fn summary(&self) -> ChunkLatencySummary {
+        let h = &self.histogram;
+        let ms = |us: u64| us as f64 / 1000.0;
+        ChunkLatencySummary {
+            chunks: h.len(),
+            mean_ms: h.mean() / 1000.0,
+            p50_ms: ms(h.value_at_quantile(0.50)),
+            p95_ms: ms(h.value_at_quantile(0.95)),
+            p99_ms: ms(h.value_at_quantile(0.99)),
+            max_ms: ms(h.max()),
+            slowest: self.slowest.clone(),
+        }
+    }
+}
+
+pub fn record(file: &str, chunk_index: usize, first_read: usize, latency: Duration) {
+    with_state(|run| {
+        if let Ok(mut tracker) = run.latency.lock() {
+            tracker.record(file, chunk_index, first_read, latency);
+        }
+    });
+}
+
+pub fn summary() -> ChunkLatencySummary {
+    with_state(|run| run.latency.lock().map(|tracker| tracker.summary()).unwrap_or_default())
+}
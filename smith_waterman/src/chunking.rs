@@ -0,0 +1,154 @@
+// Chunk size for every read pipeline (WGS readers, pairwise file alignment, base counting)
+// Set once from --chunk-size; without it, GPU_CHUNK_SIZE_READS or GPU_CHUNK_SIZE_BASES
+// from .env, and otherwise 1 MB of bases. A chunk is handed to the GPU as soon as it
+// reaches the limit, so a bases limit can be exceeded by at most one read
+// "auto" (--chunk-size auto, or as either .env value) starts WGS runs at a
+// conservative size and adapts it to the kernel latency (adaptive_chunks.rs)
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+// Matches the old --chunk-size default
+pub const DEFAULT_CHUNK_BASES: usize = 1024 * 1024;
+
+// Read length assumed when preallocating a chunk sized in bases
+pub(crate) const TYPICAL_READ_LENGTH: usize = 150;
+
+// Where an adaptive chunk size starts: a quarter of the default
+pub const ADAPTIVE_START_BASES: usize = DEFAULT_CHUNK_BASES / 4;
+pub const ADAPTIVE_START_READS: usize = ADAPTIVE_START_BASES / TYPICAL_READ_LENGTH;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkSize {
+    Reads(usize),
+    Bases(usize),
+}
+
+impl ChunkSize {
+    // "500000r" / "500000reads" for reads, "64KB" / "16MB" / "1GB" for bases,
+    // and a bare number for MB of bases (the original meaning of --chunk-size)
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let lower = value.trim().to_ascii_lowercase();
+        let split = lower.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(split);
+        let number: f64 = number.parse()
+            .map_err(|_| format!("Invalid chunk size '{}': expected e.g. 500000reads, 512KB, or 16MB", value))?;
+        let size = match unit.trim() {
+            "r" | "reads" => ChunkSize::Reads(number as usize),
+            "" | "m" | "mb" => ChunkSize::Bases((number * 1024.0 * 1024.0) as usize),
+            "k" | "kb" => ChunkSize::Bases((number * 1024.0) as usize),
+            "g" | "gb" => ChunkSize::Bases((number * 1024.0 * 1024.0 * 1024.0) as usize),
+            "b" | "bases" => ChunkSize::Bases(number as usize),
+            other => return Err(format!("Invalid chunk size unit '{}' in '{}'", other, value)),
+        };
+        if size.value() == 0 {
+            return Err(format!("Chunk size '{}' must be greater than zero", value));
+        }
+        Ok(size)
+    }
+
+    // GPU_CHUNK_SIZE_READS takes precedence over GPU_CHUNK_SIZE_BASES; true
+    // with the size when the value is "auto"
+    pub fn from_env() -> Result<Option<(Self, bool)>, String> {
+        for (name, make, start) in [("GPU_CHUNK_SIZE_READS", ChunkSize::Reads as fn(usize) -> ChunkSize, ADAPTIVE_START_READS),
+                                    ("GPU_CHUNK_SIZE_BASES", ChunkSize::Bases, ADAPTIVE_START_BASES)] {
+            if let Ok(value) = std::env::var(name) {
+                if value.trim().eq_ignore_ascii_case("auto") {
+                    return Ok(Some((make(start), true)));
+                }
+                let n = value.trim().parse::<usize>()
+                    .map_err(|e| format!("Invalid {} value '{}': {}", name, value, e))?;
+                if n == 0 {
+                    return Err(format!("{} must be greater than zero", name));
+                }
+                return Ok(Some((make(n), false)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn value(&self) -> usize {
+        match *self {
+            ChunkSize::Reads(n) | ChunkSize::Bases(n) => n,
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            ChunkSize::Reads(_) => "reads",
+            ChunkSize::Bases(_) => "bases",
+        }
+    }
+
+    // True once a chunk holding `reads` reads and `bases` bases should be sent on;
+    // while the current run adapts its chunk size in this unit, at the adapted size
+    pub fn is_full(&self, reads: usize, bases: usize) -> bool {
+        match (*self, crate::adaptive_chunks::limit()) {
+            (ChunkSize::Reads(_), Some(ChunkSize::Reads(limit))) => reads >= limit,
+            (ChunkSize::Bases(_), Some(ChunkSize::Bases(limit))) => bases >= limit,
+            (ChunkSize::Reads(n), _) => reads >= n,
+            (ChunkSize::Bases(n), _) => bases >= n,
+        }
+    }
+
+    // Reads to preallocate room for
+    pub fn capacity_reads(&self) -> usize {
+        match *self {
+            ChunkSize::Reads(n) => n,
+            ChunkSize::Bases(n) => (n / TYPICAL_READ_LENGTH).clamp(1, 1 << 20),
+        }
+    }
+
+    // Chunks needed for an input of the given size
+    pub fn estimated_chunks(&self, reads: usize, bases: usize) -> usize {
+        match *self {
+            ChunkSize::Reads(n) => reads.div_ceil(n),
+            ChunkSize::Bases(n) => bases.div_ceil(n),
+        }
+    }
+}
+
+impl std::fmt::Display for ChunkSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.value(), self.unit())
+    }
+}
+
+// Size, whether it adapts, and where it came from
+static CHUNK_SIZE: OnceCell<(ChunkSize, bool, &'static str)> = OnceCell::new();
+
+// Set the size from the command line; must run before the first get()
+pub fn install(size: ChunkSize) {
+    let _ = CHUNK_SIZE.set((size, false, "--chunk-size"));
+}
+
+// --chunk-size auto: adapted from a conservative start in bases
+pub fn install_adaptive() {
+    let _ = CHUNK_SIZE.set((ChunkSize::Bases(ADAPTIVE_START_BASES), true, "--chunk-size"));
+}
+
+fn resolved() -> Result<&'static (ChunkSize, bool, &'static str), String> {
+    CHUNK_SIZE.get_or_try_init(|| {
+        Ok(match ChunkSize::from_env()? {
+            Some((size, adaptive)) => (size, adaptive, ".env"),
+            None => (ChunkSize::Bases(DEFAULT_CHUNK_BASES), false, "default"),
+        })
+    })
+}
+
+// The configured size (the starting size when it adapts), resolved from .env
+// or the default on first use
+pub fn get() -> Result<ChunkSize, String> {
+    resolved().map(|(size, _, _)| *size)
+}
+
+// True for "auto"
+pub fn is_adaptive() -> bool {
+    resolved().is_ok_and(|(_, adaptive, _)| *adaptive)
+}
+
+// Where the size came from, for the startup banner
+pub fn source() -> &'static str {
+    CHUNK_SIZE.get().map(|(_, _, source)| *source).unwrap_or("default")
+}
@@ -0,0 +1,229 @@
+// --screen: contamination screening against a panel of references during the WGS pass
+// Reads and references are sampled as sketch.rs samples them: canonical
+// k-mers hashed under u64::MAX / scale (FracMinHash), so a panel sketch holds about
+// 1/scale of its reference's k-mers and reads are sampled the same way. Each
+// chunk's sampled k-mers are looked up in the panel; a file's contamination
+// fraction for a reference is the share of its sampled k-mers found in that
+// reference's sketch, which doesn't depend on the scale. Reads with at least one
+// hit are counted too, but at large scales short reads often have no sampled
+// k-mer at all, so the k-mer fraction is the estimate to go by
+// Panel entries are `name` (the FASTA <name>.fa/.fasta[.gz] in the panel
+// directory, CONTAMINANT_PANEL_DIR in .env), `name=path`, or a path
+
+use crate::memory_profile::{MemoryBudget, Reservation, Site};
+use crate::pipeline::ChunkProcessor;
+use crate::read_chunk::ReadChunk;
+use crate::sketch::{for_each_sampled, MAX_KMER};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const DEFAULT_KMER: usize = 21;
+pub const DEFAULT_SCALE: u64 = 10;
+pub const DEFAULT_PANEL_DIR: &str = "contaminants";
+// One bit per reference in a sketch entry
+pub const MAX_REFERENCES: usize = 64;
+
+const EXTENSIONS: [&str; 4] = ["fa", "fasta", "fa.gz", "fasta.gz"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelEntry {
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScreenOptions {
+    pub panel: Vec<PanelEntry>,
+    pub k: usize,
+    pub scale: u64,
+}
+
+impl ScreenOptions {
+    // `spec` is the comma-separated panel; bare names are looked up in panel_dir
+    pub fn new(spec: &str, panel_dir: &str, k: usize, scale: u64) -> Result<Self, String> {
+        if k == 0 || k > MAX_KMER {
+            return Err(format!("Invalid --screen-kmer {}: expected 1 to {}", k, MAX_KMER));
+        }
+        if scale == 0 {
+            return Err("Invalid --screen-scale 0: expected at least 1".to_string());
+        }
+        let mut panel = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let entry = match entry.split_once('=') {
+                Some((name, path)) => PanelEntry { name: name.to_string(), path: path.to_string() },
+                None if !crate::paths::is_bare_name(entry) || EXTENSIONS.iter().any(|ext| entry.ends_with(&format!(".{}", ext))) => {
+                    let file = crate::paths::file_name(entry);
+                    PanelEntry { name: file.split('.').next().unwrap_or(file).to_string(), path: entry.to_string() }
+                },
+                None => {
+                    let path = EXTENSIONS.iter()
+                        .map(|ext| crate::paths::join(panel_dir, &format!("{}.{}", entry, ext)))
+                        .find(|path| std::path::Path::new(path).exists())
+                        .ok_or_else(|| format!("No reference for '{}' in the panel directory {} (expected {}.fa, .fasta, .fa.gz or .fasta.gz)", entry, panel_dir, entry))?;
+                    PanelEntry { name: entry.to_string(), path }
+                },
+            };
+            if panel.iter().any(|e: &PanelEntry| e.name == entry.name) {
+                return Err(format!("'{}' appears twice in --screen", entry.name));
+            }
+            panel.push(entry);
+        }
+        if panel.is_empty() || panel.len() > MAX_REFERENCES {
+            return Err(format!("--screen takes 1 to {} references, got {}", MAX_REFERENCES, panel.len()));
+        }
+        Ok(Self { panel, k, scale })
+    }
+}
+
+pub struct Panel {
+    pub names: Vec<String>,
+    k: usize,
+    max_hash: u64,
+    // Sampled hash -> bit per reference containing it
+    sketch: HashMap<u64, u64>,
+    // The sketch's share of the --memory-profile strict budget
+    budget: Reservation,
+}
+
+impl Panel {
+    pub fn build(options: &ScreenOptions) -> Result<Self, String> {
+        let max_hash = crate::sketch::max_hash(options.scale);
+        let mut sketch: HashMap<u64, u64> = HashMap::new();
+        for (i, entry) in options.panel.iter().enumerate() {
+            for (_, sequence) in crate::simulate::load_reference(&entry.path)? {
+                for_each_sampled(&sequence, options.k, max_hash, |hash| *sketch.entry(hash).or_insert(0) |= 1 << i);
+            }
+        }
+        let names = options.panel.iter().map(|e| e.name.clone()).collect();
+        Ok(Self { names, k: options.k, max_hash, sketch, budget: Reservation::new(Site::KmerTables) })
+    }
+
+    // Hold the sketch against a run's budget; Err if it doesn't fit
+    pub fn hold_against(&mut self, memory: &Arc<MemoryBudget>) -> Result<(), String> {
+        self.budget.hold_against(memory);
+        // Keys, values and a control byte per slot
+        self.budget.resize(self.sketch.capacity() * 17)
+    }
+
+    pub fn sketch_size(&self) -> usize {
+        self.sketch.len()
+    }
+
+    // Adds the chunk's sampled k-mers and panel hits to `stats`
+    pub fn screen_chunk(&self, chunk: &ReadChunk, stats: &mut ScreenStats) {
+        let references = self.names.len();
+        let chunk_stats = (0..chunk.len()).into_par_iter().map(|read| {
+            let mut stats = ScreenStats::new(references);
+            let mut hit = 0u64;
+            for_each_sampled(chunk.read(read), self.k, self.max_hash, |hash| {
+                stats.kmers += 1;
+                if let Some(&bits) = self.sketch.get(&hash) {
+                    hit |= bits;
+                    for (r, kmers) in stats.kmer_hits.iter_mut().enumerate() {
+                        *kmers += (bits >> r) & 1;
+                    }
+                }
+            });
+            stats.reads = 1;
+            for (r, reads) in stats.read_hits.iter_mut().enumerate() {
+                *reads += (hit >> r) & 1;
+            }
+            stats
+        }).reduce(|| ScreenStats::new(references), |mut a, b| {
+            a.add(&b);
+            a
+        });
+        stats.add(&chunk_stats);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScreenStats {
+    pub reads: u64,
+    // Sampled k-mers
+    pub kmers: u64,
+    // Per reference, in panel order
+    pub kmer_hits: Vec<u64>,
+    pub read_hits: Vec<u64>,
+}
+
+impl ScreenStats {
+    pub fn new(references: usize) -> Self {
+        Self { reads: 0, kmers: 0, kmer_hits: vec![0; references], read_hits: vec![0; references] }
+    }
+
+    pub fn add(&mut self, other: &ScreenStats) {
+        self.reads += other.reads;
+        self.kmers += other.kmers;
+        for (a, b) in self.kmer_hits.iter_mut().zip(&other.kmer_hits) {
+            *a += b;
+        }
+        for (a, b) in self.read_hits.iter_mut().zip(&other.read_hits) {
+            *a += b;
+        }
+    }
+
+    // Share of sampled k-mers in each reference's sketch
+    pub fn fractions(&self) -> Vec<f64> {
+        self.kmer_hits.iter().map(|&hits| hits as f64 / self.kmers.max(1) as f64).collect()
+    }
+
+    pub fn summary(&self, panel: &Panel) -> String {
+        let entries: Vec<String> = panel.names.iter().zip(self.fractions()).zip(&self.read_hits)
+            .map(|((name, fraction), reads)| format!("{} {:.3}% of k-mers ({} reads)", name, fraction * 100.0, reads))
+            .collect();
+        format!("{} sampled k-mers: {}", self.kmers, entries.join(", "))
+    }
+}
+
+// The screen as a pipeline processor (`analyze --screen`); after the last file
+// every file's result per reference is written to `output` as TSV
+pub struct ScreenProcessor {
+    panel: Panel,
+    stats: ScreenStats,
+    files: Vec<(String, ScreenStats)>,
+    output: std::path::PathBuf,
+}
+
+impl ScreenProcessor {
+    pub fn new(panel: Panel, output: impl Into<std::path::PathBuf>) -> Self {
+        let stats = ScreenStats::new(panel.names.len());
+        Self { panel, stats, files: Vec::new(), output: output.into() }
+    }
+}
+
+impl ChunkProcessor for ScreenProcessor {
+    fn name(&self) -> &str {
+        "Contamination"
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        self.stats = ScreenStats::new(self.panel.names.len());
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        self.panel.screen_chunk(chunk, &mut self.stats);
+        Ok(())
+    }
+
+    fn finish_file(&mut self, file: &str) -> Result<String, String> {
+        let summary = self.stats.summary(&self.panel);
+        self.files.push((file.to_string(), std::mem::take(&mut self.stats)));
+        Ok(summary)
+    }
+
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        let mut tsv = String::from("#file\treference\tsampled_kmers\tkmer_hits\tfraction\treads_with_hits\n");
+        let mut total = ScreenStats::new(self.panel.names.len());
+        for (file, stats) in &self.files {
+            for (r, name) in self.panel.names.iter().enumerate() {
+                tsv.push_str(&format!("{}\t{}\t{}\t{}\t{:.6}\t{}\n", file, name, stats.kmers, stats.kmer_hits[r], stats.fractions()[r], stats.read_hits[r]));
+            }
+            total.add(stats);
+        }
+        std::fs::write(&self.output, tsv).map_err(|e| format!("Failed to write {}: {}", self.output.display(), e))?;
+        Ok(Some(format!("{} (written to {})", total.summary(&self.panel), self.output.display())))
+    }
+}
@@ -0,0 +1,126 @@
+// Contig-aware chunking of a FASTA target in pair mode (--files)
+// A multi-contig reference isn't one flat string: each contig is cut into
+// windows of at most the chunk size in bases (a chunk size in reads keeps
+// contigs whole), so no launch spans two contigs and every score has contig
+// coordinates. Scores are summed per contig along with the best window, for
+// the per-contig report and --contig-scores
+
+use crate::chunking::ChunkSize;
+use crate::input_format::SeqFormat;
+use crate::read_chunk::ReadNameMode;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+// Contigs shown in the printed report; --contig-scores has all of them
+const REPORTED_CONTIGS: usize = 20;
+
+// [start, end) of one contig, 0-based
+pub struct ContigWindow<'a> {
+    pub contig: &'a str,
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub bases: &'a [u8],
+}
+
+// FASTA targets are chunked by contig; reads (FASTQ) are chunked as before
+pub fn is_contig_target(path: &str) -> Result<bool, String> {
+    Ok(crate::input_format::detect_path(path)?.format == Some(SeqFormat::Fasta))
+}
+
+// Every window of every contig of `path`, in file order
+pub fn for_each_window<F>(path: &str, chunk_size: ChunkSize, mut processor: F) -> Result<(), String>
+where F: FnMut(&ContigWindow<'_>) -> Result<(), String> {
+    let window = match chunk_size {
+        ChunkSize::Bases(bases) => bases.max(1),
+        ChunkSize::Reads(_) => usize::MAX,
+    };
+    let mut index = 0;
+    crate::async_input::process_input_in_chunks(path, chunk_size, ReadNameMode::Keep, |chunk| {
+        for i in 0..chunk.len() {
+            let contig = chunk.name(i).map(|name| name.to_string()).unwrap_or_else(|| format!("contig_{}", index + 1));
+            let bases = chunk.read(i);
+            let mut start = 0;
+            while start < bases.len() {
+                let end = bases.len().min(start.saturating_add(window));
+                processor(&ContigWindow { contig: &contig, index, start, end, bases: &bases[start..end] })?;
+                start = end;
+            }
+            index += 1;
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContigScore {
+    pub contig: String,
+    pub length: usize,
+    // Windows times query chunks
+    pub launches: usize,
+    pub score: i64,
+    // Best single launch: its window and query chunk (1-based)
+    pub best_score: i32,
+    pub best_start: usize,
+    pub best_end: usize,
+    pub best_query_chunk: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ContigTally {
+    contigs: Vec<ContigScore>,
+}
+
+impl ContigTally {
+    pub fn add(&mut self, window: &ContigWindow<'_>, query_chunk: usize, score: i32) {
+        if self.contigs.len() <= window.index {
+            self.contigs.resize_with(window.index + 1, Default::default);
+        }
+        let contig = &mut self.contigs[window.index];
+        if contig.launches == 0 {
+            contig.contig = window.contig.to_string();
+        }
+        if contig.launches == 0 || score > contig.best_score {
+            (contig.best_score, contig.best_start, contig.best_end, contig.best_query_chunk) = (score, window.start, window.end, query_chunk);
+        }
+        contig.length = contig.length.max(window.end);
+        contig.launches += 1;
+        contig.score += score as i64;
+    }
+
+    pub fn into_scores(self) -> Vec<ContigScore> {
+        self.contigs.into_iter().filter(|c| c.launches > 0).collect()
+    }
+}
+
+// The highest-scoring contigs, with the coordinates of their best window
+pub fn print(contigs: &[ContigScore]) {
+    if contigs.is_empty() {
+        return;
+    }
+    let mut ranked: Vec<&ContigScore> = contigs.iter().collect();
+    ranked.sort_by_key(|c| std::cmp::Reverse(c.score));
+    println!("  Per contig ({} contigs{}):", contigs.len(),
+             if contigs.len() > REPORTED_CONTIGS { format!(", top {} by score", REPORTED_CONTIGS) } else { String::new() });
+    for contig in ranked.iter().take(REPORTED_CONTIGS) {
+        println!("    {:<20} {:>12} bp  score {:>10}  best {} at {}-{} (query chunk {})",
+                 contig.contig, contig.length, contig.score, contig.best_score,
+                 contig.best_start + 1, contig.best_end, contig.best_query_chunk);
+    }
+}
+
+// Every contig as TSV, in target order, with 1-based inclusive coordinates
+pub fn write_tsv(contigs: &[ContigScore], path: &str) -> Result<(), String> {
+    let file = std::fs::File::create(path)
+        .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut out = std::io::BufWriter::new(file);
+    let mut write = || -> std::io::Result<()> {
+        writeln!(out, "contig\tlength\tlaunches\tscore\tbest_score\tbest_start\tbest_end\tbest_query_chunk")?;
+        for c in contigs {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                     c.contig, c.length, c.launches, c.score, c.best_score, c.best_start + 1, c.best_end, c.best_query_chunk)?;
+        }
+        out.flush()
+    };
+    write().map_err(|e| format!("Failed to write {}: {}", path, e))
+}
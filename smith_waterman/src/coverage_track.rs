@@ -0,0 +1,138 @@
+// Read depth along a reference, for modes that map reads to one (pair-stats
+// --coverage, --coverage-bigwig)
+// Each aligned read adds one to the depth at its first base and takes it away
+// past its last, kept as a sparse map of depth changes per contig: memory grows
+// with the number of distinct read ends, not with the reference, so a few
+// million reads against a 3 Gb genome cost a few million map entries. Sweeping a
+// contig's changes in order gives its depth as runs of equal depth
+// The tracks are those runs with depth 1 or more, contigs in reference order and
+// uncovered stretches left out, as `bedtools genomecov -bg` writes them: a
+// bedGraph (0-based, end-exclusive) or a BigWig of the same runs (bigwig.rs)
+// The summary reports the covered share, the mean depth over the whole
+// reference and the uniformity: the share of reference bases at 20% of the mean
+// depth or more (Illumina's PCT > 0.2x mean), which drops as coverage piles up
+// in some regions at the expense of others
+
+use crate::bigwig::{self, BigWigRun};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Write};
+
+const UNIFORM_SHARE_OF_MEAN: f64 = 0.2;
+
+#[derive(Debug, Clone)]
+struct ContigDepth {
+    name: String,
+    length: usize,
+    // Depth change at each position where a read starts or ends
+    changes: BTreeMap<u32, i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CoverageTrack {
+    contigs: Vec<ContigDepth>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CoverageSummary {
+    pub reference_bases: u64,
+    pub covered_bases: u64,
+    pub covered_fraction: f64,
+    pub mean_depth: f64,
+    pub max_depth: u32,
+    // Share of reference bases at UNIFORM_SHARE_OF_MEAN of the mean depth or more
+    pub uniformity: f64,
+}
+
+impl CoverageTrack {
+    // An empty track over contigs given as (name, length), in reference order
+    pub fn new<'a>(contigs: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        let contigs = contigs.into_iter()
+            .map(|(name, length)| ContigDepth { name: name.to_string(), length, changes: BTreeMap::new() })
+            .collect();
+        Self { contigs }
+    }
+
+    // One read covering [start, end) of contig `contig`
+    pub fn add(&mut self, contig: usize, start: usize, end: usize) {
+        let Some(depth) = self.contigs.get_mut(contig) else { return };
+        let end = end.min(depth.length);
+        if start >= end {
+            return;
+        }
+        *depth.changes.entry(start as u32).or_insert(0) += 1;
+        *depth.changes.entry(end as u32).or_insert(0) -= 1;
+    }
+
+    // Runs of equal non-zero depth of one contig, as (start, end, depth)
+    fn runs(&self, contig: usize) -> impl Iterator<Item = (u32, u32, u32)> + '_ {
+        // A read ending where another starts nets to no change and no boundary
+        let mut changes = self.contigs[contig].changes.iter().filter(|(_, &change)| change != 0);
+        let (mut depth, mut run_start) = (0i64, 0u32);
+        std::iter::from_fn(move || loop {
+            let (&position, &change) = changes.next()?;
+            let (start, run_depth) = (run_start, depth);
+            depth += i64::from(change);
+            run_start = position;
+            if run_depth > 0 {
+                return Some((start, position, run_depth as u32));
+            }
+        })
+    }
+
+    pub fn summary(&self) -> CoverageSummary {
+        let reference_bases: u64 = self.contigs.iter().map(|contig| contig.length as u64).sum();
+        let (mut covered_bases, mut depth_sum, mut max_depth) = (0u64, 0u64, 0u32);
+        let mut bases_at_depth: BTreeMap<u32, u64> = BTreeMap::new();
+        for contig in 0..self.contigs.len() {
+            for (start, end, depth) in self.runs(contig) {
+                let bases = u64::from(end - start);
+                covered_bases += bases;
+                depth_sum += bases * u64::from(depth);
+                max_depth = max_depth.max(depth);
+                *bases_at_depth.entry(depth).or_insert(0) += bases;
+            }
+        }
+        let share = |count: u64| if reference_bases == 0 { 0.0 } else { count as f64 / reference_bases as f64 };
+        let mean_depth = share(depth_sum);
+        let floor = UNIFORM_SHARE_OF_MEAN * mean_depth;
+        let uniform_bases = bases_at_depth.iter().filter(|(&depth, _)| f64::from(depth) >= floor).map(|(_, &bases)| bases).sum();
+        CoverageSummary {
+            reference_bases,
+            covered_bases,
+            covered_fraction: share(covered_bases),
+            mean_depth,
+            max_depth,
+            uniformity: if covered_bases == 0 { 0.0 } else { share(uniform_bases) },
+        }
+    }
+
+    pub fn write_bedgraph(&self, path: &str, track_name: &str) -> Result<(), String> {
+        let file = std::fs::File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        let write_error = |e: std::io::Error| format!("Failed to write {}: {}", path, e);
+        writeln!(out, "track type=bedGraph name=\"{}\" description=\"read depth\"", track_name).map_err(write_error)?;
+        for (index, contig) in self.contigs.iter().enumerate() {
+            for (start, end, depth) in self.runs(index) {
+                writeln!(out, "{}\t{}\t{}\t{}", contig.name, start, end, depth).map_err(write_error)?;
+            }
+        }
+        out.flush().map_err(write_error)
+    }
+
+    pub fn write_bigwig(&self, path: &str) -> Result<(), String> {
+        let chroms: Vec<(&str, usize)> = self.contigs.iter().map(|contig| (contig.name.as_str(), contig.length)).collect();
+        let runs = (0..self.contigs.len()).flat_map(|index| {
+            self.runs(index).map(move |(start, end, depth)| BigWigRun { chrom: index as u32, start, end, value: depth as f32 })
+        });
+        bigwig::write(path, &chroms, runs)
+    }
+}
+
+impl CoverageSummary {
+    pub fn print(&self) {
+        println!("   Coverage: {:.2}% of {} reference bases covered, mean depth {:.2}, max {}, {:.2}% of bases at {:.0}% of the mean or more",
+                 self.covered_fraction * 100.0, self.reference_bases, self.mean_depth, self.max_depth,
+                 self.uniformity * 100.0, UNIFORM_SHARE_OF_MEAN * 100.0);
+    }
+}
@@ -0,0 +1,404 @@
+// SIMD CPU path mirroring the OpenCL smith_waterman_align kernel
+// Each work item keeps a running local score over positions strided by the
+// work group size, so consecutive positions map directly onto SIMD lanes
+
+use crate::gpu::{GPU_MAX_WORK_GROUPS, GPU_WORK_GROUP_SIZE};
+use crate::n_policy::{has_n, is_n};
+use crate::scoring::Scoring;
+use crate::warnings::{self, Category};
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SimdLevel {
+    Scalar,
+    Sse41,
+    Avx2,
+    Avx512,
+    // aarch64 (Apple Silicon, Graviton)
+    Neon,
+}
+
+impl SimdLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimdLevel::Scalar => "scalar",
+            SimdLevel::Sse41 => "sse4.1",
+            SimdLevel::Avx2 => "avx2",
+            SimdLevel::Avx512 => "avx512",
+            SimdLevel::Neon => "neon",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "scalar" => Some(SimdLevel::Scalar),
+            "sse4.1" | "sse41" | "sse" => Some(SimdLevel::Sse41),
+            "avx2" => Some(SimdLevel::Avx2),
+            "avx512" | "avx-512" => Some(SimdLevel::Avx512),
+            "neon" => Some(SimdLevel::Neon),
+            _ => None,
+        }
+    }
+
+    // Levels this CPU can execute
+    pub fn supported() -> Vec<SimdLevel> {
+        #[allow(unused_mut)]
+        let mut levels = vec![SimdLevel::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse4.1") {
+                levels.push(SimdLevel::Sse41);
+            }
+            if is_x86_feature_detected!("avx2") {
+                levels.push(SimdLevel::Avx2);
+            }
+            if is_x86_feature_detected!("avx512f") {
+                levels.push(SimdLevel::Avx512);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                levels.push(SimdLevel::Neon);
+            }
+        }
+        levels
+    }
+}
+
+// Update `cur`/`best` lanes for one block of positions
+// All four slices have the same length (at most one work group)
+type BlockKernel = fn(&[u8], &[u8], &mut [i32], &mut [i32], Scoring);
+
+// Blocks with an N take this path; the SIMD kernels only compare bytes
+fn block_with_n(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+    let n_score = crate::n_policy::selected().n_score();
+    for i in 0..a.len() {
+        let score = if is_n(a[i]) || is_n(b[i]) {
+            n_score
+        } else if a[i] == b[i] {
+            scoring.match_score
+        } else {
+            scoring.mismatch_penalty
+        };
+        cur[i] = (cur[i] + score).max(0);
+        best[i] = best[i].max(cur[i]);
+    }
+}
+
+fn block_scalar(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+    for i in 0..a.len() {
+        let score = if a[i] == b[i] { scoring.match_score } else { scoring.mismatch_penalty };
+        cur[i] = (cur[i] + score).max(0);
+        best[i] = best[i].max(cur[i]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{block_scalar, Scoring};
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn block_sse41_impl(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        const W: usize = 4;
+        let n = a.len() - a.len() % W;
+        let zero = _mm_setzero_si128();
+        let mismatch = _mm_set1_epi32(scoring.mismatch_penalty);
+        // match - mismatch, added where the bases are equal
+        let bonus = _mm_set1_epi32(scoring.match_score - scoring.mismatch_penalty);
+        let mut i = 0;
+        while i < n {
+            let va = _mm_cvtepu8_epi32(_mm_cvtsi32_si128((a.as_ptr().add(i) as *const i32).read_unaligned()));
+            let vb = _mm_cvtepu8_epi32(_mm_cvtsi32_si128((b.as_ptr().add(i) as *const i32).read_unaligned()));
+            let score = _mm_add_epi32(mismatch, _mm_and_si128(_mm_cmpeq_epi32(va, vb), bonus));
+            let c = _mm_loadu_si128(cur.as_ptr().add(i) as *const __m128i);
+            let c = _mm_max_epi32(_mm_add_epi32(c, score), zero);
+            let m = _mm_max_epi32(_mm_loadu_si128(best.as_ptr().add(i) as *const __m128i), c);
+            _mm_storeu_si128(cur.as_mut_ptr().add(i) as *mut __m128i, c);
+            _mm_storeu_si128(best.as_mut_ptr().add(i) as *mut __m128i, m);
+            i += W;
+        }
+        block_scalar(&a[n..], &b[n..], &mut cur[n..], &mut best[n..], scoring);
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn block_avx2_impl(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        const W: usize = 8;
+        let n = a.len() - a.len() % W;
+        let zero = _mm256_setzero_si256();
+        let mismatch = _mm256_set1_epi32(scoring.mismatch_penalty);
+        let bonus = _mm256_set1_epi32(scoring.match_score - scoring.mismatch_penalty);
+        let mut i = 0;
+        while i < n {
+            let va = _mm256_cvtepu8_epi32(_mm_loadl_epi64(a.as_ptr().add(i) as *const __m128i));
+            let vb = _mm256_cvtepu8_epi32(_mm_loadl_epi64(b.as_ptr().add(i) as *const __m128i));
+            let score = _mm256_add_epi32(mismatch, _mm256_and_si256(_mm256_cmpeq_epi32(va, vb), bonus));
+            let c = _mm256_loadu_si256(cur.as_ptr().add(i) as *const __m256i);
+            let c = _mm256_max_epi32(_mm256_add_epi32(c, score), zero);
+            let m = _mm256_max_epi32(_mm256_loadu_si256(best.as_ptr().add(i) as *const __m256i), c);
+            _mm256_storeu_si256(cur.as_mut_ptr().add(i) as *mut __m256i, c);
+            _mm256_storeu_si256(best.as_mut_ptr().add(i) as *mut __m256i, m);
+            i += W;
+        }
+        block_scalar(&a[n..], &b[n..], &mut cur[n..], &mut best[n..], scoring);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    unsafe fn block_avx512_impl(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        const W: usize = 16;
+        let n = a.len() - a.len() % W;
+        let zero = _mm512_setzero_si512();
+        let mismatch = _mm512_set1_epi32(scoring.mismatch_penalty);
+        let matched = _mm512_set1_epi32(scoring.match_score);
+        let mut i = 0;
+        while i < n {
+            let va = _mm512_cvtepu8_epi32(_mm_loadu_si128(a.as_ptr().add(i) as *const __m128i));
+            let vb = _mm512_cvtepu8_epi32(_mm_loadu_si128(b.as_ptr().add(i) as *const __m128i));
+            let score = _mm512_mask_blend_epi32(_mm512_cmpeq_epi32_mask(va, vb), mismatch, matched);
+            let c = _mm512_loadu_si512(cur.as_ptr().add(i) as *const __m512i);
+            let c = _mm512_max_epi32(_mm512_add_epi32(c, score), zero);
+            let m = _mm512_max_epi32(_mm512_loadu_si512(best.as_ptr().add(i) as *const __m512i), c);
+            _mm512_storeu_si512(cur.as_mut_ptr().add(i) as *mut __m512i, c);
+            _mm512_storeu_si512(best.as_mut_ptr().add(i) as *mut __m512i, m);
+            i += W;
+        }
+        block_scalar(&a[n..], &b[n..], &mut cur[n..], &mut best[n..], scoring);
+    }
+
+    // Safe wrappers: only handed out after runtime feature detection
+    pub fn block_sse41(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        unsafe { block_sse41_impl(a, b, cur, best, scoring) }
+    }
+
+    pub fn block_avx2(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        unsafe { block_avx2_impl(a, b, cur, best, scoring) }
+    }
+
+    pub fn block_avx512(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        unsafe { block_avx512_impl(a, b, cur, best, scoring) }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use super::{block_scalar, Scoring};
+    use std::arch::aarch64::*;
+
+    // 16 bases per compare; the byte mask is widened to four vectors of i32 lanes
+    #[target_feature(enable = "neon")]
+    unsafe fn block_neon_impl(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        const W: usize = 16;
+        let n = a.len() - a.len() % W;
+        let zero = vdupq_n_s32(0);
+        let mismatch = vdupq_n_s32(scoring.mismatch_penalty);
+        let bonus = vdupq_n_s32(scoring.match_score - scoring.mismatch_penalty);
+        let mut i = 0;
+        while i < n {
+            // 0xff where equal, sign-extended to all ones per i32 lane
+            let eq = vreinterpretq_s8_u8(vceqq_u8(vld1q_u8(a.as_ptr().add(i)), vld1q_u8(b.as_ptr().add(i))));
+            let (lo, hi) = (vmovl_s8(vget_low_s8(eq)), vmovl_high_s8(eq));
+            let masks = [vmovl_s16(vget_low_s16(lo)), vmovl_high_s16(lo), vmovl_s16(vget_low_s16(hi)), vmovl_high_s16(hi)];
+            for (lane, mask) in masks.into_iter().enumerate() {
+                let p = i + lane * 4;
+                let score = vaddq_s32(mismatch, vandq_s32(mask, bonus));
+                let c = vmaxq_s32(vaddq_s32(vld1q_s32(cur.as_ptr().add(p)), score), zero);
+                let m = vmaxq_s32(vld1q_s32(best.as_ptr().add(p)), c);
+                vst1q_s32(cur.as_mut_ptr().add(p), c);
+                vst1q_s32(best.as_mut_ptr().add(p), m);
+            }
+            i += W;
+        }
+        block_scalar(&a[n..], &b[n..], &mut cur[n..], &mut best[n..], scoring);
+    }
+
+    // Safe wrapper: only handed out after runtime feature detection
+    pub fn block_neon(a: &[u8], b: &[u8], cur: &mut [i32], best: &mut [i32], scoring: Scoring) {
+        unsafe { block_neon_impl(a, b, cur, best, scoring) }
+    }
+}
+
+fn kernel_for(level: SimdLevel) -> BlockKernel {
+    if !SimdLevel::supported().contains(&level) {
+        return block_scalar;
+    }
+    match level {
+        SimdLevel::Scalar => block_scalar,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse41 => x86::block_sse41,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => x86::block_avx2,
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx512 => x86::block_avx512,
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => arm::block_neon,
+        // Levels of another architecture (supported() never lists them)
+        _ => block_scalar,
+    }
+}
+
+// Score with an explicit launch geometry and SIMD level
+pub fn align_with(seq1: &[u8], seq2: &[u8], work_groups: usize, group_size: usize, level: SimdLevel) -> i32 {
+    align_scored(seq1, seq2, work_groups, group_size, level, crate::scoring::selected())
+}
+
+// align_with under other scores than the process-wide ones
+pub fn align_scored(seq1: &[u8], seq2: &[u8], work_groups: usize, group_size: usize, level: SimdLevel, scoring: Scoring) -> i32 {
+    let len = seq1.len().min(seq2.len());
+    if len == 0 || work_groups == 0 || group_size == 0 {
+        return 0;
+    }
+    let kernel = kernel_for(level);
+    let chunk_size = len.div_ceil(work_groups);
+
+    (0..work_groups).into_par_iter().map(|group| {
+        let start = group * chunk_size;
+        if start >= len {
+            return 0;
+        }
+        let end = (start + chunk_size).min(len);
+        let mut cur = vec![0i32; group_size];
+        let mut best = vec![0i32; group_size];
+        let mut pos = start;
+        while pos < end {
+            let n = group_size.min(end - pos);
+            let (a, b) = (&seq1[pos..pos + n], &seq2[pos..pos + n]);
+            if has_n(a) || has_n(b) {
+                block_with_n(a, b, &mut cur[..n], &mut best[..n], scoring);
+            } else {
+                kernel(a, b, &mut cur[..n], &mut best[..n], scoring);
+            }
+            pos += group_size;
+        }
+        best.into_iter().max().unwrap_or(0)
+    }).max().unwrap_or(0)
+}
+
+// CPU counterpart of gpu_align_bytes, using the same launch geometry
+pub fn cpu_align_bytes(seq1: &[u8], seq2: &[u8]) -> i32 {
+    cpu_align_bytes_scored(seq1, seq2, crate::scoring::selected())
+}
+
+// cpu_align_bytes under other scores, e.g. one set of a --sweep
+pub fn cpu_align_bytes_scored(seq1: &[u8], seq2: &[u8], scoring: Scoring) -> i32 {
+    let (seq1, seq2) = (crate::n_policy::prepare(seq1), crate::n_policy::prepare(seq2));
+    let (seq1, seq2) = (&seq1[..], &seq2[..]);
+    let len = seq1.len().min(seq2.len());
+    let work_groups = len.div_ceil(GPU_WORK_GROUP_SIZE).min(GPU_MAX_WORK_GROUPS);
+    align_scored(seq1, seq2, work_groups, GPU_WORK_GROUP_SIZE, selected_level(), scoring)
+}
+
+pub fn cpu_align(seq1: &str, seq2: &str) -> i32 {
+    cpu_align_bytes(seq1.as_bytes(), seq2.as_bytes())
+}
+
+// Per-host dispatch decision, cached so the micro-benchmark runs once per machine
+#[derive(Debug, Serialize, Deserialize)]
+struct DispatchCache {
+    cpu_model: String,
+    level: SimdLevel,
+    throughput_mb_per_s: BTreeMap<String, f64>,
+}
+
+static SELECTED_LEVEL: OnceCell<SimdLevel> = OnceCell::new();
+
+fn cpu_model() -> String {
+    let mut system = sysinfo::System::new();
+    system.refresh_cpu_list(sysinfo::CpuRefreshKind::nothing());
+    system.cpus().first()
+        .map(|cpu| cpu.brand().trim().to_string())
+        .filter(|brand| !brand.is_empty())
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
+
+fn cache_path() -> Option<std::path::PathBuf> {
+    let base = std::env::var("XDG_CACHE_HOME").ok()
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::var("HOME").ok().map(|home| std::path::Path::new(&home).join(".cache")))
+        .or_else(|| std::env::var("LOCALAPPDATA").ok().map(std::path::PathBuf::from))?;
+    Some(base.join("rustseq_mini").join("cpu_dispatch.json"))
+}
+
+fn load_cached_level(model: &str) -> Option<SimdLevel> {
+    let file = std::fs::File::open(cache_path()?).ok()?;
+    let cache: DispatchCache = serde_json::from_reader(file).ok()?;
+    // Only trust the cache on the machine (and a level) that produced it
+    if cache.cpu_model == model && SimdLevel::supported().contains(&cache.level) {
+        Some(cache.level)
+    } else {
+        None
+    }
+}
+
+fn save_cached_level(cache: &DispatchCache) {
+    let Some(path) = cache_path() else { return };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(cache) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+// Measure every supported level on a synthetic buffer and pick the fastest
+// Narrower vectors can win when wide units throttle the clock
+pub fn benchmark_levels() -> (SimdLevel, BTreeMap<String, f64>) {
+    const BYTES: usize = 4 * 1024 * 1024;
+    let seq1: Vec<u8> = (0..BYTES).map(|i| b"ACGT"[(i * 7 + i / 13) % 4]).collect();
+    let seq2: Vec<u8> = (0..BYTES).map(|i| b"ACGT"[(i * 7 + i / 11) % 4]).collect();
+    let work_groups = BYTES.div_ceil(GPU_WORK_GROUP_SIZE);
+    let expected = align_with(&seq1, &seq2, work_groups, GPU_WORK_GROUP_SIZE, SimdLevel::Scalar);
+
+    let mut results = BTreeMap::new();
+    let mut best = (SimdLevel::Scalar, 0.0);
+    for level in SimdLevel::supported() {
+        // A level that disagrees with scalar is never selected
+        if align_with(&seq1, &seq2, work_groups, GPU_WORK_GROUP_SIZE, level) != expected {
+            warnings::warn(Category::Device, format!("{} CPU path disagrees with scalar, skipping", level.name()));
+            continue;
+        }
+        let start = Instant::now();
+        let mut iterations = 0;
+        while iterations < 3 || start.elapsed().as_millis() < 50 {
+            std::hint::black_box(align_with(&seq1, &seq2, work_groups, GPU_WORK_GROUP_SIZE, level));
+            iterations += 1;
+        }
+        let mb_per_s = (BYTES * iterations) as f64 / (1024.0 * 1024.0) / start.elapsed().as_secs_f64();
+        results.insert(level.name().to_string(), mb_per_s);
+        if mb_per_s > best.1 {
+            best = (level, mb_per_s);
+        }
+    }
+    (best.0, results)
+}
+
+// SIMD level used by the CPU path: CPU_SIMD_LEVEL override, then the per-host cache,
+// then a fresh micro-benchmark
+pub fn selected_level() -> SimdLevel {
+    *SELECTED_LEVEL.get_or_init(|| {
+        if let Ok(value) = std::env::var("CPU_SIMD_LEVEL") {
+            match SimdLevel::parse(&value) {
+                Some(level) if SimdLevel::supported().contains(&level) => return level,
+                _ => warnings::warn(Category::Device, format!("CPU_SIMD_LEVEL={} not available on this CPU, benchmarking instead", value)),
+            }
+        }
+
+        let model = cpu_model();
+        if let Some(level) = load_cached_level(&model) {
+            println!("CPU path: {} (cached for this host)", level.name());
+            return level;
+        }
+
+        let (level, throughput) = benchmark_levels();
+        for (name, mb_per_s) in &throughput {
+            println!("  CPU {}: {:.0} MB/s", name, mb_per_s);
+        }
+        println!("CPU path: {} (fastest on this host)", level.name());
+        save_cached_level(&DispatchCache { cpu_model: model, level, throughput_mb_per_s: throughput });
+        level
+    })
+}
@@ -0,0 +1,131 @@
+// --deterministic: bit-identical scores across reruns and GPUs
+// Each work group scores its own slice of a chunk, so the kernel geometry is part
+// of the result; in this mode it is fixed instead of sized to the device. A chunk
+// that fails to align fails the run instead of being skipped, and the run ends
+// with a digest of the per-file results that two runs can be compared by
+// The mode is the run's: WGS runs take it from WgsConfig::deterministic into
+// their RunContext, where the launches read it; the CLI's other modes set it
+// for the thread they align on
+
+use crate::chunking::ChunkSize;
+use crate::run_context::{with_state, RunContext};
+use crate::wgs_report::FileReport;
+use crate::warnings::{self, Category};
+use std::sync::atomic::Ordering;
+
+// Every OpenCL device supports work groups of at least this size
+pub const DETERMINISTIC_WORK_GROUP_SIZE: usize = 64;
+
+pub const DIGEST_FILE: &str = "result_digest.txt";
+
+// For the run the current thread works for
+pub fn set_enabled(enabled: bool) {
+    with_state(|run| run.deterministic.store(enabled, Ordering::SeqCst));
+}
+
+pub fn is_enabled() -> bool {
+    with_state(|run| run.deterministic.load(Ordering::SeqCst))
+}
+
+// Work-group size for a launch on a device that allows up to `device_max`
+pub fn work_group_size(device_max: usize, preferred: usize) -> usize {
+    if is_enabled() {
+        DETERMINISTIC_WORK_GROUP_SIZE
+    } else {
+        device_max.min(preferred)
+    }
+}
+
+// FNV-1a over the chunk size and each file's score, reads, and bases, in file order
+pub fn results_digest(chunk_size: ChunkSize, results: &[FileReport]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |value: u64| {
+        for byte in value.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    feed(chunk_size.value() as u64);
+    feed(matches!(chunk_size, ChunkSize::Bases(_)) as u64);
+    for result in results {
+        feed(result.score as u32 as u64);
+        feed(result.total_reads as u64);
+        feed(result.total_bases as u64);
+    }
+    format!("{:016x}", hash)
+}
+
+// Print the digest and keep it next to `run`'s logs
+pub fn report(run: &RunContext, chunk_size: ChunkSize, results: &[FileReport]) {
+    let digest = results_digest(chunk_size, results);
+    println!("Result digest: {} (deterministic mode)", digest);
+    let logs_dir = run.run().logs_dir.as_path();
+    let path = logs_dir.join(DIGEST_FILE);
+    let written = std::fs::create_dir_all(logs_dir).and_then(|_| std::fs::write(&path, format!("{}\n", digest)));
+    if let Err(e) = written {
+        warnings::warn(Category::Storage, format!("Failed to write {}: {}", path.display(), e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_context::RunState;
+    use crate::score_metrics::NormalizedScore;
+    use crate::wgs_report::FileStatus;
+    use std::sync::Arc;
+
+    fn file(index: usize, score: i32) -> FileReport {
+        FileReport {
+            index,
+            path: format!("reads_{}.fastq", index),
+            status: FileStatus::Completed,
+            score,
+            total_reads: 24,
+            total_bases: 2400,
+            chunks: 3,
+            skipped_chunks: 0,
+            processing_time_ms: 0.0,
+            gpu_device: "test".to_string(),
+            normalized: NormalizedScore::default(),
+            error: None,
+            excluded_reads: 0,
+            excluded_bases: 0,
+            excluded_fraction: 0.0,
+            is_partial: false,
+            aggregates: Default::default(),
+        }
+    }
+
+    #[test]
+    fn digest_is_stable_for_equal_results() {
+        let results = vec![file(0, 120), file(1, -4)];
+        let mut rerun = results.clone();
+        // Timings and devices differ between runs and aren't part of the result
+        rerun[0].processing_time_ms = 51.0;
+        rerun[1].gpu_device = "other".to_string();
+        assert_eq!(results_digest(ChunkSize::Reads(8), &results), results_digest(ChunkSize::Reads(8), &rerun));
+    }
+
+    #[test]
+    fn digest_changes_with_a_score() {
+        let results = vec![file(0, 120), file(1, -4)];
+        let mut changed = results.clone();
+        changed[1].score = -3;
+        assert_ne!(results_digest(ChunkSize::Reads(8), &results), results_digest(ChunkSize::Reads(8), &changed));
+        assert_ne!(results_digest(ChunkSize::Reads(8), &results), results_digest(ChunkSize::Bases(8), &results));
+    }
+
+    #[test]
+    fn the_mode_is_the_runs() {
+        let (deterministic, other) = (Arc::new(RunState::default()), Arc::new(RunState::default()));
+        {
+            let _entered = deterministic.enter();
+            set_enabled(true);
+            assert_eq!(work_group_size(1024, 256), DETERMINISTIC_WORK_GROUP_SIZE);
+        }
+        let _entered = other.enter();
+        assert!(!is_enabled());
+        assert_eq!(work_group_size(1024, 256), 256);
+    }
+}
@@ -0,0 +1,291 @@
+// Direct I/O reader for local NVMe inputs (Linux only)
+// Bypasses the page cache with O_DIRECT and, with the `io-uring` feature,
+// keeps the next block in flight while the current one is being parsed
+
+use crate::warnings::{self, Category};
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+
+// O_DIRECT requires offsets, lengths, and buffers aligned to the logical block size
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+// Size of each read submitted to the device
+pub const DIRECT_IO_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+// Check if direct I/O should be used (opt-in, like pinned memory)
+pub fn is_enabled() -> bool {
+    std::env::var("USE_DIRECT_IO")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse::<bool>()
+        .unwrap_or(false)
+}
+
+// Heap buffer with the alignment O_DIRECT needs
+struct AlignedBuffer {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// The buffer is uniquely owned, so moving it between threads is fine
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    fn new(len: usize) -> io::Result<Self> {
+        let layout = Layout::from_size_align(len, DIRECT_IO_ALIGNMENT)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(io::Error::new(io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
+        }
+        Ok(Self { ptr, layout })
+    }
+
+    fn as_slice(&self, len: usize) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, len.min(self.layout.size())) }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr
+    }
+
+    fn len(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+// Open with O_DIRECT, falling back to buffered I/O on filesystems that reject it (tmpfs, some FUSE)
+fn open_direct(path: &str) -> io::Result<File> {
+    match OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path) {
+        Ok(file) => Ok(file),
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            warnings::warn(Category::Input, format!("O_DIRECT not supported for {}, using buffered reads", path));
+            File::open(path)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+// Sequential reader over aligned block reads
+pub struct DirectReader {
+    // Declared before the file so in-flight reads finish before the fd closes
+    backend: Backend,
+    pos: usize,
+    _file: File,
+}
+
+enum Backend {
+    #[cfg(feature = "io-uring")]
+    Uring(Box<uring::UringBlocks>),
+    Pread(PreadBlocks),
+}
+
+impl DirectReader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = open_direct(path)?;
+
+        #[cfg(feature = "io-uring")]
+        {
+            match uring::UringBlocks::new(&file) {
+                Ok(blocks) => {
+                    return Ok(Self { backend: Backend::Uring(Box::new(blocks)), pos: 0, _file: file });
+                },
+                Err(e) => {
+                    warnings::warn(Category::Input, format!("io_uring unavailable ({}), using pread", e));
+                },
+            }
+        }
+
+        let blocks = PreadBlocks::new(file.try_clone()?)?;
+        Ok(Self { backend: Backend::Pread(blocks), pos: 0, _file: file })
+    }
+
+    fn current(&self) -> &[u8] {
+        match &self.backend {
+            #[cfg(feature = "io-uring")]
+            Backend::Uring(blocks) => blocks.current(),
+            Backend::Pread(blocks) => blocks.current(),
+        }
+    }
+
+    fn advance(&mut self) -> io::Result<()> {
+        self.pos = 0;
+        match &mut self.backend {
+            #[cfg(feature = "io-uring")]
+            Backend::Uring(blocks) => blocks.advance(),
+            Backend::Pread(blocks) => blocks.advance(),
+        }
+    }
+}
+
+impl Read for DirectReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.current().len() {
+            self.advance()?;
+        }
+        let available = &self.current()[self.pos..];
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Blocking pread fallback (still O_DIRECT, but one block at a time)
+struct PreadBlocks {
+    file: File,
+    buffer: AlignedBuffer,
+    filled: usize,
+    offset: u64,
+    eof: bool,
+}
+
+impl PreadBlocks {
+    fn new(file: File) -> io::Result<Self> {
+        Ok(Self {
+            file,
+            buffer: AlignedBuffer::new(DIRECT_IO_BLOCK_SIZE)?,
+            filled: 0,
+            offset: 0,
+            eof: false,
+        })
+    }
+
+    fn current(&self) -> &[u8] {
+        self.buffer.as_slice(self.filled)
+    }
+
+    fn advance(&mut self) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+
+        self.filled = 0;
+        if self.eof {
+            return Ok(());
+        }
+        let len = self.buffer.len();
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.buffer.as_mut_ptr(), len) };
+        // Short reads only happen at end of file
+        while self.filled < len {
+            let n = self.file.read_at(&mut buf[self.filled..], self.offset + self.filled as u64)?;
+            if n == 0 {
+                self.eof = true;
+                break;
+            }
+            self.filled += n;
+        }
+        self.offset += self.filled as u64;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "io-uring")]
+mod uring {
+    use super::{AlignedBuffer, DIRECT_IO_BLOCK_SIZE};
+    use io_uring::{opcode, types, IoUring};
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    // Two buffers: one being parsed, one being filled by the kernel
+    const SLOTS: usize = 2;
+
+    pub struct UringBlocks {
+        ring: IoUring,
+        fd: i32,
+        buffers: Vec<AlignedBuffer>,
+        filled: [usize; SLOTS],
+        pending: [bool; SLOTS],
+        current: usize,
+        next_offset: u64,
+        started: bool,
+        eof: bool,
+    }
+
+    impl UringBlocks {
+        pub fn new(file: &File) -> io::Result<Self> {
+            let mut blocks = Self {
+                ring: IoUring::new(SLOTS as u32 * 2)?,
+                fd: file.as_raw_fd(),
+                buffers: (0..SLOTS).map(|_| AlignedBuffer::new(DIRECT_IO_BLOCK_SIZE)).collect::<io::Result<_>>()?,
+                filled: [0; SLOTS],
+                pending: [false; SLOTS],
+                current: SLOTS - 1,
+                next_offset: 0,
+                started: false,
+                eof: false,
+            };
+            for slot in 0..SLOTS {
+                blocks.submit(slot)?;
+            }
+            Ok(blocks)
+        }
+
+        fn submit(&mut self, slot: usize) -> io::Result<()> {
+            let len = self.buffers[slot].len();
+            let entry = opcode::Read::new(types::Fd(self.fd), self.buffers[slot].as_mut_ptr(), len as u32)
+                .offset(self.next_offset)
+                .build()
+                .user_data(slot as u64);
+            // The buffer outlives the request: Drop waits for everything in flight
+            unsafe {
+                self.ring.submission().push(&entry)
+                    .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+            }
+            self.ring.submit()?;
+            self.pending[slot] = true;
+            self.next_offset += len as u64;
+            Ok(())
+        }
+
+        fn wait(&mut self, slot: usize) -> io::Result<()> {
+            while self.pending[slot] {
+                self.ring.submit_and_wait(1)?;
+                let completions: Vec<(usize, i32)> = self.ring.completion()
+                    .map(|cqe| (cqe.user_data() as usize, cqe.result()))
+                    .collect();
+                for (done, result) in completions {
+                    self.pending[done] = false;
+                    if result < 0 {
+                        return Err(io::Error::from_raw_os_error(-result));
+                    }
+                    self.filled[done] = result as usize;
+                }
+            }
+            Ok(())
+        }
+
+        pub fn current(&self) -> &[u8] {
+            self.buffers[self.current].as_slice(self.filled[self.current])
+        }
+
+        pub fn advance(&mut self) -> io::Result<()> {
+            let consumed = self.current;
+            self.filled[consumed] = 0;
+            // Hand the consumed buffer back for the block after the one in flight
+            if self.started && !self.eof {
+                self.submit(consumed)?;
+            }
+            self.started = true;
+            self.current = (consumed + 1) % SLOTS;
+            self.wait(self.current)?;
+            if self.filled[self.current] < self.buffers[self.current].len() {
+                self.eof = true;
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for UringBlocks {
+        fn drop(&mut self) {
+            for slot in 0..SLOTS {
+                let _ = self.wait(slot);
+            }
+        }
+    }
+}
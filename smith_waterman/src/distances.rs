@@ -0,0 +1,114 @@
+// distances: a pairwise distance matrix over the sequences of a multi-FASTA,
+// written for tree-building tools (PHYLIP's neighbor, FastME, SplitsTree, PAUP*)
+//   identity  1 - normalized all-diagonals alignment score, as msa's guide
+//             tree uses (msa::distance_matrix), on the GPU or the CPU
+//   ani       1 - ANI from k-mer sketches (ani.rs), for whole genomes
+// PHYLIP is the square matrix in relaxed PHYLIP (names in full, then the row);
+// Nexus is a TAXA block and a DISTANCES block with the full matrix. Names are
+// the first word of each header, quoted in Nexus where they need it
+
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMethod {
+    #[default]
+    Identity,
+    Ani,
+}
+
+impl DistanceMethod {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "identity" => Ok(DistanceMethod::Identity),
+            "ani" => Ok(DistanceMethod::Ani),
+            _ => Err(format!("Invalid --method '{}': expected identity or ani", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DistanceMethod::Identity => "alignment identity",
+            DistanceMethod::Ani => "ANI",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceFormat {
+    #[default]
+    Phylip,
+    Nexus,
+}
+
+impl DistanceFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "phylip" => Ok(DistanceFormat::Phylip),
+            "nexus" => Ok(DistanceFormat::Nexus),
+            _ => Err(format!("Invalid --format '{}': expected phylip or nexus", value)),
+        }
+    }
+
+    pub fn default_output(&self) -> &'static str {
+        match self {
+            DistanceFormat::Phylip => "distances.phy",
+            DistanceFormat::Nexus => "distances.nex",
+        }
+    }
+}
+
+// Distances between every pair of `sequences`; `ani` is used by the ANI method
+pub fn matrix(sequences: &[(String, Vec<u8>)], method: DistanceMethod, ani: &crate::ani::AniOptions, use_gpu: bool) -> Result<Vec<Vec<f64>>, String> {
+    if sequences.len() < 2 {
+        return Err(format!("A distance matrix needs at least 2 sequences, got {}", sequences.len()));
+    }
+    let mut seen = std::collections::HashSet::new();
+    if let Some((name, _)) = sequences.iter().find(|(name, _)| !seen.insert(name.as_str())) {
+        return Err(format!("Sequence name '{}' appears twice; tree tools need unique names", name));
+    }
+    match method {
+        DistanceMethod::Identity => {
+            let bases: Vec<Vec<u8>> = sequences.iter().map(|(_, sequence)| sequence.clone()).collect();
+            crate::msa::distance_matrix(&bases, use_gpu)
+        },
+        DistanceMethod::Ani => Ok(crate::ani::distance_matrix(sequences, ani)),
+    }
+}
+
+// A Nexus word, quoted when it has anything but letters, digits, '_', '.' or '-'
+fn nexus_label(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c)) {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\'', "''"))
+    }
+}
+
+pub fn write(names: &[&str], distances: &[Vec<f64>], format: DistanceFormat, out: &mut dyn Write) -> std::io::Result<()> {
+    let row = |i: usize| distances[i].iter().map(|d| format!("{:.6}", d)).collect::<Vec<_>>().join(" ");
+    match format {
+        DistanceFormat::Phylip => {
+            writeln!(out, "{}", names.len())?;
+            for (i, name) in names.iter().enumerate() {
+                writeln!(out, "{:<10} {}", name, row(i))?;
+            }
+        },
+        DistanceFormat::Nexus => {
+            let labels: Vec<String> = names.iter().map(|name| nexus_label(name)).collect();
+            writeln!(out, "#NEXUS")?;
+            writeln!(out, "BEGIN TAXA;")?;
+            writeln!(out, "  DIMENSIONS NTAX={};", names.len())?;
+            writeln!(out, "  TAXLABELS {};", labels.join(" "))?;
+            writeln!(out, "END;")?;
+            writeln!(out, "BEGIN DISTANCES;")?;
+            writeln!(out, "  FORMAT TRIANGLE=BOTH DIAGONAL LABELS=LEFT;")?;
+            writeln!(out, "  MATRIX")?;
+            for (i, label) in labels.iter().enumerate() {
+                writeln!(out, "    {} {}", label, row(i))?;
+            }
+            writeln!(out, "  ;")?;
+            writeln!(out, "END;")?;
+        },
+    }
+    Ok(())
+}
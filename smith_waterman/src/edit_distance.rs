@@ -0,0 +1,192 @@
+// Batch edit distance between short patterns (primers, barcodes) and texts
+// Bases are encoded as 4-bit masks, A=1 C=2 G=4 T=8, and two bases match when
+// their masks share a bit. Patterns may use IUPAC codes (R = A|G, N = any, ...);
+// text bases other than A/C/G/T/U encode as 0 and match nothing, so an N in a
+// read is always an edit. Each pair costs one unit per substitution, insertion
+// or deletion:
+// - Global: the whole pattern against the whole text
+// - SemiGlobal: the whole pattern against the best substring of the text
+//   (Sellers), for finding a primer anywhere in a read
+// On the GPU (edit_distance_batch) one work item computes one pattern/text
+// pair; the CPU path runs the same recurrence with the same tie-breaks, so
+// both report the same hits. Patterns are at most MAX_PATTERN bases long, the
+// size of the kernel's column
+
+use crate::gpu::{GpuContext, GPU_MAX_WORK_GROUPS, GPU_WORK_GROUP_SIZE};
+use crate::ungapped::SequenceSet;
+use ocl::{Buffer, Kernel, MemFlags};
+use rayon::prelude::*;
+
+pub const MAX_PATTERN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Global,
+    SemiGlobal,
+}
+
+// Mask of a pattern base, IUPAC codes included; anything unknown matches nothing
+pub fn encode_pattern(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' | b'U' => 8,
+        b'R' => 1 | 4,
+        b'Y' => 2 | 8,
+        b'S' => 2 | 4,
+        b'W' => 1 | 8,
+        b'K' => 4 | 8,
+        b'M' => 1 | 2,
+        b'B' => 2 | 4 | 8,
+        b'D' => 1 | 4 | 8,
+        b'H' => 1 | 2 | 8,
+        b'V' => 1 | 2 | 4,
+        b'N' => 15,
+        _ => 0,
+    }
+}
+
+// Mask of a text base: only A/C/G/T/U match anything
+pub fn encode_text(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' | b'U' => 8,
+        _ => 0,
+    }
+}
+
+// Best placement of a pattern in a text; start..end are 0-based, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditHit {
+    pub distance: u32,
+    pub start: u32,
+    pub end: u32,
+}
+
+// Same recurrence as the kernel: each cell carries the text position its
+// alignment started at; the diagonal wins ties, then the text gap, then the
+// pattern gap. In SemiGlobal mode the earliest end with the lowest distance wins
+pub fn pair_distance(pattern: &[u8], text: &[u8], mode: Mode) -> EditHit {
+    let m = pattern.len();
+    let mut distance: Vec<u32> = (0..=m as u32).collect();
+    let mut start = vec![0u32; m + 1];
+    let mut best = EditHit { distance: m as u32, start: 0, end: 0 };
+    for (j, &t) in text.iter().enumerate() {
+        let (mut diagonal, mut diagonal_start) = (distance[0], start[0]);
+        if mode == Mode::SemiGlobal {
+            start[0] = j as u32 + 1;
+        } else {
+            distance[0] += 1;
+        }
+        for i in 1..=m {
+            let substitution = diagonal + u32::from(pattern[i - 1] & t == 0);
+            let (up, up_start) = (distance[i] + 1, start[i]);
+            let (left, left_start) = (distance[i - 1] + 1, start[i - 1]);
+            (diagonal, diagonal_start, distance[i], start[i]) = (distance[i], start[i], substitution, diagonal_start);
+            if up < distance[i] {
+                (distance[i], start[i]) = (up, up_start);
+            }
+            if left < distance[i] {
+                (distance[i], start[i]) = (left, left_start);
+            }
+        }
+        if mode == Mode::SemiGlobal && distance[m] < best.distance {
+            best = EditHit { distance: distance[m], start: start[m], end: j as u32 + 1 };
+        }
+    }
+    if mode == Mode::Global {
+        best = EditHit { distance: distance[m], start: 0, end: text.len() as u32 };
+    }
+    best
+}
+
+// Every pattern against every text, indexed text * patterns + pattern
+pub fn cpu_distances(patterns: &SequenceSet, texts: &SequenceSet, mode: Mode) -> Vec<EditHit> {
+    (0..patterns.len() * texts.len()).into_par_iter().map(|pair| {
+        pair_distance(patterns.get(pair % patterns.len()), texts.get(pair / patterns.len()), mode)
+    }).collect()
+}
+
+// GPU counterpart of cpu_distances, split into launches within the work item limit
+pub fn gpu_distances(patterns: &SequenceSet, texts: &SequenceSet, mode: Mode) -> Result<Vec<EditHit>, String> {
+    let mut hits = Vec::with_capacity(patterns.len() * texts.len());
+    if patterns.is_empty() {
+        return Ok(hits);
+    }
+    let texts_per_launch = (GPU_MAX_WORK_GROUPS * GPU_WORK_GROUP_SIZE / patterns.len()).max(1);
+    let mut first = 0;
+    while first < texts.len() {
+        let last = (first + texts_per_launch).min(texts.len());
+        hits.extend(launch(patterns, &texts.slice(first, last), mode)?);
+        first = last;
+    }
+    Ok(hits)
+}
+
+fn launch(patterns: &SequenceSet, texts: &SequenceSet, mode: Mode) -> Result<Vec<EditHit>, String> {
+    let gpu = GpuContext::shared()?;
+    let (queue, program) = (gpu.queue(), gpu.program()?);
+    // OpenCL rejects empty buffers; a zero byte is never read
+    let upload_u8 = |data: &[u8], what: &str| Buffer::<u8>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(data.len().max(1))
+        .copy_host_slice(if data.is_empty() { &[0u8] } else { data })
+        .build()
+        .map_err(|e| format!("Failed to create {} buffer: {}", what, e));
+    let upload_u32 = |data: &[u32], what: &str| Buffer::<u32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(data.len())
+        .copy_host_slice(data)
+        .build()
+        .map_err(|e| format!("Failed to create {} buffer: {}", what, e));
+    let pattern_buffer = upload_u8(&patterns.residues, "pattern")?;
+    let pattern_offsets = upload_u32(&patterns.offsets, "pattern offset")?;
+    let text_buffer = upload_u8(&texts.residues, "text")?;
+    let text_offsets = upload_u32(&texts.offsets, "text offset")?;
+    // distance, start, end per pair
+    let pairs = patterns.len() * texts.len();
+    let result_buffer = Buffer::<u32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().write_only())
+        .len(pairs * 3)
+        .build()
+        .map_err(|e| format!("Failed to create result buffer: {}", e))?;
+    let kernel = Kernel::builder()
+        .program(program)
+        .name("edit_distance_batch")
+        .queue(queue.clone())
+        .global_work_size([patterns.len(), texts.len()])
+        .arg(&pattern_buffer)
+        .arg(&pattern_offsets)
+        .arg(patterns.len() as u32)
+        .arg(&text_buffer)
+        .arg(&text_offsets)
+        .arg(u32::from(mode == Mode::SemiGlobal))
+        .arg(&result_buffer)
+        .build()
+        .map_err(|e| format!("Failed to create edit distance kernel: {}", e))?;
+    unsafe {
+        kernel.enq().map_err(|e| format!("Failed to execute edit distance kernel: {}", e))?;
+    }
+    let mut results = vec![0u32; pairs * 3];
+    result_buffer.read(&mut results).enq()
+        .map_err(|e| format!("Failed to read edit distance kernel result: {}", e))?;
+    Ok(results.chunks_exact(3).map(|r| EditHit { distance: r[0], start: r[1], end: r[2] }).collect())
+}
+
+// Every pattern against every text, on the GPU or the CPU
+pub fn distances(patterns: &SequenceSet, texts: &SequenceSet, mode: Mode, use_gpu: bool) -> Result<Vec<EditHit>, String> {
+    if let Some(i) = (0..patterns.len()).find(|&i| patterns.get(i).len() > MAX_PATTERN) {
+        return Err(format!("Pattern {} is {} bases long; at most {} are supported", i + 1, patterns.get(i).len(), MAX_PATTERN));
+    }
+    if use_gpu {
+        gpu_distances(patterns, texts, mode)
+    } else {
+        Ok(cpu_distances(patterns, texts, mode))
+    }
+}
@@ -0,0 +1,383 @@
+// Aligner: a configured alignment engine for library callers, built in code
+//   let aligner = Aligner::builder().device(0).backend(Backend::Gpu)
+//       .params(AlignParams::default()).chunking(ChunkSize::Reads(10_000)).build()?;
+// The aligner owns its device (a GpuContext), backend and chunk size, so two
+// aligners in one process can use different GPUs or chunkings, and nothing is
+// read from .env. The kernel variant, scores and N policy are process-wide:
+// the CPU path and the OpenCL program are built with them. build() installs them, and
+// fails if an earlier aligner or the CLI installed different ones. Only
+// align_scored and align_dataset (parameter sweeps) score with other scores:
+// the GPU builds a program for each set of them, kept for the aligner's lifetime
+// On the GPU backend, align_files keeps file2 on the device and streams only
+// file1's chunks (resident_target.rs), unless file2 is too large to keep
+// With a scorer (scorer.rs; CPU backend only) the aligner scores with it in
+// place of the kernels' score: align, and align_files with each chunk's qualities
+// The CLI builds one of these for the pair and --full-wgs modes
+// Backend::Wgpu exists in every build but only opens in builds with the wgpu
+// backend (macOS, or --features wgpu); elsewhere build() says so
+
+use crate::aligner;
+use crate::chunking::{ChunkSize, DEFAULT_CHUNK_BASES};
+use crate::gpu::{GpuAlignmentResult, GpuContext, GpuDevice};
+use crate::in_memory::InMemoryDataset;
+use crate::kernels::{self, KernelVariant};
+use crate::n_policy::{self, NPolicy};
+use crate::resident_target::ResidentTarget;
+use crate::run_context::RunContext;
+use crate::run_control::{CancellationToken, Progress, ProgressStage};
+use crate::scorer::{ScorePair, Scorer};
+use crate::scoring::{self, Scoring};
+use crate::wgs_config::WgsConfig;
+use crate::wgs_report::WgsRunReport;
+use std::sync::{Arc, Mutex};
+#[cfg(wgpu_backend)]
+use crate::wgpu_align::WgpuContext;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    // OpenCL kernels on the aligner's device
+    #[default]
+    Gpu,
+    // SIMD CPU path (cpu_align.rs), no device needed
+    Cpu,
+    // WGSL port of the basic kernel through wgpu (wgpu_align.rs): Metal on
+    // macOS; pairs only, no WGS runs
+    Wgpu,
+    // Top rung of the fallback ladder (backend_ladder.rs). This build has no
+    // CUDA kernels, so building on it fails with what the driver probe found
+    Cuda,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AlignParams {
+    pub kernel: KernelVariant,
+    pub n_policy: NPolicy,
+    pub scoring: Scoring,
+}
+
+#[derive(Default)]
+pub struct AlignerBuilder {
+    device: Option<usize>,
+    context: Option<GpuContext>,
+    backend: Backend,
+    params: AlignParams,
+    chunk_size: Option<ChunkSize>,
+    scorer: Option<Arc<dyn Scorer>>,
+}
+
+impl AlignerBuilder {
+    // GPU by index in get_gpu_devices order (wgpu_align::adapter_names order on
+    // the wgpu backend); without one the shared context is used
+    pub fn device(mut self, index: usize) -> Self {
+        self.device = Some(index);
+        self
+    }
+
+    // An existing context, e.g. one shared with other aligners
+    pub fn context(mut self, gpu: GpuContext) -> Self {
+        self.context = Some(gpu);
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn params(mut self, params: AlignParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    // Chunk size of file pairs and WGS runs (default 1 MB of bases)
+    pub fn chunking(mut self, chunk_size: ChunkSize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    // Score with `scorer` instead of the kernels (CPU backend)
+    pub fn scorer(mut self, scorer: Arc<dyn Scorer>) -> Self {
+        self.scorer = Some(scorer);
+        self
+    }
+
+    pub fn build(self) -> Result<Aligner, String> {
+        if self.device.is_some() && self.context.is_some() {
+            return Err("Aligner takes a device or a context, not both".to_string());
+        }
+        kernels::select(self.params.kernel);
+        if kernels::selected() != self.params.kernel {
+            return Err(format!("The {} kernel is already selected in this process; every aligner must use the same one",
+                               kernels::selected().name()));
+        }
+        n_policy::configure(self.params.n_policy);
+        if n_policy::selected() != self.params.n_policy {
+            return Err(format!("The N policy is already {} in this process; every aligner must use the same one",
+                               n_policy::selected().name()));
+        }
+        scoring::configure(self.params.scoring);
+        if scoring::selected() != self.params.scoring {
+            return Err(format!("The scores are already {} in this process; every aligner must use the same ones",
+                               scoring::selected().name()));
+        }
+        if let Some(scorer) = &self.scorer {
+            if self.backend != Backend::Cpu {
+                return Err(format!("The {} scorer runs on the CPU; build the aligner with Backend::Cpu", scorer.name()));
+            }
+            if scorer.needs_qualities() {
+                crate::read_chunk::keep_qualities(true);
+            }
+        }
+        if self.backend == Backend::Cuda {
+            return Err(crate::backend_ladder::cuda_unavailable());
+        }
+        #[cfg(wgpu_backend)]
+        let wgpu = match self.backend {
+            Backend::Wgpu => Some(self.wgpu_context()?),
+            Backend::Gpu | Backend::Cpu | Backend::Cuda => None,
+        };
+        #[cfg(not(wgpu_backend))]
+        if self.backend == Backend::Wgpu {
+            return Err("This build has no wgpu backend; rebuild with --features wgpu (it is always built on macOS)".to_string());
+        }
+        let gpu = match self.backend {
+            Backend::Cpu | Backend::Wgpu | Backend::Cuda => None,
+            Backend::Gpu => Some(match (self.context, self.device) {
+                (Some(gpu), _) => gpu,
+                (None, Some(index)) => GpuContext::new(index)?,
+                (None, None) => GpuContext::shared()?,
+            }),
+        };
+        Ok(Aligner {
+            backend: self.backend,
+            gpu,
+            #[cfg(wgpu_backend)]
+            wgpu,
+            params: self.params,
+            chunk_size: self.chunk_size.unwrap_or(ChunkSize::Bases(DEFAULT_CHUNK_BASES)),
+            programs: Arc::default(),
+            scorer: self.scorer,
+        })
+    }
+
+    #[cfg(wgpu_backend)]
+    fn wgpu_context(&self) -> Result<WgpuContext, String> {
+        if self.context.is_some() {
+            return Err("An OpenCL context can't be used with the wgpu backend".to_string());
+        }
+        if self.params.kernel != KernelVariant::Basic {
+            return Err(format!("The wgpu backend only has the basic kernel, not {}", self.params.kernel.name()));
+        }
+        WgpuContext::new(self.device.unwrap_or(0))
+    }
+}
+
+#[derive(Clone)]
+pub struct Aligner {
+    backend: Backend,
+    gpu: Option<GpuContext>,
+    #[cfg(wgpu_backend)]
+    wgpu: Option<WgpuContext>,
+    params: AlignParams,
+    chunk_size: ChunkSize,
+    // OpenCL programs built for align_scored, by their scores
+    programs: Arc<Mutex<Vec<(Scoring, ocl::Program)>>>,
+    scorer: Option<Arc<dyn Scorer>>,
+}
+
+impl Aligner {
+    pub fn builder() -> AlignerBuilder {
+        AlignerBuilder::default()
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn params(&self) -> AlignParams {
+        self.params
+    }
+
+    pub fn chunk_size(&self) -> ChunkSize {
+        self.chunk_size
+    }
+
+    // None on the CPU and wgpu backends
+    pub fn device(&self) -> Option<&GpuDevice> {
+        self.gpu.as_ref().map(GpuContext::info)
+    }
+
+    pub fn scorer(&self) -> Option<&Arc<dyn Scorer>> {
+        self.scorer.as_ref()
+    }
+
+    // What the scores were computed on, as results report it
+    pub fn device_name(&self) -> String {
+        if let Some(scorer) = &self.scorer {
+            return format!("CPU ({} scorer)", scorer.name());
+        }
+        #[cfg(wgpu_backend)]
+        if let Some(wgpu) = &self.wgpu {
+            return wgpu.describe();
+        }
+        match &self.gpu {
+            Some(gpu) => gpu.info().name.clone(),
+            None => format!("CPU ({})", crate::cpu_align::selected_level().name()),
+        }
+    }
+
+    // Score of two sequences
+    pub fn align(&self, seq1: &[u8], seq2: &[u8]) -> Result<i32, String> {
+        if let Some(scorer) = &self.scorer {
+            return scorer.score(&ScorePair::new(seq1, seq2));
+        }
+        #[cfg(wgpu_backend)]
+        if let Some(wgpu) = &self.wgpu {
+            return wgpu.align(seq1, seq2);
+        }
+        match &self.gpu {
+            Some(_) if seq1.is_empty() || seq2.is_empty() => Ok(0),
+            Some(gpu) => aligner::enqueue_alignment(gpu.queue(), gpu.program()?, seq1, seq2, gpu.info(), self.params.kernel)?.wait(),
+            None => Ok(crate::cpu_align::cpu_align_bytes(seq1, seq2)),
+        }
+    }
+
+    // Score of two sequences under `scoring` instead of the aligner's scores
+    pub fn align_scored(&self, seq1: &[u8], seq2: &[u8], scoring: Scoring) -> Result<i32, String> {
+        if scoring == self.params.scoring {
+            return self.align(seq1, seq2);
+        }
+        if let Some(scorer) = &self.scorer {
+            return Err(format!("The {} scorer only scores with the aligner's scores ({}), not {}", scorer.name(), self.params.scoring.name(), scoring.name()));
+        }
+        #[cfg(wgpu_backend)]
+        if self.wgpu.is_some() {
+            return Err(format!("The wgpu backend only scores with the aligner's scores ({}), not {}", self.params.scoring.name(), scoring.name()));
+        }
+        match &self.gpu {
+            Some(_) if seq1.is_empty() || seq2.is_empty() => Ok(0),
+            Some(gpu) => aligner::enqueue_alignment(gpu.queue(), &self.program_for(gpu, scoring)?, seq1, seq2, gpu.info(), self.params.kernel)?.wait(),
+            None => Ok(crate::cpu_align::cpu_align_bytes_scored(seq1, seq2, scoring)),
+        }
+    }
+
+    fn program_for(&self, gpu: &GpuContext, scoring: Scoring) -> Result<ocl::Program, String> {
+        let mut programs = self.programs.lock().map_err(|e| format!("Failed to acquire program lock: {}", e))?;
+        if let Some((_, program)) = programs.iter().find(|(scores, _)| *scores == scoring) {
+            return Ok(program.clone());
+        }
+        let program = aligner::build_program_scored(gpu.context(), scoring)?;
+        programs.push((scoring, program.clone()));
+        Ok(program)
+    }
+
+    // A pair loaded with InMemoryDataset::load, scored like align_files but
+    // under `scoring`; the dataset is reused for every set of scores of a sweep
+    pub fn align_dataset(&self, dataset: &InMemoryDataset, scoring: Scoring) -> Result<GpuAlignmentResult, String> {
+        let start = std::time::Instant::now();
+        let (score, contigs) = dataset.align(|bytes1, bytes2| self.align_scored(bytes1, bytes2, scoring))?;
+        Ok(GpuAlignmentResult {
+            score,
+            processing_time_ms: start.elapsed().as_millis() as f64,
+            gpu_device: self.device_name(),
+            total_reads: 0,
+            total_bases: 0,
+            contigs,
+        })
+    }
+
+    // Every chunk of one FASTA/FASTQ file against every chunk of the other,
+    // summed; a FASTA file2 is chunked by contig, with per-contig scores in the result
+    pub fn align_files(&self, file1: &str, file2: &str) -> Result<GpuAlignmentResult, String> {
+        self.align_files_with(file1, file2, &CancellationToken::new(), &mut |_| {})
+    }
+
+    // align_files, checking `cancel` before each chunk pair and reporting each
+    // pair to `progress` (bases count both chunks)
+    pub fn align_files_with(&self, file1: &str, file2: &str, cancel: &CancellationToken,
+                            progress: &mut dyn FnMut(Progress)) -> Result<GpuAlignmentResult, String> {
+        if let Some(result) = self.align_files_resident(file1, file2, cancel, progress)? {
+            return Ok(result);
+        }
+        let start = std::time::Instant::now();
+        let (mut pairs, mut bases, mut arrived) = (0, 0, 0i64);
+        let (score, contigs) = aligner::align_pair_chunks_with(file1, file2, self.chunk_size, |pair| {
+            cancel.check()?;
+            let score = match &self.scorer {
+                Some(scorer) => scorer.score(pair)?,
+                None => self.align(pair.seq1, pair.seq2)?,
+            };
+            pairs += 1;
+            bases += pair.seq1.len() + pair.seq2.len();
+            arrived += score as i64;
+            progress(Progress {
+                stage: ProgressStage::Chunk, file: file1.to_string(), file_index: 0, total_files: 1, chunks: pairs,
+                reads: 0, bases, score: arrived, elapsed: start.elapsed(),
+            });
+            Ok(score)
+        })?;
+        Ok(GpuAlignmentResult {
+            score,
+            processing_time_ms: start.elapsed().as_millis() as f64,
+            gpu_device: self.device_name(),
+            total_reads: 0,
+            total_bases: 0,
+            contigs,
+        })
+    }
+
+    // align_files_with against file2 kept on the GPU (resident_target.rs); None
+    // on other backends, with a scorer, or when file2 doesn't fit
+    fn align_files_resident(&self, file1: &str, file2: &str, cancel: &CancellationToken,
+                            progress: &mut dyn FnMut(Progress)) -> Result<Option<GpuAlignmentResult>, String> {
+        let gpu = match &self.gpu {
+            Some(gpu) if self.scorer.is_none() => gpu,
+            _ => return Ok(None),
+        };
+        let start = std::time::Instant::now();
+        let mut target = match ResidentTarget::upload(gpu, file2, self.chunk_size)? {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let (mut pairs, mut bases, mut arrived) = (0, 0, 0i64);
+        let (score, contigs) = target.align_file(gpu, self.params.kernel, file1, self.chunk_size, cancel, |pair_bases, score| {
+            pairs += 1;
+            bases += pair_bases;
+            arrived += score as i64;
+            progress(Progress {
+                stage: ProgressStage::Chunk, file: file1.to_string(), file_index: 0, total_files: 1, chunks: pairs,
+                reads: 0, bases, score: arrived, elapsed: start.elapsed(),
+            });
+        })?;
+        Ok(Some(GpuAlignmentResult {
+            score,
+            processing_time_ms: start.elapsed().as_millis() as f64,
+            gpu_device: self.device_name(),
+            total_reads: 0,
+            total_bases: 0,
+            contigs,
+        }))
+    }
+
+    // A full WGS run (process_full_wgs_dataset) on this aligner's device, with
+    // its chunk size in place of config.chunk_size. Each run claims the next
+    // run number and records into a RunContext of its own
+    pub fn run_wgs(&self, config: &WgsConfig) -> Result<WgsRunReport, String> {
+        self.run_wgs_with(config, &CancellationToken::new(), &mut |_| {})
+    }
+
+    // run_wgs, stopping at the next chunk once `cancel` is cancelled and
+    // reporting each file and chunk to `progress`
+    pub fn run_wgs_with(&self, config: &WgsConfig, cancel: &CancellationToken,
+                        progress: &mut dyn FnMut(Progress)) -> Result<WgsRunReport, String> {
+        self.run_wgs_in(&RunContext::register(), config, cancel, progress)
+    }
+
+    // run_wgs_with, recording into `run`: its number, benchmark and monitors
+    pub fn run_wgs_in(&self, run: &RunContext, config: &WgsConfig, cancel: &CancellationToken,
+                      progress: &mut dyn FnMut(Progress)) -> Result<WgsRunReport, String> {
+        let gpu = self.gpu.as_ref().ok_or("WGS runs need the GPU backend")?;
+        let config = WgsConfig { chunk_size: self.chunk_size, ..config.clone() };
+        aligner::process_full_wgs_dataset_in(run, gpu, &config, cancel, progress)
+    }
+}
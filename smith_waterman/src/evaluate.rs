@@ -0,0 +1,198 @@
+// `evaluate`: accuracy of reported read placements against simulation truth
+// Truth comes from the descriptions `simulate from-reference` writes
+// (`pos=contig:start-end strand=+|- edits=n`); results come from a SAM file,
+// joined by read name. A placement is correct when it's on the true contig and
+// strand and its position is within the tolerance of the true start, which
+// allows for clipping and indels near the read ends. Secondary and
+// supplementary records are ignored.
+//   sensitivity = correct / simulated reads
+//   precision   = correct / placed reads
+// Per-read output from this tool is unplaced SAM (reads are scored per chunk,
+// not placed), so it evaluates as 0% sensitivity until a mode reports positions
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+#[derive(Debug, Clone)]
+struct Truth {
+    contig: String,
+    start: u64,
+    reverse: bool,
+    edits: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct EditBucket {
+    pub reads: usize,
+    pub correct: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct EvaluationReport {
+    pub simulated_reads: usize,
+    pub reported_reads: usize,
+    pub placed: usize,
+    pub correct: usize,
+    pub wrong_contig: usize,
+    pub wrong_strand: usize,
+    pub wrong_position: usize,
+    pub unplaced: usize,
+    // Simulated reads with no primary record in the results
+    pub missing: usize,
+    // Result records whose name isn't in the truth set
+    pub unknown: usize,
+    pub sensitivity: f64,
+    pub precision: f64,
+    // Mean AS:i score of correct and wrong placements, when the results have it
+    pub mean_score_correct: Option<f64>,
+    pub mean_score_wrong: Option<f64>,
+    // Sensitivity by simulated edits: 0, 1, 2, 3+
+    pub by_edits: [EditBucket; 4],
+    pub tolerance: u64,
+}
+
+fn open_text(path: &str) -> Result<Box<dyn BufRead>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    if path.ends_with(".gz") {
+        Ok(Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+// `pos=chr1:1001-1100 strand=- edits=2` after the read name
+fn parse_truth(description: &str) -> Option<Truth> {
+    let mut fields: HashMap<&str, &str> = HashMap::new();
+    for field in description.split_whitespace() {
+        if let Some((key, value)) = field.split_once('=') {
+            fields.insert(key, value);
+        }
+    }
+    // Contig names can contain ':', the range never does
+    let (contig, range) = fields.get("pos")?.rsplit_once(':')?;
+    let (start, _end) = range.split_once('-')?;
+    Some(Truth {
+        contig: contig.to_string(),
+        start: start.parse().ok()?,
+        reverse: *fields.get("strand")? == "-",
+        edits: fields.get("edits").and_then(|e| e.parse().ok()).unwrap_or(0),
+    })
+}
+
+fn load_truth(path: &str) -> Result<HashMap<String, Truth>, String> {
+    let mut truth = HashMap::new();
+    for (i, line) in open_text(path)?.lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        if i % 4 != 0 {
+            continue;
+        }
+        let header = line.strip_prefix('@')
+            .ok_or_else(|| format!("{} line {}: expected a FASTQ header", path, i + 1))?;
+        let (name, description) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+        let parsed = parse_truth(description)
+            .ok_or_else(|| format!("{} line {}: no simulation truth in '{}' (was it written by simulate from-reference?)", path, i + 1, header))?;
+        truth.insert(name.to_string(), parsed);
+    }
+    if truth.is_empty() {
+        return Err(format!("{} has no reads", path));
+    }
+    Ok(truth)
+}
+
+pub fn evaluate(truth_path: &str, results_path: &str, tolerance: u64) -> Result<EvaluationReport, String> {
+    let truth = load_truth(truth_path)?;
+    let mut report = EvaluationReport { simulated_reads: truth.len(), tolerance, ..Default::default() };
+    let mut seen = std::collections::HashSet::new();
+    let (mut correct_scores, mut wrong_scores) = (Vec::new(), Vec::new());
+
+    for (i, line) in open_text(results_path)?.lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", results_path, e))?;
+        if line.starts_with('@') || line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 11 {
+            return Err(format!("{} line {}: not a SAM record", results_path, i + 1));
+        }
+        let flag: u16 = fields[1].parse()
+            .map_err(|_| format!("{} line {}: invalid FLAG '{}'", results_path, i + 1, fields[1]))?;
+        if flag & 0x900 != 0 {
+            continue;
+        }
+        report.reported_reads += 1;
+        let Some(expected) = truth.get(fields[0]) else {
+            report.unknown += 1;
+            continue;
+        };
+        seen.insert(fields[0].to_string());
+        let bucket = expected.edits.min(3);
+        report.by_edits[bucket].reads += 1;
+        if flag & 0x4 != 0 || fields[2] == "*" {
+            report.unplaced += 1;
+            continue;
+        }
+        report.placed += 1;
+        let position: u64 = fields[3].parse().unwrap_or(0);
+        let score = fields[11..].iter().find_map(|tag| tag.strip_prefix("AS:i:")).and_then(|s| s.parse::<i64>().ok());
+        let correct = if fields[2] != expected.contig {
+            report.wrong_contig += 1;
+            false
+        } else if (flag & 0x10 != 0) != expected.reverse {
+            report.wrong_strand += 1;
+            false
+        } else if position.abs_diff(expected.start) > tolerance {
+            report.wrong_position += 1;
+            false
+        } else {
+            true
+        };
+        if correct {
+            report.correct += 1;
+            report.by_edits[bucket].correct += 1;
+        }
+        if let Some(score) = score {
+            if correct { correct_scores.push(score) } else { wrong_scores.push(score) }
+        }
+    }
+
+    // Reads the results never mentioned still count against sensitivity
+    for (name, expected) in &truth {
+        if !seen.contains(name) {
+            report.missing += 1;
+            report.by_edits[expected.edits.min(3)].reads += 1;
+        }
+    }
+    let mean = |scores: &[i64]| (!scores.is_empty()).then(|| scores.iter().sum::<i64>() as f64 / scores.len() as f64);
+    report.mean_score_correct = mean(&correct_scores);
+    report.mean_score_wrong = mean(&wrong_scores);
+    report.sensitivity = report.correct as f64 / report.simulated_reads as f64;
+    report.precision = if report.placed > 0 { report.correct as f64 / report.placed as f64 } else { 0.0 };
+    Ok(report)
+}
+
+impl EvaluationReport {
+    pub fn print(&self) {
+        println!("Accuracy against simulation truth (tolerance {} bp):", self.tolerance);
+        println!("   Simulated reads: {}", self.simulated_reads);
+        println!("   Placed: {} (correct {}, wrong contig {}, wrong strand {}, wrong position {})",
+                 self.placed, self.correct, self.wrong_contig, self.wrong_strand, self.wrong_position);
+        println!("   Unplaced: {}, missing from results: {}, not in truth: {}", self.unplaced, self.missing, self.unknown);
+        println!("   Sensitivity: {:.2}%", self.sensitivity * 100.0);
+        println!("   Precision: {:.2}%", self.precision * 100.0);
+        if let Some(score) = self.mean_score_correct {
+            println!("   Mean AS, correct placements: {:.1}", score);
+        }
+        if let Some(score) = self.mean_score_wrong {
+            println!("   Mean AS, wrong placements: {:.1}", score);
+        }
+        println!("   Sensitivity by simulated edits:");
+        for (edits, bucket) in self.by_edits.iter().enumerate() {
+            if bucket.reads > 0 {
+                println!("      {:>2}{}: {:.2}% of {}", edits, if edits == 3 { "+" } else { " " },
+                         bucket.correct as f64 / bucket.reads as f64 * 100.0, bucket.reads);
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+// Process exit codes, so wrapper pipelines can branch on the kind of failure
+//   0    success
+//   1    unexpected failure
+//   2    configuration error (bad arguments, .env, manifest, output directory)
+//   3    no usable GPU/OpenCL device
+//   4    data error (unreadable or malformed input, alignment failure)
+//   5    partial success (finished, but some chunks, files or samples failed and were skipped)
+//   130  interrupted (Ctrl-C); completed files are checkpointed
+//   143  preempted (cloud preemption notice or SIGTERM); checkpointed and resumable
+
+use crate::run_context::with_state;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitKind {
+    Success,
+    Failure,
+    ConfigError,
+    NoDevice,
+    DataError,
+    PartialSuccess,
+    Interrupted,
+    Preempted,
+}
+
+impl ExitKind {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitKind::Success => 0,
+            ExitKind::Failure => 1,
+            ExitKind::ConfigError => 2,
+            ExitKind::NoDevice => 3,
+            ExitKind::DataError => 4,
+            ExitKind::PartialSuccess => 5,
+            ExitKind::Interrupted => 130,
+            ExitKind::Preempted => 143,
+        }
+    }
+
+    pub fn from_code(code: Option<i32>) -> Self {
+        match code {
+            Some(0) => ExitKind::Success,
+            Some(2) => ExitKind::ConfigError,
+            Some(3) => ExitKind::NoDevice,
+            Some(4) => ExitKind::DataError,
+            Some(5) => ExitKind::PartialSuccess,
+            // No code means the process was killed by a signal
+            Some(130) | None => ExitKind::Interrupted,
+            Some(143) => ExitKind::Preempted,
+            Some(_) => ExitKind::Failure,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitKind::Success => "ok",
+            ExitKind::Failure => "failed",
+            ExitKind::ConfigError => "config_error",
+            ExitKind::NoDevice => "no_device",
+            ExitKind::DataError => "data_error",
+            ExitKind::PartialSuccess => "partial",
+            ExitKind::Interrupted => "interrupted",
+            ExitKind::Preempted => "preempted",
+        }
+    }
+
+    // Finished with usable results (possibly with some chunks skipped)
+    pub fn is_success(self) -> bool {
+        matches!(self, ExitKind::Success | ExitKind::PartialSuccess)
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+// Print the error and exit with the code for its kind
+pub fn fail(kind: ExitKind, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    kind.exit()
+}
+
+// Chunks that failed to align and were left out of the totals, with their
+// reads and bases, and files quarantined after failing repeatedly
+// (quarantine.rs) and left out of the totals; counted per run
+// (run_context::RunState)
+#[derive(Default)]
+pub struct Skipped {
+    chunks: AtomicUsize,
+    reads: AtomicUsize,
+    bases: AtomicUsize,
+    quarantined_files: AtomicUsize,
+}
+
+impl Skipped {
+    // Success, or partial success if any chunk was skipped or file quarantined
+    pub fn completion_kind(&self) -> ExitKind {
+        if self.chunks.load(Ordering::SeqCst) > 0 || self.quarantined_files.load(Ordering::SeqCst) > 0 {
+            ExitKind::PartialSuccess
+        } else {
+            ExitKind::Success
+        }
+    }
+}
+
+pub fn record_skipped_chunk(reads: usize, bases: usize) {
+    with_state(|run| {
+        run.skipped.chunks.fetch_add(1, Ordering::SeqCst);
+        run.skipped.reads.fetch_add(reads, Ordering::SeqCst);
+        run.skipped.bases.fetch_add(bases, Ordering::SeqCst);
+    });
+}
+
+pub fn skipped_chunks() -> usize {
+    with_state(|run| run.skipped.chunks.load(Ordering::SeqCst))
+}
+
+pub fn skipped_reads() -> usize {
+    with_state(|run| run.skipped.reads.load(Ordering::SeqCst))
+}
+
+pub fn skipped_bases() -> usize {
+    with_state(|run| run.skipped.bases.load(Ordering::SeqCst))
+}
+
+pub fn record_quarantined_file() {
+    with_state(|run| run.skipped.quarantined_files.fetch_add(1, Ordering::SeqCst));
+}
+
+pub fn quarantined_files() -> usize {
+    with_state(|run| run.skipped.quarantined_files.load(Ordering::SeqCst))
+}
+
+pub fn completion_kind() -> ExitKind {
+    with_state(|run| run.skipped.completion_kind())
+}
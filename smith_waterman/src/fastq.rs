@@ -0,0 +1,207 @@
+// Public streaming FASTQ API
+// FastqChunks yields owned batches of records (ids, sequences, qualities)
+// so library users can build their own pipelines on the same I/O layer
+
+use crate::read_chunk::{trim_line_end, ReadChunk};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use crate::input_format::{check_sequence_line, detect_path, line_terminator, strip_bom, SeqFormat};
+use std::process::{Child, Stdio};
+
+// One record borrowed from a batch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FastqRecord<'a> {
+    pub id: &'a [u8],
+    pub seq: &'a [u8],
+    pub qual: &'a [u8],
+}
+
+impl FastqRecord<'_> {
+    pub fn id_str(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(self.id)
+    }
+}
+
+// Owned batch of records stored in three arenas (no per-read allocation)
+#[derive(Debug, Default, Clone)]
+pub struct FastqBatch {
+    pub ids: ReadChunk,
+    pub seqs: ReadChunk,
+    pub quals: ReadChunk,
+}
+
+impl FastqBatch {
+    pub fn with_capacity(reads: usize) -> Self {
+        Self {
+            ids: ReadChunk::with_capacity(reads),
+            seqs: ReadChunk::with_capacity(reads),
+            quals: ReadChunk::with_capacity(reads),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.seqs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seqs.is_empty()
+    }
+
+    pub fn record(&self, index: usize) -> FastqRecord<'_> {
+        FastqRecord {
+            id: self.ids.read(index),
+            seq: self.seqs.read(index),
+            qual: self.quals.read(index),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = FastqRecord<'_>> {
+        (0..self.len()).map(move |i| self.record(i))
+    }
+
+    // All sequences back to back, ready for the aligner
+    pub fn bases(&self) -> &[u8] {
+        self.seqs.bases()
+    }
+
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.seqs.clear();
+        self.quals.clear();
+    }
+}
+
+// Iterator over batches of at most `chunk_size_reads` records
+pub struct FastqChunks {
+    reader: Box<dyn BufRead + Send>,
+    decompressor: Option<Child>,
+    chunk_size_reads: usize,
+    lines: [Vec<u8>; 4],
+    records_read: usize,
+    // Line ending byte, from the first buffer of input
+    terminator: Option<u8>,
+    finished: bool,
+}
+
+impl FastqChunks {
+    // Open a FASTQ file; compressed files are streamed through zcat or zstd, or
+    // decoded in-process with DECOMPRESSOR=native
+    pub fn open(filepath: &str, chunk_size_reads: usize) -> Result<Self, String> {
+        let detected = detect_path(filepath)?;
+        if detected.format == Some(SeqFormat::Fasta) {
+            return Err(format!("{} is FASTA; FastqChunks needs FASTQ input", filepath));
+        }
+        match detected.compression.decompress_command() {
+            Some(mut command) => {
+                let mut child = command
+                    .arg(filepath)
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("Failed to spawn {} decompressor for {}: {}", detected.compression.name(), filepath, e))?;
+                crate::affinity::pin_decompressor(Some(child.id()));
+                let stdout = child.stdout.take()
+                    .ok_or_else(|| format!("Failed to get stdout from decompressor for {}", filepath))?;
+                let mut chunks = Self::from_reader(BufReader::new(stdout), chunk_size_reads);
+                chunks.decompressor = Some(child);
+                Ok(chunks)
+            },
+            None => {
+                let file = File::open(filepath)
+                    .map_err(|e| format!("Failed to open file {}: {}", filepath, e))?;
+                Ok(Self::from_reader(BufReader::new(detected.compression.native_decoder(file)?), chunk_size_reads))
+            },
+        }
+    }
+
+    pub fn from_reader<R: BufRead + Send + 'static>(reader: R, chunk_size_reads: usize) -> Self {
+        Self {
+            reader: Box::new(reader),
+            decompressor: None,
+            chunk_size_reads: chunk_size_reads.max(1),
+            lines: Default::default(),
+            records_read: 0,
+            terminator: None,
+            finished: false,
+        }
+    }
+
+    // Records yielded so far
+    pub fn records_read(&self) -> usize {
+        self.records_read
+    }
+
+    // Read the next four lines into the line buffers; Ok(false) at clean EOF
+    fn read_record(&mut self) -> Result<bool, String> {
+        let terminator = match self.terminator {
+            Some(terminator) => terminator,
+            None => {
+                let terminator = self.reader.fill_buf().map(line_terminator)
+                    .map_err(|e| format!("Failed to read record 1: {}", e))?;
+                *self.terminator.insert(terminator)
+            },
+        };
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            line.clear();
+            let n = self.reader.read_until(terminator, line)
+                .map_err(|e| format!("Failed to read record {}: {}", self.records_read + 1, e))?;
+            if n == 0 {
+                if i == 0 {
+                    return Ok(false);
+                }
+                return Err(format!("Truncated FASTQ record {} at end of input", self.records_read + 1));
+            }
+        }
+        if self.records_read == 0 {
+            let bom = self.lines[0].len() - strip_bom(&self.lines[0])?.len();
+            self.lines[0].drain(..bom);
+        }
+        if !self.lines[0].starts_with(b"@") || !self.lines[2].starts_with(b"+") {
+            return Err(format!("Malformed FASTQ record {}: expected '@' header and '+' separator", self.records_read + 1));
+        }
+        Ok(true)
+    }
+
+    // Fill `batch` in place, reusing its allocations; returns false when input is exhausted
+    pub fn next_into(&mut self, batch: &mut FastqBatch) -> Result<bool, String> {
+        batch.clear();
+        while !self.finished && batch.len() < self.chunk_size_reads {
+            if !self.read_record()? {
+                self.finished = true;
+                break;
+            }
+            check_sequence_line(trim_line_end(&self.lines[1]), self.records_read * 4 + 2)
+                .map_err(|e| format!("Record {}: {}", self.records_read + 1, e))?;
+            let header = trim_line_end(&self.lines[0]);
+            batch.ids.push_read(&header[1..]);
+            batch.seqs.push_read(&self.lines[1]);
+            batch.quals.push_read(&self.lines[3]);
+            self.records_read += 1;
+        }
+        Ok(!batch.is_empty())
+    }
+}
+
+impl Iterator for FastqChunks {
+    type Item = Result<FastqBatch, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = FastqBatch::with_capacity(self.chunk_size_reads);
+        match self.next_into(&mut batch) {
+            Ok(true) => Some(Ok(batch)),
+            Ok(false) => None,
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+impl Drop for FastqChunks {
+    fn drop(&mut self) {
+        if let Some(child) = self.decompressor.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
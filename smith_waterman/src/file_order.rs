@@ -0,0 +1,55 @@
+// --order: the order the selected files of a WGS run are processed in
+//   as-listed       discovery order (the lane layout or WGS_INPUT_FILES)
+//   largest-first   biggest input on disk first, so the long pole starts early
+//   smallest-first  smallest first, for quick results from the short files
+// Sizes are the files' sizes on disk (compressed, for compressed inputs).
+// Remote inputs have no size until they are fetched; they keep their listed
+// order after the local ones. The order is applied after --file-range,
+// --only-files and --num-files, which number files in discovery order
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileOrder {
+    #[default]
+    AsListed,
+    LargestFirst,
+    SmallestFirst,
+}
+
+impl FileOrder {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "as-listed" => Ok(FileOrder::AsListed),
+            "largest-first" => Ok(FileOrder::LargestFirst),
+            "smallest-first" => Ok(FileOrder::SmallestFirst),
+            _ => Err(format!("Invalid --order '{}': expected as-listed, largest-first or smallest-first", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileOrder::AsListed => "as-listed",
+            FileOrder::LargestFirst => "largest-first",
+            FileOrder::SmallestFirst => "smallest-first",
+        }
+    }
+
+    // `files` in this order; ties keep their listed order
+    pub fn apply(&self, files: Vec<String>) -> Vec<String> {
+        if *self == FileOrder::AsListed {
+            return files;
+        }
+        let mut sized: Vec<(Option<u64>, String)> = files.into_iter()
+            .map(|file| (std::fs::metadata(&file).ok().map(|m| m.len()), file))
+            .collect();
+        match self {
+            FileOrder::LargestFirst => sized.sort_by_key(|(size, _)| (size.is_none(), std::cmp::Reverse(size.unwrap_or(0)))),
+            _ => sized.sort_by_key(|(size, _)| (size.is_none(), size.unwrap_or(0))),
+        }
+        println!("File order ({}):", self.name());
+        for (i, (size, file)) in sized.iter().enumerate() {
+            let size = size.map_or("remote".to_string(), |bytes| format!("{:.2} GB", bytes as f64 / 1e9));
+            println!("  {:>3}  {} ({})", i + 1, crate::paths::file_name(file), size);
+        }
+        sized.into_iter().map(|(_, file)| file).collect()
+    }
+}
@@ -0,0 +1,89 @@
+// Subsets of a WGS dataset, for debugging one lane or a few files
+// --only-files keeps files whose name contains any of the given patterns,
+// --file-range keeps files by their 1-based position in the discovered list
+// (both ends inclusive, matching the "file i/N" progress output), and
+// --num-files then keeps the first N of what is left
+
+#[derive(Debug, Clone, Default)]
+pub struct FileSelection {
+    pub only: Vec<String>,
+    // 1-based, inclusive
+    pub range: Option<(usize, usize)>,
+    pub num_files: Option<usize>,
+}
+
+impl FileSelection {
+    pub fn new(num_files: Option<usize>, range: Option<&str>, only: &[String]) -> Result<Self, String> {
+        if num_files == Some(0) {
+            return Err("--num-files must be at least 1".to_string());
+        }
+        Ok(Self {
+            only: only.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect(),
+            range: range.map(parse_range).transpose()?,
+            num_files,
+        })
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.only.is_empty() && self.range.is_none() && self.num_files.is_none()
+    }
+
+    // Apply to the discovered files, keeping their order
+    pub fn apply(&self, files: Vec<String>) -> Result<Vec<String>, String> {
+        let discovered = files.len();
+        let mut selected: Vec<(usize, String)> = files.into_iter().enumerate().collect();
+
+        if !self.only.is_empty() {
+            for pattern in &self.only {
+                if !selected.iter().any(|(_, f)| file_name(f).contains(pattern.as_str())) {
+                    return Err(format!("--only-files: no input file matches '{}'", pattern));
+                }
+            }
+            selected.retain(|(_, f)| self.only.iter().any(|p| file_name(f).contains(p.as_str())));
+        }
+        if let Some((first, last)) = self.range {
+            if first > discovered {
+                return Err(format!("--file-range {}..{} starts past the last of {} input files", first, last, discovered));
+            }
+            selected.retain(|(i, _)| (first..=last).contains(&(i + 1)));
+        }
+        if let Some(n) = self.num_files {
+            selected.truncate(n);
+        }
+        if selected.is_empty() {
+            return Err("File selection matches no input files".to_string());
+        }
+
+        if !self.is_all() {
+            println!("Selected {} of {} input files:", selected.len(), discovered);
+            for (i, file) in &selected {
+                println!("  {:>3}  {}", i + 1, file_name(file));
+            }
+        }
+        Ok(selected.into_iter().map(|(_, f)| f).collect())
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    crate::paths::file_name(path)
+}
+
+// "3..8" or "3..=8" (both inclusive), "3.." (to the end), "..8", or a single "5"
+fn parse_range(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("Invalid --file-range '{}': expected e.g. 3..8, 3.., ..8, or 5", value);
+    let bound = |s: &str, default: usize| -> Result<usize, String> {
+        if s.is_empty() { Ok(default) } else { s.parse::<usize>().map_err(|_| invalid()) }
+    };
+    let value = value.trim();
+    let (first, last) = match value.split_once("..") {
+        Some((first, last)) => (bound(first, 1)?, bound(last.trim_start_matches('='), usize::MAX)?),
+        None => {
+            let n = bound(value, 0)?;
+            (n, n)
+        },
+    };
+    if first == 0 || last < first {
+        return Err(format!("Invalid --file-range '{}': files are numbered from 1 and the range can't be empty", value));
+    }
+    Ok((first, last))
+}
@@ -0,0 +1,163 @@
+// Per-file timing breakdown, saved as `files` in the benchmark results
+// Files are processed one at a time, so readers and GPU launches add to the
+// current file's counters without being told which file it is. Reader time is
+// split into waiting for input bytes (disk or network reads and decompression),
+// waiting for the GPU side to take a chunk, and the rest, which is parsing.
+// BAM/CRAM decoding interleaves decompression and record decoding, so all of
+// it counts as decompression
+// The counters and finished files are the run's (run_context::RunState)
+
+use crate::run_context::with_state;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileTiming {
+    pub index: usize,
+    pub file: String,
+    pub completed: bool,
+    pub reads: usize,
+    pub bases: usize,
+    pub wall_ms: f64,
+    pub decompress_ms: f64,
+    pub parse_ms: f64,
+    pub transfer_ms: f64,
+    pub kernel_ms: f64,
+    // Busy, starved and blocked time of each pipeline stage (stages.rs)
+    #[serde(default)]
+    pub stages: Vec<crate::stages::StageStats>,
+}
+
+impl FileTiming {
+    pub fn bases_per_second(&self) -> f64 {
+        if self.wall_ms > 0.0 { self.bases as f64 / (self.wall_ms / 1000.0) } else { 0.0 }
+    }
+}
+
+// One run's counters for its current file, and its finished files
+#[derive(Default)]
+pub struct FileTimings {
+    decompress_ns: AtomicU64,
+    parse_ns: AtomicU64,
+    transfer_ns: AtomicU64,
+    kernel_ns: AtomicU64,
+    files: Mutex<Vec<FileTiming>>,
+}
+
+pub fn start_file() {
+    with_state(|run| {
+        let timings = &run.files;
+        for counter in [&timings.decompress_ns, &timings.parse_ns, &timings.transfer_ns, &timings.kernel_ns] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    });
+    crate::stages::start_file();
+}
+
+// Called for every GPU launch
+pub fn add_gpu(transfer_ns: u64, kernel_ns: u64) {
+    with_state(|run| {
+        run.files.transfer_ns.fetch_add(transfer_ns, Ordering::Relaxed);
+        run.files.kernel_ns.fetch_add(kernel_ns, Ordering::Relaxed);
+    });
+}
+
+pub fn finish_file(index: usize, file: &str, completed: bool, reads: usize, bases: usize, wall: Duration) {
+    let ms = |counter: &AtomicU64| counter.load(Ordering::Relaxed) as f64 / 1e6;
+    with_state(|run| {
+        let timings = &run.files;
+        let timing = FileTiming {
+            index,
+            file: file.to_string(),
+            completed,
+            reads,
+            bases,
+            wall_ms: wall.as_secs_f64() * 1000.0,
+            decompress_ms: ms(&timings.decompress_ns),
+            parse_ms: ms(&timings.parse_ns),
+            transfer_ms: ms(&timings.transfer_ns),
+            kernel_ms: ms(&timings.kernel_ns),
+            stages: crate::stages::snapshot(),
+        };
+        if let Ok(mut files) = timings.files.lock() {
+            files.push(timing);
+        }
+    });
+}
+
+pub fn files() -> Vec<FileTiming> {
+    with_state(|run| run.files.files.lock().map(|f| f.clone()).unwrap_or_default())
+}
+
+// Table printed with the benchmark results; the slowest file by bases/s is marked
+pub fn print_files(files: &[FileTiming]) {
+    if files.is_empty() {
+        return;
+    }
+    let slowest = files.iter()
+        .filter(|f| f.completed && f.bases > 0)
+        .min_by(|a, b| a.bases_per_second().total_cmp(&b.bases_per_second()))
+        .map(|f| f.index);
+    println!("Per-file timing (s):");
+    println!("   {:>4}  {:>8}  {:>10}  {:>8}  {:>8}  {:>8}  {:>10}  file", "#", "wall", "decompress", "parse", "transfer", "kernel", "Mbases/s");
+    for f in files {
+        println!("   {:>4}  {:>8.1}  {:>10.1}  {:>8.1}  {:>8.1}  {:>8.1}  {:>10.1}  {}{}",
+                 f.index + 1, f.wall_ms / 1000.0, f.decompress_ms / 1000.0, f.parse_ms / 1000.0,
+                 f.transfer_ms / 1000.0, f.kernel_ms / 1000.0, f.bases_per_second() / 1e6,
+                 crate::paths::file_name(&f.file),
+                 if slowest == Some(f.index) { "  <- slowest" } else if !f.completed { "  (failed)" } else { "" });
+    }
+}
+
+// Splits one reader's time for the current file; recorded when dropped, so
+// early returns are counted too
+pub struct ReaderClock {
+    started: Instant,
+    input: Duration,
+    blocked: Duration,
+    // Remaining time is decoding rather than parsing (BAM/CRAM)
+    decoding: bool,
+}
+
+impl ReaderClock {
+    pub fn new() -> Self {
+        Self { started: Instant::now(), input: Duration::ZERO, blocked: Duration::ZERO, decoding: false }
+    }
+
+    pub fn decoding() -> Self {
+        Self { decoding: true, ..Self::new() }
+    }
+
+    // Time spent waiting for input bytes
+    pub fn add_input(&mut self, waited: Duration) {
+        self.input += waited;
+    }
+
+    // Time spent handing chunks downstream (channel backpressure or the GPU callback)
+    pub fn add_blocked(&mut self, waited: Duration) {
+        self.blocked += waited;
+    }
+}
+
+impl Default for ReaderClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ReaderClock {
+    fn drop(&mut self) {
+        let rest = self.started.elapsed().saturating_sub(self.input + self.blocked);
+        let (input, rest) = (self.input.as_nanos() as u64, rest.as_nanos() as u64);
+        with_state(|run| {
+            if self.decoding {
+                run.files.decompress_ns.fetch_add(input + rest, Ordering::Relaxed);
+            } else {
+                run.files.decompress_ns.fetch_add(input, Ordering::Relaxed);
+                run.files.parse_ns.fetch_add(rest, Ordering::Relaxed);
+            }
+        });
+    }
+}
@@ -0,0 +1,195 @@
+// QC by flow cell lane and tile, from Illumina read names
+// Names in the current form (instrument:run:flowcell:lane:tile:x:y, with any
+// fields after y such as a UMI) give all five; the older form
+// (instrument:lane:tile:x:y) has no run or flow cell, which are then left
+// empty. Reads are grouped by instrument, run, flow cell and lane, and within a
+// lane by tile, and each group gets its reads, N share and, from quality lines,
+// mean quality and Q30 share. A lane or tile going bad shows up there when the
+// whole-file figures average it away
+// A tile with at least MIN_TILE_READS reads whose mean quality is
+// LOW_TILE_DROP or more below its lane's is listed as a low-quality tile
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MIN_TILE_READS: u64 = 100;
+const LOW_TILE_DROP: f64 = 2.0;
+
+// Where a read was sequenced, borrowed from its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOrigin<'a> {
+    pub instrument: &'a str,
+    pub run: &'a str,
+    pub flowcell: &'a str,
+    pub lane: u32,
+    pub tile: u32,
+}
+
+pub fn parse_origin(name: &[u8]) -> Option<ReadOrigin<'_>> {
+    let name = std::str::from_utf8(name).ok()?;
+    let fields: Vec<&str> = name.split(':').collect();
+    let (instrument, run, flowcell, lane, tile) = match fields.len() {
+        5 => (fields[0], "", "", fields[1], fields[2]),
+        n if n >= 7 => (fields[0], fields[1], fields[2], fields[3], fields[4]),
+        _ => return None,
+    };
+    Some(ReadOrigin { instrument, run, flowcell, lane: lane.parse().ok()?, tile: tile.parse().ok()? })
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    reads: u64,
+    bases: u64,
+    n_bases: u64,
+    quality_bases: u64,
+    quality_sum: u64,
+    q30_bases: u64,
+}
+
+impl Tally {
+    fn add(&mut self, read: &Tally) {
+        self.reads += read.reads;
+        self.bases += read.bases;
+        self.n_bases += read.n_bases;
+        self.quality_bases += read.quality_bases;
+        self.quality_sum += read.quality_sum;
+        self.q30_bases += read.q30_bases;
+    }
+
+    fn share(count: u64, of: u64) -> f64 {
+        if of == 0 { 0.0 } else { count as f64 / of as f64 }
+    }
+
+    fn mean_quality(&self) -> f64 {
+        Self::share(self.quality_sum, self.quality_bases)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TileQc {
+    pub tile: u32,
+    pub reads: u64,
+    pub n_fraction: f64,
+    // 0 without quality lines
+    pub mean_quality: f64,
+    pub q30_fraction: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaneQc {
+    pub instrument: String,
+    // Empty for names in the older form
+    pub run: String,
+    pub flowcell: String,
+    pub lane: u32,
+    pub reads: u64,
+    pub bases: u64,
+    pub n_fraction: f64,
+    pub mean_quality: f64,
+    pub q30_fraction: f64,
+    // Tiles LOW_TILE_DROP or more below the lane's mean quality
+    pub low_quality_tiles: Vec<u32>,
+    pub tiles: Vec<TileQc>,
+}
+
+impl LaneQc {
+    // flowcell:lane, or instrument:lane for names without a flow cell
+    pub fn label(&self) -> String {
+        let unit = if self.flowcell.is_empty() { &self.instrument } else { &self.flowcell };
+        format!("{}:{}", unit, self.lane)
+    }
+}
+
+struct LaneTally {
+    instrument: String,
+    run: String,
+    flowcell: String,
+    lane: u32,
+    total: Tally,
+    tiles: BTreeMap<u32, Tally>,
+}
+
+impl LaneTally {
+    fn is(&self, origin: &ReadOrigin) -> bool {
+        self.lane == origin.lane && self.flowcell == origin.flowcell && self.run == origin.run && self.instrument == origin.instrument
+    }
+}
+
+// Lane and tile statistics of one file, fed read by read
+#[derive(Default)]
+pub struct FlowCellStats {
+    lanes: Vec<LaneTally>,
+    // Lane of the previous read; runs of reads share a lane
+    last: usize,
+}
+
+impl FlowCellStats {
+    pub fn add(&mut self, name: &[u8], read: &[u8], quality: Option<&[u8]>) {
+        let Some(origin) = parse_origin(name) else { return };
+        let mut tally = Tally {
+            reads: 1,
+            bases: read.len() as u64,
+            n_bases: read.iter().filter(|&&base| base == b'N' || base == b'n').count() as u64,
+            ..Default::default()
+        };
+        if let Some(quality) = quality {
+            for &symbol in quality {
+                let score = symbol.saturating_sub(33) as u64;
+                tally.quality_sum += score;
+                tally.q30_bases += u64::from(score >= 30);
+            }
+            tally.quality_bases = quality.len() as u64;
+        }
+        if !self.lanes.get(self.last).is_some_and(|lane| lane.is(&origin)) {
+            self.last = match self.lanes.iter().position(|lane| lane.is(&origin)) {
+                Some(index) => index,
+                None => {
+                    self.lanes.push(LaneTally {
+                        instrument: origin.instrument.to_string(),
+                        run: origin.run.to_string(),
+                        flowcell: origin.flowcell.to_string(),
+                        lane: origin.lane,
+                        total: Tally::default(),
+                        tiles: BTreeMap::new(),
+                    });
+                    self.lanes.len() - 1
+                },
+            };
+        }
+        let lane = &mut self.lanes[self.last];
+        lane.total.add(&tally);
+        lane.tiles.entry(origin.tile).or_default().add(&tally);
+    }
+
+    // One entry per lane, in instrument, run, flow cell and lane order
+    pub fn finish(mut self) -> Vec<LaneQc> {
+        self.lanes.sort_by(|a, b| (&a.instrument, &a.run, &a.flowcell, a.lane).cmp(&(&b.instrument, &b.run, &b.flowcell, b.lane)));
+        self.lanes.into_iter().map(|lane| {
+            let mean_quality = lane.total.mean_quality();
+            let low_quality_tiles = lane.tiles.iter()
+                .filter(|(_, tile)| tile.reads >= MIN_TILE_READS && tile.quality_bases > 0 && mean_quality - tile.mean_quality() >= LOW_TILE_DROP)
+                .map(|(&tile, _)| tile)
+                .collect();
+            let tiles = lane.tiles.iter().map(|(&tile, t)| TileQc {
+                tile,
+                reads: t.reads,
+                n_fraction: Tally::share(t.n_bases, t.bases),
+                mean_quality: t.mean_quality(),
+                q30_fraction: Tally::share(t.q30_bases, t.quality_bases),
+            }).collect();
+            LaneQc {
+                reads: lane.total.reads,
+                bases: lane.total.bases,
+                n_fraction: Tally::share(lane.total.n_bases, lane.total.bases),
+                mean_quality,
+                q30_fraction: Tally::share(lane.total.q30_bases, lane.total.quality_bases),
+                instrument: lane.instrument,
+                run: lane.run,
+                flowcell: lane.flowcell,
+                lane: lane.lane,
+                low_quality_tiles,
+                tiles,
+            }
+        }).collect()
+    }
+}
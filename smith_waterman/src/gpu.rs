@@ -2,15 +2,15 @@
 // Optimized for RTX 4070 and cross-platform GPU support
 
 use ocl;
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::{Arc, Mutex};
 
 // Aggressive GPU constants for RTX 4070 Ti - use that 12GB!
 pub const GPU_WORK_GROUP_SIZE: usize = 1024; // Increased work group size
 pub const GPU_MAX_WORK_GROUPS: usize = 1000000; // Massive increase - use more GPU memory
 
-// Global OpenCL context manager to prevent resource exhaustion
-static OPENCL_CONTEXT: Lazy<Mutex<Option<(ocl::Context, ocl::Queue, ocl::Device)>>> = 
+// Process-wide context on the first GPU, shared to prevent resource exhaustion
+static SHARED_CONTEXT: Lazy<Mutex<Option<GpuContext>>> = 
     Lazy::new(|| Mutex::new(None));
 
 // GPU device information
@@ -27,6 +27,17 @@ pub struct GpuAlignmentResult {
     pub score: i32,
     pub processing_time_ms: f64,
     pub gpu_device: String,
+    // Input behind the score (0 where not tracked, e.g. pair mode)
+    pub total_reads: usize,
+    pub total_bases: usize,
+    // Per-contig scores of a pair against a FASTA target (contig_chunks.rs), empty otherwise
+    pub contigs: Vec<crate::contig_chunks::ContigScore>,
+}
+
+impl GpuAlignmentResult {
+    pub fn normalized(&self) -> crate::score_metrics::NormalizedScore {
+        crate::score_metrics::NormalizedScore::from_totals(self.score as i64, self.total_reads, self.total_bases)
+    }
 }
 
 // Check if GPU is available using OpenCL
@@ -93,40 +104,159 @@ pub fn get_gpu_devices() -> Vec<GpuDevice> {
     devices_out
 }
 
-// Get or create OpenCL context and queue (thread-safe singleton)
-pub fn get_opencl_context() -> Result<(ocl::Context, ocl::Queue, ocl::Device), String> {
-    let mut context_guard = OPENCL_CONTEXT.lock().map_err(|e| format!("Failed to acquire context lock: {}", e))?;
-    
-    if let Some((context, queue, device)) = context_guard.as_ref() {
-        // Return clones of existing context
-        Ok((context.clone(), queue.clone(), device.clone()))
-    } else {
-        // Initialize new context
-        let (context, queue, device) = init_opencl()?;
-        *context_guard = Some((context.clone(), queue.clone(), device.clone()));
-        Ok((context, queue, device))
+// Every OpenCL GPU, platform by platform, in get_gpu_devices order
+fn opencl_gpus() -> Vec<(ocl::Platform, ocl::Device)> {
+    ocl::Platform::list().into_iter()
+        .flat_map(|platform| ocl::Device::list(platform, Some(ocl::flags::DEVICE_TYPE_GPU))
+            .unwrap_or_default()
+            .into_iter()
+            .map(move |device| (platform, device)))
+        .collect()
+}
+
+// Every OpenCL device of any type, GPUs, CPUs and accelerators alike, platform
+// by platform; for checking the kernels on whatever device a machine has
+// (`kernel test`) rather than for running on it
+pub fn opencl_devices() -> Vec<(ocl::Platform, ocl::Device)> {
+    ocl::Platform::list().into_iter()
+        .flat_map(|platform| ocl::Device::list(platform, Some(ocl::flags::DEVICE_TYPE_ALL))
+            .unwrap_or_default()
+            .into_iter()
+            .map(move |device| (platform, device)))
+        .collect()
+}
+
+// "GPU", "CPU", "accelerator" or "other" for an OpenCL device
+pub fn device_kind(device: ocl::Device) -> &'static str {
+    match device.info(ocl::enums::DeviceInfo::Type) {
+        Ok(ocl::enums::DeviceInfoResult::Type(kind)) if kind.contains(ocl::flags::DEVICE_TYPE_GPU) => "GPU",
+        Ok(ocl::enums::DeviceInfoResult::Type(kind)) if kind.contains(ocl::flags::DEVICE_TYPE_CPU) => "CPU",
+        Ok(ocl::enums::DeviceInfoResult::Type(kind)) if kind.contains(ocl::flags::DEVICE_TYPE_ACCELERATOR) => "accelerator",
+        _ => "other",
     }
 }
 
-// Initialize OpenCL context and queue
-pub fn init_opencl() -> Result<(ocl::Context, ocl::Queue, ocl::Device), String> {
-    let platforms = ocl::Platform::list();
-    if platforms.is_empty() {
-        return Err("No OpenCL platforms found".to_string());
-    }
-    let platform = platforms[0];
-    let devices = match ocl::Device::list(platform, Some(ocl::flags::DEVICE_TYPE_GPU)) {
-        Ok(devs) => devs,
-        Err(_) => return Err("No OpenCL GPU devices found".to_string()),
-    };
-    if devices.is_empty() {
-        return Err("No OpenCL GPU devices found".to_string());
-    }
-    let device = devices[0];
-    let context = ocl::Context::builder()
-        .platform(platform)
-        .devices(device)
-        .build()?;
-    let queue = ocl::Queue::new(&context, device, None)?;
-    Ok((context, queue, device))
-} 
\ No newline at end of file
+// Handle on one GPU's OpenCL context, default queue and built alignment
+// program. Clones share them, and the handle can be sent to and used from any
+// thread: OpenCL allows concurrent enqueues on one queue, and threads that want
+// their launches ordered independently take their own with new_queue()
+#[derive(Clone)]
+pub struct GpuContext {
+    inner: Arc<ContextInner>,
+}
+
+struct ContextInner {
+    context: ocl::Context,
+    queue: ocl::Queue,
+    device: ocl::Device,
+    info: GpuDevice,
+    // Built on first use with the N policy selected at that time
+    program: OnceCell<ocl::Program>,
+}
+
+// Handles cross threads; fail the build if an ocl upgrade changes that
+const _: fn() = || {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<GpuContext>();
+};
+
+impl GpuContext {
+    // The whole `index`th GPU (get_gpu_devices order), in a context of its own
+    pub fn new(index: usize) -> Result<Self, String> {
+        Self::build(index, false)
+    }
+
+    // The context every built-in GPU path uses: the first GPU, or the part of
+    // it --gpu-fraction leaves this run. Created once; later calls clone it
+    pub fn shared() -> Result<Self, String> {
+        let mut shared = SHARED_CONTEXT.lock().map_err(|e| format!("Failed to acquire context lock: {}", e))?;
+        if let Some(context) = shared.as_ref() {
+            return Ok(context.clone());
+        }
+        let context = Self::build(0, true)?;
+        *shared = Some(context.clone());
+        Ok(context)
+    }
+
+    fn build(index: usize, partition: bool) -> Result<Self, String> {
+        let gpus = opencl_gpus();
+        if gpus.is_empty() {
+            return Err("No OpenCL GPU devices found".to_string());
+        }
+        let (platform, device) = *gpus.get(index)
+            .ok_or_else(|| format!("No GPU {}: found {} (numbered from 0)", index, gpus.len()))?;
+        let info = get_gpu_devices().into_iter().nth(index).unwrap_or_else(|| GpuDevice {
+            name: device.name().unwrap_or_else(|_| "Unknown".to_string()),
+            memory_gb: 8.0,
+            max_work_group_size: device.max_wg_size().unwrap_or(1024),
+        });
+        // A sub-device when --gpu-fraction asks for part of the GPU and fission is supported
+        let device = if partition { crate::gpu_share::partition(device) } else { device };
+        Self::from_device(platform, device, info)
+    }
+
+    // The `index`th device of any type (opencl_devices order), in a context of its own
+    pub fn any_device(index: usize) -> Result<Self, String> {
+        let devices = opencl_devices();
+        let (platform, device) = *devices.get(index)
+            .ok_or_else(|| format!("No OpenCL device {}: found {} (numbered from 0)", index, devices.len()))?;
+        let memory_gb = match device.info(ocl::enums::DeviceInfo::GlobalMemSize) {
+            Ok(ocl::enums::DeviceInfoResult::GlobalMemSize(bytes)) => bytes as f32 / (1024.0 * 1024.0 * 1024.0),
+            _ => 8.0,
+        };
+        let info = GpuDevice {
+            name: device.name().unwrap_or_else(|_| "Unknown".to_string()),
+            memory_gb,
+            max_work_group_size: device.max_wg_size().unwrap_or(1024),
+        };
+        Self::from_device(platform, device, info)
+    }
+
+    fn from_device(platform: ocl::Platform, device: ocl::Device, info: GpuDevice) -> Result<Self, String> {
+        let context = ocl::Context::builder()
+            .platform(platform)
+            .devices(device)
+            .build()
+            .map_err(|e| format!("Failed to create OpenCL context on {}: {}", info.name, e))?;
+        let queue = Self::profiling_queue(&context, device)?;
+        Ok(Self { inner: Arc::new(ContextInner { context, queue, device, info, program: OnceCell::new() }) })
+    }
+
+    // Profiling events feed the kernel/transfer/host breakdown in gpu_timing
+    fn profiling_queue(context: &ocl::Context, device: ocl::Device) -> Result<ocl::Queue, String> {
+        ocl::Queue::new(context, device, Some(ocl::flags::QUEUE_PROFILING_ENABLE))
+            .map_err(|e| format!("Failed to create GPU queue: {}", e))
+    }
+
+    pub fn context(&self) -> &ocl::Context {
+        &self.inner.context
+    }
+
+    pub fn queue(&self) -> &ocl::Queue {
+        &self.inner.queue
+    }
+
+    // A further queue on the same device, e.g. one per submitting thread
+    pub fn new_queue(&self) -> Result<ocl::Queue, String> {
+        Self::profiling_queue(&self.inner.context, self.inner.device)
+    }
+
+    pub fn device(&self) -> ocl::Device {
+        self.inner.device
+    }
+
+    pub fn info(&self) -> &GpuDevice {
+        &self.inner.info
+    }
+
+    // The alignment kernels (smith_waterman.cl), built once per context
+    pub fn program(&self) -> Result<&ocl::Program, String> {
+        self.inner.program.get_or_try_init(|| crate::aligner::build_program(&self.inner.context))
+    }
+}
+
+// The shared context as (context, queue, device)
+pub fn get_opencl_context() -> Result<(ocl::Context, ocl::Queue, ocl::Device), String> {
+    let shared = GpuContext::shared()?;
+    Ok((shared.context().clone(), shared.queue().clone(), shared.device()))
+}
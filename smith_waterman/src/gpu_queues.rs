@@ -0,0 +1,212 @@
+// Several OpenCL command queues per device, so one chunk's upload overlaps
+// another chunk's kernel. Launches go to the queues round-robin; with N queues up
+// to N-1 launches stay in flight while the reader hands over the next chunk, and
+// their scores come back in launch order. Set GPU_QUEUES in .env:
+//   auto  time 1 to MAX_QUEUES queues on the first chunks of the run and keep
+//         the fastest (default)
+//   N     always use N queues (1 waits for every launch before the next)
+// Per-read output needs a chunk's reads when its score arrives, so it always
+// runs with one queue
+// A pool scheduled() as a job of a GpuScheduler (gpu_scheduler.rs) takes a slot
+// before each launch and holds it until the launch completes
+
+use crate::aligner::{enqueue_alignment, PendingAlignment};
+use crate::gpu::{GpuContext, GpuDevice};
+use crate::gpu_scheduler::{Job, Slot};
+use crate::kernels::{self, KernelVariant};
+use crate::memory_profile::{MemoryBudget, Reservation, Site};
+use ocl::Program;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub const MAX_QUEUES: usize = 4;
+
+// Launches timed for each queue count while auto-tuning
+const TUNING_WINDOW: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueCount {
+    Auto,
+    Fixed(usize),
+}
+
+impl QueueCount {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Ok(QueueCount::Auto),
+            other => match other.parse::<usize>() {
+                Ok(n) if n >= 1 => Ok(QueueCount::Fixed(n)),
+                _ => Err(format!("Invalid GPU_QUEUES '{}': expected auto or a queue count >= 1", value)),
+            },
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("GPU_QUEUES") {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Ok(QueueCount::Auto),
+        }
+    }
+}
+
+// Throughput of each candidate queue count over its tuning window
+struct Tuner {
+    candidate: usize,
+    launches: usize,
+    bases: usize,
+    started: Instant,
+    rates: Vec<(usize, f64)>,
+}
+
+// A launch, with its host copies' share of the --memory-profile strict budget
+// and its scheduler slot
+type InFlight = (PendingAlignment, Reservation, Option<Slot>);
+
+pub struct LaunchPool<T> {
+    program: Program,
+    queues: Vec<ocl::Queue>,
+    active: usize,
+    next: usize,
+    in_flight: VecDeque<(T, Result<InFlight, String>)>,
+    tuner: Option<Tuner>,
+    variant: KernelVariant,
+    job: Option<Job>,
+    // What the launches' host copies are held against; the default tracks nothing
+    memory: Arc<MemoryBudget>,
+}
+
+impl<T> LaunchPool<T> {
+    pub fn new(count: QueueCount) -> Result<Self, String> {
+        Self::on(&GpuContext::shared()?, count)
+    }
+
+    // Queues of the pool's own on `gpu`
+    pub fn on(gpu: &GpuContext, count: QueueCount) -> Result<Self, String> {
+        let created = match count {
+            QueueCount::Auto => MAX_QUEUES,
+            QueueCount::Fixed(n) => n,
+        };
+        let queues = (0..created)
+            .map(|_| gpu.new_queue())
+            .collect::<Result<Vec<_>, _>>()?;
+        let (active, tuner) = match count {
+            QueueCount::Auto => (1, Some(Tuner { candidate: 1, launches: 0, bases: 0, started: Instant::now(), rates: Vec::new() })),
+            QueueCount::Fixed(n) => (n, None),
+        };
+        Ok(Self {
+            program: gpu.program()?.clone(),
+            queues,
+            active,
+            next: 0,
+            in_flight: VecDeque::new(),
+            tuner,
+            variant: kernels::selected(),
+            job: None,
+            memory: Arc::default(),
+        })
+    }
+
+    // Launch as `job` of its scheduler, when there is one
+    pub fn scheduled(mut self, job: Option<Job>) -> Self {
+        self.job = job;
+        self
+    }
+
+    // Hold the launches' host copies against a run's budget
+    pub fn held_against(mut self, memory: &Arc<MemoryBudget>) -> Self {
+        self.memory = memory.clone();
+        self
+    }
+
+    // Queues in use (the tuning candidate while auto-tuning)
+    pub fn queues(&self) -> usize {
+        self.active
+    }
+
+    // Enqueue an alignment of `bytes1` against `bytes2` and return the launches
+    // that finished to make room for it, oldest first
+    pub fn submit(&mut self, tag: T, bytes1: &[u8], bytes2: &[u8], device: &GpuDevice) -> Vec<(T, Result<i32, String>)> {
+        let _scope = crate::profiler::scope("gpu_submit");
+        if let Some(tuner) = self.tuner.as_mut().filter(|t| t.launches == 0) {
+            tuner.started = Instant::now();
+        }
+        let mut finished = Vec::new();
+        let slot = match self.take_slot((bytes1.len() + bytes2.len()) as u64, &mut finished) {
+            Ok(slot) => slot,
+            Err(e) => {
+                finished.push((tag, Err(e)));
+                return finished;
+            },
+        };
+        let queue = &self.queues[self.next % self.active];
+        self.next += 1;
+        let pending = Reservation::reserve(&self.memory, Site::Batches, bytes1.len() + bytes2.len())
+            .and_then(|budget| Ok((enqueue_alignment(queue, &self.program, bytes1, bytes2, device, self.variant)?, budget, slot)));
+        self.in_flight.push_back((tag, pending));
+
+        while self.in_flight.len() >= self.active {
+            finished.extend(self.complete_oldest());
+        }
+        if self.tuner.is_some() {
+            self.tune(bytes1.len(), &mut finished);
+        }
+        finished
+    }
+
+    // Wait for every launch still in flight
+    pub fn drain(&mut self) -> Vec<(T, Result<i32, String>)> {
+        let mut finished = Vec::new();
+        while !self.in_flight.is_empty() {
+            finished.extend(self.complete_oldest());
+        }
+        finished
+    }
+
+    fn complete_oldest(&mut self) -> Option<(T, Result<i32, String>)> {
+        let (tag, pending) = self.in_flight.pop_front()?;
+        Some((tag, pending.and_then(|(pending, _budget, _slot)| pending.wait())))
+    }
+
+    // The job's slot for the next launch, completing this pool's own launches
+    // while waiting for one (they may hold the slots it is waiting for)
+    fn take_slot(&mut self, bytes: u64, finished: &mut Vec<(T, Result<i32, String>)>) -> Result<Option<Slot>, String> {
+        let Some(job) = self.job.clone() else { return Ok(None) };
+        loop {
+            if let Some(slot) = job.try_acquire(bytes)? {
+                return Ok(Some(slot));
+            }
+            match self.complete_oldest() {
+                Some(done) => finished.push(done),
+                None => return job.acquire(bytes).map(Some),
+            }
+        }
+    }
+
+    // Count a launch towards the current candidate; when its window is full, wait
+    // for it to finish, note its rate, and move on to the next candidate or settle
+    fn tune(&mut self, bases: usize, finished: &mut Vec<(T, Result<i32, String>)>) {
+        let Some(tuner) = self.tuner.as_mut() else { return };
+        tuner.launches += 1;
+        tuner.bases += bases;
+        if tuner.launches < TUNING_WINDOW {
+            return;
+        }
+        finished.extend(self.drain());
+        let Some(mut tuner) = self.tuner.take() else { return };
+        let seconds = tuner.started.elapsed().as_secs_f64().max(1e-9);
+        tuner.rates.push((tuner.candidate, tuner.bases as f64 / seconds));
+        if tuner.candidate < self.queues.len() {
+            tuner.candidate += 1;
+            tuner.launches = 0;
+            tuner.bases = 0;
+            self.active = tuner.candidate;
+            self.tuner = Some(tuner);
+            return;
+        }
+        let best = tuner.rates.iter().copied().fold((1, 0.0), |best, rate| if rate.1 > best.1 { rate } else { best });
+        self.active = best.0;
+        let rates: Vec<String> = tuner.rates.iter().map(|(n, rate)| format!("{}: {:.1} Mbases/s", n, rate / 1e6)).collect();
+        println!("GPU queues: auto-tuned to {} ({})", self.active, rates.join(", "));
+    }
+}
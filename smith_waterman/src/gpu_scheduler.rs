@@ -0,0 +1,245 @@
+// Fair sharing of one GPU between concurrent runs of a process, such as a
+// service running several jobs' WGS runs on threads of its own. Without it each
+// run's launch pool enqueues as fast as its reader feeds it, so whichever job
+// started first floods the device and the others wait behind it, FIFO
+// `serve` runs every job it takes through one (serve.rs), and library callers
+// running run_wgs_in on several threads can do the same. Each job joins a
+// GpuScheduler with a priority and an optional GPU memory quota, and its
+// RunContext carries the Job into the run's LaunchPool, which takes a Slot
+// before every launch and gives it back when the launch completes
+// Time slicing: at most `slots` launches are on the device at once across every
+// job. A slot goes to the waiting job that has had the least GPU work for its
+// priority: each launch charges its bases / priority to the job's virtual time
+// (kernel time grows with the bases), so a job of priority 2 gets twice the
+// launches' worth of a job of priority 1 while both have work, and an idle job
+// loses no share. A job joining late starts level with the least-served job
+// rather than owed everything it missed
+// Quotas: a job's launches in flight hold at most its quota of sequence bytes
+// on the device; a launch that alone exceeds it fails
+// A pool holding slots of its own completes its launches before waiting for
+// another, so a job never waits on slots only it can give back
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SLOTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobOptions {
+    // Share of the GPU relative to the other jobs (at least 1)
+    pub priority: u32,
+    // Sequence bytes this job's launches may hold on the device at once
+    pub memory_quota: Option<u64>,
+}
+
+impl Default for JobOptions {
+    fn default() -> Self {
+        Self { priority: 1, memory_quota: None }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JobStats {
+    pub name: String,
+    pub priority: u32,
+    pub launches: u64,
+    pub bytes: u64,
+    // Time the job's launches held a slot, and time spent waiting for one
+    pub gpu_time: Duration,
+    pub waited: Duration,
+}
+
+struct JobState {
+    id: u64,
+    options: JobOptions,
+    in_flight_bytes: u64,
+    virtual_time: f64,
+    // Bytes of each launch waiting for a slot
+    waiting: Vec<u64>,
+    stats: JobStats,
+}
+
+struct State {
+    jobs: Vec<JobState>,
+    running: usize,
+    next_id: u64,
+}
+
+struct Shared {
+    slots: usize,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Clone)]
+pub struct GpuScheduler {
+    shared: Arc<Shared>,
+}
+
+struct Membership {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+// A job's handle; clones share it, and the job leaves when the last one drops
+#[derive(Clone)]
+pub struct Job {
+    membership: Arc<Membership>,
+}
+
+// One launch's turn on the GPU, given back when dropped
+pub struct Slot {
+    shared: Arc<Shared>,
+    id: u64,
+    bytes: u64,
+    started: Instant,
+}
+
+impl GpuScheduler {
+    // A scheduler letting `slots` launches run at once
+    pub fn new(slots: usize) -> Self {
+        let state = State { jobs: Vec::new(), running: 0, next_id: 0 };
+        Self { shared: Arc::new(Shared { slots: slots.max(1), state: Mutex::new(state), changed: Condvar::new() }) }
+    }
+
+    pub fn join(&self, name: &str, options: JobOptions) -> Result<Job, String> {
+        if options.priority == 0 {
+            return Err(format!("Job {}: priority must be at least 1", name));
+        }
+        if options.memory_quota == Some(0) {
+            return Err(format!("Job {}: a memory quota of 0 bytes leaves no room for a launch", name));
+        }
+        let mut state = self.shared.lock();
+        let virtual_time = state.jobs.iter().map(|job| job.virtual_time).reduce(f64::min).unwrap_or(0.0);
+        let id = state.next_id;
+        state.next_id += 1;
+        state.jobs.push(JobState {
+            id,
+            options,
+            in_flight_bytes: 0,
+            virtual_time,
+            waiting: Vec::new(),
+            stats: JobStats { name: name.to_string(), priority: options.priority, ..Default::default() },
+        });
+        Ok(Job { membership: Arc::new(Membership { shared: self.shared.clone(), id }) })
+    }
+
+    // Every job still in, in the order they joined
+    pub fn stats(&self) -> Vec<JobStats> {
+        self.shared.lock().jobs.iter().map(|job| job.stats.clone()).collect()
+    }
+}
+
+impl State {
+    fn job(&mut self, id: u64) -> Option<&mut JobState> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    // Whether job `id` may launch `bytes` now: a slot is free, the launch fits
+    // its quota, and no other waiting job that could launch is owed more
+    fn may_launch(&self, slots: usize, id: u64, bytes: u64) -> bool {
+        if self.running >= slots {
+            return false;
+        }
+        let fits = |job: &JobState, bytes: u64| job.options.memory_quota.is_none_or(|quota| job.in_flight_bytes + bytes <= quota);
+        let Some(own) = self.jobs.iter().find(|job| job.id == id) else { return false };
+        if !fits(own, bytes) {
+            return false;
+        }
+        !self.jobs.iter().any(|job| {
+            job.id != id && job.virtual_time < own.virtual_time
+                && job.waiting.iter().min().is_some_and(|&waiting| fits(job, waiting))
+        })
+    }
+
+    fn grant(&mut self, shared: &Arc<Shared>, id: u64, bytes: u64) -> Slot {
+        self.running += 1;
+        if let Some(job) = self.job(id) {
+            job.in_flight_bytes += bytes;
+            job.virtual_time += bytes as f64 / f64::from(job.options.priority);
+            job.stats.launches += 1;
+            job.stats.bytes += bytes;
+        }
+        Slot { shared: shared.clone(), id, bytes, started: Instant::now() }
+    }
+}
+
+impl Job {
+    fn check_quota(&self, state: &mut State, bytes: u64) -> Result<(), String> {
+        let Some(job) = state.job(self.membership.id) else { return Err("GPU job has left the scheduler".to_string()) };
+        match job.options.memory_quota {
+            Some(quota) if bytes > quota => Err(format!("Launch of {} bytes exceeds job {}'s GPU memory quota of {} bytes",
+                                                        bytes, job.stats.name, quota)),
+            _ => Ok(()),
+        }
+    }
+
+    // This job's launches, bytes and times so far
+    pub fn stats(&self) -> Option<JobStats> {
+        self.membership.shared.lock().job(self.membership.id).map(|job| job.stats.clone())
+    }
+
+    // A slot for a launch of `bytes` if one can be had now
+    pub fn try_acquire(&self, bytes: u64) -> Result<Option<Slot>, String> {
+        let shared = &self.membership.shared;
+        let mut state = shared.lock();
+        self.check_quota(&mut state, bytes)?;
+        if !state.may_launch(shared.slots, self.membership.id, bytes) {
+            return Ok(None);
+        }
+        Ok(Some(state.grant(shared, self.membership.id, bytes)))
+    }
+
+    // A slot for a launch of `bytes`, waiting for this job's turn
+    pub fn acquire(&self, bytes: u64) -> Result<Slot, String> {
+        let shared = &self.membership.shared;
+        let id = self.membership.id;
+        let started = Instant::now();
+        let mut state = shared.lock();
+        self.check_quota(&mut state, bytes)?;
+        if let Some(job) = state.job(id) {
+            job.waiting.push(bytes);
+        }
+        while !state.may_launch(shared.slots, id, bytes) {
+            state = shared.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if let Some(job) = state.job(id) {
+            if let Some(index) = job.waiting.iter().position(|&waiting| waiting == bytes) {
+                job.waiting.swap_remove(index);
+            }
+            job.stats.waited += started.elapsed();
+        }
+        let slot = state.grant(shared, id, bytes);
+        // Another waiter may be next now that this one's request is gone
+        shared.changed.notify_all();
+        Ok(slot)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.running -= 1;
+        if let Some(job) = state.job(self.id) {
+            job.in_flight_bytes -= self.bytes;
+            job.stats.gpu_time += self.started.elapsed();
+        }
+        drop(state);
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.jobs.retain(|job| job.id != self.id);
+        drop(state);
+        self.shared.changed.notify_all();
+    }
+}
@@ -0,0 +1,107 @@
+// --gpu-fraction: share one GPU with other workloads on a workstation
+// Below 1.0 the OpenCL device is split with clCreateSubDevices (device fission)
+// and the aligner runs on a sub-device holding that share of the compute units.
+// Most GPU drivers don't implement fission; there the aligner keeps the whole
+// device and throttles itself instead, buffering fewer chunks ahead of the GPU.
+// Either way the GPU memory budget is scaled by the fraction. Launch geometry
+// doesn't depend on compute units, so scores are the same as on the whole device
+
+use crate::warnings::{self, Category};
+use ocl::core::ffi;
+use once_cell::sync::OnceCell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShareMode {
+    Whole,
+    SubDevice { compute_units: u32, total_units: u32 },
+    Throttled,
+}
+
+static FRACTION: OnceCell<f64> = OnceCell::new();
+static MODE: OnceCell<ShareMode> = OnceCell::new();
+
+pub fn configure(fraction: f64) -> Result<(), String> {
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(format!("Invalid --gpu-fraction {}: expected a value in (0, 1]", fraction));
+    }
+    let _ = FRACTION.set(fraction);
+    Ok(())
+}
+
+pub fn fraction() -> f64 {
+    FRACTION.get().copied().unwrap_or(1.0)
+}
+
+// Decided when the OpenCL context is created
+pub fn mode() -> ShareMode {
+    MODE.get().copied().unwrap_or(ShareMode::Whole)
+}
+
+// Device to build the shared context on: `device` itself, or a sub-device
+pub fn partition(device: ocl::Device) -> ocl::Device {
+    let share = fraction();
+    if share >= 1.0 {
+        let _ = MODE.set(ShareMode::Whole);
+        return device;
+    }
+    match create_sub_device(device, share) {
+        Ok((sub_device, compute_units, total_units)) => {
+            let _ = MODE.set(ShareMode::SubDevice { compute_units, total_units });
+            sub_device
+        },
+        Err(e) => {
+            warnings::warn(Category::Throttling, format!("GPU share: device fission not available ({}); throttling queue depth and memory instead", e));
+            let _ = MODE.set(ShareMode::Throttled);
+            device
+        },
+    }
+}
+
+fn create_sub_device(device: ocl::Device, share: f64) -> Result<(ocl::Device, u32, u32), String> {
+    let total_units = match device.info(ocl::enums::DeviceInfo::MaxComputeUnits) {
+        Ok(ocl::enums::DeviceInfoResult::MaxComputeUnits(n)) => n,
+        Ok(other) => return Err(format!("unexpected compute unit info {:?}", other)),
+        Err(e) => return Err(format!("failed to query compute units: {}", e)),
+    };
+    let compute_units = ((total_units as f64 * share).round() as u32).clamp(1, total_units.max(1));
+    let properties: [ffi::cl_device_partition_property; 4] = [
+        ffi::CL_DEVICE_PARTITION_BY_COUNTS as ffi::cl_device_partition_property,
+        compute_units as ffi::cl_device_partition_property,
+        ffi::CL_DEVICE_PARTITION_BY_COUNTS_LIST_END as ffi::cl_device_partition_property,
+        0,
+    ];
+    let mut sub_device: ffi::cl_device_id = std::ptr::null_mut();
+    let mut created: ffi::cl_uint = 0;
+    // ocl doesn't wrap clCreateSubDevices, so call it directly
+    let status = unsafe {
+        ffi::clCreateSubDevices(device.as_core().as_raw(), properties.as_ptr(), 1, &mut sub_device, &mut created)
+    };
+    if status != ffi::CL_SUCCESS || created == 0 || sub_device.is_null() {
+        return Err(format!("clCreateSubDevices returned {}", status));
+    }
+    let sub_device = unsafe { ocl::core::DeviceId::from_raw(sub_device) };
+    Ok((ocl::Device::from(sub_device), compute_units, total_units))
+}
+
+// Share of `bytes` of GPU memory this run may use
+pub fn memory_budget(bytes: usize) -> usize {
+    (bytes as f64 * fraction()) as usize
+}
+
+// Chunks buffered ahead of the GPU; scaled down only when the device wasn't split
+pub fn channel_depth(depth: usize) -> usize {
+    match mode() {
+        ShareMode::Throttled => ((depth as f64 * fraction()).ceil() as usize).max(1),
+        _ => depth,
+    }
+}
+
+pub fn describe() -> String {
+    match mode() {
+        ShareMode::Whole => "whole device".to_string(),
+        ShareMode::SubDevice { compute_units, total_units } =>
+            format!("sub-device with {} of {} compute units, {:.0}% of GPU memory", compute_units, total_units, fraction() * 100.0),
+        ShareMode::Throttled =>
+            format!("whole device, throttled to {:.0}% of queue depth and GPU memory", fraction() * 100.0),
+    }
+}
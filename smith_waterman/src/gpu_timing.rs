@@ -0,0 +1,102 @@
+// GPU time breakdown from OpenCL profiling events
+// The shared queue is created with CL_QUEUE_PROFILING_ENABLE, so every write,
+// kernel, and read in gpu_align_bytes carries queued/submit/start/end device
+// timestamps. Each launch splits its wall time into transfer time (writes and
+// the result read), kernel time, queue time (queued -> start), and the host time
+// left over (buffer and program setup, synchronization)
+// The totals are the run's (run_context::RunState)
+
+use crate::run_context::with_state;
+use serde::{Deserialize, Serialize};
+
+// Device timestamps of one command, in nanoseconds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandTimes {
+    pub queued: u64,
+    pub submit: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl CommandTimes {
+    pub fn from_event(event: &ocl::Event) -> Result<Self, String> {
+        use ocl::enums::ProfilingInfo;
+        let time = |info: ProfilingInfo| -> Result<u64, String> {
+            event.profiling_info(info)
+                .and_then(|r| r.time().map_err(Into::into))
+                .map_err(|e| format!("Failed to read OpenCL profiling info: {}", e))
+        };
+        Ok(Self {
+            queued: time(ProfilingInfo::Queued)?,
+            submit: time(ProfilingInfo::Submit)?,
+            start: time(ProfilingInfo::Start)?,
+            end: time(ProfilingInfo::End)?,
+        })
+    }
+
+    pub fn execution_ns(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn queue_ns(&self) -> u64 {
+        self.start.saturating_sub(self.queued)
+    }
+}
+
+// Totals over all launches in the run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpuTimingSummary {
+    pub launches: usize,
+    pub kernel_ms: f64,
+    pub transfer_ms: f64,
+    pub queue_ms: f64,
+    pub host_ms: f64,
+    pub max_kernel_ms: f64,
+}
+
+impl GpuTimingSummary {
+    pub fn total_ms(&self) -> f64 {
+        self.kernel_ms + self.transfer_ms + self.host_ms
+    }
+
+    fn percent(&self, part: f64) -> f64 {
+        let total = self.total_ms();
+        if total > 0.0 { part / total * 100.0 } else { 0.0 }
+    }
+
+    pub fn print(&self) {
+        if self.launches == 0 {
+            return;
+        }
+        println!("GPU time breakdown ({} launches):", self.launches);
+        println!("   Kernel:   {:.1} ms ({:.1}%), avg {:.3} ms, max {:.3} ms",
+                 self.kernel_ms, self.percent(self.kernel_ms), self.kernel_ms / self.launches as f64, self.max_kernel_ms);
+        println!("   Transfer: {:.1} ms ({:.1}%)", self.transfer_ms, self.percent(self.transfer_ms));
+        println!("   Host:     {:.1} ms ({:.1}%)", self.host_ms, self.percent(self.host_ms));
+        println!("   Queued before start: {:.1} ms", self.queue_ms);
+    }
+}
+
+// Add one launch: its transfers, its kernel, and its total wall time on the host
+pub fn record_launch(transfers: &[CommandTimes], kernel: &CommandTimes, wall_ns: u64) {
+    let transfer_ns: u64 = transfers.iter().map(|t| t.execution_ns()).sum();
+    let kernel_ns = kernel.execution_ns();
+    let queue_ns: u64 = transfers.iter().chain(std::iter::once(kernel)).map(|t| t.queue_ns()).sum();
+    let host_ns = wall_ns.saturating_sub(transfer_ns + kernel_ns);
+    crate::file_timing::add_gpu(transfer_ns, kernel_ns);
+    crate::adaptive_chunks::observe(kernel_ns);
+    with_state(|run| {
+        if let Ok(mut summary) = run.gpu_timing.lock() {
+            summary.launches += 1;
+            summary.kernel_ms += kernel_ns as f64 / 1e6;
+            summary.transfer_ms += transfer_ns as f64 / 1e6;
+            summary.queue_ms += queue_ns as f64 / 1e6;
+            summary.host_ms += host_ns as f64 / 1e6;
+            summary.max_kernel_ms = summary.max_kernel_ms.max(kernel_ns as f64 / 1e6);
+        }
+    });
+}
+
+pub fn summary() -> GpuTimingSummary {
+    with_state(|run| run.gpu_timing.lock().map(|s| s.clone()).unwrap_or_default())
+}
@@ -0,0 +1,28 @@
+// --top-n / --min-score: which hits of each query the search modes report
+// A query keeps its `top_n` best hits that score at least `min_score`. Hits
+// arrive in database order and the sort is stable, so equal scores keep it
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HitLimits {
+    pub top_n: usize,
+    pub min_score: i32,
+}
+
+impl HitLimits {
+    pub fn new(top_n: usize, min_score: i32) -> Result<Self, String> {
+        if top_n == 0 {
+            return Err("Invalid --top-n 0: at least one hit per query has to be reported".to_string());
+        }
+        if min_score < 1 {
+            return Err(format!("Invalid --min-score {}: hits need a positive score", min_score));
+        }
+        Ok(Self { top_n, min_score })
+    }
+
+    // Keep the reported hits, best first
+    pub fn select<T>(&self, hits: &mut Vec<T>, score: impl Fn(&T) -> i32) {
+        hits.retain(|hit| score(hit) >= self.min_score);
+        hits.sort_by_key(|hit| std::cmp::Reverse(score(hit)));
+        hits.truncate(self.top_n);
+    }
+}
@@ -0,0 +1,179 @@
+// --keep-in-memory: a file pair read, parsed and packed once, for repeated
+// alignment runs with different scores (--sweep) without reading or
+// decompressing either file again
+// Both files are chunked as the streaming pair mode chunks them (a FASTA file2
+// by contig, aligner::align_pair_chunks), and each chunk's bases are packed 2
+// bits each with the other bytes (N, IUPAC codes, lowercase) kept as runs, as
+// in the packed read cache. A run unpacks each chunk into a scratch buffer
+// before it is launched, and pairs the chunks in the same order as the
+// streaming mode, so its scores and contig report are the same
+// Loading stops with an error as soon as the packed chunks go over
+// MEMORY_BUDGET from .env or, without it, MEMORY_SHARE of the memory available
+// when loading started; the pair then has to be streamed as before
+
+use crate::chunking::ChunkSize;
+use crate::contig_chunks::{ContigScore, ContigTally, ContigWindow};
+use crate::packed_reads::{base_code, BASES};
+use crate::planner::format_bytes;
+
+// Share of available memory the packed dataset may take without MEMORY_BUDGET
+const MEMORY_SHARE: f64 = 0.8;
+
+// One chunk's bases, 2 bits each, with runs of other bytes
+#[derive(Debug, Clone, Default)]
+struct PackedChunk {
+    length: usize,
+    packed: Vec<u8>,
+    // (start, length, byte)
+    runs: Vec<(u32, u32, u8)>,
+}
+
+impl PackedChunk {
+    fn pack(bases: &[u8]) -> Self {
+        let mut runs: Vec<(u32, u32, u8)> = Vec::new();
+        for (position, &base) in bases.iter().enumerate() {
+            if base_code(base).is_some() {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((start, run, byte)) if *byte == base && (*start + *run) as usize == position => *run += 1,
+                _ => runs.push((position as u32, 1, base)),
+            }
+        }
+        let packed = bases.chunks(4)
+            .map(|four| four.iter().enumerate().fold(0u8, |packed, (j, &base)| packed | base_code(base).unwrap_or(0) << (j * 2)))
+            .collect();
+        Self { length: bases.len(), packed, runs }
+    }
+
+    fn unpack_into(&self, bases: &mut Vec<u8>) {
+        bases.clear();
+        bases.extend((0..self.length).map(|j| BASES[(self.packed[j / 4] >> ((j % 4) * 2) & 3) as usize]));
+        for &(start, run, byte) in &self.runs {
+            bases[start as usize..(start + run) as usize].fill(byte);
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.packed.len() + self.runs.len() * std::mem::size_of::<(u32, u32, u8)>()
+    }
+}
+
+// A contig window of a FASTA file2, without its bases
+#[derive(Debug, Clone)]
+struct WindowPlace {
+    contig: String,
+    index: usize,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct InMemoryDataset {
+    pub file1: String,
+    pub file2: String,
+    chunks1: Vec<PackedChunk>,
+    chunks2: Vec<PackedChunk>,
+    // Set when file2 is chunked by contig, one per chunk of chunks2
+    windows: Option<Vec<WindowPlace>>,
+    bases: usize,
+    bytes: usize,
+    limit: u64,
+}
+
+// MEMORY_BUDGET from .env, or MEMORY_SHARE of the memory available now
+fn memory_limit() -> Result<u64, String> {
+    if std::env::var("MEMORY_BUDGET").is_ok() {
+        return crate::memory_profile::budget_from_env();
+    }
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    match system.available_memory() {
+        0 => Err("--keep-in-memory couldn't read the available memory; set MEMORY_BUDGET in .env".to_string()),
+        available => Ok((available as f64 * MEMORY_SHARE) as u64),
+    }
+}
+
+impl InMemoryDataset {
+    // Read and pack both files in `chunk_size` chunks
+    pub fn load(file1: &str, file2: &str, chunk_size: ChunkSize) -> Result<Self, String> {
+        let mut dataset = Self {
+            file1: file1.to_string(),
+            file2: file2.to_string(),
+            chunks1: Vec::new(),
+            chunks2: Vec::new(),
+            windows: None,
+            bases: 0,
+            bytes: 0,
+            limit: memory_limit()?,
+        };
+        crate::aligner::process_fastq_file_in_chunks(file1, chunk_size, |chunk| {
+            let packed = dataset.add(file1, chunk.bases())?;
+            dataset.chunks1.push(packed);
+            Ok(())
+        })?;
+        if crate::contig_chunks::is_contig_target(file2)? {
+            let mut windows = Vec::new();
+            crate::contig_chunks::for_each_window(file2, chunk_size, |window| {
+                let packed = dataset.add(file2, window.bases)?;
+                dataset.chunks2.push(packed);
+                windows.push(WindowPlace { contig: window.contig.to_string(), index: window.index, start: window.start, end: window.end });
+                Ok(())
+            })?;
+            dataset.windows = Some(windows);
+        } else {
+            crate::aligner::process_fastq_file_in_chunks(file2, chunk_size, |chunk| {
+                let packed = dataset.add(file2, chunk.bases())?;
+                dataset.chunks2.push(packed);
+                Ok(())
+            })?;
+        }
+        Ok(dataset)
+    }
+
+    fn add(&mut self, file: &str, bases: &[u8]) -> Result<PackedChunk, String> {
+        let packed = PackedChunk::pack(bases);
+        self.bases += bases.len();
+        self.bytes += packed.bytes();
+        if self.bytes as u64 > self.limit {
+            return Err(format!("{} and {} don't fit in memory: {} packed after {} bases (in {}), over the {} limit; run without --keep-in-memory",
+                               self.file1, self.file2, format_bytes(self.bytes as u64), self.bases, file, format_bytes(self.limit)));
+        }
+        Ok(packed)
+    }
+
+    pub fn bases(&self) -> usize {
+        self.bases
+    }
+
+    // Memory the packed chunks take
+    pub fn packed_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn chunks(&self) -> (usize, usize) {
+        (self.chunks1.len(), self.chunks2.len())
+    }
+
+    // Every chunk of file1 against every chunk of file2, summed, as
+    // aligner::align_pair_chunks scores the files
+    pub fn align(&self, mut score: impl FnMut(&[u8], &[u8]) -> Result<i32, String>) -> Result<(i32, Vec<ContigScore>), String> {
+        let (mut bases1, mut bases2) = (Vec::new(), Vec::new());
+        let mut total_score = 0;
+        let mut tally = ContigTally::default();
+        for (query_chunk, chunk1) in self.chunks1.iter().enumerate() {
+            chunk1.unpack_into(&mut bases1);
+            for (i, chunk2) in self.chunks2.iter().enumerate() {
+                chunk2.unpack_into(&mut bases2);
+                let chunk_score = score(&bases1, &bases2)?;
+                total_score += chunk_score;
+                if let Some(windows) = &self.windows {
+                    let place = &windows[i];
+                    let window = ContigWindow { contig: &place.contig, index: place.index, start: place.start, end: place.end, bases: &bases2 };
+                    tally.add(&window, query_chunk + 1, chunk_score);
+                }
+            }
+        }
+        Ok((total_score, tally.into_scores()))
+    }
+}
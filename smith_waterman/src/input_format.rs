@@ -0,0 +1,373 @@
+// Input detection and sequence format handling for the streaming readers
+// Compression and format are sniffed from the content (magic bytes and the
+// first record) rather than the file extension, so the same pipeline runs
+// FASTQ reads, assembled contigs, and reference chunks
+
+use crate::async_input::InputSource;
+use crate::read_chunk::ReadChunk;
+use std::io::Read;
+use std::process::Command;
+
+// Bytes read from the start of an input for detection
+pub const SNIFF_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    // Blocked gzip (bgzip), readable by any gzip decoder
+    Bgzf,
+    Zstd,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Bgzf => "bgzf",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    // Compression from the magic bytes at the start of the input
+    pub fn sniff(prefix: &[u8]) -> Result<Self, String> {
+        if prefix.starts_with(&[0x1f, 0x8b]) {
+            // bgzf sets FEXTRA and carries a 'BC' subfield
+            let is_bgzf = prefix.len() >= 14 && prefix[3] & 0x04 != 0 && prefix[12] == b'B' && prefix[13] == b'C';
+            return Ok(if is_bgzf { Compression::Bgzf } else { Compression::Gzip });
+        }
+        if prefix.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Ok(Compression::Zstd);
+        }
+        if prefix.starts_with(b"BZh") {
+            return Err("Unsupported input: bzip2 compression (recompress with gzip, bgzip, or zstd)".to_string());
+        }
+        if prefix.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            return Err("Unsupported input: xz compression (recompress with gzip, bgzip, or zstd)".to_string());
+        }
+        Ok(Compression::None)
+    }
+
+    // External tool that decompresses to stdout (input as argument or stdin);
+    // None when the input is uncompressed or is decoded in-process (native_decoder)
+    pub fn decompress_command(&self) -> Option<Command> {
+        if decompressor() == Decompressor::Native {
+            return None;
+        }
+        match self {
+            Compression::None => None,
+            Compression::Gzip | Compression::Bgzf => Some(Command::new("zcat")),
+            Compression::Zstd => {
+                let mut cmd = Command::new("zstd");
+                cmd.args(["-dc"]);
+                Some(cmd)
+            },
+        }
+    }
+
+    // In-process decoder over the raw bytes (passthrough when uncompressed)
+    pub fn native_decoder<R: Read + Send + 'static>(&self, raw: R) -> Result<Box<dyn Read + Send>, String> {
+        Ok(match self {
+            Compression::None => Box::new(raw),
+            // bgzf blocks are independent, so they are inflated across the DECOMPRESS_THREADS pool
+            Compression::Bgzf if crate::parallel_gzip::enabled() => Box::new(crate::parallel_gzip::BgzfReader::new(raw)),
+            // MultiGzDecoder also handles bgzf, which is a series of gzip members
+            Compression::Gzip | Compression::Bgzf => Box::new(flate2::read::MultiGzDecoder::new(raw)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(raw)
+                .map_err(|e| format!("Failed to start zstd decoder: {}", e))?),
+        })
+    }
+}
+
+// DECOMPRESSOR in .env: how compressed inputs are decoded
+//   external  piped through zcat / zstd -dc, off the reading thread (default on Linux)
+//   native    decoded in-process with flate2 / zstd, so no tools are needed (default
+//             elsewhere: Windows has neither tool, and macOS has no zstd)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decompressor {
+    External,
+    Native,
+}
+
+pub fn decompressor() -> Decompressor {
+    match std::env::var("DECOMPRESSOR").map(|v| v.trim().to_ascii_lowercase()) {
+        Ok(v) if v == "native" => Decompressor::Native,
+        Ok(v) if v == "external" => Decompressor::External,
+        _ if cfg!(target_os = "linux") => Decompressor::External,
+        _ => Decompressor::Native,
+    }
+}
+
+// What the detection layer found at the start of an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedInput {
+    pub compression: Compression,
+    // None for empty inputs or when the prefix can't be decoded in-process (zstd)
+    pub format: Option<SeqFormat>,
+}
+
+impl DetectedInput {
+    pub fn describe(&self) -> String {
+        format!("{}, {}", self.format.map_or("unknown format", |f| f.name()), self.compression.name())
+    }
+}
+
+// Sequence text is ASCII. A UTF-8 byte order mark (as some Windows editors
+// write) is dropped from the start of an input; CRLF endings are trimmed with
+// the line, and an input with bare CR endings (classic Mac) is split on CR, as
+// its first line ending says. A byte of 0x80 or above in a sequence line is
+// refused with its line and column: scoring compares bytes, so a multi-byte
+// character would silently count as one mismatch per byte
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+// `text` without a leading UTF-8 byte order mark; UTF-16 text is refused
+pub fn strip_bom(text: &[u8]) -> Result<&[u8], String> {
+    if text.starts_with(b"\xff\xfe") || text.starts_with(b"\xfe\xff") {
+        return Err("Unsupported input: UTF-16 text (save it as ASCII or UTF-8)".to_string());
+    }
+    Ok(text.strip_prefix(UTF8_BOM).unwrap_or(text))
+}
+
+// The byte lines of an input end with, from its first line ending: b'\r' for
+// bare CR endings, b'\n' for LF and CRLF (and when there is no ending yet)
+pub fn line_terminator(prefix: &[u8]) -> u8 {
+    match prefix.iter().position(|&b| b == b'\n' || b == b'\r') {
+        Some(end) if prefix[end] == b'\r' && prefix.get(end + 1).is_some_and(|&next| next != b'\n') => b'\r',
+        _ => b'\n',
+    }
+}
+
+// A sequence line (without its ending) that is ASCII throughout; `line_number`
+// counts from 1
+pub fn check_sequence_line(line: &[u8], line_number: usize) -> Result<(), String> {
+    if line.is_ascii() && !line.contains(&b'\r') {
+        return Ok(());
+    }
+    let Some(column) = line.iter().position(|&b| !b.is_ascii() || b == b'\r') else { return Ok(()) };
+    if line[column] == b'\r' {
+        return Err(format!("Line {}, column {}: carriage return inside a sequence (mixed CR and LF line endings?)", line_number, column + 1));
+    }
+    let rest = &line[column..];
+    let found = match rest.utf8_chunks().next().and_then(|chunk| chunk.valid().chars().next()) {
+        Some(character) => format!("character '{}' (U+{:04X}, {} bytes in UTF-8)", character, character as u32, character.len_utf8()),
+        None => format!("byte 0x{:02X} (not UTF-8)", rest[0]),
+    };
+    Err(format!("Line {}, column {}: non-ASCII {} in a sequence; sequences must be ASCII, as each byte is scored as a base",
+                line_number, column + 1, found))
+}
+
+// Detect compression and sequence format from the first bytes of an input
+pub fn detect_bytes(prefix: &[u8]) -> Result<DetectedInput, String> {
+    let compression = Compression::sniff(prefix)?;
+    if prefix.starts_with(b"CRAM") {
+        return Ok(DetectedInput { compression, format: Some(SeqFormat::Cram) });
+    }
+    let decoded;
+    let text: &[u8] = match compression {
+        Compression::None => prefix,
+        Compression::Gzip | Compression::Bgzf => {
+            // The prefix ends mid-stream, so keep whatever decodes before the cut
+            let mut out = Vec::new();
+            let mut decoder = flate2::read::MultiGzDecoder::new(prefix);
+            let mut buf = [0u8; 8192];
+            while out.len() < SNIFF_BYTES {
+                match decoder.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => out.extend_from_slice(&buf[..n]),
+                }
+            }
+            decoded = out;
+            &decoded
+        },
+        Compression::Zstd => return Ok(DetectedInput { compression, format: None }),
+    };
+
+    if text.starts_with(b"BAM\x01") {
+        return Ok(DetectedInput { compression, format: Some(SeqFormat::Bam) });
+    }
+    let text = strip_bom(text)?;
+    let terminator = line_terminator(text);
+    let first_line = text
+        .split(|&b| b == terminator)
+        .map(crate::read_chunk::trim_line_end)
+        .find(|line| !line.is_empty());
+    let format = match first_line {
+        Some(line) => Some(SeqFormat::from_first_line(line)?),
+        None => None,
+    };
+    Ok(DetectedInput { compression, format })
+}
+
+// Detect a local path or remote URL
+pub fn detect_input(source: &InputSource) -> Result<DetectedInput, String> {
+    let prefix = source.read_prefix(SNIFF_BYTES)?;
+    detect_bytes(&prefix).map_err(|e| format!("{}: {}", source.path(), e))
+}
+
+pub fn detect_path(path: &str) -> Result<DetectedInput, String> {
+    detect_input(&InputSource::from_path(path))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqFormat {
+    Fasta,
+    Fastq,
+    // Alignment files, read through alignment_input
+    Bam,
+    Cram,
+}
+
+impl SeqFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SeqFormat::Fasta => "FASTA",
+            SeqFormat::Fastq => "FASTQ",
+            SeqFormat::Bam => "BAM",
+            SeqFormat::Cram => "CRAM",
+        }
+    }
+
+    pub fn is_alignment(&self) -> bool {
+        matches!(self, SeqFormat::Bam | SeqFormat::Cram)
+    }
+
+    // Format from the first non-empty line of (decompressed) input
+    pub fn from_first_line(line: &[u8]) -> Result<Self, String> {
+        match line.first() {
+            Some(b'>') => Ok(SeqFormat::Fasta),
+            Some(b'@') => Ok(SeqFormat::Fastq),
+            Some(&other) => Err(format!("Unrecognized input format: expected '>' (FASTA) or '@' (FASTQ), found '{}'",
+                                        (other as char).escape_default())),
+            None => Err("Unrecognized input format: empty first line".to_string()),
+        }
+    }
+}
+
+// Turns a stream of lines into reads in a ReadChunk
+// FASTQ reads are complete at their quality line, so a chunk is never swapped
+// between a read and its quality; a FASTA read is only complete
+// at the next header (or end of input), since its sequence may span many lines.
+// The header of the next FASTA read is held back until its first sequence line,
+// so the caller can swap in a fresh chunk whenever push_line reports a read.
+// Lines the chunk won't store (FASTQ '+' lines, and quality lines unless the
+// chunk keeps qualities) can be skipped in the reader without being copied
+#[derive(Debug, Default)]
+pub struct RecordAssembler {
+    format: Option<SeqFormat>,
+    // FASTQ: position within the 4-line record
+    fastq_line: usize,
+    // FASTA: held header and whether its read is open in the chunk
+    header: Vec<u8>,
+    has_header: bool,
+    open: bool,
+    // Lines pushed or skipped, for messages
+    line: usize,
+}
+
+impl RecordAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn format(&self) -> Option<SeqFormat> {
+        self.format
+    }
+
+    // Whether the next line is one `chunk` has no use for: a FASTQ '+' line, or a
+    // quality line when the chunk doesn't keep qualities. The caller can skip its
+    // bytes in the reader (skip_line) rather than copy them into a line buffer
+    pub fn skips_next_line(&self, chunk: &ReadChunk) -> bool {
+        self.format == Some(SeqFormat::Fastq) && (self.fastq_line == 2 || (self.fastq_line == 3 && !chunk.keeps_qualities()))
+    }
+
+    // In place of push_line for a line skipped after skips_next_line; returns true
+    // when it was the quality line, which completes its read
+    pub fn skip_line(&mut self) -> bool {
+        self.line += 1;
+        let position = self.fastq_line;
+        self.fastq_line = (self.fastq_line + 1) % 4;
+        position == 3
+    }
+
+    // Feed one line; returns true when a read was completed in `chunk`
+    pub fn push_line(&mut self, line: &[u8], chunk: &mut ReadChunk) -> Result<bool, String> {
+        self.line += 1;
+        let mut trimmed = crate::read_chunk::trim_line_end(line);
+        let format = match self.format {
+            Some(format) => format,
+            None => {
+                trimmed = strip_bom(trimmed)?;
+                // Skip leading blank lines before detecting
+                if trimmed.is_empty() {
+                    return Ok(false);
+                }
+                let format = SeqFormat::from_first_line(trimmed)?;
+                self.format = Some(format);
+                format
+            },
+        };
+
+        match format {
+            SeqFormat::Bam | SeqFormat::Cram => Err(format!("{} input needs the alignment file reader", format.name())),
+            SeqFormat::Fastq => {
+                let position = self.fastq_line;
+                self.fastq_line = (self.fastq_line + 1) % 4;
+                match position {
+                    0 => {
+                        chunk.push_name(trimmed);
+                        Ok(false)
+                    },
+                    1 => {
+                        check_sequence_line(trimmed, self.line)?;
+                        chunk.push_read(trimmed);
+                        Ok(false)
+                    },
+                    3 => {
+                        chunk.push_quality(trimmed);
+                        Ok(true)
+                    },
+                    _ => Ok(false),
+                }
+            },
+            SeqFormat::Fasta => {
+                if trimmed.starts_with(b">") {
+                    let completed = self.close(chunk);
+                    self.header.clear();
+                    self.header.extend_from_slice(trimmed);
+                    self.has_header = true;
+                    return Ok(completed);
+                }
+                if trimmed.is_empty() {
+                    return Ok(false);
+                }
+                check_sequence_line(trimmed, self.line)?;
+                if !self.open {
+                    chunk.push_name(&self.header);
+                    self.open = true;
+                }
+                chunk.extend_open_read(trimmed);
+                Ok(false)
+            },
+        }
+    }
+
+    // End of input: complete any read still being assembled
+    pub fn finish(&mut self, chunk: &mut ReadChunk) -> bool {
+        self.close(chunk)
+    }
+
+    fn close(&mut self, chunk: &mut ReadChunk) -> bool {
+        if !self.has_header {
+            return false;
+        }
+        if !self.open {
+            // Header with no sequence lines: keep it as an empty read
+            chunk.push_name(&self.header);
+        }
+        chunk.close_read();
+        self.has_header = false;
+        self.open = false;
+        true
+    }
+}
@@ -0,0 +1,125 @@
+// Known-answer battery for the alignment kernels (`kernel test`), to catch a
+// driver that miscompiles them on the machine a run is on rather than on ours
+// Each case is a small fixed pair picked to hit one thing a compiler or device
+// can get wrong: the local reset of a score, byte values above 127 (uchar read
+// as signed), lowercase and IUPAC bytes, N under the N policy, unequal lengths,
+// and lengths on either side of the packed kernel's lanes, the work group and
+// the tiled kernel's tile (4 bases per work item). Every case runs through each
+// kernel variant on the device and is diffed against the CPU path's score at the
+// same launch geometry, scalar, so no SIMD code is trusted either
+// Unlike --verify-kernel (one variant, the run's device, pseudo-random pairs),
+// this takes any OpenCL device, CPU and accelerator ones included, and reports
+// every case rather than stopping at the first
+
+use crate::gpu::GpuContext;
+use crate::kernels::KernelVariant;
+
+pub struct Case {
+    pub name: String,
+    seq1: Vec<u8>,
+    seq2: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub case: String,
+    pub variant: KernelVariant,
+    pub length: usize,
+    pub expected: i32,
+    // The kernel's score, or why it didn't return one
+    pub got: Result<i32, String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.got.as_ref().is_ok_and(|&score| score == self.expected)
+    }
+}
+
+// `pattern` repeated to `len` bytes
+fn repeat(pattern: &[u8], len: usize) -> Vec<u8> {
+    pattern.iter().copied().cycle().take(len).collect()
+}
+
+// `seq` with the byte at every `every`-th position replaced by `with`
+fn every(seq: &[u8], every: usize, with: u8) -> Vec<u8> {
+    seq.iter().enumerate().map(|(i, &base)| if i % every == every - 1 { with } else { base }).collect()
+}
+
+fn case(name: impl Into<String>, seq1: Vec<u8>, seq2: Vec<u8>) -> Case {
+    Case { name: name.into(), seq1, seq2 }
+}
+
+// The battery for a device whose launches use work groups of `group_size`
+pub fn battery(group_size: usize) -> Vec<Case> {
+    let acgt = |len| repeat(b"ACGT", len);
+    let mut cases = vec![
+        case("single match", b"A".to_vec(), b"A".to_vec()),
+        case("single mismatch", b"A".to_vec(), b"C".to_vec()),
+        case("all mismatches", repeat(b"A", 300), repeat(b"C", 300)),
+        case("mismatch every 7th", acgt(4097), every(&acgt(4097), 7, b'T')),
+        case("score resets to zero", [acgt(100), repeat(b"A", 200), acgt(50)].concat(), [acgt(100), repeat(b"C", 200), acgt(50)].concat()),
+        case("lowercase against uppercase", repeat(b"acgt", 256), acgt(256)),
+        case("IUPAC codes", repeat(b"RYKMSWBDHV", 500), acgt(500)),
+        case("N every 5th", every(&acgt(1000), 5, b'N'), acgt(1000)),
+        case("N against N", repeat(b"N", 400), repeat(b"N", 400)),
+        case("bytes above 127", (0..1024).map(|i| 128 + (i % 128) as u8).collect(), (0..1024).map(|i| 128 + (i % 128) as u8).collect()),
+        case("zero bytes", every(&acgt(512), 3, 0), acgt(512)),
+        case("seq1 longer", acgt(1500), acgt(700)),
+        case("seq2 longer", every(&acgt(700), 11, b'G'), acgt(1500)),
+        case("many work groups", acgt(65_537), every(&acgt(65_537), 101, b'A')),
+    ];
+    // Around the packed kernel's lanes, the work group and the tile
+    let tile = group_size * 4;
+    for len in [3, 4, 5, 7, group_size - 1, group_size, group_size + 1, tile - 1, tile, tile + 1] {
+        if len > 0 {
+            cases.push(case(format!("identical, {} bases", len), acgt(len), acgt(len)));
+        }
+    }
+    cases
+}
+
+// Run every case through each of `variants` on `gpu`; fails only when the
+// kernels can't be built there at all
+pub fn run(gpu: &GpuContext, variants: &[KernelVariant]) -> Result<Vec<CaseResult>, String> {
+    let (_, group_size) = crate::aligner::launch_geometry(1, gpu.info());
+    let program = gpu.program()?;
+    let mut results = Vec::new();
+    for case in battery(group_size) {
+        // The CPU path sees the bytes the kernels do, after the N policy
+        let (seq1, seq2) = (crate::n_policy::prepare(&case.seq1), crate::n_policy::prepare(&case.seq2));
+        let length = seq1.len().min(seq2.len());
+        let (work_groups, group_size) = crate::aligner::launch_geometry(length, gpu.info());
+        let expected = crate::cpu_align::align_with(&seq1, &seq2, work_groups, group_size, crate::cpu_align::SimdLevel::Scalar);
+        for &variant in variants {
+            let got = crate::aligner::enqueue_alignment(gpu.queue(), program, &case.seq1, &case.seq2, gpu.info(), variant)
+                .and_then(|pending| pending.wait());
+            results.push(CaseResult { case: case.name.clone(), variant, length, expected, got });
+        }
+    }
+    Ok(results)
+}
+
+// One line per variant, then every case that failed; returns the failures
+pub fn print(device: &str, results: &[CaseResult]) -> usize {
+    let mut failed = 0;
+    for variant in KernelVariant::all() {
+        let ran: Vec<&CaseResult> = results.iter().filter(|result| result.variant == variant).collect();
+        if ran.is_empty() {
+            continue;
+        }
+        let passed = ran.iter().filter(|result| result.passed()).count();
+        println!("  {:<7} {}/{} cases match the CPU path", variant.name(), passed, ran.len());
+        for result in ran.iter().filter(|result| !result.passed()) {
+            match &result.got {
+                Ok(score) => println!("    FAIL {} ({} bases): kernel scored {}, CPU path {}", result.case, result.length, score, result.expected),
+                Err(e) => println!("    FAIL {} ({} bases): {}", result.case, result.length, e),
+            }
+        }
+        failed += ran.len() - passed;
+    }
+    if failed == 0 {
+        println!("  {}: every kernel passed", device);
+    }
+    failed
+}
@@ -0,0 +1,149 @@
+// Alignment kernel variants in smith_waterman.cl, chosen with --kernel
+// All variants produce the same scores as the CPU path for the same launch
+// geometry; --verify-kernel checks that on the current device before a run, and
+// `kernel test` on any OpenCL device with known-answer cases (kernel_test.rs)
+
+use crate::gpu::GpuDevice;
+use crate::gpu_timing;
+use crate::seed::Seedable;
+use once_cell::sync::OnceCell;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KernelVariant {
+    // One global byte load per position
+    #[default]
+    Basic,
+    // Sequence tiles staged in local memory with vector loads
+    Tiled,
+    // Four bases per 32-bit word compared with SIMD-within-a-register
+    // (experimental; see `bench precision`)
+    Packed,
+}
+
+// Lanes of the launch geometry each packed work item covers
+pub const PACKED_LANES: usize = 4;
+
+impl KernelVariant {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "basic" => Ok(KernelVariant::Basic),
+            "tiled" => Ok(KernelVariant::Tiled),
+            "packed" => Ok(KernelVariant::Packed),
+            other => Err(format!("Invalid --kernel '{}': expected basic, tiled, or packed", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KernelVariant::Basic => "basic",
+            KernelVariant::Tiled => "tiled",
+            KernelVariant::Packed => "packed",
+        }
+    }
+
+    // Entry point in smith_waterman.cl
+    pub fn entry_point(&self) -> &'static str {
+        match self {
+            KernelVariant::Basic => "smith_waterman_align",
+            KernelVariant::Tiled => "smith_waterman_align_tiled",
+            KernelVariant::Packed => "smith_waterman_align_packed",
+        }
+    }
+
+    pub fn all() -> [KernelVariant; 3] {
+        [KernelVariant::Basic, KernelVariant::Tiled, KernelVariant::Packed]
+    }
+}
+
+static SELECTED: OnceCell<KernelVariant> = OnceCell::new();
+
+pub fn select(variant: KernelVariant) {
+    let _ = SELECTED.set(variant);
+}
+
+pub fn selected() -> KernelVariant {
+    SELECTED.get().copied().unwrap_or_default()
+}
+
+// `reference` with every `mismatch_every`-th base redrawn, or random bases
+// past its end
+pub(crate) fn test_sequence(len: usize, rng: &mut Seedable, mismatch_every: usize, reference: &[u8]) -> Vec<u8> {
+    (0..len).map(|i| {
+        let drawn = rng.base();
+        match reference.get(i) {
+            Some(&base) if mismatch_every == 0 || i % mismatch_every != 0 => base,
+            _ => drawn,
+        }
+    }).collect()
+}
+
+// Compare `variant` against the CPU path on lengths around tile and work group
+// edges; returns the number of cases checked
+pub fn verify_against_cpu(variant: KernelVariant, device: &GpuDevice) -> Result<usize, String> {
+    let lengths = [1000, 1023, 4096, 4097, 65_537, 1_000_003];
+    for (case, &len) in lengths.iter().enumerate() {
+        // Fixed seeds, so every run checks the same cases
+        let seq1 = test_sequence(len, &mut Seedable::new(case as u64 + 1), 0, &[]);
+        let seq2 = test_sequence(len, &mut Seedable::new(case as u64 + 101), 7, &seq1);
+        let (work_groups, group_size) = crate::aligner::launch_geometry(len, device);
+        let expected = crate::cpu_align::align_with(&seq1, &seq2, work_groups, group_size, crate::cpu_align::SimdLevel::Scalar);
+        let got = crate::aligner::gpu_align_bytes_with(&seq1, &seq2, device, variant)?;
+        if got != expected {
+            return Err(format!("{} kernel: length {} scored {}, CPU path scored {} ({} x {} work items)",
+                               variant.name(), len, got, expected, work_groups, group_size));
+        }
+    }
+    Ok(lengths.len())
+}
+
+#[derive(Debug, Clone)]
+pub struct PrecisionResult {
+    pub variant: KernelVariant,
+    pub score: i32,
+    // Mean device time of one kernel launch
+    pub kernel_ms: f64,
+    pub bases_per_sec: f64,
+}
+
+// `bench precision`: run every variant `runs` times on the same pair of `length`
+// bases and time the kernels from their profiling events. Fails if any variant's
+// score differs from the basic kernel's
+pub fn bench_precision(device: &GpuDevice, length: usize, runs: usize) -> Result<Vec<PrecisionResult>, String> {
+    let mut rng = Seedable::stream("bench precision");
+    let seq1 = test_sequence(length, &mut rng, 0, &[]);
+    let seq2 = test_sequence(length, &mut rng, 7, &seq1);
+    let mut results: Vec<PrecisionResult> = Vec::new();
+    for variant in KernelVariant::all() {
+        // Warm-up launch so the program build isn't timed
+        let score = crate::aligner::gpu_align_bytes_with(&seq1, &seq2, device, variant)?;
+        let before = gpu_timing::summary();
+        for _ in 0..runs.max(1) {
+            let again = crate::aligner::gpu_align_bytes_with(&seq1, &seq2, device, variant)?;
+            if again != score {
+                return Err(format!("{} kernel scored {} and then {} on the same input", variant.name(), score, again));
+            }
+        }
+        let after = gpu_timing::summary();
+        let launches = (after.launches - before.launches).max(1);
+        let kernel_ms = (after.kernel_ms - before.kernel_ms) / launches as f64;
+        if let Some(basic) = results.first() {
+            if score != basic.score {
+                return Err(format!("{} kernel scored {}, basic kernel scored {}", variant.name(), score, basic.score));
+            }
+        }
+        let bases_per_sec = if kernel_ms > 0.0 { length as f64 / (kernel_ms / 1000.0) } else { 0.0 };
+        results.push(PrecisionResult { variant, score, kernel_ms, bases_per_sec });
+    }
+    Ok(results)
+}
+
+pub fn print_precision(results: &[PrecisionResult]) {
+    let Some(basic) = results.first() else { return };
+    println!("{:<8} {:>10} {:>12} {:>14} {:>8}", "kernel", "score", "kernel ms", "Gbases/s", "speedup");
+    for result in results {
+        let speedup = if result.kernel_ms > 0.0 { basic.kernel_ms / result.kernel_ms } else { 0.0 };
+        println!("{:<8} {:>10} {:>12.3} {:>14.2} {:>7.2}x",
+                 result.variant.name(), result.score, result.kernel_ms, result.bases_per_sec / 1e9, speedup);
+    }
+    println!("All kernels returned the same score ({})", basic.score);
+}
@@ -0,0 +1,134 @@
+// K-mer hash functions for the k-mer counter (analyze --kmer-spectrum)
+// A hasher yields a hash for every canonical k-mer of a sequence (A/C/G/T only;
+// a k-mer and its reverse complement hash the same), and the counter is
+// generic over it (KmerSpectrum<H>), so each one gets its own monomorphized
+// loop rather than a call through a table per k-mer
+//   nt        the ntHash rolling hash (rolling_hash.rs), which the sketches
+//             use too; the default, so spectra are sampled at the same k-mers
+//             as the contamination screen. The hash itself rolls, so each
+//             window costs a few rotates and XORs
+//   splitmix  the 2-bit packed k-mer through the splitmix64 finalizer
+//   fx        one multiply of the packed k-mer (rustc's FxHash); spreads worst
+//   wy        wyhash's 64-bit mix of the packed k-mer
+// The packed k-mer rolls for the other three, but is hashed anew at every window
+// The hashes differ, so spectra sampled (--kmer-scale) with different hashers
+// keep different k-mers; their histograms estimate the same spectrum
+
+use crate::rolling_hash::{self, base_code};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashKind {
+    #[default]
+    Nt,
+    SplitMix,
+    Fx,
+    Wy,
+}
+
+impl HashKind {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "splitmix" => Ok(HashKind::SplitMix),
+            "fx" | "fxhash" => Ok(HashKind::Fx),
+            "wy" | "wyhash" => Ok(HashKind::Wy),
+            "nt" | "nthash" => Ok(HashKind::Nt),
+            _ => Err(format!("Invalid k-mer hash '{}': expected nt, splitmix, fx, or wy", value)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashKind::SplitMix => "splitmix",
+            HashKind::Fx => "fx",
+            HashKind::Wy => "wy",
+            HashKind::Nt => "nt",
+        }
+    }
+}
+
+pub trait KmerHasher {
+    const KIND: HashKind;
+
+    // Calls `f` with the hash of every canonical k-mer of `sequence`, k at most
+    // sketch::MAX_KMER
+    fn for_each(sequence: &[u8], k: usize, f: impl FnMut(u64));
+}
+
+// Calls `f` with `hash` of every canonical k-mer packed 2 bits a base
+fn for_each_packed(sequence: &[u8], k: usize, hash: impl Fn(u64) -> u64, mut f: impl FnMut(u64)) {
+    let mask = (1u64 << (2 * k)) - 1;
+    let shift = 2 * (k as u64 - 1);
+    let (mut forward, mut reverse, mut run) = (0u64, 0u64, 0usize);
+    for &base in sequence {
+        let Some(code) = base_code(base) else {
+            run = 0;
+            continue;
+        };
+        forward = ((forward << 2) | code) & mask;
+        reverse = (reverse >> 2) | ((3 - code) << shift);
+        run += 1;
+        if run >= k {
+            f(hash(forward.min(reverse)));
+        }
+    }
+}
+
+pub struct SplitMix;
+
+fn splitmix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+impl KmerHasher for SplitMix {
+    const KIND: HashKind = HashKind::SplitMix;
+
+    fn for_each(sequence: &[u8], k: usize, f: impl FnMut(u64)) {
+        for_each_packed(sequence, k, splitmix, f);
+    }
+}
+
+pub struct Fx;
+
+const FX_SEED: u64 = 0x517cc1b727220a95;
+
+impl KmerHasher for Fx {
+    const KIND: HashKind = HashKind::Fx;
+
+    fn for_each(sequence: &[u8], k: usize, f: impl FnMut(u64)) {
+        for_each_packed(sequence, k, |kmer| kmer.wrapping_mul(FX_SEED), f);
+    }
+}
+
+pub struct Wy;
+
+const WY_P0: u64 = 0xa0761d6478bd642f;
+const WY_P1: u64 = 0xe7037ed1a0b428db;
+
+// Both halves of the 128-bit product, XORed
+fn wymum(a: u64, b: u64) -> u64 {
+    let product = u128::from(a) * u128::from(b);
+    (product as u64) ^ (product >> 64) as u64
+}
+
+impl KmerHasher for Wy {
+    const KIND: HashKind = HashKind::Wy;
+
+    fn for_each(sequence: &[u8], k: usize, f: impl FnMut(u64)) {
+        for_each_packed(sequence, k, |kmer| wymum(wymum(kmer ^ WY_P0, kmer ^ WY_P1) ^ WY_P0, WY_P1), f);
+    }
+}
+
+pub struct Nt;
+
+impl KmerHasher for Nt {
+    const KIND: HashKind = HashKind::Nt;
+
+    fn for_each(sequence: &[u8], k: usize, mut f: impl FnMut(u64)) {
+        rolling_hash::for_each_kmer(sequence, k, |_, forward, reverse| {
+            f(rolling_hash::canonical(forward, reverse));
+            true
+        });
+    }
+}
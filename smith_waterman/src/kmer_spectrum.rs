@@ -0,0 +1,210 @@
+// `analyze --kmer-spectrum`: per-file k-mer multiplicity histogram
+// Canonical k-mers are sampled as in the contamination screen (sketch.rs,
+// hashes under u64::MAX / scale), and every copy of a k-mer hashes the same,
+// so the sampled k-mers keep their full multiplicity: the histogram is the
+// whole spectrum with every bar about 1/scale as tall. Memory is one counter
+// per distinct sampled k-mer of the current file
+// Low-multiplicity k-mers are mostly sequencing errors; the peak past them is
+// the k-mer coverage of the genome
+// The k-mer hash is a type parameter (kmer_hash.rs, --kmer-hash); the default
+// is the sketches' rolling hash
+// `kmer suggest-k` picks k for a genome size: the smallest k at which a random
+// k-mer is unlikely to occur in the genome by chance (Merqury's rule, log4 of
+// G(1 - p)/p with p = RANDOM_HIT), made odd so no canonical k-mer is its own
+// reverse complement. Each base of a k-mer is a chance for a sequencing error,
+// so the error rate says how many k-mers of that length come out whole
+
+use crate::kmer_hash::{self, HashKind, KmerHasher};
+use crate::sketch::{self, MAX_KMER};
+use crate::pipeline::ChunkProcessor;
+use crate::read_chunk::ReadChunk;
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+
+pub const DEFAULT_KMER: usize = 21;
+pub const DEFAULT_SCALE: u64 = 16;
+
+pub struct KmerSpectrum<H: KmerHasher = kmer_hash::Nt> {
+    hasher: PhantomData<H>,
+    k: usize,
+    max_hash: u64,
+    counts: HashMap<u64, u32>,
+    // Per file: multiplicity -> distinct sampled k-mers
+    files: Vec<(String, BTreeMap<u32, u64>)>,
+    output: std::path::PathBuf,
+}
+
+impl<H: KmerHasher> KmerSpectrum<H> {
+    pub fn new(k: usize, scale: u64, output: impl Into<std::path::PathBuf>) -> Result<Self, String> {
+        if k == 0 || k > MAX_KMER {
+            return Err(format!("Invalid --kmer-spectrum {}: expected 1 to {}", k, MAX_KMER));
+        }
+        if scale == 0 {
+            return Err("Invalid --kmer-scale 0: expected at least 1".to_string());
+        }
+        Ok(Self { hasher: PhantomData, k, max_hash: sketch::max_hash(scale), counts: HashMap::new(), files: Vec::new(), output: output.into() })
+    }
+}
+
+// Most common multiplicity past the valley after the error k-mers, i.e. from
+// the first multiplicity whose bar is lower than the next one's
+fn peak(histogram: &BTreeMap<u32, u64>) -> Option<u32> {
+    let bar = |m: u32| histogram.get(&m).copied().unwrap_or(0);
+    let valley = histogram.keys().copied().find(|&m| bar(m + 1) > bar(m))?;
+    histogram.range(valley..).max_by_key(|&(&m, &kmers)| (kmers, std::cmp::Reverse(m))).map(|(&m, _)| m)
+}
+
+// A spectrum counted with the hasher `kind` names
+pub fn processor(kind: HashKind, k: usize, scale: u64, output: impl Into<std::path::PathBuf>) -> Result<Box<dyn ChunkProcessor>, String> {
+    Ok(match kind {
+        HashKind::Nt => Box::new(KmerSpectrum::<kmer_hash::Nt>::new(k, scale, output)?),
+        HashKind::SplitMix => Box::new(KmerSpectrum::<kmer_hash::SplitMix>::new(k, scale, output)?),
+        HashKind::Fx => Box::new(KmerSpectrum::<kmer_hash::Fx>::new(k, scale, output)?),
+        HashKind::Wy => Box::new(KmerSpectrum::<kmer_hash::Wy>::new(k, scale, output)?),
+    })
+}
+
+impl<H: KmerHasher> ChunkProcessor for KmerSpectrum<H> {
+    fn name(&self) -> &str {
+        "K-mer spectrum"
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        self.counts.clear();
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        for read in 0..chunk.len() {
+            let (counts, max_hash) = (&mut self.counts, self.max_hash);
+            H::for_each(chunk.read(read), self.k, |hash| if hash <= max_hash {
+                *counts.entry(hash).or_insert(0) += 1;
+            });
+        }
+        Ok(())
+    }
+
+    fn finish_file(&mut self, file: &str) -> Result<String, String> {
+        let mut histogram = BTreeMap::new();
+        for &count in self.counts.values() {
+            *histogram.entry(count).or_insert(0u64) += 1;
+        }
+        let total: u64 = histogram.iter().map(|(&m, &kmers)| m as u64 * kmers).sum();
+        let summary = format!("{} distinct sampled {}-mers ({} total, {} hash), {} seen once, peak multiplicity {}",
+                              self.counts.len(), self.k, total, H::KIND.name(), histogram.get(&1).copied().unwrap_or(0),
+                              peak(&histogram).map_or_else(|| "none".to_string(), |m| m.to_string()));
+        self.counts = HashMap::new();
+        self.files.push((file.to_string(), histogram));
+        Ok(summary)
+    }
+
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        let mut tsv = String::from("#file\tmultiplicity\tkmers\n");
+        for (file, histogram) in &self.files {
+            for (multiplicity, kmers) in histogram {
+                tsv.push_str(&format!("{}\t{}\t{}\n", file, multiplicity, kmers));
+            }
+        }
+        std::fs::write(&self.output, tsv).map_err(|e| format!("Failed to write {}: {}", self.output.display(), e))?;
+        Ok(Some(format!("{} files written to {}", self.files.len(), self.output.display())))
+    }
+}
+
+// Chance a random k-mer may have of occurring in the genome for k to count as long enough
+const RANDOM_HIT: f64 = 0.001;
+// Share of k-mers that should be error-free at the suggested k
+const MIN_WHOLE: f64 = 0.5;
+
+// "3g", "4.6M", "120kb", "5000000": bases
+pub fn parse_genome_size(value: &str) -> Result<u64, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches("bp").trim_end_matches('b');
+    let (number, unit) = match digits.chars().last() {
+        Some('k') => (&digits[..digits.len() - 1], 1e3),
+        Some('m') => (&digits[..digits.len() - 1], 1e6),
+        Some('g') => (&digits[..digits.len() - 1], 1e9),
+        _ => (digits, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number > 0.0 && (number * unit).is_finite() && number * unit >= 1.0 => Ok((number * unit).round() as u64),
+        _ => Err(format!("Invalid genome size '{}': expected bases, optionally with k, m or g (e.g. 3g, 4.6m)", value)),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KChoice {
+    pub k: usize,
+    // Expected occurrences of a random k-mer in the genome
+    pub random_hits: f64,
+    // Share of k-mers with no sequencing error
+    pub whole: f64,
+    // Share of a read's bases that start a k-mer: k-mer coverage over base coverage
+    pub coverage_factor: f64,
+}
+
+impl KChoice {
+    fn new(k: usize, genome_size: u64, error_rate: f64, read_length: usize) -> Self {
+        Self {
+            k,
+            random_hits: genome_size as f64 / 4f64.powi(k as i32),
+            whole: (1.0 - error_rate).powi(k as i32),
+            coverage_factor: read_length.saturating_sub(k - 1) as f64 / read_length as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KSuggestion {
+    pub genome_size: u64,
+    pub error_rate: f64,
+    pub read_length: usize,
+    // Smallest k that passes RANDOM_HIT, before rounding to odd and capping at MAX_KMER
+    pub min_unique_k: usize,
+    pub suggested: KChoice,
+    // Odd k from MIN_TABLE_K to MAX_KMER, for comparison
+    pub table: Vec<KChoice>,
+    pub warnings: Vec<String>,
+}
+
+const MIN_TABLE_K: usize = 11;
+
+pub fn suggest_k(genome_size: u64, error_rate: f64, read_length: usize) -> Result<KSuggestion, String> {
+    if !(0.0..1.0).contains(&error_rate) {
+        return Err(format!("Invalid --error-rate {}: expected at least 0 and under 1", error_rate));
+    }
+    if read_length == 0 {
+        return Err("Invalid --read-length 0: expected at least 1 base".to_string());
+    }
+    let min_unique_k = ((genome_size as f64 * (1.0 - RANDOM_HIT) / RANDOM_HIT).ln() / 4f64.ln()).ceil().max(1.0) as usize;
+    let k = (min_unique_k | 1).min(MAX_KMER);
+    let suggested = KChoice::new(k, genome_size, error_rate, read_length);
+    let mut warnings = Vec::new();
+    if min_unique_k > MAX_KMER {
+        warnings.push(format!("the genome needs k of {} for k-mers to be unique, over the {} the k-mer modes support; repeats will be overcounted", min_unique_k, MAX_KMER));
+    }
+    if suggested.whole < MIN_WHOLE {
+        warnings.push(format!("at {:.2}% errors only {:.0}% of {}-mers are error-free; correct the reads first, or expect a large error peak", error_rate * 100.0, suggested.whole * 100.0, k));
+    }
+    if read_length < k {
+        warnings.push(format!("{}-base reads are shorter than k; they have no k-mers", read_length));
+    } else if suggested.coverage_factor < 0.5 {
+        warnings.push(format!("{}-base reads give k-mer coverage of only {:.0}% of the base coverage at k {}", read_length, suggested.coverage_factor * 100.0, k));
+    }
+    let table = (MIN_TABLE_K..=MAX_KMER).step_by(2).map(|k| KChoice::new(k, genome_size, error_rate, read_length)).collect();
+    Ok(KSuggestion { genome_size, error_rate, read_length, min_unique_k, suggested, table, warnings })
+}
+
+impl KSuggestion {
+    pub fn print(&self) {
+        println!("Genome size {} bases, {:.3}% errors, {}-base reads", self.genome_size, self.error_rate * 100.0, self.read_length);
+        println!("{:>4}  {:>14}  {:>10}  {:>12}", "k", "random hits", "error-free", "kmer cov/cov");
+        for choice in &self.table {
+            println!("{:>4}  {:>14.3e}  {:>9.1}%  {:>12.2}{}", choice.k, choice.random_hits, choice.whole * 100.0, choice.coverage_factor,
+                     if choice.k == self.suggested.k { "  <- suggested" } else { "" });
+        }
+        println!("Suggested k: {} (k-mers unique from k {}; {:.1}% error-free)", self.suggested.k, self.min_unique_k, self.suggested.whole * 100.0);
+        for warning in &self.warnings {
+            println!("Warning: {}", warning);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+// Launch reuse: the fixed cost of a launch, cut for small chunks
+// A launch used to build its Kernel (clCreateKernel and every argument set
+// again) and upload both sequences with blocking writes, so the host waited on
+// the device three times per launch: each write, then the result read. With
+// chunks of a few KB that fixed cost was most of a launch. Now:
+//   - one kernel per program, variant and work group size is built on first use
+//     and kept; a launch sets its buffer and length arguments (set_arg) and
+//     enqueues it. The local memory arguments depend only on the work group
+//     size, so they are set once. The kernel is locked from its first set_arg
+//     to its enqueue, since OpenCL kernel arguments aren't safe to set from
+//     several threads at once (LaunchPool and library threads share a program)
+//   - the uploads don't block: the launch keeps its host copies until it is
+//     waited for, the kernel waits on their events and the result read on the
+//     kernel's, and the host waits once, on the read
+// The queue is in order, so the events change no ordering; they keep each
+// launch's dependencies explicit for queues that aren't. A cached kernel holds
+// its program, so a program that has launched stays built for the process
+// (as GpuContext and Aligner already keep theirs)
+// set_enabled(false) goes back to building a kernel and blocking on every
+// upload, which `bench launch` measures against
+
+use crate::gpu::GpuDevice;
+use crate::gpu_timing;
+use crate::kernels::KernelVariant;
+use crate::seed::Seedable;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub const DEFAULT_BENCH_BASES: usize = 4096;
+pub const DEFAULT_BENCH_LAUNCHES: usize = 1000;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+struct CachedKernel {
+    // The program's cl_program, which the kernel keeps alive, so it is never
+    // reused by another program while the entry exists
+    program: usize,
+    variant: KernelVariant,
+    work_group_size: usize,
+    kernel: Arc<Mutex<ocl::Kernel>>,
+}
+
+static KERNELS: Lazy<Mutex<Vec<CachedKernel>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// The kept kernel of `variant` in `program` for work groups of `work_group_size`,
+// built by `build` the first time
+pub fn kernel(program: &ocl::Program, variant: KernelVariant, work_group_size: usize,
+              build: impl FnOnce() -> Result<ocl::Kernel, String>) -> Result<Arc<Mutex<ocl::Kernel>>, String> {
+    let key = program.as_core().as_ptr() as usize;
+    let mut kernels = KERNELS.lock().map_err(|e| format!("Failed to acquire kernel cache lock: {}", e))?;
+    if let Some(cached) = kernels.iter().find(|cached| cached.program == key && cached.variant == variant && cached.work_group_size == work_group_size) {
+        return Ok(cached.kernel.clone());
+    }
+    let kernel = Arc::new(Mutex::new(build()?));
+    kernels.push(CachedKernel { program: key, variant, work_group_size, kernel: kernel.clone() });
+    Ok(kernel)
+}
+
+// One configuration's launches in `bench launch`
+#[derive(Debug, Clone)]
+pub struct LaunchOverhead {
+    pub mode: &'static str,
+    // Means per launch, in microseconds
+    pub wall_us: f64,
+    pub kernel_us: f64,
+    pub transfer_us: f64,
+    // Wall time outside the device's transfers and kernel: building the
+    // kernel, setting arguments, enqueueing, waiting on the host
+    pub host_us: f64,
+}
+
+impl LaunchOverhead {
+    pub fn launches_per_sec(&self) -> f64 {
+        if self.wall_us > 0.0 { 1e6 / self.wall_us } else { 0.0 }
+    }
+}
+
+// `bench launch`: `launches` launches of one pair of `bases` bases on the shared
+// context, with kernels rebuilt and blocking uploads, then with kept kernels and
+// event-driven uploads. Each configuration gets an untimed warm-up launch, and
+// both must score the same
+pub fn bench(device: &GpuDevice, bases: usize, launches: usize) -> Result<Vec<LaunchOverhead>, String> {
+    let mut rng = Seedable::stream("bench launch");
+    let seq1 = crate::kernels::test_sequence(bases, &mut rng, 0, &[]);
+    let seq2 = crate::kernels::test_sequence(bases, &mut rng, 7, &seq1);
+    let was_enabled = enabled();
+    let mut results = Vec::new();
+    let mut score = None;
+    for (mode, reuse) in [("rebuilt", false), ("reused", true)] {
+        set_enabled(reuse);
+        let measured = measure(mode, &seq1, &seq2, device, launches, &mut score);
+        set_enabled(was_enabled);
+        results.push(measured?);
+    }
+    Ok(results)
+}
+
+fn measure(mode: &'static str, seq1: &[u8], seq2: &[u8], device: &GpuDevice, launches: usize, score: &mut Option<i32>) -> Result<LaunchOverhead, String> {
+    let first = crate::aligner::gpu_align_bytes(seq1, seq2, device)?;
+    if let Some(score) = score.filter(|&score| score != first) {
+        return Err(format!("{} launches scored {}, rebuilt ones {}", mode, first, score));
+    }
+    *score = Some(first);
+    let before = gpu_timing::summary();
+    let start = Instant::now();
+    for _ in 0..launches.max(1) {
+        crate::aligner::gpu_align_bytes(seq1, seq2, device)?;
+    }
+    let wall = start.elapsed();
+    let after = gpu_timing::summary();
+    let count = (after.launches - before.launches).max(1) as f64;
+    Ok(LaunchOverhead {
+        mode,
+        wall_us: wall.as_secs_f64() * 1e6 / count,
+        kernel_us: (after.kernel_ms - before.kernel_ms) * 1000.0 / count,
+        transfer_us: (after.transfer_ms - before.transfer_ms) * 1000.0 / count,
+        host_us: (after.host_ms - before.host_ms) * 1000.0 / count,
+    })
+}
+
+pub fn print(results: &[LaunchOverhead]) {
+    let Some(rebuilt) = results.first() else { return };
+    println!("{:<8} {:>10} {:>10} {:>12} {:>10} {:>12} {:>8}", "mode", "wall us", "host us", "transfer us", "kernel us", "launches/s", "speedup");
+    for result in results {
+        let speedup = if result.wall_us > 0.0 { rebuilt.wall_us / result.wall_us } else { 0.0 };
+        println!("{:<8} {:>10.1} {:>10.1} {:>12.1} {:>10.1} {:>12.0} {:>7.2}x",
+                 result.mode, result.wall_us, result.host_us, result.transfer_us, result.kernel_us, result.launches_per_sec(), speedup);
+    }
+}
@@ -0,0 +1,205 @@
+// `simulate learn-profile`: an error profile learned from a real FASTQ, so
+// simulated reads look like the user's instrument rather than a built-in curve
+// From the first --max-reads reads it records, per cycle (base position in the
+// read), the distribution of base qualities and the share of N calls, and the
+// read length distribution as percentiles. Cycles past MAX_CYCLES share the last
+// one's distributions, which keeps long-read profiles small
+// Miscalls follow from the qualities (substitution scale 1), as in the built-in
+// profiles: without a reference there are no true bases to count real errors
+// against. Indels can't be seen either, so their rates come from a built-in
+// profile (--indels-from). simulate from-reference takes the JSON file as its
+// --error-profile and then
+//   - draws each base's quality from its cycle's distribution, so the shape of
+//     the curve, the spread and quality binning carry over as they were
+//   - calls the base N at its cycle's rate, with quality 2 (#)
+//   - draws each read's length from the percentiles, unless --read-length is given
+
+use crate::fastq::{FastqBatch, FastqChunks};
+use crate::seed::Seedable;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MAX_READS: usize = 1_000_000;
+pub const MAX_CYCLES: usize = 1000;
+const MAX_QUALITY: usize = 93;
+const PHRED_OFFSET: u8 = 33;
+// Quality written with an N call, as Illumina instruments do
+pub const N_QUALITY: u8 = 2;
+const BATCH_READS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LearnedProfile {
+    // See schema.rs
+    #[serde(default)]
+    pub schema_version: u32,
+    pub source: String,
+    pub reads: usize,
+    pub bases: usize,
+    // Read length at each percentile from 0 (shortest) to 100 (longest)
+    pub length_percentiles: Vec<usize>,
+    // Per cycle: each quality seen on a called base, with its share of them
+    pub qualities: Vec<Vec<(u8, f64)>>,
+    // Per cycle: share of bases called N
+    pub n_rates: Vec<f64>,
+    // From the --indels-from profile
+    pub indels_from: String,
+    pub insertion_rate: f64,
+    pub deletion_rate: f64,
+}
+
+impl LearnedProfile {
+    fn cycle(&self, cycle: usize) -> usize {
+        cycle.min(self.qualities.len().saturating_sub(1))
+    }
+
+    pub fn longest(&self) -> usize {
+        self.length_percentiles.last().copied().unwrap_or(0)
+    }
+
+    pub fn median_length(&self) -> usize {
+        self.length_percentiles.get(50).copied().unwrap_or(0)
+    }
+
+    // Mean quality of the called bases of a cycle
+    pub fn mean_quality(&self, cycle: usize) -> f64 {
+        self.qualities.get(self.cycle(cycle)).map_or(0.0, |shares| shares.iter().map(|&(q, share)| q as f64 * share).sum())
+    }
+
+    // Quality of a called base at `cycle`, drawn from its distribution
+    pub fn draw_quality(&self, cycle: usize, rng: &mut Seedable) -> u8 {
+        let Some(shares) = self.qualities.get(self.cycle(cycle)) else {
+            return N_QUALITY;
+        };
+        let mut left = rng.next_f64();
+        for &(quality, share) in shares {
+            if left < share {
+                return quality;
+            }
+            left -= share;
+        }
+        shares.last().map_or(N_QUALITY, |&(quality, _)| quality)
+    }
+
+    pub fn n_rate(&self, cycle: usize) -> f64 {
+        self.n_rates.get(self.cycle(cycle)).copied().unwrap_or(0.0)
+    }
+
+    // A read length drawn from the percentiles, interpolating between them
+    pub fn draw_length(&self, rng: &mut Seedable) -> usize {
+        let steps = self.length_percentiles.len().saturating_sub(1);
+        if steps == 0 {
+            return self.longest();
+        }
+        let position = rng.next_f64() * steps as f64;
+        let (low, high) = (self.length_percentiles[position as usize], self.length_percentiles[(position as usize + 1).min(steps)]);
+        (low as f64 + (high as f64 - low as f64) * position.fract()).round().max(1.0) as usize
+    }
+
+    // The distinct qualities when there are few of them (binned instruments)
+    pub fn quality_bins(&self) -> Option<Vec<u8>> {
+        let mut seen: Vec<u8> = self.qualities.iter().flatten().map(|&(q, _)| q).collect();
+        seen.sort_unstable();
+        seen.dedup();
+        (seen.len() <= 8).then_some(seen)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read error profile {}: {}", path, e))?;
+        let profile: Self = crate::schema::parse(&crate::schema::ERROR_PROFILE, &json, path)?;
+        if profile.qualities.is_empty() || profile.length_percentiles.is_empty() {
+            return Err(format!("Error profile {} has no cycles; learn it again with simulate learn-profile", path));
+        }
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize error profile: {}", e))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    pub fn print(&self) {
+        let cycles = self.qualities.len();
+        println!("Learned from {} reads ({} bases) of {}", self.reads, self.bases, self.source);
+        println!("   Read length: {} to {}, median {}", self.length_percentiles[0], self.longest(), self.median_length());
+        println!("   Mean quality: Q{:.1} at cycle 1, Q{:.1} at cycle {}{}", self.mean_quality(0), self.mean_quality(cycles - 1), cycles,
+                 if cycles == MAX_CYCLES { " (and later cycles)" } else { "" });
+        if let Some(bins) = self.quality_bins() {
+            let bins: Vec<String> = bins.iter().map(u8::to_string).collect();
+            println!("   Qualities binned to {}", bins.join("/"));
+        }
+        let n_calls = self.n_rates.iter().sum::<f64>() / cycles as f64;
+        println!("   N calls: {:.3}% of bases, up to {:.3}% in one cycle", n_calls * 100.0,
+                 self.n_rates.iter().copied().fold(0.0, f64::max) * 100.0);
+        println!("   Indels: {:.4}% insertions, {:.4}% deletions per base (from {})", self.insertion_rate * 100.0, self.deletion_rate * 100.0, self.indels_from);
+    }
+}
+
+// Learn a profile from the first `max_reads` reads of a FASTQ (plain or compressed)
+pub fn learn(path: &str, max_reads: usize, indels_from: &crate::simulate::ErrorProfile) -> Result<LearnedProfile, String> {
+    if max_reads == 0 {
+        return Err("Invalid --max-reads 0: expected at least 1".to_string());
+    }
+    let mut chunks = FastqChunks::open(path, BATCH_READS)
+        .map_err(|e| format!("{}; learn-profile needs FASTQ qualities", e))?;
+    let mut counts: Vec<[u64; MAX_QUALITY + 1]> = Vec::new();
+    let mut n_calls: Vec<u64> = Vec::new();
+    let mut lengths: Vec<usize> = Vec::new();
+    let mut bases = 0;
+    let mut batch = FastqBatch::with_capacity(BATCH_READS);
+    'reading: while chunks.next_into(&mut batch)? {
+        for record in batch.iter() {
+            if lengths.len() == max_reads {
+                break 'reading;
+            }
+            if record.seq.len() != record.qual.len() {
+                return Err(format!("{}: read {} has {} bases but {} qualities", path, record.id_str(), record.seq.len(), record.qual.len()));
+            }
+            let cycles = record.seq.len().min(MAX_CYCLES);
+            if counts.len() < cycles {
+                counts.resize(cycles, [0; MAX_QUALITY + 1]);
+                n_calls.resize(cycles, 0);
+            }
+            for (cycle, (&base, &quality)) in record.seq.iter().zip(record.qual).enumerate() {
+                let cycle = cycle.min(MAX_CYCLES - 1);
+                if base == b'N' || base == b'n' {
+                    n_calls[cycle] += 1;
+                    continue;
+                }
+                let quality = quality.checked_sub(PHRED_OFFSET)
+                    .ok_or_else(|| format!("{}: read {} has a quality below '!'; only Phred+33 is read", path, record.id_str()))?;
+                counts[cycle][(quality as usize).min(MAX_QUALITY)] += 1;
+            }
+            bases += record.seq.len();
+            lengths.push(record.seq.len());
+        }
+    }
+    if counts.is_empty() {
+        return Err(format!("{} has no reads with bases", path));
+    }
+
+    lengths.sort_unstable();
+    let length_percentiles = (0..=100).map(|p| lengths[(lengths.len() - 1) * p / 100]).collect();
+    let qualities = counts.iter()
+        .map(|counts| {
+            let called = counts.iter().sum::<u64>().max(1) as f64;
+            counts.iter().enumerate()
+                .filter(|(_, &count)| count > 0)
+                .map(|(quality, &count)| (quality as u8, count as f64 / called))
+                .collect()
+        })
+        .collect();
+    let n_rates = counts.iter().zip(&n_calls)
+        .map(|(counts, &n)| n as f64 / (counts.iter().sum::<u64>() + n).max(1) as f64)
+        .collect();
+    Ok(LearnedProfile {
+        schema_version: crate::schema::ERROR_PROFILE.version(),
+        source: path.to_string(),
+        reads: lengths.len(),
+        bases,
+        length_percentiles,
+        qualities,
+        n_rates,
+        indels_from: indels_from.name.to_string(),
+        insertion_rate: indels_from.insertion_rate,
+        deletion_rate: indels_from.deletion_rate,
+    })
+}
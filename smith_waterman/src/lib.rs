@@ -0,0 +1,134 @@
+// Library interface for genome-scale sequence alignment
+// The rustseq_mini binary is a thin CLI over these modules
+
+pub mod gpu;
+pub mod gpu_timing;
+pub mod file_timing;
+pub mod gpu_share;
+pub mod gpu_queues;
+pub mod gpu_scheduler;
+pub mod planner;
+pub mod kernels;
+pub mod kernel_test;
+pub mod launch_reuse;
+pub mod tools;
+pub mod aligner;
+pub mod engine;
+pub mod backend_ladder;
+pub mod run_control;
+pub mod system_info;
+pub mod perf_logger;
+pub mod async_input;
+pub mod stages;
+pub mod memory_profile;
+pub mod watchdog;
+pub mod mmap_input;
+pub mod input_format;
+pub mod parallel_gzip;
+pub mod packed_reads;
+pub mod in_memory;
+pub mod alignment_input;
+pub mod assembly_compare;
+pub mod ani;
+pub mod read_binning;
+pub mod low_complexity;
+pub mod sanitize;
+pub mod long_read_trim;
+pub mod read_trim;
+pub mod long_reads;
+pub mod read_chunk;
+pub mod read_group;
+pub mod read_output;
+pub mod output_sink;
+pub mod parquet_output;
+pub mod read_store;
+pub mod schema;
+pub mod reference_cache;
+pub mod resident_target;
+pub mod fastq;
+pub mod simulate;
+pub mod learned_profile;
+pub mod evaluate;
+pub mod affinity;
+pub mod cpu_align;
+pub mod scoring;
+pub mod score_matrix;
+pub mod scorer;
+pub mod aggregation;
+pub mod presets;
+pub mod n_policy;
+pub mod splice;
+pub mod protein_matrix;
+pub mod translated;
+pub mod ungapped;
+pub mod sequence_db;
+pub mod query_batcher;
+pub mod serve;
+pub mod align_pairs;
+pub mod overlap;
+pub mod parity;
+pub mod soak;
+pub mod hit_limits;
+pub mod msa;
+pub mod distances;
+pub mod pssm;
+pub mod edit_distance;
+pub mod seq_transform;
+pub mod primer_scan;
+pub mod barcode_correction;
+pub mod contamination;
+pub mod rolling_hash;
+pub mod sketch;
+pub mod contig_chunks;
+pub mod chromosome_walk;
+pub mod pair_stats;
+pub mod coverage_track;
+pub mod bigwig;
+pub mod flow_cell;
+pub mod qc;
+pub mod kmer_hash;
+pub mod kmer_spectrum;
+pub mod pipeline;
+pub mod score_metrics;
+pub mod run_cost;
+pub mod wgs_summary;
+pub mod wgs_report;
+pub mod wgs_config;
+pub mod batch;
+pub mod sweep;
+pub mod chunk_latency;
+pub mod chunking;
+pub mod chunk_audit;
+pub mod adaptive_chunks;
+pub mod determinism;
+pub mod seed;
+pub mod file_selection;
+pub mod file_order;
+pub mod tui;
+pub mod notify;
+pub mod preemption;
+pub mod cancel_request;
+pub mod output_layout;
+pub mod paths;
+pub mod remote_state;
+pub mod resume;
+pub mod checkpoint_store;
+pub mod quarantine;
+pub mod run_registry;
+pub mod run_diff;
+pub mod run_context;
+pub mod exit_codes;
+pub mod warnings;
+pub mod metrics_sampler;
+pub mod profiler;
+#[cfg(target_os = "linux")]
+pub mod direct_io;
+#[cfg(wgpu_backend)]
+pub mod wgpu_align;
+
+pub use engine::{AlignParams, Aligner, AlignerBuilder, Backend};
+pub use fastq::{FastqBatch, FastqChunks, FastqRecord};
+pub use read_chunk::{ReadChunk, ReadName, ReadNameMode};
+pub use run_context::RunContext;
+pub use run_control::{CancellationToken, Progress, ProgressStage};
+pub use wgs_report::{FileReport, FileStatus, WgsRunReport};
@@ -0,0 +1,345 @@
+// --trim-long-reads: preprocessing of ONT and PacBio reads before alignment
+// Long reads carry sequencing adapters at their ends, cDNA reads a poly-A tail
+// (poly-T at the start when read from the other strand), and reads from
+// chromosome ends runs of the telomeric repeat. All of them align to
+// themselves far better than genomic sequence and inflate chunk scores, so
+// they are trimmed off in this order:
+// - adapter: the adapter's start sequence within the first END_WINDOW bases
+//   and its end sequence within the last END_WINDOW, found with a semi-global
+//   edit distance and accepted at ADAPTER_IDENTITY or better, as long reads
+//   have too many errors for exact matching. Everything up to the start
+//   adapter and from the end adapter on is removed
+// - poly-a: the best-scoring run of A at the 3' end and of T at the 5' end
+//   (+1 for the base, -2 for any other), if at least MIN_POLY_A long
+// - telomere: the best-scoring run at either end where the hexamer at each
+//   position is a rotation of TTAGGG or CCCTAA (+1 if it is, -1 if not), if at
+//   least MIN_TELOMERE long; scoring hexamers rather than a fixed phase keeps
+//   a run going over the indels long reads have
+// Reads shorter than the minimum length after trimming are dropped. Read
+// lengths before and after are kept for the per-file and run statistics
+// (mean, N50, longest)
+
+use crate::alignment_input::complement_base;
+use crate::edit_distance::{encode_pattern, encode_text, pair_distance, Mode};
+use crate::read_chunk::ReadChunk;
+
+// Bases at either end of a read searched for adapters
+const END_WINDOW: usize = 200;
+const ADAPTER_IDENTITY: f64 = 0.8;
+const MIN_POLY_A: usize = 10;
+// Six repeats
+const MIN_TELOMERE: usize = 36;
+
+// Porechop's ligation and rapid kit adapters and the PacBio SMRTbell hairpin
+pub const DEFAULT_ADAPTERS: [(&str, &str, &str); 3] = [
+    ("ONT ligation (SQK-LSK)", "AATGTACTTCGTTCAGTTACGTATTGCT", "GCAATACGTAACTGAACGAAGT"),
+    ("ONT rapid (SQK-RAD)", "GTTTTCGCATTTATCGTGAAACGCTTTCGCGTTTTTCGTGCGCCGCTTCA", ""),
+    ("PacBio SMRTbell", "ATCTCTCTCAACAACAACAACGGAGGAGGAGGAAAAGAGAGAGAT", "ATCTCTCTCAACAACAACAACGGAGGAGGAGGAAAAGAGAGAGAT"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LongReadAdapter {
+    pub name: String,
+    // Found at the 5' end; empty when the kit only adds one at the 3' end
+    pub start: Vec<u8>,
+    // Found at the 3' end; empty when the kit only adds one at the 5' end
+    pub end: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrimOptions {
+    pub adapters: Option<Vec<LongReadAdapter>>,
+    pub poly_a: bool,
+    pub telomere: bool,
+    // Reads shorter than this after trimming are dropped; empty reads always are
+    pub min_length: usize,
+}
+
+impl TrimOptions {
+    // "all", or a comma-separated list of adapter, poly-a and telomere
+    pub fn parse(value: &str, min_length: usize) -> Result<Self, String> {
+        let mut options = Self { adapters: None, poly_a: false, telomere: false, min_length: min_length.max(1) };
+        for kind in value.split(',').map(|kind| kind.trim().to_ascii_lowercase()) {
+            match kind.as_str() {
+                "all" => (options.adapters, options.poly_a, options.telomere) = (Some(default_adapters()), true, true),
+                "adapter" | "adapters" => options.adapters = Some(default_adapters()),
+                "poly-a" | "polya" => options.poly_a = true,
+                "telomere" | "telomeres" => options.telomere = true,
+                _ => return Err(format!("Invalid --trim-long-reads '{}': expected all, or a comma-separated list of adapter, poly-a and telomere", value)),
+            }
+        }
+        Ok(options)
+    }
+
+    // LONG_READ_ADAPTERS (a FASTA path) replaces the built-in adapters
+    pub fn with_env_adapters(mut self) -> Result<Self, String> {
+        if let (Some(adapters), Ok(path)) = (self.adapters.as_mut(), std::env::var("LONG_READ_ADAPTERS")) {
+            *adapters = load_adapters(path.trim())?;
+        }
+        Ok(self)
+    }
+
+    // What is trimmed, for logs and the checkpoint's run parameters
+    pub fn name(&self) -> String {
+        let mut kinds = Vec::new();
+        if let Some(adapters) = &self.adapters {
+            kinds.push(format!("adapter ({})", adapters.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", ")));
+        }
+        if self.poly_a {
+            kinds.push("poly-a".to_string());
+        }
+        if self.telomere {
+            kinds.push("telomere".to_string());
+        }
+        format!("{}, min length {}", kinds.join(", "), self.min_length)
+    }
+}
+
+pub fn default_adapters() -> Vec<LongReadAdapter> {
+    DEFAULT_ADAPTERS.iter()
+        .map(|(name, start, end)| LongReadAdapter { name: name.to_string(), start: start.as_bytes().to_vec(), end: end.as_bytes().to_vec() })
+        .collect()
+}
+
+// Adapters from a FASTA file, one record each: the record is the start
+// adapter and its reverse complement the end adapter
+pub fn load_adapters(path: &str) -> Result<Vec<LongReadAdapter>, String> {
+    let adapters = crate::simulate::load_reference(path)?;
+    if adapters.is_empty() {
+        return Err(format!("No adapters in {}", path));
+    }
+    if let Some((name, _)) = adapters.iter().find(|(_, seq)| seq.is_empty()) {
+        return Err(format!("Adapter {} in {} has no sequence", name, path));
+    }
+    Ok(adapters.into_iter()
+        .map(|(name, start)| {
+            let end = start.iter().rev().map(|&b| complement_base(b)).collect();
+            LongReadAdapter { name, start, end }
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrimStats {
+    pub reads: usize,
+    pub trimmed_reads: usize,
+    pub dropped_reads: usize,
+    pub adapter_reads: usize,
+    pub adapter_bases: usize,
+    pub poly_a_reads: usize,
+    pub poly_a_bases: usize,
+    pub telomere_reads: usize,
+    pub telomere_bases: usize,
+    lengths_before: Vec<u32>,
+    // Kept reads only
+    lengths_after: Vec<u32>,
+}
+
+// Mean, N50 and longest of a set of read lengths
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LengthSummary {
+    pub reads: usize,
+    pub bases: u64,
+    pub mean: f64,
+    pub n50: u32,
+    pub longest: u32,
+}
+
+impl LengthSummary {
+    fn of(lengths: &[u32]) -> Self {
+        let mut sorted = lengths.to_vec();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        let bases: u64 = sorted.iter().map(|&l| l as u64).sum();
+        let mut covered = 0u64;
+        let n50 = sorted.iter().find(|&&l| {
+            covered += l as u64;
+            covered * 2 >= bases
+        }).copied().unwrap_or(0);
+        Self {
+            reads: sorted.len(),
+            bases,
+            mean: bases as f64 / sorted.len().max(1) as f64,
+            n50,
+            longest: sorted.first().copied().unwrap_or(0),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("{} reads, {} bases, mean {:.0}, N50 {}, longest {}", self.reads, self.bases, self.mean, self.n50, self.longest)
+    }
+}
+
+impl TrimStats {
+    pub fn add(&mut self, other: &TrimStats) {
+        self.reads += other.reads;
+        self.trimmed_reads += other.trimmed_reads;
+        self.dropped_reads += other.dropped_reads;
+        self.adapter_reads += other.adapter_reads;
+        self.adapter_bases += other.adapter_bases;
+        self.poly_a_reads += other.poly_a_reads;
+        self.poly_a_bases += other.poly_a_bases;
+        self.telomere_reads += other.telomere_reads;
+        self.telomere_bases += other.telomere_bases;
+        self.lengths_before.extend_from_slice(&other.lengths_before);
+        self.lengths_after.extend_from_slice(&other.lengths_after);
+    }
+
+    pub fn before(&self) -> LengthSummary {
+        LengthSummary::of(&self.lengths_before)
+    }
+
+    pub fn after(&self) -> LengthSummary {
+        LengthSummary::of(&self.lengths_after)
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{} reads: {} trimmed, {} dropped; removed {} adapter bases ({} reads), {} poly-A bases ({} reads), {} telomeric bases ({} reads)\n      before: {}\n      after:  {}",
+                self.reads, self.trimmed_reads, self.dropped_reads, self.adapter_bases, self.adapter_reads,
+                self.poly_a_bases, self.poly_a_reads, self.telomere_bases, self.telomere_reads,
+                self.before().describe(), self.after().describe())
+    }
+}
+
+pub struct Trimmer {
+    options: TrimOptions,
+    // Adapters encoded for edit_distance, as (start, end)
+    encoded: Vec<(Vec<u8>, Vec<u8>)>,
+    text: Vec<u8>,
+}
+
+impl Trimmer {
+    pub fn new(options: TrimOptions) -> Self {
+        let encode = |seq: &[u8]| seq.iter().map(|&b| encode_pattern(b)).collect::<Vec<u8>>();
+        let encoded = options.adapters.iter().flatten().map(|a| (encode(&a.start), encode(&a.end))).collect();
+        Self { options, encoded, text: Vec::new() }
+    }
+
+    // Copy the trimmed reads of `chunk` that are long enough into `out`,
+    // adding what was removed to `stats`
+    pub fn trim_into(&mut self, chunk: &ReadChunk, out: &mut ReadChunk, stats: &mut TrimStats) {
+        out.clear();
+        for i in 0..chunk.len() {
+            let read = chunk.read(i);
+            stats.reads += 1;
+            stats.lengths_before.push(read.len() as u32);
+            let (mut start, mut end) = (0, read.len());
+
+            if self.options.adapters.is_some() {
+                let (adapter_start, adapter_end) = self.adapter_bounds(read);
+                if adapter_start > 0 || adapter_end < read.len() {
+                    stats.adapter_reads += 1;
+                    stats.adapter_bases += adapter_start + (read.len() - adapter_end.max(adapter_start));
+                }
+                (start, end) = (adapter_start, adapter_end.max(adapter_start));
+            }
+            if self.options.poly_a && start < end {
+                let tail = run_length(read[start..end].iter().rev(), |&b| b.eq_ignore_ascii_case(&b'A'));
+                let head = run_length(read[start..end].iter(), |&b| b.eq_ignore_ascii_case(&b'T'));
+                let (head, tail) = (head.filter(|&n| n >= MIN_POLY_A).unwrap_or(0), tail.filter(|&n| n >= MIN_POLY_A).unwrap_or(0));
+                let removed = (head + tail).min(end - start);
+                if removed > 0 {
+                    stats.poly_a_reads += 1;
+                    stats.poly_a_bases += removed;
+                    start = (start + head).min(end);
+                    end = end.saturating_sub(tail).max(start);
+                }
+            }
+            if self.options.telomere && start < end {
+                let removed = telomere_ends(&read[start..end]);
+                if removed.0 + removed.1 > 0 {
+                    stats.telomere_reads += 1;
+                    stats.telomere_bases += (removed.0 + removed.1).min(end - start);
+                    start = (start + removed.0).min(end);
+                    end = end.saturating_sub(removed.1).max(start);
+                }
+            }
+
+            if (start, end) != (0, read.len()) {
+                stats.trimmed_reads += 1;
+            }
+            if end - start < self.options.min_length {
+                stats.dropped_reads += 1;
+                continue;
+            }
+            stats.lengths_after.push((end - start) as u32);
+            out.push_range(chunk, i, start..end);
+        }
+    }
+
+    // [start, end) of a read once its adapters are removed
+    fn adapter_bounds(&mut self, read: &[u8]) -> (usize, usize) {
+        let (mut start, mut end) = (0, read.len());
+        let head = &read[..read.len().min(END_WINDOW)];
+        let tail_offset = read.len().saturating_sub(END_WINDOW);
+        for (adapter_start, adapter_end) in &self.encoded {
+            if let Some(hit) = find_adapter(adapter_start, head, &mut self.text) {
+                start = start.max(hit.1);
+            }
+            if let Some(hit) = find_adapter(adapter_end, &read[tail_offset..], &mut self.text) {
+                end = end.min(tail_offset + hit.0);
+            }
+        }
+        (start, end)
+    }
+}
+
+// [start, end) of the adapter's best placement in `window`, if close enough
+fn find_adapter(adapter: &[u8], window: &[u8], text: &mut Vec<u8>) -> Option<(usize, usize)> {
+    if adapter.is_empty() || window.len() < adapter.len() / 2 {
+        return None;
+    }
+    text.clear();
+    text.extend(window.iter().map(|&b| encode_text(b)));
+    let hit = pair_distance(adapter, text, Mode::SemiGlobal);
+    let max_edits = (adapter.len() as f64 * (1.0 - ADAPTER_IDENTITY)).floor() as u32;
+    (hit.distance <= max_edits).then_some((hit.start as usize, hit.end as usize))
+}
+
+// Length of the best-scoring run from the start of `bases`: +1 for a base
+// `in_run` accepts, -2 for any other; None if there is none
+fn run_length<'a>(bases: impl Iterator<Item = &'a u8>, in_run: impl Fn(&u8) -> bool) -> Option<usize> {
+    let (mut score, mut best, mut length) = (0i64, 0i64, None);
+    for (n, base) in bases.enumerate() {
+        score += if in_run(base) { 1 } else { -2 };
+        if score > best {
+            (best, length) = (score, Some(n + 1));
+        }
+        // Far below the best run: it can't be extended any more
+        if score < best - 2 * MIN_POLY_A as i64 {
+            break;
+        }
+    }
+    length
+}
+
+// Bases of telomeric repeat at the start and at the end of a read
+fn telomere_ends(read: &[u8]) -> (usize, usize) {
+    if read.len() < MIN_TELOMERE {
+        return (0, 0);
+    }
+    let head = telomere_run(read.windows(6));
+    let tail = telomere_run(read.windows(6).rev());
+    (if head >= MIN_TELOMERE { head } else { 0 }, if tail >= MIN_TELOMERE { tail } else { 0 })
+}
+
+// Bases covered by the best-scoring run of telomeric hexamers from the first one
+fn telomere_run<'a>(hexamers: impl Iterator<Item = &'a [u8]>) -> usize {
+    let (mut score, mut best, mut length) = (0i64, 0i64, 0usize);
+    for (n, hexamer) in hexamers.enumerate() {
+        score += if is_telomeric_hexamer(hexamer) { 1 } else { -1 };
+        if score > best {
+            (best, length) = (score, n + 1);
+        }
+        if score < best - MIN_TELOMERE as i64 {
+            break;
+        }
+    }
+    // Hexamers at 0..length cover 5 more bases
+    if length == 0 { 0 } else { length + 5 }
+}
+
+fn is_telomeric_hexamer(hexamer: &[u8]) -> bool {
+    const REPEATS: [&[u8; 12]; 2] = [b"TTAGGGTTAGGG", b"CCCTAACCCTAA"];
+    let upper = [hexamer[0], hexamer[1], hexamer[2], hexamer[3], hexamer[4], hexamer[5]].map(|b| b.to_ascii_uppercase());
+    REPEATS.iter().any(|repeat| repeat.windows(6).take(6).any(|rotation| rotation == upper))
+}
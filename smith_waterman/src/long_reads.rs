@@ -0,0 +1,137 @@
+// --long-reads: WGS mode for ONT/PacBio reads of 100 kb and more
+// A chunk size in reads assumes short reads: a few thousand 1 Mb reads are a
+// launch no GPU takes. In this mode the reader streams one read per chunk
+// (WgsConfig::launch_chunk_size), and each read is cut into tiles of at most
+// the tile size with `overlap` bases shared between neighbours. The tiles of a
+// read are launched back to back on the context's queue and waited for
+// together. The read's score is its best tile's, as a launch's score is its
+// best work group's; the overlap keeps a high-scoring run that crosses a tile
+// boundary whole in one tile. Each tile also gives the CIGAR of the part of
+// the read it owns (up to the middle of each overlap), and the parts are
+// joined into the read's CIGAR. The kernel is ungapped, so the CIGAR holds only
+// = and X: a position is X when the launched copy differs (a masked base) or
+// either base is N
+
+use crate::aligner::{enqueue_alignment, max_launch_bases, MIN_CHUNK_BASES};
+use crate::gpu::{GpuContext, GpuDevice};
+
+pub const DEFAULT_TILE_BASES: usize = 1024 * 1024;
+pub const DEFAULT_TILE_OVERLAP: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LongReadOptions {
+    pub tile_bases: usize,
+    pub overlap: usize,
+}
+
+impl Default for LongReadOptions {
+    fn default() -> Self {
+        Self { tile_bases: DEFAULT_TILE_BASES, overlap: DEFAULT_TILE_OVERLAP }
+    }
+}
+
+impl LongReadOptions {
+    pub fn new(tile_bases: usize, overlap: usize) -> Result<Self, String> {
+        if tile_bases < MIN_CHUNK_BASES {
+            return Err(format!("--tile-size must be at least {} bases, got {}", MIN_CHUNK_BASES, tile_bases));
+        }
+        if overlap >= tile_bases / 2 {
+            return Err(format!("--tile-overlap must be less than half the tile size ({} bases), got {}", tile_bases / 2, overlap));
+        }
+        Ok(Self { tile_bases, overlap })
+    }
+
+    pub fn name(&self) -> String {
+        format!("tiles of {} bases, {} overlap", self.tile_bases, self.overlap)
+    }
+}
+
+// [start, end) of each tile of a read of `length` bases; the last tile is
+// pulled back to end at the read's end, so no tile is short unless the read is
+pub fn tiles(length: usize, tile_bases: usize, overlap: usize) -> Vec<(usize, usize)> {
+    if length <= tile_bases {
+        return vec![(0, length)];
+    }
+    let step = tile_bases - overlap;
+    let mut tiles = Vec::with_capacity(length.div_ceil(step));
+    let mut start = 0;
+    loop {
+        let end = (start + tile_bases).min(length);
+        tiles.push((end.saturating_sub(tile_bases), end));
+        if end == length {
+            return tiles;
+        }
+        start += step;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadScore {
+    pub score: i32,
+    // Tiles launched; 0 when the read is too short to launch
+    pub tiles: usize,
+    pub cigar: String,
+}
+
+// Tile, launch and reassemble one read; `query` is the read as launched
+// (masked or not) and `target` the read itself
+pub fn score_read(gpu: &GpuContext, device: &GpuDevice, query: &[u8], target: &[u8], options: &LongReadOptions) -> Result<ReadScore, String> {
+    let length = query.len().min(target.len());
+    let tile_bases = options.tile_bases.min(max_launch_bases(device));
+    let overlap = options.overlap.min(tile_bases / 2);
+    let tiles = tiles(length, tile_bases, overlap);
+
+    let mut cigar = Cigar::default();
+    for (n, &(start, end)) in tiles.iter().enumerate() {
+        // Each tile owns its bases up to the middle of the overlap with the next
+        let owned_start = if n == 0 { 0 } else { (tiles[n - 1].1 + start) / 2 };
+        let owned_end = tiles.get(n + 1).map_or(end, |&(next_start, _)| (end + next_start) / 2);
+        cigar.extend(&query[owned_start..owned_end], &target[owned_start..owned_end]);
+    }
+    if length < MIN_CHUNK_BASES {
+        return Ok(ReadScore { score: 0, tiles: 0, cigar: cigar.finish() });
+    }
+
+    let program = gpu.program()?;
+    let pending = tiles.iter()
+        .map(|&(start, end)| enqueue_alignment(gpu.queue(), program, &query[start..end], &target[start..end], device, crate::kernels::selected()))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut score = 0;
+    for launch in pending {
+        score = score.max(launch.wait()?);
+    }
+    Ok(ReadScore { score, tiles: tiles.len(), cigar: cigar.finish() })
+}
+
+// Run-length = and X operations
+#[derive(Default)]
+struct Cigar {
+    text: String,
+    op: u8,
+    run: usize,
+}
+
+impl Cigar {
+    fn extend(&mut self, query: &[u8], target: &[u8]) {
+        for (&q, &t) in query.iter().zip(target) {
+            let op = if q == t && !crate::n_policy::is_n(q) { b'=' } else { b'X' };
+            if op != self.op {
+                self.flush();
+                self.op = op;
+            }
+            self.run += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.run > 0 {
+            self.text.push_str(&format!("{}{}", self.run, self.op as char));
+            self.run = 0;
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        if self.text.is_empty() { "*".to_string() } else { self.text }
+    }
+}
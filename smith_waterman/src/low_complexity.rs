@@ -0,0 +1,198 @@
+// --mask-low-complexity: SDUST masking of tandem repeats and other low-complexity runs
+// SDUST (Morgulis et al. 2006) scores each 64-base window by how often its
+// triplets repeat and reports the "perfect" intervals whose score per triplet is
+// over the threshold. This follows minimap2's sdust.c with its defaults
+// (threshold 20, window 64). Anything other than A/C/G/T (either case) splits
+// the sequence into independent pieces.
+// Self-alignment compares the chunk with itself, so repeats would otherwise
+// score as long runs of matches. The launch gets a copy of the bases with the
+// masked bases set to N, which mismatches the original at every masked base
+
+use crate::read_chunk::ReadChunk;
+use std::collections::VecDeque;
+
+pub const DEFAULT_THRESHOLD: usize = 20;
+pub const DEFAULT_WINDOW: usize = 64;
+
+const WORD_LENGTH: usize = 3;
+const WORDS: usize = 1 << (2 * WORD_LENGTH);
+
+#[derive(Debug, Clone, Copy)]
+struct PerfectInterval {
+    start: usize,
+    finish: usize,
+    r: usize,
+    l: usize,
+}
+
+struct Sdust {
+    threshold: usize,
+    window: usize,
+    // Triplets in the current window, oldest first
+    words: VecDeque<usize>,
+    // Triplet counts over the whole window (cw, rw) and over its suffix of length l (cv, rv)
+    cw: [usize; WORDS],
+    cv: [usize; WORDS],
+    rw: usize,
+    rv: usize,
+    l: usize,
+    perfect: Vec<PerfectInterval>,
+    masked: Vec<(usize, usize)>,
+}
+
+impl Sdust {
+    fn new(threshold: usize, window: usize) -> Self {
+        Self {
+            threshold,
+            window,
+            words: VecDeque::with_capacity(window),
+            cw: [0; WORDS],
+            cv: [0; WORDS],
+            rw: 0,
+            rv: 0,
+            l: 0,
+            perfect: Vec::new(),
+            masked: Vec::new(),
+        }
+    }
+
+    fn reset_window(&mut self) {
+        self.words.clear();
+        self.cw = [0; WORDS];
+        self.cv = [0; WORDS];
+        self.rw = 0;
+        self.rv = 0;
+        self.l = 0;
+    }
+
+    fn shift_window(&mut self, t: usize) {
+        if self.words.len() > self.window - WORD_LENGTH {
+            if let Some(s) = self.words.pop_front() {
+                self.cw[s] -= 1;
+                self.rw -= self.cw[s];
+                if self.l > self.words.len() {
+                    self.l -= 1;
+                    self.cv[s] -= 1;
+                    self.rv -= self.cv[s];
+                }
+            }
+        }
+        self.words.push_back(t);
+        self.l += 1;
+        self.rw += self.cw[t];
+        self.cw[t] += 1;
+        self.rv += self.cv[t];
+        self.cv[t] += 1;
+        if self.cv[t] * 10 > self.threshold * 2 {
+            loop {
+                let s = self.words[self.words.len() - self.l];
+                self.cv[s] -= 1;
+                self.rv -= self.cv[s];
+                self.l -= 1;
+                if s == t {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn find_perfect(&mut self, start: usize) {
+        let mut c = self.cv;
+        let mut r = self.rv;
+        let (mut max_r, mut max_l) = (0, 0);
+        let size = self.words.len();
+        for i in (0..size - self.l).rev() {
+            let t = self.words[i];
+            r += c[t];
+            c[t] += 1;
+            let (new_r, new_l) = (r, size - i - 1);
+            if new_r * 10 > self.threshold * new_l {
+                let mut j = 0;
+                while j < self.perfect.len() && self.perfect[j].start >= i + start {
+                    let p = self.perfect[j];
+                    if max_r == 0 || p.r * max_l > max_r * p.l {
+                        max_r = p.r;
+                        max_l = p.l;
+                    }
+                    j += 1;
+                }
+                if max_r == 0 || new_r * max_l >= max_r * new_l {
+                    max_r = new_r;
+                    max_l = new_l;
+                    self.perfect.insert(j, PerfectInterval { start: i + start, finish: size + WORD_LENGTH - 1 + start, r: new_r, l: new_l });
+                }
+            }
+        }
+    }
+
+    // Move the perfect interval with the smallest start to the output once it has
+    // left the window, merging overlaps
+    fn save_masked(&mut self, start: usize) {
+        let Some(p) = self.perfect.last().copied().filter(|p| p.start < start) else { return };
+        match self.masked.last_mut() {
+            Some(last) if p.start <= last.1 => last.1 = last.1.max(p.finish),
+            _ => self.masked.push((p.start, p.finish)),
+        }
+        // Intervals are kept latest start first; drop every one that starts before the window
+        let keep = self.perfect.iter().take_while(|q| q.start >= start).count();
+        self.perfect.truncate(keep);
+    }
+
+    fn run(mut self, seq: &[u8]) -> Vec<(usize, usize)> {
+        let mut run_length = 0;
+        let mut word = 0;
+        for i in 0..=seq.len() {
+            let code = seq.get(i).and_then(|b| match b.to_ascii_uppercase() {
+                b'A' => Some(0),
+                b'C' => Some(1),
+                b'G' => Some(2),
+                b'T' => Some(3),
+                _ => None,
+            });
+            if let Some(code) = code {
+                run_length += 1;
+                word = ((word << 2) | code) & (WORDS - 1);
+                if run_length >= WORD_LENGTH {
+                    let start = run_length.saturating_sub(self.window) + (i + 1 - run_length);
+                    self.save_masked(start);
+                    self.shift_window(word);
+                    if self.rw * 10 > self.l * self.threshold {
+                        self.find_perfect(start);
+                    }
+                }
+            } else {
+                let mut start = (run_length + 1).saturating_sub(self.window) + (i + 1 - run_length);
+                while !self.perfect.is_empty() {
+                    self.save_masked(start);
+                    start += 1;
+                }
+                self.reset_window();
+                run_length = 0;
+                word = 0;
+            }
+        }
+        self.masked
+    }
+}
+
+// Half-open [start, end) intervals to mask, sorted and non-overlapping
+pub fn sdust(seq: &[u8], threshold: usize, window: usize) -> Vec<(usize, usize)> {
+    Sdust::new(threshold, window.max(WORD_LENGTH + 1)).run(seq)
+}
+
+// Copy the chunk's bases into `out` with each read's low-complexity intervals
+// set to N; returns the number of bases masked
+pub fn mask_chunk(chunk: &ReadChunk, out: &mut Vec<u8>) -> usize {
+    out.clear();
+    out.extend_from_slice(chunk.bases());
+    let mut masked = 0;
+    let mut offset = 0;
+    for read in chunk.iter() {
+        for (start, end) in sdust(read, DEFAULT_THRESHOLD, DEFAULT_WINDOW) {
+            out[offset + start..offset + end].fill(b'N');
+            masked += end - start;
+        }
+        offset += read.len();
+    }
+    masked
+}
@@ -1,89 +1,2627 @@
 // CLI entry point for genome-scale sequence alignment
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::env;
-mod gpu;
-mod tools;
-mod aligner;
-mod system_info;
-mod perf_logger;
+use rustseq_mini::exit_codes::ExitKind;
+use rustseq_mini::{AlignParams, Aligner, Backend, CancellationToken};
+use rustseq_mini::tools::{bench_export, benchmark, ceilings};
+use rustseq_mini::{adaptive_chunks, affinity, aggregation, align_pairs, aligner, alignment_input, ani, assembly_compare, backend_ladder, barcode_correction, batch, cancel_request, checkpoint_store, chunk_audit, chromosome_walk, chunking, contamination, contig_chunks, cpu_align, determinism, distances, evaluate, exit_codes, file_order, file_selection, gpu, gpu_queues, gpu_scheduler, gpu_share, hit_limits, in_memory, input_format, kernel_test, kernels, kmer_hash, kmer_spectrum, launch_reuse, learned_profile, long_read_trim, long_reads, memory_profile, msa, n_policy, notify, output_layout, overlap, packed_reads, parity, pair_stats, parallel_gzip, paths, perf_logger, pipeline, planner, presets, preemption, primer_scan, pssm, qc, quarantine, query_batcher, read_binning, read_group, read_output, read_store, read_trim, reference_cache, remote_state, resume, run_context, run_cost, run_diff, sanitize, serve, score_matrix, scorer, scoring, seed, seq_transform, sequence_db, simulate, soak, splice, sweep, system_info, translated, tui, warnings, watchdog, wgs_config, wgs_summary};
 
 #[derive(Parser)]
 #[command(name = "rustseq_mini")]
 #[command(about = "High-performance sequence alignment for genome-scale data")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    
     /// first sequence or file path
-    #[arg(short = '1', long)]
+    #[arg(short = '1', long, requires = "seq2", conflicts_with_all = ["full_wgs", "test_wgs"])]
     seq1: Option<String>,
     
-    /// second sequence or file path  
-    #[arg(short = '2', long)]
-    seq2: Option<String>,
+    /// second sequence or file path
+    #[arg(short = '2', long, requires = "seq1")]
+    seq2: Option<String>,
+    
+    /// treat inputs as file paths instead of direct sequences
+    #[arg(short, long, default_value = "false", requires = "seq1")]
+    files: bool,
+    
+    /// with --files and a FASTA seq2, write every contig's scores and best window (TSV)
+    #[arg(long, requires = "files")]
+    contig_scores: Option<String>,
+    
+    /// with --files, read and pack both files into memory once (they must fit), then align them from there
+    #[arg(long, default_value = "false", requires = "files")]
+    keep_in_memory: bool,
+    
+    /// with --keep-in-memory, also align under these scores: match,mismatch,gap (repeatable), e.g. --sweep 1,-3,-5
+    #[arg(long, requires = "keep_in_memory", allow_hyphen_values = true)]
+    sweep: Vec<String>,
+    
+    /// align seq1 (a transcript) against seq2 (genomic) on the CPU with an intron state for large gaps
+    #[arg(long, default_value = "false", requires = "seq1")]
+    splice_aware: bool,
+    
+    /// write the full Smith-Waterman matrix of the pair (each at most 4096 bases), computed on the CPU, to a .npy or .tsv file
+    #[arg(long, requires = "seq1", conflicts_with = "splice_aware")]
+    dump_matrix: Option<String>,
+    
+    /// intron open penalty for --splice-aware
+    #[arg(long, default_value_t = splice::DEFAULT_INTRON_OPEN, requires = "splice_aware")]
+    intron_open: i32,
+    
+    /// intron penalty per skipped base for --splice-aware
+    #[arg(long, default_value_t = splice::DEFAULT_INTRON_EXTEND, requires = "splice_aware")]
+    intron_extend: i32,
+    
+    /// chunk size: MB of bases (e.g. 1, 512KB, 16MB), a read count (e.g. 500000reads),
+    /// or auto to adapt it during WGS runs; overrides GPU_CHUNK_SIZE_READS/GPU_CHUNK_SIZE_BASES, default 1MB
+    #[arg(short, long)]
+    chunk_size: Option<String>,
+    
+    /// use GPU acceleration if available
+    #[arg(short, long, default_value = "false", conflicts_with = "cpu")]
+    gpu: bool,
+    
+    /// use the SIMD CPU path instead of the GPU (level picked by a per-host micro-benchmark)
+    #[arg(long, default_value = "false", conflicts_with_all = ["full_wgs", "test_wgs"])]
+    cpu: bool,
+    
+    /// with --cpu, score the pair with this scorer instead of Smith-Waterman: edit-distance, hamming, quality-weighted, or a registered one
+    #[arg(long, requires = "cpu", conflicts_with = "fallback")]
+    scorer: Option<String>,
+    
+    /// align the pair through wgpu (Metal on macOS) with the basic kernel instead of OpenCL
+    #[arg(long, default_value = "false", conflicts_with_all = ["cpu", "gpu", "full_wgs", "test_wgs"])]
+    wgpu: bool,
+    
+    /// with --gpu or --wgpu, fall back to the next backend down (CUDA, OpenCL, wgpu, CPU) when it can't be opened, instead of exiting; with --full-wgs nothing runs below OpenCL, so it only reports why OpenCL is missing before exiting with code 3
+    #[arg(long, default_value = "false")]
+    fallback: bool,
+    
+    /// process only the first N input files (after --file-range/--only-files)
+    #[arg(short, long, requires = "full_wgs")]
+    num_files: Option<usize>,
+    
+    /// process only input files in this 1-based inclusive range, e.g. 3..8, 3.., ..8
+    #[arg(long, requires = "full_wgs")]
+    file_range: Option<String>,
+    
+    /// process only input files whose name contains one of these, e.g. L003_R1,L003_R2
+    #[arg(long, requires = "full_wgs", value_delimiter = ',')]
+    only_files: Vec<String>,
+    
+    /// order to process the selected files in: as-listed, largest-first, or smallest-first
+    #[arg(long, default_value = "as-listed", requires = "full_wgs")]
+    order: String,
+    
+    /// default, or strict to hold chunks, batches, output buffers and k-mer tables to MEMORY_BUDGET
+    #[arg(long, default_value = "default", requires = "full_wgs")]
+    memory_profile: String,
+    
+    /// per-file aggregates of chunk scores to report: sum, max, min, mean, mean-identity, or registered names
+    #[arg(long, requires = "full_wgs", value_delimiter = ',')]
+    aggregate: Vec<String>,
+    
+    /// test mode: read WGS files from USB drive
+    #[arg(short, long, default_value = "false", conflicts_with = "full_wgs")]
+    test_wgs: bool,
+    
+    /// process the configured WGS input files (needs --gpu)
+    #[arg(long, default_value = "false", requires = "gpu")]
+    full_wgs: bool,
+    
+    /// NUMA placement: off, auto (node closest to the GPU), or a node number
+    #[arg(long, default_value = "off", requires = "full_wgs")]
+    numa: String,
+    
+    /// cores for reader threads, e.g. 0-3,8
+    #[arg(long, requires = "full_wgs")]
+    pin_reader: Option<String>,
+    
+    /// cores for the decompressor process, e.g. 4-5
+    #[arg(long, requires = "full_wgs")]
+    pin_decompressor: Option<String>,
+    
+    /// core for the GPU submit thread
+    #[arg(long, requires = "full_wgs")]
+    pin_gpu: Option<usize>,
+    
+    /// write results, checkpoints, logs, and manifest.json under this directory
+    #[arg(long, requires = "full_wgs")]
+    output_dir: Option<String>,
+    
+    /// record a flamegraph of the run (perf on Linux, in-process sampler otherwise)
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    profile: bool,
+    
+    /// live terminal dashboard instead of scrolling output (full WGS runs)
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    tui: bool,
+    
+    /// POST the run summary as JSON to this URL when a full WGS run ends
+    #[arg(long, requires = "full_wgs")]
+    notify_webhook: Option<String>,
+    
+    /// show a desktop notification when a full WGS run ends
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    notify_desktop: bool,
+    
+    /// continue from the latest checkpoint (fetched from --remote-state if there is none locally)
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    resume: bool,
+    
+    /// mirror checkpoints, the manifest and results to this s3:// or gs:// prefix as they are written
+    #[arg(long, requires = "full_wgs")]
+    remote_state: Option<String>,
+    
+    /// hourly price of the instance in USD, for the run's estimated cost
+    #[arg(long, requires = "full_wgs")]
+    cost_per_hour: Option<f64>,
+    
+    /// instance type recorded with the estimated cost, e.g. g5.xlarge
+    #[arg(long, requires = "cost_per_hour")]
+    instance_type: Option<String>,
+    
+    /// write per-read results keyed by read name (format from --output-format or the extension: .sam, .jsonl, .parquet, .rskv, otherwise TSV)
+    #[arg(long, requires = "full_wgs")]
+    read_output: Option<String>,
+    
+    /// per-read results format: tsv, jsonl, sam, parquet, rskv or null (null discards them and needs no --read-output)
+    #[arg(long, requires = "full_wgs")]
+    output_format: Option<String>,
+    
+    /// store 64-bit hashes of read names instead of the names themselves
+    #[arg(long, default_value = "false", requires = "read_output")]
+    hash_read_names: bool,
+    
+    /// sample name (SM) for the read group of the run's outputs; defaults to WGS_SAMPLE_ID with --library or --platform-unit
+    #[arg(long, requires = "full_wgs")]
+    sample: Option<String>,
+    
+    /// library (LB) for the read group of the run's outputs
+    #[arg(long, requires = "full_wgs")]
+    library: Option<String>,
+    
+    /// platform unit (PU), e.g. flowcell.lane, also used as the read group ID
+    #[arg(long, requires = "full_wgs")]
+    platform_unit: Option<String>,
+    
+    /// clean reads before aligning: uppercase, strip whitespace, and fix or reject invalid characters
+    #[arg(long, requires = "full_wgs")]
+    sanitize: Option<String>,
+    
+    /// alphabet the sanitizer checks against: auto, dna, rna, or protein
+    #[arg(long, default_value = "auto", requires = "sanitize")]
+    alphabet: String,
+    
+    /// long-read mode: stream one read per chunk, cut into tiles, with a score and CIGAR per read
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    long_reads: bool,
+    
+    /// longest tile of a read in long-read mode, e.g. 512KB or 1MB
+    #[arg(long, default_value = "1MB", requires = "long_reads")]
+    tile_size: String,
+    
+    /// bases shared by neighbouring tiles of a read
+    #[arg(long, default_value_t = long_reads::DEFAULT_TILE_OVERLAP, requires = "long_reads")]
+    tile_overlap: usize,
+    
+    /// trim long reads before aligning: all, or a comma-separated list of adapter, poly-a and telomere
+    #[arg(long, requires = "full_wgs", num_args = 0..=1, default_missing_value = "all")]
+    trim_long_reads: Option<String>,
+    
+    /// drop reads shorter than this after long-read trimming
+    #[arg(long, default_value_t = 1, requires = "trim_long_reads")]
+    min_trimmed_length: usize,
+    
+    /// trim this many bases from the start of every read
+    #[arg(long, default_value_t = 0, requires = "full_wgs")]
+    trim_head: usize,
+    
+    /// trim this many bases from the end of every read
+    #[arg(long, default_value_t = 0, requires = "full_wgs")]
+    trim_tail: usize,
+    
+    /// cut reads where the mean quality of a window drops below a threshold, as window:quality (e.g. 4:20)
+    #[arg(long, requires = "full_wgs")]
+    trim_sliding_window: Option<String>,
+    
+    /// mask low-complexity runs (SDUST) so tandem repeats don't dominate chunk scores
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    mask_low_complexity: bool,
+    
+    /// screen reads for contamination against these references: names in CONTAMINANT_PANEL_DIR, name=path, or paths (comma-separated)
+    #[arg(long, requires = "full_wgs")]
+    screen: Option<String>,
+    
+    /// k-mer length of the contamination screen (1 to 31)
+    #[arg(long, default_value_t = contamination::DEFAULT_KMER, requires = "screen")]
+    screen_kmer: usize,
+    
+    /// keep about one k-mer in this many for the screen's sketches
+    #[arg(long, default_value_t = contamination::DEFAULT_SCALE, requires = "screen")]
+    screen_scale: u64,
+    
+    /// collect per-file QC statistics (duplicate and optical duplicate rates) into qc_report.json
+    #[arg(long, default_value = "false", requires = "full_wgs")]
+    qc: bool,
+    
+    /// input format: auto (sniffed from content), fastq, fasta, bam, or cram
+    #[arg(long, default_value = "auto", requires = "full_wgs")]
+    input_format: String,
+    
+    /// only read BAM/CRAM records overlapping this region, e.g. chr1:10000-20000 (needs an index)
+    #[arg(long, requires = "full_wgs")]
+    region: Option<String>,
+    
+    /// alignment kernel: basic (default), tiled (sequence tiles staged in local memory), or packed (experimental)
+    #[arg(long)]
+    kernel: Option<String>,
+    
+    /// named scores, band width and kernel: short-read, long-read-ont, or protein; explicit flags override it
+    #[arg(long)]
+    preset: Option<String>,
+    
+    /// score of a matching base (default 2)
+    #[arg(long)]
+    match_score: Option<i32>,
+    
+    /// score of a mismatching base, 0 or negative (default -1)
+    #[arg(long, allow_negative_numbers = true)]
+    mismatch_penalty: Option<i32>,
+    
+    /// score of each gapped base, 0 or negative (default -2)
+    #[arg(long, allow_negative_numbers = true)]
+    gap_penalty: Option<i32>,
+    
+    /// scoring of N bases: mismatch, zero (score 0), or skip (dropped before aligning)
+    #[arg(long, default_value = "mismatch")]
+    n_policy: String,
+    
+    /// use this share of the GPU (0-1]: a sub-device where supported, otherwise less queue depth and memory
+    #[arg(long, requires = "gpu")]
+    gpu_fraction: Option<f64>,
+    
+    /// check the selected kernel against the CPU path on this device before aligning
+    #[arg(long, default_value = "false", requires = "gpu")]
+    verify_kernel: bool,
+    
+    /// print the memory and launch plan for the run, then exit without aligning
+    #[arg(long, default_value = "false", requires = "gpu")]
+    dry_run: bool,
+    
+    /// bit-identical results across runs: fixed kernel geometry, fail on chunk errors, print a result digest
+    #[arg(long, default_value = "false")]
+    deterministic: bool,
+    
+    /// seed for everything random in the run; recorded in manifest.json
+    #[arg(long, default_value_t = seed::DEFAULT_SEED)]
+    seed: u64,
+    
+    /// reference FASTA (with .fai) for decoding CRAM inputs, or a name fetched with reference fetch (e.g. GRCh38)
+    #[arg(long, requires = "full_wgs")]
+    reference: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// run every sample in a manifest through the WGS pipeline
+    Batch {
+        /// TSV manifest: sample_id, data_dir, optional comma-separated input files
+        manifest: String,
+        
+        /// number of samples to run at the same time
+        #[arg(long, default_value = "1")]
+        parallel: usize,
+        
+        /// root directory for the per-sample output directories
+        #[arg(long, default_value = "batch_output")]
+        output_root: String,
+        
+        /// extra arguments for every sample run, after --
+        #[arg(last = true)]
+        sample_args: Vec<String>,
+    },
+    
+    /// run the WGS pipeline over a grid of settings and report the Pareto-optimal ones for speed and score
+    Sweep {
+        /// setting=values, as a range (gap=1..10) or a list (chunk_reads=500,1000,5000); repeat for each setting
+        #[arg(long = "param", required = true)]
+        params: Vec<String>,
+        
+        /// output directory every point runs in; results land in its results/
+        #[arg(long, default_value = "sweep_output")]
+        output_root: String,
+        
+        /// run each point on only the first N input files
+        #[arg(long)]
+        subsample: Option<usize>,
+        
+        /// extra arguments for every run, after --
+        #[arg(last = true)]
+        run_args: Vec<String>,
+    },
+    
+    /// take full WGS jobs and database searches from clients over TCP; jobs share the GPU by priority, searches are batched
+    Serve {
+        /// address to listen on
+        #[arg(long, default_value = serve::DEFAULT_LISTEN)]
+        listen: String,
+        
+        /// jobs running at once; later ones wait their turn
+        #[arg(long, default_value_t = serve::DEFAULT_MAX_JOBS)]
+        max_jobs: usize,
+        
+        /// GPU launches in flight at once across all jobs
+        #[arg(long, default_value_t = gpu_scheduler::DEFAULT_SLOTS)]
+        slots: usize,
+        
+        /// write each job's results, checkpoints, logs, and the manifest under this directory
+        #[arg(long)]
+        output_dir: Option<String>,
+        
+        /// database written by db build, to answer search requests against
+        #[arg(long)]
+        db: Option<String>,
+        
+        /// hits returned per search
+        #[arg(long, default_value_t = sequence_db::DEFAULT_TOP_N)]
+        top_n: usize,
+        
+        /// return only hits scoring at least this
+        #[arg(long, default_value_t = sequence_db::DEFAULT_MIN_SCORE)]
+        min_score: i32,
+        
+        /// searches aligned together in one launch, at most
+        #[arg(long, default_value_t = query_batcher::DEFAULT_MAX_BATCH)]
+        max_batch: usize,
+        
+        /// milliseconds to wait after a search arrives for more to launch with it
+        #[arg(long, default_value_t = query_batcher::DEFAULT_LINGER.as_millis() as u64)]
+        linger_ms: u64,
+    },
+    
+    /// compare or cancel runs
+    Run {
+        #[command(subcommand)]
+        command: RunCommand,
+    },
+    
+    /// run two files through the legacy pair functions and the Aligner, and diff their scores and timings
+    Parity {
+        /// first FASTA/FASTQ file (plain or compressed); keep it small
+        file1: String,
+        
+        /// second FASTA/FASTQ file
+        file2: String,
+        
+        /// times each path aligns each case (the best time is kept)
+        #[arg(long, default_value_t = parity::DEFAULT_RUNS)]
+        runs: usize,
+        
+        /// compare the CPU paths instead of the GPU ones
+        #[arg(long)]
+        cpu: bool,
+    },
+    
+    /// align a fixed workload for hours, failing if GPU memory, handles or throughput drift
+    Soak {
+        /// how long to soak
+        #[arg(long, default_value_t = soak::DEFAULT_HOURS)]
+        hours: f64,
+        
+        /// align synthetic pairs of 1 kb to 1 Mb (from --seed)
+        #[arg(long, conflicts_with = "input")]
+        synthetic: bool,
+        
+        /// align the first chunks of this FASTA/FASTQ file, each against itself
+        #[arg(long)]
+        input: Option<String>,
+        
+        /// seconds between samples
+        #[arg(long, default_value_t = soak::DEFAULT_INTERVAL_S)]
+        interval: u64,
+        
+        /// GPU memory used may grow this many MB over the first sample
+        #[arg(long, default_value_t = soak::DEFAULT_MAX_GPU_GROWTH_MB)]
+        max_gpu_growth_mb: f64,
+        
+        /// process resident memory may grow this many MB over the first sample
+        #[arg(long, default_value_t = soak::DEFAULT_MAX_RSS_GROWTH_MB)]
+        max_rss_growth_mb: f64,
+        
+        /// open file descriptors, and threads, may each grow by this many
+        #[arg(long, default_value_t = soak::DEFAULT_MAX_HANDLE_GROWTH)]
+        max_handle_growth: usize,
+        
+        /// throughput may fall by this fraction of its starting rate
+        #[arg(long, default_value_t = soak::DEFAULT_MAX_THROUGHPUT_DROP)]
+        max_throughput_drop: f64,
+        
+        /// every sample, as JSON lines
+        #[arg(long, default_value = "soak.jsonl")]
+        output: String,
+        
+        /// soak the CPU path instead of the GPU one
+        #[arg(long)]
+        cpu: bool,
+    },
+    
+    /// reproduce what a run did with one chunk
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommand,
+    },
+    
+    /// micro-benchmarks
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommand,
+    },
+    
+    /// check the alignment kernels on an OpenCL device against the CPU path
+    Kernel {
+        #[command(subcommand)]
+        command: KernelCommand,
+    },
+    
+    /// generate benchmark datasets with known read origins
+    Simulate {
+        #[command(subcommand)]
+        command: SimulateCommand,
+    },
+    
+    /// look reads up by name in a per-read result store (--read-output ending in .rskv)
+    Lookup {
+        /// store written by --read-output
+        store: String,
+        
+        /// read names (or h:<hex> hashes)
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    
+    /// score reported read placements (SAM) against simulated reads' true positions
+    Evaluate {
+        /// FASTQ written by simulate from-reference
+        truth: String,
+        
+        /// SAM with the placements to check
+        results: String,
+        
+        /// bases a placement may be off the true start and still count as correct
+        #[arg(long, default_value = "20")]
+        tolerance: u64,
+        
+        /// also write the report as JSON
+        #[arg(long)]
+        json: Option<String>,
+    },
+    
+    /// alignment-free average nucleotide identity between two genomes from k-mer sketches
+    Ani {
+        /// first genome or assembly FASTA (plain or .gz)
+        a: String,
+        
+        /// second genome or assembly FASTA (plain or .gz)
+        b: String,
+        
+        /// k-mer length (up to 31)
+        #[arg(long = "kmer", default_value_t = ani::DEFAULT_KMER)]
+        kmer: usize,
+        
+        /// keep about one k-mer in this many for the sketches
+        #[arg(long, default_value_t = ani::DEFAULT_SCALE)]
+        scale: u64,
+        
+        /// bases per window of A (and of B, for B -> A)
+        #[arg(long, default_value_t = ani::DEFAULT_WINDOW)]
+        window: usize,
+        
+        /// also write the report as JSON
+        #[arg(long)]
+        json: Option<String>,
+    },
+    
+    /// assembly QC: pair the contigs of two assemblies and report per-contig identity, indels and breakpoints
+    CompareAssemblies {
+        /// first assembly FASTA (plain or .gz)
+        a: String,
+        
+        /// second assembly FASTA (plain or .gz)
+        b: String,
+        
+        /// per-pair TSV: identity, mismatches, insertions and deletions in B, breakpoints
+        #[arg(long, default_value = "assembly_comparison.tsv")]
+        output: String,
+        
+        /// also write every breakpoint (TSV, 0-based half-open spans)
+        #[arg(long)]
+        breakpoints: Option<String>,
+        
+        /// also write the report as JSON
+        #[arg(long)]
+        json: Option<String>,
+        
+        /// diagonals either side of each block's start diagonal (default 32, or the --preset's)
+        #[arg(long)]
+        band: Option<usize>,
+    },
+    
+    /// synteny track: slide a window along each reference contig, place it on the query and score it (bedGraph)
+    Walk {
+        /// query genome FASTA (plain or .gz)
+        query: String,
+        
+        /// reference FASTA (plain or .gz), streamed one contig at a time
+        reference: String,
+        
+        /// bases per reference window
+        #[arg(long, default_value_t = chromosome_walk::DEFAULT_WINDOW)]
+        window: usize,
+        
+        /// bases between window starts (default --window, so windows don't overlap)
+        #[arg(long)]
+        step: Option<usize>,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// bedGraph score track: contig, start, end (0-based, half-open), score
+        #[arg(long, default_value = "synteny.bedgraph")]
+        output: String,
+        
+        /// also write where each window was placed on the query (TSV)
+        #[arg(long)]
+        placements: Option<String>,
+    },
+    
+    /// score track of a genome against itself (repeats, segmental duplications) or against a second genome
+    Profile {
+        /// genome FASTA (plain or .gz)
+        genome: String,
+        
+        /// score against this genome instead, as walk GENOME_B GENOME does
+        #[arg(long)]
+        against: Option<String>,
+        
+        /// bases per window, optionally with k or m (e.g. 10k)
+        #[arg(long, default_value = "10k")]
+        window: String,
+        
+        /// bases between window starts, optionally with k or m (default --window)
+        #[arg(long)]
+        step: Option<String>,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// bedGraph score track: contig, start, end (0-based, half-open), score
+        #[arg(long, default_value = "profile.bedgraph")]
+        output: String,
+        
+        /// also write where each window was placed (TSV)
+        #[arg(long)]
+        placements: Option<String>,
+    },
+    
+    /// read pair concordance and insert sizes per lane, from R1/R2 FASTQ files aligned against a reference
+    PairStats {
+        /// reference FASTA (plain or .gz)
+        reference: String,
+        
+        /// FASTQ files as R1 R2 pairs, in that order (plain or compressed)
+        #[arg(required = true)]
+        files: Vec<String>,
+        
+        /// longest template, in bases, a concordant pair may span
+        #[arg(long, default_value_t = pair_stats::DEFAULT_MAX_INSERT)]
+        max_insert: usize,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// JSON report with per-lane counts and insert size distributions
+        #[arg(long, default_value = "pair_stats.json")]
+        output: String,
+        
+        /// bedGraph of read depth along the reference, from every aligned mate
+        #[arg(long)]
+        coverage: Option<String>,
+        
+        /// BigWig of the same read depth
+        #[arg(long)]
+        coverage_bigwig: Option<String>,
+    },
+    
+    /// blastx-style screen: six-frame translations of DNA reads against protein sequences
+    Translated {
+        /// FASTQ reads (plain or compressed)
+        query: String,
+        
+        /// protein FASTA (plain or .gz)
+        db: String,
+        
+        /// hits reported per read
+        #[arg(long, default_value_t = translated::DEFAULT_TOP_N)]
+        top_n: usize,
+        
+        /// report only hits scoring at least this (BLOSUM62, ungapped)
+        #[arg(long, default_value_t = translated::DEFAULT_MIN_SCORE)]
+        min_score: i32,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// hits TSV: read, rank, target, frame, score
+        #[arg(long, default_value = "translated_hits.tsv")]
+        output: String,
+    },
+    
+    /// progressive multiple sequence alignment of a small-to-medium nucleotide set (e.g. amplicons)
+    Msa {
+        /// sequences to align, FASTA (plain or .gz)
+        input: String,
+        
+        /// guide tree: upgma or nj
+        #[arg(long, default_value = "upgma")]
+        tree: String,
+        
+        /// output format: fasta or clustal
+        #[arg(long, default_value = "fasta")]
+        format: String,
+        
+        /// compute the distance matrix on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// output file (default: msa.fasta, or msa.aln for clustal)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    
+    /// pairwise distance matrix of a multi-FASTA for tree-building tools (PHYLIP or Nexus)
+    Distances {
+        /// sequences or genomes, FASTA (plain or .gz)
+        input: String,
+        
+        /// identity (alignment scores, as msa) or ani (k-mer sketches, for whole genomes)
+        #[arg(long, default_value = "identity")]
+        method: String,
+        
+        /// output format: phylip or nexus
+        #[arg(long, default_value = "phylip")]
+        format: String,
+        
+        /// score the identity distances on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// k-mer length for --method ani
+        #[arg(long = "kmer", default_value_t = ani::DEFAULT_KMER)]
+        kmer: usize,
+        
+        /// keep about one k-mer in this many, for --method ani
+        #[arg(long, default_value_t = ani::DEFAULT_SCALE)]
+        scale: u64,
+        
+        /// bases per window, for --method ani
+        #[arg(long, default_value_t = ani::DEFAULT_WINDOW)]
+        window: usize,
+        
+        /// output file (default: distances.phy, or distances.nex for nexus)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    
+    /// find primers (IUPAC codes allowed) in every read, on either strand, within an edit distance
+    PrimerScan {
+        /// FASTQ reads (plain or compressed)
+        reads: String,
+        
+        /// primer FASTA (plain or .gz)
+        #[arg(long)]
+        primers: String,
+        
+        /// substitutions, insertions and deletions allowed in a hit
+        #[arg(long, default_value_t = primer_scan::DEFAULT_MAX_EDITS)]
+        max_edits: u32,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// hits TSV: read, primer, strand, edits, start, end
+        #[arg(long, default_value = "primer_hits.tsv")]
+        output: String,
+    },
+    
+    /// cluster observed cell barcodes/UMIs within an edit distance and write a correction map
+    CorrectBarcodes {
+        /// FASTQ reads holding the barcode (plain or compressed), e.g. R1
+        reads: String,
+        
+        /// barcode length in bases
+        #[arg(long)]
+        length: usize,
+        
+        /// bases before the barcode
+        #[arg(long, default_value = "0")]
+        offset: usize,
+        
+        /// edit distance within which barcodes are merged (1 or 2)
+        #[arg(long, default_value_t = barcode_correction::DEFAULT_MAX_EDITS)]
+        max_edits: u32,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// correction map TSV: observed, corrected, count, edits
+        #[arg(long, default_value = "barcode_corrections.tsv")]
+        output: String,
+    },
+    
+    /// pre-alignment QC of FASTQ/FASTA files: duplicates, qualities, read lengths and adapter content
+    Stats {
+        /// input files (plain, compressed, or remote, as in WGS_INPUT_FILES)
+        #[arg(required = true)]
+        files: Vec<String>,
+        
+        /// adapter FASTA to look for instead of the built-in Illumina/Nextera/SOLiD list
+        #[arg(long)]
+        adapters: Option<String>,
+        
+        /// QC report (JSON)
+        #[arg(long, default_value = "qc_report.json")]
+        output: String,
+    },
+    
+    /// several analyses of FASTQ/FASTA files in one pass over each: QC, contamination screen, k-mer spectrum, GPU alignment
+    Analyze {
+        /// input files (plain, compressed, or remote, as in WGS_INPUT_FILES)
+        #[arg(required = true)]
+        files: Vec<String>,
+        
+        /// QC report, as in stats (qc_report.json)
+        #[arg(long)]
+        qc: bool,
+        
+        /// adapter FASTA for the QC report instead of the built-in list
+        #[arg(long, requires = "qc")]
+        adapters: Option<String>,
+        
+        /// contamination screen against these references, as in the WGS --screen (contamination.tsv)
+        #[arg(long)]
+        screen: Option<String>,
+        
+        /// k-mer length of the contamination screen (1 to 31)
+        #[arg(long, default_value_t = contamination::DEFAULT_KMER, requires = "screen")]
+        screen_kmer: usize,
+        
+        /// keep about one k-mer in this many for the screen's sketches
+        #[arg(long, default_value_t = contamination::DEFAULT_SCALE, requires = "screen")]
+        screen_scale: u64,
+        
+        /// k-mer multiplicity histogram with this k-mer length (1 to 31) (kmer_spectrum.tsv)
+        #[arg(long, num_args = 0..=1, default_missing_value = "21")]
+        kmer_spectrum: Option<usize>,
+        
+        /// count about one k-mer in this many for the spectrum
+        #[arg(long, default_value_t = kmer_spectrum::DEFAULT_SCALE, requires = "kmer_spectrum")]
+        kmer_scale: u64,
+        
+        /// k-mer hash of the spectrum: nt (rolling), splitmix, fx, or wy
+        #[arg(long, default_value = "nt", requires = "kmer_spectrum")]
+        kmer_hash: String,
+        
+        /// self-align every chunk on the GPU, as the WGS pass does
+        #[arg(long)]
+        align: bool,
+        
+        /// directory for the reports
+        #[arg(long, default_value = ".")]
+        report_dir: String,
+    },
+    
+    /// k-mer length guidance for the k-mer modes
+    Kmer {
+        #[command(subcommand)]
+        command: KmerCommand,
+    },
+    
+    /// align many pairs in batches
+    Align {
+        #[command(subcommand)]
+        command: AlignCommand,
+    },
+    
+    /// find all-vs-all overlaps between reads, without a reference, as PAF for assemblers
+    Overlap {
+        /// FASTQ or FASTA files of reads (plain or compressed), pooled
+        #[arg(required = true)]
+        reads: Vec<String>,
+        
+        /// minimizer k-mer length (1 to 31)
+        #[arg(long, default_value_t = overlap::DEFAULT_KMER)]
+        kmer: usize,
+        
+        /// minimizer window, in k-mers
+        #[arg(long, default_value_t = overlap::DEFAULT_WINDOW)]
+        window: usize,
+        
+        /// fewest chained minimizers for an overlap
+        #[arg(long, default_value_t = overlap::DEFAULT_MIN_MINIMIZERS)]
+        min_minimizers: usize,
+        
+        /// shortest overlap, in bases of the query read
+        #[arg(long, default_value_t = overlap::DEFAULT_MIN_OVERLAP)]
+        min_overlap: usize,
+        
+        /// lowest extension score (AS:i) to report
+        #[arg(long, default_value_t = 0)]
+        min_score: i32,
+        
+        /// extend on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// PAF output
+        #[arg(long, default_value = "overlaps.paf")]
+        output: String,
+    },
+    
+    /// build and search a persistent, k-mer indexed target database
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    
+    /// position-specific scoring matrices: build one from an alignment, scan sequences for motifs/domains
+    Pssm {
+        #[command(subcommand)]
+        command: PssmCommand,
+    },
+    
+    /// base-level utilities for large FASTA files, on the GPU
+    Seq {
+        #[command(subcommand)]
+        command: SeqCommand,
+    },
+    
+    /// download and index standard reference genomes for --reference
+    Reference {
+        #[command(subcommand)]
+        command: ReferenceCommand,
+    },
+    
+    /// build sidecar indexes that let one compressed input be decoded in parallel, or update a db build database in place
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+    
+    /// convert FASTQ/FASTA inputs once into packed <file>.rspk caches that WGS runs read instead
+    Prepare {
+        /// local FASTQ or FASTA inputs (plain, gzip, bgzf or zstd)
+        #[arg(required = true)]
+        files: Vec<String>,
+        
+        /// keep read names, for --read-output and --sample
+        #[arg(long)]
+        names: bool,
+        
+        /// keep quality lines, for --qc and --trim-sliding-window
+        #[arg(long)]
+        qualities: bool,
+        
+        /// convert again even if the cache is up to date
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexCommand {
+    /// decode a plain gzip file once and save access points to <file>.gzindex
+    Gzip {
+        /// local gzip file
+        file: String,
+        
+        /// MB of decompressed output between access points
+        #[arg(long, default_value_t = parallel_gzip::DEFAULT_SPAN_MB)]
+        span: u64,
+    },
+    
+    /// append the sequences of a FASTA file to a db build database without rebuilding it
+    Add {
+        /// FASTA of the new targets (plain or .gz)
+        targets: String,
+        
+        /// database written by db build
+        #[arg(long)]
+        db: String,
+        
+        /// tombstone targets with the same name as a new one instead of failing
+        #[arg(long)]
+        replace: bool,
+    },
+    
+    /// tombstone targets of a db build database by name, so searches skip them
+    Remove {
+        /// names of the targets to remove
+        #[arg(required = true)]
+        names: Vec<String>,
+        
+        /// database written by db build
+        #[arg(long)]
+        db: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReferenceCommand {
+    /// download, checksum, decompress and index a reference (GRCh38, GRCh37, CHM13)
+    Fetch {
+        /// catalog name or alias (see reference list)
+        name: String,
+        
+        /// cache directory (default: REFERENCE_CACHE_DIR, or ~/.cache/rustseq)
+        #[arg(long)]
+        dir: Option<String>,
+        
+        /// expected MD5 of the download, in place of the published checksum
+        #[arg(long)]
+        md5: Option<String>,
+        
+        /// fetch again even if the reference is cached
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// list the catalog and which references are cached
+    List {
+        /// cache directory (default: REFERENCE_CACHE_DIR, or ~/.cache/rustseq)
+        #[arg(long)]
+        dir: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PssmCommand {
+    /// build a PSSM from an aligned FASTA (e.g. msa output)
+    Build {
+        /// aligned FASTA (plain or .gz), every row the same length
+        msa: String,
+        
+        /// PSSM file
+        #[arg(long, default_value = "profile.pssm")]
+        output: String,
+    },
+    
+    /// report each target's best ungapped match to a PSSM
+    Scan {
+        /// sequences to scan, FASTA (plain or .gz)
+        targets: String,
+        
+        /// PSSM file (pssm build output, or any file in that format)
+        #[arg(long, conflicts_with = "msa", required_unless_present = "msa")]
+        pssm: Option<String>,
+        
+        /// build the PSSM from this aligned FASTA instead of reading one
+        #[arg(long)]
+        msa: Option<String>,
+        
+        /// report only targets scoring at least this (default: half the PSSM's best possible score)
+        #[arg(long)]
+        min_score: Option<i32>,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// hits TSV: target, score, target start/end, profile start/end
+        #[arg(long, default_value = "pssm_hits.tsv")]
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RunCommand {
+    /// ask a running full WGS run to stop after its current chunk, checkpointed and resumable
+    Cancel {
+        /// run number or run id, from run_registry.json
+        run: String,
+        
+        /// the run's --output-dir (default: the working directory)
+        #[arg(long, default_value = ".")]
+        dir: String,
+        
+        /// wait until the run has checkpointed and is exiting
+        #[arg(long)]
+        wait: bool,
+        
+        /// give up waiting after this many seconds
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
+    
+    /// compare two runs' settings, totals and per-file scores and throughput
+    Diff {
+        /// run directory (--output-dir) or its manifest.json
+        a: String,
+        
+        /// run directory (--output-dir) or its manifest.json
+        b: String,
+        
+        /// run number in A (default: the latest)
+        #[arg(long)]
+        run_a: Option<u64>,
+        
+        /// run number in B (default: the latest)
+        #[arg(long)]
+        run_b: Option<u64>,
+        
+        /// also write the diff as JSON to this file
+        #[arg(long)]
+        json: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// write the reads of one chunk of a full WGS run to a FASTQ, from the run's chunk audit log
+    ExtractChunk {
+        /// run number
+        run: u64,
+        
+        /// chunk id from the audit log, file:chunk (e.g. 3:17)
+        chunk: String,
+        
+        /// the run's --output-dir (default: the working directory)
+        #[arg(long, default_value = ".")]
+        dir: String,
+        
+        /// FASTQ to write (default: chunk_<file>_<chunk>.fastq)
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SeqCommand {
+    /// reverse complement, uppercase, U to T and/or 2-bit pack every record of a FASTA
+    Transform {
+        /// FASTA (plain or compressed)
+        input: String,
+        
+        /// comma-separated transforms, applied in order: revcomp, upper, u-to-t, pack (last only)
+        #[arg(long)]
+        ops: String,
+        
+        /// transform on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// output file (default: transformed.fa, or transformed.2bit with pack; .gz compresses FASTA)
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlignCommand {
+    /// score every pair listed in a pairs file, batched onto the GPU
+    Pairs {
+        /// TSV: seq1, seq2 (sequences, FASTA files or path:record), then optional id=, match=, mismatch=
+        #[arg(long)]
+        pairs: String,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// scores TSV: id, seq1, seq2, lengths, match, mismatch, score
+        #[arg(long, default_value = "pair_scores.tsv")]
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KmerCommand {
+    /// recommend k for a genome size and read error rate
+    SuggestK {
+        /// genome size in bases, optionally with k, m or g (e.g. 3g)
+        #[arg(long)]
+        genome_size: String,
+        
+        /// per-base sequencing error rate
+        #[arg(long, default_value_t = 0.01)]
+        error_rate: f64,
+        
+        /// read length in bases
+        #[arg(long, default_value_t = 150)]
+        read_length: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// encode and index the sequences of a FASTA file
+    Build {
+        /// target FASTA (plain or .gz)
+        targets: String,
+        
+        /// database file (default: the FASTA path with a .db extension)
+        #[arg(long)]
+        output: Option<String>,
+        
+        /// k-mer length of the index (1 to 31)
+        #[arg(long, default_value_t = sequence_db::DEFAULT_KMER)]
+        kmer: usize,
+    },
+    
+    /// align every query against the database and report each one's best hits
+    Search {
+        /// query FASTA (plain or .gz)
+        query: String,
+        
+        /// database written by db build
+        #[arg(long)]
+        db: String,
+        
+        /// align only against targets sharing at least this many distinct k-mers with the query (0: all targets)
+        #[arg(long, default_value = "0")]
+        min_shared_kmers: u32,
+        
+        /// hits reported per query
+        #[arg(long, default_value_t = sequence_db::DEFAULT_TOP_N)]
+        top_n: usize,
+        
+        /// report only hits scoring at least this
+        #[arg(long, default_value_t = sequence_db::DEFAULT_MIN_SCORE)]
+        min_score: i32,
+        
+        /// stop scoring a pair as soon as it can no longer reach this score (score-only screening)
+        #[arg(long)]
+        min_score_early_exit: Option<i32>,
+        
+        /// score on the CPU instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// hits TSV: query, rank, target, score, shared k-mers
+        #[arg(long, default_value = "db_hits.tsv")]
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SimulateCommand {
+    /// sample FASTQ reads from a reference FASTA with a sequencer error model
+    FromReference {
+        /// reference FASTA (plain or .gz)
+        reference: String,
+        
+        /// mean read depth over the reference
+        #[arg(long, default_value = "5")]
+        coverage: f64,
+        
+        /// error and quality model: hiseq, novaseq, perfect, or a profile file from learn-profile
+        #[arg(long, default_value = "hiseq")]
+        error_profile: String,
+        
+        /// read length (default: the profile's)
+        #[arg(long)]
+        read_length: Option<usize>,
+        
+        /// output FASTQ; gzipped if it ends in .gz
+        #[arg(long, default_value = "simulated_reads.fastq")]
+        output: String,
+        
+        /// random seed; the same seed gives the same reads (default: the top-level --seed)
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    
+    /// learn per-cycle qualities, N calls and read lengths from a real FASTQ, for --error-profile
+    LearnProfile {
+        /// FASTQ reads (plain or compressed)
+        input: String,
+        
+        /// profile file (JSON)
+        #[arg(long, visible_alias = "out", default_value = "error_profile.json")]
+        output: String,
+        
+        /// learn from at most this many reads, from the start of the file
+        #[arg(long, default_value_t = learned_profile::DEFAULT_MAX_READS)]
+        max_reads: usize,
+        
+        /// built-in profile to take the insertion and deletion rates from (indels can't be seen without a reference)
+        #[arg(long, default_value = "hiseq")]
+        indels_from: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KernelCommand {
+    /// list every OpenCL device, of any type, with the index kernel test takes
+    Devices,
+    
+    /// run the known-answer cases through each kernel variant and diff them against the CPU path
+    Test {
+        /// device index from kernel devices (default: the first GPU, or the first device without one)
+        #[arg(long, conflicts_with = "all_devices")]
+        device: Option<usize>,
+        
+        /// test every OpenCL device in turn
+        #[arg(long)]
+        all_devices: bool,
+        
+        /// only this kernel variant (basic, tiled, or packed)
+        #[arg(long)]
+        kernel: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BenchCommand {
+    /// time every kernel variant on the same pair and check they return the same score
+    Precision {
+        /// bases per sequence
+        #[arg(long, default_value = "8000000")]
+        length: usize,
+        
+        /// timed launches per kernel
+        #[arg(long, default_value = "5")]
+        runs: usize,
+    },
+    
+    /// time many launches of a small pair with kernels rebuilt and blocking uploads, then kept and event-driven: the fixed cost of a launch
+    Launch {
+        /// bases per sequence
+        #[arg(long, default_value_t = launch_reuse::DEFAULT_BENCH_BASES)]
+        bases: usize,
+        
+        /// timed launches per configuration
+        #[arg(long, default_value_t = launch_reuse::DEFAULT_BENCH_LAUNCHES)]
+        launches: usize,
+    },
+    
+    /// read and parse input files as a WGS run would, without aligning: the I/O ceiling
+    Io {
+        /// input files (plain, compressed, packed, BAM/CRAM, or remote, as in WGS_INPUT_FILES)
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    
+    /// align the first chunk of a file again and again from memory: the compute ceiling
+    Compute {
+        /// input file whose first chunk is aligned
+        file: String,
+        
+        /// timed alignments of the chunk
+        #[arg(long, default_value = "20")]
+        runs: usize,
+        
+        /// align on the SIMD CPU path instead of the GPU
+        #[arg(long)]
+        cpu: bool,
+        
+        /// also read the whole file as bench io does and say which side bounds the run
+        #[arg(long)]
+        with_io: bool,
+    },
+    
+    /// list stored benchmark runs, including aborted ones
+    List {
+        /// results directory (default: benchmark_results)
+        #[arg(long)]
+        results_dir: Option<String>,
+    },
+    
+    /// flatten stored benchmark runs into a table for plotting
+    Export {
+        /// output format (csv)
+        #[arg(long, default_value = "csv")]
+        format: String,
+        
+        /// one row per file of each run instead of one row per run
+        #[arg(long)]
+        per_file: bool,
+        
+        /// write to this file instead of stdout
+        #[arg(long)]
+        output: Option<String>,
+        
+        /// results directory (default: benchmark_results)
+        #[arg(long)]
+        results_dir: Option<String>,
+    },
+}
+
+// --splice-aware: transcript (seq1) against genomic sequence (seq2); files are
+// FASTA and their first record is used
+fn splice_align(seq1: &str, seq2: &str, files: bool, intron_open: i32, intron_extend: i32) {
+    let params = splice::SpliceParams::new(intron_open, intron_extend)
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    let load = |value: &str| -> Vec<u8> {
+        if !files {
+            return value.as_bytes().to_vec();
+        }
+        let mut records = simulate::load_reference(value).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let mut sequence = records.swap_remove(0).1;
+        sanitize::rna_to_dna(&mut sequence);
+        sequence
+    };
+    let (transcript, genomic) = (load(seq1), load(seq2));
+    let start = std::time::Instant::now();
+    let result = splice::align(&transcript, &genomic, params);
+    println!("Splice-aware alignment (CPU, intron open {} / extend {}):", params.intron_open, params.intron_extend);
+    println!("  Score: {}", result.score);
+    println!("  Ends: seq1 {} of {}, seq2 {} of {}", result.seq1_end, transcript.len(), result.seq2_end, genomic.len());
+    println!("  Introns: {} ({} bases)", result.introns, result.intron_bases);
+    println!("  Processing time: {:.2} ms", start.elapsed().as_secs_f64() * 1000.0);
+}
+
+fn dump_matrix(path: &str, seq1: &str, seq2: &str, files: bool) {
+    let format = score_matrix::MatrixFormat::from_path(path).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    let load = |value: &str| -> Vec<u8> {
+        if !files {
+            return value.as_bytes().to_vec();
+        }
+        let mut sequence = score_matrix::load_file(value).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        sanitize::rna_to_dna(&mut sequence);
+        sequence
+    };
+    let matrix = score_matrix::ScoreMatrix::compute(&load(seq1), &load(seq2), scoring::selected())
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    let file = std::fs::File::create(path).unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &format!("Failed to create {}: {}", path, e)));
+    let mut out = std::io::BufWriter::new(file);
+    matrix.write(format, &mut out)
+        .and_then(|_| std::io::Write::flush(&mut out))
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &format!("Failed to write {}: {}", path, e)));
+    let ((rows, columns), (best, row, column)) = (matrix.shape(), matrix.best());
+    println!("Score matrix ({}, {} x {}, gapped, CPU) written to {}: best {} at row {}, column {}", format.name(), rows, columns, path, best, row, column);
+}
+
+// --verify-kernel: stop before aligning if the selected kernel disagrees with the CPU path
+fn verify_kernel(device: &gpu::GpuDevice) {
+    let variant = kernels::selected();
+    match kernels::verify_against_cpu(variant, device) {
+        Ok(cases) => println!("Kernel check: {} kernel matches the CPU path on {} cases", variant.name(), cases),
+        Err(e) => exit_codes::fail(ExitKind::NoDevice, &format!("kernel check failed: {}", e)),
+    }
+}
+
+// The library Aligner the pair and --full-wgs modes run on, with the CLI's chunk size
+fn build_aligner(backend: Backend, params: AlignParams) -> Aligner {
+    let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    Aligner::builder().backend(backend).params(params).chunking(chunk_size).build()
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::NoDevice, &e))
+}
+
+// A CPU aligner scoring with the --scorer named `name`
+fn build_scored_aligner(name: &str, params: AlignParams) -> Aligner {
+    let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    let scorer = scorer::lookup(name).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &format!("--scorer: {}", e)));
+    Aligner::builder().backend(Backend::Cpu).params(params).chunking(chunk_size).scorer(scorer).build()
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e))
+}
+
+// --fallback: the first backend from `preferred` down the ladder that opens,
+// with a warning for each one passed over
+fn land(preferred: Backend, usable: &[Backend], params: AlignParams) -> Aligner {
+    let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    let warn = |rejected: &[backend_ladder::Rejection]| for rejection in rejected {
+        warnings::warn(warnings::Category::Device, format!("{} backend unavailable: {}", backend_ladder::name(rejection.backend), rejection.reason));
+    };
+    match backend_ladder::descend(preferred, usable, params, chunk_size) {
+        Ok((aligner, rejected)) => {
+            warn(&rejected);
+            if !rejected.is_empty() {
+                println!("Falling back to the {} backend", backend_ladder::name(aligner.backend()));
+            }
+            aligner
+        },
+        Err(rejected) => {
+            warn(&rejected);
+            let only = if usable.len() == 1 { format!("; this mode runs on {} only", backend_ladder::name(usable[0])) } else { String::new() };
+            exit_codes::fail(ExitKind::NoDevice, &format!("No backend on the fallback ladder could be opened{}", only))
+        },
+    }
+}
+
+// --keep-in-memory: load the pair once, then align it under the configured
+// scores and each --sweep set in turn
+fn align_in_memory(aligner: &Aligner, seq1: &str, seq2: &str, sweep: &[scoring::Scoring], contig_scores: Option<&str>) {
+    let start = std::time::Instant::now();
+    let dataset = in_memory::InMemoryDataset::load(seq1, seq2, aligner.chunk_size())
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+    let (chunks1, chunks2) = dataset.chunks();
+    println!("Kept {} bases in memory ({} packed; {} x {} chunks), loaded in {:.2} s",
+             dataset.bases(), planner::format_bytes(dataset.packed_bytes() as u64), chunks1, chunks2, start.elapsed().as_secs_f64());
+    let mut runs = vec![aligner.params().scoring];
+    for scoring in sweep {
+        if !runs.contains(scoring) {
+            runs.push(*scoring);
+        }
+    }
+    println!("Alignment results on {}:", aligner.device_name());
+    for (i, scoring) in runs.iter().enumerate() {
+        let result = aligner.align_dataset(&dataset, *scoring)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("alignment with {}: {}", scoring.name(), e)));
+        println!("  {}: score {} in {:.2} ms", scoring.name(), result.score, result.processing_time_ms);
+        // The contig report is the configured scores' run
+        if i == 0 {
+            report_contigs(&result.contigs, contig_scores);
+        }
+    }
+}
+
+// .env count, or the default when unset
+fn env_usize(name: &str, default: usize) -> Result<usize, String> {
+    match env::var(name) {
+        Ok(value) => value.trim().parse::<usize>()
+            .map_err(|e| format!("Invalid {} value '{}': {}", name, value, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+// The only place WGS settings are read from the environment; the library takes a WgsConfig
+fn wgs_config_from_env(args: &Args) -> Result<wgs_config::WgsConfig, String> {
+    let mut config = wgs_config::WgsConfig::new(
+        &env::var("WGS_DATA_DIR").unwrap_or_else(|_| "/path/to/wgs/data".to_string()),
+        &env::var("WGS_SAMPLE_ID").unwrap_or_else(|_| "SAMPLE_ID".to_string()),
+    );
+    config.lanes = env_usize("WGS_LANES", config.lanes)?;
+    config.reads_per_lane = env_usize("WGS_READS_PER_LANE", config.reads_per_lane)?;
+    // WGS_INPUT_FILES lists inputs explicitly; otherwise paths come from the lane layout
+    config.input_files = env::var("WGS_INPUT_FILES").ok()
+        .filter(|list| !list.trim().is_empty())
+        .map(|list| list.split(',').map(|f| f.to_string()).collect());
+    config.chunk_size = chunking::get()?;
+    if chunking::is_adaptive() {
+        config.adaptive_chunks = Some(adaptive_chunks::target_ms_from_env()?);
+    }
+    config.long_reads = if args.long_reads {
+        let tile_bases = match chunking::ChunkSize::parse(&args.tile_size)? {
+            chunking::ChunkSize::Bases(bases) => bases,
+            chunking::ChunkSize::Reads(_) => return Err(format!("Invalid --tile-size '{}': expected bases, e.g. 512KB or 1MB", args.tile_size)),
+        };
+        Some(long_reads::LongReadOptions::new(tile_bases, args.tile_overlap)?)
+    } else {
+        None
+    };
+    config.binning = read_binning::BinningPolicy::from_env()?;
+    config.sanitize = match &args.sanitize {
+        Some(action) => Some(sanitize::SanitizeOptions {
+            alphabet: sanitize::Alphabet::parse(&args.alphabet)?,
+            action: sanitize::InvalidAction::parse(action)?,
+        }),
+        None => None,
+    };
+    config.trim = args.trim_long_reads.as_deref()
+        .map(|kinds| long_read_trim::TrimOptions::parse(kinds, args.min_trimmed_length)?.with_env_adapters())
+        .transpose()?;
+    config.read_trim = read_trim::ReadTrimOptions::new(args.trim_head, args.trim_tail, args.trim_sliding_window.as_deref())?;
+    config.mask_low_complexity = args.mask_low_complexity;
+    config.screen = match &args.screen {
+        Some(panel) => {
+            let panel_dir = env::var("CONTAMINANT_PANEL_DIR").unwrap_or_else(|_| contamination::DEFAULT_PANEL_DIR.to_string());
+            Some(contamination::ScreenOptions::new(panel, &panel_dir, args.screen_kmer, args.screen_scale)?)
+        },
+        None => None,
+    };
+    config.qc = if args.qc { Some(qc::QcOptions::from_env()?) } else { None };
+    config.gpu_queues = gpu_queues::QueueCount::from_env()?;
+    config.expectations = wgs_summary::WgsExpectations::from_env();
+    config.alignment_input = alignment_input::AlignmentInputOptions {
+        format: alignment_input::InputFormat::parse(&args.input_format)?,
+        region: args.region.clone(),
+        reference: args.reference.as_deref()
+            .map(|reference| reference_cache::resolve(reference, &reference_cache_dir(None)))
+            .transpose()?,
+    };
+    let output_format = args.output_format.as_deref().map(read_output::ReadOutputFormat::parse).transpose()?;
+    config.read_output = match (&args.read_output, output_format) {
+        (Some(path), format) => Some(read_output::ReadOutputOptions::new(path, format, args.hash_read_names)),
+        (None, Some(read_output::ReadOutputFormat::Null)) => Some(read_output::ReadOutputOptions::new("", output_format, false)),
+        (None, Some(format)) => return Err(format!("--output-format {} needs --read-output", format.name())),
+        (None, None) => None,
+    };
+    config.read_group = match (&args.sample, &args.library, &args.platform_unit) {
+        (None, None, None) => None,
+        (sample, library, platform_unit) => Some(read_group::ReadGroup::new(
+            sample.as_deref().unwrap_or(&config.sample_id), library.as_deref(), platform_unit.as_deref())?),
+    };
+    config.selection = file_selection::FileSelection::new(args.num_files, args.file_range.as_deref(), &args.only_files)?;
+    config.order = file_order::FileOrder::parse(&args.order)?;
+    aggregation::validate(&args.aggregate)?;
+    config.aggregates = args.aggregate.clone();
+    config.quarantine_after = quarantine::quarantine_after_from_env()?;
+    config.memory_budget = match memory_profile::MemoryProfile::parse(&args.memory_profile)? {
+        memory_profile::MemoryProfile::Strict => Some(memory_profile::budget_from_env()?),
+        memory_profile::MemoryProfile::Default => None,
+    };
+    config.compress_checkpoints = checkpoint_store::compression_from_env()?;
+    config.deterministic = args.deterministic;
+    config.resume = args.resume;
+    Ok(config)
+}
+
+// Per-contig breakdown of a pair against a FASTA target, and the full table with --contig-scores
+fn report_contigs(contigs: &[contig_chunks::ContigScore], path: Option<&str>) {
+    contig_chunks::print(contigs);
+    if let Some(path) = path {
+        if contigs.is_empty() {
+            println!("  No contig scores for {} (seq2 isn't FASTA)", path);
+        } else if let Err(e) = contig_chunks::write_tsv(contigs, path) {
+            exit_codes::fail(ExitKind::DataError, &e);
+        } else {
+            println!("  Contig scores written to {}", path);
+        }
+    }
+}
+
+// --dir, else REFERENCE_CACHE_DIR, else ~/.cache/rustseq
+fn reference_cache_dir(dir: Option<&str>) -> std::path::PathBuf {
+    dir.map(std::path::PathBuf::from)
+        .or_else(|| env::var("REFERENCE_CACHE_DIR").ok().filter(|d| !d.trim().is_empty()).map(std::path::PathBuf::from))
+        .unwrap_or_else(reference_cache::default_cache_dir)
+}
+
+// --files means both inputs are paths; without it both must be sequences, so a
+// path passed by mistake is reported instead of being aligned as text
+fn validate_pair_inputs(seq1: &str, seq2: &str, files: bool) -> Result<(), String> {
+    for (flag, value) in [("--seq1", seq1), ("--seq2", seq2)] {
+        if files {
+            if !std::path::Path::new(value).is_file() {
+                return Err(format!("{} {}: no such file", flag, value));
+            }
+        } else if let Some((position, character)) = value.char_indices().find(|(_, character)| !character.is_ascii()) {
+            return Err(format!("{} has a non-ASCII character '{}' at position {}; sequences must be ASCII letters",
+                               flag, character, value[..position].chars().count() + 1));
+        } else if value.is_empty() || !value.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err(if std::path::Path::new(value).exists() {
+                format!("{} {} is a file path; pass --files to align file contents", flag, value)
+            } else {
+                format!("{} must be a sequence of letters (got {:?})", flag, value)
+            });
+        }
+    }
+    Ok(())
+}
+
+// Runs a walk into the track at `output` and the optional placements file
+fn write_walk(output: &str, placements: Option<&str>,
+              run: impl FnOnce(&mut dyn std::io::Write, Option<&mut dyn std::io::Write>) -> Result<chromosome_walk::WalkSummary, String>) -> chromosome_walk::WalkSummary {
+    let create = |path: &str| std::fs::File::create(path).map(std::io::BufWriter::new)
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", path, e)));
+    let mut track = create(output);
+    let mut placement_writer = placements.map(create);
+    run(&mut track, placement_writer.as_mut().map(|w| w as &mut dyn std::io::Write))
+        .and_then(|summary| std::io::Write::flush(&mut track).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+        .and_then(|summary| match placement_writer.as_mut() {
+            Some(writer) => std::io::Write::flush(writer).map(|_| summary).map_err(|e| format!("Failed to write placements: {}", e)),
+            None => Ok(summary),
+        })
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e))
+}
+
+fn print_walk(summary: &chromosome_walk::WalkSummary, window: usize, step: usize, placed_on: &str, output: &str, placements: Option<&str>) {
+    println!("   {} windows of {} bases every {} bases, {} placed {} ({} on the minus strand), {} batches",
+             summary.windows, window, step, summary.placed, placed_on, summary.minus_strand, summary.batches);
+    println!("   Track written to {}", output);
+    if let Some(path) = placements {
+        println!("   Placements written to {}", path);
+    }
+}
+
+fn main() {
+    // Load environment variables from .env file
+    dotenv::dotenv().ok();
+    
+    // Display system information at startup
+    if let Ok(system_info) = system_info::get_system_info() {
+        system_info.print_info();
+    }
+    
+    let args = Args::parse();
+    
+    seed::configure(args.seed);
+    // Scores are installed before any subcommand or program build reads them
+    let preset = args.preset.as_deref().map(|name| presets::lookup(name).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e)));
+    let scoring = presets::resolve_scoring(preset.as_ref(), args.match_score, args.mismatch_penalty, args.gap_penalty)
+        .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+    scoring::configure(scoring);
+    if let Some(preset) = &preset {
+        println!("Preset: {} ({})", preset.name, preset.description);
+    }
+    
+    // Batch mode: each sample runs as a separate --full-wgs process
+    if let Some(Commands::Batch { manifest, parallel, output_root, sample_args }) = &args.command {
+        match batch::run_batch(manifest, output_root, *parallel, sample_args) {
+            Ok(outcomes) => {
+                batch::print_summary(&outcomes);
+                println!("Summary saved to: {}/batch_summary.tsv", output_root);
+                batch::exit_kind(&outcomes).exit();
+            },
+            Err(e) => exit_codes::fail(ExitKind::ConfigError, &format!("batch: {}", e)),
+        }
+    }
+    
+    // Sweep mode: each grid point runs as a separate --full-wgs process
+    if let Some(Commands::Sweep { params, output_root, subsample, run_args }) = &args.command {
+        let params: Vec<sweep::Param> = params.iter().map(|spec| sweep::Param::parse(spec))
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        match sweep::run_sweep(&params, output_root, *subsample, run_args) {
+            Ok(outcomes) => {
+                sweep::print_summary(&outcomes);
+                println!("Results saved to: {}", sweep::results_path(std::path::Path::new(output_root)).display());
+                sweep::exit_kind(&outcomes).exit();
+            },
+            Err(e) => exit_codes::fail(ExitKind::ConfigError, &format!("sweep: {}", e)),
+        }
+    }
+    
+    if let Some(Commands::Bench { command: BenchCommand::List { results_dir } }) = &args.command {
+        let dir = results_dir.as_ref().map(std::path::PathBuf::from).unwrap_or_else(output_layout::results_dir);
+        match benchmark::load_runs(&dir) {
+            Ok(runs) => benchmark::print_run_list(&runs),
+            Err(e) => exit_codes::fail(ExitKind::DataError, &e),
+        }
+        return;
+    }
+    if let Some(Commands::Simulate { command: SimulateCommand::FromReference { reference, coverage, error_profile, read_length, output, seed } }) = &args.command {
+        let profile = simulate::ErrorProfile::parse(error_profile)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let config = simulate::SimulateConfig {
+            reference: reference.clone(),
+            coverage: *coverage,
+            read_length: read_length.unwrap_or(profile.read_length),
+            vary_length: read_length.is_none(),
+            profile,
+            output: output.clone(),
+            seed: seed.unwrap_or(args.seed),
+        };
+        if let Err(e) = config.validate() {
+            exit_codes::fail(ExitKind::ConfigError, &e);
+        }
+        let lengths = match &config.profile.learned {
+            Some(learned) if config.vary_length => format!("{}-{}", learned.length_percentiles[0], learned.longest()),
+            _ => config.read_length.to_string(),
+        };
+        println!("Simulating {}x {} bp reads from {} ({} profile, seed {})", coverage, lengths, reference, config.profile.name, config.seed);
+        match simulate::from_reference(&config) {
+            Ok(summary) => println!("Wrote {} reads ({} bases, {} errors) from {} contigs ({} bases) to {}",
+                                    summary.reads, summary.bases, summary.edits, summary.contigs, summary.reference_bases, output),
+            Err(e) => exit_codes::fail(ExitKind::DataError, &e),
+        }
+        return;
+    }
+    if let Some(Commands::Simulate { command: SimulateCommand::LearnProfile { input, output, max_reads, indels_from } }) = &args.command {
+        let indels_from = simulate::ErrorProfile::parse(indels_from)
+            .ok().filter(|profile| profile.learned.is_none())
+            .unwrap_or_else(|| exit_codes::fail(ExitKind::ConfigError, &format!("Invalid --indels-from '{}': expected hiseq, novaseq or perfect", indels_from)));
+        let start = std::time::Instant::now();
+        let profile = learned_profile::learn(input, *max_reads, &indels_from)
+            .and_then(|profile| profile.save(output).map(|_| profile))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        profile.print();
+        println!("Profile written to {} in {:.2} s; simulate with --error-profile {}", output, start.elapsed().as_secs_f64(), output);
+        return;
+    }
+    if let Some(Commands::Lookup { store, names }) = &args.command {
+        let mut store = read_store::ReadStore::open(store)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("#read_name\t{}", store.columns());
+        for name in names {
+            let values = store.get(name).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+            if values.is_empty() {
+                eprintln!("{}: not found", name);
+            }
+            for value in values {
+                println!("{}\t{}", name, value);
+            }
+        }
+        return;
+    }
+    if let Some(Commands::Evaluate { truth, results, tolerance, json }) = &args.command {
+        let report = evaluate::evaluate(truth, results, *tolerance)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        report.print();
+        if let Some(path) = json {
+            let written = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())
+                .and_then(|body| std::fs::write(path, body).map_err(|e| e.to_string()));
+            if let Err(e) = written {
+                exit_codes::fail(ExitKind::DataError, &format!("Failed to write {}: {}", path, e));
+            }
+        }
+        return;
+    }
+    if let Some(Commands::Ani { a, b, kmer, scale, window, json }) = &args.command {
+        let options = ani::AniOptions::new(*kmer, *scale, *window)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let start = std::time::Instant::now();
+        let report = ani::estimate(a, b, &options)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        report.print();
+        if let Some(path) = json {
+            let written = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())
+                .and_then(|body| std::fs::write(path, body).map_err(|e| e.to_string()));
+            if let Err(e) = written {
+                exit_codes::fail(ExitKind::DataError, &format!("Failed to write {}: {}", path, e));
+            }
+        }
+        println!("Estimated in {:.2} s", start.elapsed().as_secs_f64());
+        return;
+    }
+    if let Some(Commands::CompareAssemblies { a, b, output, breakpoints, json, band }) = &args.command {
+        let band = band.or(preset.map(|preset| preset.band)).unwrap_or(assembly_compare::DEFAULT_BAND);
+        if band == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "--band must be at least 1");
+        }
+        let start = std::time::Instant::now();
+        let report = assembly_compare::compare(a, b, band)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        report.print();
+        let mut written = report.write_tsv(output);
+        if let Some(path) = breakpoints {
+            written = written.and_then(|_| report.write_breakpoints(path));
+        }
+        if let Some(path) = json {
+            written = written.and_then(|_| serde_json::to_string_pretty(&report).map_err(|e| e.to_string())
+                .and_then(|body| std::fs::write(path, body).map_err(|e| format!("Failed to write {}: {}", path, e))));
+        }
+        if let Err(e) = written {
+            exit_codes::fail(ExitKind::DataError, &e);
+        }
+        println!("Compared in {:.2} s; pairs written to {}", start.elapsed().as_secs_f64(), output);
+        return;
+    }
+    if let Some(Commands::Walk { query, reference, window, step, cpu, output, placements }) = &args.command {
+        let step = step.unwrap_or(*window);
+        if *window == 0 || step == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "walk --window and --step must be at least 1");
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "walk needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let start = std::time::Instant::now();
+        let summary = write_walk(output, placements.as_deref(), |track, placements| {
+            chromosome_walk::walk(query, reference, *window, step, !*cpu, track, placements)
+        });
+        println!("Walked {} reference contigs against {} query contigs ({} bases) on the {} in {:.2} s",
+                 summary.contigs, summary.query_contigs, summary.query_bases, if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64());
+        print_walk(&summary, *window, step, "on the query", output, placements.as_deref());
+        return;
+    }
+    if let Some(Commands::Profile { genome, against, window, step, cpu, output, placements }) = &args.command {
+        let bases = |value: &str, flag: &str| kmer_spectrum::parse_genome_size(value)
+            .map(|bases| bases as usize)
+            .unwrap_or_else(|_| exit_codes::fail(ExitKind::ConfigError, &format!("Invalid profile --{} '{}': expected bases, optionally with k or m (e.g. 10k)", flag, value)));
+        let window = bases(window, "window");
+        let step = step.as_deref().map_or(window, |step| bases(step, "step"));
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "profile needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let start = std::time::Instant::now();
+        let summary = write_walk(output, placements.as_deref(), |track, placements| match against {
+            Some(against) => chromosome_walk::walk(against, genome, window, step, !*cpu, track, placements),
+            None => chromosome_walk::profile(genome, window, step, !*cpu, track, placements),
+        });
+        println!("Profiled {} contigs of {} against {} on the {} in {:.2} s",
+                 summary.contigs, genome, against.as_deref().unwrap_or("itself"), if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64());
+        print_walk(&summary, window, step, if against.is_some() { "on the other genome" } else { "on a copy elsewhere" }, output, placements.as_deref());
+        return;
+    }
+    if let Some(Commands::PairStats { reference, files, max_insert, cpu, output, coverage, coverage_bigwig }) = &args.command {
+        if files.len() % 2 != 0 {
+            exit_codes::fail(ExitKind::ConfigError, &format!("pair-stats takes R1 R2 file pairs, got {} files", files.len()));
+        }
+        if *max_insert == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "pair-stats --max-insert must be at least 1");
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "pair-stats needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let pairs: Vec<(String, String)> = files.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+        let start = std::time::Instant::now();
+        let report = pair_stats::pair_stats(reference, &pairs, *max_insert, !*cpu, coverage.is_some() || coverage_bigwig.is_some())
+            .and_then(|report| report.write(output).map(|_| report))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Aligned {} read pairs from {} file pairs against {} on the {} in {:.2} s",
+                 report.total.pairs, pairs.len(), reference, if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64());
+        report.print();
+        println!("   Report written to {}", output);
+        if let Some(track) = &report.track {
+            if let Some(path) = coverage {
+                track.write_bedgraph(path, paths::file_name(&pairs[0].0)).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+                println!("   Coverage bedGraph written to {}", path);
+            }
+            if let Some(path) = coverage_bigwig {
+                track.write_bigwig(path).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+                println!("   Coverage BigWig written to {}", path);
+            }
+        }
+        return;
+    }
+    if let Some(Commands::Translated { query, db, top_n, min_score, cpu, output }) = &args.command {
+        let limits = hit_limits::HitLimits::new(*top_n, *min_score)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "translated needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let start = std::time::Instant::now();
+        let summary = translated::screen(query, db, limits, !*cpu, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Translated {} reads in six frames against {} proteins on the {} in {:.2} s: {} reads with hits scoring >= {}, {} hits (top {}) written to {}",
+                 summary.reads, summary.targets, if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(), summary.reads_with_hits, min_score, summary.hits, top_n, output);
+        return;
+    }
+    if let Some(Commands::Msa { input, tree, format, cpu, output }) = &args.command {
+        let tree = msa::GuideTree::parse(tree).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let format = msa::MsaFormat::parse(format).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "msa needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let output = output.clone().unwrap_or_else(|| format.default_output().to_string());
+        let sequences = simulate::load_reference(input).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let start = std::time::Instant::now();
+        let alignment = msa::align(&sequences, tree, !*cpu).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let written = std::fs::File::create(&output).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            alignment.write(format, &mut writer)?;
+            std::io::Write::flush(&mut writer)
+        });
+        if let Err(e) = written {
+            exit_codes::fail(ExitKind::DataError, &format!("Failed to write {}: {}", output, e));
+        }
+        println!("Aligned {} sequences ({} guide tree, distances on the {}) in {:.2} s: {} columns, {} identical, written to {}",
+                 alignment.rows.len(), tree.name(), if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(),
+                 alignment.columns(), alignment.identical_columns(), output);
+        return;
+    }
+    if let Some(Commands::Distances { input, method, format, cpu, kmer, scale, window, output }) = &args.command {
+        let method = distances::DistanceMethod::parse(method).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let format = distances::DistanceFormat::parse(format).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let ani_options = ani::AniOptions::new(*kmer, *scale, *window).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let use_gpu = method == distances::DistanceMethod::Identity && !*cpu;
+        if use_gpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "distances needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let output = output.clone().unwrap_or_else(|| format.default_output().to_string());
+        let sequences = simulate::load_reference(input).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let start = std::time::Instant::now();
+        let matrix = distances::matrix(&sequences, method, &ani_options, use_gpu)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let names: Vec<&str> = sequences.iter().map(|(name, _)| name.as_str()).collect();
+        let written = std::fs::File::create(&output).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            distances::write(&names, &matrix, format, &mut writer)?;
+            std::io::Write::flush(&mut writer)
+        });
+        if let Err(e) = written {
+            exit_codes::fail(ExitKind::DataError, &format!("Failed to write {}: {}", output, e));
+        }
+        println!("{} distances between {} sequences in {:.2} s, written to {}",
+                 method.name(), sequences.len(), start.elapsed().as_secs_f64(), output);
+        return;
+    }
+    if let Some(Commands::PrimerScan { reads, primers, max_edits, cpu, output }) = &args.command {
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "primer-scan needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let primer_set = primer_scan::load_primers(primers, *max_edits).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let start = std::time::Instant::now();
+        let summary = primer_scan::scan(&primer_set, reads, *max_edits, !*cpu, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Scanned {} reads for {} primers on the {} in {:.2} s: {} reads with a primer within {} edits, {} hits written to {}",
+                 summary.reads, primer_set.len(), if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(),
+                 summary.reads_with_hits, max_edits, summary.hits, output);
+        for (primer, reads_hit) in primer_set.iter().zip(&summary.per_primer) {
+            println!("  {}: {} reads", primer.name, reads_hit);
+        }
+        return;
+    }
+    if let Some(Commands::CorrectBarcodes { reads, length, offset, max_edits, cpu, output }) = &args.command {
+        let options = barcode_correction::BarcodeOptions::new(*offset, *length, *max_edits, !*cpu)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "correct-barcodes needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let start = std::time::Instant::now();
+        let summary = barcode_correction::correct(reads, options, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Clustered {} distinct barcodes from {} reads ({} too short) on the {} in {:.2} s: {} clusters within {} edits, {} reads corrected, map written to {}",
+                 summary.distinct, summary.reads, summary.skipped, if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(),
+                 summary.clusters, max_edits, summary.reads_corrected, output);
+        return;
+    }
+    if let Some(Commands::Stats { files, adapters, output }) = &args.command {
+        let mut options = qc::QcOptions::from_env().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        if let Some(path) = adapters {
+            options.adapters = qc::load_adapters(path).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        }
+        let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let start = std::time::Instant::now();
+        let mut analyses = pipeline::Pipeline::new(chunk_size);
+        analyses.register(Box::new(qc::QcProcessor::new(options, output)));
+        analyses.run(files).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("QC of {} files in {:.2} s written to {}", files.len(), start.elapsed().as_secs_f64(), output);
+        return;
+    }
+    if let Some(Commands::Analyze { files, qc, adapters, screen, screen_kmer, screen_scale, kmer_spectrum, kmer_scale, kmer_hash, align, report_dir }) = &args.command {
+        if !*qc && screen.is_none() && kmer_spectrum.is_none() && !*align {
+            exit_codes::fail(ExitKind::ConfigError, "analyze needs at least one of --qc, --screen, --kmer-spectrum and --align");
+        }
+        let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let report_dir = std::path::Path::new(report_dir);
+        if let Err(e) = std::fs::create_dir_all(report_dir) {
+            exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", report_dir.display(), e));
+        }
+        let mut analyses = pipeline::Pipeline::new(chunk_size);
+        if *qc {
+            let mut options = qc::QcOptions::from_env().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+            if let Some(path) = adapters {
+                options.adapters = qc::load_adapters(path).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+            }
+            analyses.register(Box::new(qc::QcProcessor::new(options, report_dir.join("qc_report.json"))));
+        }
+        if let Some(panel) = screen {
+            let panel_dir = env::var("CONTAMINANT_PANEL_DIR").unwrap_or_else(|_| contamination::DEFAULT_PANEL_DIR.to_string());
+            let options = contamination::ScreenOptions::new(panel, &panel_dir, *screen_kmer, *screen_scale)
+                .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+            let panel = contamination::Panel::build(&options).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+            println!("Contamination screen: {} ({} sampled k-mers)", panel.names.join(", "), panel.sketch_size());
+            analyses.register(Box::new(contamination::ScreenProcessor::new(panel, report_dir.join("contamination.tsv"))));
+        }
+        if let Some(k) = kmer_spectrum {
+            let spectrum = kmer_hash::HashKind::parse(kmer_hash)
+                .and_then(|hash| kmer_spectrum::processor(hash, *k, *kmer_scale, report_dir.join("kmer_spectrum.tsv")))
+                .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+            analyses.register(spectrum);
+        }
+        if *align {
+            if !gpu::is_gpu_available() {
+                exit_codes::fail(ExitKind::NoDevice, "analyze --align needs a GPU and no compatible gpu was found");
+            }
+            let queues = gpu_queues::QueueCount::from_env().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+            let aligner = gpu::GpuContext::shared().and_then(|gpu| aligner::ChunkAligner::new(&gpu, queues))
+                .unwrap_or_else(|e| exit_codes::fail(ExitKind::NoDevice, &e));
+            analyses.register(Box::new(aligner));
+        }
+        let start = std::time::Instant::now();
+        analyses.run(files).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Analyzed {} files in one pass each in {:.2} s", files.len(), start.elapsed().as_secs_f64());
+        return;
+    }
+    if let Some(Commands::Kmer { command: KmerCommand::SuggestK { genome_size, error_rate, read_length } }) = &args.command {
+        let suggestion = kmer_spectrum::parse_genome_size(genome_size)
+            .and_then(|genome_size| kmer_spectrum::suggest_k(genome_size, *error_rate, *read_length))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        suggestion.print();
+        return;
+    }
+    if let Some(Commands::Db { command: DbCommand::Build { targets, output, kmer } }) = &args.command {
+        let output = output.clone().unwrap_or_else(|| std::path::Path::new(targets).with_extension("db").to_string_lossy().into_owned());
+        let start = std::time::Instant::now();
+        let db = sequence_db::SequenceDb::build(targets, *kmer).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        if let Err(e) = db.save(&output) {
+            exit_codes::fail(ExitKind::DataError, &e);
+        }
+        println!("Built {} from {}: {} targets ({} bases), {} {}-mer index entries in {:.2} s",
+                 output, targets, db.names.len(), db.targets.residues.len(), db.index_entries(), db.k, start.elapsed().as_secs_f64());
+        return;
+    }
+    if let Some(Commands::Db { command: DbCommand::Search { query, db, min_shared_kmers, top_n, min_score, min_score_early_exit, cpu, output } }) = &args.command {
+        let limits = hit_limits::HitLimits::new(*top_n, *min_score)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        if let Some(early_exit) = min_score_early_exit.filter(|&n| n < 1) {
+            exit_codes::fail(ExitKind::ConfigError, &format!("Invalid --min-score-early-exit {}: expected a positive score", early_exit));
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "db search needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let database = sequence_db::SequenceDb::load(db).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let options = sequence_db::SearchOptions {
+            min_shared_kmers: *min_shared_kmers, limits, use_gpu: !*cpu, early_exit: min_score_early_exit.unwrap_or(0),
+        };
+        let start = std::time::Instant::now();
+        let summary = sequence_db::search(&database, query, options, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Searched {} queries against {} targets on the {} in {:.2} s: {} alignments, {} queries with hits scoring >= {}, {} hits (top {}) written to {}",
+                 summary.queries, database.live_targets().len(), if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(), summary.alignments,
+                 summary.queries_with_hits, min_score, summary.hits, top_n, output);
+        if let Some(early_exit) = min_score_early_exit {
+            println!("Early exit: {} of {} pairs screened out below {}", summary.screened, summary.alignments, early_exit);
+        }
+        return;
+    }
+    if let Some(Commands::Align { command: AlignCommand::Pairs { pairs, cpu, output } }) = &args.command {
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "align pairs needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let start = std::time::Instant::now();
+        let summary = align_pairs::align(pairs, !*cpu, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Scored {} pairs ({} bases, {} files) in {} batches on the {} in {:.2} s; scores written to {}",
+                 summary.pairs, summary.bases, summary.files, summary.batches, if *cpu { "CPU" } else { "GPU" },
+                 start.elapsed().as_secs_f64(), output);
+        return;
+    }
+    if let Some(Commands::Overlap { reads, kmer, window, min_minimizers, min_overlap, min_score, cpu, output }) = &args.command {
+        let options = overlap::OverlapOptions {
+            kmer: *kmer,
+            window: *window,
+            min_minimizers: *min_minimizers,
+            min_overlap: *min_overlap,
+            min_score: *min_score,
+        };
+        if let Err(e) = options.validate() {
+            exit_codes::fail(ExitKind::ConfigError, &e);
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "overlap needs a GPU and no compatible gpu was found; pass --cpu to extend on the CPU");
+        }
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let start = std::time::Instant::now();
+        let summary = overlap::find_overlaps(reads, &options, !*cpu, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        summary.print();
+        println!("Found overlaps on the {} in {:.2} s; written to {}", if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(), output);
+        return;
+    }
+    if let Some(Commands::Parity { file1, file2, runs, cpu }) = &args.command {
+        if *runs == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "parity --runs must be at least 1");
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "parity compares the GPU paths and no compatible gpu was found; pass --cpu to compare the CPU paths");
+        }
+        let engine = build_aligner(if *cpu { Backend::Cpu } else { Backend::Gpu }, AlignParams::default());
+        println!("Parity on {} ({}, chunk size {}, {} run(s) per path)", engine.device_name(),
+                 if *cpu { "CPU paths" } else { "GPU paths" }, engine.chunk_size(), runs);
+        let cases = parity::run(file1, file2, &engine, *runs).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let mismatches = parity::print(&cases);
+        if mismatches > 0 {
+            exit_codes::fail(ExitKind::DataError, &format!("{} of {} cases differ between the legacy functions and the Aligner", mismatches, cases.len()));
+        }
+        println!("Every case matches between the legacy functions and the Aligner");
+        return;
+    }
+    if let Some(Commands::Soak { hours, synthetic, input, interval, max_gpu_growth_mb, max_rss_growth_mb, max_handle_growth,
+                                 max_throughput_drop, output, cpu }) = &args.command {
+        let options = soak::SoakOptions {
+            duration: std::time::Duration::from_secs_f64(hours.max(0.0) * 3600.0),
+            interval: std::time::Duration::from_secs(*interval),
+            limits: soak::SoakLimits {
+                max_gpu_growth_mb: *max_gpu_growth_mb,
+                max_rss_growth_mb: *max_rss_growth_mb,
+                max_handle_growth: *max_handle_growth,
+                max_throughput_drop: *max_throughput_drop,
+            },
+        };
+        if let Err(e) = options.validate() {
+            exit_codes::fail(ExitKind::ConfigError, &e);
+        }
+        if !*synthetic && input.is_none() {
+            exit_codes::fail(ExitKind::ConfigError, "soak needs a workload: --synthetic, or --input FILE");
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "soak runs on the GPU and no compatible gpu was found; pass --cpu to soak the CPU path");
+        }
+        let engine = build_aligner(if *cpu { Backend::Cpu } else { Backend::Gpu }, AlignParams::default());
+        let workload = match input {
+            Some(input) => soak::Workload::from_file(input, engine.chunk_size()).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e)),
+            None => soak::Workload::synthetic(),
+        };
+        let file = std::fs::File::create(output).unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &format!("Failed to create {}: {}", output, e)));
+        let mut log = std::io::BufWriter::new(file);
+        println!("Soaking {} for {} h on {}: {}, a sample every {} s to {}", if *cpu { "the CPU path" } else { "the GPU path" }, hours,
+                 engine.device_name(), workload.name, interval, output);
+        let summary = soak::run(&workload, &options, engine.device(), &mut log).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        summary.print(&workload);
+        if let Some(reason) = &summary.failure {
+            exit_codes::fail(ExitKind::Failure, &format!("Soak failed: {}", reason));
+        }
+        println!("Soak passed: no limit exceeded");
+        return;
+    }
+    if let Some(Commands::Debug { command: DebugCommand::ExtractChunk { run, chunk, dir, output } }) = &args.command {
+        let path = chunk_audit::audit_file(std::path::Path::new(dir), *run);
+        let log = chunk_audit::AuditLog::load(&path).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let entry = log.find(chunk).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        entry.print();
+        let output = output.clone().unwrap_or_else(|| format!("chunk_{}.fastq", chunk.replace(':', "_")));
+        let file = std::fs::File::create(&output).unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &format!("Failed to create {}: {}", output, e)));
+        let mut out = std::io::BufWriter::new(file);
+        let written = chunk_audit::extract(entry, &log.input, &mut out)
+            .and_then(|written| std::io::Write::flush(&mut out).map(|_| written).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Wrote {} reads to {}", written, output);
+        return;
+    }
+    if let Some(Commands::Run { command: RunCommand::Cancel { run, dir, wait, timeout } }) = &args.command {
+        let dir = std::path::Path::new(dir);
+        let record = cancel_request::find_run(dir, run).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let path = cancel_request::request(dir, &record).unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &e));
+        println!("Asked run {} ({}) to stop after its current chunk: {}", record.number, record.run_id, path.display());
+        if *wait {
+            cancel_request::wait(&path, std::time::Duration::from_secs(*timeout)).unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &e));
+            println!("Run {} has checkpointed and stopped; continue it with --resume", record.number);
+        }
+        return;
+    }
+    if let Some(Commands::Run { command: RunCommand::Diff { a, b, run_a, run_b, json } }) = &args.command {
+        let a = run_diff::RunSide::load(a, *run_a).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let b = run_diff::RunSide::load(b, *run_b).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let diff = run_diff::diff(&a, &b);
+        diff.print();
+        if let Some(path) = json {
+            let written = serde_json::to_string_pretty(&diff).map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e)));
+            if let Err(e) = written {
+                exit_codes::fail(ExitKind::DataError, &e);
+            }
+            println!("\nDiff written to {}", path);
+        }
+        return;
+    }
+    if let Some(Commands::Seq { command: SeqCommand::Transform { input, ops, cpu, output } }) = &args.command {
+        let transforms = seq_transform::parse_list(ops).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "seq transform needs a GPU and no compatible gpu was found; pass --cpu to transform on the CPU");
+        }
+        let packing = transforms.last() == Some(&seq_transform::Transform::Pack);
+        let output = output.clone().unwrap_or_else(|| if packing { "transformed.2bit" } else { "transformed.fa" }.to_string());
+        let start = std::time::Instant::now();
+        let summary = seq_transform::transform_fasta(input, &output, &transforms, !*cpu)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let names: Vec<&str> = transforms.iter().map(|t| t.name()).collect();
+        println!("Applied {} to {} records ({} bases) on the {} in {:.2} s, written to {}",
+                 names.join(", "), summary.records, summary.bases, if *cpu { "CPU" } else { "GPU" },
+                 start.elapsed().as_secs_f64(), output);
+        if summary.unpackable > 0 {
+            println!("Warning: {} bases other than A, C, G, T or U were packed as A", summary.unpackable);
+        }
+        return;
+    }
+    if let Some(Commands::Pssm { command: PssmCommand::Build { msa, output } }) = &args.command {
+        let pssm = pssm::Pssm::from_msa(msa).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        if let Err(e) = pssm.save(output, msa) {
+            exit_codes::fail(ExitKind::DataError, &e);
+        }
+        println!("Built {} from {}: {} positions over {} letters, best possible score {}",
+                 output, msa, pssm.len(), pssm.alphabet.len(), pssm.max_score());
+        return;
+    }
+    if let Some(Commands::Pssm { command: PssmCommand::Scan { targets, pssm, msa, min_score, cpu, output } }) = &args.command {
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "pssm scan needs a GPU and no compatible gpu was found; pass --cpu to score on the CPU");
+        }
+        let profile = match (pssm, msa) {
+            (Some(path), _) => pssm::Pssm::load(path),
+            (None, Some(path)) => pssm::Pssm::from_msa(path),
+            (None, None) => Err("pssm scan needs --pssm or --msa".to_string()),
+        }.unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let min_score = min_score.unwrap_or(profile.max_score() / 2);
+        let file = std::fs::File::create(output)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("Failed to create {}: {}", output, e)));
+        let mut writer = std::io::BufWriter::new(file);
+        let start = std::time::Instant::now();
+        let summary = pssm::scan(&profile, targets, min_score, !*cpu, &mut writer)
+            .and_then(|summary| std::io::Write::flush(&mut writer).map(|_| summary).map_err(|e| format!("Failed to write {}: {}", output, e)))
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Scanned {} sequences with a {}-position PSSM on the {} in {:.2} s: {} hits scoring >= {} written to {}",
+                 summary.targets, profile.len(), if *cpu { "CPU" } else { "GPU" }, start.elapsed().as_secs_f64(), summary.hits, min_score, output);
+        return;
+    }
+    if let Some(Commands::Reference { command: ReferenceCommand::Fetch { name, dir, md5, force } }) = &args.command {
+        let entry = reference_cache::lookup(name).unwrap_or_else(|| exit_codes::fail(ExitKind::ConfigError, &format!(
+            "Unknown reference '{}': expected one of {}", name,
+            reference_cache::CATALOG.iter().map(|e| e.name).collect::<Vec<_>>().join(", "))));
+        let cache_dir = reference_cache_dir(dir.as_deref());
+        let start = std::time::Instant::now();
+        let reference = reference_cache::fetch(entry, &cache_dir, md5.as_deref(), *force)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("{}: {} sequences, {} bases in {} ({:.0} s); use --reference {}",
+                 reference.name, reference.sequences, reference.bases, cache_dir.join(entry.name).display(),
+                 start.elapsed().as_secs_f64(), entry.name);
+        return;
+    }
+    if let Some(Commands::Index { command: IndexCommand::Add { targets, db, replace } }) = &args.command {
+        let start = std::time::Instant::now();
+        let added = sequence_db::SequenceDb::append(db, targets, *replace)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Added {} targets ({} bases, {} index entries) from {} to {} in {:.2} s",
+                 added.added, added.bases, added.index_entries, targets, db, start.elapsed().as_secs_f64());
+        if added.replaced > 0 {
+            println!("   {} targets of the same name tombstoned", added.replaced);
+        }
+        return;
+    }
+    if let Some(Commands::Index { command: IndexCommand::Remove { names, db } }) = &args.command {
+        let removed = sequence_db::SequenceDb::remove(db, names)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        println!("Tombstoned {} targets in {}", removed, db);
+        return;
+    }
+    if let Some(Commands::Index { command: IndexCommand::Gzip { file, span } }) = &args.command {
+        if *span == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "--span must be at least 1 MB");
+        }
+        let detected = input_format::detect_path(file).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        match detected.compression {
+            input_format::Compression::Gzip => {},
+            input_format::Compression::Bgzf => {
+                println!("{} is bgzf: its blocks are decoded in parallel without an index", file);
+                return;
+            },
+            other => exit_codes::fail(ExitKind::DataError, &format!("{} is {}, not gzip", file, other.name())),
+        }
+        let start = std::time::Instant::now();
+        let index = parallel_gzip::build_index(file, span * 1024 * 1024)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let index_path = parallel_gzip::index_path(file);
+        index.save(&index_path).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let index_size = std::fs::metadata(&index_path).map_or(0, |m| m.len());
+        println!("{}: {} access points over {:.1} MB of output ({:.0} s); index {} ({:.1} MB)",
+                 file, index.points.len(), index.total_output as f64 / 1e6, start.elapsed().as_secs_f64(),
+                 index_path, index_size as f64 / 1e6);
+        if !parallel_gzip::enabled() {
+            println!("DECOMPRESS_THREADS is 1, so the index won't be used until it is raised");
+        }
+        return;
+    }
+    if let Some(Commands::Prepare { files, names, qualities, force }) = &args.command {
+        for file in files {
+            if !force && packed_reads::is_prepared(file) {
+                println!("{}: {} is up to date (--force to convert again)", file, packed_reads::cache_path(file));
+                continue;
+            }
+            let start = std::time::Instant::now();
+            let summary = packed_reads::prepare(file, *names, *qualities)
+                .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+            println!("{}: {} reads, {} bases ({} not ACGT) in {:.0} s; {} ({:.1} MB, {:.0}% of the input)",
+                     file, summary.reads, summary.bases, summary.unpacked_bases, start.elapsed().as_secs_f64(),
+                     packed_reads::cache_path(file), summary.cache_bytes as f64 / 1e6,
+                     100.0 * summary.cache_bytes as f64 / summary.input_bytes.max(1) as f64);
+        }
+        return;
+    }
+    if let Some(Commands::Reference { command: ReferenceCommand::List { dir } }) = &args.command {
+        let cache_dir = reference_cache_dir(dir.as_deref());
+        println!("Reference cache: {}", cache_dir.display());
+        for entry in reference_cache::CATALOG {
+            let status = match reference_cache::cached(&cache_dir, entry) {
+                Some(reference) => format!("cached {} ({} bases)", reference.fetched.format("%Y-%m-%d"), reference.bases),
+                None => "not cached".to_string(),
+            };
+            println!("  {:<8} {:<55} {}  (also: {})", entry.name, entry.description, status, entry.aliases.join(", "));
+        }
+        return;
+    }
+    if let Some(Commands::Bench { command: BenchCommand::Export { format, per_file, output, results_dir } }) = &args.command {
+        let format = bench_export::ExportFormat::parse(format)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let dir = results_dir.as_ref().map(std::path::PathBuf::from).unwrap_or_else(output_layout::results_dir);
+        let runs = benchmark::load_runs(&dir).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e));
+        let table = bench_export::export(&runs, format, *per_file);
+        match output {
+            Some(path) => match std::fs::write(path, table) {
+                Ok(()) => eprintln!("Exported {} runs to {}", runs.len(), path),
+                Err(e) => exit_codes::fail(ExitKind::DataError, &format!("Failed to write {}: {}", path, e)),
+            },
+            None => print!("{}", table),
+        }
+        return;
+    }
+    if let Some(Commands::Kernel { command: KernelCommand::Devices }) = &args.command {
+        let devices = gpu::opencl_devices();
+        if devices.is_empty() {
+            exit_codes::fail(ExitKind::NoDevice, "No OpenCL devices found: no platform is installed, or none has a device");
+        }
+        for (index, (platform, device)) in devices.iter().enumerate() {
+            println!("{:>3}  {:<12} {} ({})", index, gpu::device_kind(*device),
+                     device.name().unwrap_or_else(|_| "Unknown".to_string()), platform.name().unwrap_or_else(|_| "Unknown platform".to_string()));
+        }
+        return;
+    }
+    if let Some(Commands::Kernel { command: KernelCommand::Test { device, all_devices, kernel } }) = &args.command {
+        let variants = match kernel {
+            Some(name) => vec![kernels::KernelVariant::parse(name).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e))],
+            None => kernels::KernelVariant::all().to_vec(),
+        };
+        let devices = gpu::opencl_devices();
+        if devices.is_empty() {
+            exit_codes::fail(ExitKind::NoDevice, "kernel test needs an OpenCL device and none was found");
+        }
+        let indexes: Vec<usize> = match device {
+            _ if *all_devices => (0..devices.len()).collect(),
+            Some(index) => vec![*index],
+            None => vec![devices.iter().position(|(_, device)| gpu::device_kind(*device) == "GPU").unwrap_or(0)],
+        };
+        let mut failed = 0;
+        for index in indexes {
+            let gpu = gpu::GpuContext::any_device(index).unwrap_or_else(|e| exit_codes::fail(ExitKind::NoDevice, &e));
+            println!("Kernel test on device {}: {} ({})", index, gpu.info().name, gpu::device_kind(gpu.device()));
+            match kernel_test::run(&gpu, &variants) {
+                Ok(results) => failed += kernel_test::print(&gpu.info().name, &results),
+                Err(e) => {
+                    println!("  FAIL kernels can't be built: {}", e);
+                    failed += 1;
+                },
+            }
+        }
+        if failed > 0 {
+            exit_codes::fail(ExitKind::NoDevice, &format!("kernel test: {} case(s) failed", failed));
+        }
+        return;
+    }
+    if let Some(Commands::Bench { command: BenchCommand::Io { files } }) = &args.command {
+        let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        println!("I/O ceiling: reading {} file(s) in chunks of {}, nothing aligned", files.len(), chunk_size);
+        match ceilings::measure_io(files, chunk_size) {
+            Ok(ceiling) => ceiling.print(),
+            Err(e) => exit_codes::fail(ExitKind::DataError, &format!("bench io: {}", e)),
+        }
+        return;
+    }
+    if let Some(Commands::Bench { command: BenchCommand::Compute { file, runs, cpu, with_io } }) = &args.command {
+        if *runs == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "bench compute needs --runs of 1 or more");
+        }
+        if !*cpu && !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "bench compute needs a GPU and no compatible gpu was found (--cpu times the CPU path)");
+        }
+        let chunk_size = chunking::get().unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let chunk = ceilings::first_chunk(file, chunk_size)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("bench compute: {}", e)));
+        println!("Compute ceiling: the first chunk of {} ({} reads, {} bases) aligned {} times from memory",
+                 paths::file_name(file), chunk.len(), chunk.bases().len(), runs);
+        let gpu = (!*cpu).then(|| gpu::GpuContext::shared().unwrap_or_else(|e| exit_codes::fail(ExitKind::NoDevice, &e)));
+        let compute = ceilings::measure_compute(&chunk, *runs, gpu.as_ref())
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &format!("bench compute: {}", e)));
+        compute.print();
+        if *with_io {
+            let io = ceilings::measure_io(std::slice::from_ref(file), chunk_size)
+                .unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &format!("bench io: {}", e)));
+            io.print();
+            ceilings::print_verdict(&io, &compute);
+        }
+        return;
+    }
+    if let Some(Commands::Bench { command: BenchCommand::Precision { length, runs } }) = &args.command {
+        if !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "bench precision needs a GPU and no compatible gpu was found");
+        }
+        let devices = gpu::get_gpu_devices();
+        println!("Kernel precision benchmark on {}: {} bases, {} runs per kernel", devices[0].name, length, runs);
+        match kernels::bench_precision(&devices[0], *length, *runs) {
+            Ok(results) => kernels::print_precision(&results),
+            Err(e) => exit_codes::fail(ExitKind::NoDevice, &format!("bench precision: {}", e)),
+        }
+        return;
+    }
+    
+    if let Some(Commands::Bench { command: BenchCommand::Launch { bases, launches } }) = &args.command {
+        if *bases == 0 {
+            exit_codes::fail(ExitKind::ConfigError, "bench launch needs --bases of at least 1");
+        }
+        if !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "bench launch needs a GPU and no compatible gpu was found");
+        }
+        let devices = gpu::get_gpu_devices();
+        println!("Launch overhead benchmark on {}: {} bases, {} launches per configuration", devices[0].name, bases, launches);
+        match launch_reuse::bench(&devices[0], *bases, *launches) {
+            Ok(results) => launch_reuse::print(&results),
+            Err(e) => exit_codes::fail(ExitKind::NoDevice, &format!("bench launch: {}", e)),
+        }
+        return;
+    }
     
-    /// treat inputs as file paths instead of direct sequences
-    #[arg(short, long, default_value = "false")]
-    files: bool,
+    // Display system information at startup
+    println!("Detecting system information...");
+    match system_info::get_system_info() {
+        Ok(system_info) => {
+            system_info.print_info();
+        }
+        Err(e) => {
+            warnings::warn(warnings::Category::Monitoring, format!("Could not detect system information: {}", e));
+            println!("Using fallback values for GPU memory and system specs");
+        }
+    }
     
-    /// chunk size in MB for large sequences (default: 1MB, chosen just because, no math reason)
-    #[arg(short, long, default_value = "1")]
-    chunk_size: usize,
+    // Mirror state files from the first one written
+    if let Some(url) = &args.remote_state {
+        if let Err(e) = remote_state::configure(url) {
+            exit_codes::fail(ExitKind::ConfigError, &e);
+        }
+    }
     
-    /// use GPU acceleration if available
-    #[arg(short, long, default_value = "false")]
-    gpu: bool,
+    // Structured per-run output directory
+    if let Some(dir) = &args.output_dir {
+        if let Err(e) = output_layout::install(dir) {
+            exit_codes::fail(ExitKind::ConfigError, &e);
+        }
+    }
     
-    /// number of files to process (for multi-file mode)
-    #[arg(short, long)]
-    num_files: Option<usize>,
+    // Before anything claims a run number, so numbering continues from the remote registry
+    if args.resume {
+        if let Err(e) = resume::restore_from_remote() {
+            exit_codes::fail(ExitKind::DataError, &e);
+        }
+    }
     
-    /// test mode: read WGS files from USB drive
-    #[arg(short, long, default_value = "false")]
-    test_wgs: bool,
+    // Thread pinning and NUMA placement for the input pipeline
+    let affinity_config = affinity::NumaMode::parse(&args.numa).and_then(|numa| {
+        affinity::configure(numa, args.pin_reader.as_deref(), args.pin_decompressor.as_deref(), args.pin_gpu)
+    });
+    match affinity_config {
+        Ok(config) => affinity::install(config),
+        Err(e) => exit_codes::fail(ExitKind::ConfigError, &e),
+    }
     
-    /// process full WGS dataset from all 16 files
-    #[arg(long, default_value = "false")]
-    full_wgs: bool,
-}
-
-fn main() {
-    // Load environment variables from .env file
-    dotenv::dotenv().ok();
+    // The CLI's other modes align on this thread; WGS runs take it from their config
+    determinism::set_enabled(args.deterministic);
+    // Installed now for the planner and --verify-kernel; the Aligner checks the same values
+    let params = AlignParams {
+        kernel: match &args.kernel {
+            Some(kernel) => kernels::KernelVariant::parse(kernel).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e)),
+            None => preset.map(|preset| preset.kernel).unwrap_or_default(),
+        },
+        n_policy: n_policy::NPolicy::parse(&args.n_policy).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e)),
+        scoring,
+    };
+    kernels::select(params.kernel);
+    n_policy::configure(params.n_policy);
     
-    // Display system information at startup
-    if let Ok(system_info) = system_info::get_system_info() {
-        system_info.print_info();
+    // Partition the GPU up front so the reader's queue depth already reflects the share
+    if let Some(fraction) = args.gpu_fraction {
+        if let Err(e) = gpu_share::configure(fraction) {
+            exit_codes::fail(ExitKind::ConfigError, &e);
+        }
+        if gpu::is_gpu_available() && gpu::get_opencl_context().is_ok() {
+            println!("GPU share: {}", gpu_share::describe());
+        }
     }
     
-    let args = Args::parse();
+    // One chunk size for every reader, from --chunk-size or .env
+    if let Some(size) = &args.chunk_size {
+        match chunking::ChunkSize::parse(size) {
+            Ok(size) => chunking::install(size),
+            Err(_) if size.trim().eq_ignore_ascii_case("auto") => chunking::install_adaptive(),
+            Err(e) => exit_codes::fail(ExitKind::ConfigError, &e),
+        }
+    }
+    if let Err(e) = chunking::get() {
+        exit_codes::fail(ExitKind::ConfigError, &e);
+    }
+    // Adapted chunk boundaries follow the timing, which --deterministic can't have
+    if chunking::is_adaptive() && args.deterministic {
+        exit_codes::fail(ExitKind::ConfigError, "An adaptive chunk size (auto) can't be combined with --deterministic; set a fixed --chunk-size");
+    }
     
-    // Display system information at startup
-    println!("Detecting system information...");
-    match system_info::get_system_info() {
-        Ok(system_info) => {
-            system_info.print_info();
+    if args.fallback && !args.gpu && !args.wgpu {
+        exit_codes::fail(ExitKind::ConfigError, "--fallback needs --gpu or --wgpu: the backend to start the ladder at");
+    }
+    
+    // Serve mode: WGS jobs from clients, each a run of its own on this process's GPU
+    if let Some(Commands::Serve { listen, max_jobs, slots, output_dir, db, top_n, min_score, max_batch, linger_ms }) = &args.command {
+        if !gpu::is_gpu_available() {
+            exit_codes::fail(ExitKind::NoDevice, "serve runs full WGS jobs, which need a GPU, and no compatible gpu was found");
         }
-        Err(e) => {
-            println!("Warning: Could not detect system information: {}", e);
-            println!("Using fallback values for GPU memory and system specs");
+        if let Some(dir) = output_dir {
+            if let Err(e) = output_layout::install(dir) {
+                exit_codes::fail(ExitKind::ConfigError, &e);
+            }
+        }
+        let base = wgs_config_from_env(&args).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        let listener = std::net::TcpListener::bind(listen)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &format!("Failed to listen on {}: {}", listen, e)));
+        let (aligner, scheduler) = (build_aligner(Backend::Gpu, params), gpu_scheduler::GpuScheduler::new(*slots));
+        let server = match db {
+            Some(path) => {
+                let limits = hit_limits::HitLimits::new(*top_n, *min_score)
+                    .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+                let database = std::sync::Arc::new(sequence_db::SequenceDb::load(path).unwrap_or_else(|e| exit_codes::fail(ExitKind::DataError, &e)));
+                let options = sequence_db::SearchOptions { min_shared_kmers: 0, limits, use_gpu: true, early_exit: 0 };
+                let batcher = query_batcher::QueryBatcher::new(std::sync::Arc::clone(&database), options, *max_batch, std::time::Duration::from_millis(*linger_ms))
+                    .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+                println!("Searching {} ({} targets), up to {} queries per launch", path, database.live_targets().len(), max_batch);
+                serve::Server::with_search(aligner, base, scheduler, *max_jobs, database, batcher)
+            }
+            None => serve::Server::new(aligner, base, scheduler, *max_jobs),
+        };
+        println!("Serving WGS jobs on {} ({} at once, {} GPU launch slots)", listen, max_jobs, slots);
+        if let Err(e) = server.serve(listener) {
+            exit_codes::fail(ExitKind::Failure, &e);
         }
+        return;
     }
     
     // Process full WGS dataset
     if args.full_wgs {
-        println!("Processing FULL WGS dataset from all 16 files...");
-        println!("This will process your complete 3.2B base pair genome!");
+        println!("Processing FULL WGS dataset...");
         
-        if !args.gpu || !gpu::is_gpu_available() {
-            eprintln!("error: gpu acceleration is required for full WGS processing");
-            std::process::exit(1);
+        if args.dry_run {
+            let config = wgs_config_from_env(&args)
+                .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+            let device = planner::planning_device();
+            let limits = planner::DeviceLimits::query(&device);
+            match planner::plan_wgs(&config, &device, &limits) {
+                Ok(plan) => {
+                    plan.print();
+                    if !plan.is_feasible() {
+                        ExitKind::ConfigError.exit();
+                    }
+                    return;
+                },
+                Err(e) => exit_codes::fail(ExitKind::ConfigError, &e),
+            }
         }
         
+        // WGS runs have no backend below OpenCL; --fallback only says why it's missing
+        let wgs_aligner = if args.fallback {
+            Some(land(Backend::Gpu, &[Backend::Gpu], params))
+        } else {
+            if !gpu::is_gpu_available() {
+                exit_codes::fail(ExitKind::NoDevice, "gpu acceleration is required for full WGS processing and no compatible gpu was found");
+            }
+            None
+        };
+        
+        let config = wgs_config_from_env(&args)
+            .unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        
+        notify::configure(notify::NotifyOptions {
+            webhook: args.notify_webhook.clone(),
+            desktop: args.notify_desktop,
+        });
+        run_cost::configure(run_cost::Pricing {
+            cost_per_hour: args.cost_per_hour,
+            instance_type: args.instance_type.clone(),
+        }).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        
         // Setup signal handlers for clean shutdown
         perf_logger::setup_signal_handlers();
         
         // Start system monitors
-        if let Err(e) = perf_logger::start_system_monitors() {
-            eprintln!("Warning: Failed to start system monitors: {}", e);
+        perf_logger::set_profile_mode(args.profile);
+        if let Err(e) = run_context::cli().start_monitors() {
+            warnings::warn(warnings::Category::Monitoring, format!("Failed to start system monitors: {}", e));
         }
         
         let devices = gpu::get_gpu_devices();
@@ -91,36 +2629,90 @@ fn main() {
         for device in &devices {
             println!("  Found GPU: {} ({} GB)", device.name, device.memory_gb);
         }
+        if args.verify_kernel {
+            verify_kernel(&devices[0]);
+        }
         
-        match aligner::process_full_wgs_dataset(&devices[0]) {
-            Ok(results) => {
+        if args.tui {
+            tui::enable();
+            if let Err(e) = tui::start() {
+                warnings::warn(warnings::Category::Monitoring, format!("Failed to start dashboard: {}", e));
+            }
+        }
+        let cancel = CancellationToken::new();
+        preemption::watch(&cancel).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        watchdog::watch(&cancel).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e));
+        cancel_request::watch(run_context::cli().run(), &cancel).unwrap_or_else(|e| exit_codes::fail(ExitKind::Failure, &e));
+        let wgs_result = wgs_aligner.unwrap_or_else(|| build_aligner(Backend::Gpu, params)).run_wgs_in(run_context::cli(), &config, &cancel, &mut |_| {});
+        tui::stop();
+        if preemption::is_preempted() {
+            preemption::shutdown();
+        }
+        if cancel_request::is_requested() && wgs_result.is_err() {
+            cancel_request::shutdown(run_context::cli().run());
+        }
+        match wgs_result {
+            Ok(report) => {
                 println!("\n🎉 FULL WGS PROCESSING COMPLETE! 🎉");
                 println!("==========================================");
-                println!("Total files processed: {}", results.len());
-                println!("Total reads processed: ~415 million");
-                println!("Total base pairs: ~62 billion");
-                println!("Genome coverage: ~19x");
-                println!("Total processing time: {:.2} seconds", 
-                    results.iter().map(|r| r.processing_time_ms).sum::<f64>() / 1000.0);
+                println!("Total files processed: {}", report.files.len());
+                let totals = &report.totals;
+                config.expectations.print_observed(totals.total_reads, totals.total_bases);
+                println!("Total processing time: {:.2} seconds", totals.processing_time_ms / 1000.0);
                 
-                for (i, result) in results.iter().enumerate() {
-                    println!("File {}: Score={}, Time={:.2}s", i+1, result.score, result.processing_time_ms/1000.0);
+                // Raw scores grow with input size, so compare files by the normalized metrics
+                for file in &report.files {
+                    println!("File {}: {}, Score={}, Time={:.2}s, {}{}", file.index + 1, file.name(), file.score, file.processing_time_ms/1000.0,
+                             file.normalized.summary(), file.partial_note());
+                }
+                println!("Total score: {} ({}){}", totals.score, totals.normalized.summary(), totals.partial_note());
+                if config.deterministic {
+                    determinism::report(run_context::cli(), config.chunk_size, &report.files);
                 }
                 
                 // Stop system monitors
-                if let Err(e) = perf_logger::stop_system_monitors() {
-                    eprintln!("Warning: Failed to stop system monitors: {}", e);
+                if let Err(e) = run_context::cli().stop_monitors() {
+                    warnings::warn(warnings::Category::Monitoring, format!("Failed to stop system monitors: {}", e));
+                }
+                
+                let mut notification = notify::RunNotification::new(notify::RunEvent::Completed);
+                notification.files_processed = report.files.len();
+                notification.total_reads = totals.total_reads;
+                notification.total_bases = totals.total_bases;
+                notification.total_score = totals.score;
+                notification.score_per_base = totals.normalized.score_per_base;
+                notify::send(&notification);
+                
+                let skipped = exit_codes::skipped_chunks();
+                if skipped > 0 {
+                    eprintln!("Warning: {} chunk(s) ({} reads, {} bases) failed and were skipped; totals are partial", skipped,
+                              exit_codes::skipped_reads(), exit_codes::skipped_bases());
+                }
+                let quarantined = exit_codes::quarantined_files();
+                if quarantined > 0 {
+                    eprintln!("Warning: {} file(s) quarantined after repeated failures; totals are partial (see {})", quarantined, quarantine::FAILURES_FILE);
                 }
+                warnings::finish(run_context::cli());
+                remote_state::flush();
+                exit_codes::completion_kind().exit();
             },
             Err(e) => {
                 eprintln!("Full WGS processing error: {}", e);
                 
                 // Stop system monitors on error
-                let _ = perf_logger::stop_system_monitors();
-                std::process::exit(1);
+                let _ = run_context::cli().stop_monitors();
+                let mut notification = notify::RunNotification::new(notify::RunEvent::Failed);
+                notification.error = Some(e);
+                notify::send(&notification);
+                warnings::finish(run_context::cli());
+                remote_state::flush();
+                // Over the --memory-profile strict budget is down to the settings
+                if run_context::cli().memory().exceeded() {
+                    ExitKind::ConfigError.exit();
+                }
+                ExitKind::DataError.exit();
             }
         }
-        return;
     }
     
     // Test WGS files from configured directory
@@ -137,8 +2729,9 @@ fn main() {
             format!("{}_L001_R2_001.fastq.gz", sample_id),
         ];
         
+        let mut failed = false;
         for file in &test_files {
-            let full_path = format!("{}/{}", wgs_path, file);
+            let full_path = paths::join(&wgs_path, file);
             println!("Testing: {}", full_path);
             match aligner::count_bases_in_fastq(&full_path) {
                 Ok(bases) => {
@@ -146,46 +2739,181 @@ fn main() {
                 },
                 Err(e) => {
                     println!("❌ Error counting bases in {}: {}", file, e);
+                    failed = true;
                 }
             }
         }
+        if failed {
+            ExitKind::DataError.exit();
+        }
         return;
     }
     
     // Require seq1 and seq2 for non-test mode
-    let seq1 = args.seq1.expect("--seq1 is required when not in test mode");
-    let seq2 = args.seq2.expect("--seq2 is required when not in test mode");
+    let (Some(seq1), Some(seq2)) = (args.seq1, args.seq2) else {
+        exit_codes::fail(ExitKind::ConfigError, "--seq1 and --seq2 are required when not in test mode");
+    };
+    if let Err(e) = validate_pair_inputs(&seq1, &seq2, args.files) {
+        exit_codes::fail(ExitKind::ConfigError, &e);
+    }
+    let sweep: Vec<scoring::Scoring> = args.sweep.iter()
+        .map(|value| scoring::Scoring::parse(value).unwrap_or_else(|e| exit_codes::fail(ExitKind::ConfigError, &e)))
+        .collect();
+    // RNA given on the command line aligns as DNA
+    let (seq1, seq2) = if args.files {
+        (seq1, seq2)
+    } else {
+        let mut bytes = (seq1.into_bytes(), seq2.into_bytes());
+        sanitize::rna_to_dna(&mut bytes.0);
+        sanitize::rna_to_dna(&mut bytes.1);
+        (String::from_utf8_lossy(&bytes.0).into_owned(), String::from_utf8_lossy(&bytes.1).into_owned())
+    };
+    
+    // Gapped alignment with an intron state, CPU only
+    if args.splice_aware {
+        splice_align(&seq1, &seq2, args.files, args.intron_open, args.intron_extend);
+        return;
+    }
+    
+    // The reference matrix first, so the run below prints its kernel's score
+    if let Some(path) = &args.dump_matrix {
+        dump_matrix(path, &seq1, &seq2, args.files);
+    }
+    
+    // A default-false --cpu satisfies clap's `requires`, so --scorer checks it here
+    if args.scorer.is_some() && !args.cpu {
+        exit_codes::fail(ExitKind::ConfigError, "--scorer runs on the CPU; pass --cpu");
+    }
+    
+    // --fallback: the backend asked for, or the first one below it that opens
+    let mut landed = (args.fallback && !args.dry_run)
+        .then(|| land(if args.wgpu { Backend::Wgpu } else { Backend::Cuda }, &backend_ladder::LADDER, params));
+    let backend = landed.as_ref().map_or(if args.cpu { Backend::Cpu } else if args.wgpu { Backend::Wgpu } else { Backend::Gpu }, Aligner::backend);
+    
+    // SIMD CPU path, no GPU required
+    if backend == Backend::Cpu {
+        let level = cpu_align::selected_level();
+        let aligner = match &args.scorer {
+            Some(name) => build_scored_aligner(name, params),
+            None => landed.take().unwrap_or_else(|| build_aligner(Backend::Cpu, params)),
+        };
+        let level = aligner.scorer().map_or(level.name().to_string(), |scorer| format!("{} scorer", scorer.name()));
+        if args.keep_in_memory {
+            align_in_memory(&aligner, &seq1, &seq2, &sweep, args.contig_scores.as_deref());
+        } else if args.files {
+            match aligner.align_files(&seq1, &seq2) {
+                Ok(result) => {
+                    println!("CPU Alignment Result:");
+                    println!("  Score: {}", result.score);
+                    println!("  Processing time: {:.2} ms", result.processing_time_ms);
+                    println!("  CPU path: {}", level);
+                    report_contigs(&result.contigs, args.contig_scores.as_deref());
+                },
+                Err(e) => {
+                    eprintln!("CPU alignment error: {}", e);
+                    ExitKind::DataError.exit();
+                }
+            }
+        } else {
+            match aligner.align(seq1.as_bytes(), seq2.as_bytes()) {
+                Ok(score) => println!("CPU Alignment score: {} ({})", score, level),
+                Err(e) => {
+                    eprintln!("CPU alignment error: {}", e);
+                    ExitKind::DataError.exit();
+                }
+            }
+        }
+        return;
+    }
+    
+    // Basic kernel through wgpu (Metal on macOS)
+    if backend == Backend::Wgpu {
+        if params.kernel != kernels::KernelVariant::Basic {
+            exit_codes::fail(ExitKind::ConfigError, &format!("--wgpu only has the basic kernel, not {}", params.kernel.name()));
+        }
+        if !cfg!(wgpu_backend) {
+            exit_codes::fail(ExitKind::ConfigError, "--wgpu needs a build with the wgpu backend (cargo build --features wgpu; always built on macOS)");
+        }
+        let aligner = landed.take().unwrap_or_else(|| build_aligner(Backend::Wgpu, params));
+        println!("wgpu device: {}", aligner.device_name());
+        if args.keep_in_memory {
+            align_in_memory(&aligner, &seq1, &seq2, &sweep, args.contig_scores.as_deref());
+            return;
+        }
+        let result = if args.files {
+            aligner.align_files(&seq1, &seq2).map(|result| (result.score, result.contigs))
+        } else {
+            aligner.align(seq1.as_bytes(), seq2.as_bytes()).map(|score| (score, Vec::new()))
+        };
+        match result {
+            Ok((score, contigs)) => {
+                println!("wgpu Alignment score: {}", score);
+                report_contigs(&contigs, args.contig_scores.as_deref());
+            },
+            Err(e) => {
+                eprintln!("wgpu alignment error: {}", e);
+                ExitKind::DataError.exit();
+            }
+        }
+        return;
+    }
     
     // GPU only
-    if !args.gpu || !gpu::is_gpu_available() {
-        eprintln!("error: gpu acceleration is required and no compatible gpu was found");
-        std::process::exit(1);
+    if !args.gpu {
+        exit_codes::fail(ExitKind::ConfigError, "--gpu, --cpu or --wgpu is required");
+    }
+    if args.dry_run {
+        let device = planner::planning_device();
+        let limits = planner::DeviceLimits::query(&device);
+        // File pairs are aligned chunk by chunk, sequences in one launch
+        let (len1, len2) = if args.files {
+            let chunk_bases = planner::chunk_launch_bases(chunking::get().unwrap_or(chunking::ChunkSize::Bases(chunking::DEFAULT_CHUNK_BASES)), &wgs_summary::WgsExpectations::default());
+            (chunk_bases, chunk_bases)
+        } else {
+            (seq1.len(), seq2.len())
+        };
+        let plan = planner::plan(len1, len2, planner::AlignmentMode::ScoreOnly, kernels::selected(), &device, &limits);
+        plan.print();
+        if !plan.is_feasible() {
+            ExitKind::ConfigError.exit();
+        }
+        return;
+    }
+    if !gpu::is_gpu_available() {
+        exit_codes::fail(ExitKind::NoDevice, "gpu acceleration is required and no compatible gpu was found");
     }
             println!("GPU acceleration enabled");
             let devices = gpu::get_gpu_devices();
             for device in &devices {
                 println!("  Found GPU: {} ({} GB)", device.name, device.memory_gb);
     }
+    if args.verify_kernel {
+        verify_kernel(&devices[0]);
+    }
     
-    if args.files {
-        match aligner::gpu_align_pair(&seq1, &seq2, &devices[0]) {
+    let aligner = landed.take().unwrap_or_else(|| build_aligner(Backend::Gpu, params));
+    if args.keep_in_memory {
+        align_in_memory(&aligner, &seq1, &seq2, &sweep, args.contig_scores.as_deref());
+    } else if args.files {
+        match aligner.align_files(&seq1, &seq2) {
                 Ok(result) => {
                     println!("GPU Alignment Result:");
                     println!("  Score: {}", result.score);
                     println!("  Processing time: {:.2} ms", result.processing_time_ms);
                     println!("  GPU device: {}", result.gpu_device);
+                    report_contigs(&result.contigs, args.contig_scores.as_deref());
                 },
                 Err(e) => {
                     eprintln!("GPU alignment error: {}", e);
-                    std::process::exit(1);
+                    ExitKind::DataError.exit();
             }
         }
     } else {
-        match aligner::gpu_align(&seq1, &seq2, &devices[0]) {
+        match aligner.align(seq1.as_bytes(), seq2.as_bytes()) {
                     Ok(score) => println!("GPU Alignment score: {}", score),
                     Err(e) => {
                         eprintln!("GPU alignment error: {}", e);
-                std::process::exit(1);
+                ExitKind::DataError.exit();
             }
         }
     }
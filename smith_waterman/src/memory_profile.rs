@@ -0,0 +1,350 @@
+// --memory-profile: how a WGS run's host memory is held in check
+//   default  nothing is tracked; memory follows the chunk size, channel depths and GPU queues
+//   strict   every allocation site below draws from a budget, and the run stops with a
+//            message naming the sites the moment a reservation would go over it, instead
+//            of the host OOM-killing the process mid-run
+// Sites:
+//   chunks          read chunks between and inside the stages, at their allocated
+//                   capacity, and the low-complexity masked copies
+//   batches         host copies of the launches in flight on the GPU queues
+//   output buffers  row groups and index entries the per-read writer holds
+//   k-mer tables    the contamination panel's sketch and the QC duplicate sample
+// Each holder keeps a Reservation that grows with its buffers and is released
+// when it is dropped. The budget, each site's use and whether it was exceeded
+// belong to the run (RunContext::memory): runs in one process each keep to
+// their own budget, and one run's breach doesn't stop another. The budget is
+// MEMORY_BUDGET in .env (e.g. 24GB or 512MB; a bare number is GB), or
+// BUDGET_SHARE of system RAM. Before the first file the planned peak (every
+// chunk the stages and channels can hold, and the launches in flight) is
+// checked against it, so a run that can't fit stops before reading
+// anything. A breach stops the run with exit code 2 and doesn't count towards
+// quarantine: the settings are at fault, not the file
+
+use crate::planner::format_bytes;
+use crate::stages::Stage;
+use crate::wgs_config::WgsConfig;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Share of system RAM budgeted when MEMORY_BUDGET isn't set
+const BUDGET_SHARE: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryProfile {
+    #[default]
+    Default,
+    Strict,
+}
+
+impl MemoryProfile {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "default" => Ok(MemoryProfile::Default),
+            "strict" => Ok(MemoryProfile::Strict),
+            _ => Err(format!("Invalid --memory-profile '{}': expected default or strict", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryProfile::Default => "default",
+            MemoryProfile::Strict => "strict",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Site {
+    #[default]
+    Chunks,
+    Batches,
+    OutputBuffers,
+    KmerTables,
+}
+
+const SITES: [Site; 4] = [Site::Chunks, Site::Batches, Site::OutputBuffers, Site::KmerTables];
+
+impl Site {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Site::Chunks => "chunks",
+            Site::Batches => "batches",
+            Site::OutputBuffers => "output buffers",
+            Site::KmerTables => "k-mer tables",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Account {
+    used: AtomicU64,
+    peak: AtomicU64,
+}
+
+// One run's budget, what each site holds against it and whether a reservation
+// went over it. RunContext::memory is the run's; the default tracks nothing
+#[derive(Debug, Default)]
+pub struct MemoryBudget {
+    strict: AtomicBool,
+    exceeded: AtomicBool,
+    budget: AtomicU64,
+    total: Account,
+    accounts: [Account; 4],
+}
+
+// MEMORY_BUDGET from .env in bytes, or BUDGET_SHARE of system RAM
+pub fn budget_from_env() -> Result<u64, String> {
+    match std::env::var("MEMORY_BUDGET") {
+        Ok(value) => parse_budget(&value),
+        Err(_) => {
+            let mut system = sysinfo::System::new();
+            system.refresh_memory();
+            match system.total_memory() {
+                0 => Err("MEMORY_BUDGET is needed with --memory-profile strict: system RAM couldn't be read".to_string()),
+                total => Ok((total as f64 * BUDGET_SHARE) as u64),
+            }
+        },
+    }
+}
+
+fn parse_budget(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid MEMORY_BUDGET '{}': expected e.g. 24GB or 512MB", value);
+    let lower = value.trim().to_ascii_lowercase();
+    let split = lower.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let scale = match unit.trim() {
+        "" | "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "t" | "tb" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(invalid()),
+    };
+    match (number * scale) as u64 {
+        0 => Err(invalid()),
+        bytes => Ok(bytes),
+    }
+}
+
+impl MemoryBudget {
+    fn account(&self, site: Site) -> &Account {
+        &self.accounts[site as usize]
+    }
+
+    // Track allocations against `budget` from now on (None: the default profile)
+    pub fn start(&self, budget: Option<u64>) {
+        self.strict.store(budget.is_some(), Ordering::SeqCst);
+        self.exceeded.store(false, Ordering::SeqCst);
+        self.budget.store(budget.unwrap_or(0), Ordering::SeqCst);
+        for account in self.accounts.iter().chain([&self.total]) {
+            account.peak.store(account.used.load(Ordering::SeqCst), Ordering::SeqCst);
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict.load(Ordering::Relaxed)
+    }
+
+    // Whether a reservation went over the budget; the run stops on the first one
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::SeqCst)
+    }
+
+    // check_plan against this budget, marking it exceeded on Err
+    pub fn check_plan(&self, config: &WgsConfig, budget: u64) -> Result<u64, String> {
+        check_plan(config, budget).inspect_err(|_| self.exceeded.store(true, Ordering::SeqCst))
+    }
+
+    fn usage(&self) -> String {
+        SITES.iter()
+            .map(|site| format!("{} {}", site.name(), format_bytes(self.account(*site).used.load(Ordering::SeqCst))))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // Peak use of each site since start(), e.g. "peak 3.1 GB of 24.0 GB (chunks 2.9 GB, ...)"
+    pub fn summary(&self) -> String {
+        let peaks: Vec<String> = SITES.iter()
+            .map(|site| format!("{} {}", site.name(), format_bytes(self.account(*site).peak.load(Ordering::SeqCst))))
+            .collect();
+        format!("peak {} of {} ({})", format_bytes(self.total.peak.load(Ordering::SeqCst)),
+                format_bytes(self.budget.load(Ordering::SeqCst)), peaks.join(", "))
+    }
+
+    // Take `grow` more bytes for `site`, or Err naming the sites if that would
+    // go over the budget
+    fn grow(&self, site: Site, grow: u64) -> Result<(), String> {
+        let budget = self.budget.load(Ordering::Relaxed);
+        let reserved = self.total.used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+            (used + grow <= budget).then_some(used + grow)
+        });
+        match reserved {
+            Ok(used) => {
+                self.total.peak.fetch_max(used + grow, Ordering::SeqCst);
+                let account = self.account(site);
+                let site_used = account.used.fetch_add(grow, Ordering::SeqCst) + grow;
+                account.peak.fetch_max(site_used, Ordering::SeqCst);
+                Ok(())
+            },
+            Err(used) => {
+                self.exceeded.store(true, Ordering::SeqCst);
+                Err(format!("Memory budget exceeded: {} more for {} would bring tracked memory to {} of the {} budget ({}); \
+                             raise MEMORY_BUDGET or lower --chunk-size, GPU_QUEUES or the channel depths",
+                            format_bytes(grow), site.name(), format_bytes(used + grow), format_bytes(budget), self.usage()))
+            },
+        }
+    }
+
+    fn release(&self, site: Site, bytes: u64) {
+        self.total.used.fetch_sub(bytes, Ordering::SeqCst);
+        self.account(site).used.fetch_sub(bytes, Ordering::SeqCst);
+    }
+}
+
+// Bytes one allocation site holds against a run's budget; released when
+// dropped. A reservation not held against a budget tracks nothing. Clones keep
+// the budget, start empty and hold nothing until they are resized
+#[derive(Debug, Default)]
+pub struct Reservation {
+    memory: Option<Arc<MemoryBudget>>,
+    site: Site,
+    bytes: u64,
+}
+
+impl Reservation {
+    pub fn new(site: Site) -> Self {
+        Self { memory: None, site, bytes: 0 }
+    }
+
+    pub fn against(memory: &Arc<MemoryBudget>, site: Site) -> Self {
+        Self { memory: Some(memory.clone()), site, bytes: 0 }
+    }
+
+    pub fn reserve(memory: &Arc<MemoryBudget>, site: Site, bytes: usize) -> Result<Self, String> {
+        let mut reservation = Self::against(memory, site);
+        reservation.resize(bytes)?;
+        Ok(reservation)
+    }
+
+    // Hold against `memory` from now on, releasing what was held elsewhere;
+    // the next resize takes the bytes again
+    pub fn hold_against(&mut self, memory: &Arc<MemoryBudget>) {
+        if !self.memory.as_ref().is_some_and(|held| Arc::ptr_eq(held, memory)) {
+            *self = Self::against(memory, self.site);
+        }
+    }
+
+    // Hold `bytes` in all; Err if growing would go over the budget. A no-op
+    // outside the strict profile
+    pub fn resize(&mut self, bytes: usize) -> Result<(), String> {
+        let bytes = bytes as u64;
+        let Some(memory) = self.memory.as_ref().filter(|memory| memory.is_strict()) else {
+            return Ok(());
+        };
+        if bytes < self.bytes {
+            memory.release(self.site, self.bytes - bytes);
+        } else if bytes > self.bytes {
+            memory.grow(self.site, bytes - self.bytes)?;
+        }
+        self.bytes = bytes;
+        Ok(())
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl Clone for Reservation {
+    fn clone(&self) -> Self {
+        Self { memory: self.memory.clone(), site: self.site, bytes: 0 }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(memory) = self.memory.as_ref().filter(|_| self.bytes > 0) {
+            memory.release(self.site, self.bytes);
+        }
+    }
+}
+
+// Planned peak of each site for `config`: every chunk the stages and channels
+// can hold at once, with its quality copy when qualities are kept and its masked
+// copy when masking, and both sequences of every launch in flight. Names and
+// the k-mer tables, which depend on the input, aren't planned
+pub fn planned_peak(config: &WgsConfig) -> Vec<(Site, u64)> {
+    let chunk_bases = crate::planner::chunk_launch_bases(config.launch_chunk_size(), &config.expectations) as u64;
+    let qualities = config.qc.is_some() || config.read_trim.is_some_and(|trim| trim.window.is_some());
+    let chunk_bytes = chunk_bases * if qualities { 2 } else { 1 };
+    // In the parser-to-encoder and encoder-to-GPU channels, plus one being filled
+    // by the reader, two in the encoder (with its spare), one at the GPU and one
+    // at the writer; the GPU-to-writer channel holds chunks too
+    let queued = Stage::Parser.channel_depth() + Stage::Encoder.channel_depth() + Stage::Gpu.channel_depth();
+    let mut chunks = (queued as u64 + 5) * chunk_bytes;
+    if config.mask_low_complexity {
+        chunks += (Stage::Encoder.channel_depth() + Stage::Gpu.channel_depth() + 2) as u64 * chunk_bases;
+    }
+    let in_flight = match (config.read_output.is_some(), config.gpu_queues) {
+        (true, _) => 1,
+        (false, crate::gpu_queues::QueueCount::Auto) => crate::gpu_queues::MAX_QUEUES,
+        (false, crate::gpu_queues::QueueCount::Fixed(n)) => n,
+    };
+    vec![(Site::Chunks, chunks), (Site::Batches, in_flight as u64 * 2 * chunk_bases)]
+}
+
+// Err when the planned peak alone is over `budget`
+pub fn check_plan(config: &WgsConfig, budget: u64) -> Result<u64, String> {
+    let planned = planned_peak(config);
+    let total = planned.iter().map(|(_, bytes)| bytes).sum::<u64>();
+    if total > budget {
+        let sites: Vec<String> = planned.iter().map(|(site, bytes)| format!("{} {}", site.name(), format_bytes(*bytes))).collect();
+        return Err(format!("--memory-profile strict: the run plans for up to {} ({}), over the {} budget; \
+                            raise MEMORY_BUDGET or lower --chunk-size, GPU_QUEUES or the channel depths",
+                           format_bytes(total), sites.join(", "), format_bytes(budget)));
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn runs_keep_their_own_budget() {
+        let (strict, default) = (Arc::new(MemoryBudget::default()), Arc::new(MemoryBudget::default()));
+        strict.start(Some(MB));
+        default.start(None);
+        let mut chunk = Reservation::reserve(&strict, Site::Chunks, (MB / 2) as usize).unwrap();
+        assert_eq!(Reservation::reserve(&default, Site::Chunks, (2 * MB) as usize).unwrap().bytes(), 0);
+
+        // The other run going over its budget neither fails this one nor resets its peaks
+        let other = Arc::new(MemoryBudget::default());
+        other.start(Some(MB));
+        assert!(Reservation::reserve(&other, Site::Batches, (2 * MB) as usize).is_err());
+        assert!(other.exceeded() && !strict.exceeded());
+        assert!(strict.summary().starts_with(&format!("peak {} of", format_bytes(MB / 2))));
+
+        assert!(chunk.resize((2 * MB) as usize).is_err());
+        assert!(strict.exceeded() && !default.exceeded());
+        drop(chunk);
+        assert_eq!(strict.total.used.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn reservations_move_between_budgets() {
+        let (first, second) = (Arc::new(MemoryBudget::default()), Arc::new(MemoryBudget::default()));
+        first.start(Some(MB));
+        second.start(Some(MB));
+        let mut reservation = Reservation::new(Site::Chunks);
+        reservation.resize(MB as usize).unwrap();
+        assert_eq!(reservation.bytes(), 0);
+        reservation.hold_against(&first);
+        reservation.resize(1000).unwrap();
+        reservation.hold_against(&second);
+        assert_eq!(first.account(Site::Chunks).used.load(Ordering::SeqCst), 0);
+        reservation.resize(1000).unwrap();
+        assert_eq!(second.account(Site::Chunks).used.load(Ordering::SeqCst), 1000);
+    }
+}
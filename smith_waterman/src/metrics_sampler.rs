@@ -0,0 +1,337 @@
+// In-process sampling of the metrics shown in the monitoring summary
+// A background thread reads /proc (sysinfo on Windows and macOS, and
+// nvidia-smi's CSV query interface for the GPU) at a fixed interval and appends one JSON object per sample to
+// logs/run_N/metrics.jsonl, so the summary doesn't depend on the text layout of
+// vmstat/iostat/pidstat/nvidia-smi dmon, which differs between versions
+// Set METRICS_SAMPLE_INTERVAL_MS in .env to change the interval (default 1000)
+
+use crate::tools::benchmark::CpuUtilizationSummary;
+use crate::warnings::{self, Category};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub const METRICS_FILE: &str = "metrics.jsonl";
+
+// Most recent sample, for the live dashboard
+static LATEST: Lazy<Mutex<Option<MetricsSample>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn latest() -> Option<MetricsSample> {
+    LATEST.lock().ok().and_then(|s| s.clone())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSample {
+    pub timestamp: DateTime<Utc>,
+    pub elapsed_s: f64,
+    pub cpu_user_percent: f64,
+    pub cpu_system_percent: f64,
+    pub cpu_idle_percent: f64,
+    pub ram_used_gb: f64,
+    pub disk_read_mbps: f64,
+    pub context_switches_per_s: f64,
+    // None when nvidia-smi isn't available
+    pub gpu_util_percent: Option<f64>,
+    pub gpu_memory_used_mb: Option<f64>,
+    // Board power summed over every GPU; None where nvidia-smi can't read it,
+    // and in samples recorded before power was sampled
+    #[serde(default)]
+    pub gpu_power_watts: Option<f64>,
+}
+
+fn sample_interval() -> Duration {
+    let ms = std::env::var("METRICS_SAMPLE_INTERVAL_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|&ms| ms > 0)
+        .unwrap_or(1000);
+    Duration::from_millis(ms)
+}
+
+// Cumulative counters, diffed between samples
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    cpu_user: u64,
+    cpu_system: u64,
+    cpu_idle: u64,
+    context_switches: u64,
+    sectors_read: u64,
+}
+
+impl Counters {
+    #[cfg(target_os = "linux")]
+    fn read() -> Self {
+        let mut counters = Counters::default();
+        if let Ok(stat) = std::fs::read_to_string("/proc/stat") {
+            for line in stat.lines() {
+                let mut fields = line.split_whitespace();
+                match fields.next() {
+                    Some("cpu") => {
+                        // user nice system idle iowait irq softirq steal
+                        let v: Vec<u64> = fields.take(8).filter_map(|f| f.parse().ok()).collect();
+                        if v.len() == 8 {
+                            counters.cpu_user = v[0] + v[1];
+                            counters.cpu_system = v[2] + v[5] + v[6] + v[7];
+                            counters.cpu_idle = v[3] + v[4];
+                        }
+                    },
+                    Some("ctxt") => {
+                        counters.context_switches = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+                    },
+                    _ => {},
+                }
+            }
+        }
+        if let Ok(diskstats) = std::fs::read_to_string("/proc/diskstats") {
+            for line in diskstats.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                // Whole disks only (partitions would be counted twice)
+                if fields.len() < 6 || fields[2].starts_with("loop") || fields[2].starts_with("ram")
+                    || !std::path::Path::new("/sys/block").join(fields[2]).exists() {
+                    continue;
+                }
+                counters.sectors_read += fields[5].parse::<u64>().unwrap_or(0);
+            }
+        }
+        counters
+    }
+
+    // sysinfo reports CPU usage as a percentage since its last refresh, so busy
+    // and idle time are accumulated here in milliseconds; it has no user/system
+    // split (busy time counts as user) and no context switch count (stays 0)
+    #[cfg(not(target_os = "linux"))]
+    fn read() -> Self {
+        static SOURCE: Lazy<Mutex<(sysinfo::System, sysinfo::Disks, Counters, Instant)>> = Lazy::new(|| {
+            let mut system = sysinfo::System::new();
+            system.refresh_cpu_usage();
+            Mutex::new((system, sysinfo::Disks::new_with_refreshed_list(), Counters::default(), Instant::now()))
+        });
+        let Ok(mut source) = SOURCE.lock() else { return Counters::default() };
+        let (system, disks, counters, refreshed_at) = &mut *source;
+        system.refresh_cpu_usage();
+        let ms = refreshed_at.elapsed().as_secs_f64() * 1000.0;
+        *refreshed_at = Instant::now();
+        let busy = (system.global_cpu_usage() as f64 / 100.0).clamp(0.0, 1.0);
+        counters.cpu_user += (busy * ms) as u64;
+        counters.cpu_idle += ((1.0 - busy) * ms) as u64;
+        disks.refresh(true);
+        counters.sectors_read = disks.list().iter().map(|disk| disk.usage().total_read_bytes / 512).sum();
+        *counters
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ram_used_gb() -> f64 {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    system.used_memory() as f64 / (1024.0 * 1024.0 * 1024.0)
+}
+
+#[cfg(target_os = "linux")]
+fn ram_used_gb() -> f64 {
+    let Ok(meminfo) = std::fs::read_to_string("/proc/meminfo") else { return 0.0 };
+    let field = |name: &str| -> Option<f64> {
+        meminfo.lines()
+            .find(|l| l.starts_with(name))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|v| v.parse::<f64>().ok())
+    };
+    match (field("MemTotal:"), field("MemAvailable:")) {
+        (Some(total_kb), Some(available_kb)) => (total_kb - available_kb) / (1024.0 * 1024.0),
+        _ => 0.0,
+    }
+}
+
+// (utilization %, memory used MB) of the busiest GPU, and the power draw of
+// all of them in watts (None if any GPU doesn't report it)
+fn gpu_stats() -> Option<(f64, f64, Option<f64>)> {
+    let output = Command::new("nvidia-smi")
+        .args(["--query-gpu=utilization.gpu,memory.used,power.draw", "--format=csv,noheader,nounits"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let gpus: Vec<(f64, f64, Option<f64>)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // power.draw is "[N/A]" on GPUs without a power sensor
+            let mut fields = line.split(',').map(|f| f.trim().parse::<f64>().ok());
+            Some((fields.next()??, fields.next()??, fields.next().flatten()))
+        })
+        .collect();
+    let power = gpus.iter().map(|g| g.2).sum::<Option<f64>>();
+    gpus.into_iter()
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(util, memory, _)| (util, memory, power))
+}
+
+// Memory used on the busiest GPU in MB, None without nvidia-smi
+pub fn gpu_memory_used_mb() -> Option<f64> {
+    gpu_stats().map(|(_, memory, _)| memory)
+}
+
+pub struct MetricsSampler {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsSampler {
+    // Start sampling into `<logs_dir>/metrics.jsonl`
+    pub fn start(logs_dir: &Path) -> Result<Self, String> {
+        let path = logs_dir.join(METRICS_FILE);
+        let mut file = File::create(&path)
+            .map_err(|e| format!("Failed to create metrics file {}: {}", path.display(), e))?;
+        let interval = sample_interval();
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+
+        let handle = std::thread::Builder::new()
+            .name("metrics-sampler".to_string())
+            .spawn(move || {
+                let started = Instant::now();
+                let mut previous = Counters::read();
+                let mut previous_at = Instant::now();
+                let mut gpu_available = true;
+                while flag.load(Ordering::SeqCst) {
+                    // Sleep in short steps so stop() returns promptly
+                    let wake = Instant::now() + interval;
+                    while flag.load(Ordering::SeqCst) && Instant::now() < wake {
+                        std::thread::sleep(Duration::from_millis(50).min(interval));
+                    }
+
+                    let counters = Counters::read();
+                    let now = Instant::now();
+                    let seconds = now.duration_since(previous_at).as_secs_f64().max(1e-3);
+                    let cpu_user = counters.cpu_user.saturating_sub(previous.cpu_user) as f64;
+                    let cpu_system = counters.cpu_system.saturating_sub(previous.cpu_system) as f64;
+                    let cpu_idle = counters.cpu_idle.saturating_sub(previous.cpu_idle) as f64;
+                    let cpu_total = (cpu_user + cpu_system + cpu_idle).max(1.0);
+                    let gpu = if gpu_available { gpu_stats() } else { None };
+                    gpu_available = gpu.is_some();
+
+                    let sample = MetricsSample {
+                        timestamp: Utc::now(),
+                        elapsed_s: started.elapsed().as_secs_f64(),
+                        cpu_user_percent: cpu_user / cpu_total * 100.0,
+                        cpu_system_percent: cpu_system / cpu_total * 100.0,
+                        cpu_idle_percent: cpu_idle / cpu_total * 100.0,
+                        ram_used_gb: ram_used_gb(),
+                        disk_read_mbps: counters.sectors_read.saturating_sub(previous.sectors_read) as f64 * 512.0
+                            / (1024.0 * 1024.0) / seconds,
+                        context_switches_per_s: counters.context_switches.saturating_sub(previous.context_switches) as f64 / seconds,
+                        gpu_util_percent: gpu.map(|g| g.0),
+                        gpu_memory_used_mb: gpu.map(|g| g.1),
+                        gpu_power_watts: gpu.and_then(|g| g.2),
+                    };
+                    previous = counters;
+                    previous_at = now;
+                    if let Ok(mut latest) = LATEST.lock() {
+                        *latest = Some(sample.clone());
+                    }
+
+                    let written = serde_json::to_string(&sample)
+                        .map_err(|e| e.to_string())
+                        .and_then(|json| writeln!(file, "{}", json).map_err(|e| e.to_string()));
+                    if let Err(e) = written {
+                        warnings::warn(Category::Monitoring, format!("metrics sampler stopped: {}", e));
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start metrics sampler: {}", e))?;
+
+        println!("  Metrics sampler started: {} (every {} ms)", path.display(), interval.as_millis());
+        Ok(Self { running, handle: Some(handle) })
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for MetricsSampler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsSummary {
+    pub samples: usize,
+    pub max_gpu_util_percent: Option<f64>,
+    pub avg_gpu_util_percent: Option<f64>,
+    pub max_gpu_memory_mb: Option<f64>,
+    pub peak_disk_read_mbps: f64,
+    pub max_ram_gb: f64,
+    pub cpu: CpuUtilizationSummary,
+    pub total_context_switches: u64,
+    // None without power samples
+    pub avg_gpu_power_watts: Option<f64>,
+    pub gpu_energy_kwh: Option<f64>,
+}
+
+impl MetricsSummary {
+    pub fn from_samples(samples: &[MetricsSample]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let n = samples.len() as f64;
+        let gpu: Vec<(f64, f64)> = samples.iter()
+            .filter_map(|s| Some((s.gpu_util_percent?, s.gpu_memory_used_mb?)))
+            .collect();
+        let mut interval_start = 0.0;
+        let mut total_context_switches = 0.0;
+        let mut gpu_energy_joules = 0.0;
+        for sample in samples {
+            let seconds = sample.elapsed_s - interval_start;
+            total_context_switches += sample.context_switches_per_s * seconds;
+            gpu_energy_joules += sample.gpu_power_watts.unwrap_or(0.0) * seconds;
+            interval_start = sample.elapsed_s;
+        }
+        let power: Vec<f64> = samples.iter().filter_map(|s| s.gpu_power_watts).collect();
+
+        Some(Self {
+            samples: samples.len(),
+            max_gpu_util_percent: gpu.iter().map(|g| g.0).reduce(f64::max),
+            avg_gpu_util_percent: (!gpu.is_empty()).then(|| gpu.iter().map(|g| g.0).sum::<f64>() / gpu.len() as f64),
+            max_gpu_memory_mb: gpu.iter().map(|g| g.1).reduce(f64::max),
+            peak_disk_read_mbps: samples.iter().map(|s| s.disk_read_mbps).fold(0.0, f64::max),
+            max_ram_gb: samples.iter().map(|s| s.ram_used_gb).fold(0.0, f64::max),
+            cpu: CpuUtilizationSummary {
+                avg_user_percent: samples.iter().map(|s| s.cpu_user_percent).sum::<f64>() / n,
+                avg_system_percent: samples.iter().map(|s| s.cpu_system_percent).sum::<f64>() / n,
+                avg_idle_percent: samples.iter().map(|s| s.cpu_idle_percent).sum::<f64>() / n,
+                max_user_percent: samples.iter().map(|s| s.cpu_user_percent).fold(0.0, f64::max),
+                max_system_percent: samples.iter().map(|s| s.cpu_system_percent).fold(0.0, f64::max),
+            },
+            total_context_switches: total_context_switches.round() as u64,
+            avg_gpu_power_watts: (!power.is_empty()).then(|| power.iter().sum::<f64>() / power.len() as f64),
+            gpu_energy_kwh: (!power.is_empty()).then(|| gpu_energy_joules / 3.6e6),
+        })
+    }
+
+    // Summary of `<logs_dir>/metrics.jsonl`; a truncated last line (from a kill) is ignored
+    pub fn from_logs_dir(logs_dir: &Path) -> Result<Option<Self>, String> {
+        let path = logs_dir.join(METRICS_FILE);
+        let file = File::open(&path)
+            .map_err(|e| format!("Failed to open metrics file {}: {}", path.display(), e))?;
+        let samples: Vec<MetricsSample> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        Ok(Self::from_samples(&samples))
+    }
+}
@@ -0,0 +1,192 @@
+// Memory-mapped reader for uncompressed FASTA/FASTQ inputs
+// Records are byte slices into the map, so no per-read String is allocated
+
+use crate::chunking::ChunkSize;
+use memmap2::Mmap;
+use std::fs::File;
+
+// Check if memory-mapped input should be used (opt-in, like pinned memory)
+pub fn is_enabled() -> bool {
+    std::env::var("USE_MMAP_INPUT")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse::<bool>()
+        .unwrap_or(false)
+}
+
+// Only uncompressed local FASTA/FASTQ can be mapped (detected from content, not
+// extension); a byte order mark or bare CR line endings leave it to the
+// streaming parser, which handles both
+pub fn can_map(path: &str) -> bool {
+    use crate::input_format::{line_terminator, SNIFF_BYTES};
+    !path.contains("://") && matches!(
+        crate::input_format::detect_path(path),
+        Ok(detected) if detected.compression == crate::input_format::Compression::None
+            && !detected.format.is_some_and(|f| f.is_alignment())
+    ) && matches!(
+        crate::async_input::InputSource::from_path(path).read_prefix(SNIFF_BYTES),
+        Ok(prefix) if !prefix.starts_with(b"\xef\xbb\xbf") && line_terminator(&prefix) == b'\n'
+    )
+}
+
+// One record borrowed from the map
+// For multi-line FASTA the sequence span still contains the line breaks
+#[derive(Debug, Clone, Copy)]
+pub struct MappedRecord<'a> {
+    pub id: &'a [u8],
+    pub seq: &'a [u8],
+    pub qual: Option<&'a [u8]>,
+}
+
+impl<'a> MappedRecord<'a> {
+    // Sequence lines without line terminators
+    pub fn seq_lines(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.seq
+            .split(|&b| b == b'\n')
+            .map(trim_cr)
+            .filter(|line| !line.is_empty())
+    }
+}
+
+fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Failed to open file {}: {}", path, e))?;
+        // Safety: the input is treated as read-only; truncating it while mapped is unsupported
+        let map = unsafe { Mmap::map(&file) }
+            .map_err(|e| format!("Failed to memory-map {}: {}", path, e))?;
+        #[cfg(unix)]
+        {
+            let _ = map.advise(memmap2::Advice::Sequential);
+        }
+        Ok(Self { map })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    pub fn records(&self) -> MappedRecords<'_> {
+        MappedRecords { data: &self.map, pos: 0 }
+    }
+}
+
+// Iterator over FASTQ (`@`) or FASTA (`>`) records, detected per record
+pub struct MappedRecords<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MappedRecords<'a> {
+    fn next_line(&mut self) -> Option<&'a [u8]> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let rest = &self.data[self.pos..];
+        let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        self.pos += (end + 1).min(rest.len());
+        Some(trim_cr(&rest[..end]))
+    }
+}
+
+impl<'a> Iterator for MappedRecords<'a> {
+    type Item = Result<MappedRecord<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Skip blank lines between records
+        let header = loop {
+            let line = self.next_line()?;
+            if !line.is_empty() {
+                break line;
+            }
+        };
+
+        match header[0] {
+            b'@' => {
+                let seq = self.next_line();
+                let _plus = self.next_line();
+                let qual = self.next_line();
+                match (seq, qual) {
+                    (Some(seq), Some(qual)) => Some(Ok(MappedRecord { id: &header[1..], seq, qual: Some(qual) })),
+                    _ => Some(Err(format!("Truncated FASTQ record at byte {}", self.pos))),
+                }
+            },
+            b'>' => {
+                // Sequence runs until the next header or end of file
+                let start = self.pos;
+                let rest = &self.data[start..];
+                let mut end = rest.len();
+                let mut line_start = 0;
+                while line_start < rest.len() {
+                    if rest[line_start] == b'>' {
+                        end = line_start;
+                        break;
+                    }
+                    match rest[line_start..].iter().position(|&b| b == b'\n') {
+                        Some(offset) => line_start += offset + 1,
+                        None => line_start = rest.len(),
+                    }
+                }
+                self.pos = start + end;
+                Some(Ok(MappedRecord { id: &header[1..], seq: &rest[..end], qual: None }))
+            },
+            other => Some(Err(format!("Unexpected record start '{}' at byte {}", other as char, self.pos))),
+        }
+    }
+}
+
+// Zero-copy counterpart of process_fastq_file_in_chunks: each chunk is a list of
+// records borrowed from the mapped file
+pub fn process_mapped_file_in_chunks<F>(filepath: &str, chunk_size: ChunkSize, mut processor: F) -> Result<(), String>
+where F: FnMut(&[MappedRecord<'_>]) -> Result<(), String> {
+    let _scope = crate::profiler::scope("mapped_records");
+    let mapped = MappedFile::open(filepath)?;
+    let mut chunk: Vec<MappedRecord<'_>> = Vec::with_capacity(chunk_size.capacity_reads());
+    let mut chunk_bases = 0;
+    let mut total_reads = 0;
+    let mut chunks = 0;
+
+    for record in mapped.records() {
+        let record = record?;
+        if let Some(qual) = record.qual {
+            if qual.len() != record.seq.len() {
+                return Err(format!("Sequence and quality lengths differ for read {}",
+                                   String::from_utf8_lossy(record.id)));
+            }
+        }
+        for line in record.seq_lines() {
+            if crate::input_format::check_sequence_line(line, 0).is_err() {
+                // Line numbers are only counted for the message
+                let offset = line.as_ptr() as usize - mapped.bytes().as_ptr() as usize;
+                let number = mapped.bytes()[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+                crate::input_format::check_sequence_line(line, number)?;
+            }
+        }
+        chunk_bases += record.seq_lines().map(|line| line.len()).sum::<usize>();
+        chunk.push(record);
+        total_reads += 1;
+
+        if chunk_size.is_full(chunk.len(), chunk_bases) {
+            processor(&chunk)?;
+            chunks += 1;
+            chunk.clear();
+            chunk_bases = 0;
+        }
+    }
+
+    if !chunk.is_empty() {
+        processor(&chunk)?;
+        chunks += 1;
+    }
+
+    println!("    Processed {} total reads in {} chunks (memory-mapped, {} MB)",
+             total_reads, chunks, mapped.bytes().len() / (1024 * 1024));
+    Ok(())
+}
@@ -0,0 +1,308 @@
+// `msa`: progressive multiple sequence alignment for small-to-medium nucleotide sets
+// 1. Distances: every pair is scored with the all-diagonals kernel (ungapped.rs),
+//    on the GPU or the CPU, and d(i, j) = 1 - s(i, j) / min(s(i, i), s(j, j))
+// 2. Guide tree: UPGMA or neighbour joining over the distances; only the join
+//    order is used, so NJ's unrooted tree needs no root
+// 3. Profiles are merged in join order with a global (Needleman-Wunsch)
+//    profile-profile alignment: columns score the average kernel score over all
+//    residue pairs (match and mismatch from scoring.rs, N per --n-policy), a
+//    residue against a gap scores the gap penalty and a gap against a gap
+//    nothing. Gaps already in a profile are kept
+// Bases other than A/C/G/T are written as N; sequences come out in input order
+
+use crate::ungapped::{self, ScoreMatrix, SequenceSet};
+use std::io::Write;
+
+const GAP: u8 = b'-';
+const CLUSTAL_WIDTH: usize = 60;
+
+// A C G T N, then the gap
+const SYMBOLS: usize = 6;
+const GAP_SYMBOL: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GuideTree {
+    #[default]
+    Upgma,
+    NeighborJoining,
+}
+
+impl GuideTree {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "upgma" => Ok(GuideTree::Upgma),
+            "nj" => Ok(GuideTree::NeighborJoining),
+            _ => Err(format!("Invalid --tree '{}': expected upgma or nj", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GuideTree::Upgma => "UPGMA",
+            GuideTree::NeighborJoining => "neighbour joining",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MsaFormat {
+    #[default]
+    Fasta,
+    Clustal,
+}
+
+impl MsaFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "fasta" => Ok(MsaFormat::Fasta),
+            "clustal" => Ok(MsaFormat::Clustal),
+            _ => Err(format!("Invalid --format '{}': expected fasta or clustal", value)),
+        }
+    }
+
+    pub fn default_output(&self) -> &'static str {
+        match self {
+            MsaFormat::Fasta => "msa.fasta",
+            MsaFormat::Clustal => "msa.aln",
+        }
+    }
+}
+
+// Merged column: its source column in each profile, None for a gap
+type Column = (Option<usize>, Option<usize>);
+
+// Input indices of a cluster's sequences and their aligned rows, in the same order
+struct Cluster {
+    members: Vec<usize>,
+    rows: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Msa {
+    pub names: Vec<String>,
+    pub rows: Vec<Vec<u8>>,
+}
+
+// Pairwise distances in [0, 1], from the all-diagonals scores
+pub fn distance_matrix(sequences: &[Vec<u8>], use_gpu: bool) -> Result<Vec<Vec<f64>>, String> {
+    let matrix = ScoreMatrix::nucleotide();
+    let mut set = SequenceSet::new();
+    for sequence in sequences {
+        set.push(sequence, &matrix);
+    }
+    let n = sequences.len();
+    let scores = ungapped::scores(&set, &set, &matrix, use_gpu)?;
+    let score = |i: usize, j: usize| scores[j * n + i] as f64;
+    Ok((0..n).map(|i| (0..n).map(|j| {
+        let scale = score(i, i).min(score(j, j));
+        if i == j {
+            0.0
+        } else if scale <= 0.0 {
+            1.0
+        } else {
+            (1.0 - score(i, j) / scale).clamp(0.0, 1.0)
+        }
+    }).collect()).collect())
+}
+
+// Pair of cluster ids joined at each step: leaves are 0..n, the cluster made
+// by step k is n + k
+pub fn guide_tree(distances: &[Vec<f64>], tree: GuideTree) -> Vec<(usize, usize)> {
+    let n = distances.len();
+    // Distances between active clusters, grown as clusters are made
+    let mut d: Vec<Vec<f64>> = distances.to_vec();
+    let mut size = vec![1usize; n];
+    let mut active: Vec<usize> = (0..n).collect();
+    let mut joins = Vec::with_capacity(n.saturating_sub(1));
+    while active.len() > 1 {
+        // Lowest criterion wins; ties go to the earliest pair
+        let r = active.len() as f64;
+        let net: Vec<f64> = (0..d.len()).map(|i| active.iter().map(|&k| d[i][k]).sum()).collect();
+        let criterion = |i: usize, j: usize| match tree {
+            GuideTree::Upgma => d[i][j],
+            GuideTree::NeighborJoining => (r - 2.0) * d[i][j] - net[i] - net[j],
+        };
+        let mut best = (active[0], active[1]);
+        for (x, &i) in active.iter().enumerate() {
+            for &j in &active[x + 1..] {
+                if criterion(i, j) < criterion(best.0, best.1) {
+                    best = (i, j);
+                }
+            }
+        }
+        let (a, b) = best;
+        let new = d.len();
+        let row: Vec<f64> = (0..new).map(|k| match tree {
+            GuideTree::Upgma => (size[a] as f64 * d[a][k] + size[b] as f64 * d[b][k]) / (size[a] + size[b]) as f64,
+            GuideTree::NeighborJoining => (d[a][k] + d[b][k] - d[a][b]) / 2.0,
+        }).collect();
+        for (k, row_k) in d.iter_mut().enumerate() {
+            row_k.push(row[k]);
+        }
+        d.push(row.into_iter().chain(std::iter::once(0.0)).collect());
+        size.push(size[a] + size[b]);
+        active.retain(|&k| k != a && k != b);
+        active.push(new);
+        joins.push((a, b));
+    }
+    joins
+}
+
+fn symbol(base: u8) -> usize {
+    match base {
+        GAP => GAP_SYMBOL,
+        _ => crate::ungapped::encode_nucleotide(base) as usize,
+    }
+}
+
+// Fraction of each symbol in every column
+fn column_profile(rows: &[Vec<u8>]) -> Vec<[f64; SYMBOLS]> {
+    let len = rows.first().map_or(0, |row| row.len());
+    let weight = 1.0 / rows.len() as f64;
+    (0..len).map(|c| {
+        let mut column = [0.0; SYMBOLS];
+        for row in rows {
+            column[symbol(row[c])] += weight;
+        }
+        column
+    }).collect()
+}
+
+// Global alignment of two profiles; returns the rows of both with gap columns added
+fn align_profiles(a: &[Vec<u8>], b: &[Vec<u8>], matrix: &ScoreMatrix) -> Vec<Vec<u8>> {
+    let gap_penalty = crate::scoring::selected().gap_penalty as f64;
+    let mut pair = [[0.0f64; SYMBOLS]; SYMBOLS];
+    for (x, row) in pair.iter_mut().enumerate() {
+        for (y, cell) in row.iter_mut().enumerate() {
+            *cell = match (x == GAP_SYMBOL, y == GAP_SYMBOL) {
+                (true, true) => 0.0,
+                (true, false) | (false, true) => gap_penalty,
+                _ => matrix.score(x as u8, y as u8) as f64,
+            };
+        }
+    }
+    let (pa, pb) = (column_profile(a), column_profile(b));
+    let score = |ca: &[f64; SYMBOLS], cb: &[f64; SYMBOLS]| -> f64 {
+        (0..SYMBOLS).map(|x| (0..SYMBOLS).map(|y| ca[x] * cb[y] * pair[x][y]).sum::<f64>()).sum()
+    };
+    let mut gap_column = [0.0; SYMBOLS];
+    gap_column[GAP_SYMBOL] = 1.0;
+    let (la, lb) = (pa.len(), pb.len());
+
+    // Full matrix, for the traceback
+    let mut h = vec![vec![0.0f64; lb + 1]; la + 1];
+    for i in 1..=la {
+        h[i][0] = h[i - 1][0] + score(&pa[i - 1], &gap_column);
+    }
+    for j in 1..=lb {
+        h[0][j] = h[0][j - 1] + score(&gap_column, &pb[j - 1]);
+    }
+    for i in 1..=la {
+        for j in 1..=lb {
+            let diagonal = h[i - 1][j - 1] + score(&pa[i - 1], &pb[j - 1]);
+            let up = h[i - 1][j] + score(&pa[i - 1], &gap_column);
+            let left = h[i][j - 1] + score(&gap_column, &pb[j - 1]);
+            h[i][j] = diagonal.max(up).max(left);
+        }
+    }
+
+    // Walk back, preferring the diagonal, then a gap in b, then a gap in a
+    let mut columns: Vec<Column> = Vec::with_capacity(la + lb);
+    let (mut i, mut j) = (la, lb);
+    while i > 0 || j > 0 {
+        let eq = |x: f64, y: f64| (x - y).abs() < 1e-9;
+        if i > 0 && j > 0 && eq(h[i][j], h[i - 1][j - 1] + score(&pa[i - 1], &pb[j - 1])) {
+            columns.push((Some(i - 1), Some(j - 1)));
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && (j == 0 || eq(h[i][j], h[i - 1][j] + score(&pa[i - 1], &gap_column))) {
+            columns.push((Some(i - 1), None));
+            i -= 1;
+        } else {
+            columns.push((None, Some(j - 1)));
+            j -= 1;
+        }
+    }
+    columns.reverse();
+
+    let gapped = |rows: &[Vec<u8>], pick: fn(&Column) -> Option<usize>| -> Vec<Vec<u8>> {
+        rows.iter().map(|row| columns.iter().map(|column| pick(column).map_or(GAP, |c| row[c])).collect()).collect()
+    };
+    let mut merged = gapped(a, |column| column.0);
+    merged.extend(gapped(b, |column| column.1));
+    merged
+}
+
+pub fn align(sequences: &[(String, Vec<u8>)], tree: GuideTree, use_gpu: bool) -> Result<Msa, String> {
+    if sequences.is_empty() {
+        return Err("No sequences to align".to_string());
+    }
+    let matrix = ScoreMatrix::nucleotide();
+    let bases: Vec<Vec<u8>> = sequences.iter()
+        .map(|(_, sequence)| sequence.iter().map(|&b| b"ACGTN"[crate::ungapped::encode_nucleotide(b) as usize]).collect())
+        .collect();
+    let distances = distance_matrix(&bases, use_gpu)?;
+
+    let mut clusters: Vec<Option<Cluster>> = bases.iter().enumerate()
+        .map(|(i, row)| Some(Cluster { members: vec![i], rows: vec![row.clone()] }))
+        .collect();
+    for (a, b) in guide_tree(&distances, tree) {
+        let first = clusters[a].take().ok_or("guide tree joined a cluster twice")?;
+        let second = clusters[b].take().ok_or("guide tree joined a cluster twice")?;
+        let rows = align_profiles(&first.rows, &second.rows, &matrix);
+        clusters.push(Some(Cluster { members: first.members.into_iter().chain(second.members).collect(), rows }));
+    }
+    let Cluster { members, rows } = clusters.pop().flatten().ok_or("guide tree left no final cluster")?;
+
+    let mut ordered = vec![Vec::new(); sequences.len()];
+    for (member, row) in members.into_iter().zip(rows) {
+        ordered[member] = row;
+    }
+    Ok(Msa { names: sequences.iter().map(|(name, _)| name.clone()).collect(), rows: ordered })
+}
+
+impl Msa {
+    pub fn columns(&self) -> usize {
+        self.rows.first().map_or(0, |row| row.len())
+    }
+
+    // Columns with the same base in every sequence and no gap
+    pub fn identical_columns(&self) -> usize {
+        (0..self.columns()).filter(|&c| self.identical(c)).count()
+    }
+
+    fn identical(&self, column: usize) -> bool {
+        let first = self.rows[0][column];
+        first != GAP && self.rows.iter().all(|row| row[column] == first)
+    }
+
+    pub fn write(&self, format: MsaFormat, out: &mut dyn Write) -> std::io::Result<()> {
+        match format {
+            MsaFormat::Fasta => {
+                for (name, row) in self.names.iter().zip(&self.rows) {
+                    writeln!(out, ">{}", name)?;
+                    for line in row.chunks(CLUSTAL_WIDTH) {
+                        out.write_all(line)?;
+                        writeln!(out)?;
+                    }
+                }
+            },
+            MsaFormat::Clustal => {
+                writeln!(out, "CLUSTAL W multiple sequence alignment (rustseq_mini)")?;
+                let width = self.names.iter().map(|name| name.len()).max().unwrap_or(0) + 6;
+                let mut start = 0;
+                while start < self.columns() {
+                    let end = (start + CLUSTAL_WIDTH).min(self.columns());
+                    writeln!(out)?;
+                    for (name, row) in self.names.iter().zip(&self.rows) {
+                        writeln!(out, "{:<width$}{}", name, String::from_utf8_lossy(&row[start..end]), width = width)?;
+                    }
+                    let conservation: String = (start..end).map(|c| if self.identical(c) { '*' } else { ' ' }).collect();
+                    writeln!(out, "{:<width$}{}", "", conservation, width = width)?;
+                    start = end;
+                }
+            },
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+// --n-policy: how ambiguous 'N' bases score, in the OpenCL kernels and the CPU path
+//   mismatch  an N scores as a mismatch against anything, another N included (default)
+//   zero      a position with an N in either sequence scores 0 and the run carries on
+//   skip      N bases are dropped from both sequences before launch
+// Before this an N matched another N, since bases were compared byte for byte.
+// Lowercase 'n' counts as N. The kernels get the policy as a compile-time define,
+// so the program is built with it; skip leaves no N for them to see
+
+use once_cell::sync::OnceCell;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NPolicy {
+    #[default]
+    Mismatch,
+    Zero,
+    Skip,
+}
+
+// Kernel-side N_POLICY values (smith_waterman.cl)
+const KERNEL_MISMATCH: i32 = 0;
+const KERNEL_ZERO: i32 = 1;
+
+static SELECTED: OnceCell<NPolicy> = OnceCell::new();
+
+impl NPolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "mismatch" => Ok(NPolicy::Mismatch),
+            "zero" => Ok(NPolicy::Zero),
+            "skip" => Ok(NPolicy::Skip),
+            _ => Err(format!("Invalid --n-policy '{}': expected mismatch, zero, or skip", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NPolicy::Mismatch => "mismatch",
+            NPolicy::Zero => "zero",
+            NPolicy::Skip => "skip",
+        }
+    }
+
+    pub fn kernel_define(&self) -> i32 {
+        match self {
+            NPolicy::Zero => KERNEL_ZERO,
+            NPolicy::Mismatch | NPolicy::Skip => KERNEL_MISMATCH,
+        }
+    }
+
+    // Score of a position with an N in either sequence
+    pub fn n_score(&self) -> i32 {
+        match self {
+            NPolicy::Zero => 0,
+            NPolicy::Mismatch | NPolicy::Skip => crate::scoring::selected().mismatch_penalty,
+        }
+    }
+}
+
+// Set once at startup, before the OpenCL program is built
+pub fn configure(policy: NPolicy) {
+    let _ = SELECTED.set(policy);
+}
+
+pub fn selected() -> NPolicy {
+    SELECTED.get().copied().unwrap_or_default()
+}
+
+pub fn is_n(base: u8) -> bool {
+    base == b'N' || base == b'n'
+}
+
+pub fn has_n(bases: &[u8]) -> bool {
+    bases.iter().any(|&b| is_n(b))
+}
+
+pub fn count_n(bases: &[u8]) -> usize {
+    bases.iter().filter(|&&b| is_n(b)).count()
+}
+
+// Sequence as launched: without its N bases under skip, untouched otherwise
+pub fn prepare(bases: &[u8]) -> Cow<'_, [u8]> {
+    if selected() == NPolicy::Skip && has_n(bases) {
+        Cow::Owned(bases.iter().copied().filter(|&b| !is_n(b)).collect())
+    } else {
+        Cow::Borrowed(bases)
+    }
+}
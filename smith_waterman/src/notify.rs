@@ -0,0 +1,162 @@
+// Run-end notifications for long WGS runs
+// --notify-webhook posts the final summary as JSON (through curl, like the
+// remote input readers) and --notify-desktop shows a desktop notification
+// (notify-send on Linux, osascript on macOS, a PowerShell toast on Windows) when a run completes, fails, or is
+// interrupted. Notification failures are reported but never fail the run
+
+use crate::warnings::{self, Category};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Default)]
+pub struct NotifyOptions {
+    pub webhook: Option<String>,
+    pub desktop: bool,
+}
+
+static OPTIONS: OnceCell<NotifyOptions> = OnceCell::new();
+
+pub fn configure(options: NotifyOptions) {
+    let _ = OPTIONS.set(options);
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunEvent {
+    Completed,
+    Failed,
+    Interrupted,
+    Preempted,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunNotification {
+    pub event: RunEvent,
+    pub run_number: u64,
+    pub run_id: String,
+    pub sample_id: Option<String>,
+    pub host: String,
+    pub output_dir: Option<String>,
+    pub elapsed_s: f64,
+    pub files_processed: usize,
+    pub total_reads: usize,
+    pub total_bases: usize,
+    pub total_score: i64,
+    pub score_per_base: f64,
+    pub error: Option<String>,
+}
+
+impl RunNotification {
+    // Run identity filled in; totals are zero until set by the caller
+    pub fn new(event: RunEvent) -> Self {
+        let run = crate::run_registry::current();
+        let elapsed_s = (chrono::Utc::now() - run.started).num_milliseconds() as f64 / 1000.0;
+        Self {
+            event,
+            run_number: run.number,
+            run_id: run.run_id.clone(),
+            sample_id: std::env::var("WGS_SAMPLE_ID").ok(),
+            host: hostname(),
+            output_dir: crate::output_layout::get().map(|d| d.display().to_string()),
+            elapsed_s,
+            files_processed: 0,
+            total_reads: 0,
+            total_bases: 0,
+            total_score: 0,
+            score_per_base: 0.0,
+            error: None,
+        }
+    }
+
+    fn headline(&self) -> String {
+        let sample = self.sample_id.as_deref().unwrap_or("WGS run");
+        match self.event {
+            RunEvent::Completed => format!("{} completed: {} files, {} reads in {:.0} s",
+                                           sample, self.files_processed, self.total_reads, self.elapsed_s),
+            RunEvent::Failed => format!("{} failed after {:.0} s: {}",
+                                        sample, self.elapsed_s, self.error.as_deref().unwrap_or("unknown error")),
+            RunEvent::Interrupted => format!("{} interrupted after {:.0} s", sample, self.elapsed_s),
+            RunEvent::Preempted => format!("{} preempted after {:.0} s; resumable", sample, self.elapsed_s),
+        }
+    }
+}
+
+fn hostname() -> String {
+    sysinfo::System::host_name()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn post_webhook(url: &str, json: &str) -> Result<(), String> {
+    let mut child = Command::new("curl")
+        .args(["-sS", "--fail", "--max-time", "30", "-X", "POST", "-H", "Content-Type: application/json", "--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start curl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(json.as_bytes())
+            .map_err(|e| format!("Failed to send webhook body: {}", e))?;
+    }
+    let output = child.wait_with_output()
+        .map_err(|e| format!("curl failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("webhook POST failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+// Toast through the WinRT notification API, shown under PowerShell's app id
+// (an unregistered app id is silently dropped on recent Windows builds)
+const WINDOWS_TOAST: &str = "\
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+$toast = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+$text = $toast.GetElementsByTagName('text'); \
+$text.Item(0).AppendChild($toast.CreateTextNode($env:NOTIFY_TITLE)) > $null; \
+$text.Item(1).AppendChild($toast.CreateTextNode($env:NOTIFY_BODY)) > $null; \
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe').Show([Windows.UI.Notifications.ToastNotification]::new($toast))";
+
+fn desktop_notification(title: &str, body: &str) -> Result<(), String> {
+    let status = if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title {:?}", body, title);
+        Command::new("osascript").args(["-e", &script]).status()
+    } else if cfg!(windows) {
+        // Title and body go through the environment to stay clear of PowerShell quoting
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", WINDOWS_TOAST])
+            .env("NOTIFY_TITLE", title)
+            .env("NOTIFY_BODY", body)
+            .status()
+    } else {
+        Command::new("notify-send").args([title, body]).status()
+    };
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("desktop notification exited with {}", status)),
+        Err(e) => Err(format!("desktop notification unavailable: {}", e)),
+    }
+}
+
+// Send to every configured target
+pub fn send(notification: &RunNotification) {
+    let Some(options) = OPTIONS.get() else { return };
+    if let Some(url) = &options.webhook {
+        match serde_json::to_string(notification) {
+            Ok(json) => match post_webhook(url, &json) {
+                Ok(()) => println!("Notification posted to webhook"),
+                Err(e) => warnings::warn(Category::Monitoring, e),
+            },
+            Err(e) => warnings::warn(Category::Monitoring, format!("Failed to serialize notification: {}", e)),
+        }
+    }
+    if options.desktop {
+        if let Err(e) = desktop_notification("rustseq_mini", &notification.headline()) {
+            warnings::warn(Category::Monitoring, e);
+        }
+    }
+}
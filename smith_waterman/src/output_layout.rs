@@ -0,0 +1,278 @@
+// Per-run output directory layout
+// With --output-dir, everything a run writes lands under one directory:
+//   results/       benchmark results
+//   checkpoints/   per-file checkpoints
+//   logs/run_N/    system monitor logs
+//   manifest.json  what was run, when, where, with which --seed, and each
+//                  run's read group, file order and warnings by category
+//                  (warnings.rs)
+//   run_registry.json  run numbering (see run_registry)
+// With --remote-state, these are mirrored to object storage as they are
+// written (remote_state.rs)
+// Without it, files go to the working directory as before
+// The layout belongs to the process: installing a second, different directory
+// fails instead of writing a manifest there for outputs that go elsewhere
+// Runs on several threads of the process (run_context.rs) share it: what
+// each records goes under its run number in the manifest's `runs`, and the
+// manifest is rewritten under a lock so none of them loses another's update
+
+use crate::read_group::ReadGroup;
+use crate::warnings::CategorySummary;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static OUTPUT_DIR: OnceCell<PathBuf> = OnceCell::new();
+// Held while the manifest is read, changed and written back
+static MANIFEST: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub version: String,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub command: Vec<String>,
+    pub working_dir: PathBuf,
+    pub results_dir: PathBuf,
+    pub checkpoints_dir: PathBuf,
+    pub logs_dir: PathBuf,
+    // Every device a run in this directory aligned on, kept across runs so a
+    // resumed run's hardware history stays visible
+    #[serde(default)]
+    pub devices: Vec<DeviceRecord>,
+    // Sample and read group of the latest run that was given them (each run's
+    // are in `runs`)
+    #[serde(default)]
+    pub read_group: Option<ReadGroup>,
+    // The latest run's seed (seed.rs); absent in manifests from before it was recorded
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // The latest run's --order and its files in processing order
+    #[serde(default)]
+    pub file_order: Option<FileOrderRecord>,
+    // The warnings by category of the latest run to end
+    #[serde(default)]
+    pub warnings: Vec<CategorySummary>,
+    // What each run in this directory recorded, by run number
+    #[serde(default)]
+    pub runs: Vec<RunEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunEntry {
+    pub run: u64,
+    #[serde(default)]
+    pub read_group: Option<ReadGroup>,
+    #[serde(default)]
+    pub file_order: Option<FileOrderRecord>,
+    // Written when the run ends
+    #[serde(default)]
+    pub warnings: Vec<CategorySummary>,
+}
+
+impl RunManifest {
+    // Run `run`'s entry, added if it has none yet
+    pub fn run_entry(&mut self, run: u64) -> &mut RunEntry {
+        let index = match self.runs.iter().position(|entry| entry.run == run) {
+            Some(index) => index,
+            None => {
+                self.runs.push(RunEntry { run, ..Default::default() });
+                self.runs.len() - 1
+            },
+        };
+        &mut self.runs[index]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileOrderRecord {
+    pub order: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceRecord {
+    pub run: u64,
+    pub device: String,
+    pub recorded: chrono::DateTime<chrono::Utc>,
+}
+
+fn manifest_path(root: &Path) -> PathBuf {
+    root.join("manifest.json")
+}
+
+fn write_manifest(root: &Path, manifest: &RunManifest) -> Result<(), String> {
+    let path = manifest_path(root);
+    let json = serde_json::to_string_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize run manifest: {}", e))?;
+    // Written in full beside it and renamed over it, so readers never see a partial manifest
+    let temp = root.join("manifest.json.tmp");
+    std::fs::File::create(&temp)
+        .and_then(|mut file| {
+            std::io::Write::write_all(&mut file, json.as_bytes())?;
+            file.sync_all()
+        })
+        .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, &path)
+        .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))?;
+    crate::remote_state::mirror(&path);
+    Ok(())
+}
+
+fn read_manifest(root: &Path) -> Option<RunManifest> {
+    load_manifest(&manifest_path(root)).ok()
+}
+
+// Change manifest.json with `change` (a no-op without --output-dir)
+fn update_manifest(change: impl FnOnce(&mut RunManifest)) -> Result<(), String> {
+    let Some(root) = get() else { return Ok(()) };
+    let _lock = MANIFEST.lock().unwrap_or_else(|e| e.into_inner());
+    let mut manifest = read_manifest(root)
+        .ok_or_else(|| format!("Failed to read {}", manifest_path(root).display()))?;
+    change(&mut manifest);
+    write_manifest(root, &manifest)
+}
+
+// manifest.json at `path`, for tools reading another run's directory
+pub fn load_manifest(path: &Path) -> Result<RunManifest, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+// Create the layout under `dir` and route all run outputs there. The layout is
+// the process's: installing another directory once one is in place is an error
+pub fn install(dir: &str) -> Result<(), String> {
+    let root = PathBuf::from(dir);
+    let already = |installed: &Path| format!("Output directory {} is already in use by this process; can't switch to {}",
+                                             installed.display(), root.display());
+    if let Some(installed) = get().filter(|installed| *installed != root) {
+        return Err(already(installed));
+    }
+    for sub in ["results", "checkpoints", "logs"] {
+        create_dir_all(root.join(sub))
+            .map_err(|e| format!("Failed to create {}: {}", root.join(sub).display(), e))?;
+    }
+
+    let lock = MANIFEST.lock().unwrap_or_else(|e| e.into_inner());
+    let previous = read_manifest(&root);
+    let manifest = RunManifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created: chrono::Utc::now(),
+        command: std::env::args().collect(),
+        working_dir: std::env::current_dir().unwrap_or_default(),
+        results_dir: root.join("results"),
+        checkpoints_dir: root.join("checkpoints"),
+        logs_dir: root.join("logs"),
+        devices: previous.as_ref().map(|m| m.devices.clone()).unwrap_or_default(),
+        read_group: previous.as_ref().and_then(|m| m.read_group.clone()),
+        seed: Some(crate::seed::run_seed()),
+        file_order: None,
+        warnings: Vec::new(),
+        runs: previous.map(|m| m.runs).unwrap_or_default(),
+    };
+    write_manifest(&root, &manifest)?;
+    drop(lock);
+
+    println!("Output directory: {}", root.display());
+    // Another thread may have installed one since the check above
+    match OUTPUT_DIR.get_or_init(|| root.clone()) {
+        installed if *installed == root => Ok(()),
+        installed => Err(already(installed)),
+    }
+}
+
+pub fn get() -> Option<&'static Path> {
+    OUTPUT_DIR.get().map(|p| p.as_path())
+}
+
+// Add the device run `run` aligns on to manifest.json, unless it is already
+// the last one listed (a no-op without --output-dir)
+pub fn record_device(run: u64, device: &str) -> Result<(), String> {
+    update_manifest(|manifest| {
+        if manifest.devices.last().is_some_and(|d| d.device == device) {
+            return;
+        }
+        manifest.devices.push(DeviceRecord {
+            run,
+            device: device.to_string(),
+            recorded: chrono::Utc::now(),
+        });
+    })
+}
+
+// Record run `run`'s sample and read group in manifest.json (a no-op without --output-dir)
+pub fn record_read_group(run: u64, read_group: &ReadGroup) -> Result<(), String> {
+    update_manifest(|manifest| {
+        manifest.run_entry(run).read_group = Some(read_group.clone());
+        manifest.read_group = Some(read_group.clone());
+    })
+}
+
+// Record run `run`'s --order and its files in processing order in manifest.json
+// (a no-op without --output-dir)
+pub fn record_file_order(run: u64, order: &str, files: &[String]) -> Result<(), String> {
+    let record = FileOrderRecord { order: order.to_string(), files: files.to_vec() };
+    update_manifest(|manifest| {
+        manifest.run_entry(run).file_order = Some(record.clone());
+        manifest.file_order = Some(record);
+    })
+}
+
+// Record run `run`'s warnings in manifest.json (a no-op without --output-dir)
+pub fn record_warnings(run: u64, warnings: &[CategorySummary]) -> Result<(), String> {
+    update_manifest(|manifest| {
+        manifest.run_entry(run).warnings = warnings.to_vec();
+        manifest.warnings = warnings.to_vec();
+    })
+}
+
+// Directory for per-run benchmark results (legacy: ./benchmark_results)
+pub fn results_dir() -> PathBuf {
+    match get() {
+        Some(root) => root.join("results"),
+        None => PathBuf::from("benchmark_results"),
+    }
+}
+
+// Run-level result file (legacy: ./<name>)
+pub fn result_file(name: &str) -> PathBuf {
+    match get() {
+        Some(root) => root.join("results").join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+// Directory checkpoint files are written to (legacy: the working directory)
+pub fn checkpoints_dir() -> PathBuf {
+    match get() {
+        Some(root) => root.join("checkpoints"),
+        None => PathBuf::from("."),
+    }
+}
+
+// Checkpoint file (legacy: ./<name>)
+pub fn checkpoint_file(name: &str) -> PathBuf {
+    match get() {
+        Some(root) => root.join("checkpoints").join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+// System monitor logs for one run (./logs/run_N without a layout)
+pub fn logs_dir(run_number: u64) -> PathBuf {
+    let run_dir = format!("run_{}", run_number);
+    match get() {
+        Some(root) => root.join("logs").join(run_dir),
+        None => Path::new("logs").join(run_dir),
+    }
+}
+
+// Persistent run numbering (legacy: ./run_registry.json)
+pub fn registry_file() -> PathBuf {
+    match get() {
+        Some(root) => root.join("run_registry.json"),
+        None => PathBuf::from("run_registry.json"),
+    }
+}
@@ -0,0 +1,270 @@
+// Output sinks for per-read results: one per --output-format
+// A sink takes read results as they are scored, a summary line after each file
+// and a finish at the end of the run. The pipeline only sees the trait (through
+// ReadResultWriter), so a new format is a new sink here and a name in
+// ReadOutputFormat. All the tabular formats share the same columns:
+//   read_name file chunk length chunk_score [tiles cigar] [sample library platform_unit]
+// File summaries go where each format has room for them: #file comment lines
+// in TSV, "file_summary" records in JSONL and key-value metadata in Parquet.
+// SAM (whose header comes before every record), the store and the null sink
+// leave them to the run report
+
+use crate::parquet_output::ParquetSink;
+use crate::read_group::ReadGroup;
+use crate::read_output::{ReadOutputFormat, ReadOutputOptions, ReadResult};
+use crate::read_store::ReadStoreWriter;
+use crate::wgs_report::{FileReport, FileStatus};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+pub trait OutputSink: Send {
+    fn write_read_result(&mut self, result: &ReadResult<'_>) -> Result<(), String>;
+    fn write_file_summary(&mut self, file: &FileReport) -> Result<(), String>;
+    fn finish(self: Box<Self>) -> Result<(), String>;
+
+    // Rows or index entries held in memory until they are written
+    fn buffered_bytes(&self) -> usize {
+        0
+    }
+}
+
+pub fn create(options: &ReadOutputOptions, read_group: Option<&ReadGroup>, long_reads: bool) -> Result<Box<dyn OutputSink>, String> {
+    let columns = Columns { long_reads, read_group: read_group.cloned() };
+    let hashed_names = options.name_mode == crate::read_chunk::ReadNameMode::Hash;
+    Ok(match options.format {
+        ReadOutputFormat::Tsv => Box::new(TsvSink::create(&options.path, columns)?),
+        ReadOutputFormat::Jsonl => Box::new(JsonlSink { out: create_file(&options.path)?, columns }),
+        ReadOutputFormat::Sam => Box::new(SamSink::create(&options.path, columns)?),
+        ReadOutputFormat::Parquet => Box::new(ParquetSink::create(&options.path, columns)?),
+        ReadOutputFormat::Store => {
+            let store = ReadStoreWriter::create(&options.path, &columns.names()[1..].join("\t"), hashed_names)?;
+            Box::new(StoreSink { store, columns })
+        },
+        ReadOutputFormat::Null => Box::new(NullSink),
+    })
+}
+
+fn create_file(path: &str) -> Result<BufWriter<File>, String> {
+    let file = File::create(path)
+        .map_err(|e| format!("Failed to create read output {}: {}", path, e))?;
+    Ok(BufWriter::new(file))
+}
+
+fn write_line(out: &mut BufWriter<File>, line: &str) -> Result<(), String> {
+    writeln!(out, "{}", line)
+        .map_err(|e| format!("Failed to write read output: {}", e))
+}
+
+fn flush(mut out: BufWriter<File>) -> Result<(), String> {
+    out.flush()
+        .map_err(|e| format!("Failed to flush read output: {}", e))
+}
+
+// Which optional columns a run's records have
+#[derive(Debug, Clone)]
+pub struct Columns {
+    pub long_reads: bool,
+    pub read_group: Option<ReadGroup>,
+}
+
+impl Columns {
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = vec!["read_name", "file", "chunk", "length", "chunk_score"];
+        if self.long_reads {
+            names.extend(["tiles", "cigar"]);
+        }
+        if self.read_group.is_some() {
+            names.extend(["sample", "library", "platform_unit"]);
+        }
+        names
+    }
+
+    // Tiles and CIGAR of a long read; 0 and "*" for a read without them
+    pub fn tiles_cigar<'a>(&self, result: &ReadResult<'a>) -> (usize, &'a str) {
+        result.read_score.map_or((0, "*"), |read| (read.tiles, read.cigar.as_str()))
+    }
+
+    // Every column after the name, tab-separated
+    fn tsv_fields(&self, result: &ReadResult<'_>) -> String {
+        let mut line = format!("{}\t{}\t{}\t{}", result.file_index + 1, result.chunk_index, result.seq.len(), result.chunk_score);
+        if self.long_reads {
+            let (tiles, cigar) = self.tiles_cigar(result);
+            line.push_str(&format!("\t{}\t{}", tiles, cigar));
+        }
+        if let Some(read_group) = &self.read_group {
+            line.push_str(&format!("\t{}", read_group.tsv_fields()));
+        }
+        line
+    }
+}
+
+pub fn status_name(status: FileStatus) -> &'static str {
+    match status {
+        FileStatus::Completed => "completed",
+        FileStatus::Resumed => "resumed",
+        FileStatus::Failed => "failed",
+        FileStatus::Quarantined => "quarantined",
+    }
+}
+
+// One line for a file's summary, e.g. "file 1 reads.fastq: completed, score 123, ..."
+fn summary_line(file: &FileReport) -> String {
+    let mut line = format!("file {} {}: {}, score {}, {} reads, {} bases, {} chunks", file.index + 1, file.path, status_name(file.status),
+                           file.score, file.total_reads, file.total_bases, file.chunks);
+    line.push_str(&file.partial_note());
+    if let Some(error) = &file.error {
+        line.push_str(&format!(", error: {}", error));
+    }
+    line
+}
+
+struct TsvSink {
+    out: BufWriter<File>,
+    columns: Columns,
+}
+
+impl TsvSink {
+    fn create(path: &str, columns: Columns) -> Result<Self, String> {
+        let mut out = create_file(path)?;
+        write_line(&mut out, &format!("#{}", columns.names().join("\t")))?;
+        Ok(Self { out, columns })
+    }
+}
+
+impl OutputSink for TsvSink {
+    fn write_read_result(&mut self, result: &ReadResult<'_>) -> Result<(), String> {
+        let line = format!("{}\t{}", result.name_string(), self.columns.tsv_fields(result));
+        write_line(&mut self.out, &line)
+    }
+
+    fn write_file_summary(&mut self, file: &FileReport) -> Result<(), String> {
+        write_line(&mut self.out, &format!("#{}", summary_line(file)))
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        flush(self.out)
+    }
+}
+
+struct JsonlSink {
+    out: BufWriter<File>,
+    columns: Columns,
+}
+
+impl OutputSink for JsonlSink {
+    fn write_read_result(&mut self, result: &ReadResult<'_>) -> Result<(), String> {
+        let mut record = serde_json::json!({
+            "type": "read",
+            "read_name": result.name_string(),
+            "file": result.file_index + 1,
+            "chunk": result.chunk_index,
+            "length": result.seq.len(),
+            "chunk_score": result.chunk_score,
+        });
+        if self.columns.long_reads {
+            let (tiles, cigar) = self.columns.tiles_cigar(result);
+            record["tiles"] = tiles.into();
+            record["cigar"] = cigar.into();
+        }
+        if let Some(read_group) = &self.columns.read_group {
+            record["sample"] = read_group.sample.as_str().into();
+            record["library"] = read_group.library.as_deref().unwrap_or("").into();
+            record["platform_unit"] = read_group.platform_unit.as_deref().unwrap_or("").into();
+        }
+        write_line(&mut self.out, &record.to_string())
+    }
+
+    fn write_file_summary(&mut self, file: &FileReport) -> Result<(), String> {
+        let mut record = serde_json::to_value(file)
+            .map_err(|e| format!("Failed to serialize file summary: {}", e))?;
+        record["type"] = "file_summary".into();
+        write_line(&mut self.out, &record.to_string())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        flush(self.out)
+    }
+}
+
+// Unmapped records; chunk index and score go in local-use Z* tags
+struct SamSink {
+    out: BufWriter<File>,
+    columns: Columns,
+}
+
+impl SamSink {
+    fn create(path: &str, columns: Columns) -> Result<Self, String> {
+        let mut out = create_file(path)?;
+        write_line(&mut out, "@HD\tVN:1.6\tSO:unsorted")?;
+        if let Some(read_group) = &columns.read_group {
+            write_line(&mut out, &read_group.sam_header())?;
+        }
+        write_line(&mut out, &format!("@PG\tID:rustseq_mini\tPN:rustseq_mini\tVN:{}", env!("CARGO_PKG_VERSION")))?;
+        Ok(Self { out, columns })
+    }
+}
+
+impl OutputSink for SamSink {
+    fn write_read_result(&mut self, result: &ReadResult<'_>) -> Result<(), String> {
+        let mut line = format!("{}\t4\t*\t0\t0\t*\t*\t0\t0\t{}\t*\tZF:i:{}\tZC:i:{}\tZS:i:{}",
+                               result.name_string(), if result.seq.is_empty() { "*".into() } else { String::from_utf8_lossy(result.seq) },
+                               result.file_index + 1, result.chunk_index, result.chunk_score);
+        if self.columns.long_reads {
+            let (tiles, cigar) = self.columns.tiles_cigar(result);
+            line.push_str(&format!("\tZT:i:{}\tZG:Z:{}", tiles, cigar));
+        }
+        if let Some(read_group) = &self.columns.read_group {
+            line.push_str(&format!("\tRG:Z:{}", read_group.id));
+        }
+        write_line(&mut self.out, &line)
+    }
+
+    // SAM headers can't follow records
+    fn write_file_summary(&mut self, _: &FileReport) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        flush(self.out)
+    }
+}
+
+// Keyed by the read name, the value is the TSV columns after it
+struct StoreSink {
+    store: ReadStoreWriter,
+    columns: Columns,
+}
+
+impl OutputSink for StoreSink {
+    fn write_read_result(&mut self, result: &ReadResult<'_>) -> Result<(), String> {
+        self.store.put(&result.name_string(), &self.columns.tsv_fields(result))
+    }
+
+    fn write_file_summary(&mut self, _: &FileReport) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.store.finish().map(|_| ())
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.store.buffered_bytes()
+    }
+}
+
+struct NullSink;
+
+impl OutputSink for NullSink {
+    fn write_read_result(&mut self, _: &ReadResult<'_>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn write_file_summary(&mut self, _: &FileReport) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+// `overlap`: all-vs-all overlaps between reads, with no reference, written as
+// PAF for assemblers (miniasm, and the overlap input of other OLC assemblers)
+// Prefilter: each read's (w, k) minimizers, the smallest canonical ntHash of
+// every w consecutive k-mers together with the strand it was read on, go into
+// one index. Minimizers found more than MAX_OCCURRENCES times are repeats that
+// would pair every read with every other, and are dropped. Each read looks its
+// minimizers up and keeps the hits on reads after it in the input, so a pair is
+// found once. Hits on the other strand are put in the reverse-complemented
+// target's coordinates, so both strands chain the same way
+// Chaining: per target and strand, hits are sorted along the query and each one
+// extends the longest chain among the LOOKBACK hits before it that lies before
+// it on both reads, within MAX_GAP bases and with the two gaps differing by no
+// more than MIN_DRIFT or a tenth of the gap (indels of noisy long reads). The
+// best chain of a target, on either strand, is its overlap candidate; it needs
+// --min-minimizers anchors and --min-overlap bases of the query
+// Extension: the stretch from each anchor to the next (and the last anchor's
+// k-mer) is one pair of the batch pairwise machinery (ungapped.rs, paired
+// diagonals), one GPU launch per batch of BATCH_BLOCKS stretches or a parallel
+// pass on the CPU. The overlap's score is the sum of its stretches' best
+// ungapped local scores, so an overlap whose anchors are chance hits between
+// unrelated sequence scores low and --min-score drops it
+// PAF columns follow minimap2 without base-level alignment: matches are the
+// query bases covered by anchor k-mers, the block length is the longer of the
+// two spans and the mapping quality is 255 (missing). Tags: cm:i (anchors in the
+// chain), s1:i (chain score: the anchors' covered bases) and AS:i (the score)
+
+use crate::fastq::FastqChunks;
+use crate::input_format::{detect_path, SeqFormat};
+use crate::ungapped::{ScoreMatrix, SequenceSet};
+use std::collections::HashMap;
+use std::io::Write;
+
+pub const DEFAULT_KMER: usize = 15;
+pub const DEFAULT_WINDOW: usize = 10;
+pub const DEFAULT_MIN_MINIMIZERS: usize = 3;
+pub const DEFAULT_MIN_OVERLAP: usize = 500;
+
+const MAX_OCCURRENCES: usize = 200;
+const MAX_GAP: u32 = 5000;
+const MIN_DRIFT: u32 = 50;
+const LOOKBACK: usize = 50;
+const BATCH_BLOCKS: usize = 65_536;
+const FASTQ_BATCH_READS: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OverlapOptions {
+    pub kmer: usize,
+    pub window: usize,
+    pub min_minimizers: usize,
+    pub min_overlap: usize,
+    pub min_score: i32,
+}
+
+impl OverlapOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.kmer == 0 || self.kmer > crate::sketch::MAX_KMER {
+            return Err(format!("overlap --kmer must be 1 to {}, got {}", crate::sketch::MAX_KMER, self.kmer));
+        }
+        if self.window == 0 {
+            return Err("overlap --window must be at least 1 k-mer".to_string());
+        }
+        if self.min_minimizers == 0 {
+            return Err("overlap --min-minimizers must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OverlapSummary {
+    pub reads: usize,
+    pub bases: usize,
+    pub minimizers: usize,
+    // Distinct minimizers dropped as repeats
+    pub repetitive: usize,
+    // Read pairs with a chain, before the length, anchor and score limits
+    pub candidates: usize,
+    pub overlaps: usize,
+    pub blocks: usize,
+}
+
+struct Read {
+    name: String,
+    bases: Vec<u8>,
+}
+
+// A minimizer hit: (read, position, read on the reverse strand)
+type Occurrence = (u32, u32, bool);
+
+struct Overlap {
+    query: u32,
+    target: u32,
+    reverse: bool,
+    // (query position, target position in the oriented target), in chain order
+    anchors: Vec<(u32, u32)>,
+}
+
+// Names and bases of every read of FASTA or FASTQ files (plain or compressed)
+fn load_reads(paths: &[String]) -> Result<Vec<Read>, String> {
+    let mut reads = Vec::new();
+    for path in paths {
+        if detect_path(path)?.format == Some(SeqFormat::Fasta) {
+            reads.extend(crate::simulate::load_reference(path)?.into_iter().map(|(name, bases)| Read { name, bases }));
+            continue;
+        }
+        for batch in FastqChunks::open(path, FASTQ_BATCH_READS)? {
+            for record in batch?.iter() {
+                let name = record.id_str().split_whitespace().next().unwrap_or("").to_string();
+                reads.push(Read { name, bases: record.seq.to_ascii_uppercase() });
+            }
+        }
+    }
+    Ok(reads)
+}
+
+// (position, hash, reverse) of the (w, k) minimizers of `sequence`, each once
+fn minimizers(sequence: &[u8], k: usize, w: usize) -> Vec<(u32, u64, bool)> {
+    let mut kmers = Vec::new();
+    crate::rolling_hash::for_each_kmer(sequence, k, |start, forward, reverse| {
+        // A k-mer that is its own reverse complement has no strand
+        if forward != reverse {
+            kmers.push((start as u32, crate::rolling_hash::canonical(forward, reverse), reverse < forward));
+        }
+        true
+    });
+    let mut picked: Vec<(u32, u64, bool)> = Vec::new();
+    // Indexes into kmers with increasing hashes: the front is the window's minimum
+    let mut window = std::collections::VecDeque::new();
+    for (index, &(_, hash, _)) in kmers.iter().enumerate() {
+        while window.back().is_some_and(|&last: &usize| kmers[last].1 >= hash) {
+            window.pop_back();
+        }
+        window.push_back(index);
+        if window.front().is_some_and(|&first| first + w <= index) {
+            window.pop_front();
+        }
+        if index + 1 >= w.min(kmers.len()) {
+            let minimum = kmers[window[0]];
+            if picked.last() != Some(&minimum) {
+                picked.push(minimum);
+            }
+        }
+    }
+    picked
+}
+
+// Longest chain of hits sorted by query position, as indexes into `hits`
+fn chain(hits: &[(u32, u32)]) -> Vec<usize> {
+    let mut length = vec![1usize; hits.len()];
+    let mut previous = vec![usize::MAX; hits.len()];
+    for i in 0..hits.len() {
+        let (q, t) = hits[i];
+        for j in (i.saturating_sub(LOOKBACK)..i).rev() {
+            let (pq, pt) = hits[j];
+            if pq >= q || pt >= t {
+                continue;
+            }
+            let (dq, dt) = (q - pq, t - pt);
+            if dq.max(dt) > MAX_GAP || dq.abs_diff(dt) > MIN_DRIFT.max(dq / 10) {
+                continue;
+            }
+            if length[j] + 1 > length[i] {
+                length[i] = length[j] + 1;
+                previous[i] = j;
+            }
+        }
+    }
+    let Some(mut end) = (0..hits.len()).max_by_key(|&i| length[i]) else { return Vec::new() };
+    let mut indexes = vec![end];
+    while previous[end] != usize::MAX {
+        end = previous[end];
+        indexes.push(end);
+    }
+    indexes.reverse();
+    indexes
+}
+
+// Bases covered by k-mers starting at sorted `positions`
+fn covered(positions: impl Iterator<Item = u32>, k: u32) -> u32 {
+    let (mut bases, mut end) = (0, 0);
+    for start in positions {
+        bases += (start + k).saturating_sub(start.max(end));
+        end = end.max(start + k);
+    }
+    bases
+}
+
+// The best chain with each later read that shares minimizers with read `query`
+fn candidates(query: u32, reads: &[Read], own: &[(u32, u64, bool)], index: &HashMap<u64, Vec<Occurrence>>, k: u32) -> Vec<Overlap> {
+    // (target, reverse) -> (query position, oriented target position)
+    let mut hits: HashMap<(u32, bool), Vec<(u32, u32)>> = HashMap::new();
+    for &(position, hash, reverse) in own {
+        for &(target, target_position, target_reverse) in index.get(&hash).into_iter().flatten() {
+            if target <= query {
+                continue;
+            }
+            let strand = reverse != target_reverse;
+            let oriented = if strand { reads[target as usize].bases.len() as u32 - target_position - k } else { target_position };
+            hits.entry((target, strand)).or_default().push((position, oriented));
+        }
+    }
+    let mut best: HashMap<u32, Overlap> = HashMap::new();
+    for ((target, reverse), mut hits) in hits {
+        hits.sort_unstable();
+        hits.dedup();
+        let anchors: Vec<(u32, u32)> = chain(&hits).into_iter().map(|i| hits[i]).collect();
+        let replace = best.get(&target).is_none_or(|current| anchors.len() > current.anchors.len());
+        if replace {
+            best.insert(target, Overlap { query, target, reverse, anchors });
+        }
+    }
+    let mut overlaps: Vec<Overlap> = best.into_values().collect();
+    overlaps.sort_by_key(|overlap| overlap.target);
+    overlaps
+}
+
+impl Overlap {
+    fn query_span(&self, k: u32) -> (u32, u32) {
+        (self.anchors[0].0, self.anchors[self.anchors.len() - 1].0 + k)
+    }
+
+    fn oriented_target_span(&self, k: u32) -> (u32, u32) {
+        (self.anchors[0].1, self.anchors[self.anchors.len() - 1].1 + k)
+    }
+
+    // The stretches between anchors, as (query bases, oriented target bases)
+    fn blocks<'a>(&self, reads: &'a [Read], k: u32) -> Vec<(&'a [u8], Vec<u8>)> {
+        let query = &reads[self.query as usize].bases;
+        let target = &reads[self.target as usize].bases;
+        let (start, end) = self.oriented_target_span(k);
+        let oriented = if self.reverse {
+            let length = target.len() as u32;
+            crate::assembly_compare::reverse_complement(&target[(length - end) as usize..(length - start) as usize])
+        } else {
+            target[start as usize..end as usize].to_vec()
+        };
+        let mut ends: Vec<(u32, u32)> = self.anchors.iter().skip(1).copied().collect();
+        let &(last_q, last_t) = self.anchors.last().unwrap_or(&(0, 0));
+        ends.push((last_q + k, last_t + k));
+        self.anchors.iter().zip(ends).map(|(&(q, t), (next_q, next_t))| {
+            (&query[q as usize..next_q as usize], oriented[(t - start) as usize..(next_t - start) as usize].to_vec())
+        }).collect()
+    }
+
+    fn write_paf(&self, reads: &[Read], k: u32, score: i32, out: &mut dyn Write) -> std::io::Result<()> {
+        let (query, target) = (&reads[self.query as usize], &reads[self.target as usize]);
+        let (qs, qe) = self.query_span(k);
+        let (os, oe) = self.oriented_target_span(k);
+        let length = target.bases.len() as u32;
+        let (ts, te) = if self.reverse { (length - oe, length - os) } else { (os, oe) };
+        let matches = covered(self.anchors.iter().map(|&(q, _)| q), k);
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t255\tcm:i:{}\ts1:i:{}\tAS:i:{}",
+                 query.name, query.bases.len(), qs, qe, if self.reverse { '-' } else { '+' },
+                 target.name, length, ts, te, matches, (qe - qs).max(te - ts),
+                 self.anchors.len(), matches, score)
+    }
+}
+
+// Score the stretches of `overlaps` and write those reaching --min-score
+fn extend(overlaps: &[Overlap], reads: &[Read], options: &OverlapOptions, use_gpu: bool,
+          summary: &mut OverlapSummary, out: &mut dyn Write) -> Result<(), String> {
+    let k = options.kmer as u32;
+    let matrix = ScoreMatrix::nucleotide();
+    let (mut queries, mut targets) = (SequenceSet::new(), SequenceSet::new());
+    for overlap in overlaps {
+        for (query, target) in overlap.blocks(reads, k) {
+            queries.push(query, &matrix);
+            targets.push(&target, &matrix);
+        }
+    }
+    summary.blocks += queries.len();
+    let scores = crate::ungapped::paired_scores(&queries, &targets, std::slice::from_ref(&matrix), &vec![0; queries.len()], use_gpu)?;
+    let mut scores = scores.into_iter();
+    for overlap in overlaps {
+        let score: i32 = scores.by_ref().take(overlap.anchors.len()).sum();
+        if score < options.min_score {
+            continue;
+        }
+        overlap.write_paf(reads, k, score, out).map_err(|e| format!("Failed to write overlaps: {}", e))?;
+        summary.overlaps += 1;
+    }
+    Ok(())
+}
+
+// Every overlap between the reads of `paths`, as PAF lines
+pub fn find_overlaps(paths: &[String], options: &OverlapOptions, use_gpu: bool, out: &mut dyn Write) -> Result<OverlapSummary, String> {
+    let reads = load_reads(paths)?;
+    if reads.len() > u32::MAX as usize {
+        return Err(format!("overlap takes up to {} reads, got {}", u32::MAX, reads.len()));
+    }
+    let mut summary = OverlapSummary { reads: reads.len(), bases: reads.iter().map(|read| read.bases.len()).sum(), ..Default::default() };
+    let k = options.kmer as u32;
+
+    let sketches: Vec<Vec<(u32, u64, bool)>> = reads.iter().map(|read| minimizers(&read.bases, options.kmer, options.window)).collect();
+    let mut index: HashMap<u64, Vec<Occurrence>> = HashMap::new();
+    for (read, sketch) in sketches.iter().enumerate() {
+        summary.minimizers += sketch.len();
+        for &(position, hash, reverse) in sketch {
+            index.entry(hash).or_default().push((read as u32, position, reverse));
+        }
+    }
+    index.retain(|_, occurrences| {
+        let keep = occurrences.len() <= MAX_OCCURRENCES;
+        summary.repetitive += usize::from(!keep);
+        keep
+    });
+
+    let mut batch: Vec<Overlap> = Vec::new();
+    let mut blocks = 0;
+    for (query, sketch) in sketches.iter().enumerate() {
+        for overlap in candidates(query as u32, &reads, sketch, &index, k) {
+            summary.candidates += 1;
+            let (qs, qe) = overlap.query_span(k);
+            if overlap.anchors.len() < options.min_minimizers || ((qe - qs) as usize) < options.min_overlap {
+                continue;
+            }
+            blocks += overlap.anchors.len();
+            batch.push(overlap);
+        }
+        if blocks >= BATCH_BLOCKS {
+            extend(&batch, &reads, options, use_gpu, &mut summary, out)?;
+            batch.clear();
+            blocks = 0;
+        }
+    }
+    extend(&batch, &reads, options, use_gpu, &mut summary, out)?;
+    Ok(summary)
+}
+
+impl OverlapSummary {
+    pub fn print(&self) {
+        println!("Reads: {} ({} bases), {} minimizers indexed, {} repetitive minimizers dropped",
+                 self.reads, self.bases, self.minimizers, self.repetitive);
+        println!("Overlaps: {} of {} chained read pairs, {} stretches extended", self.overlaps, self.candidates, self.blocks);
+    }
+}
@@ -0,0 +1,344 @@
+// Packed read cache: `prepare` converts a FASTQ (or FASTA) input once into
+// <file>.rspk next to it, and WGS runs read that instead of decompressing and
+// parsing the input again. Bases are packed 2 bits each (A, C, G, T); anything
+// else (N, IUPAC codes, lowercase) is kept as runs of the original byte, so the
+// reads come back byte for byte and scores don't change. Names and qualities
+// are stored only when asked for (--names, --qualities): a run that needs
+// them (--read-output, --qc, --trim-sliding-window) falls back to the input
+// when the cache doesn't have them
+// One file: a header (magic, flags, size and modification time of the input),
+// the reads in input order, then a footer with the read and base counts. A
+// cache without its footer (prepare didn't finish) or older than its input is
+// ignored with a warning, like a stale gzip index
+// Per read: length (u32), name (u32 length + bytes), runs of other bytes
+// (u32 count, then u32 start, u32 length, byte each), packed bases, qualities
+// (u32 length + bytes)
+
+use crate::chunking::ChunkSize;
+use crate::read_chunk::{ReadChunk, ReadName, ReadNameMode};
+use crate::warnings::{self, Category};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+const MAGIC: &[u8; 8] = b"RSPACK01";
+const FOOTER_MAGIC: &[u8; 8] = b"RSPKEND1";
+// Magic, flags, input size and modification time
+const HEADER_BYTES: u64 = 25;
+// Reads, bases, magic
+const FOOTER_BYTES: u64 = 24;
+const FLAG_NAMES: u8 = 1;
+const FLAG_QUALITIES: u8 = 2;
+pub(crate) const BASES: &[u8; 4] = b"ACGT";
+// Quality length of a read without a quality line (FASTA)
+const NO_QUALITY: u32 = u32::MAX;
+// Reads per chunk while preparing
+const PREPARE_CHUNK_READS: usize = 64 * 1024;
+
+pub fn cache_path(path: &str) -> String {
+    format!("{}.rspk", path)
+}
+
+pub(crate) fn base_code(base: u8) -> Option<u8> {
+    match base {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrepareSummary {
+    pub reads: u64,
+    pub bases: u64,
+    // Bases kept as runs of their original byte
+    pub unpacked_bases: u64,
+    pub input_bytes: u64,
+    pub cache_bytes: u64,
+}
+
+struct PackedWriter {
+    path: String,
+    out: BufWriter<File>,
+    flags: u8,
+    reads: u64,
+    bases: u64,
+    unpacked_bases: u64,
+    record: Vec<u8>,
+    runs: Vec<(u32, u32, u8)>,
+}
+
+impl PackedWriter {
+    fn create(path: &str, flags: u8, input_size: u64, input_modified: u64) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        let mut header = MAGIC.to_vec();
+        header.push(flags);
+        header.extend_from_slice(&input_size.to_le_bytes());
+        header.extend_from_slice(&input_modified.to_le_bytes());
+        out.write_all(&header).map_err(|e| write_error(path, e))?;
+        Ok(Self { path: path.to_string(), out, flags, reads: 0, bases: 0, unpacked_bases: 0, record: Vec::new(), runs: Vec::new() })
+    }
+
+    fn write_chunk(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        for i in 0..chunk.len() {
+            let read = chunk.read(i);
+            let length = u32::try_from(read.len())
+                .map_err(|_| format!("Read {} of {} bases is too long for a packed cache", self.reads + 1, read.len()))?;
+            self.record.clear();
+            self.record.extend_from_slice(&length.to_le_bytes());
+            if self.flags & FLAG_NAMES != 0 {
+                let name = match chunk.name(i) {
+                    Some(ReadName::Name(name)) => name,
+                    _ => &[],
+                };
+                self.record.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                self.record.extend_from_slice(name);
+            }
+
+            self.runs.clear();
+            for (position, &base) in read.iter().enumerate() {
+                if base_code(base).is_some() {
+                    continue;
+                }
+                match self.runs.last_mut() {
+                    Some((start, run, byte)) if *byte == base && (*start + *run) as usize == position => *run += 1,
+                    _ => self.runs.push((position as u32, 1, base)),
+                }
+                self.unpacked_bases += 1;
+            }
+            self.record.extend_from_slice(&(self.runs.len() as u32).to_le_bytes());
+            for &(start, run, byte) in &self.runs {
+                self.record.extend_from_slice(&start.to_le_bytes());
+                self.record.extend_from_slice(&run.to_le_bytes());
+                self.record.push(byte);
+            }
+            for four in read.chunks(4) {
+                let packed = four.iter().enumerate()
+                    .fold(0u8, |packed, (j, &base)| packed | base_code(base).unwrap_or(0) << (j * 2));
+                self.record.push(packed);
+            }
+
+            if self.flags & FLAG_QUALITIES != 0 {
+                match chunk.quality(i) {
+                    Some(quality) => {
+                        self.record.extend_from_slice(&(quality.len() as u32).to_le_bytes());
+                        self.record.extend_from_slice(quality);
+                    },
+                    None => self.record.extend_from_slice(&NO_QUALITY.to_le_bytes()),
+                }
+            }
+            self.out.write_all(&self.record).map_err(|e| write_error(&self.path, e))?;
+            self.reads += 1;
+            self.bases += read.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(u64, u64, u64), String> {
+        let mut footer = self.reads.to_le_bytes().to_vec();
+        footer.extend_from_slice(&self.bases.to_le_bytes());
+        footer.extend_from_slice(FOOTER_MAGIC);
+        self.out.write_all(&footer).and_then(|_| self.out.flush())
+            .map_err(|e| write_error(&self.path, e))?;
+        Ok((self.reads, self.bases, self.unpacked_bases))
+    }
+}
+
+fn write_error(path: &str, e: std::io::Error) -> String {
+    format!("Failed to write {}: {}", path, e)
+}
+
+// Convert `path` into its packed cache; written under a temporary name and
+// renamed when complete, so an interrupted prepare leaves the old cache or none
+pub fn prepare(path: &str, names: bool, qualities: bool) -> Result<PrepareSummary, String> {
+    let detected = crate::input_format::detect_path(path)?;
+    if detected.format.is_some_and(|format| format.is_alignment()) {
+        return Err(format!("{} is {}; prepare converts FASTQ and FASTA inputs", path, detected.describe()));
+    }
+    let (input_bytes, modified) = crate::parallel_gzip::file_stamp(path)?;
+    let flags = if names { FLAG_NAMES } else { 0 } | if qualities { FLAG_QUALITIES } else { 0 };
+    let cache = cache_path(path);
+    let partial = format!("{}.tmp", cache);
+    let mut writer = PackedWriter::create(&partial, flags, input_bytes, modified)?;
+
+    crate::read_chunk::keep_qualities(qualities);
+    let name_mode = if names { ReadNameMode::Keep } else { ReadNameMode::Discard };
+    let written = crate::async_input::process_input_in_chunks(path, ChunkSize::Reads(PREPARE_CHUNK_READS), name_mode, |chunk| writer.write_chunk(chunk))
+        .and_then(|_| writer.finish());
+    crate::read_chunk::keep_qualities(false);
+    let (reads, bases, unpacked_bases) = match written {
+        Ok(counts) => counts,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e);
+        },
+    };
+    std::fs::rename(&partial, &cache).map_err(|e| format!("Failed to move {} to {}: {}", partial, cache, e))?;
+    let cache_bytes = std::fs::metadata(&cache).map_or(0, |m| m.len());
+    Ok(PrepareSummary { reads, bases, unpacked_bases, input_bytes, cache_bytes })
+}
+
+// Whether `path` has a complete cache that still matches it
+pub fn is_prepared(path: &str) -> bool {
+    PackedReader::open(path).is_ok_and(|reader| reader.matches(path).unwrap_or(false))
+}
+
+pub struct PackedReader {
+    path: String,
+    input: BufReader<File>,
+    flags: u8,
+    input_size: u64,
+    input_modified: u64,
+    reads: u64,
+    bases: u64,
+    read_so_far: u64,
+    packed: Vec<u8>,
+    read: Vec<u8>,
+    field: Vec<u8>,
+}
+
+impl PackedReader {
+    // The cache of input `input_path`
+    fn open(input_path: &str) -> Result<Self, String> {
+        let path = cache_path(input_path);
+        let mut file = File::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let invalid = |what: &str| format!("{} is not a packed read cache ({})", path, what);
+        let mut header = [0u8; HEADER_BYTES as usize];
+        file.read_exact(&mut header).map_err(|_| invalid("too short"))?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        let size = file.metadata().map_err(|e| format!("Failed to read {}: {}", path, e))?.len();
+        let mut footer = [0u8; FOOTER_BYTES as usize];
+        file.seek(SeekFrom::Start(size.saturating_sub(FOOTER_BYTES)))
+            .and_then(|_| file.read_exact(&mut footer))
+            .map_err(|_| invalid("no footer"))?;
+        if size < HEADER_BYTES + FOOTER_BYTES || &footer[16..] != FOOTER_MAGIC {
+            return Err(invalid("no footer; prepare may not have finished"));
+        }
+        file.seek(SeekFrom::Start(HEADER_BYTES)).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Ok(Self {
+            path,
+            input: BufReader::with_capacity(crate::stages::RAW_BLOCK_BYTES, file),
+            flags: header[8],
+            input_size: u64::from_le_bytes(header[9..17].try_into().unwrap()),
+            input_modified: u64::from_le_bytes(header[17..25].try_into().unwrap()),
+            reads: u64::from_le_bytes(footer[..8].try_into().unwrap()),
+            bases: u64::from_le_bytes(footer[8..16].try_into().unwrap()),
+            read_so_far: 0,
+            packed: Vec::new(),
+            read: Vec::new(),
+            field: Vec::new(),
+        })
+    }
+
+    // The cache next to `input_path`, if there is one that is complete, still
+    // matches the input, and has what a run reading names `name_mode` needs
+    pub fn for_file(input_path: &str, name_mode: ReadNameMode) -> Result<Option<Self>, String> {
+        let path = cache_path(input_path);
+        if !std::path::Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let reader = match Self::open(input_path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                warnings::warn(Category::Input, format!("{}; parsing {} instead", e, crate::paths::file_name(input_path)));
+                return Ok(None);
+            },
+        };
+        let unusable = if !reader.matches(input_path)? {
+            Some(("is older than its input", ""))
+        } else if name_mode != ReadNameMode::Discard && !reader.has_names() {
+            Some(("has no read names", " with --names"))
+        } else if crate::read_chunk::keeps_qualities() && !reader.has_qualities() {
+            Some(("has no qualities", " with --qualities"))
+        } else {
+            None
+        };
+        if let Some((reason, flag)) = unusable {
+            warnings::warn(Category::Input, format!("{} {}; parsing the input instead (run `prepare {}` again{})", path, reason, input_path, flag));
+            return Ok(None);
+        }
+        Ok(Some(reader))
+    }
+
+    fn matches(&self, input_path: &str) -> Result<bool, String> {
+        Ok(crate::parallel_gzip::file_stamp(input_path)? == (self.input_size, self.input_modified))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn has_names(&self) -> bool {
+        self.flags & FLAG_NAMES != 0
+    }
+
+    pub fn has_qualities(&self) -> bool {
+        self.flags & FLAG_QUALITIES != 0
+    }
+
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    pub fn bases(&self) -> u64 {
+        self.bases
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0u8; 4];
+        self.input.read_exact(&mut bytes).map_err(|e| self.read_error(e))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<(), String> {
+        self.field.resize(length, 0);
+        let Self { path, input, field, .. } = self;
+        input.read_exact(field).map_err(|e| format!("Failed to read {}: {}", path, e))
+    }
+
+    fn read_error(&self, e: std::io::Error) -> String {
+        format!("Failed to read {}: {}", self.path, e)
+    }
+
+    // Append the next read to `chunk`; false once every read has been read
+    pub fn next_read(&mut self, chunk: &mut ReadChunk) -> Result<bool, String> {
+        if self.read_so_far == self.reads {
+            return Ok(false);
+        }
+        let length = self.u32()? as usize;
+        if self.has_names() {
+            let name_length = self.u32()? as usize;
+            self.bytes(name_length)?;
+            chunk.push_name(&self.field);
+        }
+        let runs = self.u32()? as usize;
+        self.bytes(runs * 9)?;
+        self.packed.resize(length.div_ceil(4), 0);
+        let Self { path, input, packed, .. } = self;
+        input.read_exact(packed).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+        self.read.clear();
+        self.read.extend((0..length).map(|j| BASES[(self.packed[j / 4] >> ((j % 4) * 2) & 3) as usize]));
+        for run in self.field.chunks_exact(9) {
+            let start = u32::from_le_bytes(run[..4].try_into().unwrap()) as usize;
+            let end = start + u32::from_le_bytes(run[4..8].try_into().unwrap()) as usize;
+            let target = self.read.get_mut(start..end)
+                .ok_or_else(|| format!("{} is corrupt: a run past the end of read {}", self.path, self.read_so_far + 1))?;
+            target.fill(run[8]);
+        }
+        chunk.push_read(&self.read);
+        if self.has_qualities() {
+            let quality_length = self.u32()?;
+            if quality_length != NO_QUALITY {
+                self.bytes(quality_length as usize)?;
+                chunk.push_quality(&self.field);
+            }
+        }
+        self.read_so_far += 1;
+        Ok(true)
+    }
+}
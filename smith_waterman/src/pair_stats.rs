@@ -0,0 +1,339 @@
+// `pair-stats`: read pair concordance and insert sizes per lane, from R1/R2
+// FASTQ files aligned against a reference
+// The files are read in lockstep, so R1's nth read is R2's nth; names that
+// don't agree (after the first word and any /1 or /2) stop the run. Each mate
+// is placed on the reference by its 21-mers as walk places a window
+// (chromosome_walk.rs), on either strand, then scored against the placed span
+// as the best ungapped local run, in batches of BATCH_PAIRS pairs on the GPU or
+// the CPU. A mate is aligned when it is placed and scores at least
+// MIN_SCORE_SHARE of its best possible score (every base a match)
+// Pairs with both mates aligned are concordant when the mates are on the same
+// contig, on opposite strands and facing each other (FR: the plus-strand mate
+// starts no later than the minus-strand mate ends), and the template they
+// span is at most --max-insert bases. The insert size is that span, from the
+// leftmost mate start to the rightmost mate end, and its distribution is taken
+// over the concordant pairs
+// Reads are grouped by lane, taken from Illumina read names as the QC report
+// reads them (qc.rs, from the name's first word) or else from the _L001_ part of the R1 file name; pairs
+// with neither are under lane "unknown"
+// With a coverage track asked for, every aligned mate's reference span adds to
+// the read depth (coverage_track.rs), whether or not its pair is concordant
+
+use crate::assembly_compare::reverse_complement;
+use crate::chromosome_walk::{Placement, QueryIndex};
+use crate::coverage_track::{CoverageSummary, CoverageTrack};
+use crate::fastq::{FastqBatch, FastqChunks};
+use crate::ungapped::{self, ScoreMatrix, SequenceSet};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const DEFAULT_MAX_INSERT: usize = 1000;
+const BATCH_PAIRS: usize = 10_000;
+const MIN_SCORE_SHARE: f64 = 0.5;
+const UNKNOWN_LANE: &str = "unknown";
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InsertSizes {
+    pub pairs: u64,
+    pub mean: f64,
+    pub sd: f64,
+    pub median: usize,
+    // Median absolute deviation from the median
+    pub mad: usize,
+    pub p5: usize,
+    pub p25: usize,
+    pub p75: usize,
+    pub p95: usize,
+    // (insert size, pairs), smallest first
+    pub histogram: Vec<(usize, u64)>,
+}
+
+impl InsertSizes {
+    fn from_counts(counts: &BTreeMap<usize, u64>) -> Self {
+        let pairs: u64 = counts.values().sum();
+        if pairs == 0 {
+            return Self::default();
+        }
+        let mean = counts.iter().map(|(&size, &n)| size as f64 * n as f64).sum::<f64>() / pairs as f64;
+        let variance = counts.iter().map(|(&size, &n)| (size as f64 - mean).powi(2) * n as f64).sum::<f64>() / pairs as f64;
+        let percentile = |counts: &BTreeMap<usize, u64>, p: u64| {
+            let rank = ((pairs - 1) * p / 100) + 1;
+            let mut seen = 0;
+            counts.iter().find(|(_, &n)| { seen += n; seen >= rank }).map_or(0, |(&size, _)| size)
+        };
+        let median = percentile(counts, 50);
+        let mut deviations: BTreeMap<usize, u64> = BTreeMap::new();
+        for (&size, &n) in counts {
+            *deviations.entry(size.abs_diff(median)).or_insert(0) += n;
+        }
+        Self {
+            pairs,
+            mean,
+            sd: variance.sqrt(),
+            median,
+            mad: percentile(&deviations, 50),
+            p5: percentile(counts, 5),
+            p25: percentile(counts, 25),
+            p75: percentile(counts, 75),
+            p95: percentile(counts, 95),
+            histogram: counts.iter().map(|(&size, &n)| (size, n)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LaneStats {
+    pub lane: String,
+    pub pairs: u64,
+    pub both_aligned: u64,
+    pub one_aligned: u64,
+    pub neither_aligned: u64,
+    pub concordant: u64,
+    // Both mates aligned but the pair isn't concordant, by the first reason found
+    pub different_contigs: u64,
+    pub wrong_orientation: u64,
+    pub too_far: u64,
+    // Concordant pairs out of those with both mates aligned
+    pub concordance_rate: f64,
+    pub insert_size: InsertSizes,
+    #[serde(skip)]
+    sizes: BTreeMap<usize, u64>,
+}
+
+impl LaneStats {
+    fn new(lane: &str) -> Self {
+        Self { lane: lane.to_string(), ..Default::default() }
+    }
+
+    fn add(&mut self, outcome: PairOutcome) {
+        self.pairs += 1;
+        match outcome {
+            PairOutcome::Neither => self.neither_aligned += 1,
+            PairOutcome::One => self.one_aligned += 1,
+            PairOutcome::Concordant(size) => {
+                self.both_aligned += 1;
+                self.concordant += 1;
+                *self.sizes.entry(size).or_insert(0) += 1;
+            },
+            PairOutcome::DifferentContigs => { self.both_aligned += 1; self.different_contigs += 1 },
+            PairOutcome::WrongOrientation => { self.both_aligned += 1; self.wrong_orientation += 1 },
+            PairOutcome::TooFar => { self.both_aligned += 1; self.too_far += 1 },
+        }
+    }
+
+    fn merge(&mut self, other: &LaneStats) {
+        self.pairs += other.pairs;
+        self.both_aligned += other.both_aligned;
+        self.one_aligned += other.one_aligned;
+        self.neither_aligned += other.neither_aligned;
+        self.concordant += other.concordant;
+        self.different_contigs += other.different_contigs;
+        self.wrong_orientation += other.wrong_orientation;
+        self.too_far += other.too_far;
+        for (&size, &n) in &other.sizes {
+            *self.sizes.entry(size).or_insert(0) += n;
+        }
+    }
+
+    fn finish(&mut self) {
+        self.concordance_rate = if self.both_aligned == 0 { 0.0 } else { self.concordant as f64 / self.both_aligned as f64 };
+        self.insert_size = InsertSizes::from_counts(&self.sizes);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PairReport {
+    pub reference: String,
+    // (R1, R2) of each file pair
+    pub files: Vec<(String, String)>,
+    pub max_insert: usize,
+    pub lanes: Vec<LaneStats>,
+    pub total: LaneStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<CoverageSummary>,
+    // Depth of every aligned mate, when asked for; written by the caller
+    #[serde(skip)]
+    pub track: Option<CoverageTrack>,
+}
+
+impl PairReport {
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize pair report: {}", e))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    pub fn print(&self) {
+        println!("{:<8}  {:>12}  {:>8}  {:>8}  {:>10}  {:>8}  {:>8}  {:>8}  {:>6}  {:>6}  {:>6}",
+                 "lane", "pairs", "both %", "one %", "concord %", "contig", "orient", "far", "median", "mad", "mean");
+        for stats in self.lanes.iter().chain(std::iter::once(&self.total)) {
+            let share = |n: u64| if stats.pairs == 0 { 0.0 } else { n as f64 * 100.0 / stats.pairs as f64 };
+            println!("{:<8}  {:>12}  {:>7.2}%  {:>7.2}%  {:>9.2}%  {:>8}  {:>8}  {:>8}  {:>6}  {:>6}  {:>6.1}",
+                     stats.lane, stats.pairs, share(stats.both_aligned), share(stats.one_aligned), stats.concordance_rate * 100.0,
+                     stats.different_contigs, stats.wrong_orientation, stats.too_far,
+                     stats.insert_size.median, stats.insert_size.mad, stats.insert_size.mean);
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.print();
+        }
+    }
+}
+
+enum PairOutcome {
+    Neither,
+    One,
+    Concordant(usize),
+    DifferentContigs,
+    WrongOrientation,
+    TooFar,
+}
+
+// An aligned mate's contig, strand and reference span
+#[derive(Clone, Copy)]
+struct Mate {
+    contig: usize,
+    minus: bool,
+    start: usize,
+    end: usize,
+}
+
+fn classify(mates: [Option<Mate>; 2], max_insert: usize) -> PairOutcome {
+    let (a, b) = match mates {
+        [Some(a), Some(b)] => (a, b),
+        [None, None] => return PairOutcome::Neither,
+        _ => return PairOutcome::One,
+    };
+    if a.contig != b.contig {
+        return PairOutcome::DifferentContigs;
+    }
+    let (plus, minus) = match (a.minus, b.minus) {
+        (false, true) => (a, b),
+        (true, false) => (b, a),
+        _ => return PairOutcome::WrongOrientation,
+    };
+    if plus.start > minus.end {
+        return PairOutcome::WrongOrientation;
+    }
+    let size = plus.end.max(minus.end) - plus.start.min(minus.start);
+    if size > max_insert {
+        PairOutcome::TooFar
+    } else {
+        PairOutcome::Concordant(size)
+    }
+}
+
+// The read name the mates share: the first word, without /1 or /2
+fn pair_name(id: &[u8]) -> &[u8] {
+    let word = id.split(|b| b.is_ascii_whitespace()).next().unwrap_or(id);
+    word.strip_suffix(b"/1").or_else(|| word.strip_suffix(b"/2")).unwrap_or(word)
+}
+
+// Lane from an Illumina file name (SAMPLE_S1_L001_R1_001.fastq.gz)
+fn file_lane(path: &str) -> Option<u32> {
+    let name = std::path::Path::new(path).file_name()?.to_string_lossy().to_string();
+    name.split('_').find_map(|field| field.strip_prefix('L').filter(|digits| digits.len() == 3).and_then(|digits| digits.parse().ok()))
+}
+
+// Concordance and insert sizes of every pair in `pairs`, by lane, and the read
+// depth along the reference with `coverage`
+pub fn pair_stats(reference: &str, pairs: &[(String, String)], max_insert: usize, use_gpu: bool, coverage: bool) -> Result<PairReport, String> {
+    let genome = crate::simulate::load_reference(reference)?;
+    if genome.iter().all(|(_, bases)| bases.is_empty()) {
+        return Err(format!("Reference {} has no sequence", reference));
+    }
+    let index = QueryIndex::new(&genome);
+    let matrix = ScoreMatrix::nucleotide();
+    let match_score = crate::scoring::selected().match_score;
+    let mut lanes: BTreeMap<String, LaneStats> = BTreeMap::new();
+    let mut track = coverage.then(|| CoverageTrack::new(genome.iter().map(|(name, bases)| (name.as_str(), bases.len()))));
+
+    for (r1, r2) in pairs {
+        let fallback = file_lane(r1).map_or(UNKNOWN_LANE.to_string(), |lane| lane.to_string());
+        let mut chunks = [FastqChunks::open(r1, BATCH_PAIRS)?, FastqChunks::open(r2, BATCH_PAIRS)?];
+        let mut batches = [FastqBatch::with_capacity(BATCH_PAIRS), FastqBatch::with_capacity(BATCH_PAIRS)];
+        let mut read = 0;
+        loop {
+            let more = [chunks[0].next_into(&mut batches[0])?, chunks[1].next_into(&mut batches[1])?];
+            if !more[0] && !more[1] {
+                break;
+            }
+            let (n1, n2) = (if more[0] { batches[0].len() } else { 0 }, if more[1] { batches[1].len() } else { 0 });
+            if n1 != n2 {
+                return Err(format!("{} and {} have different numbers of reads (after read {}); pair-stats needs mates in the same order",
+                                   r1, r2, read + n1.min(n2)));
+            }
+            for i in 0..n1 {
+                let (id1, id2) = (batches[0].record(i).id, batches[1].record(i).id);
+                if pair_name(id1) != pair_name(id2) {
+                    return Err(format!("Read {} is {} in {} but {} in {}; pair-stats needs mates in the same order",
+                                       read + i + 1, String::from_utf8_lossy(id1), r1, String::from_utf8_lossy(id2), r2));
+                }
+            }
+            let mates = align_mates(&batches, &index, &genome, &matrix, match_score, use_gpu)?;
+            for (i, mates) in mates.into_iter().enumerate() {
+                if let Some(track) = track.as_mut() {
+                    for mate in mates.iter().flatten() {
+                        track.add(mate.contig, mate.start, mate.end);
+                    }
+                }
+                let lane = crate::qc::parse_location(pair_name(batches[0].record(i).id)).map_or_else(|| fallback.clone(), |location| location.lane.to_string());
+                lanes.entry(lane.clone()).or_insert_with(|| LaneStats::new(&lane)).add(classify(mates, max_insert));
+            }
+            read += n1;
+        }
+    }
+
+    let mut total = LaneStats::new("all");
+    let mut lanes: Vec<LaneStats> = lanes.into_values().collect();
+    for lane in lanes.iter_mut() {
+        total.merge(lane);
+        lane.finish();
+    }
+    total.finish();
+    if total.pairs == 0 {
+        return Err("The input files have no reads".to_string());
+    }
+    let coverage = track.as_ref().map(CoverageTrack::summary);
+    Ok(PairReport { reference: reference.to_string(), files: pairs.to_vec(), max_insert, lanes, total, coverage, track })
+}
+
+// Place every mate of a batch and score the placed ones; None for a mate that
+// didn't align
+fn align_mates(batches: &[FastqBatch; 2], index: &QueryIndex, genome: &[(String, Vec<u8>)], matrix: &ScoreMatrix,
+               match_score: i32, use_gpu: bool) -> Result<Vec<[Option<Mate>; 2]>, String> {
+    let pairs = batches[0].len();
+    let placements: Vec<[Option<Placement>; 2]> = (0..pairs).into_par_iter()
+        .map(|i| [index.place(batches[0].record(i).seq, genome), index.place(batches[1].record(i).seq, genome)])
+        .collect();
+    let (mut queries, mut targets) = (SequenceSet::new(), SequenceSet::new());
+    for (i, placed) in placements.iter().enumerate() {
+        for (mate, placement) in placed.iter().enumerate() {
+            if let Some(p) = placement {
+                let seq = batches[mate].record(i).seq;
+                let seq = if p.minus { reverse_complement(seq) } else { seq.to_vec() };
+                queries.push(&seq, matrix);
+                targets.push(&genome[p.contig].1[p.start..p.end], matrix);
+            }
+        }
+    }
+    let scores = if queries.is_empty() {
+        Vec::new()
+    } else {
+        ungapped::paired_scores(&queries, &targets, std::slice::from_ref(matrix), &vec![0; queries.len()], use_gpu)?
+    };
+    let mut scores = scores.into_iter();
+    let mates = placements.iter().enumerate().map(|(i, placed)| {
+        let mut mates = [None, None];
+        for (mate, placement) in placed.iter().enumerate() {
+            let Some(p) = placement else { continue };
+            let length = batches[mate].record(i).seq.len();
+            let score = scores.next().unwrap_or(0);
+            if score as f64 >= MIN_SCORE_SHARE * (length as i64 * match_score as i64) as f64 {
+                let contig_length = genome[p.contig].1.len();
+                mates[mate] = Some(Mate { contig: p.contig, minus: p.minus, start: p.offset, end: (p.offset + length).min(contig_length) });
+            }
+        }
+        mates
+    }).collect();
+    Ok(mates)
+}
@@ -0,0 +1,525 @@
+// Parallel decompression within one gzip input
+// A bgzf file is a series of independent gzip members of at most 64 KiB each,
+// so batches of them are inflated across a thread pool and their output put
+// back in file order. A plain gzip file is one deflate stream that can only be
+// entered where the decoder's state is known: `index gzip` decodes it once and
+// saves an access point (compressed position, bit offset and the 32 KiB of
+// output before it) every --span MB of output to <file>.gzindex, as zlib's
+// zran example does. With that index next to a local file, the spans between
+// access points are decoded in parallel the same way, e.g. when --resume reads
+// the interrupted file again. DECOMPRESS_THREADS in .env sizes the pool; 1
+// turns both off and leaves gzip to zcat or the single-threaded decoder
+
+use crate::warnings::{self, Category};
+use libz_rs_sys::{inflate, inflateEnd, inflateInit2_, inflatePrime, inflateReset2, inflateSetDictionary, zlibVersion, z_stream,
+                  Z_BLOCK, Z_BUF_ERROR, Z_NO_FLUSH, Z_OK, Z_STREAM_END};
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+
+// Output a deflate stream can refer back to
+const WINDOW: usize = 32 * 1024;
+
+// Compressed bytes read per call while building an index
+const INDEX_READ_BUFFER: usize = 1024 * 1024;
+
+// Output between access points of a gzip index, in MB
+pub const DEFAULT_SPAN_MB: u64 = 32;
+
+// bgzf blocks inflated per thread in one batch (at most 64 KiB of output each)
+const BLOCKS_PER_THREAD: usize = 16;
+
+const INDEX_MAGIC: &[u8; 8] = b"RSGZIDX1";
+
+// gzip header window bits for zlib (15 + 16)
+const GZIP_WINDOW_BITS: i32 = 31;
+// Raw deflate, no header
+const RAW_WINDOW_BITS: i32 = -15;
+
+// Threads from DECOMPRESS_THREADS in .env, else the CPU count up to 8
+pub fn threads() -> usize {
+    std::env::var("DECOMPRESS_THREADS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&v| v > 0)
+        .unwrap_or_else(|| num_cpus::get().min(8))
+}
+
+pub fn enabled() -> bool {
+    threads() > 1
+}
+
+static POOL: OnceCell<rayon::ThreadPool> = OnceCell::new();
+
+// Own pool, so inflating doesn't queue behind --parallel-files work on the global one
+fn pool() -> &'static rayon::ThreadPool {
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads())
+            .thread_name(|i| format!("gunzip-{}", i))
+            .start_handler(|_| crate::affinity::pin_decompressor_thread())
+            .build()
+            .expect("Failed to start the decompression thread pool")
+    })
+}
+
+// Whether a local file is decoded here rather than by zcat: bgzf, or gzip with an index
+pub fn takes_local(path: &str) -> bool {
+    if !enabled() {
+        return false;
+    }
+    let mut prefix = Vec::with_capacity(18);
+    let sniffed = std::fs::File::open(path).and_then(|file| file.take(18).read_to_end(&mut prefix));
+    match sniffed.ok().and_then(|_| crate::input_format::Compression::sniff(&prefix).ok()) {
+        Some(crate::input_format::Compression::Bgzf) => true,
+        Some(crate::input_format::Compression::Gzip) => std::path::Path::new(&index_path(path)).exists(),
+        _ => false,
+    }
+}
+
+// Reads bgzf blocks off `raw` in order and inflates them a batch at a time
+pub struct BgzfReader<R: Read> {
+    raw: R,
+    offset: u64,
+    out: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(raw: R) -> Self {
+        Self { raw, offset: 0, out: Vec::new(), pos: 0, finished: false }
+    }
+
+    // Whole next block (header to trailer), or None at the end of the input
+    fn next_block(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; 12];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.raw.read(&mut header[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(invalid(format!("bgzf input ends inside a block header at byte {}", self.offset))),
+                n => filled += n,
+            }
+        }
+        if header[..2] != [0x1f, 0x8b] || header[3] & 0x04 == 0 {
+            return Err(invalid(format!("gzip member at byte {} is not a bgzf block (set DECOMPRESS_THREADS=1 to decode it as plain gzip)", self.offset)));
+        }
+        let extra_len = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut block = header.to_vec();
+        block.resize(12 + extra_len, 0);
+        self.raw.read_exact(&mut block[12..])?;
+        let block_size = bgzf_block_size(&block[12..])
+            .ok_or_else(|| invalid(format!("gzip member at byte {} has no bgzf block size (set DECOMPRESS_THREADS=1 to decode it as plain gzip)", self.offset)))?;
+        if block_size < block.len() + 8 {
+            return Err(invalid(format!("bgzf block at byte {} is shorter than its header", self.offset)));
+        }
+        let header_len = block.len();
+        block.resize(block_size, 0);
+        self.raw.read_exact(&mut block[header_len..])?;
+        self.offset += block_size as u64;
+        Ok(Some(block))
+    }
+
+    fn fill(&mut self) -> std::io::Result<()> {
+        let mut blocks = Vec::new();
+        while blocks.len() < threads() * BLOCKS_PER_THREAD {
+            match self.next_block()? {
+                Some(block) => blocks.push(block),
+                None => {
+                    self.finished = true;
+                    break;
+                },
+            }
+        }
+        let inflated: Vec<Vec<u8>> = pool().install(|| blocks.par_iter().map(|block| inflate_member(block)).collect::<std::io::Result<_>>())?;
+        self.out.clear();
+        self.pos = 0;
+        for block in inflated {
+            self.out.extend_from_slice(&block);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Empty blocks (the bgzf end marker) decode to nothing, so refill until there's output
+        while self.pos == self.out.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// BSIZE from the 'BC' subfield of the extra field: total block size minus 1
+fn bgzf_block_size(extra: &[u8]) -> Option<usize> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + len)?;
+        if rest[0] == b'B' && rest[1] == b'C' && len == 2 {
+            return Some(u16::from_le_bytes([data[0], data[1]]) as usize + 1);
+        }
+        rest = &rest[4 + len..];
+    }
+    None
+}
+
+// One gzip member, CRC checked; ISIZE (the last 4 bytes) sizes the output
+fn inflate_member(block: &[u8]) -> std::io::Result<Vec<u8>> {
+    let size = u32::from_le_bytes([block[block.len() - 4], block[block.len() - 3], block[block.len() - 2], block[block.len() - 1]]);
+    let mut out = Vec::with_capacity(size as usize);
+    flate2::bufread::GzDecoder::new(block).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// A place decoding can start from
+#[derive(Debug, Clone)]
+pub struct AccessPoint {
+    // Decompressed offset
+    pub output: u64,
+    // Compressed offset of the first whole byte after the point
+    pub input: u64,
+    // Bits of the byte before `input` that belong after the point (0-7)
+    pub bits: u8,
+    // Start of a gzip member, header included; decoded with no window
+    pub member_start: bool,
+    // Up to 32 KiB of output before the point, within its member
+    pub window: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GzipIndex {
+    // Size and modification time of the indexed file, to spot a stale index
+    pub file_size: u64,
+    pub modified: u64,
+    pub span: u64,
+    pub total_output: u64,
+    pub points: Vec<AccessPoint>,
+}
+
+pub fn index_path(path: &str) -> String {
+    format!("{}.gzindex", path)
+}
+
+// Size and modification time (seconds) of a file, to spot stale sidecars
+pub(crate) fn file_stamp(path: &str) -> Result<(u64, u64), String> {
+    let metadata = std::fs::metadata(path).map_err(|e| format!("Failed to stat {}: {}", path, e))?;
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    Ok((metadata.len(), modified))
+}
+
+// zlib inflate state; boxed because zlib keeps a pointer back to the stream
+struct Inflater(Box<z_stream>);
+
+impl Inflater {
+    fn new(window_bits: i32) -> Result<Self, String> {
+        let mut stream = Box::new(z_stream::default());
+        let ret = unsafe { inflateInit2_(&mut *stream, window_bits, zlibVersion(), std::mem::size_of::<z_stream>() as i32) };
+        if ret != Z_OK {
+            return Err(format!("Failed to start the gzip decoder (zlib error {})", ret));
+        }
+        Ok(Self(stream))
+    }
+
+    // Returns zlib's return code and the bytes consumed and produced
+    fn inflate(&mut self, input: &[u8], output: &mut [u8], flush: i32) -> (i32, usize, usize) {
+        self.0.next_in = input.as_ptr();
+        self.0.avail_in = input.len().min(u32::MAX as usize) as u32;
+        self.0.next_out = output.as_mut_ptr();
+        self.0.avail_out = output.len().min(u32::MAX as usize) as u32;
+        let (avail_in, avail_out) = (self.0.avail_in as usize, self.0.avail_out as usize);
+        let ret = unsafe { inflate(&mut *self.0, flush) };
+        let consumed = avail_in - self.0.avail_in as usize;
+        let produced = avail_out - self.0.avail_out as usize;
+        self.0.next_in = std::ptr::null();
+        self.0.next_out = std::ptr::null_mut();
+        (ret, consumed, produced)
+    }
+
+    // Unused bits in the last byte consumed, +64 in the last block, +128 at a block boundary
+    fn data_type(&self) -> i32 {
+        self.0.data_type
+    }
+
+    fn reset(&mut self, window_bits: i32) -> Result<(), String> {
+        match unsafe { inflateReset2(&mut *self.0, window_bits) } {
+            Z_OK => Ok(()),
+            ret => Err(format!("Failed to reset the gzip decoder (zlib error {})", ret)),
+        }
+    }
+
+    fn prime(&mut self, bits: u8, value: u8) -> Result<(), String> {
+        match unsafe { inflatePrime(&mut *self.0, bits as i32, value as i32) } {
+            Z_OK => Ok(()),
+            ret => Err(format!("Failed to restore the gzip decoder's bit offset (zlib error {})", ret)),
+        }
+    }
+
+    fn set_dictionary(&mut self, window: &[u8]) -> Result<(), String> {
+        match unsafe { inflateSetDictionary(&mut *self.0, window.as_ptr(), window.len() as u32) } {
+            Z_OK => Ok(()),
+            ret => Err(format!("Failed to restore the gzip decoder's window (zlib error {})", ret)),
+        }
+    }
+}
+
+impl Drop for Inflater {
+    fn drop(&mut self) {
+        unsafe { inflateEnd(&mut *self.0) };
+    }
+}
+
+// The first pass: decode the whole file once, noting an access point at every
+// member start and at the first block boundary after each `span` bytes of output
+pub fn build_index(path: &str, span: u64) -> Result<GzipIndex, String> {
+    let (file_size, modified) = file_stamp(path)?;
+    let mut file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut inflater = Inflater::new(GZIP_WINDOW_BITS)?;
+    let mut input = vec![0u8; INDEX_READ_BUFFER];
+    let (mut in_pos, mut in_len) = (0, 0);
+    // Output goes round a window-sized buffer; `window_pos` restarts with each member
+    let mut window = vec![0u8; WINDOW];
+    let mut window_pos = 0;
+    let mut member_output = 0u64;
+    let (mut total_in, mut total_out, mut last) = (0u64, 0u64, 0u64);
+    let mut points = vec![AccessPoint { output: 0, input: 0, bits: 0, member_start: true, window: Vec::new() }];
+    let mut at_eof = false;
+
+    loop {
+        if in_pos == in_len && !at_eof {
+            in_len = file.read(&mut input).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            in_pos = 0;
+            at_eof = in_len == 0;
+        }
+        let (ret, consumed, produced) = inflater.inflate(&input[in_pos..in_len], &mut window[window_pos..], Z_BLOCK);
+        in_pos += consumed;
+        total_in += consumed as u64;
+        total_out += produced as u64;
+        member_output += produced as u64;
+        window_pos = (window_pos + produced) % WINDOW;
+
+        match ret {
+            Z_STREAM_END => {
+                // Another member may follow; anything else after the trailer is ignored, as zcat does
+                if in_len - in_pos < 2 && !at_eof {
+                    input.copy_within(in_pos..in_len, 0);
+                    in_len -= in_pos;
+                    in_pos = 0;
+                    let n = file.read(&mut input[in_len..]).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+                    in_len += n;
+                    at_eof = n == 0;
+                }
+                if !input[in_pos..in_len].starts_with(&[0x1f, 0x8b]) {
+                    break;
+                }
+                inflater.reset(GZIP_WINDOW_BITS)?;
+                points.push(AccessPoint { output: total_out, input: total_in, bits: 0, member_start: true, window: Vec::new() });
+                last = total_out;
+                window_pos = 0;
+                member_output = 0;
+                continue;
+            },
+            Z_OK => {},
+            Z_BUF_ERROR if at_eof => return Err(format!("{} ends in the middle of its gzip stream", path)),
+            Z_BUF_ERROR => {},
+            ret => return Err(format!("{} is not valid gzip at byte {} (zlib error {})", path, total_in, ret)),
+        }
+
+        let data_type = inflater.data_type();
+        let at_block_end = data_type & 128 != 0 && data_type & 64 == 0;
+        if at_block_end && total_out - last >= span {
+            let window = if member_output >= WINDOW as u64 {
+                [&window[window_pos..], &window[..window_pos]].concat()
+            } else {
+                window[..member_output as usize].to_vec()
+            };
+            points.push(AccessPoint { output: total_out, input: total_in, bits: (data_type & 7) as u8, member_start: false, window });
+            last = total_out;
+        }
+    }
+
+    Ok(GzipIndex { file_size, modified, span, total_output: total_out, points })
+}
+
+impl GzipIndex {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut bytes = INDEX_MAGIC.to_vec();
+        for value in [self.file_size, self.modified, self.span, self.total_output, self.points.len() as u64] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for point in &self.points {
+            bytes.extend_from_slice(&point.output.to_le_bytes());
+            bytes.extend_from_slice(&point.input.to_le_bytes());
+            bytes.push(point.bits);
+            bytes.push(point.member_start as u8);
+            bytes.extend_from_slice(&(point.window.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&point.window);
+        }
+        std::fs::write(path, bytes).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let truncated = || format!("{} is truncated or not a gzip index", path);
+        let rest = bytes.strip_prefix(INDEX_MAGIC.as_slice()).ok_or_else(truncated)?;
+        let mut fields = IndexFields(rest);
+        let (file_size, modified, span, total_output, count) = (fields.u64(), fields.u64(), fields.u64(), fields.u64(), fields.u64());
+        let mut points = Vec::new();
+        for _ in 0..count.ok_or_else(truncated)? {
+            let (output, input, bits, member_start) = (fields.u64(), fields.u64(), fields.take(1), fields.take(1));
+            let window_len = fields.take(4).map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize);
+            let window = window_len.and_then(|len| fields.take(len));
+            match (output, input, bits, member_start, window) {
+                (Some(output), Some(input), Some(bits), Some(member_start), Some(window)) =>
+                    points.push(AccessPoint { output, input, bits: bits[0], member_start: member_start[0] != 0, window: window.to_vec() }),
+                _ => return Err(truncated()),
+            }
+        }
+        match (file_size, modified, span, total_output) {
+            (Some(file_size), Some(modified), Some(span), Some(total_output)) if points.first().is_some_and(|first| first.output == 0) =>
+                Ok(Self { file_size, modified, span, total_output, points }),
+            _ => Err(truncated()),
+        }
+    }
+
+    // The index next to a gzip file, if there is one and it still matches the file
+    pub fn for_file(path: &str) -> Result<Option<Self>, String> {
+        let sidecar = index_path(path);
+        if !std::path::Path::new(&sidecar).exists() {
+            return Ok(None);
+        }
+        let index = Self::load(&sidecar)?;
+        if file_stamp(path)? != (index.file_size, index.modified) {
+            warnings::warn(Category::Input, format!("{} is older than {}; decoding without it (run `index gzip {}` again)", sidecar, path, path));
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+
+    // Output from access point `i` up to the next one
+    fn decode_span(&self, path: &str, i: usize) -> Result<Vec<u8>, String> {
+        let point = &self.points[i];
+        let end_output = self.points.get(i + 1).map_or(self.total_output, |next| next.output);
+        let wanted = (end_output - point.output) as usize;
+        // The partial byte before the point, through the first byte of the next
+        let start = point.input - (point.bits > 0) as u64;
+        let end = self.points.get(i + 1).map_or(self.file_size, |next| (next.input + 1).min(self.file_size));
+
+        let mut file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        file.seek(SeekFrom::Start(start)).map_err(|e| format!("Failed to seek in {}: {}", path, e))?;
+        let mut compressed = Vec::with_capacity((end - start) as usize);
+        file.take(end - start).read_to_end(&mut compressed).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+        let mut inflater;
+        let mut input = &compressed[..];
+        if point.member_start {
+            inflater = Inflater::new(GZIP_WINDOW_BITS)?;
+        } else {
+            inflater = Inflater::new(RAW_WINDOW_BITS)?;
+            if point.bits > 0 {
+                inflater.prime(point.bits, input[0] >> (8 - point.bits))?;
+                input = &input[1..];
+            }
+            inflater.set_dictionary(&point.window)?;
+        }
+
+        let mut out = vec![0u8; wanted];
+        let mut produced_total = 0;
+        while produced_total < wanted {
+            let (ret, consumed, produced) = inflater.inflate(input, &mut out[produced_total..], Z_NO_FLUSH);
+            input = &input[consumed..];
+            produced_total += produced;
+            match ret {
+                Z_OK => {},
+                Z_STREAM_END => break,
+                Z_BUF_ERROR if consumed == 0 && produced == 0 => break,
+                Z_BUF_ERROR => {},
+                ret => return Err(format!("{}: span at byte {} doesn't decode (zlib error {}); rebuild the index with `index gzip {}`", path, point.input, ret, path)),
+            }
+        }
+        if produced_total != wanted {
+            return Err(format!("{}: span at byte {} decoded to {} bytes, the index expects {}; rebuild it with `index gzip {}`", path, point.input, produced_total, wanted, path));
+        }
+        Ok(out)
+    }
+}
+
+// Little-endian fields of a saved index, None once they run out
+struct IndexFields<'a>(&'a [u8]);
+
+impl<'a> IndexFields<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|bytes| u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]))
+    }
+}
+
+// Reads a local gzip file through its index, a batch of spans at a time
+pub struct IndexedGzipReader {
+    path: String,
+    index: GzipIndex,
+    next_span: usize,
+    // Decoded spans waiting to be read, in file order; the front one is being read
+    ready: VecDeque<Vec<u8>>,
+    pos: usize,
+}
+
+impl IndexedGzipReader {
+    pub fn new(path: &str, index: GzipIndex) -> Self {
+        Self { path: path.to_string(), index, next_span: 0, ready: VecDeque::new(), pos: 0 }
+    }
+
+    fn fill(&mut self) -> std::io::Result<()> {
+        let first = self.next_span;
+        let last = (first + threads()).min(self.index.points.len());
+        let (index, path) = (&self.index, self.path.as_str());
+        let spans: Vec<Vec<u8>> = pool().install(|| (first..last).into_par_iter().map(|i| index.decode_span(path, i)).collect::<Result<_, String>>())
+            .map_err(invalid)?;
+        self.next_span = last;
+        self.ready.extend(spans);
+        Ok(())
+    }
+}
+
+impl Read for IndexedGzipReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.ready.front().is_none_or(|span| self.pos == span.len()) {
+            if self.ready.pop_front().is_some() {
+                self.pos = 0;
+                continue;
+            }
+            if self.next_span == self.index.points.len() {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let span = &self.ready[0];
+        let n = buf.len().min(span.len() - self.pos);
+        buf[..n].copy_from_slice(&span[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,135 @@
+// `parity`: one small dataset through both alignment paths of the crate, with
+// scores and timings side by side, to check the older path can go
+// Before the Aligner (engine.rs), the pair mode called free functions in
+// aligner.rs: gpu_align_pair / cpu_align_pair (chunk size from the environment,
+// the shared OpenCL context) and gpu_align_bytes for two sequences. The CLI and
+// library callers now run on the Aligner, which owns its device and chunking,
+// but the free functions are still public and still built: this tree builds
+// one binary, and these are the two implementations in it. Until the old ones
+// are deleted, each case here runs through both, `runs` times each:
+//   pair          the two files, chunk by chunk (per contig for a FASTA file2)
+//   first chunks  the first chunk of each file against each other
+//   self          the first chunk of file1 against itself
+// Scores (and per-contig scores) must match exactly, and each path must score
+// the same on every run. Timings are the best run of each, for a regression
+// either way; the GPU paths share one device, so they aren't run concurrently
+
+use crate::aligner;
+use crate::contig_chunks::ContigScore;
+use crate::engine::Aligner;
+use crate::tools::ceilings::first_chunk;
+use std::time::Instant;
+
+pub const DEFAULT_RUNS: usize = 3;
+
+// One path's result for a case
+#[derive(Debug, Clone)]
+pub struct PathRun {
+    pub score: i32,
+    pub contigs: Vec<ContigScore>,
+    // Best of the runs
+    pub best_ms: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParityCase {
+    pub case: String,
+    pub legacy: Result<PathRun, String>,
+    pub engine: Result<PathRun, String>,
+}
+
+impl ParityCase {
+    pub fn matches(&self) -> bool {
+        match (&self.legacy, &self.engine) {
+            (Ok(legacy), Ok(engine)) => legacy.score == engine.score && legacy.contigs == engine.contigs,
+            _ => false,
+        }
+    }
+
+    // Engine time over legacy time
+    pub fn time_ratio(&self) -> Option<f64> {
+        match (&self.legacy, &self.engine) {
+            (Ok(legacy), Ok(engine)) if legacy.best_ms > 0.0 => Some(engine.best_ms / legacy.best_ms),
+            _ => None,
+        }
+    }
+}
+
+// `align` `runs` times; every run must score the same
+fn measure(runs: usize, mut align: impl FnMut() -> Result<(i32, Vec<ContigScore>), String>) -> Result<PathRun, String> {
+    let mut best: Option<PathRun> = None;
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let (score, contigs) = align()?;
+        let ms = start.elapsed().as_secs_f64() * 1000.0;
+        match &mut best {
+            Some(best) if best.score != score || best.contigs != contigs => {
+                return Err(format!("scored {} and then {} on the same input", best.score, score));
+            },
+            Some(best) => best.best_ms = best.best_ms.min(ms),
+            None => best = Some(PathRun { score, contigs, best_ms: ms }),
+        }
+    }
+    best.ok_or_else(|| "no runs".to_string())
+}
+
+// Every case through the free functions and through `engine`, which decides
+// the backend (GPU or CPU) and chunk size for both
+pub fn run(file1: &str, file2: &str, engine: &Aligner, runs: usize) -> Result<Vec<ParityCase>, String> {
+    let device = engine.device().cloned();
+    let legacy_bytes = |bytes1: &[u8], bytes2: &[u8]| match &device {
+        Some(device) => aligner::gpu_align_bytes(bytes1, bytes2, device),
+        None => Ok(crate::cpu_align::cpu_align_bytes(bytes1, bytes2)),
+    };
+    let mut cases = vec![ParityCase {
+        case: "pair".to_string(),
+        legacy: measure(runs, || {
+            let result = match &device {
+                Some(device) => aligner::gpu_align_pair(file1, file2, device)?,
+                None => aligner::cpu_align_pair(file1, file2)?,
+            };
+            Ok((result.score, result.contigs))
+        }),
+        engine: measure(runs, || engine.align_files(file1, file2).map(|result| (result.score, result.contigs))),
+    }];
+
+    let chunk1 = first_chunk(file1, engine.chunk_size())?;
+    let chunk2 = first_chunk(file2, engine.chunk_size())?;
+    for (case, bytes1, bytes2) in [("first chunks", chunk1.bases(), chunk2.bases()), ("self", chunk1.bases(), chunk1.bases())] {
+        cases.push(ParityCase {
+            case: case.to_string(),
+            legacy: measure(runs, || legacy_bytes(bytes1, bytes2).map(|score| (score, Vec::new()))),
+            engine: measure(runs, || engine.align(bytes1, bytes2).map(|score| (score, Vec::new()))),
+        });
+    }
+    Ok(cases)
+}
+
+// A line per case; returns how many don't match
+pub fn print(cases: &[ParityCase]) -> usize {
+    let cell = |run: &Result<PathRun, String>| match run {
+        Ok(run) => format!("{:>12} {:>10.1}", run.score, run.best_ms),
+        Err(_) => format!("{:>12} {:>10}", "error", "-"),
+    };
+    println!("{:<13} {:>12} {:>10} {:>12} {:>10} {:>7}  parity", "case", "legacy", "ms", "engine", "ms", "time");
+    let mut mismatches = 0;
+    for case in cases {
+        let ratio = case.time_ratio().map_or("-".to_string(), |ratio| format!("{:.2}x", ratio));
+        let verdict = if case.matches() { "ok" } else { "MISMATCH" };
+        println!("{:<13} {} {} {:>7}  {}", case.case, cell(&case.legacy), cell(&case.engine), ratio, verdict);
+        if !case.matches() {
+            mismatches += 1;
+            for (path, run) in [("legacy", &case.legacy), ("engine", &case.engine)] {
+                if let Err(e) = run {
+                    println!("    {}: {}", path, e);
+                }
+            }
+            if let (Ok(legacy), Ok(engine)) = (&case.legacy, &case.engine) {
+                if legacy.score == engine.score {
+                    println!("    per-contig scores differ ({} contigs against {})", legacy.contigs.len(), engine.contigs.len());
+                }
+            }
+        }
+    }
+    mismatches
+}
@@ -0,0 +1,535 @@
+// Parquet output for per-read results (--output-format parquet)
+// A small writer for the subset of Parquet the records need: flat required
+// columns, PLAIN encoding, no compression, one data page per column per row
+// group. Names, CIGARs and read group fields are UTF8 byte arrays, the rest
+// INT32/INT64. Rows are buffered column by column and written as a row group
+// every ROW_GROUP_ROWS rows; the footer (FileMetaData, Thrift compact
+// protocol) goes at the end, with each file's summary as key-value metadata
+// (file_summary.N, the FileReport as JSON), so readers see the file only once
+// the run has finished
+
+use crate::output_sink::{Columns, OutputSink};
+use crate::read_output::ReadResult;
+use crate::wgs_report::FileReport;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const MAGIC: &[u8; 4] = b"PAR1";
+const ROW_GROUP_ROWS: usize = 1024 * 1024;
+
+// Parquet physical types
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const BYTE_ARRAY: i32 = 6;
+// Repetition, converted type, encodings, codec and page type values used here
+const REQUIRED: i32 = 0;
+const UTF8: i32 = 0;
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+const UNCOMPRESSED: i32 = 0;
+const DATA_PAGE: i32 = 0;
+
+// Thrift compact protocol, writing only
+struct Thrift {
+    buf: Vec<u8>,
+    // Last field id of each open struct
+    last: Vec<i16>,
+}
+
+// Compact protocol type ids
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+impl Thrift {
+    fn new() -> Self {
+        Self { buf: Vec::new(), last: vec![0] }
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("thrift struct");
+        let delta = id - *last;
+        *last = id;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.varint(((id << 1) ^ (id >> 15)) as u16 as u64);
+        }
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, T_I32);
+        self.raw_i32(value);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, T_I64);
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, T_BINARY);
+        self.raw_binary(value);
+    }
+
+    fn raw_i32(&mut self, value: i32) {
+        self.varint(((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    fn raw_binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn list(&mut self, id: i16, element: u8, len: usize) {
+        self.field(id, T_LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | element);
+        } else {
+            self.buf.push(0xF0 | element);
+            self.varint(len as u64);
+        }
+    }
+
+    // A struct field; end it with end()
+    fn begin(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.last.push(0);
+    }
+
+    // A struct element of a list; end it with end()
+    fn begin_element(&mut self) {
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.last.pop();
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0);
+        self.buf
+    }
+}
+
+struct Column {
+    name: &'static str,
+    physical: i32,
+    // PLAIN-encoded values of the row group being buffered
+    data: Vec<u8>,
+}
+
+impl Column {
+    fn new(name: &'static str) -> Self {
+        let physical = match name {
+            "read_name" | "cigar" | "sample" | "library" | "platform_unit" => BYTE_ARRAY,
+            "chunk" => INT64,
+            _ => INT32,
+        };
+        Self { name, physical, data: Vec::new() }
+    }
+
+    fn push_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_i64(&mut self, value: i64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(value);
+    }
+}
+
+// Where a column chunk went, for the footer
+struct ChunkMeta {
+    offset: u64,
+    size: u64,
+}
+
+struct RowGroupMeta {
+    rows: usize,
+    chunks: Vec<ChunkMeta>,
+}
+
+pub struct ParquetSink {
+    path: String,
+    out: BufWriter<File>,
+    offset: u64,
+    columns: Columns,
+    buffered: Vec<Column>,
+    rows: usize,
+    total_rows: usize,
+    row_groups: Vec<RowGroupMeta>,
+    summaries: Vec<(String, String)>,
+}
+
+impl ParquetSink {
+    pub fn create(path: &str, columns: Columns) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create read output {}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        out.write_all(MAGIC).map_err(|e| write_error(path, e))?;
+        let buffered = columns.names().into_iter().map(Column::new).collect();
+        Ok(Self {
+            path: path.to_string(), out, offset: MAGIC.len() as u64, columns, buffered,
+            rows: 0, total_rows: 0, row_groups: Vec::new(), summaries: Vec::new(),
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).map_err(|e| write_error(&self.path, e))?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn flush_row_group(&mut self) -> Result<(), String> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut chunks = Vec::new();
+        for i in 0..self.buffered.len() {
+            let data = std::mem::take(&mut self.buffered[i].data);
+            let mut header = Thrift::new();
+            header.i32(1, DATA_PAGE);
+            header.i32(2, data.len() as i32);
+            header.i32(3, data.len() as i32);
+            header.begin(5);
+            header.i32(1, self.rows as i32);
+            header.i32(2, PLAIN);
+            header.i32(3, RLE);
+            header.i32(4, RLE);
+            header.end();
+            let header = header.finish();
+            let offset = self.offset;
+            self.write(&header)?;
+            self.write(&data)?;
+            chunks.push(ChunkMeta { offset, size: (header.len() + data.len()) as u64 });
+            // Keep the allocation for the next row group
+            self.buffered[i].data = data;
+            self.buffered[i].data.clear();
+        }
+        self.row_groups.push(RowGroupMeta { rows: self.rows, chunks });
+        self.total_rows += self.rows;
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn footer(&self) -> Vec<u8> {
+        let mut meta = Thrift::new();
+        meta.i32(1, 1);
+        // Schema: a root with the columns as its children
+        meta.list(2, T_STRUCT, self.buffered.len() + 1);
+        meta.begin_element();
+        meta.binary(4, b"schema");
+        meta.i32(5, self.buffered.len() as i32);
+        meta.end();
+        for column in &self.buffered {
+            meta.begin_element();
+            meta.i32(1, column.physical);
+            meta.i32(3, REQUIRED);
+            meta.binary(4, column.name.as_bytes());
+            if column.physical == BYTE_ARRAY {
+                meta.i32(6, UTF8);
+            }
+            meta.end();
+        }
+        meta.i64(3, self.total_rows as i64);
+        meta.list(4, T_STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            meta.begin_element();
+            meta.list(1, T_STRUCT, group.chunks.len());
+            for (column, chunk) in self.buffered.iter().zip(&group.chunks) {
+                meta.begin_element();
+                meta.i64(2, chunk.offset as i64);
+                meta.begin(3);
+                meta.i32(1, column.physical);
+                meta.list(2, T_I32, 2);
+                meta.raw_i32(PLAIN);
+                meta.raw_i32(RLE);
+                meta.list(3, T_BINARY, 1);
+                meta.raw_binary(column.name.as_bytes());
+                meta.i32(4, UNCOMPRESSED);
+                meta.i64(5, group.rows as i64);
+                meta.i64(6, chunk.size as i64);
+                meta.i64(7, chunk.size as i64);
+                meta.i64(9, chunk.offset as i64);
+                meta.end();
+                meta.end();
+            }
+            meta.i64(2, group.chunks.iter().map(|chunk| chunk.size).sum::<u64>() as i64);
+            meta.i64(3, group.rows as i64);
+            meta.end();
+        }
+        if !self.summaries.is_empty() {
+            meta.list(5, T_STRUCT, self.summaries.len());
+            for (key, value) in &self.summaries {
+                meta.begin_element();
+                meta.binary(1, key.as_bytes());
+                meta.binary(2, value.as_bytes());
+                meta.end();
+            }
+        }
+        meta.binary(6, format!("rustseq_mini version {}", env!("CARGO_PKG_VERSION")).as_bytes());
+        meta.finish()
+    }
+}
+
+fn write_error(path: &str, e: std::io::Error) -> String {
+    format!("Failed to write read output {}: {}", path, e)
+}
+
+impl OutputSink for ParquetSink {
+    fn write_read_result(&mut self, result: &ReadResult<'_>) -> Result<(), String> {
+        let name = result.name_string();
+        let (tiles, cigar) = self.columns.tiles_cigar(result);
+        let read_group = self.columns.read_group.as_ref();
+        for column in &mut self.buffered {
+            match column.name {
+                "read_name" => column.push_bytes(name.as_bytes()),
+                "file" => column.push_i32(result.file_index as i32 + 1),
+                "chunk" => column.push_i64(result.chunk_index as i64),
+                "length" => column.push_i32(result.seq.len() as i32),
+                "chunk_score" => column.push_i32(result.chunk_score),
+                "tiles" => column.push_i32(tiles as i32),
+                "cigar" => column.push_bytes(cigar.as_bytes()),
+                "sample" => column.push_bytes(read_group.map_or("", |g| g.sample.as_str()).as_bytes()),
+                "library" => column.push_bytes(read_group.and_then(|g| g.library.as_deref()).unwrap_or("").as_bytes()),
+                "platform_unit" => column.push_bytes(read_group.and_then(|g| g.platform_unit.as_deref()).unwrap_or("").as_bytes()),
+                _ => {},
+            }
+        }
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_ROWS {
+            self.flush_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.buffered.iter().map(|column| column.data.capacity()).sum()
+    }
+
+    fn write_file_summary(&mut self, file: &FileReport) -> Result<(), String> {
+        let json = serde_json::to_string(file)
+            .map_err(|e| format!("Failed to serialize file summary: {}", e))?;
+        self.summaries.push((format!("file_summary.{}", file.index + 1), json));
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.flush_row_group()?;
+        let footer = self.footer();
+        self.write(&footer)?;
+        self.write(&(footer.len() as u32).to_le_bytes())?;
+        self.write(MAGIC)?;
+        self.out.flush().map_err(|e| write_error(&self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::long_reads::ReadScore;
+    use crate::read_chunk::ReadName;
+    use std::collections::BTreeMap;
+
+    // Thrift compact values, as far as the footer and page headers use them
+    #[derive(Debug, PartialEq)]
+    enum Value {
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Value>),
+        Struct(BTreeMap<i16, Value>),
+    }
+
+    impl Value {
+        fn get(&self, id: i16) -> &Value {
+            match self {
+                Value::Struct(fields) => fields.get(&id).unwrap_or_else(|| panic!("no field {}", id)),
+                _ => panic!("not a struct: {:?}", self),
+            }
+        }
+
+        fn int(&self) -> i64 {
+            match self {
+                Value::Int(value) => *value,
+                _ => panic!("not an integer: {:?}", self),
+            }
+        }
+
+        fn text(&self) -> &str {
+            match self {
+                Value::Binary(bytes) => std::str::from_utf8(bytes).unwrap(),
+                _ => panic!("not binary: {:?}", self),
+            }
+        }
+
+        fn list(&self) -> &[Value] {
+            match self {
+                Value::List(values) => values,
+                _ => panic!("not a list: {:?}", self),
+            }
+        }
+    }
+
+    struct Reader<'a> {
+        buf: &'a [u8],
+        pos: usize,
+    }
+
+    impl Reader<'_> {
+        fn byte(&mut self) -> u8 {
+            self.pos += 1;
+            self.buf[self.pos - 1]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let (mut value, mut shift) = (0, 0);
+            loop {
+                let byte = self.byte();
+                value |= ((byte & 0x7F) as u64) << shift;
+                if byte < 0x80 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+
+        fn zigzag(&mut self) -> i64 {
+            let value = self.varint();
+            (value >> 1) as i64 ^ -((value & 1) as i64)
+        }
+
+        fn value(&mut self, kind: u8) -> Value {
+            match kind {
+                T_I32 | T_I64 => Value::Int(self.zigzag()),
+                T_BINARY => {
+                    let len = self.varint() as usize;
+                    self.pos += len;
+                    Value::Binary(self.buf[self.pos - len..self.pos].to_vec())
+                },
+                T_LIST => {
+                    let header = self.byte();
+                    let len = if header >> 4 == 15 { self.varint() as usize } else { (header >> 4) as usize };
+                    Value::List((0..len).map(|_| self.value(header & 0x0F)).collect())
+                },
+                T_STRUCT => self.structure(),
+                _ => panic!("unexpected thrift type {}", kind),
+            }
+        }
+
+        fn structure(&mut self) -> Value {
+            let (mut fields, mut last) = (BTreeMap::new(), 0i16);
+            loop {
+                let header = self.byte();
+                if header == 0 {
+                    return Value::Struct(fields);
+                }
+                let id = match header >> 4 {
+                    0 => self.zigzag() as i16,
+                    delta => last + delta as i16,
+                };
+                last = id;
+                fields.insert(id, self.value(header & 0x0F));
+            }
+        }
+    }
+
+    // PLAIN values of one page as text, for comparing across types
+    fn decode(physical: i64, data: &[u8], rows: usize) -> Vec<String> {
+        let mut pos = 0;
+        (0..rows).map(|_| {
+            let (len, value) = match physical as i32 {
+                INT32 => (4, i32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()).to_string()),
+                INT64 => (8, i64::from_le_bytes(data[pos..pos + 8].try_into().unwrap()).to_string()),
+                BYTE_ARRAY => {
+                    let len = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+                    (4 + len, String::from_utf8(data[pos + 4..pos + 4 + len].to_vec()).unwrap())
+                },
+                other => panic!("unexpected physical type {}", other),
+            };
+            pos += len;
+            value
+        }).collect()
+    }
+
+    #[test]
+    fn rows_read_back_from_the_footer() {
+        let path = std::env::temp_dir().join(format!("rustseq_mini_parquet_{}.parquet", std::process::id()));
+        let columns = Columns { long_reads: true, read_group: None };
+        let names = columns.names();
+        let mut sink = Box::new(ParquetSink::create(&path.to_string_lossy(), columns).unwrap());
+        let read = ReadScore { score: 40, tiles: 3, cigar: "5M1I4M".to_string() };
+        let rows = [(&b"r1"[..], &b"ACG"[..], 0, 1, 10, None), ("read é 2".as_bytes(), &b"ACGTA"[..], 0, 2, -1, Some(&read)),
+                    (&b"r3"[..], &b""[..], 1, 3_000_000_000, 7, None)];
+        for (i, &(name, seq, file_index, chunk_index, chunk_score, read_score)) in rows.iter().enumerate() {
+            sink.write_read_result(&ReadResult { name: Some(ReadName::Name(name)), seq, file_index, chunk_index, chunk_score, read_score }).unwrap();
+            // Two row groups: the first two rows, then the last
+            if i == 1 {
+                sink.flush_row_group().unwrap();
+            }
+        }
+        sink.summaries.push(("file_summary.1".to_string(), "{}".to_string()));
+        sink.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(&bytes[bytes.len() - 4..], MAGIC);
+        let footer_len = u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap()) as usize;
+        let footer_start = bytes.len() - 8 - footer_len;
+        let mut reader = Reader { buf: &bytes[..bytes.len() - 8], pos: footer_start };
+        let meta = reader.structure();
+        assert_eq!(reader.pos, bytes.len() - 8);
+
+        assert_eq!(meta.get(3).int(), 3);
+        let schema = meta.get(2).list();
+        assert_eq!(schema[0].get(5).int(), names.len() as i64);
+        assert_eq!(schema[1..].iter().map(|element| element.get(4).text()).collect::<Vec<_>>(), names);
+        let physical: Vec<i64> = schema[1..].iter().map(|element| element.get(1).int()).collect();
+        let key_values = meta.get(5).list();
+        assert_eq!((key_values[0].get(1).text(), key_values[0].get(2).text()), ("file_summary.1", "{}"));
+
+        let mut values = vec![Vec::new(); names.len()];
+        let groups = meta.get(4).list();
+        assert_eq!(groups.iter().map(|group| group.get(3).int()).collect::<Vec<_>>(), [2, 1]);
+        for group in groups {
+            let group_rows = group.get(3).int() as usize;
+            for (c, chunk) in group.get(1).list().iter().enumerate() {
+                let chunk_meta = chunk.get(3);
+                assert_eq!(chunk_meta.get(3).list()[0].text(), names[c]);
+                assert_eq!(chunk_meta.get(5).int(), group_rows as i64);
+                let mut page = Reader { buf: &bytes, pos: chunk_meta.get(9).int() as usize };
+                let header = page.structure();
+                assert_eq!(header.get(1).int(), DATA_PAGE as i64);
+                assert_eq!(header.get(5).get(1).int(), group_rows as i64);
+                let size = header.get(3).int() as usize;
+                assert_eq!((page.pos + size) as i64, chunk_meta.get(9).int() + chunk_meta.get(7).int());
+                values[c].extend(decode(physical[c], &bytes[page.pos..page.pos + size], group_rows));
+            }
+        }
+        let column = |name: &str| values[names.iter().position(|n| *n == name).unwrap()].join(",");
+        assert_eq!(column("read_name"), "r1,read é 2,r3");
+        assert_eq!(column("file"), "1,1,2");
+        assert_eq!(column("chunk"), "1,2,3000000000");
+        assert_eq!(column("length"), "3,5,0");
+        assert_eq!(column("chunk_score"), "10,-1,7");
+        assert_eq!(column("tiles"), "0,3,0");
+        assert_eq!(column("cigar"), "*,5M1I4M,*");
+    }
+}
@@ -0,0 +1,75 @@
+// Path helpers for strings that name files: input lists, --output-dir, panel
+// entries. Inputs stay strings because they can also be URLs (s3://, gs://,
+// https://), but joining and splitting goes through std::path, so Windows
+// separators, UNC prefixes (\\server\share\...), spaces and non-ASCII names
+// come out whole. Everything the run writes (checkpoints, results, logs) is
+// a PathBuf from output_layout end to end
+
+use std::path::Path;
+
+// Last component of a path or URL, e.g. for printing which file is running
+pub fn file_name(path: &str) -> &str {
+    if path.contains("://") {
+        return path.trim_end_matches('/').rsplit('/').next().unwrap_or(path);
+    }
+    Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path)
+}
+
+// True for a bare file name, with no directory or URL in front of it
+pub fn is_bare_name(path: &str) -> bool {
+    !path.contains("://") && Path::new(path).components().count() == 1 && !Path::new(path).has_root()
+}
+
+// `name` in directory `dir`, with the platform's separator
+pub fn join(dir: &str, name: &str) -> String {
+    Path::new(dir).join(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_keeps_spaces_and_non_ascii() {
+        assert_eq!(file_name("reads é 1.fastq.gz"), "reads é 1.fastq.gz");
+        assert_eq!(file_name("runs/NA12878 été/reads é 1.fastq.gz"), "reads é 1.fastq.gz");
+        assert_eq!(file_name("/data/サンプル/reads_1.fq"), "reads_1.fq");
+    }
+
+    #[test]
+    fn file_name_of_urls() {
+        assert_eq!(file_name("s3://bucket/dir/x.fq"), "x.fq");
+        assert_eq!(file_name("s3://bucket/dir/"), "dir");
+        assert_eq!(file_name("https://host/reads%201.fq.gz"), "reads%201.fq.gz");
+        assert_eq!(file_name("gs://bucket/reads é.fq//"), "reads é.fq");
+    }
+
+    #[test]
+    fn bare_names() {
+        assert!(is_bare_name("reads é 1.fastq.gz"));
+        assert!(is_bare_name("x.fq"));
+        assert!(!is_bare_name("dir/x.fq"));
+        assert!(!is_bare_name("/x.fq"));
+        assert!(!is_bare_name("/data/x.fq"));
+        assert!(!is_bare_name("s3://bucket/x.fq"));
+    }
+
+    #[test]
+    fn join_round_trips_through_file_name() {
+        for (dir, name) in [("data", "x.fq"), ("data/", "reads é 1.fastq.gz"), ("/runs/NA12878 été", "reads é 1.fastq.gz"), (".", "サンプル.fa")] {
+            let joined = join(dir, name);
+            assert_eq!(file_name(&joined), name, "{}", joined);
+            assert!(!is_bare_name(&joined), "{}", joined);
+        }
+        assert_eq!(join("data", "x.fq"), Path::new("data").join("x.fq").to_string_lossy());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_separators_and_unc() {
+        assert_eq!(file_name(r"C:\data\reads é 1.fastq.gz"), "reads é 1.fastq.gz");
+        assert_eq!(file_name(r"\\server\share\reads.fq"), "reads.fq");
+        assert!(!is_bare_name(r"data\x.fq"));
+        assert_eq!(join(r"C:\data", "x.fq"), r"C:\data\x.fq");
+    }
+}
@@ -1,11 +1,20 @@
 use std::process::{Child, Command, Stdio};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use crate::metrics_sampler::MetricsSampler;
+use crate::profiler::InProcessProfiler;
+use crate::run_registry::RunRecord;
+use crate::warnings::{self, Category};
 
 static MONITORS_RUNNING: AtomicBool = AtomicBool::new(false);
-static RUN_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+static PROFILE_MODE: AtomicBool = AtomicBool::new(false);
+
+// --profile: record a flamegraph of the run (perf, or the in-process sampler)
+pub fn set_profile_mode(enabled: bool) {
+    PROFILE_MODE.store(enabled, Ordering::SeqCst);
+}
 
 pub struct SystemMonitors {
     gpu_monitor: Option<Child>,
@@ -13,20 +22,19 @@ pub struct SystemMonitors {
     mem_cpu_monitor: Option<Child>,
     context_switch_monitor: Option<Child>,
     perf_record: Option<Child>,
+    in_process_profiler: Option<InProcessProfiler>,
+    metrics_sampler: Option<MetricsSampler>,
     run_number: u64,
     run_id: String,
-    logs_dir: String,
+    logs_dir: PathBuf,
 }
 
 impl SystemMonitors {
-    pub fn new() -> Self {
-        let run_number = RUN_COUNTER.fetch_add(1, Ordering::SeqCst) + 1;
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let run_id = format!("run_{}", timestamp);
-        let logs_dir = format!("logs/run_{}", run_number);
+    // Monitors logging to `run`'s logs directory
+    pub fn for_run(run: &RunRecord) -> Self {
+        let run_number = run.number;
+        let run_id = run.run_id.clone();
+        let logs_dir = run.logs_dir.clone();
         
         Self {
             gpu_monitor: None,
@@ -34,6 +42,8 @@ impl SystemMonitors {
             mem_cpu_monitor: None,
             context_switch_monitor: None,
             perf_record: None,
+            in_process_profiler: None,
+            metrics_sampler: None,
             run_number,
             run_id,
             logs_dir,
@@ -50,44 +60,64 @@ impl SystemMonitors {
             .map_err(|e| format!("Failed to create logs directory: {}", e))?;
 
         println!("Starting system monitors for run #{}: {}", self.run_number, self.run_id);
-        println!("Logs directory: {}", self.logs_dir);
+        println!("Logs directory: {}", self.logs_dir.display());
+
+        // Structured samples for the benchmark summary (the tool logs below are kept as raw artifacts)
+        self.metrics_sampler = Some(MetricsSampler::start(&self.logs_dir)?);
 
         // Start GPU utilization monitor
         self.start_gpu_monitor()?;
 
-        // Start disk I/O monitor
-        self.start_disk_monitor()?;
+        // iostat/vmstat/pidstat logs are Linux-only; elsewhere metrics.jsonl
+        // (sysinfo) is the record of CPU, memory and disk
+        if cfg!(target_os = "linux") {
+            // Start disk I/O monitor
+            self.start_disk_monitor()?;
 
-        // Start memory/CPU monitor
-        self.start_mem_cpu_monitor()?;
+            // Start memory/CPU monitor
+            self.start_mem_cpu_monitor()?;
 
-        // Start context switch monitor (optional)
-        self.start_context_switch_monitor()?;
+            // Start context switch monitor (optional)
+            self.start_context_switch_monitor()?;
+        } else {
+            println!("  iostat/vmstat/pidstat logs are Linux-only; CPU, memory and disk are in the metrics samples");
+        }
 
-        // Start perf record (optional)
-        self.start_perf_record()?;
+        // Profiling (only with --profile)
+        if PROFILE_MODE.load(Ordering::SeqCst) {
+            self.start_profiler()?;
+        }
 
         MONITORS_RUNNING.store(true, Ordering::SeqCst);
         Ok(())
     }
 
     fn start_gpu_monitor(&mut self) -> Result<(), String> {
-        let log_file = format!("{}/gpu_util.log", self.logs_dir);
+        let log_file = self.logs_dir.join("gpu_util.log");
         
         let child = Command::new("nvidia-smi")
-            .args(&["dmon", "-s", "u", "-o", "DT", "-f", &log_file])
+            .args(&["dmon", "-s", "u", "-o", "DT", "-f"])
+            .arg(&log_file)
             .stdout(Stdio::null())
             .stderr(Stdio::null())
-            .spawn()
-            .map_err(|e| format!("Failed to start GPU monitor: {}", e))?;
+            .spawn();
 
-        self.gpu_monitor = Some(child);
-        println!("  GPU monitor started: {}", log_file);
+        // Optional like pidstat: non-NVIDIA GPUs have no nvidia-smi, and the
+        // metrics sampler records GPU use when it can
+        match child {
+            Ok(child) => {
+                self.gpu_monitor = Some(child);
+                println!("  GPU monitor started: {}", log_file.display());
+            }
+            Err(_) => {
+                println!("  GPU monitor not available (nvidia-smi not found)");
+            }
+        }
         Ok(())
     }
 
     fn start_disk_monitor(&mut self) -> Result<(), String> {
-        let log_file = format!("{}/disk_io.log", self.logs_dir);
+        let log_file = self.logs_dir.join("disk_io.log");
         
         let output_file = fs::File::create(&log_file)
             .map_err(|e| format!("Failed to create disk I/O log file: {}", e))?;
@@ -100,12 +130,12 @@ impl SystemMonitors {
             .map_err(|e| format!("Failed to start disk I/O monitor: {}", e))?;
 
         self.disk_monitor = Some(child);
-        println!("  Disk I/O monitor started: {}", log_file);
+        println!("  Disk I/O monitor started: {}", log_file.display());
         Ok(())
     }
 
     fn start_mem_cpu_monitor(&mut self) -> Result<(), String> {
-        let log_file = format!("{}/mem_cpu.log", self.logs_dir);
+        let log_file = self.logs_dir.join("mem_cpu.log");
         
         let output_file = fs::File::create(&log_file)
             .map_err(|e| format!("Failed to create memory/CPU log file: {}", e))?;
@@ -118,12 +148,12 @@ impl SystemMonitors {
             .map_err(|e| format!("Failed to start memory/CPU monitor: {}", e))?;
 
         self.mem_cpu_monitor = Some(child);
-        println!("  Memory/CPU monitor started: {}", log_file);
+        println!("  Memory/CPU monitor started: {}", log_file.display());
         Ok(())
     }
 
     fn start_context_switch_monitor(&mut self) -> Result<(), String> {
-        let log_file = format!("{}/context_switch.log", self.logs_dir);
+        let log_file = self.logs_dir.join("context_switch.log");
         
         let output_file = fs::File::create(&log_file)
             .map_err(|e| format!("Failed to create context switch log file: {}", e))?;
@@ -137,7 +167,7 @@ impl SystemMonitors {
         match child {
             Ok(child) => {
                 self.context_switch_monitor = Some(child);
-                println!("  Context switch monitor started: {}", log_file);
+                println!("  Context switch monitor started: {}", log_file.display());
             }
             Err(_) => {
                 println!("  Context switch monitor not available (pidstat not found)");
@@ -146,25 +176,62 @@ impl SystemMonitors {
         Ok(())
     }
 
-    fn start_perf_record(&mut self) -> Result<(), String> {
-        let perf_data_path = format!("{}/perf.data", self.logs_dir);
+    fn start_profiler(&mut self) -> Result<(), String> {
+        if cfg!(target_os = "linux") && self.start_perf_record() {
+            return Ok(());
+        }
+        self.in_process_profiler = Some(InProcessProfiler::start()?);
+        println!("  In-process sampling profiler started");
+        Ok(())
+    }
+
+    // True if perf is recording; perf exits right away when it lacks permission
+    fn start_perf_record(&mut self) -> bool {
+        let perf_data_path = self.logs_dir.join("perf.data");
         
         let child = Command::new("perf")
-            .args(&["record", "-g", "-o", &perf_data_path, "-p", &std::process::id().to_string()])
+            .args(["record", "-F", "99", "-g", "-o"])
+            .arg(&perf_data_path)
+            .args(["-p", &std::process::id().to_string()])
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .spawn();
 
         match child {
-            Ok(child) => {
+            Ok(mut child) => {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                if let Ok(Some(_)) = child.try_wait() {
+                    println!("  Perf record exited early (check kernel.perf_event_paranoid), using the in-process profiler");
+                    return false;
+                }
                 self.perf_record = Some(child);
-                println!("  Perf record started: {}", perf_data_path);
+                println!("  Perf record started: {}", perf_data_path.display());
+                true
             }
             Err(_) => {
-                println!("  Perf record not available (perf not found or insufficient permissions)");
+                println!("  Perf record not available (perf not found), using the in-process profiler");
+                false
             }
         }
-        Ok(())
+    }
+
+    // perf must be interrupted (not killed) to write a complete perf.data
+    fn stop_perf_record(&mut self) {
+        let Some(mut child) = self.perf_record.take() else { return };
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = child.kill();
+        match child.wait() {
+            Ok(_) => println!("  Perf record stopped"),
+            Err(e) => warnings::warn(Category::Monitoring, format!("Failed to stop perf record: {}", e)),
+        }
+        match crate::profiler::perf_flamegraph(&self.logs_dir) {
+            Ok(()) => println!("  Flamegraph: {}", self.logs_dir.join(crate::profiler::FLAMEGRAPH_FILE).display()),
+            Err(e) => warnings::warn(Category::Monitoring, format!("Failed to build flamegraph: {}", e)),
+        }
     }
 
     pub fn stop(&mut self) -> Result<(), String> {
@@ -175,6 +242,10 @@ impl SystemMonitors {
         println!("Stopping system monitors...");
 
         // Stop all monitors
+        if let Some(mut sampler) = self.metrics_sampler.take() {
+            sampler.stop();
+            println!("  Metrics sampler stopped");
+        }
         if let Some(child) = self.gpu_monitor.take() {
             self.stop_monitor(&mut Some(child), "GPU");
         }
@@ -187,12 +258,17 @@ impl SystemMonitors {
         if let Some(child) = self.context_switch_monitor.take() {
             self.stop_monitor(&mut Some(child), "Context Switch");
         }
-        if let Some(child) = self.perf_record.take() {
-            self.stop_monitor(&mut Some(child), "Perf Record");
+        self.stop_perf_record();
+        if let Some(profiler) = self.in_process_profiler.take() {
+            match profiler.finish(&self.logs_dir) {
+                Ok(0) => println!("  In-process profiler recorded no samples"),
+                Ok(samples) => println!("  Flamegraph: {} ({} samples)", self.logs_dir.join(crate::profiler::FLAMEGRAPH_FILE).display(), samples),
+                Err(e) => warnings::warn(Category::Monitoring, format!("Failed to build flamegraph: {}", e)),
+            }
         }
 
         MONITORS_RUNNING.store(false, Ordering::SeqCst);
-        println!("System monitors stopped. Logs saved to: {}", self.logs_dir);
+        println!("System monitors stopped. Logs saved to: {}", self.logs_dir.display());
         Ok(())
     }
 
@@ -200,7 +276,7 @@ impl SystemMonitors {
         if let Some(mut child) = monitor.take() {
             match child.kill() {
                 Ok(_) => println!("  {} monitor stopped", name),
-                Err(e) => println!("  Warning: Failed to stop {} monitor: {}", name, e),
+                Err(e) => warnings::warn(Category::Monitoring, format!("Failed to stop {} monitor: {}", name, e)),
             }
         }
     }
@@ -213,7 +289,7 @@ impl SystemMonitors {
         &self.run_id
     }
 
-    pub fn get_logs_dir(&self) -> &str {
+    pub fn get_logs_dir(&self) -> &Path {
         &self.logs_dir
     }
 }
@@ -224,58 +300,18 @@ impl Drop for SystemMonitors {
     }
 }
 
-// Global system monitors instance
-use std::sync::Mutex;
-use once_cell::sync::Lazy;
-
-static SYSTEM_MONITORS: Lazy<Mutex<Option<SystemMonitors>>> = Lazy::new(|| Mutex::new(None));
-
-pub fn start_system_monitors() -> Result<(), String> {
-    let mut monitors = SystemMonitors::new();
-    monitors.start()?;
-    
-    if let Ok(mut global_monitors) = SYSTEM_MONITORS.lock() {
-        *global_monitors = Some(monitors);
-    }
-    Ok(())
-}
-
-pub fn stop_system_monitors() -> Result<(), String> {
-    if let Ok(mut global_monitors) = SYSTEM_MONITORS.lock() {
-        if let Some(mut monitors) = global_monitors.take() {
-            monitors.stop()?;
-        }
-    }
-    Ok(())
-}
-
-pub fn get_current_run_number() -> Option<u64> {
-    if let Ok(global_monitors) = SYSTEM_MONITORS.lock() {
-        if let Some(monitors) = global_monitors.as_ref() {
-            return Some(monitors.get_run_number());
-        }
-    }
-    None
-}
-
-pub fn get_current_run_id() -> Option<String> {
-    if let Ok(global_monitors) = SYSTEM_MONITORS.lock() {
-        if let Some(monitors) = global_monitors.as_ref() {
-            return Some(monitors.get_run_id().to_string());
-        }
-    }
-    None
-}
-
 // Signal handler setup
 pub fn setup_signal_handlers() {
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
     
     ctrlc::set_handler(move || {
+        crate::tui::stop();
         println!("\nReceived interrupt signal, stopping monitors...");
         r.store(false, Ordering::SeqCst);
-        let _ = stop_system_monitors();
-        std::process::exit(0);
+        crate::run_context::interrupt_cli();
+        crate::remote_state::flush();
+        crate::notify::send(&crate::notify::RunNotification::new(crate::notify::RunEvent::Interrupted));
+        crate::exit_codes::ExitKind::Interrupted.exit();
     }).expect("Error setting Ctrl-C handler");
 } 
\ No newline at end of file
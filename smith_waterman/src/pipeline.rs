@@ -0,0 +1,87 @@
+// Several streaming analyses over one read of each input file
+// A ChunkProcessor sees every chunk of every file in order. The Pipeline reads
+// each file once (async_input.rs, so compressed and remote inputs work) and
+// hands each chunk to all registered processors in registration order, so a
+// 60 GB run is read once however many analyses it feeds. Processors say
+// whether they need read names or quality lines; the pipeline keeps them only
+// if one does. Built in: the QC collector (qc.rs), the contamination screen
+// (contamination.rs), the k-mer spectrum (kmer_spectrum.rs), and GPU
+// self-alignment of each chunk (aligner.rs)
+
+use crate::chunking::ChunkSize;
+use crate::read_chunk::{ReadChunk, ReadNameMode};
+
+pub trait ChunkProcessor {
+    // Short name for the per-file summary lines
+    fn name(&self) -> &str;
+
+    fn needs_names(&self) -> bool {
+        false
+    }
+
+    fn needs_qualities(&self) -> bool {
+        false
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String>;
+
+    // After the file's last chunk; returns the file's summary line
+    fn finish_file(&mut self, file: &str) -> Result<String, String>;
+
+    // After the last file: write any outputs, and return a run summary line
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+}
+
+pub struct Pipeline {
+    chunk_size: ChunkSize,
+    processors: Vec<Box<dyn ChunkProcessor>>,
+}
+
+impl Pipeline {
+    pub fn new(chunk_size: ChunkSize) -> Self {
+        Self { chunk_size, processors: Vec::new() }
+    }
+
+    pub fn register(&mut self, processor: Box<dyn ChunkProcessor>) -> &mut Self {
+        self.processors.push(processor);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    // One pass over each file, printing every processor's summary as the file
+    // finishes, then each processor's run summary
+    pub fn run(&mut self, files: &[String]) -> Result<(), String> {
+        let name_mode = if self.processors.iter().any(|p| p.needs_names()) { ReadNameMode::Keep } else { ReadNameMode::Discard };
+        crate::read_chunk::keep_qualities(self.processors.iter().any(|p| p.needs_qualities()));
+        for file in files {
+            for processor in self.processors.iter_mut() {
+                processor.start_file(file)?;
+            }
+            let mut reads = 0;
+            crate::async_input::process_input_in_chunks(file, self.chunk_size, name_mode, |chunk| {
+                reads += chunk.len();
+                self.processors.iter_mut().try_for_each(|processor| processor.process(chunk))
+            }).map_err(|e| format!("{}: {}", file, e))?;
+            println!("{}: {} reads", file, reads);
+            for processor in self.processors.iter_mut() {
+                let summary = processor.finish_file(file)?;
+                println!("  {}: {}", processor.name(), summary);
+            }
+        }
+        for processor in self.processors.iter_mut() {
+            if let Some(summary) = processor.finish()? {
+                println!("{}: {}", processor.name(), summary);
+            }
+        }
+        Ok(())
+    }
+}
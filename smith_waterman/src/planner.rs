@@ -0,0 +1,306 @@
+// Memory and launch planning, worked out before anything touches the GPU
+// Given the sequence lengths, the alignment mode, and the device limits, the
+// planner computes the device memory a launch needs, how the kernel splits and
+// tiles the work, whether the input fits in one launch, and whether a full
+// traceback matrix would fit. --dry-run prints the plan for a run and exits
+// The kernels are matrix-free: running scores live in registers and the DP
+// matrix is never stored, so score-only memory grows with the sequence length.
+// Traceback needs the matrix, so it grows with the product of the lengths
+
+use crate::aligner::{launch_geometry, max_launch_bases, memory_budget_bytes};
+use crate::chunking::ChunkSize;
+use crate::gpu::{GpuDevice, GPU_WORK_GROUP_SIZE};
+use crate::gpu_queues::{QueueCount, MAX_QUEUES};
+use crate::kernels::{KernelVariant, PACKED_LANES};
+use crate::wgs_config::WgsConfig;
+use crate::wgs_summary::{format_count, WgsExpectations};
+
+// Local memory every OpenCL device provides
+const MIN_LOCAL_MEMORY_BYTES: u64 = 32 * 1024;
+
+// Read length assumed for read-count chunks when no expected totals are set
+const ASSUMED_READ_LENGTH: usize = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentMode {
+    // Best local score only (what the kernels compute)
+    ScoreOnly,
+    // Score plus the alignment path, which needs the full DP matrix
+    Traceback,
+}
+
+impl AlignmentMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlignmentMode::ScoreOnly => "score-only",
+            AlignmentMode::Traceback => "traceback",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceLimits {
+    pub name: String,
+    // GPU memory this process may use (after the safety margin and --gpu-fraction)
+    pub budget_bytes: u64,
+    pub max_alloc_bytes: u64,
+    pub local_memory_bytes: u64,
+    pub max_work_group_size: usize,
+    pub max_launch_bases: usize,
+}
+
+impl DeviceLimits {
+    // Limits reported by the OpenCL device, or the OpenCL minimums without one
+    pub fn query(device: &GpuDevice) -> Self {
+        use ocl::enums::{DeviceInfo, DeviceInfoResult};
+        let budget_bytes = memory_budget_bytes(device) as u64;
+        let mut limits = Self {
+            name: device.name.clone(),
+            budget_bytes,
+            // OpenCL guarantees single buffers of at least a quarter of global memory
+            max_alloc_bytes: budget_bytes / 4,
+            local_memory_bytes: MIN_LOCAL_MEMORY_BYTES,
+            max_work_group_size: device.max_work_group_size,
+            max_launch_bases: max_launch_bases(device),
+        };
+        if let Ok((_, _, ocl_device)) = crate::gpu::get_opencl_context() {
+            if let Ok(DeviceInfoResult::MaxMemAllocSize(bytes)) = ocl_device.info(DeviceInfo::MaxMemAllocSize) {
+                limits.max_alloc_bytes = bytes.min(budget_bytes);
+            }
+            if let Ok(DeviceInfoResult::LocalMemSize(bytes)) = ocl_device.info(DeviceInfo::LocalMemSize) {
+                limits.local_memory_bytes = bytes;
+            }
+        }
+        limits
+    }
+}
+
+// The first GPU, or a stand-in with default limits so a plan can be made on a
+// machine without one
+pub fn planning_device() -> GpuDevice {
+    if crate::gpu::is_gpu_available() {
+        if let Some(device) = crate::gpu::get_gpu_devices().into_iter().next() {
+            return device;
+        }
+    }
+    println!("No GPU found; planning with default device limits");
+    let memory_gb = crate::system_info::get_system_info().map(|info| info.gpu_memory_gb as f32).unwrap_or(8.0);
+    GpuDevice {
+        name: "default (no GPU found)".to_string(),
+        memory_gb,
+        max_work_group_size: GPU_WORK_GROUP_SIZE,
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1024.0 * 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} TB", bytes / (1024.0 * 1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} GB", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+    } else if bytes >= 1024.0 {
+        format!("{:.1} KB", bytes / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AlignmentPlan {
+    pub mode: AlignmentMode,
+    pub variant: KernelVariant,
+    pub len1: usize,
+    pub len2: usize,
+    // Launches needed with at most max_launch_bases per launch, and the longest one
+    pub launches: usize,
+    pub launch_bases: usize,
+    pub work_groups: usize,
+    pub work_group_size: usize,
+    // Work items actually launched per group (a quarter for the packed kernel)
+    pub local_size: usize,
+    pub device_bytes: u64,
+    pub local_bytes: u64,
+    pub traceback_bytes: u64,
+    pub traceback_feasible: bool,
+    pub limits: DeviceLimits,
+    pub problems: Vec<String>,
+}
+
+impl AlignmentPlan {
+    pub fn is_feasible(&self) -> bool {
+        self.problems.is_empty() && (self.mode == AlignmentMode::ScoreOnly || self.traceback_feasible)
+    }
+
+    pub fn print(&self) {
+        println!("Alignment plan ({}, {} kernel, {})", self.mode.name(), self.variant.name(), self.limits.name);
+        println!("   Sequences: {} x {} bases", self.len1, self.len2);
+        println!("   Launches: {} of up to {} bases (limit {} per launch)", self.launches, self.launch_bases, self.limits.max_launch_bases);
+        println!("   Geometry: {} work groups x {} work items, {} positions per group",
+                 self.work_groups, self.local_size, self.launch_bases.div_ceil(self.work_groups.max(1)));
+        let tiling = match self.variant {
+            KernelVariant::Basic => "positions strided across the work group, read from global memory".to_string(),
+            KernelVariant::Tiled => format!("tiles of {} bases staged in local memory", self.work_group_size * 4),
+            KernelVariant::Packed => format!("{} bases per work item per step, one 32-bit word per sequence", PACKED_LANES),
+        };
+        println!("   Kernel tiling: {}", tiling);
+        println!("   Local memory: {} per work group (device: {})", format_bytes(self.local_bytes), format_bytes(self.limits.local_memory_bytes));
+        println!("   Device memory per launch: {} (budget {}, largest buffer {})",
+                 format_bytes(self.device_bytes), format_bytes(self.limits.budget_bytes), format_bytes(self.limits.max_alloc_bytes));
+        println!("   Traceback: {} for the full matrix, {}", format_bytes(self.traceback_bytes),
+                 if self.traceback_feasible { "feasible" } else { "not feasible on this device" });
+        for problem in &self.problems {
+            println!("   Problem: {}", problem);
+        }
+    }
+}
+
+// Plan aligning `len1` against `len2` bases with `variant` on `device`
+pub fn plan(len1: usize, len2: usize, mode: AlignmentMode, variant: KernelVariant, device: &GpuDevice, limits: &DeviceLimits) -> AlignmentPlan {
+    let mut problems = Vec::new();
+    // The kernels score the overlapping prefix of the two sequences
+    let len = len1.min(len2);
+    let launches = len.div_ceil(limits.max_launch_bases.max(1)).max(1);
+    if launches > 1 {
+        problems.push(format!("{} bases exceed the {} bases one launch accepts; split the input into {} chunks with --chunk-size",
+                              len, limits.max_launch_bases, launches));
+    }
+    let launch_bases = len.min(limits.max_launch_bases);
+    let (work_groups, work_group_size) = launch_geometry(launch_bases.max(1), device);
+    let local_size = match variant {
+        KernelVariant::Packed => work_group_size / PACKED_LANES,
+        _ => work_group_size,
+    };
+    if variant == KernelVariant::Packed && work_group_size % PACKED_LANES != 0 {
+        problems.push(format!("packed kernel needs a work group size divisible by {}, got {}", PACKED_LANES, work_group_size));
+    }
+    let local_bytes = match variant {
+        // Fixed reduction array in the kernel source
+        KernelVariant::Basic => 256 * 4,
+        // Two tiles of 4 bases per work item, plus the reduction scratch
+        KernelVariant::Tiled => (work_group_size * 4 * 2 + work_group_size * 4) as u64,
+        KernelVariant::Packed => (local_size * 4) as u64,
+    };
+    if local_bytes > limits.local_memory_bytes {
+        problems.push(format!("{} kernel needs {} of local memory per work group, device has {}",
+                              variant.name(), format_bytes(local_bytes), format_bytes(limits.local_memory_bytes)));
+    }
+    // Both sequences plus the result, for the longest launch
+    let device_bytes = (len1.min(limits.max_launch_bases) + len2.min(limits.max_launch_bases) + 4) as u64;
+    if device_bytes > limits.budget_bytes {
+        problems.push(format!("a launch needs {} of device memory, budget is {}", format_bytes(device_bytes), format_bytes(limits.budget_bytes)));
+    }
+    // One direction byte per matrix cell, two rows of scores, and both sequences
+    let cells = (len1 as u64 + 1).saturating_mul(len2 as u64 + 1);
+    let traceback_bytes = cells.saturating_add((len2 as u64 + 1) * 8).saturating_add((len1 + len2) as u64);
+    let traceback_feasible = cells <= limits.max_alloc_bytes && traceback_bytes <= limits.budget_bytes;
+    AlignmentPlan {
+        mode,
+        variant,
+        len1,
+        len2,
+        launches,
+        launch_bases,
+        work_groups,
+        work_group_size,
+        local_size,
+        device_bytes,
+        local_bytes,
+        traceback_bytes,
+        traceback_feasible,
+        limits: limits.clone(),
+        problems,
+    }
+}
+
+// Bases in one launch for chunks of `chunk_size`; read-count chunks use the
+// expected mean read length when the totals are known
+pub fn chunk_launch_bases(chunk_size: ChunkSize, expectations: &WgsExpectations) -> usize {
+    match chunk_size {
+        ChunkSize::Bases(bases) => bases,
+        ChunkSize::Reads(reads) => {
+            let read_length = match (expectations.reads, expectations.bases) {
+                (Some(r), Some(b)) if r > 0 => b / r,
+                _ => ASSUMED_READ_LENGTH,
+            };
+            reads * read_length
+        },
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WgsPlan {
+    // Selected inputs with their size on disk (None when missing or remote)
+    pub files: Vec<(String, Option<u64>)>,
+    pub chunk_size: ChunkSize,
+    // Every chunk is aligned against itself
+    pub chunk: AlignmentPlan,
+    pub in_flight: usize,
+    pub expected_launches: Option<usize>,
+    pub problems: Vec<String>,
+}
+
+impl WgsPlan {
+    pub fn is_feasible(&self) -> bool {
+        self.problems.is_empty() && self.chunk.is_feasible()
+    }
+
+    pub fn print(&self) {
+        println!("WGS plan: {} input files", self.files.len());
+        for (file, size) in &self.files {
+            let size = size.map(format_bytes).unwrap_or_else(|| "size unknown".to_string());
+            println!("   {} ({})", file, size);
+        }
+        println!("Chunk size: {}", self.chunk_size);
+        if let Some(launches) = self.expected_launches {
+            println!("Expected kernel launches: ~{}", format_count(launches));
+        }
+        println!("Launches in flight: up to {} ({} of device memory)",
+                 self.in_flight, format_bytes(self.chunk.device_bytes * self.in_flight as u64));
+        self.chunk.print();
+        for problem in &self.problems {
+            println!("Problem: {}", problem);
+        }
+        println!("Dry run: {}", if self.is_feasible() { "plan is feasible" } else { "plan is not feasible" });
+    }
+}
+
+// Plan a full WGS run without reading any input
+pub fn plan_wgs(config: &WgsConfig, device: &GpuDevice, limits: &DeviceLimits) -> Result<WgsPlan, String> {
+    let mut problems = Vec::new();
+    let files: Vec<(String, Option<u64>)> = config.order.apply(config.selection.apply(config.discover_files())?)
+        .into_iter()
+        .map(|file| {
+            let size = std::fs::metadata(&file).ok().map(|m| m.len());
+            (file, size)
+        })
+        .collect();
+    for (file, size) in &files {
+        if size.is_none() && !file.contains("://") {
+            problems.push(format!("input not found: {}", file));
+        }
+    }
+    let launch_bases = chunk_launch_bases(config.chunk_size, &config.expectations);
+    let chunk = plan(launch_bases, launch_bases, AlignmentMode::ScoreOnly, crate::kernels::selected(), device, limits);
+    let in_flight = match config.gpu_queues {
+        QueueCount::Auto => MAX_QUEUES,
+        QueueCount::Fixed(n) => n,
+    };
+    let in_flight = if config.read_output.is_some() { 1 } else { in_flight };
+    let resident = chunk.device_bytes * in_flight as u64;
+    if resident > limits.budget_bytes {
+        problems.push(format!("{} launches in flight need {}, budget is {}; lower GPU_QUEUES or the chunk size",
+                              in_flight, format_bytes(resident), format_bytes(limits.budget_bytes)));
+    }
+    if let Some(budget) = config.memory_budget {
+        if let Err(e) = crate::memory_profile::check_plan(config, budget) {
+            problems.push(e);
+        }
+    }
+    let expected_launches = match config.chunk_size {
+        ChunkSize::Reads(_) => config.expectations.reads.map(|reads| config.chunk_size.estimated_chunks(reads, 0)),
+        ChunkSize::Bases(_) => config.expectations.bases.map(|bases| config.chunk_size.estimated_chunks(0, bases)),
+    };
+    Ok(WgsPlan { files, chunk_size: config.chunk_size, chunk, in_flight, expected_launches, problems })
+}
@@ -0,0 +1,153 @@
+// Spot/preemptible instance awareness for full WGS runs
+// With PREEMPTION_WATCH=aws|gcp|azure in .env, a watcher thread polls the
+// cloud's metadata endpoint (through curl, like the remote input readers) for
+// a preemption notice; on Unix, SIGTERM counts as a notice as well, since
+// that's how most schedulers and shutdown sequences announce the end. A notice
+// cancels the run's token, so the current chunk finishes, the interrupted file
+// is checkpointed as incomplete and the run returns. If that hasn't happened
+// within PREEMPTION_GRACE_S (default 20 s; AWS gives 2 minutes notice, GCP 30
+// seconds), the chunk is abandoned and the shutdown happens anyway. Either
+// way the benchmark result is flushed, the uploads to --remote-state finish,
+// and the process exits with 143 so wrappers know the run can be resumed
+
+use crate::exit_codes::ExitKind;
+use crate::run_control::CancellationToken;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_GRACE_S: u64 = 20;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// Metadata requests must not hang on hosts without the endpoint
+const METADATA_TIMEOUT_S: &str = "2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cloud {
+    Aws,
+    Gcp,
+    Azure,
+}
+
+impl Cloud {
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(None),
+            "aws" => Ok(Some(Cloud::Aws)),
+            "gcp" => Ok(Some(Cloud::Gcp)),
+            "azure" => Ok(Some(Cloud::Azure)),
+            _ => Err(format!("Invalid PREEMPTION_WATCH '{}': expected off, aws, gcp, or azure", value)),
+        }
+    }
+
+    // Whether the metadata endpoint announces a preemption
+    fn notice_pending(self) -> bool {
+        match self {
+            // 404 until a spot interruption is scheduled; IMDSv2 needs a session token
+            Cloud::Aws => {
+                let token = metadata(&["-X", "PUT", "-H", "X-aws-ec2-metadata-token-ttl-seconds: 60",
+                                       "http://169.254.169.254/latest/api/token"]);
+                let header = format!("X-aws-ec2-metadata-token: {}", token.unwrap_or_default());
+                metadata(&["-H", &header, "http://169.254.169.254/latest/meta-data/spot/instance-action"])
+                    .is_some_and(|body| !body.trim().is_empty())
+            },
+            Cloud::Gcp => metadata(&["-H", "Metadata-Flavor: Google",
+                                     "http://metadata.google.internal/computeMetadata/v1/instance/preempted"])
+                .is_some_and(|body| body.trim() == "TRUE"),
+            Cloud::Azure => metadata(&["-H", "Metadata: true",
+                                       "http://169.254.169.254/metadata/scheduledevents?api-version=2020-07-01"])
+                .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                .and_then(|events| events["Events"].as_array().cloned())
+                .is_some_and(|events| events.iter().any(|e| e["EventType"] == "Preempt")),
+        }
+    }
+}
+
+// Body of a successful metadata request
+fn metadata(args: &[&str]) -> Option<String> {
+    let output = Command::new("curl")
+        .args(["-sf", "--max-time", METADATA_TIMEOUT_S])
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+static PREEMPTED: AtomicBool = AtomicBool::new(false);
+// Only one of the run's return and the grace deadline performs the shutdown
+static SHUTDOWN: Mutex<bool> = Mutex::new(false);
+
+pub fn is_preempted() -> bool {
+    PREEMPTED.load(Ordering::SeqCst)
+}
+
+fn grace_period() -> Duration {
+    let seconds = std::env::var("PREEMPTION_GRACE_S")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_GRACE_S);
+    Duration::from_secs(seconds)
+}
+
+// Watch for preemption notices for the rest of the process, cancelling
+// `cancel` on the first one
+pub fn watch(cancel: &CancellationToken) -> Result<(), String> {
+    let cloud = Cloud::parse(&std::env::var("PREEMPTION_WATCH").unwrap_or_default())?;
+    let sigterm = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, sigterm.clone())
+        .map_err(|e| format!("Failed to watch for SIGTERM: {}", e))?;
+    if let Some(cloud) = cloud {
+        println!("Watching for {:?} preemption notices", cloud);
+    }
+
+    let cancel = cancel.clone();
+    std::thread::Builder::new()
+        .name("preemption-watch".to_string())
+        .spawn(move || {
+            let mut next_poll = std::time::Instant::now();
+            loop {
+                let reason = if sigterm.load(Ordering::SeqCst) {
+                    Some("SIGTERM received".to_string())
+                } else if cloud.is_some() && std::time::Instant::now() >= next_poll {
+                    next_poll += POLL_INTERVAL;
+                    cloud.filter(|c| c.notice_pending()).map(|c| format!("{:?} preemption notice", c))
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    on_notice(&reason, &cancel);
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(200));
+            }
+        })
+        .map_err(|e| format!("Failed to start preemption watcher: {}", e))?;
+    Ok(())
+}
+
+fn on_notice(reason: &str, cancel: &CancellationToken) {
+    let grace = grace_period();
+    PREEMPTED.store(true, Ordering::SeqCst);
+    crate::tui::stop();
+    println!("\n{}: stopping after the current chunk (at most {} s)", reason, grace.as_secs());
+    cancel.cancel();
+    std::thread::sleep(grace);
+    println!("Current chunk didn't finish within {} s; abandoning it", grace.as_secs());
+    shutdown();
+}
+
+// Flush, upload and exit resumable; called once the run has returned, or by
+// the watcher when the grace period runs out
+pub fn shutdown() -> ! {
+    let mut done = SHUTDOWN.lock().unwrap_or_else(|e| e.into_inner());
+    if !*done {
+        *done = true;
+        crate::run_context::interrupt_cli();
+        crate::remote_state::flush();
+        crate::notify::send(&crate::notify::RunNotification::new(crate::notify::RunEvent::Preempted));
+        println!("Run preempted; completed files are checkpointed and the run can be resumed");
+    }
+    ExitKind::Preempted.exit()
+}
@@ -0,0 +1,66 @@
+// --preset: named bundles of scores, band width and kernel
+// A preset fills in whatever the command line leaves unset: --match-score,
+// --mismatch-penalty, --gap-penalty, --kernel and compare-assemblies --band
+// each override their part of it. Without a preset the defaults are the
+// scores the kernels were written with (scoring::DEFAULT), the basic kernel
+// and assembly_compare::DEFAULT_BAND
+// protein scores residues by identity through the same kernels, so every
+// letter is a symbol; N (asparagine) is still read as an ambiguous base, so
+// pair it with --n-policy zero or mind the mismatches it takes
+
+use crate::kernels::KernelVariant;
+use crate::scoring::Scoring;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub scoring: Scoring,
+    // Diagonals either side of the start diagonal in banded alignment (compare-assemblies)
+    pub band: usize,
+    pub kernel: KernelVariant,
+}
+
+pub const PRESETS: [Preset; 3] = [
+    // Illumina-like reads: few errors, mostly substitutions, so mismatches
+    // and gaps are expensive and the band stays narrow
+    Preset {
+        name: "short-read",
+        description: "Illumina-like reads: match 1, mismatch -4, gap -6, band 32, tiled kernel",
+        scoring: Scoring { match_score: 1, mismatch_penalty: -4, gap_penalty: -6 },
+        band: 32,
+        kernel: KernelVariant::Tiled,
+    },
+    // Nanopore reads: indel-rich, so gaps cost no more than a mismatch and
+    // the band is wide enough for homopolymer slips to add up
+    Preset {
+        name: "long-read-ont",
+        description: "Nanopore reads: match 2, mismatch -4, gap -4, band 128, basic kernel",
+        scoring: Scoring { match_score: 2, mismatch_penalty: -4, gap_penalty: -4 },
+        band: 128,
+        kernel: KernelVariant::Basic,
+    },
+    // Identity scoring of amino acids, on the scale of BLOSUM62's diagonal
+    Preset {
+        name: "protein",
+        description: "amino-acid sequences: match 5, mismatch -4, gap -8, band 32, basic kernel",
+        scoring: Scoring { match_score: 5, mismatch_penalty: -4, gap_penalty: -8 },
+        band: 32,
+        kernel: KernelVariant::Basic,
+    },
+];
+
+pub fn lookup(name: &str) -> Result<Preset, String> {
+    PRESETS.iter().find(|preset| preset.name == name).copied().ok_or_else(|| {
+        let names: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+        format!("Invalid --preset '{}': expected {}", name, names.join(", "))
+    })
+}
+
+// Explicit scores over the preset's over the defaults
+pub fn resolve_scoring(preset: Option<&Preset>, match_score: Option<i32>, mismatch_penalty: Option<i32>, gap_penalty: Option<i32>) -> Result<Scoring, String> {
+    let base = preset.map_or(crate::scoring::DEFAULT, |preset| preset.scoring);
+    Scoring::new(match_score.unwrap_or(base.match_score),
+                 mismatch_penalty.unwrap_or(base.mismatch_penalty),
+                 gap_penalty.unwrap_or(base.gap_penalty))
+}
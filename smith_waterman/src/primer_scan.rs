@@ -0,0 +1,98 @@
+// `primer-scan`: where short primers land in every read, and on which strand
+// Each primer (IUPAC codes allowed) is searched in each read as given ('+') and
+// reverse complemented ('-') with a semi-global edit distance
+// (edit_distance.rs): the whole primer against the best stretch of the read.
+// A primer hits a read when its better strand is within max_edits
+// substitutions, insertions and deletions; on equal distances '+' wins
+// Positions are always on the read as sequenced
+
+use crate::edit_distance::{self, EditHit, Mode};
+use crate::fastq::{FastqBatch, FastqChunks};
+use crate::ungapped::SequenceSet;
+use std::io::Write;
+
+// Reads scanned and launched together
+const READS_PER_BATCH: usize = 4096;
+pub const DEFAULT_MAX_EDITS: u32 = 2;
+
+pub struct Primer {
+    pub name: String,
+    pub sequence: Vec<u8>,
+}
+
+// Primers from a FASTA file: every one non-empty, at most MAX_PATTERN bases,
+// and longer than max_edits so a hit says something
+pub fn load_primers(path: &str, max_edits: u32) -> Result<Vec<Primer>, String> {
+    let records = crate::simulate::load_reference(path)?;
+    if records.is_empty() {
+        return Err(format!("No primers in {}", path));
+    }
+    records.into_iter().map(|(name, sequence)| {
+        if let Some(&bad) = sequence.iter().find(|&&base| edit_distance::encode_pattern(base) == 0) {
+            return Err(format!("Primer {} has '{}', which is not an IUPAC base", name, bad as char));
+        }
+        if sequence.len() as u32 <= max_edits || sequence.len() > edit_distance::MAX_PATTERN {
+            return Err(format!("Primer {} is {} bases long; with --max-edits {} it must be {} to {}",
+                               name, sequence.len(), max_edits, max_edits + 1, edit_distance::MAX_PATTERN));
+        }
+        Ok(Primer { name, sequence })
+    }).collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanSummary {
+    pub reads: usize,
+    pub reads_with_hits: usize,
+    pub hits: usize,
+    // Reads hit by each primer, in primer order
+    pub per_primer: Vec<usize>,
+}
+
+// Scan every read of `reads` (FASTQ) and write each primer hit as TSV
+pub fn scan(primers: &[Primer], reads: &str, max_edits: u32, use_gpu: bool, out: &mut dyn Write) -> Result<ScanSummary, String> {
+    // Pattern 2p is primer p, 2p + 1 its reverse complement
+    let mut patterns = SequenceSet::new();
+    for primer in primers {
+        patterns.push_encoded(&primer.sequence.iter().map(|&b| edit_distance::encode_pattern(b)).collect::<Vec<u8>>());
+        let reverse: Vec<u8> = primer.sequence.iter().rev()
+            .map(|&b| edit_distance::encode_pattern(crate::alignment_input::complement_base(b.to_ascii_uppercase())))
+            .collect();
+        patterns.push_encoded(&reverse);
+    }
+    let mut summary = ScanSummary { per_primer: vec![0; primers.len()], ..Default::default() };
+    let write_error = |e: std::io::Error| format!("Failed to write hits: {}", e);
+    writeln!(out, "#read\tprimer\tstrand\tedits\tstart\tend").map_err(write_error)?;
+
+    let mut chunks = FastqChunks::open(reads, READS_PER_BATCH)?;
+    let mut batch = FastqBatch::with_capacity(READS_PER_BATCH);
+    while chunks.next_into(&mut batch)? {
+        let mut texts = SequenceSet::new();
+        for record in batch.iter() {
+            texts.push_encoded(&record.seq.iter().map(|&b| edit_distance::encode_text(b)).collect::<Vec<u8>>());
+        }
+        let hits = edit_distance::distances(&patterns, &texts, Mode::SemiGlobal, use_gpu)?;
+        for (read, record) in batch.iter().enumerate() {
+            summary.reads += 1;
+            let name = record.id_str();
+            let name = name.split_whitespace().next().unwrap_or("");
+            let mut matched = false;
+            for (p, primer) in primers.iter().enumerate() {
+                let row = read * patterns.len() + 2 * p;
+                let (strand, hit): (char, EditHit) = if hits[row + 1].distance < hits[row].distance {
+                    ('-', hits[row + 1])
+                } else {
+                    ('+', hits[row])
+                };
+                if hit.distance > max_edits {
+                    continue;
+                }
+                matched = true;
+                summary.hits += 1;
+                summary.per_primer[p] += 1;
+                writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", name, primer.name, strand, hit.distance, hit.start + 1, hit.end).map_err(write_error)?;
+            }
+            summary.reads_with_hits += usize::from(matched);
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,187 @@
+// Flamegraph output for --profile runs
+// On Linux, `perf record` samples the process and perf.data is folded into
+// perf.folded and rendered to flamegraph.svg at the end of the run. Where perf
+// isn't available (other platforms, or no permission), an in-process sampler
+// records which instrumented scopes each thread is in and writes the same files.
+// Scopes are free when the in-process sampler isn't running
+
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+pub const FOLDED_FILE: &str = "perf.folded";
+pub const FLAMEGRAPH_FILE: &str = "flamegraph.svg";
+
+// Sampling rate of the in-process profiler (perf record uses -F 99)
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+// Render folded stacks ("a;b;c count" per line) to an SVG flamegraph
+pub fn render_flamegraph(folded: &Path, svg: &Path, title: &str) -> Result<(), String> {
+    let input = File::open(folded)
+        .map_err(|e| format!("Failed to open {}: {}", folded.display(), e))?;
+    let output = File::create(svg)
+        .map_err(|e| format!("Failed to create {}: {}", svg.display(), e))?;
+    let mut options = inferno::flamegraph::Options::default();
+    options.title = title.to_string();
+    inferno::flamegraph::from_reader(&mut options, BufReader::new(input), BufWriter::new(output))
+        .map_err(|e| format!("Failed to render {}: {}", svg.display(), e))
+}
+
+// perf.data -> perf.folded -> flamegraph.svg in the same directory
+pub fn perf_flamegraph(dir: &Path) -> Result<(), String> {
+    use inferno::collapse::Collapse;
+
+    let perf_data = dir.join("perf.data");
+    let mut script = Command::new("perf")
+        .arg("script")
+        .arg("-i")
+        .arg(&perf_data)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run perf script: {}", e))?;
+    let stdout = script.stdout.take().ok_or("perf script produced no output")?;
+
+    let folded = dir.join(FOLDED_FILE);
+    let output = File::create(&folded)
+        .map_err(|e| format!("Failed to create {}: {}", folded.display(), e))?;
+    inferno::collapse::perf::Folder::default()
+        .collapse(BufReader::new(stdout), BufWriter::new(output))
+        .map_err(|e| format!("Failed to fold {}: {}", perf_data.display(), e))?;
+    let status = script.wait().map_err(|e| format!("perf script failed: {}", e))?;
+    if !status.success() {
+        return Err(format!("perf script exited with {}", status));
+    }
+
+    render_flamegraph(&folded, &dir.join(FLAMEGRAPH_FILE), "rustseq_mini (perf)")
+}
+
+// Scope stack of one thread, shared with the sampler
+type ScopeStack = Arc<Mutex<Vec<&'static str>>>;
+
+static SAMPLING: AtomicBool = AtomicBool::new(false);
+static THREADS: Lazy<Mutex<Vec<(String, ScopeStack)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+thread_local! {
+    static STACK: RefCell<Option<ScopeStack>> = const { RefCell::new(None) };
+}
+
+fn with_stack(f: impl FnOnce(&mut Vec<&'static str>)) {
+    let stack = STACK.with(|cell| {
+        cell.borrow_mut()
+            .get_or_insert_with(|| {
+                let stack: ScopeStack = Arc::new(Mutex::new(Vec::new()));
+                let name = std::thread::current().name().unwrap_or("thread").to_string();
+                if let Ok(mut threads) = THREADS.lock() {
+                    threads.push((name, stack.clone()));
+                }
+                stack
+            })
+            .clone()
+    });
+    if let Ok(mut stack) = stack.lock() {
+        f(&mut stack);
+    };
+}
+
+// Marks a region of code for the in-process profiler until dropped
+pub struct Scope {
+    active: bool,
+}
+
+pub fn scope(name: &'static str) -> Scope {
+    let active = SAMPLING.load(Ordering::Relaxed);
+    if active {
+        with_stack(|stack| stack.push(name));
+    }
+    Scope { active }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.active {
+            with_stack(|stack| {
+                stack.pop();
+            });
+        }
+    }
+}
+
+pub struct InProcessProfiler {
+    handle: Option<JoinHandle<HashMap<String, u64>>>,
+}
+
+impl InProcessProfiler {
+    pub fn start() -> Result<Self, String> {
+        if SAMPLING.swap(true, Ordering::SeqCst) {
+            return Err("In-process profiler already running".to_string());
+        }
+        let handle = std::thread::Builder::new()
+            .name("profiler".to_string())
+            .spawn(|| {
+                let mut counts: HashMap<String, u64> = HashMap::new();
+                while SAMPLING.load(Ordering::SeqCst) {
+                    std::thread::sleep(SAMPLE_INTERVAL);
+                    let Ok(threads) = THREADS.lock() else { break };
+                    for (name, stack) in threads.iter() {
+                        let Ok(stack) = stack.lock() else { continue };
+                        // Threads outside any scope are idle or uninstrumented
+                        if stack.is_empty() {
+                            continue;
+                        }
+                        let folded = std::iter::once(name.as_str())
+                            .chain(stack.iter().copied())
+                            .collect::<Vec<_>>()
+                            .join(";");
+                        *counts.entry(folded).or_insert(0) += 1;
+                    }
+                }
+                counts
+            })
+            .map_err(|e| format!("Failed to start in-process profiler: {}", e))?;
+        Ok(Self { handle: Some(handle) })
+    }
+
+    // Stop sampling and write perf.folded and flamegraph.svg to `logs_dir`
+    pub fn finish(mut self, dir: &Path) -> Result<usize, String> {
+        SAMPLING.store(false, Ordering::SeqCst);
+        let counts = self.handle.take()
+            .ok_or("In-process profiler not running")?
+            .join()
+            .map_err(|_| "In-process profiler panicked".to_string())?;
+        if counts.is_empty() {
+            return Ok(0);
+        }
+
+        let folded = dir.join(FOLDED_FILE);
+        let mut lines: Vec<(&String, &u64)> = counts.iter().collect();
+        lines.sort();
+        let mut output = BufWriter::new(File::create(&folded)
+            .map_err(|e| format!("Failed to create {}: {}", folded.display(), e))?);
+        for (stack, count) in &lines {
+            writeln!(output, "{} {}", stack, count)
+                .map_err(|e| format!("Failed to write {}: {}", folded.display(), e))?;
+        }
+        output.flush().map_err(|e| format!("Failed to write {}: {}", folded.display(), e))?;
+        drop(output);
+
+        render_flamegraph(&folded, &dir.join(FLAMEGRAPH_FILE), "rustseq_mini (in-process sampler)")?;
+        Ok(counts.values().sum::<u64>() as usize)
+    }
+}
+
+impl Drop for InProcessProfiler {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            SAMPLING.store(false, Ordering::SeqCst);
+        }
+    }
+}
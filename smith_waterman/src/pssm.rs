@@ -0,0 +1,329 @@
+// `pssm build` / `pssm scan`: position-specific scoring matrices for motif and domain scanning
+// A PSSM scores each residue separately at every motif position. Scanning is
+// ungapped like the other kernels: the profile is slid along every diagonal of
+// each target, and a target's hit is its best local run. On the GPU
+// (smith_waterman_profile) each work item scores one diagonal of one target
+// and writes that diagonal's best; the host picks the best diagonal and walks
+// it again to find where the hit starts and ends. The CPU path computes the
+// same per-diagonal scores.
+// File format: '#' comments, a header line with the alphabet's letters, then
+// one line per position with a score per letter. Residues outside the alphabet
+// score the position's minimum. `pssm build` makes one from an aligned FASTA
+// (e.g. `msa` output): log-odds in half bits against a uniform background,
+// with a pseudocount of 1 per letter, skipping columns that are mostly gaps
+
+use crate::gpu::{GpuContext, GPU_MAX_WORK_GROUPS, GPU_WORK_GROUP_SIZE};
+use crate::ungapped::SequenceSet;
+use ocl::{Buffer, Kernel, MemFlags};
+use rayon::prelude::*;
+use std::io::Write;
+
+const NUCLEOTIDES: &[u8] = b"ACGT";
+const AMINO_ACIDS: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+// Log-odds scale: scores are in 1/SCALE bits
+const SCALE: f64 = 2.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pssm {
+    pub alphabet: Vec<u8>,
+    // Per position: a score per alphabet letter, then the score of anything else
+    pub scores: Vec<Vec<i32>>,
+}
+
+fn is_gap(residue: u8) -> bool {
+    residue == b'-' || residue == b'.'
+}
+
+impl Pssm {
+    fn new(alphabet: Vec<u8>, rows: Vec<Vec<i32>>) -> Self {
+        let scores = rows.into_iter().map(|mut row| {
+            let unknown = row.iter().copied().min().unwrap_or(0);
+            row.push(unknown);
+            row
+        }).collect();
+        Self { alphabet, scores }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    // Columns per position in the kernel's table
+    fn columns(&self) -> usize {
+        self.alphabet.len() + 1
+    }
+
+    // Score of a run over the whole profile with the best residue everywhere
+    pub fn max_score(&self) -> i32 {
+        self.scores.iter().map(|row| row.iter().copied().max().unwrap_or(0)).sum()
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read PSSM {}: {}", path, e))?;
+        let mut alphabet: Option<Vec<u8>> = None;
+        let mut rows = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some(alphabet) = &alphabet else {
+                let letters: Vec<u8> = line.split_whitespace().map(|token| token.as_bytes()).map(|t| match t {
+                    [letter] if letter.is_ascii_alphabetic() || *letter == b'*' => Ok(letter.to_ascii_uppercase()),
+                    _ => Err(format!("{} line {}: the header lists one letter per column", path, i + 1)),
+                }).collect::<Result<_, _>>()?;
+                if (1..letters.len()).any(|k| letters[..k].contains(&letters[k])) {
+                    return Err(format!("{} line {}: a letter appears twice in the header", path, i + 1));
+                }
+                alphabet = Some(letters);
+                continue;
+            };
+            let row: Vec<i32> = line.split_whitespace()
+                .map(|token| token.parse::<i32>().map_err(|_| format!("{} line {}: invalid score '{}'", path, i + 1, token)))
+                .collect::<Result<_, _>>()?;
+            if row.len() != alphabet.len() {
+                return Err(format!("{} line {}: expected {} scores, found {}", path, i + 1, alphabet.len(), row.len()));
+            }
+            rows.push(row);
+        }
+        match alphabet {
+            Some(alphabet) if !rows.is_empty() => Ok(Self::new(alphabet, rows)),
+            _ => Err(format!("PSSM {} has no positions", path)),
+        }
+    }
+
+    // From an aligned FASTA; nucleotide unless a residue outside A/C/G/T/U/N appears
+    pub fn from_msa(path: &str) -> Result<Self, String> {
+        let aligned = crate::simulate::load_reference(path)?;
+        let width = aligned[0].1.len();
+        if let Some((name, _)) = aligned.iter().find(|(_, row)| row.len() != width) {
+            return Err(format!("{} is not an alignment: {} has a different length from {}", path, name, aligned[0].0));
+        }
+        let nucleotide = aligned.iter().flat_map(|(_, row)| row.iter()).all(|&r| is_gap(r) || b"ACGTUN".contains(&r));
+        let alphabet = if nucleotide { NUCLEOTIDES } else { AMINO_ACIDS };
+        let k = alphabet.len() as f64;
+        let mut rows = Vec::new();
+        for column in 0..width {
+            let residues: Vec<u8> = aligned.iter().map(|(_, row)| if row[column] == b'U' { b'T' } else { row[column] }).collect();
+            let gaps = residues.iter().filter(|&&r| is_gap(r)).count();
+            if gaps * 2 > residues.len() {
+                continue;
+            }
+            let counts: Vec<usize> = alphabet.iter().map(|&letter| residues.iter().filter(|&&r| r == letter).count()).collect();
+            let total: usize = counts.iter().sum();
+            rows.push(counts.iter().map(|&count| {
+                let frequency = (count as f64 + 1.0) / (total as f64 + k);
+                (SCALE * (frequency * k).log2()).round() as i32
+            }).collect());
+        }
+        if rows.is_empty() {
+            return Err(format!("{} has no columns with residues in most sequences", path));
+        }
+        Ok(Self::new(alphabet.to_vec(), rows))
+    }
+
+    pub fn save(&self, path: &str, source: &str) -> Result<(), String> {
+        let mut text = format!("# rustseq_mini PSSM from {}, {} positions, scores in half bits\n", source, self.len());
+        let header: Vec<String> = self.alphabet.iter().map(|&letter| (letter as char).to_string()).collect();
+        text.push_str(&header.join(" "));
+        text.push('\n');
+        for row in &self.scores {
+            let scores: Vec<String> = row[..self.alphabet.len()].iter().map(|s| s.to_string()).collect();
+            text.push_str(&scores.join(" "));
+            text.push('\n');
+        }
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    // Column index of a residue (either case); U reads as T in nucleotide profiles
+    pub fn encode(&self, residue: u8) -> u8 {
+        let upper = match residue.to_ascii_uppercase() {
+            b'U' if !self.alphabet.contains(&b'U') => b'T',
+            other => other,
+        };
+        self.alphabet.iter().position(|&letter| letter == upper).unwrap_or(self.alphabet.len()) as u8
+    }
+
+    // Row-major copy for the kernel
+    fn flattened(&self) -> Vec<i32> {
+        self.scores.iter().flatten().copied().collect()
+    }
+
+    fn score(&self, position: usize, residue: u8) -> i32 {
+        self.scores[position][residue as usize]
+    }
+
+    // (profile start, target start) of a diagonal, numbered as in ungapped.rs
+    fn diagonal_start(&self, diagonal: usize) -> (usize, usize) {
+        ((self.len() - 1).saturating_sub(diagonal), diagonal.saturating_sub(self.len() - 1))
+    }
+
+    // Best local run on one diagonal: (score, profile start, target start, length)
+    fn walk(&self, target: &[u8], diagonal: usize) -> (i32, usize, usize, usize) {
+        let (i0, j0) = self.diagonal_start(diagonal);
+        let steps = (self.len() - i0).min(target.len().saturating_sub(j0));
+        let (mut current, mut run_start) = (0, 0);
+        let mut best = (0, i0, j0, 0);
+        for step in 0..steps {
+            if current == 0 {
+                run_start = step;
+            }
+            current = (current + self.score(i0 + step, target[j0 + step])).max(0);
+            if current > best.0 {
+                best = (current, i0 + run_start, j0 + run_start, step + 1 - run_start);
+            }
+        }
+        best
+    }
+}
+
+// Best score of every diagonal of every target, indexed target * diagonals + diagonal
+pub fn cpu_diagonal_scores(pssm: &Pssm, targets: &SequenceSet, diagonals: usize) -> Vec<i32> {
+    (0..targets.len() * diagonals).into_par_iter().map(|cell| {
+        let (target, diagonal) = (targets.get(cell / diagonals), cell % diagonals);
+        if target.is_empty() || diagonal >= pssm.len() + target.len() - 1 {
+            0
+        } else {
+            pssm.walk(target, diagonal).0
+        }
+    }).collect()
+}
+
+// GPU counterpart of cpu_diagonal_scores
+pub fn gpu_diagonal_scores(pssm: &Pssm, targets: &SequenceSet, diagonals: usize) -> Result<Vec<i32>, String> {
+    let mut scores = Vec::with_capacity(targets.len() * diagonals);
+    if targets.residues.is_empty() {
+        scores.resize(targets.len() * diagonals, 0);
+        return Ok(scores);
+    }
+    let targets_per_launch = (GPU_MAX_WORK_GROUPS * GPU_WORK_GROUP_SIZE / diagonals).max(1);
+    let mut first = 0;
+    while first < targets.len() {
+        let last = (first + targets_per_launch).min(targets.len());
+        scores.extend(launch(pssm, &targets.slice(first, last), diagonals)?);
+        first = last;
+    }
+    Ok(scores)
+}
+
+fn launch(pssm: &Pssm, targets: &SequenceSet, diagonals: usize) -> Result<Vec<i32>, String> {
+    let cells = targets.len() * diagonals;
+    let gpu = GpuContext::shared()?;
+    let (queue, program) = (gpu.queue(), gpu.program()?);
+    let profile = pssm.flattened();
+    let profile_buffer = Buffer::<i32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(profile.len())
+        .copy_host_slice(&profile)
+        .build()
+        .map_err(|e| format!("Failed to create profile buffer: {}", e))?;
+    let target_buffer = Buffer::<u8>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(targets.residues.len())
+        .copy_host_slice(&targets.residues)
+        .build()
+        .map_err(|e| format!("Failed to create target buffer: {}", e))?;
+    let offset_buffer = Buffer::<u32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(targets.offsets.len())
+        .copy_host_slice(&targets.offsets)
+        .build()
+        .map_err(|e| format!("Failed to create target offset buffer: {}", e))?;
+    // Diagonals past a target's end are never written, so they stay zero
+    let result_buffer = Buffer::<i32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_write())
+        .len(cells)
+        .fill_val(0)
+        .build()
+        .map_err(|e| format!("Failed to create result buffer: {}", e))?;
+    let kernel = Kernel::builder()
+        .program(program)
+        .name("smith_waterman_profile")
+        .queue(queue.clone())
+        .global_work_size([diagonals, targets.len()])
+        .arg(&profile_buffer)
+        .arg(pssm.len() as u32)
+        .arg(pssm.columns() as u32)
+        .arg(&target_buffer)
+        .arg(&offset_buffer)
+        .arg(&result_buffer)
+        .arg(diagonals as u32)
+        .build()
+        .map_err(|e| format!("Failed to create profile kernel: {}", e))?;
+    unsafe {
+        kernel.enq().map_err(|e| format!("Failed to execute profile kernel: {}", e))?;
+    }
+    let mut scores = vec![0i32; cells];
+    result_buffer.read(&mut scores).enq()
+        .map_err(|e| format!("Failed to read profile kernel result: {}", e))?;
+    Ok(scores)
+}
+
+// 1-based inclusive coordinates of a target's best hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileHit {
+    pub score: i32,
+    pub target_start: usize,
+    pub target_end: usize,
+    pub profile_start: usize,
+    pub profile_end: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanSummary {
+    pub targets: usize,
+    pub hits: usize,
+}
+
+// Targets scored together
+const TARGETS_PER_BATCH: usize = 1024;
+
+// Scan every sequence of `targets_path` (FASTA) and write the best hit of each
+// target scoring at least `min_score` as TSV
+pub fn scan(pssm: &Pssm, targets_path: &str, min_score: i32, use_gpu: bool, out: &mut dyn Write) -> Result<ScanSummary, String> {
+    let records = crate::simulate::load_reference(targets_path)?;
+    let mut summary = ScanSummary::default();
+    let write_error = |e: std::io::Error| format!("Failed to write hits: {}", e);
+    writeln!(out, "#target\tscore\ttarget_start\ttarget_end\tprofile_start\tprofile_end").map_err(write_error)?;
+    for batch in records.chunks(TARGETS_PER_BATCH) {
+        let mut targets = SequenceSet::new();
+        for (_, sequence) in batch {
+            targets.push_encoded(&sequence.iter().map(|&r| pssm.encode(r)).collect::<Vec<u8>>());
+        }
+        let diagonals = (pssm.len() + targets.longest()).saturating_sub(1).max(1);
+        let scores = if use_gpu {
+            gpu_diagonal_scores(pssm, &targets, diagonals)?
+        } else {
+            cpu_diagonal_scores(pssm, &targets, diagonals)
+        };
+        for (t, (name, _)) in batch.iter().enumerate() {
+            summary.targets += 1;
+            // Best diagonal; ties go to the first
+            let row = &scores[t * diagonals..(t + 1) * diagonals];
+            let diagonal = (0..diagonals).fold(0, |best, d| if row[d] > row[best] { d } else { best });
+            if row[diagonal] < min_score || row[diagonal] <= 0 {
+                continue;
+            }
+            let (score, profile_start, target_start, length) = pssm.walk(targets.get(t), diagonal);
+            let hit = ProfileHit {
+                score,
+                target_start: target_start + 1,
+                target_end: target_start + length,
+                profile_start: profile_start + 1,
+                profile_end: profile_start + length,
+            };
+            summary.hits += 1;
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", name, hit.score, hit.target_start, hit.target_end, hit.profile_start, hit.profile_end)
+                .map_err(write_error)?;
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,444 @@
+// --qc: per-file QC statistics collected during the WGS pass, written to qc_report.json
+// Duplicates are estimated from sequence hashes: reads with identical bases
+// are one original plus duplicates. To bound memory only reads whose sequence
+// hash falls in 1 of every `sampling` buckets are kept; every copy of a
+// sequence hashes the same, so the sampled reads have the same duplicate rate
+// as the whole file. Illumina read names (instrument:run:flowcell:lane:tile:x:y,
+// or the older instrument:lane:tile:x:y) give each read's position on the flow
+// cell, and a duplicate within `pixel_distance` in both x and y of another copy
+// on the same lane and tile counts as an optical duplicate, as in Picard's
+// MarkDuplicates. Reads without a parseable name can be PCR duplicates only
+// The same names give each read's instrument, run, flow cell, lane and tile,
+// and the report breaks reads, N calls and qualities down by them (flow_cell.rs)
+// Quality lines (Phred+33) give a histogram of every base's quality and the
+// mean quality at each cycle (position in the read as sequenced); FASTA input
+// has neither. Read lengths are counted exactly. Adapter content follows
+// FastQC: a read contains an adapter from the first position where the whole
+// adapter sequence (12 bases for the built-in ones) appears, found by sliding
+// it along the read, and the report gives the share of reads with the adapter
+// at or before each position
+
+use crate::flow_cell::{FlowCellStats, LaneQc};
+use crate::memory_profile::{MemoryBudget, Reservation, Site};
+use crate::pipeline::ChunkProcessor;
+use crate::read_chunk::{hash_name, ReadChunk, ReadName};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub const DEFAULT_SAMPLING: u64 = 8;
+// Picard's default for non-patterned flow cells; patterned ones use about 2500
+pub const DEFAULT_PIXEL_DISTANCE: u32 = 100;
+// Highest Phred score Phred+33 can encode ('~')
+pub const MAX_QUALITY: usize = 93;
+
+// FastQC's adapter list
+pub const DEFAULT_ADAPTERS: [(&str, &str); 4] = [
+    ("Illumina Universal Adapter", "AGATCGGAAGAG"),
+    ("Illumina Small RNA 3' Adapter", "TGGAATTCTCGG"),
+    ("Nextera Transposase Sequence", "CTGTCTCTTATA"),
+    ("SOLID Small RNA Adapter", "CGCCTTGGCCGT"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QcOptions {
+    // Keep 1 in this many sequence hashes for duplicate estimation
+    pub sampling: u64,
+    pub pixel_distance: u32,
+    // (name, uppercase sequence) of each adapter looked for
+    pub adapters: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for QcOptions {
+    fn default() -> Self {
+        Self {
+            sampling: DEFAULT_SAMPLING,
+            pixel_distance: DEFAULT_PIXEL_DISTANCE,
+            adapters: DEFAULT_ADAPTERS.iter().map(|(name, seq)| (name.to_string(), seq.as_bytes().to_vec())).collect(),
+        }
+    }
+}
+
+// Adapters from a FASTA file, one record each
+pub fn load_adapters(path: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let adapters = crate::simulate::load_reference(path)?;
+    if adapters.is_empty() {
+        return Err(format!("No adapters in {}", path));
+    }
+    if let Some((name, _)) = adapters.iter().find(|(_, seq)| seq.is_empty()) {
+        return Err(format!("Adapter {} in {} has no sequence", name, path));
+    }
+    Ok(adapters)
+}
+
+impl QcOptions {
+    // QC_DUPLICATE_SAMPLING, QC_OPTICAL_PIXEL_DISTANCE and QC_ADAPTERS (a FASTA
+    // path) from .env, defaults when unset
+    pub fn from_env() -> Result<Self, String> {
+        let mut options = Self::default();
+        if let Ok(value) = std::env::var("QC_DUPLICATE_SAMPLING") {
+            options.sampling = value.trim().parse().ok().filter(|&n| n > 0)
+                .ok_or_else(|| format!("Invalid QC_DUPLICATE_SAMPLING value '{}': expected a positive count", value))?;
+        }
+        if let Ok(value) = std::env::var("QC_OPTICAL_PIXEL_DISTANCE") {
+            options.pixel_distance = value.trim().parse()
+                .map_err(|e| format!("Invalid QC_OPTICAL_PIXEL_DISTANCE value '{}': {}", value, e))?;
+        }
+        if let Ok(path) = std::env::var("QC_ADAPTERS") {
+            options.adapters = load_adapters(path.trim())?;
+        }
+        Ok(options)
+    }
+}
+
+// Flow cell position of a read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Location {
+    pub(crate) lane: u32,
+    tile: u32,
+    x: u32,
+    y: u32,
+}
+
+// Lane, tile, x and y are the last four ':' fields of an Illumina name; y may
+// carry a '#index/1' style suffix
+pub(crate) fn parse_location(name: &[u8]) -> Option<Location> {
+    let name = std::str::from_utf8(name).ok()?;
+    let fields: Vec<&str> = name.split(':').collect();
+    if fields.len() < 5 {
+        return None;
+    }
+    let y = fields[fields.len() - 1];
+    let y = &y[..y.find(|c: char| !c.is_ascii_digit()).unwrap_or(y.len())];
+    let number = |field: &str| field.parse::<u32>().ok();
+    Some(Location {
+        lane: number(fields[fields.len() - 4])?,
+        tile: number(fields[fields.len() - 3])?,
+        x: number(fields[fields.len() - 2])?,
+        y: number(y)?,
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DuplicateStats {
+    // Reads kept by the hash sampling
+    pub sampled_reads: u64,
+    // Sampled reads with an Illumina flow cell position in their name
+    pub located_reads: u64,
+    pub duplicates: u64,
+    pub optical_duplicates: u64,
+    pub duplicate_rate: f64,
+    pub optical_duplicate_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QualityStats {
+    pub bases: u64,
+    pub mean: f64,
+    pub q20_fraction: f64,
+    pub q30_fraction: f64,
+    // Bases at each Phred score, 0 to MAX_QUALITY
+    pub histogram: Vec<u64>,
+    // Mean Phred score at each cycle, from the first
+    pub per_cycle_mean: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LengthStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    // (length, reads) for every length seen, shortest first
+    pub histogram: Vec<(usize, u64)>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdapterContent {
+    pub adapter: String,
+    pub reads: u64,
+    pub fraction: f64,
+    // Share of reads with the adapter starting at or before each position
+    pub cumulative_by_position: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileQc {
+    pub file: String,
+    pub reads: u64,
+    pub duplicates: DuplicateStats,
+    // None when the input had no quality lines
+    #[serde(default)]
+    pub quality: Option<QualityStats>,
+    #[serde(default)]
+    pub lengths: LengthStats,
+    #[serde(default)]
+    pub adapters: Vec<AdapterContent>,
+    // By instrument, run, flow cell and lane, from Illumina read names
+    #[serde(default)]
+    pub lanes: Vec<LaneQc>,
+}
+
+impl FileQc {
+    pub fn summary(&self) -> String {
+        let d = &self.duplicates;
+        let mut summary = format!("duplicates {:.2}% (optical {:.2}%) of {} sampled reads, {} with flow cell positions",
+                                  d.duplicate_rate * 100.0, d.optical_duplicate_rate * 100.0, d.sampled_reads, d.located_reads);
+        if let Some(q) = &self.quality {
+            summary.push_str(&format!("; mean quality {:.1}, Q20 {:.2}%, Q30 {:.2}% over {} cycles",
+                                      q.mean, q.q20_fraction * 100.0, q.q30_fraction * 100.0, q.per_cycle_mean.len()));
+        }
+        summary.push_str(&format!("; lengths {}-{} (mean {:.1})", self.lengths.min, self.lengths.max, self.lengths.mean));
+        for adapter in self.adapters.iter().filter(|a| a.reads > 0) {
+            summary.push_str(&format!("; {} in {:.2}% of reads", adapter.adapter, adapter.fraction * 100.0));
+        }
+        if !self.lanes.is_empty() {
+            let lanes: Vec<String> = self.lanes.iter().map(|lane| match &self.quality {
+                Some(_) => format!("{} Q{:.1}", lane.label(), lane.mean_quality),
+                None => lane.label(),
+            }).collect();
+            let low_tiles: usize = self.lanes.iter().map(|lane| lane.low_quality_tiles.len()).sum();
+            summary.push_str(&format!("; lanes {}, {} low-quality tiles", lanes.join(", "), low_tiles));
+        }
+        summary
+    }
+}
+
+// Statistics of one file, fed chunk by chunk
+pub struct QcCollector {
+    options: QcOptions,
+    reads: u64,
+    // (sequence hash, position) of every sampled read
+    sampled: Vec<(u64, Option<Location>)>,
+    quality_histogram: [u64; MAX_QUALITY + 1],
+    // Quality sum and base count at each cycle
+    cycle_sums: Vec<u64>,
+    cycle_bases: Vec<u64>,
+    lengths: BTreeMap<usize, u64>,
+    // Per adapter: reads whose first occurrence starts at each position
+    adapter_starts: Vec<Vec<u64>>,
+    flow_cell: FlowCellStats,
+    // The duplicate sample's share of the --memory-profile strict budget
+    budget: Reservation,
+}
+
+impl QcCollector {
+    pub fn new(options: QcOptions) -> Self {
+        let adapters = options.adapters.len();
+        Self {
+            options,
+            reads: 0,
+            sampled: Vec::new(),
+            quality_histogram: [0; MAX_QUALITY + 1],
+            cycle_sums: Vec::new(),
+            cycle_bases: Vec::new(),
+            lengths: BTreeMap::new(),
+            adapter_starts: vec![Vec::new(); adapters],
+            flow_cell: FlowCellStats::default(),
+            budget: Reservation::new(Site::KmerTables),
+        }
+    }
+
+    pub fn add_chunk(&mut self, chunk: &ReadChunk) {
+        for (i, read) in chunk.iter().enumerate() {
+            self.reads += 1;
+            if let Some(quality) = chunk.quality(i) {
+                self.add_quality(quality);
+            }
+            *self.lengths.entry(read.len()).or_insert(0) += 1;
+            if let Some(ReadName::Name(name)) = chunk.name(i) {
+                self.flow_cell.add(name, read, chunk.quality(i));
+            }
+            for (a, (_, adapter)) in self.options.adapters.iter().enumerate() {
+                if let Some(start) = read.windows(adapter.len()).position(|window| window.eq_ignore_ascii_case(adapter)) {
+                    let starts = &mut self.adapter_starts[a];
+                    if starts.len() <= start {
+                        starts.resize(start + 1, 0);
+                    }
+                    starts[start] += 1;
+                }
+            }
+            let hash = hash_name(read);
+            // FNV's low bits are weak, so buckets come from the high ones
+            if !(hash >> 32).is_multiple_of(self.options.sampling) {
+                continue;
+            }
+            let location = match chunk.name(i) {
+                Some(ReadName::Name(name)) => parse_location(name),
+                _ => None,
+            };
+            self.sampled.push((hash, location));
+        }
+    }
+
+    // Hold the duplicate sample against a run's budget from the next account()
+    pub fn hold_against(&mut self, memory: &Arc<MemoryBudget>) {
+        self.budget.hold_against(memory);
+    }
+
+    // Bring the duplicate sample's reservation up to date; Err over the budget
+    pub fn account(&mut self) -> Result<(), String> {
+        let bytes = self.sampled.capacity() * std::mem::size_of::<(u64, Option<Location>)>();
+        self.budget.resize(bytes)
+    }
+
+    fn add_quality(&mut self, quality: &[u8]) {
+        if quality.len() > self.cycle_sums.len() {
+            self.cycle_sums.resize(quality.len(), 0);
+            self.cycle_bases.resize(quality.len(), 0);
+        }
+        for (cycle, &symbol) in quality.iter().enumerate() {
+            let score = (symbol.saturating_sub(33) as usize).min(MAX_QUALITY);
+            self.quality_histogram[score] += 1;
+            self.cycle_sums[cycle] += score as u64;
+            self.cycle_bases[cycle] += 1;
+        }
+    }
+
+    fn quality_stats(&self) -> Option<QualityStats> {
+        let bases: u64 = self.quality_histogram.iter().sum();
+        if bases == 0 {
+            return None;
+        }
+        let at_least = |min: usize| self.quality_histogram[min..].iter().sum::<u64>() as f64 / bases as f64;
+        Some(QualityStats {
+            bases,
+            mean: self.cycle_sums.iter().sum::<u64>() as f64 / bases as f64,
+            q20_fraction: at_least(20),
+            q30_fraction: at_least(30),
+            histogram: self.quality_histogram.to_vec(),
+            per_cycle_mean: self.cycle_sums.iter().zip(&self.cycle_bases).map(|(&sum, &n)| sum as f64 / n as f64).collect(),
+        })
+    }
+
+    fn length_stats(&self) -> LengthStats {
+        let total: u64 = self.lengths.values().sum();
+        LengthStats {
+            min: self.lengths.keys().next().copied().unwrap_or(0),
+            max: self.lengths.keys().next_back().copied().unwrap_or(0),
+            mean: self.lengths.iter().map(|(&len, &n)| len as f64 * n as f64).sum::<f64>() / total.max(1) as f64,
+            histogram: self.lengths.iter().map(|(&len, &n)| (len, n)).collect(),
+        }
+    }
+
+    fn adapter_content(&self) -> Vec<AdapterContent> {
+        let reads = self.reads.max(1) as f64;
+        self.options.adapters.iter().zip(&self.adapter_starts).map(|((name, _), starts)| {
+            let mut running = 0;
+            let cumulative_by_position = starts.iter().map(|&n| {
+                running += n;
+                running as f64 / reads
+            }).collect();
+            AdapterContent { adapter: name.clone(), reads: running, fraction: running as f64 / reads, cumulative_by_position }
+        }).collect()
+    }
+
+    // Counts duplicates within each group of identical sequences; a copy is
+    // optical when an earlier copy (in lane, tile, x order) is close enough
+    pub fn finish(mut self, file: &str) -> FileQc {
+        self.sampled.sort_unstable();
+        let distance = self.options.pixel_distance;
+        let mut stats = DuplicateStats {
+            sampled_reads: self.sampled.len() as u64,
+            located_reads: self.sampled.iter().filter(|(_, location)| location.is_some()).count() as u64,
+            ..Default::default()
+        };
+        for group in self.sampled.chunk_by(|a, b| a.0 == b.0) {
+            stats.duplicates += group.len() as u64 - 1;
+            for (i, (_, location)) in group.iter().enumerate() {
+                let Some(here) = location else { continue };
+                let optical = group[..i].iter().rev()
+                    .filter_map(|(_, earlier)| *earlier)
+                    .take_while(|earlier| earlier.lane == here.lane && earlier.tile == here.tile && here.x - earlier.x <= distance)
+                    .any(|earlier| earlier.y.abs_diff(here.y) <= distance);
+                stats.optical_duplicates += u64::from(optical);
+            }
+        }
+        let sampled = stats.sampled_reads.max(1) as f64;
+        stats.duplicate_rate = stats.duplicates as f64 / sampled;
+        stats.optical_duplicate_rate = stats.optical_duplicates as f64 / sampled;
+        FileQc {
+            file: file.to_string(),
+            reads: self.reads,
+            duplicates: stats,
+            quality: self.quality_stats(),
+            lengths: self.length_stats(),
+            adapters: self.adapter_content(),
+            lanes: self.flow_cell.finish(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QcReport {
+    pub files: Vec<FileQc>,
+}
+
+impl QcReport {
+    // qc_report.json in the results directory
+    pub fn save(&self) -> Result<std::path::PathBuf, String> {
+        let path = crate::output_layout::result_file("qc_report.json");
+        self.write(&path)?;
+        crate::remote_state::mirror(&path);
+        Ok(path)
+    }
+
+    pub fn write(&self, path: &std::path::Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize QC report: {}", e))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+// The QC collector as a pipeline processor (`stats`, `analyze --qc`); the
+// report of every file is written to `output` after the last one
+pub struct QcProcessor {
+    options: QcOptions,
+    collector: Option<QcCollector>,
+    report: QcReport,
+    output: std::path::PathBuf,
+}
+
+impl QcProcessor {
+    pub fn new(options: QcOptions, output: impl Into<std::path::PathBuf>) -> Self {
+        Self { options, collector: None, report: QcReport::default(), output: output.into() }
+    }
+}
+
+impl ChunkProcessor for QcProcessor {
+    fn name(&self) -> &str {
+        "QC"
+    }
+
+    // Flow cell positions come from the names
+    fn needs_names(&self) -> bool {
+        true
+    }
+
+    fn needs_qualities(&self) -> bool {
+        true
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        self.collector = Some(QcCollector::new(self.options.clone()));
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        if let Some(collector) = self.collector.as_mut() {
+            collector.add_chunk(chunk);
+        }
+        Ok(())
+    }
+
+    fn finish_file(&mut self, file: &str) -> Result<String, String> {
+        let collector = self.collector.take().ok_or_else(|| format!("QC of {} finished before it started", file))?;
+        let file_qc = collector.finish(file);
+        let summary = file_qc.summary();
+        self.report.files.push(file_qc);
+        Ok(summary)
+    }
+
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        self.report.write(&self.output)?;
+        Ok(Some(format!("{} files written to {}", self.report.files.len(), self.output.display())))
+    }
+}
@@ -0,0 +1,86 @@
+// Quarantine of input files that keep failing across --resume attempts
+// A file that fails stops the run, and the failure goes into the checkpoint:
+// attempts so far, the latest errors and the chunks that failed to align.
+// Resuming retries the file. The attempt that brings it to QUARANTINE_AFTER
+// failures (default 3) quarantines it and moves on to the next file instead of
+// stopping, and later resumes skip it, so one bad lane file can't keep the
+// others from finishing. A run with quarantined files ends in partial success
+// (exit code 5). failures.json in the results directory lists every failing
+// file with its attempts and errors; it is rewritten after each failure, from
+// the checkpoint, so it covers the earlier attempts as well
+// QUARANTINE_AFTER=0 turns quarantine off: a failing file always stops the run
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const FAILURES_FILE: &str = "failures.json";
+pub const DEFAULT_QUARANTINE_AFTER: u32 = 3;
+// Errors kept per file, latest last
+const ERRORS_KEPT: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileFailure {
+    pub file_path: String,
+    pub file_index: usize,
+    pub attempts: u32,
+    pub errors: Vec<String>,
+    // Chunks that failed to align on the latest attempt
+    pub failed_chunks: Vec<String>,
+    pub quarantined: bool,
+    pub last_failed: DateTime<Utc>,
+}
+
+impl FileFailure {
+    pub fn new(file_index: usize, file_path: &str) -> Self {
+        Self {
+            file_path: file_path.to_string(),
+            file_index,
+            attempts: 0,
+            errors: Vec::new(),
+            failed_chunks: Vec::new(),
+            quarantined: false,
+            last_failed: Utc::now(),
+        }
+    }
+
+    // One more failed attempt; quarantined once `quarantine_after` is reached (0: never)
+    pub fn record(&mut self, error: &str, failed_chunks: &[String], quarantine_after: u32) {
+        self.attempts += 1;
+        self.errors.push(error.to_string());
+        if self.errors.len() > ERRORS_KEPT {
+            self.errors.remove(0);
+        }
+        self.failed_chunks = failed_chunks.to_vec();
+        self.last_failed = Utc::now();
+        self.quarantined = quarantine_after > 0 && self.attempts >= quarantine_after;
+    }
+}
+
+// QUARANTINE_AFTER from .env
+pub fn quarantine_after_from_env() -> Result<u32, String> {
+    match std::env::var("QUARANTINE_AFTER") {
+        Ok(value) => value.trim().parse::<u32>()
+            .map_err(|_| format!("Invalid QUARANTINE_AFTER '{}': expected a number of attempts (0 turns quarantine off)", value)),
+        Err(_) => Ok(DEFAULT_QUARANTINE_AFTER),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FailureReport<'a> {
+    run: u64,
+    quarantine_after: u32,
+    quarantined: usize,
+    files: &'a [FileFailure],
+}
+
+// Write failures.json to the results directory
+pub fn save_report(run: u64, quarantine_after: u32, failures: &[FileFailure]) -> Result<std::path::PathBuf, String> {
+    let path = crate::output_layout::result_file(FAILURES_FILE);
+    let report = FailureReport { run, quarantine_after, quarantined: failures.iter().filter(|f| f.quarantined).count(), files: failures };
+    let json = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Failed to serialize {}: {}", FAILURES_FILE, e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    crate::remote_state::mirror(&path);
+    Ok(path)
+}
@@ -0,0 +1,133 @@
+// Batched `db search` for callers issuing many short queries at once, such as
+// `serve` answering searches from many clients (serve.rs). One short query
+// alone is far too little work for a GPU launch; queries submitted from any
+// number of threads are queued instead, and a worker thread aligns whatever is
+// queued against the database in one launch (sequence_db::batch_hits), then
+// hands each caller its own hits, ranked and cut to the HitLimits
+// The worker takes up to `max_batch` queries at a time. With fewer queued, it
+// waits up to `linger` after the first for more to arrive, trading that much
+// latency for fuller launches; a `linger` of zero launches whatever is queued
+// right away. A failed launch fails every query in it
+
+use crate::sequence_db::{self, Hit, LiveTargets, SearchOptions, SearchSummary, SequenceDb};
+use crate::ungapped::{ScoreMatrix, SequenceSet};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// As many queries as `db search` launches together; every queued query goes
+// into the one launch, so a larger max_batch makes larger launches
+pub const DEFAULT_MAX_BATCH: usize = sequence_db::QUERIES_PER_BATCH;
+pub const DEFAULT_LINGER: Duration = Duration::from_millis(2);
+
+struct Request {
+    sequence: Vec<u8>,
+    reply: Sender<Result<Vec<Hit>, String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatcherStats {
+    pub queries: usize,
+    pub launches: usize,
+    pub largest_batch: usize,
+    // Query/target pairs aligned, and those screened out (sequence_db::SearchSummary)
+    pub alignments: usize,
+    pub screened: usize,
+}
+
+impl BatcherStats {
+    pub fn mean_batch(&self) -> f64 {
+        if self.launches == 0 { 0.0 } else { self.queries as f64 / self.launches as f64 }
+    }
+}
+
+pub struct QueryBatcher {
+    requests: Option<Sender<Request>>,
+    worker: Option<JoinHandle<()>>,
+    stats: Arc<Mutex<BatcherStats>>,
+}
+
+impl QueryBatcher {
+    pub fn new(db: Arc<SequenceDb>, options: SearchOptions, max_batch: usize, linger: Duration) -> Result<Self, String> {
+        if max_batch == 0 {
+            return Err("Invalid batch size 0: expected at least 1 query".to_string());
+        }
+        let (requests, queue) = mpsc::channel();
+        let stats = Arc::new(Mutex::new(BatcherStats::default()));
+        let worker_stats = Arc::clone(&stats);
+        let worker = std::thread::Builder::new()
+            .name("db-batcher".to_string())
+            .spawn(move || run(&db, options, max_batch, linger, queue, &worker_stats))
+            .map_err(|e| format!("Failed to start the query batcher: {}", e))?;
+        Ok(Self { requests: Some(requests), worker: Some(worker), stats })
+    }
+
+    // Hits of one query (bases, as in a FASTA record), best first; blocks until
+    // the launch it went into has finished
+    pub fn search(&self, sequence: &[u8]) -> Result<Vec<Hit>, String> {
+        let (reply, hits) = mpsc::channel();
+        let request = Request { sequence: sequence.to_vec(), reply };
+        self.requests.as_ref()
+            .and_then(|requests| requests.send(request).ok())
+            .ok_or_else(|| "The query batcher has stopped".to_string())?;
+        hits.recv().map_err(|_| "The query batcher stopped before answering".to_string())?
+    }
+
+    pub fn stats(&self) -> BatcherStats {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for QueryBatcher {
+    // Queries already queued are answered before the worker exits
+    fn drop(&mut self) {
+        self.requests.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(db: &SequenceDb, options: SearchOptions, max_batch: usize, linger: Duration, queue: Receiver<Request>, stats: &Mutex<BatcherStats>) {
+    let live = LiveTargets::new(db);
+    let matrix = ScoreMatrix::nucleotide();
+    while let Ok(first) = queue.recv() {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + linger;
+        while batch.len() < max_batch {
+            match queue.try_recv() {
+                Ok(request) => batch.push(request),
+                Err(_) => match queue.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(request) => batch.push(request),
+                    Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+                },
+            }
+        }
+
+        let mut encoded = SequenceSet::new();
+        for request in &batch {
+            encoded.push(&request.sequence, &matrix);
+        }
+        let mut summary = SearchSummary::default();
+        let hits = sequence_db::batch_hits(db, &live, &encoded, options, &mut summary);
+        {
+            let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+            stats.queries += batch.len();
+            stats.launches += 1;
+            stats.largest_batch = stats.largest_batch.max(batch.len());
+            stats.alignments += summary.alignments;
+            stats.screened += summary.screened;
+        }
+        // A caller that gave up waiting has dropped its receiver; nothing to do
+        match hits {
+            Ok(hits) => for (request, mut hits) in batch.into_iter().zip(hits) {
+                options.limits.select(&mut hits, |hit| hit.score);
+                let _ = request.reply.send(Ok(hits));
+            },
+            Err(e) => for request in batch {
+                let _ = request.reply.send(Err(e.clone()));
+            },
+        }
+    }
+}
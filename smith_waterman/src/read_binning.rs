@@ -0,0 +1,161 @@
+// Read-length-aware ordering of each chunk before kernel launch
+// Work items in a SIMD group (warp/wavefront) run in lockstep, so a group
+// holding one 10 kb read and 31 short ones leaves most lanes idle while the long
+// read finishes. Sorting a chunk by length, or grouping it into length bins,
+// puts similar lengths side by side. Set READ_LENGTH_BINNING in .env:
+//   off     keep reads in input order (default)
+//   sort    stable sort by length within the chunk
+//   bins:N  N equal-width length bins, input order kept within a bin
+// Lane occupancy (useful lanes / issued lanes) is measured before and after
+// reordering and reported with the benchmark results, so the gain is visible
+// even with binning off; the counts are the run's (run_context::RunState)
+
+use crate::read_chunk::ReadChunk;
+use crate::run_context::with_state;
+use serde::{Deserialize, Serialize};
+
+// Lanes that run in lockstep on NVIDIA (AMD wavefronts are 32 or 64)
+pub const SIMD_GROUP_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinningPolicy {
+    #[default]
+    Off,
+    Sort,
+    Bins(usize),
+}
+
+impl BinningPolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "off" | "false" => Ok(BinningPolicy::Off),
+            "sort" => Ok(BinningPolicy::Sort),
+            other => match other.strip_prefix("bins:").map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n >= 2 => Ok(BinningPolicy::Bins(n)),
+                _ => Err(format!("Invalid READ_LENGTH_BINNING '{}': expected off, sort, or bins:N (N >= 2)", value)),
+            },
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("READ_LENGTH_BINNING") {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Ok(BinningPolicy::Off),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            BinningPolicy::Off => "off".to_string(),
+            BinningPolicy::Sort => "sort".to_string(),
+            BinningPolicy::Bins(n) => format!("bins:{}", n),
+        }
+    }
+}
+
+// Read indices of `chunk` in launch order under `policy`
+pub fn launch_order(chunk: &ReadChunk, policy: BinningPolicy) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..chunk.len()).collect();
+    match policy {
+        BinningPolicy::Off => {},
+        BinningPolicy::Sort => order.sort_by_key(|&i| chunk.read(i).len()),
+        BinningPolicy::Bins(bins) => {
+            let lengths = || chunk.iter().map(|r| r.len());
+            let (min, max) = (lengths().min().unwrap_or(0), lengths().max().unwrap_or(0));
+            let width = (max - min) / bins + 1;
+            order.sort_by_key(|&i| (chunk.read(i).len() - min) / width);
+        },
+    }
+    order
+}
+
+// (useful, issued) lanes when reads go to SIMD groups in `order`
+pub fn lane_usage(chunk: &ReadChunk, order: &[usize]) -> (u64, u64) {
+    let mut useful = 0u64;
+    let mut issued = 0u64;
+    for group in order.chunks(SIMD_GROUP_SIZE) {
+        let longest = group.iter().map(|&i| chunk.read(i).len()).max().unwrap_or(0);
+        useful += group.iter().map(|&i| chunk.read(i).len() as u64).sum::<u64>();
+        issued += (longest * SIMD_GROUP_SIZE) as u64;
+    }
+    (useful, issued)
+}
+
+// Copy `chunk` into `out` in launch order (names travel with their reads)
+// Returns false without touching `out` when the order is unchanged
+pub fn reorder_into(chunk: &ReadChunk, policy: BinningPolicy, out: &mut ReadChunk) -> bool {
+    let before = lane_usage(chunk, &(0..chunk.len()).collect::<Vec<_>>());
+    if policy == BinningPolicy::Off {
+        record(before, before);
+        return false;
+    }
+    let order = launch_order(chunk, policy);
+    record(before, lane_usage(chunk, &order));
+    out.clear();
+    for i in order {
+        out.push_from(chunk, i);
+    }
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BinningSummary {
+    pub policy: BinningPolicy,
+    pub chunks: u64,
+    // Useful / issued lanes in input order and in launch order
+    pub occupancy_input_order: f64,
+    pub occupancy_launch_order: f64,
+}
+
+impl BinningSummary {
+    pub fn print(&self) {
+        if self.chunks == 0 {
+            return;
+        }
+        println!("Read length binning ({}): SIMD lane occupancy {:.1}% in input order, {:.1}% at launch",
+                 self.policy.name(), self.occupancy_input_order * 100.0, self.occupancy_launch_order * 100.0);
+    }
+}
+
+#[derive(Default)]
+pub struct LaneUsage {
+    policy: BinningPolicy,
+    chunks: u64,
+    before: (u64, u64),
+    after: (u64, u64),
+}
+
+// Policy reported in the summary
+pub fn start(policy: BinningPolicy) {
+    with_state(|run| {
+        if let Ok(mut usage) = run.lanes.lock() {
+            *usage = LaneUsage { policy, ..LaneUsage::default() };
+        }
+    });
+}
+
+fn record(before: (u64, u64), after: (u64, u64)) {
+    with_state(|run| {
+        let Ok(mut usage) = run.lanes.lock() else { return };
+        usage.chunks += 1;
+        usage.before = (usage.before.0 + before.0, usage.before.1 + before.1);
+        usage.after = (usage.after.0 + after.0, usage.after.1 + after.1);
+    });
+}
+
+pub fn summary() -> BinningSummary {
+    with_state(|run| run.lanes.lock().map(|usage| usage.summary()).unwrap_or_default())
+}
+
+impl LaneUsage {
+    fn summary(&self) -> BinningSummary {
+        let ratio = |(useful, issued): (u64, u64)| if issued == 0 { 0.0 } else { useful as f64 / issued as f64 };
+        BinningSummary {
+            policy: self.policy,
+            chunks: self.chunks,
+            occupancy_input_order: ratio(self.before),
+            occupancy_launch_order: ratio(self.after),
+        }
+    }
+}
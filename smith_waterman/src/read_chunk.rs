@@ -0,0 +1,274 @@
+// Reusable byte arena holding one chunk of reads
+// Sequences are appended back to back, so the whole chunk is already the
+// contiguous buffer the GPU wants and no per-read String is allocated
+// Quality lines are only kept when something reads them (--qc, quality-window
+// trimming, a pipeline processor that asks); chunks made while keep_qualities is
+// on store them next to the bases, and the line parsers don't even copy them
+// out of the reader otherwise. The switch is the run's (run_context::RunState),
+// so one run's --qc doesn't make another's chunks carry qualities
+
+use crate::memory_profile::{MemoryBudget, Reservation, Site};
+use crate::run_context::with_state;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+// Whether chunks created from now on keep quality lines
+pub fn keep_qualities(enabled: bool) {
+    with_state(|run| run.keep_qualities.store(enabled, Ordering::SeqCst));
+}
+
+pub fn keeps_qualities() -> bool {
+    with_state(|run| run.keep_qualities.load(Ordering::SeqCst))
+}
+
+// What the chunker keeps of each read's header line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadNameMode {
+    #[default]
+    Discard,
+    Keep,
+    // 64-bit hash instead of the full name, to save memory on huge runs
+    Hash,
+}
+
+// Read name as stored in a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadName<'a> {
+    Name(&'a [u8]),
+    Hash(u64),
+}
+
+impl std::fmt::Display for ReadName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadName::Name(name) => write!(f, "{}", String::from_utf8_lossy(name)),
+            ReadName::Hash(hash) => write!(f, "h:{:016x}", hash),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ReadChunk {
+    bases: Vec<u8>,
+    // End offset of each read in `bases`
+    ends: Vec<usize>,
+    name_mode: ReadNameMode,
+    names: Vec<u8>,
+    name_ends: Vec<usize>,
+    name_hashes: Vec<u64>,
+    keeps_qualities: bool,
+    qualities: Vec<u8>,
+    quality_ends: Vec<usize>,
+    // What the buffers hold under --memory-profile strict, as of the last account()
+    budget: Reservation,
+}
+
+impl ReadChunk {
+    pub fn with_capacity(reads: usize) -> Self {
+        Self::with_names(reads, ReadNameMode::Discard)
+    }
+
+    pub fn with_names(reads: usize, name_mode: ReadNameMode) -> Self {
+        Self {
+            bases: Vec::new(),
+            ends: Vec::with_capacity(reads),
+            name_mode,
+            names: Vec::new(),
+            name_ends: Vec::new(),
+            name_hashes: Vec::new(),
+            keeps_qualities: keeps_qualities(),
+            qualities: Vec::new(),
+            quality_ends: Vec::new(),
+            budget: Reservation::new(Site::Chunks),
+        }
+    }
+
+    pub fn name_mode(&self) -> ReadNameMode {
+        self.name_mode
+    }
+
+    // Record the name from a '@'/'>' header line, per the chunk's name mode
+    // Only the first word is kept, as in SAM QNAME
+    pub fn push_name(&mut self, header: &[u8]) {
+        if self.name_mode == ReadNameMode::Discard {
+            return;
+        }
+        let header = trim_line_end(header);
+        let header = header.strip_prefix(b"@").or_else(|| header.strip_prefix(b">")).unwrap_or(header);
+        let name = header.split(|b| b.is_ascii_whitespace()).next().unwrap_or(header);
+        match self.name_mode {
+            ReadNameMode::Keep => {
+                self.names.extend_from_slice(name);
+                self.name_ends.push(self.names.len());
+            },
+            ReadNameMode::Hash => self.name_hashes.push(hash_name(name)),
+            ReadNameMode::Discard => {},
+        }
+    }
+
+    // Name of read `index`, if names are being retained
+    pub fn name(&self, index: usize) -> Option<ReadName<'_>> {
+        match self.name_mode {
+            ReadNameMode::Keep => {
+                let end = *self.name_ends.get(index)?;
+                let start = if index == 0 { 0 } else { self.name_ends[index - 1] };
+                Some(ReadName::Name(&self.names[start..end]))
+            },
+            ReadNameMode::Hash => self.name_hashes.get(index).map(|&h| ReadName::Hash(h)),
+            ReadNameMode::Discard => None,
+        }
+    }
+
+    // Append one read, dropping any trailing line terminator
+    pub fn push_read(&mut self, line: &[u8]) {
+        let line = trim_line_end(line);
+        self.bases.extend_from_slice(line);
+        self.ends.push(self.bases.len());
+    }
+
+    // Append one read split over several lines (multi-line FASTA)
+    pub fn push_read_lines<'a>(&mut self, lines: impl IntoIterator<Item = &'a [u8]>) {
+        for line in lines {
+            self.extend_open_read(line);
+        }
+        self.close_read();
+    }
+
+    // Add bases to the read being built; it becomes visible after close_read
+    pub fn extend_open_read(&mut self, line: &[u8]) {
+        self.bases.extend_from_slice(trim_line_end(line));
+    }
+
+    pub fn close_read(&mut self) {
+        self.ends.push(self.bases.len());
+    }
+
+    // Append read `index` of `other`, with its name if both chunks keep names
+    pub fn push_from(&mut self, other: &ReadChunk, index: usize) {
+        self.push_with_bases(other, index, other.read(index));
+    }
+
+    // Record the quality line of the last read pushed, if this chunk keeps them
+    pub fn push_quality(&mut self, line: &[u8]) {
+        if self.keeps_qualities {
+            self.qualities.extend_from_slice(trim_line_end(line));
+            self.quality_ends.push(self.qualities.len());
+        }
+    }
+
+    pub fn keeps_qualities(&self) -> bool {
+        self.keeps_qualities
+    }
+
+    // Quality line (Phred+33) of read `index`; None for FASTA and when qualities aren't kept
+    pub fn quality(&self, index: usize) -> Option<&[u8]> {
+        let end = *self.quality_ends.get(index)?;
+        let start = if index == 0 { 0 } else { self.quality_ends[index - 1] };
+        Some(&self.qualities[start..end])
+    }
+
+    // Quality lines of every read concatenated, lined up with bases(); None
+    // unless every read has one as long as its sequence
+    pub fn qualities(&self) -> Option<&[u8]> {
+        (self.keeps_qualities && !self.is_empty() && self.quality_ends == self.ends).then_some(&self.qualities[..])
+    }
+
+    // Append `bases` under the name (and quality) of read `index` of `other`
+    pub fn push_with_bases(&mut self, other: &ReadChunk, index: usize, bases: &[u8]) {
+        self.push_name_of(other, index);
+        if let Some(quality) = other.quality(index) {
+            self.push_quality(quality);
+        }
+        self.bases.extend_from_slice(bases);
+        self.ends.push(self.bases.len());
+    }
+
+    // Append part of read `index` of `other`, with its name and the same part of its quality
+    pub fn push_range(&mut self, other: &ReadChunk, index: usize, range: std::ops::Range<usize>) {
+        self.push_name_of(other, index);
+        if let Some(quality) = other.quality(index) {
+            self.push_quality(quality.get(range.clone()).unwrap_or(quality));
+        }
+        self.bases.extend_from_slice(&other.read(index)[range]);
+        self.ends.push(self.bases.len());
+    }
+
+    fn push_name_of(&mut self, other: &ReadChunk, index: usize) {
+        match (self.name_mode, other.name(index)) {
+            (ReadNameMode::Keep, Some(ReadName::Name(name))) => {
+                self.names.extend_from_slice(name);
+                self.name_ends.push(self.names.len());
+            },
+            (ReadNameMode::Hash, Some(ReadName::Name(name))) => self.name_hashes.push(hash_name(name)),
+            (ReadNameMode::Hash, Some(ReadName::Hash(hash))) => self.name_hashes.push(hash),
+            _ => {},
+        }
+    }
+
+    // Number of reads in the chunk
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    // All reads concatenated (what `chunk.concat()` used to build)
+    pub fn bases(&self) -> &[u8] {
+        &self.bases
+    }
+
+    pub fn read(&self, index: usize) -> &[u8] {
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+        &self.bases[start..self.ends[index]]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.len()).map(move |i| self.read(i))
+    }
+
+    // Bytes allocated by the chunk's buffers, filled or not
+    pub fn allocated_bytes(&self) -> usize {
+        let offsets = self.ends.capacity() + self.name_ends.capacity() + self.name_hashes.capacity() + self.quality_ends.capacity();
+        self.bases.capacity() + self.names.capacity() + self.qualities.capacity() + offsets * 8
+    }
+
+    // Hold the chunk's buffers against a run's budget from the next account()
+    pub fn hold_against(&mut self, memory: &Arc<MemoryBudget>) {
+        self.budget.hold_against(memory);
+    }
+
+    // Bring the chunk's reservation up to date with its buffers; Err when that
+    // goes over the --memory-profile strict budget it is held against
+    pub fn account(&mut self) -> Result<(), String> {
+        let bytes = self.allocated_bytes();
+        self.budget.resize(bytes)
+    }
+
+    // Empty the chunk but keep its allocations for the next fill
+    pub fn clear(&mut self) {
+        self.bases.clear();
+        self.ends.clear();
+        self.names.clear();
+        self.name_ends.clear();
+        self.name_hashes.clear();
+        self.qualities.clear();
+        self.quality_ends.clear();
+    }
+}
+
+pub fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// FNV-1a: stable across Rust versions, so hashed names match between runs
+pub fn hash_name(name: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in name {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
@@ -0,0 +1,56 @@
+// Read-group and sample metadata of a WGS run (--sample, --library, --platform-unit)
+// Carried into the outputs GATK-style pipelines look at: an @RG header line and
+// an RG tag on every record of a SAM --read-output, sample/library/platform_unit
+// columns in a TSV --read-output, and manifest.json. The read group ID is the
+// platform unit when there is one (flowcell.lane, as GATK recommends), and
+// otherwise the sample and library joined with '.'
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadGroup {
+    pub id: String,
+    pub sample: String,
+    pub library: Option<String>,
+    pub platform_unit: Option<String>,
+}
+
+impl ReadGroup {
+    pub fn new(sample: &str, library: Option<&str>, platform_unit: Option<&str>) -> Result<Self, String> {
+        for (flag, value) in [("--sample", Some(sample)), ("--library", library), ("--platform-unit", platform_unit)] {
+            let Some(value) = value else { continue };
+            // Header fields are tab-separated and GATK tools split on whitespace
+            if value.is_empty() || value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err(format!("Invalid {} '{}': expected a non-empty value without whitespace", flag, value));
+            }
+        }
+        let id = match (platform_unit, library) {
+            (Some(unit), _) => unit.to_string(),
+            (None, Some(library)) => format!("{}.{}", sample, library),
+            (None, None) => sample.to_string(),
+        };
+        Ok(Self {
+            id,
+            sample: sample.to_string(),
+            library: library.map(str::to_string),
+            platform_unit: platform_unit.map(str::to_string),
+        })
+    }
+
+    // @RG header line, without LB/PU when they weren't given
+    pub fn sam_header(&self) -> String {
+        let mut line = format!("@RG\tID:{}\tSM:{}", self.id, self.sample);
+        if let Some(library) = &self.library {
+            line.push_str(&format!("\tLB:{}", library));
+        }
+        if let Some(unit) = &self.platform_unit {
+            line.push_str(&format!("\tPU:{}", unit));
+        }
+        line
+    }
+
+    // sample, library and platform unit as TSV fields (empty when not given)
+    pub fn tsv_fields(&self) -> String {
+        format!("{}\t{}\t{}", self.sample, self.library.as_deref().unwrap_or(""), self.platform_unit.as_deref().unwrap_or(""))
+    }
+}
@@ -0,0 +1,178 @@
+// Per-read result records keyed by read name
+// The GPU scores whole chunks, so each read carries the score of the chunk it
+// was aligned in; the name lets downstream tools join results back to reads
+// With a read group (read_group.rs), SAM output gets an @RG line and RG tags,
+// and the other formats the sample, library and platform unit of every read
+// In long-read mode (long_reads.rs) each chunk is one read, scored on its own:
+// records also carry the read's tile count and CIGAR, as tiles and cigar
+// columns and ZT/ZG tags in SAM
+// Records go to an OutputSink (output_sink.rs) picked by --output-format or
+// the path's extension; ReadResultWriter is what the pipeline writes through
+
+use crate::long_reads::ReadScore;
+use crate::memory_profile::{MemoryBudget, Reservation, Site};
+use crate::output_sink::{self, OutputSink};
+use crate::read_chunk::{ReadChunk, ReadName, ReadNameMode};
+use crate::read_group::ReadGroup;
+use crate::wgs_report::FileReport;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadOutputFormat {
+    Tsv,
+    Jsonl,
+    Sam,
+    Parquet,
+    // Indexed store for `lookup` (read_store.rs)
+    Store,
+    // Records are made and dropped: the per-read path without the writing
+    Null,
+}
+
+impl ReadOutputFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "tsv" => Ok(ReadOutputFormat::Tsv),
+            "jsonl" => Ok(ReadOutputFormat::Jsonl),
+            "sam" => Ok(ReadOutputFormat::Sam),
+            "parquet" => Ok(ReadOutputFormat::Parquet),
+            "rskv" => Ok(ReadOutputFormat::Store),
+            "null" => Ok(ReadOutputFormat::Null),
+            other => Err(format!("Unknown output format '{}' (expected tsv, jsonl, sam, parquet, rskv or null)", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReadOutputFormat::Tsv => "tsv",
+            ReadOutputFormat::Jsonl => "jsonl",
+            ReadOutputFormat::Sam => "sam",
+            ReadOutputFormat::Parquet => "parquet",
+            ReadOutputFormat::Store => "rskv",
+            ReadOutputFormat::Null => "null",
+        }
+    }
+
+    // Picked from the file extension, TSV unless it's one of the others'
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".sam") {
+            ReadOutputFormat::Sam
+        } else if path.ends_with(".jsonl") {
+            ReadOutputFormat::Jsonl
+        } else if path.ends_with(".parquet") {
+            ReadOutputFormat::Parquet
+        } else if crate::read_store::is_store_path(path) {
+            ReadOutputFormat::Store
+        } else {
+            ReadOutputFormat::Tsv
+        }
+    }
+}
+
+// Where per-read results go, in what format, and how names are kept while reading
+#[derive(Debug, Clone)]
+pub struct ReadOutputOptions {
+    // Empty for the null format
+    pub path: String,
+    pub format: ReadOutputFormat,
+    pub name_mode: ReadNameMode,
+}
+
+impl ReadOutputOptions {
+    // `format` None picks it from the path
+    pub fn new(path: &str, format: Option<ReadOutputFormat>, hash_names: bool) -> Self {
+        Self {
+            path: path.to_string(),
+            format: format.unwrap_or_else(|| ReadOutputFormat::from_path(path)),
+            name_mode: if hash_names { ReadNameMode::Hash } else { ReadNameMode::Keep },
+        }
+    }
+}
+
+// One read's result
+#[derive(Debug, Clone, Copy)]
+pub struct ReadResult<'a> {
+    pub name: Option<ReadName<'a>>,
+    pub seq: &'a [u8],
+    pub file_index: usize,
+    pub chunk_index: usize,
+    pub chunk_score: i32,
+    // Long-read mode: the read's own score, tiles and CIGAR
+    pub read_score: Option<&'a ReadScore>,
+}
+
+impl ReadResult<'_> {
+    pub fn name_string(&self) -> String {
+        self.name.map(|n| n.to_string()).unwrap_or_else(|| "*".to_string())
+    }
+}
+
+// Results for every read of an aligned chunk
+pub fn chunk_results(chunk: &ReadChunk, file_index: usize, chunk_index: usize, chunk_score: i32) -> impl Iterator<Item = ReadResult<'_>> {
+    (0..chunk.len()).map(move |i| ReadResult {
+        name: chunk.name(i),
+        seq: chunk.read(i),
+        file_index,
+        chunk_index,
+        chunk_score,
+        read_score: None,
+    })
+}
+
+pub struct ReadResultWriter {
+    sink: Box<dyn OutputSink>,
+    rows: usize,
+    // What the sink buffers, under --memory-profile strict
+    budget: Reservation,
+}
+
+impl ReadResultWriter {
+    pub fn create(options: &ReadOutputOptions, read_group: Option<&ReadGroup>, long_reads: bool) -> Result<Self, String> {
+        let sink = output_sink::create(options, read_group, long_reads)?;
+        Ok(Self { sink, rows: 0, budget: Reservation::new(Site::OutputBuffers) })
+    }
+
+    pub fn write(&mut self, result: &ReadResult<'_>) -> Result<(), String> {
+        self.rows += 1;
+        self.sink.write_read_result(result)
+    }
+
+    pub fn write_chunk(&mut self, chunk: &ReadChunk, file_index: usize, chunk_index: usize, chunk_score: i32) -> Result<(), String> {
+        for result in chunk_results(chunk, file_index, chunk_index, chunk_score) {
+            self.write(&result)?;
+        }
+        self.account()
+    }
+
+    // The one read of a long-read chunk, with its own score, tiles and CIGAR
+    pub fn write_long_read(&mut self, chunk: &ReadChunk, file_index: usize, chunk_index: usize, read: &ReadScore) -> Result<(), String> {
+        for result in chunk_results(chunk, file_index, chunk_index, read.score) {
+            self.write(&ReadResult { read_score: Some(read), ..result })?;
+        }
+        self.account()
+    }
+
+    // Hold the sink's buffers against a run's budget from the next chunk written
+    pub fn hold_against(&mut self, memory: &Arc<MemoryBudget>) {
+        self.budget.hold_against(memory);
+    }
+
+    fn account(&mut self) -> Result<(), String> {
+        let bytes = self.sink.buffered_bytes();
+        self.budget.resize(bytes)
+    }
+
+    // After each file, whether it completed, failed or came from the checkpoint
+    pub fn write_file_summary(&mut self, file: &FileReport) -> Result<(), String> {
+        self.sink.write_file_summary(file)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn finish(self) -> Result<usize, String> {
+        self.sink.finish()?;
+        Ok(self.rows)
+    }
+}
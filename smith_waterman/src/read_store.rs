@@ -0,0 +1,264 @@
+// Per-read results in an indexed store (--read-output with a .rskv path), so a
+// read's result can be looked up by name without scanning a billion-row TSV
+// One file: a header with the value columns, the records (name and the TSV
+// columns after it) in the order they were written, then an index of
+// (FNV-1a hash of the name, record offset) sorted by hash, then a footer
+// pointing at the index. A lookup is a binary search over the index and one
+// read per match, so it stays a few dozen reads however large the store is
+// The index is kept in memory while writing until INDEX_RUN_ENTRIES entries,
+// then sorted and spilled to a run file next to the store; the runs are merged
+// into the store when it is finished, so memory stays bounded on huge runs
+
+use crate::read_chunk::hash_name;
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+const MAGIC: &[u8; 8] = b"RSKVSTO1";
+const FOOTER_MAGIC: &[u8; 8] = b"RSKVEND1";
+// Index offset, record count, magic
+const FOOTER_BYTES: u64 = 24;
+const ENTRY_BYTES: u64 = 16;
+// 16M entries (256 MB) of index per run
+const INDEX_RUN_ENTRIES: usize = 16 * 1024 * 1024;
+
+pub const EXTENSION: &str = ".rskv";
+
+pub fn is_store_path(path: &str) -> bool {
+    path.ends_with(EXTENSION)
+}
+
+fn write_error(path: &str, e: std::io::Error) -> String {
+    format!("Failed to write read store {}: {}", path, e)
+}
+
+pub struct ReadStoreWriter {
+    path: String,
+    out: BufWriter<File>,
+    offset: u64,
+    records: u64,
+    index: Vec<(u64, u64)>,
+    runs: Vec<String>,
+}
+
+impl ReadStoreWriter {
+    // `columns`: names of the value columns, tab-separated; `hashed_names` when
+    // the keys are h:<hex> hashes (--hash-read-names), so lookups can hash for the caller
+    pub fn create(path: &str, columns: &str, hashed_names: bool) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create read store {}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        let mut header = MAGIC.to_vec();
+        header.push(hashed_names as u8);
+        header.extend_from_slice(&(columns.len() as u32).to_le_bytes());
+        header.extend_from_slice(columns.as_bytes());
+        out.write_all(&header).map_err(|e| write_error(path, e))?;
+        Ok(Self { path: path.to_string(), out, offset: header.len() as u64, records: 0, index: Vec::new(), runs: Vec::new() })
+    }
+
+    pub fn put(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.index.push((hash_name(name.as_bytes()), self.offset));
+        let mut record = Vec::with_capacity(8 + name.len() + value.len());
+        record.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        record.extend_from_slice(value.as_bytes());
+        self.out.write_all(&record).map_err(|e| write_error(&self.path, e))?;
+        self.offset += record.len() as u64;
+        self.records += 1;
+        if self.index.len() >= INDEX_RUN_ENTRIES {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    // Index entries held until the next spill
+    pub fn buffered_bytes(&self) -> usize {
+        self.index.capacity() * ENTRY_BYTES as usize
+    }
+
+    fn spill(&mut self) -> Result<(), String> {
+        self.index.sort_unstable();
+        let run = format!("{}.run{}", self.path, self.runs.len());
+        let file = File::create(&run).map_err(|e| write_error(&run, e))?;
+        let mut out = BufWriter::new(file);
+        for (hash, offset) in self.index.drain(..) {
+            out.write_all(&hash.to_le_bytes()).and_then(|_| out.write_all(&offset.to_le_bytes()))
+                .map_err(|e| write_error(&run, e))?;
+        }
+        out.flush().map_err(|e| write_error(&run, e))?;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    // Writes the index and footer; returns the records stored
+    pub fn finish(mut self) -> Result<u64, String> {
+        let index_offset = self.offset;
+        self.index.sort_unstable();
+        if self.runs.is_empty() {
+            for (hash, offset) in std::mem::take(&mut self.index) {
+                self.write_entry(hash, offset)?;
+            }
+        } else {
+            self.spill()?;
+            self.merge_runs()?;
+        }
+        let mut footer = index_offset.to_le_bytes().to_vec();
+        footer.extend_from_slice(&self.records.to_le_bytes());
+        footer.extend_from_slice(FOOTER_MAGIC);
+        self.out.write_all(&footer).and_then(|_| self.out.flush())
+            .map_err(|e| write_error(&self.path, e))?;
+        Ok(self.records)
+    }
+
+    fn write_entry(&mut self, hash: u64, offset: u64) -> Result<(), String> {
+        self.out.write_all(&hash.to_le_bytes()).and_then(|_| self.out.write_all(&offset.to_le_bytes()))
+            .map_err(|e| write_error(&self.path, e))
+    }
+
+    // k-way merge of the sorted runs into the index, removing them as it goes
+    fn merge_runs(&mut self) -> Result<(), String> {
+        let mut readers = Vec::new();
+        for run in &self.runs {
+            let file = File::open(run).map_err(|e| format!("Failed to read index run {}: {}", run, e))?;
+            readers.push(BufReader::new(file));
+        }
+        let mut heap = BinaryHeap::new();
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(entry) = read_entry(reader)? {
+                heap.push(Reverse((entry, i)));
+            }
+        }
+        while let Some(Reverse(((hash, offset), i))) = heap.pop() {
+            self.write_entry(hash, offset)?;
+            if let Some(entry) = read_entry(&mut readers[i])? {
+                heap.push(Reverse((entry, i)));
+            }
+        }
+        for run in self.runs.drain(..) {
+            let _ = std::fs::remove_file(run);
+        }
+        Ok(())
+    }
+}
+
+fn read_entry<R: Read>(reader: &mut R) -> Result<Option<(u64, u64)>, String> {
+    let mut entry = [0u8; ENTRY_BYTES as usize];
+    match reader.read_exact(&mut entry) {
+        Ok(()) => Ok(Some(entry_fields(&entry))),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(format!("Failed to read index run: {}", e)),
+    }
+}
+
+fn entry_fields(entry: &[u8; ENTRY_BYTES as usize]) -> (u64, u64) {
+    (u64::from_le_bytes(entry[..8].try_into().unwrap()), u64::from_le_bytes(entry[8..].try_into().unwrap()))
+}
+
+pub struct ReadStore {
+    path: String,
+    file: File,
+    columns: String,
+    hashed_names: bool,
+    index_offset: u64,
+    records: u64,
+}
+
+impl ReadStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let mut file = File::open(path)
+            .map_err(|e| format!("Failed to open read store {}: {}", path, e))?;
+        let invalid = |what: &str| format!("{} is not a read store ({})", path, what);
+        let mut header = [0u8; 13];
+        file.read_exact(&mut header).map_err(|_| invalid("too short"))?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        let hashed_names = header[8] != 0;
+        let mut columns = vec![0u8; u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize];
+        file.read_exact(&mut columns).map_err(|_| invalid("truncated header"))?;
+
+        let size = file.metadata().map_err(|e| format!("Failed to read {}: {}", path, e))?.len();
+        let mut footer = [0u8; FOOTER_BYTES as usize];
+        file.seek(SeekFrom::Start(size.saturating_sub(FOOTER_BYTES)))
+            .and_then(|_| file.read_exact(&mut footer))
+            .map_err(|_| invalid("no footer"))?;
+        if &footer[16..] != FOOTER_MAGIC {
+            // The run that wrote it didn't finish
+            return Err(invalid("no index; the run writing it may not have finished"));
+        }
+        let index_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let records = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+        if index_offset + records * ENTRY_BYTES + FOOTER_BYTES != size {
+            return Err(invalid("index size doesn't match"));
+        }
+        Ok(Self { path: path.to_string(), file, columns: String::from_utf8_lossy(&columns).into_owned(), hashed_names, index_offset, records })
+    }
+
+    // Value column names, tab-separated
+    pub fn columns(&self) -> &str {
+        &self.columns
+    }
+
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), String> {
+        self.file.seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(buf))
+            .map_err(|e| format!("Failed to read {}: {}", self.path, e))
+    }
+
+    fn entry(&mut self, i: u64) -> Result<(u64, u64), String> {
+        let mut entry = [0u8; ENTRY_BYTES as usize];
+        self.read_at(self.index_offset + i * ENTRY_BYTES, &mut entry)?;
+        Ok(entry_fields(&entry))
+    }
+
+    fn record(&mut self, offset: u64) -> Result<(Vec<u8>, String), String> {
+        let mut len = [0u8; 4];
+        self.read_at(offset, &mut len)?;
+        let mut key = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.read_at(offset + 4, &mut key)?;
+        let value_at = offset + 4 + key.len() as u64;
+        self.read_at(value_at, &mut len)?;
+        let mut value = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.read_at(value_at + 4, &mut value)?;
+        Ok((key, String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    // Values stored under `name` (a read name can repeat across files); in a store
+    // of hashed names, a plain name is hashed the way --hash-read-names does
+    pub fn get(&mut self, name: &str) -> Result<Vec<String>, String> {
+        let key = if self.hashed_names && !name.starts_with("h:") {
+            crate::read_chunk::ReadName::Hash(hash_name(name.as_bytes())).to_string()
+        } else {
+            name.to_string()
+        };
+        let hash = hash_name(key.as_bytes());
+        // First entry with this hash
+        let (mut low, mut high) = (0, self.records);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.entry(mid)?.0 < hash {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let mut values = Vec::new();
+        for i in low..self.records {
+            let (entry_hash, offset) = self.entry(i)?;
+            if entry_hash != hash {
+                break;
+            }
+            let (stored, value) = self.record(offset)?;
+            if stored == key.as_bytes() {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+}
@@ -0,0 +1,171 @@
+// --trim-head, --trim-tail and --trim-sliding-window: Trimmomatic-style
+// trimming of short reads as chunks come off the reader, so runs match the
+// usual preprocessing without a separate pass over the files. Applied after
+// sanitizing and long-read trimming, in this order:
+// - head: the first N bases (HEADCROP)
+// - tail: the last N bases
+// - sliding window W:Q (SLIDINGWINDOW): scanning from the 5' end, the first
+//   window of W bases whose mean quality is below Q marks the cut; the bases
+//   of that window before its first one below Q are kept. Qualities are
+//   Phred+33, so reads without a quality line (FASTA) aren't cut here
+// Reads with no bases left are dropped
+
+use crate::read_chunk::ReadChunk;
+
+const PHRED_OFFSET: u8 = 33;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlidingWindow {
+    pub size: usize,
+    pub quality: u8,
+}
+
+impl SlidingWindow {
+    // "W:Q", e.g. 4:20
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid --trim-sliding-window '{}': expected window:quality, e.g. 4:20", value);
+        let (size, quality) = value.split_once(':').ok_or_else(invalid)?;
+        let size: usize = size.trim().parse().map_err(|_| invalid())?;
+        let quality: u8 = quality.trim().parse().map_err(|_| invalid())?;
+        if size == 0 || quality > 93 {
+            return Err(format!("Invalid --trim-sliding-window '{}': the window must be at least 1 base and the quality 0 to 93", value));
+        }
+        Ok(Self { size, quality })
+    }
+
+    // Bases of `quality` kept
+    fn keep(&self, quality: &[u8]) -> usize {
+        let size = self.size.min(quality.len());
+        if size == 0 {
+            return 0;
+        }
+        let required = (self.quality as u64 + PHRED_OFFSET as u64) * size as u64;
+        let mut total: u64 = quality[..size].iter().map(|&q| q as u64).sum();
+        for start in 0..=quality.len() - size {
+            if start > 0 {
+                total += quality[start + size - 1] as u64;
+                total -= quality[start - 1] as u64;
+            }
+            if total < required {
+                let good = quality[start..start + size].iter()
+                    .take_while(|&&q| q >= self.quality + PHRED_OFFSET)
+                    .count();
+                return start + good;
+            }
+        }
+        quality.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReadTrimOptions {
+    pub head: usize,
+    pub tail: usize,
+    pub window: Option<SlidingWindow>,
+}
+
+impl ReadTrimOptions {
+    // None when nothing is trimmed
+    pub fn new(head: usize, tail: usize, window: Option<&str>) -> Result<Option<Self>, String> {
+        let window = window.map(SlidingWindow::parse).transpose()?;
+        Ok((head > 0 || tail > 0 || window.is_some()).then_some(Self { head, tail, window }))
+    }
+
+    // What is trimmed, for logs and the checkpoint's run parameters
+    pub fn name(&self) -> String {
+        let mut steps = Vec::new();
+        if self.head > 0 {
+            steps.push(format!("head {}", self.head));
+        }
+        if self.tail > 0 {
+            steps.push(format!("tail {}", self.tail));
+        }
+        if let Some(window) = self.window {
+            steps.push(format!("sliding window {}:{}", window.size, window.quality));
+        }
+        steps.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReadTrimStats {
+    pub reads: usize,
+    pub trimmed_reads: usize,
+    pub dropped_reads: usize,
+    pub head_bases: usize,
+    pub tail_bases: usize,
+    pub quality_reads: usize,
+    pub quality_bases: usize,
+    // Sliding window on, but the read had no qualities
+    pub unqualified_reads: usize,
+}
+
+impl ReadTrimStats {
+    pub fn add(&mut self, other: &ReadTrimStats) {
+        self.reads += other.reads;
+        self.trimmed_reads += other.trimmed_reads;
+        self.dropped_reads += other.dropped_reads;
+        self.head_bases += other.head_bases;
+        self.tail_bases += other.tail_bases;
+        self.quality_reads += other.quality_reads;
+        self.quality_bases += other.quality_bases;
+        self.unqualified_reads += other.unqualified_reads;
+    }
+
+    pub fn summary(&self) -> String {
+        let mut line = format!("{} reads: {} trimmed, {} dropped; removed {} head bases, {} tail bases, {} low-quality bases ({} reads)",
+                               self.reads, self.trimmed_reads, self.dropped_reads, self.head_bases, self.tail_bases,
+                               self.quality_bases, self.quality_reads);
+        if self.unqualified_reads > 0 {
+            line.push_str(&format!(", {} reads without qualities", self.unqualified_reads));
+        }
+        line
+    }
+}
+
+pub struct ReadTrimmer {
+    options: ReadTrimOptions,
+}
+
+impl ReadTrimmer {
+    pub fn new(options: ReadTrimOptions) -> Self {
+        Self { options }
+    }
+
+    // Copy the trimmed reads of `chunk` that have bases left into `out`,
+    // adding what was removed to `stats`
+    pub fn trim_into(&self, chunk: &ReadChunk, out: &mut ReadChunk, stats: &mut ReadTrimStats) {
+        out.clear();
+        for i in 0..chunk.len() {
+            let length = chunk.read(i).len();
+            stats.reads += 1;
+            let start = self.options.head.min(length);
+            let mut end = length.saturating_sub(self.options.tail).max(start);
+            stats.head_bases += start;
+            stats.tail_bases += length - end;
+
+            if let Some(window) = self.options.window {
+                match chunk.quality(i).filter(|quality| quality.len() == length) {
+                    Some(quality) => {
+                        let kept = window.keep(&quality[start..end]);
+                        if start + kept < end {
+                            stats.quality_reads += 1;
+                            stats.quality_bases += end - start - kept;
+                            end = start + kept;
+                        }
+                    },
+                    None => stats.unqualified_reads += 1,
+                }
+            }
+
+            if (start, end) != (0, length) {
+                stats.trimmed_reads += 1;
+            }
+            if start == end {
+                stats.dropped_reads += 1;
+                continue;
+            }
+            out.push_range(chunk, i, start..end);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// Local cache of standard reference genomes (reference fetch / --reference NAME)
+// `reference fetch GRCh38` downloads the catalog's FASTA (through curl, like
+// remote inputs), checks its MD5 against the published checksum (or --md5),
+// decompresses it and writes the .fai index next to it:
+//   <cache dir>/GRCh38/GRCh38.fa
+//   <cache dir>/GRCh38/GRCh38.fa.fai
+//   <cache dir>/GRCh38/reference.json  where it came from, its MD5, when
+// --reference GRCh38 then resolves to the cached FASTA. The cache directory is
+// REFERENCE_CACHE_DIR, by default rustseq under the user cache directory
+// (~/.cache/rustseq)
+
+use crate::warnings::{self, Category};
+use md5::{Digest, Md5};
+use noodles::fasta;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub struct CatalogEntry {
+    pub name: &'static str,
+    // Other names --reference accepts, compared case-insensitively
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    pub url: &'static str,
+    // Checksum list published next to the download ("<md5>  <file name>" lines)
+    pub md5_url: Option<&'static str>,
+}
+
+pub const CATALOG: &[CatalogEntry] = &[
+    CatalogEntry {
+        name: "GRCh38",
+        aliases: &["hg38"],
+        description: "GRCh38 no-alt analysis set (NCBI), UCSC contig names",
+        url: "https://ftp.ncbi.nlm.nih.gov/genomes/all/GCA/000/001/405/GCA_000001405.15_GRCh38/seqs_for_alignment_pipelines.ucsc_ids/GCA_000001405.15_GRCh38_no_alt_analysis_set.fna.gz",
+        md5_url: Some("https://ftp.ncbi.nlm.nih.gov/genomes/all/GCA/000/001/405/GCA_000001405.15_GRCh38/seqs_for_alignment_pipelines.ucsc_ids/md5checksums.txt"),
+    },
+    CatalogEntry {
+        name: "GRCh37",
+        aliases: &["hs37d5", "b37"],
+        description: "GRCh37 with decoys (1000 Genomes hs37d5)",
+        url: "https://ftp.1000genomes.ebi.ac.uk/vol1/ftp/technical/reference/phase2_reference_assembly_sequence/hs37d5.fa.gz",
+        md5_url: None,
+    },
+    CatalogEntry {
+        name: "CHM13",
+        aliases: &["T2T", "T2T-CHM13", "chm13v2.0"],
+        description: "T2T-CHM13 v2.0 analysis set",
+        url: "https://s3-us-west-2.amazonaws.com/human-pangenomics/T2T/CHM13/assemblies/analysis_set/chm13v2.0.fa.gz",
+        md5_url: None,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static CatalogEntry> {
+    CATALOG.iter().find(|entry| {
+        entry.name.eq_ignore_ascii_case(name) || entry.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+    })
+}
+
+// ~/.cache/rustseq, or XDG_CACHE_HOME/rustseq when that is set
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var("XDG_CACHE_HOME").ok()
+        .map(PathBuf::from)
+        .or_else(|| std::env::var("HOME").ok().map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| std::env::var("LOCALAPPDATA").ok().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rustseq")
+}
+
+// What reference.json records about a cached reference
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedReference {
+    pub name: String,
+    pub url: String,
+    // MD5 of the downloaded (compressed) file
+    pub md5: String,
+    // Whether it matched a published checksum or --md5, rather than only being recorded
+    pub md5_verified: bool,
+    pub sequences: usize,
+    pub bases: u64,
+    pub fetched: chrono::DateTime<chrono::Utc>,
+}
+
+fn fasta_path(cache_dir: &Path, entry: &CatalogEntry) -> PathBuf {
+    cache_dir.join(entry.name).join(format!("{}.fa", entry.name))
+}
+
+fn index_path(fasta: &Path) -> PathBuf {
+    let mut path = fasta.as_os_str().to_owned();
+    path.push(".fai");
+    PathBuf::from(path)
+}
+
+fn record_path(cache_dir: &Path, entry: &CatalogEntry) -> PathBuf {
+    cache_dir.join(entry.name).join("reference.json")
+}
+
+// The cache record of `entry`, if it is fully fetched
+pub fn cached(cache_dir: &Path, entry: &CatalogEntry) -> Option<CachedReference> {
+    let fasta = fasta_path(cache_dir, entry);
+    if !fasta.exists() || !index_path(&fasta).exists() {
+        return None;
+    }
+    let json = std::fs::read_to_string(record_path(cache_dir, entry)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+// --reference value to a FASTA path: catalog names resolve to the cached copy,
+// anything else is taken as a path
+pub fn resolve(reference: &str, cache_dir: &Path) -> Result<String, String> {
+    if Path::new(reference).exists() {
+        return Ok(reference.to_string());
+    }
+    let Some(entry) = lookup(reference) else {
+        return Ok(reference.to_string());
+    };
+    if cached(cache_dir, entry).is_none() {
+        return Err(format!("Reference {} isn't in the cache at {}; fetch it with `reference fetch {} --dir {}`",
+                           entry.name, cache_dir.display(), entry.name, cache_dir.display()));
+    }
+    Ok(fasta_path(cache_dir, entry).display().to_string())
+}
+
+// Download, check, decompress and index `entry` into `cache_dir`; a cached
+// copy is kept unless `force`
+pub fn fetch(entry: &CatalogEntry, cache_dir: &Path, expected_md5: Option<&str>, force: bool) -> Result<CachedReference, String> {
+    if !force {
+        if let Some(reference) = cached(cache_dir, entry) {
+            println!("{} is already cached in {}", entry.name, cache_dir.join(entry.name).display());
+            return Ok(reference);
+        }
+    }
+    let dir = cache_dir.join(entry.name);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let download = dir.join("download.gz");
+    let partial = dir.join("download.gz.part");
+
+    println!("Downloading {} from {}", entry.name, entry.url);
+    let status = Command::new("curl")
+        .args(["-fL", "--retry", "3", "--progress-bar", "-o"])
+        .arg(&partial)
+        .arg(entry.url)
+        .status()
+        .map_err(|e| format!("Failed to start curl: {}", e))?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!("Download of {} failed (curl exited with {})", entry.url, status));
+    }
+    std::fs::rename(&partial, &download)
+        .map_err(|e| format!("Failed to move {} into place: {}", partial.display(), e))?;
+
+    let md5 = file_md5(&download)?;
+    let expected = match expected_md5 {
+        Some(md5) => Some(md5.trim().to_ascii_lowercase()),
+        None => published_md5(entry),
+    };
+    let md5_verified = match &expected {
+        Some(expected) if *expected != md5 => {
+            let _ = std::fs::remove_file(&download);
+            return Err(format!("Checksum mismatch for {}: MD5 {} but expected {}; the download was removed", entry.name, md5, expected));
+        },
+        Some(_) => {
+            println!("MD5 {} verified", md5);
+            true
+        },
+        None => {
+            println!("No published checksum for {}; recording MD5 {} (pass --md5 to check it)", entry.name, md5);
+            false
+        },
+    };
+
+    let fasta = fasta_path(cache_dir, entry);
+    println!("Decompressing to {}", fasta.display());
+    decompress(&download, &fasta)?;
+    let _ = std::fs::remove_file(&download);
+
+    println!("Indexing {}", fasta.display());
+    let index = fasta::fs::index(&fasta)
+        .map_err(|e| format!("Failed to index {}: {}", fasta.display(), e))?;
+    fasta::fai::fs::write(index_path(&fasta), &index)
+        .map_err(|e| format!("Failed to write {}: {}", index_path(&fasta).display(), e))?;
+    let records: &[fasta::fai::Record] = index.as_ref();
+
+    let reference = CachedReference {
+        name: entry.name.to_string(),
+        url: entry.url.to_string(),
+        md5,
+        md5_verified,
+        sequences: records.len(),
+        bases: records.iter().map(|r| r.length()).sum(),
+        fetched: chrono::Utc::now(),
+    };
+    let json = serde_json::to_string_pretty(&reference)
+        .map_err(|e| format!("Failed to serialize reference record: {}", e))?;
+    std::fs::write(record_path(cache_dir, entry), json)
+        .map_err(|e| format!("Failed to write {}: {}", record_path(cache_dir, entry).display(), e))?;
+    Ok(reference)
+}
+
+fn file_md5(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// The download's line of the published checksum list, None if there is none
+// or it can't be fetched
+fn published_md5(entry: &CatalogEntry) -> Option<String> {
+    let output = Command::new("curl")
+        .args(["-fsL", entry.md5_url?])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        warnings::warn(Category::Input, format!("failed to fetch the published checksums of {}", entry.name));
+        return None;
+    }
+    let file_name = entry.url.rsplit('/').next()?;
+    String::from_utf8_lossy(&output.stdout).lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches("./") == file_name)
+        .map(|(md5, _)| md5.to_ascii_lowercase())
+}
+
+// gzip (including bgzip's concatenated members) to plain FASTA, through a
+// .part file so an interrupted run never leaves a truncated reference behind
+fn decompress(source: &Path, destination: &Path) -> Result<(), String> {
+    let input = File::open(source)
+        .map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let partial = destination.with_extension("fa.part");
+    let output = File::create(&partial)
+        .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+    let mut reader = flate2::read::MultiGzDecoder::new(BufReader::new(input));
+    let mut writer = BufWriter::new(output);
+    std::io::copy(&mut reader, &mut writer)
+        .map_err(|e| format!("Failed to decompress {}: {}", source.display(), e))?;
+    writer.flush()
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    std::fs::rename(&partial, destination)
+        .map_err(|e| format!("Failed to move {} into place: {}", partial.display(), e))
+}
@@ -0,0 +1,173 @@
+// Mirror of a run's state files in object storage (--remote-state)
+// Checkpoints, the run manifest and registry, and result files are copied to
+// the s3:// or gs:// prefix each time they are written, under the same
+// relative path as in the output directory, so a run that loses its machine
+// can be resumed from another one. Uploads go through `aws s3 cp` and
+// `gsutil cp` (as remote inputs are read) on a background thread: a write
+// hands over the file's contents and returns, and a file rewritten before its
+// upload started is only uploaded once, in its latest version. flush() waits
+// for the queue at the end of a run. Upload failures are warnings and never
+// fail the run. fetch() reads a mirrored file back, for resuming elsewhere
+
+use crate::warnings::{self, Category};
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// Longest flush() waits for outstanding uploads
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(120);
+
+struct Mirror {
+    url: String,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Queue {
+    // Latest contents by object key
+    files: BTreeMap<String, Vec<u8>>,
+    uploading: bool,
+}
+
+static MIRROR: OnceCell<Mirror> = OnceCell::new();
+
+// Start mirroring to `url` (first call wins)
+pub fn configure(url: &str) -> Result<(), String> {
+    if !url.starts_with("s3://") && !url.starts_with("gs://") {
+        return Err(format!("Invalid --remote-state '{}': expected an s3:// or gs:// URL", url));
+    }
+    let mirror = Mirror {
+        url: url.trim_end_matches('/').to_string(),
+        queue: Mutex::new(Queue::default()),
+        changed: Condvar::new(),
+    };
+    if MIRROR.set(mirror).is_ok() {
+        std::thread::Builder::new()
+            .name("remote-state".to_string())
+            .spawn(upload_loop)
+            .map_err(|e| format!("Failed to start remote state uploader: {}", e))?;
+        println!("Mirroring run state to {}", url);
+    }
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    MIRROR.get().is_some()
+}
+
+// Contents of the object mirroring `local`, None if it can't be fetched
+// (usually because it doesn't exist)
+pub fn fetch(local: &Path) -> Option<Vec<u8>> {
+    let mirror = MIRROR.get()?;
+    let source = format!("{}/{}", mirror.url, object_key(local));
+    let mut command = if source.starts_with("gs://") {
+        let mut command = Command::new("gsutil");
+        command.args(["cat", &source]);
+        command
+    } else {
+        let mut command = Command::new("aws");
+        command.args(["s3", "cp", "--quiet", &source, "-"]);
+        command
+    };
+    let output = command.stderr(Stdio::null()).output().ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+// Queue the current contents of `local` for upload; a no-op without --remote-state
+pub fn mirror(local: &Path) {
+    let Some(mirror) = MIRROR.get() else { return };
+    match std::fs::read(local) {
+        Ok(contents) => {
+            let mut queue = mirror.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue.files.insert(object_key(local), contents);
+            mirror.changed.notify_all();
+        },
+        Err(e) => warnings::warn(Category::Storage, format!("not mirroring {}: {}", local.display(), e)),
+    }
+}
+
+// Wait until every queued file is uploaded (or FLUSH_TIMEOUT passes)
+pub fn flush() {
+    let Some(mirror) = MIRROR.get() else { return };
+    let deadline = Instant::now() + FLUSH_TIMEOUT;
+    let mut queue = mirror.queue.lock().unwrap_or_else(|e| e.into_inner());
+    while !queue.files.is_empty() || queue.uploading {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            warnings::warn(Category::Storage, format!("gave up waiting for {} remote state upload(s)", queue.files.len().max(1)));
+            return;
+        };
+        queue = mirror.changed.wait_timeout(queue, left).unwrap_or_else(|e| e.into_inner()).0;
+    }
+}
+
+// Path relative to the output directory (or the working directory without
+// one), with '/' separators
+fn object_key(local: &Path) -> String {
+    let relative = crate::output_layout::get()
+        .and_then(|root| local.strip_prefix(root).ok())
+        .unwrap_or(local);
+    let relative = if relative.is_absolute() {
+        relative.file_name().map(Path::new).unwrap_or(relative)
+    } else {
+        relative
+    };
+    relative.components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn upload_loop() {
+    let Some(mirror) = MIRROR.get() else { return };
+    loop {
+        let files = {
+            let mut queue = mirror.queue.lock().unwrap_or_else(|e| e.into_inner());
+            while queue.files.is_empty() {
+                queue = mirror.changed.wait(queue).unwrap_or_else(|e| e.into_inner());
+            }
+            queue.uploading = true;
+            std::mem::take(&mut queue.files)
+        };
+        for (key, contents) in files {
+            let destination = format!("{}/{}", mirror.url, key);
+            if let Err(e) = upload(&contents, &destination) {
+                warnings::warn(Category::Storage, format!("failed to upload {}: {}", destination, e));
+            }
+        }
+        let mut queue = mirror.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.uploading = false;
+        mirror.changed.notify_all();
+    }
+}
+
+// Stream `contents` to the object store client's stdin
+fn upload(contents: &[u8], destination: &str) -> Result<(), String> {
+    let mut command = if destination.starts_with("gs://") {
+        let mut command = Command::new("gsutil");
+        command.args(["-q", "cp", "-", destination]);
+        command
+    } else {
+        let mut command = Command::new("aws");
+        command.args(["s3", "cp", "--quiet", "-", destination]);
+        command
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run the object store client: {}", e))?;
+    let written = child.stdin.take()
+        .ok_or_else(|| "Failed to open the object store client's stdin".to_string())
+        .and_then(|mut stdin| stdin.write_all(contents).map_err(|e| e.to_string()));
+    let status = child.wait().map_err(|e| e.to_string())?;
+    written?;
+    if status.success() { Ok(()) } else { Err(format!("upload exited with {}", status)) }
+}
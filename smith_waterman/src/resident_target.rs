@@ -0,0 +1,143 @@
+// Resident targets: file2 of a GPU pair run uploaded once and kept on the device
+// The pair mode aligns every chunk of file1 against every chunk (or contig
+// window) of file2. Streamed, file2 is read, parsed and uploaded again for each
+// chunk of file1, so a 3 GB reference against 1000 query chunks moves 3 TB over
+// the bus. Aligner::align_files on the GPU backend uploads file2 here first, a
+// buffer per chunk or window (through the N policy, as enqueue_alignment would),
+// then streams file1: each query chunk is uploaded once and launched against
+// every resident buffer in turn. Scores, per-contig scores and launch geometry
+// are the streamed path's, launch for launch; only the uploads are saved
+// A target takes at most MAX_BUDGET_SHARE of the GPU memory budget (after
+// --gpu-fraction), so query chunks and other work still fit. A larger one is
+// dropped, and no longer read, as soon as it passes that; the pair is then
+// streamed as before
+// Each upload is charged to the GPU time breakdown with the first launch that
+// uses it, so transfer time counts every byte once
+
+use crate::aligner::{self, SequenceLaunch};
+use crate::chunking::ChunkSize;
+use crate::contig_chunks::{ContigScore, ContigTally, ContigWindow};
+use crate::gpu::GpuContext;
+use crate::kernels::KernelVariant;
+use crate::planner::format_bytes;
+use crate::run_control::CancellationToken;
+use ocl::{Buffer, Event};
+
+pub const MAX_BUDGET_SHARE: f64 = 0.5;
+// Stops the upload's read of the target once it is over budget
+const OVER_BUDGET: &str = "resident target over budget";
+
+// Where a segment came from in a FASTA target
+struct WindowOrigin {
+    contig: String,
+    index: usize,
+    start: usize,
+    end: usize,
+}
+
+struct Segment {
+    buffer: Buffer<u8>,
+    // Bases before and after the N policy
+    bases: usize,
+    len: usize,
+    window: Option<WindowOrigin>,
+    // Taken by the first launch against the segment
+    upload: Option<Event>,
+}
+
+pub struct ResidentTarget {
+    segments: Vec<Segment>,
+    bytes: usize,
+    contig_target: bool,
+}
+
+impl ResidentTarget {
+    // `path` chunked as the pair mode chunks a target, on `gpu`; None when it
+    // doesn't fit in MAX_BUDGET_SHARE of the memory budget
+    pub fn upload(gpu: &GpuContext, path: &str, chunk_size: ChunkSize) -> Result<Option<Self>, String> {
+        let budget = (aligner::memory_budget_bytes(gpu.info()) as f64 * MAX_BUDGET_SHARE) as usize;
+        let contig_target = crate::contig_chunks::is_contig_target(path)?;
+        let mut target = Self { segments: Vec::new(), bytes: 0, contig_target };
+        let mut add = |bases: &[u8], window: Option<WindowOrigin>| -> Result<(), String> {
+            let prepared = crate::n_policy::prepare(bases);
+            if target.bytes + prepared.len() > budget {
+                return Err(OVER_BUDGET.to_string());
+            }
+            // Zero-length buffers aren't allowed; an empty segment is never launched
+            let (buffer, upload) = aligner::upload_sequence(gpu.queue(), if prepared.is_empty() { b"N" } else { &prepared }, "target")?;
+            target.bytes += prepared.len();
+            target.segments.push(Segment { buffer, bases: bases.len(), len: prepared.len(), window, upload: Some(upload) });
+            Ok(())
+        };
+        let uploaded = if contig_target {
+            crate::contig_chunks::for_each_window(path, chunk_size, |window| {
+                add(window.bases, Some(WindowOrigin { contig: window.contig.to_string(), index: window.index, start: window.start, end: window.end }))
+            })
+        } else {
+            aligner::process_fastq_file_in_chunks(path, chunk_size, |chunk| add(chunk.bases(), None))
+        };
+        match uploaded {
+            Ok(()) => {
+                println!("Target {} resident on {}: {} segments, {}", path, gpu.info().name, target.segments.len(), format_bytes(target.bytes as u64));
+                Ok(Some(target))
+            },
+            Err(e) if e == OVER_BUDGET => {
+                println!("Target {} is over {} of GPU memory; streaming it for every query chunk", path, format_bytes(budget as u64));
+                Ok(None)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    // Device bytes held, after the N policy
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // Every chunk of `file1` against every segment, summed, as
+    // aligner::align_pair_chunks sums them. `cancel` is checked before each
+    // pair; `launched` gets each pair's bases (both sides) and score
+    pub fn align_file(&mut self, gpu: &GpuContext, variant: KernelVariant, file1: &str, chunk_size: ChunkSize, cancel: &CancellationToken,
+                      mut launched: impl FnMut(usize, i32)) -> Result<(i32, Vec<ContigScore>), String> {
+        let program = gpu.program()?;
+        let (mut total_score, mut tally, mut query_chunk) = (0, ContigTally::default(), 0);
+        aligner::process_fastq_file_in_chunks(file1, chunk_size, |chunk| {
+            query_chunk += 1;
+            let query = crate::n_policy::prepare(chunk.bases());
+            let uploaded = if chunk.bases().is_empty() {
+                None
+            } else {
+                Some(aligner::upload_sequence(gpu.queue(), if query.is_empty() { b"N" } else { &query }, "seq1")?)
+            };
+            let mut query_upload = uploaded.as_ref().map(|(_, write)| write.clone());
+            for segment in &mut self.segments {
+                cancel.check()?;
+                let score = match &uploaded {
+                    Some((query_buffer, _)) if segment.bases > 0 => {
+                        let launch = SequenceLaunch {
+                            seq1: query_buffer,
+                            seq2: &segment.buffer,
+                            len: query.len().min(segment.len),
+                            seq_writes: query_upload.take().into_iter().chain(segment.upload.take()).collect(),
+                            launch_start: std::time::Instant::now(),
+                        };
+                        aligner::enqueue_kernel(gpu.queue(), program, launch, gpu.info(), variant)?.wait()?
+                    },
+                    _ => 0,
+                };
+                total_score += score;
+                if let Some(origin) = &segment.window {
+                    let window = ContigWindow { contig: &origin.contig, index: origin.index, start: origin.start, end: origin.end, bases: &[] };
+                    tally.add(&window, query_chunk, score);
+                }
+                launched(chunk.bases().len() + segment.bases, score);
+            }
+            Ok(())
+        })?;
+        Ok((total_score, if self.contig_target { tally.into_scores() } else { Vec::new() }))
+    }
+}
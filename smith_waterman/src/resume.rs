@@ -0,0 +1,247 @@
+// Resuming a WGS run from its checkpoint, possibly on another machine
+// --resume continues from the latest checkpoint in the output directory; with
+// --remote-state and no local checkpoint, the run registry and the latest
+// checkpoint are fetched from the remote prefix first (restore_from_remote).
+// Scores depend on the crate version, kernel, N policy, scoring constants and
+// everything that shapes the chunks, so a checkpoint records them
+// (RunProvenance) and a resume with any of them changed is refused. On a
+// device the checkpoint hasn't seen, the first chunk of a completed file is
+// aligned again and has to reproduce its recorded score before the run goes
+// on, so scores from two devices are never mixed silently; the new device is
+// added to the checkpoint and to manifest.json
+
+use crate::aligner::{self, CheckpointState, MIN_CHUNK_BASES};
+use crate::gpu::{GpuContext, GpuDevice};
+use crate::read_chunk::{ReadChunk, ReadNameMode};
+use crate::run_registry::RunRegistry;
+use crate::wgs_config::WgsConfig;
+use crate::warnings::{self, Category};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunProvenance {
+    pub version: String,
+    pub kernel: String,
+    // FNV-1a of the OpenCL source, so an edited kernel counts as a new one
+    pub kernel_digest: String,
+    pub n_policy: String,
+    pub match_score: i32,
+    pub mismatch_penalty: i32,
+    #[serde(default = "default_gap_penalty")]
+    pub gap_penalty: i32,
+    pub chunk_size: String,
+    pub binning: String,
+    pub sanitize: String,
+    // Added later; older checkpoints ran without them
+    #[serde(default = "off")]
+    pub trim: String,
+    #[serde(default = "off")]
+    pub read_trim: String,
+    #[serde(default = "off")]
+    pub long_reads: String,
+    pub mask_low_complexity: bool,
+}
+
+impl RunProvenance {
+    pub fn current(config: &WgsConfig) -> Self {
+        let mut digest: u64 = 0xcbf29ce484222325;
+        for byte in aligner::KERNEL_SOURCE.bytes() {
+            digest ^= byte as u64;
+            digest = digest.wrapping_mul(0x100000001b3);
+        }
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            kernel: crate::kernels::selected().name().to_string(),
+            kernel_digest: format!("{:016x}", digest),
+            n_policy: crate::n_policy::selected().name().to_string(),
+            match_score: crate::scoring::selected().match_score,
+            mismatch_penalty: crate::scoring::selected().mismatch_penalty,
+            gap_penalty: crate::scoring::selected().gap_penalty,
+            chunk_size: match config.adaptive_chunks.filter(|_| config.long_reads.is_none()) {
+                Some(target) => format!("auto from {} ({} ms)", config.launch_chunk_size(), target),
+                None => config.launch_chunk_size().to_string(),
+            },
+            binning: format!("{:?}", config.binning),
+            sanitize: config.sanitize.map_or("off".to_string(), |options| format!("{:?}", options)),
+            trim: config.trim.as_ref().map_or_else(off, |options| options.name()),
+            read_trim: config.read_trim.map_or_else(off, |options| options.name()),
+            long_reads: config.long_reads.map_or_else(off, |options| options.name()),
+            mask_low_complexity: config.mask_low_complexity,
+        }
+    }
+
+    // "field: checkpoint -> now" for every field that differs
+    fn differences(&self, checkpoint: &Self) -> Vec<String> {
+        let (now, then) = (serde_json::to_value(self).unwrap_or_default(), serde_json::to_value(checkpoint).unwrap_or_default());
+        let Some(fields) = now.as_object() else { return Vec::new() };
+        fields.iter()
+            .filter(|(name, value)| then.get(name.as_str()) != Some(value))
+            .map(|(name, value)| format!("{}: {} -> {}", name, then.get(name.as_str()).unwrap_or(&serde_json::Value::Null), value))
+            .collect()
+    }
+}
+
+fn off() -> String {
+    "off".to_string()
+}
+
+fn default_gap_penalty() -> i32 {
+    crate::scoring::DEFAULT.gap_penalty
+}
+
+// Before the run claims its number: with --remote-state and no local
+// checkpoint, fetch the run registry and the newest checkpoint it lists, so
+// numbering continues where the other machine stopped and --resume finds it
+pub fn restore_from_remote() -> Result<(), String> {
+    if !crate::remote_state::is_enabled() || CheckpointState::latest_in(&crate::output_layout::checkpoints_dir())?.is_some() {
+        return Ok(());
+    }
+    let registry_path = crate::output_layout::registry_file();
+    let Some(json) = crate::remote_state::fetch(&registry_path) else {
+        println!("No run registry in the remote state; nothing to restore");
+        return Ok(());
+    };
+    let registry: RunRegistry = serde_json::from_slice(&json)
+        .map_err(|e| format!("Failed to parse the remote run registry: {}", e))?;
+    let local_last_run = RunRegistry::load(&registry_path)?.last_run;
+    if registry.last_run > local_last_run {
+        crate::checkpoint_store::write_file(&registry_path, &json)?;
+    }
+    for record in registry.runs.iter().rev() {
+        // Written plain or compressed, as CHECKPOINT_COMPRESSION was on that machine
+        for path in crate::checkpoint_store::forms(&CheckpointState::path_for(record.number)) {
+            if let Some(checkpoint) = crate::remote_state::fetch(&path) {
+                crate::checkpoint_store::write_file(&path, &checkpoint)?;
+                println!("Restored checkpoint of run {} from the remote state", record.number);
+                return Ok(());
+            }
+        }
+    }
+    println!("No checkpoint in the remote state; nothing to restore");
+    Ok(())
+}
+
+// The latest checkpoint, checked against this run's inputs and parameters and
+// re-verified on `device` if it hasn't been used for this run before
+pub fn resume(run_number: u64, gpu: &GpuContext, device: &GpuDevice, config: &WgsConfig, files: &[String]) -> Result<CheckpointState, String> {
+    let checkpoints_dir = crate::output_layout::checkpoints_dir();
+    let Some(mut state) = CheckpointState::latest_in(&checkpoints_dir)? else {
+        return Err(format!("--resume: no checkpoint in {}", checkpoints_dir.display()));
+    };
+    println!("Resuming {}: {} of {} files completed", state.run_id, state.completed_files, state.total_files);
+
+    if state.total_files != files.len() {
+        return Err(format!("--resume: the checkpoint has {} files but this run has {}", state.total_files, files.len()));
+    }
+    // Paths may differ between machines, file names may not
+    let name = |path: &str| crate::paths::file_name(path).to_string();
+    for file in state.files.iter().filter(|f| f.completed) {
+        let expected = files.get(file.file_index).map(|f| name(f)).unwrap_or_default();
+        if name(&file.file_path) != expected {
+            return Err(format!("--resume: file {} was {} in the checkpoint but is {} now", file.file_index + 1, name(&file.file_path), expected));
+        }
+    }
+
+    let current = RunProvenance::current(config);
+    match &state.provenance {
+        Some(provenance) => {
+            let differences = current.differences(provenance);
+            if !differences.is_empty() {
+                return Err(format!("--resume: scores in the checkpoint aren't comparable with this run ({})", differences.join(", ")));
+            }
+        },
+        None => warnings::warn(Category::Checkpoints, "the checkpoint predates recorded run parameters; they can't be checked"),
+    }
+
+    if !state.devices.contains(&device.name) {
+        verify_on_device(gpu, device, config, &state)?;
+        state.devices.push(device.name.clone());
+    }
+    state.provenance = Some(current);
+    crate::output_layout::record_device(run_number, &device.name)?;
+    Ok(state)
+}
+
+// Align the first chunk of the first completed file again and compare with its recorded score
+fn verify_on_device(gpu: &GpuContext, device: &GpuDevice, config: &WgsConfig, state: &CheckpointState) -> Result<(), String> {
+    let recorded = state.files.iter()
+        .filter(|f| f.completed)
+        .find_map(|f| f.first_chunk_score.map(|score| (f, score)));
+    let Some((file, recorded)) = recorded else {
+        warnings::warn(Category::Checkpoints, format!("no completed chunk with a recorded score; {} can't be checked against {}",
+                 device.name, state.devices.join(", ")));
+        return Ok(());
+    };
+    println!("Re-verifying file {} chunk 1 on {} (previously on {})", file.file_index + 1, device.name, state.devices.join(", "));
+    let score = align_first_chunk(gpu, device, config, &file.file_path)?;
+    if score != recorded {
+        return Err(format!("--resume: file {} chunk 1 scores {} on {} but {} in the checkpoint (from {}); refusing to mix scores across devices",
+                           file.file_index + 1, score, device.name, recorded, state.devices.join(", ")));
+    }
+    println!("  Score {} reproduced", score);
+    Ok(())
+}
+
+// The first chunk prepared as the WGS pass does (sanitizing, trimming, binning, masking) and aligned
+fn align_first_chunk(gpu: &GpuContext, device: &GpuDevice, config: &WgsConfig, file: &str) -> Result<i32, String> {
+    // The sliding window trims by quality, so the re-read has to keep it
+    crate::read_chunk::keep_qualities(config.read_trim.is_some_and(|trim| trim.window.is_some()));
+    const STOP: &str = "first chunk read";
+    let mut first = None;
+    let keep_first = |chunk: &ReadChunk| {
+        first = Some(chunk.clone());
+        Err(STOP.to_string())
+    };
+    let read = match config.alignment_input.alignment_format(file)? {
+        Some(format) => crate::alignment_input::process_alignment_file_in_chunks(
+            file, format, &config.alignment_input, config.launch_chunk_size(), ReadNameMode::Discard, keep_first),
+        None => crate::async_input::process_input_in_chunks(file, config.launch_chunk_size(), ReadNameMode::Discard, keep_first),
+    };
+    let chunk = match (first, read) {
+        (Some(chunk), _) => chunk,
+        (None, Err(e)) => return Err(format!("Failed to re-read {}: {}", file, e)),
+        (None, Ok(())) => return Err(format!("{} has no reads", file)),
+    };
+
+    let capacity = config.launch_chunk_size().capacity_reads();
+    let mut sanitized = ReadChunk::with_names(capacity, ReadNameMode::Discard);
+    let chunk = match config.sanitize {
+        Some(options) => {
+            crate::sanitize::Sanitizer::new(options).sanitize_into(&chunk, &mut sanitized, &mut Default::default());
+            &sanitized
+        },
+        None => &chunk,
+    };
+    let mut trimmed = ReadChunk::with_names(capacity, ReadNameMode::Discard);
+    let chunk = match &config.trim {
+        Some(options) => {
+            crate::long_read_trim::Trimmer::new(options.clone()).trim_into(chunk, &mut trimmed, &mut Default::default());
+            &trimmed
+        },
+        None => chunk,
+    };
+    let mut read_trimmed = ReadChunk::with_names(capacity, ReadNameMode::Discard);
+    let chunk = match config.read_trim {
+        Some(options) => {
+            crate::read_trim::ReadTrimmer::new(options).trim_into(chunk, &mut read_trimmed, &mut Default::default());
+            &read_trimmed
+        },
+        None => chunk,
+    };
+    let mut binned = ReadChunk::with_names(capacity, ReadNameMode::Discard);
+    let chunk = if crate::read_binning::reorder_into(chunk, config.binning, &mut binned) { &binned } else { chunk };
+    let mut masked = Vec::new();
+    let query = if config.mask_low_complexity {
+        crate::low_complexity::mask_chunk(chunk, &mut masked);
+        &masked[..]
+    } else {
+        chunk.bases()
+    };
+    if let Some(long_reads) = &config.long_reads {
+        return crate::long_reads::score_read(gpu, device, query, chunk.bases(), long_reads).map(|read| read.score);
+    }
+    if chunk.bases().len() < MIN_CHUNK_BASES {
+        return Ok(0);
+    }
+    aligner::enqueue_alignment(gpu.queue(), gpu.program()?, query, chunk.bases(), device, crate::kernels::selected())?.wait()
+}
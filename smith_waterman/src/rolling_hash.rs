@@ -0,0 +1,88 @@
+// ntHash rolling k-mer hashes, shared by every k-mer scan that hashes: the
+// sketches (sketch.rs: the contamination screen, ani, compare-assemblies'
+// contig pairing), the k-mer counter (analyze --kmer-spectrum, whose default
+// hash is this one) and the exact k-mer seed finder (assembly_compare's
+// anchors and chromosome_walk's query index)
+// Each base has a random 64-bit seed. A k-mer's forward hash is the XOR of its
+// bases' seeds, each rotated left by its distance from the k-mer's last base,
+// and its reverse hash is the forward hash of its reverse complement. Sliding
+// the window one base rotates both once and XORs out the base that left and
+// in the one that came in, so a k-mer costs the same few operations whatever k
+// is and nothing is rehashed per window; only the first k-mer after the start
+// or a non-ACGT base is hashed in full. Bases go through a lookup table rather
+// than a match, so random sequence doesn't mispredict a branch per base
+// Seeds are keyed by the strand hashes instead of the packed k-mers: two k-mers
+// share a 64-bit hash by chance far less often than a k-mer repeats, and seed
+// hits are checked against the bases anyway. canonical() folds the two strands
+// into one hash, spread so its top bits sample evenly (sketch::max_hash)
+
+// ntHash's seeds for A, C, G and T; the complement of code c is 3 - c
+const SEEDS: [u64; 4] = [0x3c8bfbb395c60474, 0x3193c18562a02b4c, 0x20323ed082572324, 0x295549f54be24456];
+const NOT_ACGT: u8 = 4;
+const MULTIPLIER: u64 = 0x94d049bb133111eb;
+
+// 2-bit code of each byte, NOT_ACGT for anything but A/C/G/T/U in either case
+static CODES: [u8; 256] = {
+    let mut codes = [NOT_ACGT; 256];
+    let bases = [(b'A', 0), (b'C', 1), (b'G', 2), (b'T', 3), (b'U', 3)];
+    let mut i = 0;
+    while i < bases.len() {
+        let (base, code) = bases[i];
+        codes[base as usize] = code;
+        codes[base.to_ascii_lowercase() as usize] = code;
+        i += 1;
+    }
+    codes
+};
+
+pub fn base_code(base: u8) -> Option<u64> {
+    let code = CODES[base as usize];
+    (code != NOT_ACGT).then_some(code as u64)
+}
+
+// Calls `f` with the start, forward hash and reverse hash of every k-mer of
+// A/C/G/T bases, until it returns false
+pub fn for_each_kmer(sequence: &[u8], k: usize, mut f: impl FnMut(usize, u64, u64) -> bool) {
+    if k == 0 {
+        return;
+    }
+    // Seeds as rotated for the base leaving the window and the one entering it
+    let leaving = SEEDS.map(|seed| seed.rotate_left(k as u32));
+    let leaving_reverse = [SEEDS[3], SEEDS[2], SEEDS[1], SEEDS[0]].map(|seed| seed.rotate_right(1));
+    let entering_reverse = [SEEDS[3], SEEDS[2], SEEDS[1], SEEDS[0]].map(|seed| seed.rotate_left(k as u32 - 1));
+    let (mut forward, mut reverse, mut run) = (0u64, 0u64, 0usize);
+    for (position, &base) in sequence.iter().enumerate() {
+        let code = CODES[base as usize];
+        if code == NOT_ACGT {
+            run = 0;
+            continue;
+        }
+        run += 1;
+        if run < k {
+            continue;
+        }
+        let start = position + 1 - k;
+        if run == k {
+            (forward, reverse) = (0, 0);
+            for (i, &base) in sequence[start..=position].iter().enumerate() {
+                let code = (CODES[base as usize] & 3) as usize;
+                forward ^= SEEDS[code].rotate_left((k - 1 - i) as u32);
+                reverse ^= SEEDS[3 - code].rotate_left(i as u32);
+            }
+        } else {
+            let out = (CODES[sequence[start - 1] as usize] & 3) as usize;
+            let code = (code & 3) as usize;
+            forward = forward.rotate_left(1) ^ leaving[out] ^ SEEDS[code];
+            reverse = reverse.rotate_right(1) ^ leaving_reverse[out] ^ entering_reverse[code];
+        }
+        if !f(start, forward, reverse) {
+            return;
+        }
+    }
+}
+
+// One hash for a k-mer and its reverse complement
+pub fn canonical(forward: u64, reverse: u64) -> u64 {
+    let hash = forward.wrapping_add(reverse).wrapping_mul(MULTIPLIER);
+    hash ^ (hash >> 32)
+}
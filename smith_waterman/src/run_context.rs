@@ -0,0 +1,301 @@
+// RunContext: the state one WGS run records into
+// A run's number and logs directory (run_registry), its benchmark tracker and
+// its system monitors belong to the run, not to the process: two runs in one
+// process, e.g. a library caller's threads, each get a context of their own
+// and write their own results, checkpoint and logs. Aligner::run_wgs registers
+// a new one per run; Aligner::run_wgs_in takes the caller's
+// The CLI runs one run per process, so its context is a global (cli()) that
+// the Ctrl-C and preemption handlers can find to stop monitors and flush the
+// benchmark. The OpenCL context is already per-aligner (GpuContext); the
+// shared one behind GpuContext::shared is the same kind of CLI convenience
+// A run sharing the GPU with others through a GpuScheduler carries its Job here
+// (with_gpu_job) for the run's launch pool to take its slots under
+// The run's --memory-profile budget (memory) is here too, so concurrent runs
+// don't reset, charge or fail each other's
+// What the modules a run passes through count as it goes (RunState: per-file
+// timing, chunk latencies, GPU time, lane occupancy, skipped chunks and
+// quarantined files, whether chunks keep qualities, --deterministic, the
+// --chunk-size auto controller, its warnings, the pipeline stage meters and the
+// file and children the stall watchdog watches) is the run's as well. Those
+// modules are called deep in the readers and launches, so rather than take the
+// context as an argument they record into the run the calling thread works for:
+// the run's threads enter its context (enter), and a thread handing work to
+// another carries it over (current, then RunState::enter there). Threads that
+// never entered one record into the CLI's
+// Runs share the output layout, and record into their own entries of
+// manifest.json (output_layout.rs). Still process-wide: the stall watchdog
+// thread and the --tui dashboard, which follow the CLI's run, and the kernel,
+// scores and N policy (engine.rs)
+
+use crate::adaptive_chunks::Adaptation;
+use crate::chunk_latency::LatencyTracker;
+use crate::chunking::ChunkSize;
+use crate::exit_codes::{ExitKind, Skipped};
+use crate::file_timing::FileTimings;
+use crate::gpu_scheduler::Job;
+use crate::gpu_timing::GpuTimingSummary;
+use crate::memory_profile::MemoryBudget;
+use crate::perf_logger::SystemMonitors;
+use crate::read_binning::LaneUsage;
+use crate::run_registry::RunRecord;
+use crate::stages::StageMeters;
+use crate::tools::benchmark::{BenchmarkResult, BenchmarkTracker};
+use crate::warnings::{self, CategorySummary};
+use crate::watchdog::Watched;
+use once_cell::sync::{Lazy, OnceCell};
+use std::cell::RefCell;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+// What one run's modules record into; each module keeps its part private
+#[derive(Default)]
+pub struct RunState {
+    pub(crate) files: FileTimings,
+    pub(crate) latency: Mutex<LatencyTracker>,
+    pub(crate) gpu_timing: Mutex<GpuTimingSummary>,
+    pub(crate) lanes: Mutex<LaneUsage>,
+    pub(crate) skipped: Skipped,
+    pub(crate) keep_qualities: AtomicBool,
+    pub(crate) deterministic: AtomicBool,
+    pub(crate) warnings: warnings::Registry,
+    pub(crate) chunks: Adaptation,
+    pub(crate) stages: StageMeters,
+    pub(crate) watched: Watched,
+}
+
+impl RunState {
+    // Record into this run on the current thread until the guard is dropped
+    pub fn enter(self: &Arc<Self>) -> Entered {
+        Entered { previous: CURRENT.with(|current| current.replace(Some(self.clone()))) }
+    }
+}
+
+// The CLI's, and that of any thread outside a run
+static PROCESS: Lazy<Arc<RunState>> = Lazy::new(Arc::default);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<RunState>>> = const { RefCell::new(None) };
+}
+
+// Back to the run the thread worked for before, when dropped
+pub struct Entered {
+    previous: Option<Arc<RunState>>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+// The state of the run the current thread works for, to enter on a thread
+// that takes over part of its work
+pub fn current() -> Arc<RunState> {
+    CURRENT.with(|current| current.borrow().clone()).unwrap_or_else(|| PROCESS.clone())
+}
+
+pub(crate) fn with_state<T>(f: impl FnOnce(&RunState) -> T) -> T {
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(state) => f(state),
+        None => f(&PROCESS),
+    })
+}
+
+pub struct RunContext {
+    run: RunRecord,
+    benchmark: Mutex<BenchmarkTracker>,
+    monitors: Mutex<Option<SystemMonitors>>,
+    gpu_job: Option<Job>,
+    memory: Arc<MemoryBudget>,
+    state: Arc<RunState>,
+}
+
+impl RunContext {
+    // A context for `run`, e.g. one claimed with run_registry::register
+    pub fn for_run(run: RunRecord) -> Self {
+        Self::with_state(run, Arc::default())
+    }
+
+    fn with_state(run: RunRecord, state: Arc<RunState>) -> Self {
+        Self {
+            benchmark: Mutex::new(BenchmarkTracker::new(run.clone())),
+            monitors: Mutex::new(None),
+            gpu_job: None,
+            memory: Arc::new(MemoryBudget::default()),
+            state,
+            run,
+        }
+    }
+
+    // A context for the next run number in the registry
+    pub fn register() -> Self {
+        Self::for_run(crate::run_registry::register())
+    }
+
+    pub fn run(&self) -> &RunRecord {
+        &self.run
+    }
+
+    // Launch on the GPU as `job` of its scheduler
+    pub fn with_gpu_job(mut self, job: Job) -> Self {
+        self.gpu_job = Some(job);
+        self
+    }
+
+    pub fn gpu_job(&self) -> Option<&Job> {
+        self.gpu_job.as_ref()
+    }
+
+    pub fn memory(&self) -> &Arc<MemoryBudget> {
+        &self.memory
+    }
+
+    pub fn state(&self) -> &Arc<RunState> {
+        &self.state
+    }
+
+    // Record into this run on the current thread until the guard is dropped
+    pub fn enter(&self) -> Entered {
+        self.state.enter()
+    }
+
+    // Success, or partial success if the run skipped a chunk or quarantined a file
+    pub fn completion_kind(&self) -> ExitKind {
+        self.state.skipped.completion_kind()
+    }
+
+    // The run's warnings so far, by category
+    pub fn warnings(&self) -> Vec<CategorySummary> {
+        self.state.warnings.summary()
+    }
+
+    // System monitors log to the run's logs directory; the tools behind them
+    // watch the whole machine, so only one set runs in a process at a time
+    pub fn start_monitors(&self) -> Result<(), String> {
+        let mut monitors = SystemMonitors::for_run(&self.run);
+        monitors.start()?;
+        if let Ok(mut running) = self.monitors.lock() {
+            *running = Some(monitors);
+        }
+        Ok(())
+    }
+
+    pub fn stop_monitors(&self) -> Result<(), String> {
+        let monitors = self.monitors.lock().ok().and_then(|mut running| running.take());
+        match monitors {
+            Some(mut monitors) => monitors.stop(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn start_benchmark(&self, mode: &str, chunk_size: ChunkSize, parallel_files: bool) {
+        if let Ok(mut tracker) = self.benchmark.lock() {
+            tracker.start_run(mode, chunk_size, parallel_files);
+        }
+    }
+
+    pub fn update_benchmark_progress(&self, files_processed: usize, reads: usize, bases: usize, score: i32) {
+        if let Ok(mut tracker) = self.benchmark.lock() {
+            tracker.update_progress(files_processed, reads, bases, score);
+        }
+    }
+
+    // Bases left out of the run's totals, and whether any chunk or file was
+    pub fn set_benchmark_excluded(&self, bases: usize, partial: bool) {
+        if let Ok(mut tracker) = self.benchmark.lock() {
+            tracker.set_excluded(bases, partial);
+        }
+    }
+
+    pub fn benchmark_chunk_done(&self) {
+        if let Ok(mut tracker) = self.benchmark.lock() {
+            tracker.chunk_done();
+        }
+    }
+
+    // Called on Ctrl-C so an interrupted run keeps its progress; doesn't wait
+    // for a tracker that is busy
+    pub fn flush_benchmark(&self) {
+        let _entered = self.enter();
+        if let Ok(tracker) = self.benchmark.try_lock() {
+            tracker.flush_partial();
+        }
+    }
+
+    pub fn finish_benchmark(&self) -> Option<BenchmarkResult> {
+        let _entered = self.enter();
+        self.benchmark.lock().ok().and_then(|mut tracker| tracker.finish_run())
+    }
+}
+
+static CLI: OnceCell<RunContext> = OnceCell::new();
+
+// The CLI's run, registered on first use (the same run as run_registry::current)
+pub fn cli() -> &'static RunContext {
+    CLI.get_or_init(|| RunContext::with_state(crate::run_registry::current().clone(), PROCESS.clone()))
+}
+
+// Ctrl-C and preemption: stop the CLI run's monitors and save its benchmark
+// so far, if it has started
+pub fn interrupt_cli() {
+    if let Some(run) = CLI.get() {
+        let _ = run.stop_monitors();
+        run.flush_benchmark();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exit_codes;
+    use crate::stages::{self, Stage, StageClock};
+    use std::time::Duration;
+
+    #[test]
+    fn runs_on_threads_keep_their_own_counts() {
+        let (first, second) = (Arc::new(RunState::default()), Arc::new(RunState::default()));
+        std::thread::scope(|scope| {
+            for (state, chunks) in [(&first, 3), (&second, 1)] {
+                scope.spawn(move || {
+                    let _entered = state.enter();
+                    crate::read_chunk::keep_qualities(chunks > 1);
+                    stages::start_file();
+                    let _clock = StageClock::start(Stage::Gpu);
+                    for _ in 0..chunks {
+                        exit_codes::record_skipped_chunk(10, 1000);
+                        crate::chunk_latency::record("reads.fastq", 1, 0, Duration::from_millis(5));
+                        stages::add_item(Stage::Gpu, 100);
+                    }
+                    // A thread taking over part of the work records into the same run
+                    let run = current();
+                    std::thread::spawn(move || {
+                        let _entered = run.enter();
+                        exit_codes::record_quarantined_file();
+                    }).join().unwrap();
+                });
+            }
+        });
+        for (state, chunks) in [(&first, 3), (&second, 1)] {
+            let _entered = state.enter();
+            assert_eq!(exit_codes::skipped_chunks(), chunks);
+            assert_eq!(exit_codes::skipped_bases(), chunks * 1000);
+            assert_eq!(exit_codes::quarantined_files(), 1);
+            assert_eq!(crate::chunk_latency::summary().chunks, chunks as u64);
+            assert_eq!(crate::read_chunk::keeps_qualities(), chunks > 1);
+            let stages = stages::snapshot();
+            assert_eq!(stages.len(), 1);
+            assert_eq!((stages[0].items, stages[0].bytes), (chunks as u64, chunks as u64 * 100));
+        }
+
+        // Entering is scoped: the thread goes back to the run it was in
+        let _outer = first.enter();
+        {
+            let _inner = second.enter();
+            assert_eq!(exit_codes::skipped_chunks(), 1);
+        }
+        assert_eq!(exit_codes::skipped_chunks(), 3);
+        assert_eq!(first.skipped.completion_kind(), ExitKind::PartialSuccess);
+        assert_eq!(RunState::default().skipped.completion_kind(), ExitKind::Success);
+    }
+}
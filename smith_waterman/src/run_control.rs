@@ -0,0 +1,68 @@
+// Cancellation and progress for long-running library calls
+// (Aligner::align_files_with, Aligner::run_wgs_with), so GUIs and services can
+// stop a run and draw their own progress without parsing stdout
+// A token is checked before each chunk: a cancelled WGS run stops at the next
+// chunk boundary, checkpoints the interrupted file as incomplete (completed
+// files stay checkpointed, so the run can be resumed) and returns an error;
+// callers tell it from a failure with is_cancelled(). Progress callbacks run on
+// the thread driving the GPU, so they should return quickly
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Clones share one flag; cancel from any thread
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Err once cancelled, for `?` at chunk boundaries
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err("Run cancelled".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStage {
+    FileStarted,
+    // A chunk was submitted; its score may arrive with a later chunk
+    Chunk,
+    FileFinished,
+    // Already completed in the checkpoint
+    FileSkipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub stage: ProgressStage,
+    pub file: String,
+    // From 0
+    pub file_index: usize,
+    pub total_files: usize,
+    // Running totals of the current file; reads are 0 for file pairs
+    pub chunks: usize,
+    pub reads: usize,
+    pub bases: usize,
+    // Scores that have arrived so far
+    pub score: i64,
+    // Since the call started
+    pub elapsed: Duration,
+}
@@ -0,0 +1,79 @@
+// Estimated energy and dollar cost of a run, for comparing chunk sizes and
+// backends on cloud GPUs
+// Energy is GPU board power (nvidia-smi power.draw, summed over every GPU)
+// integrated over the metrics samples, so the CPU, disks and the rest of the
+// instance aren't in it. Cost is wall time at --cost-per-hour, the instance's
+// hourly price. Both are also given per gigabase (10^9 bases aligned), so runs
+// over different inputs compare directly
+
+use crate::metrics_sampler::MetricsSummary;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default)]
+pub struct Pricing {
+    // USD per hour of the whole instance
+    pub cost_per_hour: Option<f64>,
+    // Free-form label recorded with the run, e.g. "g5.xlarge"
+    pub instance_type: Option<String>,
+}
+
+static PRICING: OnceCell<Pricing> = OnceCell::new();
+
+pub fn configure(pricing: Pricing) -> Result<(), String> {
+    if let Some(price) = pricing.cost_per_hour {
+        if !price.is_finite() || price < 0.0 {
+            return Err(format!("Invalid --cost-per-hour {}: expected a non-negative amount in USD", price));
+        }
+    }
+    let _ = PRICING.set(pricing);
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RunCost {
+    // Empty without --instance-type
+    pub instance_type: String,
+    // None without --cost-per-hour
+    pub cost_per_hour_usd: Option<f64>,
+    pub cost_usd: Option<f64>,
+    pub cost_per_gbase_usd: Option<f64>,
+    // None without GPU power samples
+    pub avg_gpu_power_watts: Option<f64>,
+    pub gpu_energy_kwh: Option<f64>,
+    pub kwh_per_gbase: Option<f64>,
+}
+
+impl RunCost {
+    // Cost of `seconds` of wall time aligning `bases`, with energy from the
+    // run's metrics summary
+    pub fn estimate(seconds: f64, bases: usize, metrics: Option<&MetricsSummary>) -> Self {
+        let pricing = PRICING.get().cloned().unwrap_or_default();
+        let gigabases = bases as f64 / 1e9;
+        let per_gbase = |total: Option<f64>| total.filter(|_| gigabases > 0.0).map(|total| total / gigabases);
+        let cost_usd = pricing.cost_per_hour.map(|price| price * seconds / 3600.0);
+        let gpu_energy_kwh = metrics.and_then(|m| m.gpu_energy_kwh);
+        Self {
+            instance_type: pricing.instance_type.unwrap_or_default(),
+            cost_per_hour_usd: pricing.cost_per_hour,
+            cost_usd,
+            cost_per_gbase_usd: per_gbase(cost_usd),
+            avg_gpu_power_watts: metrics.and_then(|m| m.avg_gpu_power_watts),
+            gpu_energy_kwh,
+            kwh_per_gbase: per_gbase(gpu_energy_kwh),
+        }
+    }
+
+    // Energy and cost lines for the run summaries; nothing when neither was measured
+    pub fn print(&self) {
+        if let (Some(kwh), Some(watts)) = (self.gpu_energy_kwh, self.avg_gpu_power_watts) {
+            let per_gbase = self.kwh_per_gbase.map_or(String::new(), |k| format!(", {:.4} kWh/Gbase", k));
+            println!("GPU energy: {:.4} kWh (avg {:.0} W{})", kwh, watts, per_gbase);
+        }
+        if let (Some(cost), Some(price)) = (self.cost_usd, self.cost_per_hour_usd) {
+            let per_gbase = self.cost_per_gbase_usd.map_or(String::new(), |c| format!(", ${:.4}/Gbase", c));
+            let instance = if self.instance_type.is_empty() { String::new() } else { format!(" on {}", self.instance_type) };
+            println!("Estimated cost: ${:.4} at ${:.2}/h{}{}", cost, price, instance, per_gbase);
+        }
+    }
+}
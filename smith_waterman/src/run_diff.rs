@@ -0,0 +1,464 @@
+// `run diff`: what changed between two runs, for chasing score and throughput
+// regressions across code versions, settings and machines
+// Each side is a run directory (--output-dir) or its manifest.json, and the
+// latest run in it unless one is picked. Its settings are the recorded command
+// line (flags and values), the code version, the device and the seed; its
+// results are the run's benchmark results and run report. The diff lists the
+// settings that changed, the run totals side by side, and every file matched
+// by name (paths differ between machines). A score difference is put down to
+// the changed settings that affect scores, and a throughput difference of
+// THROUGHPUT_CHANGE or more to those that affect speed, along with the files
+// whose bottleneck stage moved. With nothing recorded to blame, that is said:
+// .env values and the input data aren't in the manifest
+
+use crate::output_layout::{load_manifest, RunManifest};
+use crate::tools::benchmark::{load_runs, BenchmarkResult};
+use crate::wgs_report::{FileStatus, WgsRunReport};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Relative change in bases/s that counts as a throughput difference
+const THROUGHPUT_CHANGE: f64 = 0.05;
+
+// Flags that change scores but not (much) speed
+const RESULT_SETTINGS: [&str; 24] = [
+    "splice-aware", "intron-open", "intron-extend", "sanitize", "alphabet", "trim-long-reads",
+    "min-trimmed-length", "trim-head", "trim-tail", "trim-sliding-window", "mask-low-complexity",
+    "input-format", "region", "preset", "match-score", "mismatch-penalty", "gap-penalty", "n-policy",
+    "reference", "seed", "deterministic", "screen", "screen-kmer", "screen-scale",
+];
+// Flags that change both: chunk boundaries and tiles decide what is scored, the
+// file set decides what is read
+const BOTH_SETTINGS: [&str; 9] = [
+    "chunk-size", "kernel", "long-reads", "tile-size", "tile-overlap", "num-files", "file-range", "only-files", "order",
+];
+// Flags that say where the run went or how it was watched, not what it did
+const IGNORED_SETTINGS: [&str; 8] = [
+    "output-dir", "remote-state", "resume", "notify-webhook", "notify-desktop", "tui", "cost-per-hour", "instance-type",
+];
+const SHORT_FLAGS: [(&str, &str); 5] = [("-c", "chunk-size"), ("-g", "gpu"), ("-n", "num-files"), ("-t", "test-wgs"), ("-f", "files")];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Affects {
+    Results,
+    Performance,
+    Both,
+}
+
+impl Affects {
+    fn of(setting: &str) -> Self {
+        if BOTH_SETTINGS.contains(&setting) || setting == "version" || setting == "device" {
+            Affects::Both
+        } else if RESULT_SETTINGS.contains(&setting) {
+            Affects::Results
+        } else {
+            Affects::Performance
+        }
+    }
+
+    fn results(self) -> bool {
+        self != Affects::Performance
+    }
+
+    fn performance(self) -> bool {
+        self != Affects::Results
+    }
+}
+
+// One side of the diff
+pub struct RunSide {
+    pub dir: PathBuf,
+    pub manifest: RunManifest,
+    pub run: u64,
+    pub benchmark: BenchmarkResult,
+    pub report: Option<WgsRunReport>,
+}
+
+impl RunSide {
+    // `path` is a run directory or its manifest.json; `run` defaults to the latest
+    pub fn load(path: &str, run: Option<u64>) -> Result<Self, String> {
+        let path = Path::new(path);
+        let (dir, manifest_path) = if path.is_dir() {
+            (path.to_path_buf(), path.join("manifest.json"))
+        } else {
+            (path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf(), path.to_path_buf())
+        };
+        let manifest = load_manifest(&manifest_path)?;
+        let results = dir.join("results");
+        let mut runs = load_runs(&results)?;
+        let (run, benchmark) = match run {
+            Some(run) => {
+                let index = runs.iter().position(|(number, _)| *number == run)
+                    .ok_or_else(|| format!("No run {} in {}", run, results.display()))?;
+                (run, runs.swap_remove(index).1)
+            },
+            None => runs.pop()
+                .ok_or_else(|| format!("No benchmark results in {}", results.display()))?,
+        };
+        let report_path = results.join(format!("run_{}_wgs_report.json", run));
+        let report = report_path.exists().then(|| WgsRunReport::load(&report_path)).transpose()?;
+        Ok(Self { dir, manifest, run, benchmark, report })
+    }
+
+    fn device(&self) -> Option<&str> {
+        self.manifest.devices.iter().rev()
+            .find(|device| device.run <= self.run)
+            .or(self.manifest.devices.last())
+            .map(|device| device.device.as_str())
+    }
+
+    // Flag -> value of the recorded command line, plus the version, device and seed
+    fn settings(&self) -> BTreeMap<String, String> {
+        let mut settings = command_settings(&self.manifest.command);
+        settings.insert("version".to_string(), self.manifest.version.clone());
+        if let Some(device) = self.device() {
+            settings.insert("device".to_string(), device.to_string());
+        }
+        if let Some(seed) = self.manifest.seed {
+            settings.insert("seed".to_string(), seed.to_string());
+        }
+        for ignored in IGNORED_SETTINGS {
+            settings.remove(ignored);
+        }
+        settings
+    }
+
+    fn summary(&self) -> RunSummary {
+        RunSummary {
+            dir: self.dir.display().to_string(),
+            run: self.run,
+            version: self.manifest.version.clone(),
+            device: self.device().map(str::to_string),
+            command: self.manifest.command.join(" "),
+            timestamp: self.benchmark.timestamp.to_rfc3339(),
+            aborted: self.benchmark.partial,
+        }
+    }
+}
+
+// --flag value, --flag=value and bare --flag (true) of a command line
+fn command_settings(command: &[String]) -> BTreeMap<String, String> {
+    let mut settings = BTreeMap::new();
+    let mut args = command.iter().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        let flag = match SHORT_FLAGS.iter().find(|(short, _)| short == arg) {
+            Some((_, long)) => long.to_string(),
+            None => match arg.strip_prefix("--") {
+                Some(flag) => flag.to_string(),
+                None => continue,
+            },
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => match args.peek() {
+                Some(next) if !next.starts_with("--") && !SHORT_FLAGS.iter().any(|(short, _)| short == *next) => (flag, args.next().cloned().unwrap_or_default()),
+                _ => (flag, "true".to_string()),
+            },
+        };
+        settings.insert(name, value);
+    }
+    settings
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub dir: String,
+    pub run: u64,
+    pub version: String,
+    pub device: Option<String>,
+    pub command: String,
+    pub timestamp: String,
+    pub aborted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingChange {
+    pub setting: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+    pub affects: Affects,
+}
+
+impl SettingChange {
+    fn describe(&self) -> String {
+        let value = |v: &Option<String>| v.clone().unwrap_or_else(|| "(not set)".to_string());
+        format!("{}: {} -> {}", self.setting, value(&self.a), value(&self.b))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricDiff {
+    pub metric: String,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl MetricDiff {
+    // Relative change from A to B, None when A is 0
+    pub fn change(&self) -> Option<f64> {
+        (self.a != 0.0).then(|| (self.b - self.a) / self.a.abs())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSide {
+    pub status: String,
+    // None for runs from before the run report
+    pub score: Option<i32>,
+    pub bases: usize,
+    pub score_per_base: Option<f64>,
+    pub bases_per_second: Option<f64>,
+    pub bottleneck: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    pub file: String,
+    pub a: Option<FileSide>,
+    pub b: Option<FileSide>,
+}
+
+impl FileDiff {
+    fn score_changed(&self) -> bool {
+        match (&self.a, &self.b) {
+            (Some(a), Some(b)) => a.score.is_some() && b.score.is_some() && a.score != b.score,
+            _ => false,
+        }
+    }
+
+    fn throughput_change(&self) -> Option<f64> {
+        let a = self.a.as_ref()?.bases_per_second.filter(|v| *v > 0.0)?;
+        let b = self.b.as_ref()?.bases_per_second?;
+        Some((b - a) / a)
+    }
+
+    fn bottleneck_moved(&self) -> Option<(&str, &str)> {
+        let a = self.a.as_ref()?.bottleneck.as_deref()?;
+        let b = self.b.as_ref()?.bottleneck.as_deref()?;
+        (a != b).then_some((a, b))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunDiff {
+    pub a: RunSummary,
+    pub b: RunSummary,
+    pub changes: Vec<SettingChange>,
+    pub totals: Vec<MetricDiff>,
+    pub files: Vec<FileDiff>,
+    // What the changes can account for, in plain sentences
+    pub findings: Vec<String>,
+}
+
+fn status_name(status: FileStatus) -> &'static str {
+    match status {
+        FileStatus::Completed => "completed",
+        FileStatus::Resumed => "resumed",
+        FileStatus::Failed => "failed",
+        FileStatus::Quarantined => "quarantined",
+    }
+}
+
+// Per file name: the report's outcome with the benchmark's timing of the file
+fn file_sides(side: &RunSide) -> BTreeMap<String, FileSide> {
+    let mut files = BTreeMap::new();
+    let timings = &side.benchmark.files;
+    if let Some(report) = &side.report {
+        for file in &report.files {
+            let timing = timings.iter().rev().find(|t| t.index == file.index);
+            files.insert(file.name().to_string(), FileSide {
+                status: status_name(file.status).to_string(),
+                score: Some(file.score),
+                bases: file.total_bases,
+                score_per_base: Some(file.normalized.score_per_base),
+                bases_per_second: timing.map(|t| t.bases_per_second()),
+                bottleneck: timing.and_then(|t| bottleneck(&t.stages)),
+            });
+        }
+    }
+    // Without a run report (runs from before it), the timings are all there is
+    for timing in timings {
+        files.entry(crate::paths::file_name(&timing.file).to_string()).or_insert_with(|| FileSide {
+            status: if timing.completed { "completed" } else { "failed" }.to_string(),
+            score: None,
+            bases: timing.bases,
+            score_per_base: None,
+            bases_per_second: Some(timing.bases_per_second()),
+            bottleneck: bottleneck(&timing.stages),
+        });
+    }
+    files
+}
+
+fn bottleneck(stages: &[crate::stages::StageStats]) -> Option<String> {
+    stages.iter()
+        .max_by(|a, b| a.busy_fraction().total_cmp(&b.busy_fraction()))
+        .map(|stage| stage.stage.clone())
+}
+
+fn totals(side: &RunSide) -> Vec<(&'static str, f64)> {
+    let mut totals = Vec::new();
+    if let Some(report) = &side.report {
+        totals.push(("completed files", report.totals.completed_files as f64));
+        totals.push(("score", report.totals.score as f64));
+        totals.push(("score per base", report.totals.normalized.score_per_base));
+    }
+    let b = &side.benchmark;
+    if side.report.is_none() {
+        totals.push(("score", b.total_score as f64));
+        totals.push(("score per base", b.normalized_score.score_per_base));
+    }
+    totals.extend([
+        ("reads", b.total_reads as f64),
+        ("bases", b.total_bases as f64),
+        ("time s", b.total_time_seconds),
+        ("bases/s", b.throughput_bases_per_second),
+        ("GPU utilization %", b.gpu_utilization_avg),
+        ("kernel ms", b.gpu_timing.kernel_ms),
+        ("transfer ms", b.gpu_timing.transfer_ms),
+        ("chunk latency p95 ms", b.chunk_latency.p95_ms),
+    ]);
+    totals
+}
+
+pub fn diff(a: &RunSide, b: &RunSide) -> RunDiff {
+    let (settings_a, settings_b) = (a.settings(), b.settings());
+    let mut names: Vec<&String> = settings_a.keys().chain(settings_b.keys()).collect();
+    names.sort();
+    names.dedup();
+    let changes: Vec<SettingChange> = names.into_iter()
+        .filter(|name| settings_a.get(*name) != settings_b.get(*name))
+        .map(|name| SettingChange {
+            setting: name.clone(),
+            a: settings_a.get(name).cloned(),
+            b: settings_b.get(name).cloned(),
+            affects: Affects::of(name),
+        })
+        .collect();
+
+    let totals_b = totals(b);
+    let totals: Vec<MetricDiff> = totals(a).into_iter()
+        .filter_map(|(metric, value_a)| {
+            let (_, value_b) = totals_b.iter().find(|(name, _)| *name == metric)?;
+            Some(MetricDiff { metric: metric.to_string(), a: value_a, b: *value_b })
+        })
+        .collect();
+
+    let (mut files_a, mut files_b) = (file_sides(a), file_sides(b));
+    let mut names: Vec<String> = files_a.keys().chain(files_b.keys()).cloned().collect();
+    names.sort();
+    names.dedup();
+    let files: Vec<FileDiff> = names.into_iter()
+        .map(|file| FileDiff { a: files_a.remove(&file), b: files_b.remove(&file), file })
+        .collect();
+
+    let findings = findings(&changes, &totals, &files);
+    RunDiff { a: a.summary(), b: b.summary(), changes, totals, files, findings }
+}
+
+fn findings(changes: &[SettingChange], totals: &[MetricDiff], files: &[FileDiff]) -> Vec<String> {
+    let mut findings = Vec::new();
+    let explain = |wanted: fn(Affects) -> bool| -> String {
+        let causes: Vec<String> = changes.iter().filter(|c| wanted(c.affects)).map(|c| c.describe()).collect();
+        match causes.is_empty() {
+            true => "no recorded setting that affects it changed; look at .env values and the input data, which the manifest doesn't record".to_string(),
+            false => format!("changed settings that can explain it: {}", causes.join("; ")),
+        }
+    };
+
+    let only: Vec<String> = files.iter()
+        .filter(|f| f.a.is_none() || f.b.is_none())
+        .map(|f| format!("{} (only in {})", f.file, if f.a.is_some() { "A" } else { "B" }))
+        .collect();
+    if !only.is_empty() {
+        findings.push(format!("The runs read different files: {}", only.join(", ")));
+    }
+    let status: Vec<String> = files.iter()
+        .filter_map(|f| match (&f.a, &f.b) {
+            (Some(a), Some(b)) if a.status != b.status => Some(format!("{} {} -> {}", f.file, a.status, b.status)),
+            _ => None,
+        })
+        .collect();
+    if !status.is_empty() {
+        findings.push(format!("Files ended differently: {}", status.join(", ")));
+    }
+
+    let scores = files.iter().filter(|f| f.score_changed()).count();
+    if scores > 0 {
+        findings.push(format!("Scores differ in {} of {} files; {}", scores, files.len(), explain(Affects::results)));
+    }
+
+    if let Some(change) = totals.iter().find(|t| t.metric == "bases/s").and_then(|t| t.change()) {
+        if change.abs() >= THROUGHPUT_CHANGE {
+            findings.push(format!("Throughput is {:+.1}% (bases/s); {}", change * 100.0, explain(Affects::performance)));
+        }
+    }
+    let moved: Vec<String> = files.iter()
+        .filter_map(|f| f.bottleneck_moved().map(|(a, b)| format!("{} {} -> {}", f.file, a, b)))
+        .collect();
+    if !moved.is_empty() {
+        findings.push(format!("Bottleneck stage moved: {}", moved.join(", ")));
+    }
+    if findings.is_empty() {
+        findings.push("Same files, same scores, and throughput within 5%".to_string());
+    }
+    findings
+}
+
+fn format_change(change: Option<f64>) -> String {
+    change.map_or_else(|| "-".to_string(), |c| format!("{:+.1}%", c * 100.0))
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 { format!("{}", value as i64) } else { format!("{:.4}", value) }
+}
+
+impl RunDiff {
+    pub fn print(&self) {
+        for (label, side) in [("A", &self.a), ("B", &self.b)] {
+            println!("{}: run {} in {} (version {}, {}{})", label, side.run, side.dir, side.version,
+                     side.device.as_deref().unwrap_or("device not recorded"), if side.aborted { ", aborted" } else { "" });
+            println!("   {}", side.command);
+        }
+
+        println!("\nSettings:");
+        if self.changes.is_empty() {
+            println!("   no changes");
+        }
+        for change in &self.changes {
+            println!("   {:<40} affects {}", change.describe(), match change.affects {
+                Affects::Results => "scores",
+                Affects::Performance => "speed",
+                Affects::Both => "scores and speed",
+            });
+        }
+
+        println!("\nTotals:");
+        println!("   {:<22} {:>18} {:>18} {:>9}", "", "A", "B", "change");
+        for total in &self.totals {
+            println!("   {:<22} {:>18} {:>18} {:>9}", total.metric, format_value(total.a), format_value(total.b), format_change(total.change()));
+        }
+
+        println!("\nFiles:");
+        println!("   {:<32} {:>14} {:>14} {:>12} {:>12} {:>9}  bottleneck", "", "score A", "score B", "Mbases/s A", "Mbases/s B", "change");
+        for file in &self.files {
+            let score = |side: &Option<FileSide>| side.as_ref().and_then(|s| s.score).map_or_else(|| "-".to_string(), |s| s.to_string());
+            let speed = |side: &Option<FileSide>| side.as_ref().and_then(|s| s.bases_per_second)
+                .map_or_else(|| "-".to_string(), |v| format!("{:.1}", v / 1e6));
+            let bottleneck = |side: &Option<FileSide>| side.as_ref().and_then(|s| s.bottleneck.clone()).unwrap_or_else(|| "-".to_string());
+            println!("   {:<32} {:>14} {:>14} {:>12} {:>12} {:>9}  {} -> {}{}", file.file, score(&file.a), score(&file.b),
+                     speed(&file.a), speed(&file.b), format_change(file.throughput_change()), bottleneck(&file.a), bottleneck(&file.b),
+                     if file.score_changed() { "  (score differs)" } else { "" });
+        }
+
+        println!("\nFindings:");
+        for finding in &self.findings {
+            println!("   {}", finding);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Persistent run numbering shared by perf_logger, benchmark, and checkpoints
+// Each run claims the next number from run_registry.json (in the output
+// directory, or the working directory without --output-dir), so logs/run_N,
+// run_N_benchmark_results.json, and checkpoint_run_N.json always refer to the
+// same run and numbering continues across processes. A run's record travels
+// in its RunContext; current() is the CLI's run, claimed on first use
+
+use crate::warnings::{self, Category};
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// A lock file older than this is left over from a crashed process
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub number: u64,
+    pub run_id: String,
+    pub started: DateTime<Utc>,
+    pub command: Vec<String>,
+    pub logs_dir: PathBuf,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunRegistry {
+    pub last_run: u64,
+    pub runs: Vec<RunRecord>,
+}
+
+static CURRENT_RUN: OnceCell<RunRecord> = OnceCell::new();
+
+// Exclusive lock on the registry, released on drop
+struct RegistryLock {
+    path: PathBuf,
+}
+
+impl RegistryLock {
+    fn acquire(registry: &Path) -> Result<Self, String> {
+        let path = registry.with_extension("lock");
+        let started = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = std::fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .map(|modified| modified.elapsed().unwrap_or_default() > STALE_LOCK)
+                        .unwrap_or(false);
+                    if stale {
+                        let _ = std::fs::remove_file(&path);
+                    } else if started.elapsed() > STALE_LOCK {
+                        return Err(format!("Timed out waiting for {}", path.display()));
+                    } else {
+                        std::thread::sleep(Duration::from_millis(20));
+                    }
+                },
+                Err(e) => return Err(format!("Failed to lock {}: {}", path.display(), e)),
+            }
+        }
+    }
+}
+
+impl Drop for RegistryLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl RunRecord {
+    fn new(number: u64) -> Self {
+        let started = Utc::now();
+        Self {
+            number,
+            run_id: format!("run_{}", started.timestamp()),
+            started,
+            command: std::env::args().collect(),
+            logs_dir: crate::output_layout::logs_dir(number),
+        }
+    }
+}
+
+impl RunRegistry {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    // Write to a temporary file and rename so readers never see a partial registry
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize run registry: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+        std::fs::rename(&tmp, path)
+            .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))?;
+        crate::remote_state::mirror(path);
+        Ok(())
+    }
+
+    // Claim the next run number under the registry lock
+    fn register(path: &Path) -> Result<RunRecord, String> {
+        let _lock = RegistryLock::acquire(path)?;
+        let mut registry = Self::load(path)?;
+        let record = RunRecord::new(registry.last_run + 1);
+        registry.last_run = record.number;
+        registry.runs.push(record.clone());
+        registry.save(path)?;
+        Ok(record)
+    }
+
+    pub fn find(&self, number: u64) -> Option<&RunRecord> {
+        self.runs.iter().find(|r| r.number == number)
+    }
+}
+
+// Claim the next run number
+// If the registry can't be written the run still gets a number, but it isn't persisted
+pub fn register() -> RunRecord {
+    let path = crate::output_layout::registry_file();
+    RunRegistry::register(&path).unwrap_or_else(|e| {
+        warnings::warn(Category::Storage, format!("run registry unavailable ({}), numbering this run from the existing registry only", e));
+        RunRecord::new(RunRegistry::load(&path).map(|r| r.last_run).unwrap_or(0) + 1)
+    })
+}
+
+// The CLI's run, registered on first use
+pub fn current() -> &'static RunRecord {
+    CURRENT_RUN.get_or_init(register)
+}
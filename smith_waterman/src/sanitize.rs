@@ -0,0 +1,203 @@
+// --sanitize: clean reads before they reach the GPU
+// Every read is uppercased and loses any embedded whitespace, then checked
+// against the alphabet. Characters outside it are replaced with the alphabet's
+// wildcard (N for nucleotides, X for protein) under `fix`, or drop the whole
+// read under `reject`. With --alphabet auto the alphabet is picked from the
+// first chunk: nucleotides when almost every letter is A/C/G/T/U/N, RNA when it
+// has U and no T, protein otherwise. RNA reads leave with U turned into T, so
+// they compare equal to DNA
+
+use crate::read_chunk::ReadChunk;
+
+// Share of letters in the first chunk that must be A/C/G/T/U/N for nucleotides
+const NUCLEOTIDE_SHARE: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    Auto,
+    Dna,
+    Rna,
+    Protein,
+}
+
+impl Alphabet {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(Alphabet::Auto),
+            "dna" => Ok(Alphabet::Dna),
+            "rna" => Ok(Alphabet::Rna),
+            "protein" => Ok(Alphabet::Protein),
+            _ => Err(format!("Invalid --alphabet '{}': expected auto, dna, rna, or protein", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Alphabet::Auto => "auto",
+            Alphabet::Dna => "DNA",
+            Alphabet::Rna => "RNA",
+            Alphabet::Protein => "protein",
+        }
+    }
+
+    // Uppercase characters accepted as they are; IUPAC ambiguity codes count for nucleotides
+    fn accepts(&self, base: u8) -> bool {
+        match self {
+            Alphabet::Dna => b"ACGTNRYKMSWBDHV".contains(&base),
+            Alphabet::Rna => b"ACGUNRYKMSWBDHV".contains(&base),
+            Alphabet::Protein => b"ACDEFGHIKLMNPQRSTVWYBZXJUO*".contains(&base),
+            Alphabet::Auto => true,
+        }
+    }
+
+    fn wildcard(&self) -> u8 {
+        match self {
+            Alphabet::Protein => b'X',
+            _ => b'N',
+        }
+    }
+
+    fn detect(chunk: &ReadChunk) -> Self {
+        let (mut letters, mut nucleotides, mut t, mut u) = (0usize, 0usize, 0usize, 0usize);
+        for &b in chunk.bases() {
+            let b = b.to_ascii_uppercase();
+            if !b.is_ascii_alphabetic() {
+                continue;
+            }
+            letters += 1;
+            match b {
+                b'T' => { nucleotides += 1; t += 1 },
+                b'U' => { nucleotides += 1; u += 1 },
+                b'A' | b'C' | b'G' | b'N' => nucleotides += 1,
+                _ => {},
+            }
+        }
+        if letters > 0 && (nucleotides as f64) < letters as f64 * NUCLEOTIDE_SHARE {
+            Alphabet::Protein
+        } else if u > 0 && t == 0 {
+            Alphabet::Rna
+        } else {
+            Alphabet::Dna
+        }
+    }
+}
+
+// U to T (either case), so RNA aligns against DNA
+pub fn rna_to_dna(bases: &mut [u8]) {
+    for base in bases.iter_mut() {
+        match *base {
+            b'U' => *base = b'T',
+            b'u' => *base = b't',
+            _ => {},
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidAction {
+    Fix,
+    Reject,
+}
+
+impl InvalidAction {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "fix" => Ok(InvalidAction::Fix),
+            "reject" => Ok(InvalidAction::Reject),
+            _ => Err(format!("Invalid --sanitize '{}': expected fix or reject", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SanitizeOptions {
+    pub alphabet: Alphabet,
+    pub action: InvalidAction,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SanitizeStats {
+    pub reads: usize,
+    pub uppercased: usize,
+    pub whitespace: usize,
+    pub invalid: usize,
+    pub fixed_reads: usize,
+    pub rejected_reads: usize,
+}
+
+impl SanitizeStats {
+    pub fn add(&mut self, other: &SanitizeStats) {
+        self.reads += other.reads;
+        self.uppercased += other.uppercased;
+        self.whitespace += other.whitespace;
+        self.invalid += other.invalid;
+        self.fixed_reads += other.fixed_reads;
+        self.rejected_reads += other.rejected_reads;
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{} reads: {} bases uppercased, {} whitespace removed, {} invalid characters ({} reads fixed, {} rejected)",
+                self.reads, self.uppercased, self.whitespace, self.invalid, self.fixed_reads, self.rejected_reads)
+    }
+}
+
+pub struct Sanitizer {
+    options: SanitizeOptions,
+    // Resolved from the first chunk under auto
+    alphabet: Option<Alphabet>,
+    read: Vec<u8>,
+}
+
+impl Sanitizer {
+    pub fn new(options: SanitizeOptions) -> Self {
+        let alphabet = (options.alphabet != Alphabet::Auto).then_some(options.alphabet);
+        Self { options, alphabet, read: Vec::new() }
+    }
+
+    pub fn alphabet(&self) -> Option<Alphabet> {
+        self.alphabet
+    }
+
+    // Copy the clean reads of `chunk` into `out`, adding what was changed to `stats`
+    pub fn sanitize_into(&mut self, chunk: &ReadChunk, out: &mut ReadChunk, stats: &mut SanitizeStats) {
+        let alphabet = *self.alphabet.get_or_insert_with(|| {
+            let detected = Alphabet::detect(chunk);
+            println!("    Sanitizer: detected {} alphabet", detected.name());
+            detected
+        });
+        out.clear();
+        for i in 0..chunk.len() {
+            stats.reads += 1;
+            self.read.clear();
+            let mut invalid = 0;
+            for &b in chunk.read(i) {
+                if b.is_ascii_whitespace() {
+                    stats.whitespace += 1;
+                    continue;
+                }
+                let upper = b.to_ascii_uppercase();
+                if upper != b {
+                    stats.uppercased += 1;
+                }
+                if alphabet.accepts(upper) {
+                    self.read.push(upper);
+                } else {
+                    invalid += 1;
+                    self.read.push(alphabet.wildcard());
+                }
+            }
+            stats.invalid += invalid;
+            if invalid > 0 && self.options.action == InvalidAction::Reject {
+                stats.rejected_reads += 1;
+                continue;
+            }
+            if invalid > 0 {
+                stats.fixed_reads += 1;
+            }
+            if alphabet == Alphabet::Rna {
+                rna_to_dna(&mut self.read);
+            }
+            out.push_with_bases(chunk, i, &self.read);
+        }
+    }
+}
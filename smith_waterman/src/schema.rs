@@ -0,0 +1,70 @@
+// Schema versions of the JSON files runs leave behind (checkpoints, benchmark
+// results, run reports), so a build can read the files of older ones
+// Each saved file carries schema_version. Files from before it was recorded
+// count as version 0. Loading goes through parse(): the JSON is read as a
+// value, brought up to the current version one migration at a time, then
+// deserialized. A file from a newer build is refused with an error that says
+// so, rather than a missing-field error or fields silently dropped
+// A change to a file's shape bumps its version by adding a migration to the
+// end of its list; fields that only gain a serde default don't need one
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+pub struct Schema {
+    pub name: &'static str,
+    // migrations[n] turns version n into version n + 1
+    migrations: &'static [fn(&mut Value)],
+}
+
+impl Schema {
+    pub const fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+}
+
+pub const CHECKPOINT: Schema = Schema { name: "checkpoint", migrations: &[checkpoint_v1] };
+pub const BENCHMARK: Schema = Schema { name: "benchmark result", migrations: &[benchmark_v1] };
+pub const RUN_REPORT: Schema = Schema { name: "run report", migrations: &[run_report_v1] };
+// Written by simulate learn-profile (learned_profile.rs)
+pub const ERROR_PROFILE: Schema = Schema { name: "error profile", migrations: &[] };
+
+// v1: provenance and devices, added before versions were recorded
+fn checkpoint_v1(value: &mut Value) {
+    if let Some(checkpoint) = value.as_object_mut() {
+        checkpoint.entry("provenance").or_insert(Value::Null);
+        checkpoint.entry("devices").or_insert_with(|| Value::Array(Vec::new()));
+    }
+}
+
+// v1: chunk sizes are in reads or bases; older results were always in reads
+fn benchmark_v1(value: &mut Value) {
+    if let Some(result) = value.as_object_mut() {
+        let unit = result.entry("chunk_unit").or_insert_with(|| Value::String(String::new()));
+        if unit.as_str().is_none_or(str::is_empty) {
+            *unit = Value::String("reads".to_string());
+        }
+    }
+}
+
+// v1: unchanged; reports from before versions were recorded have the same fields
+fn run_report_v1(_: &mut Value) {}
+
+// `json` as the current version of `schema`; `source` names the file in errors
+pub fn parse<T: DeserializeOwned>(schema: &Schema, json: &str, source: &str) -> Result<T, String> {
+    let mut value: Value = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse {} {}: {}", schema.name, source, e))?;
+    let version = value.get("schema_version").and_then(Value::as_u64).unwrap_or(0);
+    if version > schema.version() as u64 {
+        return Err(format!("{} {} has schema version {}, but this build reads up to version {}; read it with a newer rustseq_mini",
+                           schema.name, source, version, schema.version()));
+    }
+    for migrate in &schema.migrations[version as usize..] {
+        migrate(&mut value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("schema_version".to_string(), Value::from(schema.version()));
+    }
+    serde_json::from_value(value)
+        .map_err(|e| format!("Failed to parse {} {}: {}", schema.name, source, e))
+}
@@ -0,0 +1,150 @@
+// --dump-matrix: the full Smith-Waterman matrix of a small pair, for teaching and
+// for checking a kernel cell by cell while developing it
+// The matrix is the gapped local alignment that smith_waterman_detailed in
+// smith_waterman.cl computes a row at a time, done on the CPU one cell at a time
+// with nothing clever in it: H[i][j] = max(0, H[i-1][j-1] + s(i, j),
+// H[i-1][j] + gap, H[i][j-1] + gap), with the selected scores (scoring.rs) and N
+// scored by the N policy (dropped first under skip). Row 0 and column 0 are the
+// zero borders; rows are seq1, columns seq2. The pair mode's kernels (basic,
+// tiled, packed) score without gaps, so the matrix's best is an upper bound on
+// their score rather than equal to it; it is the reference for a gapped kernel
+// The whole matrix is kept, so each sequence may have at most MAX_BASES bases.
+// With --files, each file's reads are concatenated as the pair mode does
+// Formats, from the extension:
+//   .npy  int32 array of shape (len1 + 1, len2 + 1), for numpy.load
+//   .tsv  a header row of seq2's bases, then a row per seq1 base: the base and
+//         its scores (row and column 0 are labelled '-')
+
+use crate::chunking::{ChunkSize, DEFAULT_CHUNK_BASES};
+use crate::n_policy::is_n;
+use crate::read_chunk::ReadNameMode;
+use crate::scoring::Scoring;
+use std::io::Write;
+
+pub const MAX_BASES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixFormat {
+    Npy,
+    Tsv,
+}
+
+impl MatrixFormat {
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        match std::path::Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some("npy") => Ok(MatrixFormat::Npy),
+            Some("tsv") => Ok(MatrixFormat::Tsv),
+            _ => Err(format!("--dump-matrix {}: the file name must end in .npy or .tsv", path)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatrixFormat::Npy => "npy",
+            MatrixFormat::Tsv => "tsv",
+        }
+    }
+}
+
+pub struct ScoreMatrix {
+    seq1: Vec<u8>,
+    seq2: Vec<u8>,
+    // Row-major, (seq1.len() + 1) x (seq2.len() + 1)
+    cells: Vec<i32>,
+}
+
+impl ScoreMatrix {
+    pub fn compute(seq1: &[u8], seq2: &[u8], scoring: Scoring) -> Result<Self, String> {
+        let (seq1, seq2) = (crate::n_policy::prepare(seq1).into_owned(), crate::n_policy::prepare(seq2).into_owned());
+        for (name, seq) in [("seq1", &seq1), ("seq2", &seq2)] {
+            if seq.len() > MAX_BASES {
+                return Err(format!("--dump-matrix keeps the whole matrix, so each sequence may have at most {} bases; {} has {}",
+                                   MAX_BASES, name, seq.len()));
+            }
+        }
+        let n_score = crate::n_policy::selected().n_score();
+        let columns = seq2.len() + 1;
+        let mut cells = vec![0i32; (seq1.len() + 1) * columns];
+        for i in 1..=seq1.len() {
+            for j in 1..=seq2.len() {
+                let (a, b) = (seq1[i - 1], seq2[j - 1]);
+                let substitution = if is_n(a) || is_n(b) {
+                    n_score
+                } else if a == b {
+                    scoring.match_score
+                } else {
+                    scoring.mismatch_penalty
+                };
+                let diagonal = cells[(i - 1) * columns + j - 1] + substitution;
+                let up = cells[(i - 1) * columns + j] + scoring.gap_penalty;
+                let left = cells[i * columns + j - 1] + scoring.gap_penalty;
+                cells[i * columns + j] = diagonal.max(up).max(left).max(0);
+            }
+        }
+        Ok(Self { seq1, seq2, cells })
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        (self.seq1.len() + 1, self.seq2.len() + 1)
+    }
+
+    // The best score and its cell, the first one in row-major order
+    pub fn best(&self) -> (i32, usize, usize) {
+        let columns = self.seq2.len() + 1;
+        let (index, score) = self.cells.iter().enumerate()
+            .fold((0, 0), |best, (index, &score)| if score > best.1 { (index, score) } else { best });
+        (score, index / columns, index % columns)
+    }
+
+    pub fn write(&self, format: MatrixFormat, out: &mut dyn Write) -> std::io::Result<()> {
+        match format {
+            MatrixFormat::Npy => self.write_npy(out),
+            MatrixFormat::Tsv => self.write_tsv(out),
+        }
+    }
+
+    // NPY format version 1.0: magic, version, a little-endian u16 header length,
+    // then the header dict padded with spaces and a newline so the data starts
+    // on a multiple of 64 bytes
+    fn write_npy(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        let (rows, columns) = self.shape();
+        let mut header = format!("{{'descr': '<i4', 'fortran_order': False, 'shape': ({}, {}), }}", rows, columns);
+        let preamble = 10;
+        let padding = (64 - (preamble + header.len() + 1) % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+        out.write_all(b"\x93NUMPY\x01\x00")?;
+        out.write_all(&(header.len() as u16).to_le_bytes())?;
+        out.write_all(header.as_bytes())?;
+        for cell in &self.cells {
+            out.write_all(&cell.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_tsv(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        let columns = self.seq2.len() + 1;
+        let label = |base: Option<&u8>| base.map_or("-".to_string(), |&base| (base as char).to_string());
+        let header: Vec<String> = std::iter::once(label(None)).chain(self.seq2.iter().map(|base| label(Some(base)))).collect();
+        writeln!(out, "\t{}", header.join("\t"))?;
+        for (i, row) in self.cells.chunks(columns).enumerate() {
+            let scores: Vec<String> = row.iter().map(|score| score.to_string()).collect();
+            writeln!(out, "{}\t{}", label(i.checked_sub(1).and_then(|i| self.seq1.get(i))), scores.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+// Every read of `file` concatenated, as the pair mode aligns them, stopping
+// once there are more than MAX_BASES
+pub fn load_file(file: &str) -> Result<Vec<u8>, String> {
+    let mut bases = Vec::new();
+    crate::aligner::read_file_only(file, &Default::default(), ChunkSize::Bases(DEFAULT_CHUNK_BASES), ReadNameMode::Discard, |chunk| {
+        bases.extend_from_slice(chunk.bases());
+        bases.len() <= MAX_BASES
+    })?;
+    if bases.len() > MAX_BASES {
+        return Err(format!("--dump-matrix keeps the whole matrix, so each sequence may have at most {} bases; {} has more", MAX_BASES, file));
+    }
+    Ok(bases)
+}
@@ -0,0 +1,29 @@
+// Length-normalized score metrics
+// Raw totals grow with the amount of input, so files and runs with different
+// read counts are only comparable through these ratios
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct NormalizedScore {
+    pub score_per_base: f64,
+    pub score_per_read: f64,
+    // Fraction of the best possible score (every base a match), 0.0 to 1.0
+    pub identity_weighted: f64,
+}
+
+impl NormalizedScore {
+    pub fn from_totals(score: i64, reads: usize, bases: usize) -> Self {
+        let ratio = |denominator: f64| if denominator > 0.0 { score as f64 / denominator } else { 0.0 };
+        Self {
+            score_per_base: ratio(bases as f64),
+            score_per_read: ratio(reads as f64),
+            identity_weighted: ratio(bases as f64 * crate::scoring::selected().match_score as f64),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{:.4} per base, {:.2} per read, {:.2}% identity-weighted",
+                self.score_per_base, self.score_per_read, self.identity_weighted * 100.0)
+    }
+}
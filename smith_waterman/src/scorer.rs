@@ -0,0 +1,182 @@
+// Scorer: a CPU scoring function an Aligner can use instead of the kernels,
+// for prototyping a metric on the same file streaming, chunking, contig
+// windows, cancellation and progress reporting the built-in alignment runs on
+//   scorer::register(Arc::new(MyMetric))?;
+//   let aligner = Aligner::builder().backend(Backend::Cpu)
+//       .scorer(scorer::lookup("my-metric")?).build()?;
+//   let result = aligner.align_files("reads.fq", "contigs.fa")?;
+// A scorer gets pairs of sequences (for files, a chunk of file1 against a chunk
+// or contig window of file2) and returns an i32, summed over the chunk pairs
+// like the kernels' scores. A scorer that needs_qualities gets each chunk's
+// quality lines concatenated like its bases; the chunkers only keep them once an
+// aligner with such a scorer is built, and FASTA has none
+// Built in, by name:
+//   smith-waterman    the kernels' score, as the CPU backend computes it
+//   edit-distance     Levenshtein distance (quadratic: keep chunks small)
+//   hamming           mismatches over the shorter sequence, plus the bases
+//                     past its end
+//   quality-weighted  best ungapped local run along the main diagonal, each
+//                     base's match or mismatch score weighted by the chance
+//                     that both bases were called right
+// The built-ins use the process-wide scores (scoring.rs) as of lookup;
+// distances are counts, so a higher sum means more different sequences
+
+use crate::scoring::Scoring;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex};
+
+// Cells an edit distance may fill: 64 KB chunks on both sides
+pub const MAX_EDIT_CELLS: u64 = 1 << 32;
+const BUILT_IN: [&str; 4] = ["smith-waterman", "edit-distance", "hamming", "quality-weighted"];
+
+// Two sequences to score, with their Phred+33 qualities where there are any
+#[derive(Debug, Clone, Copy)]
+pub struct ScorePair<'a> {
+    pub seq1: &'a [u8],
+    pub seq2: &'a [u8],
+    pub quality1: Option<&'a [u8]>,
+    pub quality2: Option<&'a [u8]>,
+}
+
+impl<'a> ScorePair<'a> {
+    pub fn new(seq1: &'a [u8], seq2: &'a [u8]) -> Self {
+        Self { seq1, seq2, quality1: None, quality2: None }
+    }
+}
+
+pub trait Scorer: Send + Sync {
+    // Unique among registered scorers; results name the scorer by it
+    fn name(&self) -> &str;
+
+    fn needs_qualities(&self) -> bool {
+        false
+    }
+
+    fn score(&self, pair: &ScorePair) -> Result<i32, String>;
+}
+
+pub struct SmithWaterman {
+    pub scoring: Scoring,
+}
+
+impl Scorer for SmithWaterman {
+    fn name(&self) -> &str {
+        "smith-waterman"
+    }
+
+    fn score(&self, pair: &ScorePair) -> Result<i32, String> {
+        Ok(crate::cpu_align::cpu_align_bytes_scored(pair.seq1, pair.seq2, self.scoring))
+    }
+}
+
+pub struct EditDistance;
+
+impl Scorer for EditDistance {
+    fn name(&self) -> &str {
+        "edit-distance"
+    }
+
+    fn score(&self, pair: &ScorePair) -> Result<i32, String> {
+        // The shorter sequence is the row, so the two rows stay small
+        let (long, short) = if pair.seq1.len() >= pair.seq2.len() { (pair.seq1, pair.seq2) } else { (pair.seq2, pair.seq1) };
+        let cells = long.len() as u64 * short.len() as u64;
+        if cells > MAX_EDIT_CELLS {
+            return Err(format!("Edit distance of {} x {} bases is over {} cells; use a smaller chunk size (e.g. -c 64KB)",
+                               long.len(), short.len(), MAX_EDIT_CELLS));
+        }
+        let mut previous: Vec<u32> = (0..=short.len() as u32).collect();
+        let mut current = vec![0u32; short.len() + 1];
+        for (i, &a) in long.iter().enumerate() {
+            current[0] = i as u32 + 1;
+            for (j, &b) in short.iter().enumerate() {
+                let substitution = previous[j] + u32::from(a != b);
+                current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        i32::try_from(previous[short.len()]).map_err(|_| "Edit distance overflows an i32".to_string())
+    }
+}
+
+pub struct Hamming;
+
+impl Scorer for Hamming {
+    fn name(&self) -> &str {
+        "hamming"
+    }
+
+    fn score(&self, pair: &ScorePair) -> Result<i32, String> {
+        let mismatches = pair.seq1.iter().zip(pair.seq2).filter(|(a, b)| a != b).count();
+        let distance = mismatches + pair.seq1.len().abs_diff(pair.seq2.len());
+        i32::try_from(distance).map_err(|_| "Hamming distance overflows an i32".to_string())
+    }
+}
+
+pub struct QualityWeighted {
+    pub scoring: Scoring,
+}
+
+// Chance a base of Phred+33 quality `quality` was called right
+fn call_accuracy(quality: u8) -> f64 {
+    1.0 - 10f64.powf(-f64::from(quality.saturating_sub(33)) / 10.0)
+}
+
+impl Scorer for QualityWeighted {
+    fn name(&self) -> &str {
+        "quality-weighted"
+    }
+
+    fn needs_qualities(&self) -> bool {
+        true
+    }
+
+    fn score(&self, pair: &ScorePair) -> Result<i32, String> {
+        let accuracy = |quality: Option<&[u8]>, index: usize| quality.and_then(|quality| quality.get(index)).map_or(1.0, |&q| call_accuracy(q));
+        let (mut running, mut best) = (0f64, 0f64);
+        for (index, (a, b)) in pair.seq1.iter().zip(pair.seq2).enumerate() {
+            let score = if a == b { self.scoring.match_score } else { self.scoring.mismatch_penalty };
+            running = (running + f64::from(score) * accuracy(pair.quality1, index) * accuracy(pair.quality2, index)).max(0.0);
+            best = best.max(running);
+        }
+        Ok(best.round() as i32)
+    }
+}
+
+static REGISTERED: Lazy<Mutex<Vec<Arc<dyn Scorer>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn registered() -> std::sync::MutexGuard<'static, Vec<Arc<dyn Scorer>>> {
+    REGISTERED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Make `scorer` available to lookup (and --scorer) by its name
+pub fn register(scorer: Arc<dyn Scorer>) -> Result<(), String> {
+    let name = scorer.name().to_string();
+    if name.is_empty() || BUILT_IN.contains(&name.as_str()) {
+        return Err(format!("Can't register a scorer named '{}': names must be non-empty and not a built-in ({})", name, BUILT_IN.join(", ")));
+    }
+    let mut scorers = registered();
+    if scorers.iter().any(|registered| registered.name() == name) {
+        return Err(format!("A scorer named '{}' is already registered", name));
+    }
+    scorers.push(scorer);
+    Ok(())
+}
+
+// Built-ins, then registered scorers in the order they were registered
+pub fn names() -> Vec<String> {
+    BUILT_IN.iter().map(|name| name.to_string()).chain(registered().iter().map(|scorer| scorer.name().to_string())).collect()
+}
+
+pub fn lookup(name: &str) -> Result<Arc<dyn Scorer>, String> {
+    let scoring = crate::scoring::selected();
+    match name {
+        "smith-waterman" => Ok(Arc::new(SmithWaterman { scoring })),
+        "edit-distance" => Ok(Arc::new(EditDistance)),
+        "hamming" => Ok(Arc::new(Hamming)),
+        "quality-weighted" => Ok(Arc::new(QualityWeighted { scoring })),
+        _ => {
+            let found = registered().iter().find(|scorer| scorer.name() == name).cloned();
+            found.ok_or_else(|| format!("Unknown scorer '{}': expected one of {}", name, names().join(", ")))
+        },
+    }
+}
@@ -0,0 +1,67 @@
+// Match, mismatch and gap scores of the nucleotide aligners
+// Set with --match-score, --mismatch-penalty and --gap-penalty, or by a
+// --preset (presets.rs). Like the N policy they are process-wide: the OpenCL
+// program is built with them as defines, the wgpu pipeline gets them as
+// override constants, and the CPU path, the splice-aware aligner, ungapped
+// database search and the normalized scores read the same values. Penalties
+// are negative scores, as they are added to the running score
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scoring {
+    pub match_score: i32,
+    pub mismatch_penalty: i32,
+    // Per gapped base, in the kernels that have gaps and in --splice-aware
+    pub gap_penalty: i32,
+}
+
+// The scores the kernels were written with
+pub const DEFAULT: Scoring = Scoring { match_score: 2, mismatch_penalty: -1, gap_penalty: -2 };
+
+impl Default for Scoring {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+impl Scoring {
+    pub fn new(match_score: i32, mismatch_penalty: i32, gap_penalty: i32) -> Result<Self, String> {
+        if match_score <= 0 {
+            return Err(format!("--match-score must be positive, got {}", match_score));
+        }
+        if mismatch_penalty > 0 {
+            return Err(format!("--mismatch-penalty is added to the score, so it can't be positive (got {})", mismatch_penalty));
+        }
+        if gap_penalty > 0 {
+            return Err(format!("--gap-penalty is added to the score, so it can't be positive (got {})", gap_penalty));
+        }
+        Ok(Self { match_score, mismatch_penalty, gap_penalty })
+    }
+
+    // "match,mismatch,gap", as --sweep takes them
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid --sweep '{}': expected match,mismatch,gap, e.g. 1,-3,-5", value);
+        let scores = value.split(',').map(|score| score.trim().parse::<i32>().map_err(|_| invalid())).collect::<Result<Vec<_>, _>>()?;
+        let [match_score, mismatch_penalty, gap_penalty] = scores[..] else {
+            return Err(invalid());
+        };
+        Self::new(match_score, mismatch_penalty, gap_penalty).map_err(|e| format!("Invalid --sweep '{}': {}", value, e))
+    }
+
+    pub fn name(&self) -> String {
+        format!("match {}, mismatch {}, gap {}", self.match_score, self.mismatch_penalty, self.gap_penalty)
+    }
+}
+
+static SELECTED: OnceCell<Scoring> = OnceCell::new();
+
+// Set once at startup, before the OpenCL program is built
+pub fn configure(scoring: Scoring) {
+    let _ = SELECTED.set(scoring);
+}
+
+pub fn selected() -> Scoring {
+    SELECTED.get().copied().unwrap_or_default()
+}
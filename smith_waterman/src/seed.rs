@@ -0,0 +1,64 @@
+// --seed: one seed for everything random in a run
+// Pseudo-random draws go through Seedable, a small xorshift generator, so a
+// run with the same seed draws the same numbers on every machine. Each user
+// of the run seed takes its own stream (Seedable::stream), so adding draws to
+// one doesn't shift the others; `simulate` seeds with it as is, so its reads
+// for a given --seed are the ones it always gave. The seed is recorded in
+// manifest.json
+// The kernel checks (--verify-kernel) keep fixed inputs of their own: they
+// must test the same cases whatever the seed
+
+use once_cell::sync::OnceCell;
+
+pub const DEFAULT_SEED: u64 = 1;
+
+static RUN_SEED: OnceCell<u64> = OnceCell::new();
+
+// Set once at startup (first call wins)
+pub fn configure(seed: u64) {
+    let _ = RUN_SEED.set(seed);
+}
+
+pub fn run_seed() -> u64 {
+    RUN_SEED.get().copied().unwrap_or(DEFAULT_SEED)
+}
+
+#[derive(Debug, Clone)]
+pub struct Seedable(u64);
+
+impl Seedable {
+    pub fn new(seed: u64) -> Self {
+        // Spread small seeds out so the first few draws aren't near zero
+        Seedable(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    // The run seed, set apart for one use by `label`
+    pub fn stream(label: &str) -> Self {
+        // FNV-1a, so a label maps to the same stream in every build
+        let salt = label.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3));
+        Self::new(run_seed() ^ salt)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && self.next_f64() < p
+    }
+
+    pub fn base(&mut self) -> u8 {
+        b"ACGT"[self.below(4)]
+    }
+}
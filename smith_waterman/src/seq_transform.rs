@@ -0,0 +1,320 @@
+// Base-level transforms of large sequence files, on the GPU or the CPU
+//   revcomp   reverse complement (IUPAC codes complemented, case kept)
+//   upper     uppercase (drops soft-masking)
+//   u-to-t    RNA to DNA
+//   pack      2-bit packing, four bases per byte (A=0 C=1 G=2 T=3, first base
+//             in the low bits); other bytes pack as A, so unpacking them isn't lossless
+// The byte-wise transforms are folded into one 256-entry table and applied in
+// a single pass (translate_bases), mirrored for revcomp; pack_2bit packs. On
+// the GPU, sequences are sent in blocks of GPU_BLOCK bytes, so chromosomes
+// don't need one buffer each. `seq transform` streams a FASTA record by record
+// through them; pack writes PACKED_MAGIC, then per record the name (u32 length
+// + bytes), the base count (u64) and the packed bases
+
+use crate::gpu::GpuContext;
+use ocl::{Buffer, Kernel, MemFlags};
+use rayon::prelude::*;
+use std::io::{BufRead, Write};
+
+// Bytes per GPU launch
+const GPU_BLOCK: usize = 64 * 1024 * 1024;
+// Bytes per rayon task on the CPU
+const CPU_BLOCK: usize = 1024 * 1024;
+pub const PACKED_MAGIC: &[u8; 8] = b"RS2BIT01";
+const LINE_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    ReverseComplement,
+    Uppercase,
+    UToT,
+    Pack,
+}
+
+impl Transform {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "revcomp" => Ok(Transform::ReverseComplement),
+            "upper" => Ok(Transform::Uppercase),
+            "u-to-t" => Ok(Transform::UToT),
+            "pack" => Ok(Transform::Pack),
+            _ => Err(format!("Invalid transform '{}': expected revcomp, upper, u-to-t or pack", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::ReverseComplement => "revcomp",
+            Transform::Uppercase => "upper",
+            Transform::UToT => "u-to-t",
+            Transform::Pack => "pack",
+        }
+    }
+}
+
+// A comma-separated list, applied in order; pack only last
+pub fn parse_list(value: &str) -> Result<Vec<Transform>, String> {
+    let transforms = value.split(',').filter(|t| !t.trim().is_empty()).map(Transform::parse).collect::<Result<Vec<_>, _>>()?;
+    if transforms.is_empty() {
+        return Err("--ops needs at least one of revcomp, upper, u-to-t or pack".to_string());
+    }
+    if transforms.iter().rev().skip(1).any(|&t| t == Transform::Pack) {
+        return Err("pack turns sequences into binary, so it must be the last of --ops".to_string());
+    }
+    Ok(transforms)
+}
+
+fn complement(base: u8) -> u8 {
+    let complemented = match base.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' | b'U' => b'A',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        other => other,
+    };
+    if base.is_ascii_lowercase() { complemented.to_ascii_lowercase() } else { complemented }
+}
+
+fn table_of(f: impl Fn(u8) -> u8) -> [u8; 256] {
+    std::array::from_fn(|byte| f(byte as u8))
+}
+
+// The byte-wise transforms in `transforms` as one table, and whether the sequence is reversed
+fn translation(transforms: &[Transform]) -> ([u8; 256], bool) {
+    let mut table = table_of(|byte| byte);
+    let mut reverse = false;
+    for transform in transforms {
+        let step = match transform {
+            Transform::ReverseComplement => {
+                reverse = !reverse;
+                table_of(complement)
+            },
+            Transform::Uppercase => table_of(|byte| byte.to_ascii_uppercase()),
+            Transform::UToT => table_of(|byte| match byte {
+                b'U' => b'T',
+                b'u' => b't',
+                other => other,
+            }),
+            Transform::Pack => continue,
+        };
+        table = table.map(|byte| step[byte as usize]);
+    }
+    (table, reverse)
+}
+
+fn pack_codes() -> [u8; 256] {
+    table_of(|byte| match byte.to_ascii_uppercase() {
+        b'C' => 1,
+        b'G' => 2,
+        b'T' | b'U' => 3,
+        _ => 0,
+    })
+}
+
+fn cpu_translate(sequence: &[u8], table: &[u8; 256], reverse: bool) -> Vec<u8> {
+    let mut out = vec![0u8; sequence.len()];
+    out.par_chunks_mut(CPU_BLOCK).enumerate().for_each(|(block, out)| {
+        let start = block * CPU_BLOCK;
+        for (i, byte) in out.iter_mut().enumerate() {
+            let source = if reverse { sequence.len() - 1 - (start + i) } else { start + i };
+            *byte = table[sequence[source] as usize];
+        }
+    });
+    out
+}
+
+fn cpu_pack(sequence: &[u8]) -> Vec<u8> {
+    let codes = pack_codes();
+    sequence.par_chunks(4).map(|four| {
+        four.iter().enumerate().fold(0u8, |packed, (j, &base)| packed | codes[base as usize] << (j * 2))
+    }).collect()
+}
+
+fn upload(queue: &ocl::Queue, data: &[u8], what: &str) -> Result<Buffer<u8>, String> {
+    Buffer::<u8>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(data.len())
+        .copy_host_slice(data)
+        .build()
+        .map_err(|e| format!("Failed to create {} buffer: {}", what, e))
+}
+
+fn output_buffer(queue: &ocl::Queue, len: usize) -> Result<Buffer<u8>, String> {
+    Buffer::<u8>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().write_only())
+        .len(len)
+        .build()
+        .map_err(|e| format!("Failed to create output buffer: {}", e))
+}
+
+fn gpu_translate(sequence: &[u8], table: &[u8; 256], reverse: bool) -> Result<Vec<u8>, String> {
+    let gpu = GpuContext::shared()?;
+    let (queue, program) = (gpu.queue(), gpu.program()?);
+    let table_buffer = upload(queue, table, "translation table")?;
+    let mut out = vec![0u8; sequence.len()];
+    for start in (0..sequence.len()).step_by(GPU_BLOCK) {
+        let end = (start + GPU_BLOCK).min(sequence.len());
+        let input = upload(queue, &sequence[start..end], "sequence")?;
+        let output = output_buffer(queue, end - start)?;
+        let kernel = Kernel::builder()
+            .program(program)
+            .name("translate_bases")
+            .queue(queue.clone())
+            .global_work_size(end - start)
+            .arg(&input)
+            .arg(&output)
+            .arg(&table_buffer)
+            .arg((end - start) as u32)
+            .arg(reverse as u32)
+            .build()
+            .map_err(|e| format!("Failed to create translate kernel: {}", e))?;
+        unsafe {
+            kernel.enq().map_err(|e| format!("Failed to execute translate kernel: {}", e))?;
+        }
+        // A reversed block lands mirrored at the other end of the sequence
+        let target = if reverse { sequence.len() - end..sequence.len() - start } else { start..end };
+        output.read(&mut out[target]).enq()
+            .map_err(|e| format!("Failed to read translate kernel result: {}", e))?;
+    }
+    Ok(out)
+}
+
+fn gpu_pack(sequence: &[u8]) -> Result<Vec<u8>, String> {
+    let gpu = GpuContext::shared()?;
+    let (queue, program) = (gpu.queue(), gpu.program()?);
+    let codes = upload(queue, &pack_codes(), "pack code")?;
+    let mut out = vec![0u8; sequence.len().div_ceil(4)];
+    // Blocks are a multiple of 4 bases, so each packs into whole bytes
+    for start in (0..sequence.len()).step_by(GPU_BLOCK) {
+        let end = (start + GPU_BLOCK).min(sequence.len());
+        let packed_len = (end - start).div_ceil(4);
+        let input = upload(queue, &sequence[start..end], "sequence")?;
+        let output = output_buffer(queue, packed_len)?;
+        let kernel = Kernel::builder()
+            .program(program)
+            .name("pack_2bit")
+            .queue(queue.clone())
+            .global_work_size(packed_len)
+            .arg(&input)
+            .arg(&output)
+            .arg(&codes)
+            .arg((end - start) as u32)
+            .build()
+            .map_err(|e| format!("Failed to create pack kernel: {}", e))?;
+        unsafe {
+            kernel.enq().map_err(|e| format!("Failed to execute pack kernel: {}", e))?;
+        }
+        output.read(&mut out[start / 4..start / 4 + packed_len]).enq()
+            .map_err(|e| format!("Failed to read pack kernel result: {}", e))?;
+    }
+    Ok(out)
+}
+
+fn translate(sequence: &[u8], transforms: &[Transform], use_gpu: bool) -> Result<Vec<u8>, String> {
+    let (table, reverse) = translation(transforms);
+    if sequence.is_empty() {
+        return Ok(Vec::new());
+    }
+    if use_gpu { gpu_translate(sequence, &table, reverse) } else { Ok(cpu_translate(sequence, &table, reverse)) }
+}
+
+pub fn reverse_complement(sequence: &[u8], use_gpu: bool) -> Result<Vec<u8>, String> {
+    translate(sequence, &[Transform::ReverseComplement], use_gpu)
+}
+
+pub fn uppercase(sequence: &[u8], use_gpu: bool) -> Result<Vec<u8>, String> {
+    translate(sequence, &[Transform::Uppercase], use_gpu)
+}
+
+pub fn u_to_t(sequence: &[u8], use_gpu: bool) -> Result<Vec<u8>, String> {
+    translate(sequence, &[Transform::UToT], use_gpu)
+}
+
+pub fn pack_2bit(sequence: &[u8], use_gpu: bool) -> Result<Vec<u8>, String> {
+    if sequence.is_empty() {
+        return Ok(Vec::new());
+    }
+    if use_gpu { gpu_pack(sequence) } else { Ok(cpu_pack(sequence)) }
+}
+
+// `transforms` in order; a trailing pack returns packed bytes
+pub fn apply(sequence: &[u8], transforms: &[Transform], use_gpu: bool) -> Result<Vec<u8>, String> {
+    let translated = translate(sequence, transforms, use_gpu)?;
+    if transforms.last() == Some(&Transform::Pack) {
+        return pack_2bit(&translated, use_gpu);
+    }
+    Ok(translated)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransformSummary {
+    pub records: usize,
+    pub bases: u64,
+    // Bytes other than A/C/G/T/U that were packed as A
+    pub unpackable: u64,
+}
+
+// Every record of a FASTA (plain or compressed) through `transforms` into `output`
+// (FASTA, gzipped for .gz; the packed layout above when the last transform is pack)
+pub fn transform_fasta(input: &str, output: &str, transforms: &[Transform], use_gpu: bool) -> Result<TransformSummary, String> {
+    let detected = crate::input_format::detect_path(input)?;
+    if let Some(format) = detected.format.filter(|&f| f != crate::input_format::SeqFormat::Fasta) {
+        return Err(format!("{} is {}; seq transform reads FASTA", input, format.name()));
+    }
+    let file = std::fs::File::open(input).map_err(|e| format!("Failed to open {}: {}", input, e))?;
+    let reader = std::io::BufReader::new(detected.compression.native_decoder(file)?);
+    let mut out = crate::simulate::open_output(output)?;
+    let packing = transforms.last() == Some(&Transform::Pack);
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", output, e);
+    if packing {
+        out.write_all(PACKED_MAGIC).map_err(write_error)?;
+    }
+    let codes = pack_codes();
+    let mut summary = TransformSummary::default();
+    let mut record: Option<(String, Vec<u8>)> = None;
+    let mut finish = |record: Option<(String, Vec<u8>)>, summary: &mut TransformSummary| -> Result<(), String> {
+        let Some((name, sequence)) = record else { return Ok(()) };
+        let transformed = apply(&sequence, transforms, use_gpu)?;
+        summary.records += 1;
+        summary.bases += sequence.len() as u64;
+        if packing {
+            summary.unpackable += sequence.iter().filter(|&&b| codes[b as usize] == 0 && !b.eq_ignore_ascii_case(&b'A')).count() as u64;
+            out.write_all(&(name.len() as u32).to_le_bytes())
+                .and_then(|_| out.write_all(name.as_bytes()))
+                .and_then(|_| out.write_all(&(sequence.len() as u64).to_le_bytes()))
+                .and_then(|_| out.write_all(&transformed))
+                .map_err(write_error)?;
+        } else {
+            writeln!(out, ">{}", name).map_err(write_error)?;
+            for line in transformed.chunks(LINE_WIDTH) {
+                out.write_all(line).and_then(|_| out.write_all(b"\n")).map_err(write_error)?;
+            }
+        }
+        Ok(())
+    };
+    for line in reader.split(b'\n') {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", input, e))?;
+        let line = crate::read_chunk::trim_line_end(&line);
+        if let Some(header) = line.strip_prefix(b">") {
+            finish(record.take(), &mut summary)?;
+            record = Some((String::from_utf8_lossy(header).into_owned(), Vec::new()));
+        } else if let Some((_, sequence)) = record.as_mut() {
+            sequence.extend_from_slice(line);
+        } else if !line.is_empty() {
+            return Err(format!("{} is not FASTA: sequence before the first '>' header", input));
+        }
+    }
+    finish(record.take(), &mut summary)?;
+    out.flush().map_err(write_error)?;
+    Ok(summary)
+}
@@ -0,0 +1,442 @@
+// `db build` / `db search`: a persistent target set that queries are searched against
+// `db build` stores the targets of a FASTA file, encoded as nucleotide matrix rows
+// (ungapped.rs), with a k-mer index: every distinct k-mer of A/C/G/T bases
+// (2 bits per base) paired with each target containing it, sorted by k-mer.
+// `db search` aligns every query against every target, or with
+// --min-shared-kmers N only against the targets sharing at least N distinct
+// k-mers with it, and reports each query's best hits within the HitLimits.
+// --min-score-early-exit N scores pairs only as far as they can still reach N
+// (ungapped.rs); pairs that can't are screened out without a score
+// `index add` appends the targets of another FASTA file to a database, and
+// `index remove` tombstones targets by name, both without rebuilding it: the
+// file is a series of blocks, and each update appends one block, so the
+// targets already in it are neither re-encoded nor re-indexed. Each block of
+// targets carries its own sorted index, searched one after another. A removed
+// target keeps its place (target numbers never change) but is skipped by every
+// search; its sequence stays in the file until the database is built again
+// File layout, little endian: magic, version, k, then blocks until the end of
+// the file, each a kind byte and then
+//   targets: target count, each target's name and encoded sequence
+//            (length-prefixed), the index entry count and the (k-mer u64,
+//            target u32) entries, sorted
+//   removed: count and the numbers of the targets tombstoned
+// Version 1 files (one targets block without the kind byte) are still read;
+// the first `index add` or `index remove` rewrites them in this layout
+
+use crate::hit_limits::HitLimits;
+use crate::ungapped::{self, ScoreMatrix, SequenceSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 8] = b"RSQMINDB";
+const VERSION: u32 = 2;
+const TARGETS_BLOCK: u8 = 1;
+const REMOVED_BLOCK: u8 = 2;
+pub const DEFAULT_KMER: usize = 11;
+pub const MAX_KMER: usize = 31;
+pub const DEFAULT_TOP_N: usize = 10;
+pub const DEFAULT_MIN_SCORE: i32 = 1;
+
+// Queries aligned together when there is no prefilter
+pub const QUERIES_PER_BATCH: usize = 256;
+
+// Encoded row of N in the nucleotide matrix; k-mers never span it
+const ENCODED_N: u8 = 4;
+
+pub struct SequenceDb {
+    pub k: usize,
+    pub names: Vec<String>,
+    pub targets: SequenceSet,
+    // Tombstones, one per target
+    pub removed: Vec<bool>,
+    // One sorted index per targets block
+    index: Vec<Vec<(u64, u32)>>,
+    version: u32,
+}
+
+// Targets appended by `index add`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddSummary {
+    pub added: usize,
+    pub bases: usize,
+    pub index_entries: usize,
+    // Live targets of the same name tombstoned (--replace)
+    pub replaced: usize,
+}
+
+// Distinct k-mers of an encoded sequence, sorted
+fn kmers(encoded: &[u8], k: usize) -> Vec<u64> {
+    let mask = if k >= 32 { u64::MAX } else { (1u64 << (2 * k)) - 1 };
+    let (mut kmer, mut run) = (0u64, 0usize);
+    let mut out = Vec::new();
+    for &code in encoded {
+        if code >= ENCODED_N {
+            run = 0;
+            continue;
+        }
+        kmer = ((kmer << 2) | code as u64) & mask;
+        run += 1;
+        if run >= k {
+            out.push(kmer);
+        }
+    }
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+fn write_targets_block(out: &mut impl Write, names: &[String], targets: &SequenceSet, first: usize, index: &[(u64, u32)]) -> std::io::Result<()> {
+    out.write_all(&[TARGETS_BLOCK])?;
+    out.write_all(&((names.len() - first) as u32).to_le_bytes())?;
+    for (i, name) in names.iter().enumerate().skip(first) {
+        out.write_all(&(name.len() as u32).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        let sequence = targets.get(i);
+        out.write_all(&(sequence.len() as u64).to_le_bytes())?;
+        out.write_all(sequence)?;
+    }
+    out.write_all(&(index.len() as u64).to_le_bytes())?;
+    for &(kmer, target) in index {
+        out.write_all(&kmer.to_le_bytes())?;
+        out.write_all(&target.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_removed_block(out: &mut impl Write, targets: &[u32]) -> std::io::Result<()> {
+    out.write_all(&[REMOVED_BLOCK])?;
+    out.write_all(&(targets.len() as u32).to_le_bytes())?;
+    for target in targets {
+        out.write_all(&target.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+impl SequenceDb {
+    pub fn build(fasta: &str, k: usize) -> Result<Self, String> {
+        if k == 0 || k > MAX_KMER {
+            return Err(format!("Invalid k-mer length {}: expected 1 to {}", k, MAX_KMER));
+        }
+        let mut db = Self { k, names: Vec::new(), targets: SequenceSet::new(), removed: Vec::new(), index: Vec::new(), version: VERSION };
+        db.add_targets(fasta)?;
+        Ok(db)
+    }
+
+    // Encode the targets of `fasta` after the current ones, with their own index
+    fn add_targets(&mut self, fasta: &str) -> Result<usize, String> {
+        let matrix = ScoreMatrix::nucleotide();
+        let first = self.names.len();
+        let mut index = Vec::new();
+        for (name, sequence) in crate::simulate::load_reference(fasta)? {
+            self.targets.push(&sequence, &matrix);
+            let target = self.names.len() as u32;
+            index.extend(kmers(self.targets.get(target as usize), self.k).into_iter().map(|kmer| (kmer, target)));
+            self.names.push(name);
+            self.removed.push(false);
+        }
+        index.sort_unstable();
+        self.index.push(index);
+        Ok(first)
+    }
+
+    pub fn index_entries(&self) -> usize {
+        self.index.iter().map(Vec::len).sum()
+    }
+
+    // Targets not removed, by number
+    pub fn live_targets(&self) -> Vec<usize> {
+        (0..self.names.len()).filter(|&t| !self.removed[t]).collect()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        self.write_to(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    // Header, one targets block with every target, and the tombstones
+    fn write_to(&self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&(self.k as u32).to_le_bytes())?;
+        let mut index: Vec<(u64, u32)> = self.index.concat();
+        index.sort_unstable();
+        write_targets_block(out, &self.names, &self.targets, 0, &index)?;
+        let removed: Vec<u32> = (0..self.names.len() as u32).filter(|&t| self.removed[t as usize]).collect();
+        if !removed.is_empty() {
+            write_removed_block(out, &removed)?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open database {}: {}", path, e))?;
+        Self::read_from(&mut BufReader::new(file))
+            .map_err(|e| format!("Failed to read database {}: {}", path, e))
+    }
+
+    fn read_from(input: &mut impl Read) -> Result<Self, String> {
+        let mut read = |len: usize| -> Result<Vec<u8>, String> {
+            let mut bytes = vec![0u8; len];
+            input.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+            Ok(bytes)
+        };
+        let u32_at = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap_or_default());
+        let u64_at = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap_or_default());
+        if read(MAGIC.len())? != MAGIC {
+            return Err("not a database written by db build".to_string());
+        }
+        let version = u32_at(&read(4)?);
+        if version != 1 && version != VERSION {
+            return Err(format!("format version {}, this build reads versions 1 and {} (run db build again)", version, VERSION));
+        }
+        let k = u32_at(&read(4)?) as usize;
+        let mut db = Self { k, names: Vec::new(), targets: SequenceSet::new(), removed: Vec::new(), index: Vec::new(), version };
+        loop {
+            let kind = if version == 1 {
+                if !db.index.is_empty() {
+                    break;
+                }
+                TARGETS_BLOCK
+            } else {
+                match read(1) {
+                    Ok(kind) => kind[0],
+                    // The end of the file, between blocks
+                    Err(_) => break,
+                }
+            };
+            match kind {
+                TARGETS_BLOCK => {
+                    let count = u32_at(&read(4)?) as usize;
+                    for _ in 0..count {
+                        let name_len = u32_at(&read(4)?) as usize;
+                        db.names.push(String::from_utf8_lossy(&read(name_len)?).into_owned());
+                        let sequence_len = u64_at(&read(8)?) as usize;
+                        db.targets.push_encoded(&read(sequence_len)?);
+                        db.removed.push(false);
+                    }
+                    let entries = u64_at(&read(8)?) as usize;
+                    db.index.push(read(entries * 12)?.chunks_exact(12)
+                        .map(|entry| (u64_at(&entry[..8]), u32_at(&entry[8..])))
+                        .collect());
+                },
+                REMOVED_BLOCK => {
+                    let count = u32_at(&read(4)?) as usize;
+                    for entry in read(count * 4)?.chunks_exact(4) {
+                        let target = u32_at(entry) as usize;
+                        *db.removed.get_mut(target).ok_or_else(|| format!("removes target {} of {}", target + 1, db.names.len()))? = true;
+                    }
+                },
+                other => return Err(format!("unknown block kind {}", other)),
+            }
+        }
+        Ok(db)
+    }
+
+    // `index add`: append the targets of `fasta` to the database at `path`.
+    // With `replace`, live targets with the name of a new one are tombstoned;
+    // without it, such a name is an error
+    pub fn append(path: &str, fasta: &str, replace: bool) -> Result<AddSummary, String> {
+        let mut db = Self::load(path)?;
+        let (first_base, first_entries) = (db.targets.residues.len(), db.index_entries());
+        let first = db.add_targets(fasta)?;
+        let mut replaced = Vec::new();
+        for new in first..db.names.len() {
+            if db.names[first..new].contains(&db.names[new]) {
+                return Err(format!("{} has target {} more than once", fasta, db.names[new]));
+            }
+            if let Some(old) = (0..first).find(|&t| !db.removed[t] && db.names[t] == db.names[new]) {
+                if !replace {
+                    return Err(format!("{} already has a target {}; pass --replace to tombstone it", path, db.names[new]));
+                }
+                db.removed[old] = true;
+                replaced.push(old as u32);
+            }
+        }
+        let index = db.index.last().map_or(&[][..], Vec::as_slice);
+        db.append_blocks(path, |out| {
+            write_targets_block(out, &db.names, &db.targets, first, index)?;
+            if !replaced.is_empty() {
+                write_removed_block(out, &replaced)?;
+            }
+            Ok(())
+        })?;
+        Ok(AddSummary {
+            added: db.names.len() - first,
+            bases: db.targets.residues.len() - first_base,
+            index_entries: db.index_entries() - first_entries,
+            replaced: replaced.len(),
+        })
+    }
+
+    // `index remove`: tombstone the live targets named in `names`
+    pub fn remove(path: &str, names: &[String]) -> Result<usize, String> {
+        let db = Self::load(path)?;
+        let mut removed = Vec::new();
+        for name in names {
+            let target = (0..db.names.len()).find(|&t| !db.removed[t] && &db.names[t] == name)
+                .ok_or_else(|| format!("{} has no target {}", path, name))?;
+            if !removed.contains(&(target as u32)) {
+                removed.push(target as u32);
+            }
+        }
+        db.append_blocks(path, |out| write_removed_block(out, &removed))?;
+        Ok(removed.len())
+    }
+
+    // Append blocks to the file at `path`, rewriting a version 1 file first. A
+    // failed append is cut off again, so the file stays readable
+    fn append_blocks(&self, path: &str, write: impl FnOnce(&mut BufWriter<&File>) -> std::io::Result<()>) -> Result<(), String> {
+        if self.version != VERSION {
+            let mut current = Self::load(path)?;
+            current.version = VERSION;
+            current.save(path)?;
+        }
+        let file = std::fs::OpenOptions::new().append(true).open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let length = file.metadata().map_err(|e| format!("Failed to read {}: {}", path, e))?.len();
+        let mut out = BufWriter::new(&file);
+        let written = write(&mut out).and_then(|_| out.flush());
+        drop(out);
+        if let Err(e) = written.and_then(|_| file.sync_all()) {
+            let _ = file.set_len(length);
+            return Err(format!("Failed to append to {}: {}", path, e));
+        }
+        Ok(())
+    }
+
+    // Distinct k-mers each target shares with an encoded query
+    fn shared_kmers(&self, query: &[u8]) -> Vec<u32> {
+        let mut shared = vec![0u32; self.names.len()];
+        for kmer in kmers(query, self.k) {
+            for index in &self.index {
+                let start = index.partition_point(|&(k, _)| k < kmer);
+                for &(_, target) in index[start..].iter().take_while(|&&(k, _)| k == kmer) {
+                    shared[target as usize] += 1;
+                }
+            }
+        }
+        shared
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    // 0 aligns every query against every target
+    pub min_shared_kmers: u32,
+    pub limits: HitLimits,
+    pub use_gpu: bool,
+    // --min-score-early-exit; 0 scores every pair in full
+    pub early_exit: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    pub target: usize,
+    pub score: i32,
+    pub shared_kmers: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchSummary {
+    pub queries: usize,
+    // Query/target pairs aligned
+    pub alignments: usize,
+    pub hits: usize,
+    pub queries_with_hits: usize,
+    // Aligned pairs abandoned below --min-score-early-exit
+    pub screened: usize,
+}
+
+// Pairs that didn't reach the early exit threshold, which score 0
+fn screened(scores: &[i32], early_exit: i32) -> usize {
+    if early_exit > 0 { scores.iter().filter(|&&score| score == 0).count() } else { 0 }
+}
+
+// The targets searches go through: every live one, with their encoded
+// sequences gathered once when some targets are removed
+pub struct LiveTargets {
+    pub ids: Vec<usize>,
+    set: Option<SequenceSet>,
+}
+
+impl LiveTargets {
+    pub fn new(db: &SequenceDb) -> Self {
+        let ids = db.live_targets();
+        let set = (ids.len() != db.names.len()).then(|| {
+            let mut set = SequenceSet::new();
+            for &t in &ids {
+                set.push_encoded(db.targets.get(t));
+            }
+            set
+        });
+        Self { ids, set }
+    }
+
+    fn sequences<'a>(&'a self, db: &'a SequenceDb) -> &'a SequenceSet {
+        self.set.as_ref().unwrap_or(&db.targets)
+    }
+}
+
+// Every hit of each encoded query against the live targets, in one launch
+// without a prefilter or one per query with it, before the HitLimits
+pub fn batch_hits(db: &SequenceDb, live: &LiveTargets, encoded: &SequenceSet, options: SearchOptions,
+                  summary: &mut SearchSummary) -> Result<Vec<Vec<Hit>>, String> {
+    let matrix = ScoreMatrix::nucleotide();
+    let queries = encoded.len();
+    let shared: Vec<Vec<u32>> = (0..queries).map(|q| db.shared_kmers(encoded.get(q))).collect();
+    if options.min_shared_kmers == 0 {
+        let scores = ungapped::screened_scores(encoded, live.sequences(db), &matrix, options.use_gpu, options.early_exit)?;
+        summary.alignments += queries * live.ids.len();
+        summary.screened += screened(&scores, options.early_exit);
+        return Ok((0..queries).map(|q| live.ids.iter().enumerate()
+            .map(|(i, &t)| Hit { target: t, score: scores[i * queries + q], shared_kmers: shared[q][t] })
+            .collect()).collect());
+    }
+    let mut hits = Vec::with_capacity(queries);
+    for (q, shared) in shared.iter().enumerate() {
+        let candidates: Vec<usize> = live.ids.iter().copied().filter(|&t| shared[t] >= options.min_shared_kmers).collect();
+        let mut query = SequenceSet::new();
+        query.push_encoded(encoded.get(q));
+        let mut subset = SequenceSet::new();
+        for &t in &candidates {
+            subset.push_encoded(db.targets.get(t));
+        }
+        let scores = ungapped::screened_scores(&query, &subset, &matrix, options.use_gpu, options.early_exit)?;
+        summary.alignments += candidates.len();
+        summary.screened += screened(&scores, options.early_exit);
+        hits.push(candidates.iter().zip(scores)
+            .map(|(&t, score)| Hit { target: t, score, shared_kmers: shared[t] })
+            .collect());
+    }
+    Ok(hits)
+}
+
+// Search every query of a FASTA file and write each one's hits as TSV
+pub fn search(db: &SequenceDb, query_path: &str, options: SearchOptions, out: &mut dyn Write) -> Result<SearchSummary, String> {
+    let matrix = ScoreMatrix::nucleotide();
+    let queries = crate::simulate::load_reference(query_path)?;
+    let live = LiveTargets::new(db);
+    let mut summary = SearchSummary::default();
+    let write_error = |e: std::io::Error| format!("Failed to write hits: {}", e);
+    writeln!(out, "#query\trank\ttarget\tscore\tshared_kmers").map_err(write_error)?;
+
+    for batch in queries.chunks(QUERIES_PER_BATCH) {
+        let mut encoded = SequenceSet::new();
+        for (_, sequence) in batch {
+            encoded.push(sequence, &matrix);
+        }
+        let hits = batch_hits(db, &live, &encoded, options, &mut summary)?;
+        for ((name, _), mut hits) in batch.iter().zip(hits) {
+            summary.queries += 1;
+            options.limits.select(&mut hits, |hit| hit.score);
+            summary.hits += hits.len();
+            summary.queries_with_hits += usize::from(!hits.is_empty());
+            for (rank, hit) in hits.iter().enumerate() {
+                writeln!(out, "{}\t{}\t{}\t{}\t{}", name, rank + 1, db.names[hit.target], hit.score, hit.shared_kmers).map_err(write_error)?;
+            }
+        }
+    }
+    Ok(summary)
+}
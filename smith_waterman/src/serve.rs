@@ -0,0 +1,426 @@
+// `serve`: a long-running process taking full WGS jobs and database searches
+// from clients over TCP
+// Each request is one line of JSON and gets one line of JSON back:
+//   {"op": "submit", "name": "NA12878", "data_dir": "/data/NA12878",
+//    "input_files": ["a.fastq.gz"], "priority": 2, "memory_quota": 4294967296}
+//                                 -> {"ok": true, "job": 1}
+//   {"op": "status", "job": 1}    -> {"ok": true, "jobs": [<the job>]}
+//   {"op": "jobs"}                -> {"ok": true, "jobs": [<every job>]}
+//   {"op": "cancel", "job": 1}    -> {"ok": true, "job": 1}
+//   {"op": "search", "sequence": "ACGT..."}
+//                                 -> {"ok": true, "hits": [{"target": "chr1",
+//                                     "score": 42, "shared_kmers": 7}, ...]}
+// A request that can't be served gets {"ok": false, "error": "..."}
+// Each job runs on a thread of its own with a RunContext of its own
+// (run_context.rs), so it has its own run number, logs, checkpoint, benchmark
+// result and warnings. Every job joins the server's GpuScheduler with its
+// priority and memory quota when it is submitted, and its launches take their
+// slots under it: jobs running at once interleave their launches by priority
+// instead of the first one flooding the device (gpu_scheduler.rs)
+// At most max_jobs run at once; later ones wait in the order they came in. A
+// job starts from the server's WgsConfig (.env and the command line, as for
+// --full-wgs) with its own name as the sample, data directory and inputs.
+// Jobs never resume: `--resume` picks the latest checkpoint in the output
+// directory, which may be another job's
+// Cancelling stops a running job at its next chunk boundary, as a
+// CancellationToken does, and drops a queued one before it starts
+// Searches need a database (`--db`). Every client is served on a thread of its
+// own, and their searches all go into the server's QueryBatcher
+// (query_batcher.rs), so short queries arriving at once from many clients are
+// aligned together in one launch and each client gets back its own hits. One
+// client's requests are answered in order, so its queries batch only with
+// other clients'
+
+use crate::engine::Aligner;
+use crate::gpu_scheduler::{GpuScheduler, Job, JobOptions, JobStats};
+use crate::query_batcher::{BatcherStats, QueryBatcher};
+use crate::run_context::RunContext;
+use crate::run_control::{CancellationToken, Progress};
+use crate::sequence_db::SequenceDb;
+use crate::wgs_config::WgsConfig;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7878";
+pub const DEFAULT_MAX_JOBS: usize = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Request {
+    Submit(JobRequest),
+    Status { job: u64 },
+    Jobs,
+    Cancel { job: u64 },
+    Search { sequence: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobRequest {
+    // Also the run's sample id
+    pub name: String,
+    pub data_dir: String,
+    // Explicit inputs instead of the lane layout, as WgsConfig::input_files
+    #[serde(default)]
+    pub input_files: Option<Vec<String>>,
+    #[serde(default = "default_priority")]
+    pub priority: u32,
+    // Sequence bytes the job's launches may hold on the GPU at once
+    #[serde(default)]
+    pub memory_quota: Option<u64>,
+}
+
+fn default_priority() -> u32 {
+    JobOptions::default().priority
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn is_finished(self) -> bool {
+        matches!(self, JobState::Completed | JobState::Failed | JobState::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub job: u64,
+    pub name: String,
+    pub state: JobState,
+    pub priority: u32,
+    // The run's number, once it has started
+    pub run: Option<u64>,
+    // Files finished of the run's files, and bases of the current file so far
+    pub files_done: usize,
+    pub total_files: usize,
+    pub bases: usize,
+    // Total score of a completed run
+    pub score: Option<i64>,
+    pub error: Option<String>,
+    // The job's share of the GPU so far (gpu_scheduler::JobStats)
+    pub launches: u64,
+    pub gpu_time_s: f64,
+    pub waited_s: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub target: String,
+    pub score: i32,
+    pub shared_kmers: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<Vec<JobStatus>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hits: Option<Vec<SearchHit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reply {
+    fn error(message: impl Into<String>) -> Self {
+        Self { error: Some(message.into()), ..Default::default() }
+    }
+}
+
+struct Entry {
+    status: JobStatus,
+    cancel: CancellationToken,
+    // Dropped when the job finishes, so it leaves the scheduler
+    gpu_job: Option<Job>,
+}
+
+struct Jobs {
+    entries: Vec<Entry>,
+    running: usize,
+    next_id: u64,
+}
+
+impl Jobs {
+    fn entry(&mut self, id: u64) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.status.job == id)
+    }
+}
+
+pub struct Server {
+    aligner: Aligner,
+    base: WgsConfig,
+    scheduler: GpuScheduler,
+    max_jobs: usize,
+    jobs: Mutex<Jobs>,
+    changed: Condvar,
+    // The database searches go to, and the batcher they are queued on
+    search: Option<(Arc<SequenceDb>, QueryBatcher)>,
+}
+
+impl Server {
+    // Jobs run on `aligner`, from `base`, at most `max_jobs` at once and with
+    // their launches scheduled by `scheduler`
+    pub fn new(aligner: Aligner, base: WgsConfig, scheduler: GpuScheduler, max_jobs: usize) -> Arc<Self> {
+        Self::build(aligner, base, scheduler, max_jobs, None)
+    }
+
+    // As `new`, also answering searches of `db` through `batcher`, which must
+    // search the same database
+    pub fn with_search(aligner: Aligner, base: WgsConfig, scheduler: GpuScheduler, max_jobs: usize,
+                       db: Arc<SequenceDb>, batcher: QueryBatcher) -> Arc<Self> {
+        Self::build(aligner, base, scheduler, max_jobs, Some((db, batcher)))
+    }
+
+    fn build(aligner: Aligner, base: WgsConfig, scheduler: GpuScheduler, max_jobs: usize,
+             search: Option<(Arc<SequenceDb>, QueryBatcher)>) -> Arc<Self> {
+        let jobs = Jobs { entries: Vec::new(), running: 0, next_id: 1 };
+        Arc::new(Self { aligner, base, scheduler, max_jobs: max_jobs.max(1), jobs: Mutex::new(jobs), changed: Condvar::new(), search })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Jobs> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Accept clients on `listener` until it fails, each on a thread of its own
+    pub fn serve(self: &Arc<Self>, listener: TcpListener) -> Result<(), String> {
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| format!("Failed to accept a connection: {}", e))?;
+            let server = Arc::clone(self);
+            let spawned = std::thread::Builder::new()
+                .name("serve-client".to_string())
+                .spawn(move || server.client(stream));
+            if let Err(e) = spawned {
+                eprintln!("Warning: failed to start a client thread: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    // One reply line per request line, until the client hangs up
+    fn client(self: &Arc<Self>, stream: TcpStream) {
+        let Ok(mut writer) = stream.try_clone() else { return };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { return };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => self.handle(request),
+                Err(e) => Reply::error(format!("Invalid request: {}", e)),
+            };
+            let json = serde_json::to_string(&reply).unwrap_or_else(|e| format!("{{\"ok\":false,\"error\":\"{}\"}}", e));
+            if writeln!(writer, "{}", json).and_then(|_| writer.flush()).is_err() {
+                return;
+            }
+        }
+    }
+
+    pub fn handle(self: &Arc<Self>, request: Request) -> Reply {
+        match request {
+            Request::Submit(job) => match self.submit(job) {
+                Ok(id) => Reply { ok: true, job: Some(id), ..Default::default() },
+                Err(e) => Reply::error(e),
+            },
+            Request::Status { job } => match self.status(job) {
+                Some(status) => Reply { ok: true, jobs: Some(vec![status]), ..Default::default() },
+                None => Reply::error(format!("No job {}", job)),
+            },
+            Request::Jobs => Reply { ok: true, jobs: Some(self.statuses()), ..Default::default() },
+            Request::Cancel { job } => match self.cancel(job) {
+                Ok(()) => Reply { ok: true, job: Some(job), ..Default::default() },
+                Err(e) => Reply::error(e),
+            },
+            Request::Search { sequence } => match self.search(&sequence) {
+                Ok(hits) => Reply { ok: true, hits: Some(hits), ..Default::default() },
+                Err(e) => Reply::error(e),
+            },
+        }
+    }
+
+    // Hits of one query, best first; blocks until the launch it was batched
+    // into has finished
+    pub fn search(&self, sequence: &str) -> Result<Vec<SearchHit>, String> {
+        let (db, batcher) = self.search.as_ref().ok_or("This server has no database to search (start it with --db)")?;
+        if sequence.trim().is_empty() {
+            return Err("A search needs a sequence".to_string());
+        }
+        let hits = batcher.search(sequence.trim().as_bytes())?;
+        Ok(hits.into_iter()
+            .map(|hit| SearchHit { target: db.names[hit.target].clone(), score: hit.score, shared_kmers: hit.shared_kmers })
+            .collect())
+    }
+
+    // Queries searched so far and the launches they took; None without a database
+    pub fn search_stats(&self) -> Option<BatcherStats> {
+        self.search.as_ref().map(|(_, batcher)| batcher.stats())
+    }
+
+    // Queue `request` and start its thread; the job's id
+    pub fn submit(self: &Arc<Self>, request: JobRequest) -> Result<u64, String> {
+        if request.name.trim().is_empty() {
+            return Err("A job needs a name".to_string());
+        }
+        let options = JobOptions { priority: request.priority, memory_quota: request.memory_quota };
+        let gpu_job = self.scheduler.join(&request.name, options)?;
+        let mut jobs = self.lock();
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+        let status = JobStatus {
+            job: id, name: request.name.clone(), state: JobState::Queued, priority: request.priority, run: None,
+            files_done: 0, total_files: 0, bases: 0, score: None, error: None, launches: 0, gpu_time_s: 0.0, waited_s: 0.0,
+        };
+        jobs.entries.push(Entry { status, cancel: CancellationToken::new(), gpu_job: Some(gpu_job) });
+        drop(jobs);
+
+        let server = Arc::clone(self);
+        std::thread::Builder::new()
+            .name(format!("job-{}", id))
+            .spawn(move || server.run_job(id, request))
+            .map_err(|e| {
+                self.finish(id, JobState::Failed, Err(format!("Failed to start the job's thread: {}", e)));
+                format!("Failed to start job {}: {}", id, e)
+            })?;
+        Ok(id)
+    }
+
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.lock().entry(id).map(|entry| with_gpu_stats(entry))
+    }
+
+    // Every job, in the order they were submitted
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.lock().entries.iter().map(with_gpu_stats).collect()
+    }
+
+    pub fn cancel(&self, id: u64) -> Result<(), String> {
+        let mut jobs = self.lock();
+        let entry = jobs.entry(id).ok_or_else(|| format!("No job {}", id))?;
+        if entry.status.state.is_finished() {
+            return Err(format!("Job {} has already {}", id, state_name(entry.status.state)));
+        }
+        entry.cancel.cancel();
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    // Block until job `id` has finished; its final status
+    pub fn wait(&self, id: u64) -> Option<JobStatus> {
+        let mut jobs = self.lock();
+        loop {
+            let entry = jobs.entry(id)?;
+            if entry.status.state.is_finished() {
+                return Some(with_gpu_stats(entry));
+            }
+            jobs = self.changed.wait(jobs).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    // Wait for a free job slot and the jobs submitted earlier; false if the
+    // job was cancelled while it waited
+    fn wait_turn(&self, id: u64) -> bool {
+        let mut jobs = self.lock();
+        loop {
+            let cancelled = jobs.entry(id).is_none_or(|entry| entry.cancel.is_cancelled());
+            if cancelled {
+                return false;
+            }
+            let earlier = jobs.entries.iter()
+                .any(|entry| entry.status.job < id && entry.status.state == JobState::Queued && !entry.cancel.is_cancelled());
+            if jobs.running < self.max_jobs && !earlier {
+                jobs.running += 1;
+                if let Some(entry) = jobs.entry(id) {
+                    entry.status.state = JobState::Running;
+                }
+                return true;
+            }
+            jobs = self.changed.wait(jobs).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn run_job(&self, id: u64, request: JobRequest) {
+        if !self.wait_turn(id) {
+            self.finish(id, JobState::Cancelled, Err("cancelled before it started".to_string()));
+            return;
+        }
+        let Some((cancel, Some(gpu_job))) = self.lock().entry(id).map(|entry| (entry.cancel.clone(), entry.gpu_job.clone())) else { return };
+        let run = RunContext::register().with_gpu_job(gpu_job);
+        let _entered = run.enter();
+        self.update(id, |status| status.run = Some(run.run().number));
+        println!("Job {} ({}): run {}", id, request.name, run.run().number);
+
+        let mut config = self.base.clone();
+        config.data_dir = request.data_dir;
+        config.sample_id = request.name;
+        config.input_files = request.input_files;
+        config.resume = false;
+        let result = self.aligner.run_wgs_in(&run, &config, &cancel, &mut |progress: Progress| {
+            self.update(id, |status| {
+                status.total_files = progress.total_files;
+                status.files_done = progress.file_index;
+                status.bases = progress.bases;
+            });
+        });
+        crate::warnings::finish(&run);
+        let state = match &result {
+            Ok(_) => JobState::Completed,
+            Err(_) if cancel.is_cancelled() => JobState::Cancelled,
+            Err(_) => JobState::Failed,
+        };
+        self.finish(id, state, result.map(|report| (report.files.len(), report.totals.score)));
+        drop(run);
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut JobStatus)) {
+        if let Some(entry) = self.lock().entry(id) {
+            change(&mut entry.status);
+        }
+    }
+
+    fn finish(&self, id: u64, state: JobState, result: Result<(usize, i64), String>) {
+        let mut jobs = self.lock();
+        if jobs.entry(id).is_some_and(|entry| entry.status.state == JobState::Running) {
+            jobs.running -= 1;
+        }
+        if let Some(entry) = jobs.entry(id) {
+            entry.status = with_gpu_stats(entry);
+            entry.gpu_job = None;
+            entry.status.state = state;
+            match result {
+                Ok((files, score)) => (entry.status.files_done, entry.status.total_files, entry.status.score) = (files, files, Some(score)),
+                Err(e) => entry.status.error = Some(e),
+            }
+            println!("Job {} ({}): {}", id, entry.status.name, state_name(state));
+        }
+        self.changed.notify_all();
+    }
+}
+
+fn state_name(state: JobState) -> &'static str {
+    match state {
+        JobState::Queued => "queued",
+        JobState::Running => "running",
+        JobState::Completed => "completed",
+        JobState::Failed => "failed",
+        JobState::Cancelled => "cancelled",
+    }
+}
+
+// The entry's status with its scheduler counts so far
+fn with_gpu_stats(entry: &Entry) -> JobStatus {
+    let mut status = entry.status.clone();
+    if let Some(JobStats { launches, gpu_time, waited, .. }) = entry.gpu_job.as_ref().and_then(Job::stats) {
+        (status.launches, status.gpu_time_s, status.waited_s) = (launches, gpu_time.as_secs_f64(), waited.as_secs_f64());
+    }
+    status
+}
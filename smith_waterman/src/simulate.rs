@@ -0,0 +1,268 @@
+// `simulate from-reference`: FASTQ reads sampled from a reference with known origins
+// Reads are drawn uniformly over the reference (contigs weighted by length) from
+// either strand, skipping windows that contain N. Each base gets a quality from
+// the error profile's curve and is miscalled with the probability that quality
+// implies, so errors pile up towards the 3' end like real runs; insertions and
+// deletions are added at the profile's rates. The true position goes in the
+// read description:
+//   @sim_42 pos=chr20:1000001-1000150 strand=- edits=3
+// Positions are 1-based and inclusive, on the forward strand of the reference.
+// The same reference, profile, and seed (--seed, or the run's; seed.rs) give
+// the same reads. A profile learned from real reads (learned_profile.rs) takes
+// the place of the quality curve and read length
+
+use crate::alignment_input::complement_base;
+use crate::learned_profile::LearnedProfile;
+use crate::seed::Seedable;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorProfile {
+    pub name: Cow<'static, str>,
+    pub read_length: usize,
+    // Mean base quality at the first and last cycle; quadratic decay in between
+    pub first_quality: f64,
+    pub last_quality: f64,
+    // Qualities are rounded to these values when set (NovaSeq-style binning)
+    pub quality_bins: &'static [u8],
+    // Miscall probability relative to what the quality implies (0 for error-free reads)
+    pub substitution_scale: f64,
+    pub insertion_rate: f64,
+    pub deletion_rate: f64,
+    // Per-cycle qualities, N calls and read lengths of a learned profile
+    pub learned: Option<Arc<LearnedProfile>>,
+}
+
+pub const PROFILES: [ErrorProfile; 3] = [
+    ErrorProfile { name: Cow::Borrowed("hiseq"), read_length: 100, first_quality: 37.0, last_quality: 27.0, quality_bins: &[], substitution_scale: 1.0, insertion_rate: 0.0001, deletion_rate: 0.0001, learned: None },
+    ErrorProfile { name: Cow::Borrowed("novaseq"), read_length: 150, first_quality: 36.0, last_quality: 30.0, quality_bins: &[2, 12, 23, 37], substitution_scale: 1.0, insertion_rate: 0.00005, deletion_rate: 0.00005, learned: None },
+    ErrorProfile { name: Cow::Borrowed("perfect"), read_length: 150, first_quality: 40.0, last_quality: 40.0, quality_bins: &[], substitution_scale: 0.0, insertion_rate: 0.0, deletion_rate: 0.0, learned: None },
+];
+
+impl ErrorProfile {
+    // A built-in profile by name, or a profile file from simulate learn-profile
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let wanted = value.trim().to_ascii_lowercase();
+        if let Some(profile) = PROFILES.iter().find(|p| p.name == wanted) {
+            return Ok(profile.clone());
+        }
+        if std::path::Path::new(value).is_file() {
+            return Ok(Self::learned(LearnedProfile::load(value)?, value));
+        }
+        let names: Vec<&str> = PROFILES.iter().map(|p| p.name.as_ref()).collect();
+        Err(format!("Invalid error profile '{}': expected one of {}, or a file written by simulate learn-profile", value, names.join(", ")))
+    }
+
+    // `learned` as a profile named after its file; the curve fields are its means
+    pub fn learned(learned: LearnedProfile, path: &str) -> Self {
+        Self {
+            name: Cow::Owned(path.to_string()),
+            read_length: learned.longest(),
+            first_quality: learned.mean_quality(0),
+            last_quality: learned.mean_quality(usize::MAX),
+            quality_bins: &[],
+            substitution_scale: 1.0,
+            insertion_rate: learned.insertion_rate,
+            deletion_rate: learned.deletion_rate,
+            learned: Some(Arc::new(learned)),
+        }
+    }
+
+    fn quality(&self, cycle: usize, length: usize, rng: &mut Seedable) -> u8 {
+        if let Some(learned) = &self.learned {
+            return learned.draw_quality(cycle, rng);
+        }
+        if self.first_quality == self.last_quality {
+            return self.first_quality as u8;
+        }
+        let position = cycle as f64 / length.max(1) as f64;
+        let mean = self.first_quality - (self.first_quality - self.last_quality) * position * position;
+        // Some spread around the curve, with the odd low-quality call
+        let quality = if rng.chance(0.01) { 2.0 + rng.next_f64() * 10.0 } else { mean + (rng.next_f64() - 0.5) * 6.0 };
+        let quality = quality.round().clamp(2.0, 41.0) as u8;
+        match self.quality_bins {
+            [] => quality,
+            bins => bins.iter().copied().min_by_key(|&bin| bin.abs_diff(quality)).unwrap_or(quality),
+        }
+    }
+}
+
+pub struct SimulateConfig {
+    pub reference: String,
+    pub coverage: f64,
+    pub profile: ErrorProfile,
+    pub read_length: usize,
+    // Draw each read's length from a learned profile instead (no --read-length)
+    pub vary_length: bool,
+    pub output: String,
+    pub seed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct SimulateSummary {
+    pub contigs: usize,
+    pub reference_bases: usize,
+    pub reads: usize,
+    pub bases: usize,
+    pub edits: usize,
+}
+
+// Whole reference in memory, uppercased; plain or gzipped FASTA
+pub fn load_reference(path: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open reference {}: {}", path, e))?;
+    let reader: Box<dyn BufRead> = if path.ends_with(".gz") {
+        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    let mut contigs: Vec<(String, Vec<u8>)> = Vec::new();
+    for line in reader.split(b'\n') {
+        let line = line.map_err(|e| format!("Failed to read reference {}: {}", path, e))?;
+        let line = crate::read_chunk::trim_line_end(&line);
+        if let Some(header) = line.strip_prefix(b">") {
+            let name = String::from_utf8_lossy(header).split_whitespace().next().unwrap_or("").to_string();
+            contigs.push((name, Vec::new()));
+        } else if let Some((_, sequence)) = contigs.last_mut() {
+            sequence.extend(line.iter().map(|b| b.to_ascii_uppercase()));
+        } else if !line.is_empty() {
+            return Err(format!("{} is not FASTA: sequence before the first '>' header", path));
+        }
+    }
+    if contigs.iter().all(|(_, s)| s.is_empty()) {
+        return Err(format!("Reference {} has no sequence", path));
+    }
+    Ok(contigs)
+}
+
+pub(crate) fn open_output(path: &str) -> Result<Box<dyn Write>, String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    if path.ends_with(".gz") {
+        Ok(Box::new(flate2::write::GzEncoder::new(BufWriter::new(file), flate2::Compression::default())))
+    } else {
+        Ok(Box::new(BufWriter::new(file)))
+    }
+}
+
+// First `length` bases of a read's template with errors applied; returns the number of edits
+fn apply_errors(template: &[u8], length: usize, profile: &ErrorProfile, rng: &mut Seedable, seq: &mut Vec<u8>, qual: &mut Vec<u8>) -> usize {
+    seq.clear();
+    qual.clear();
+    let mut edits = 0;
+    let mut bases = template.iter().copied();
+    while seq.len() < length {
+        let cycle = seq.len();
+        if rng.chance(profile.learned.as_ref().map_or(0.0, |learned| learned.n_rate(cycle))) {
+            bases.next();
+            seq.push(b'N');
+            qual.push(crate::learned_profile::N_QUALITY + 33);
+            continue;
+        }
+        let quality = profile.quality(cycle, length, rng);
+        if rng.chance(profile.insertion_rate) {
+            seq.push(rng.base());
+            edits += 1;
+        } else {
+            if rng.chance(profile.deletion_rate) {
+                bases.next();
+                edits += 1;
+            }
+            // Deletions near the end can use up the template; pad from the generator
+            let mut base = bases.next().unwrap_or_else(|| rng.base());
+            if rng.chance(profile.substitution_scale * 10f64.powf(-(quality as f64) / 10.0)) {
+                let wrong = b"ACGT".iter().copied().filter(|&b| b != base).collect::<Vec<_>>();
+                base = wrong[rng.below(wrong.len())];
+                edits += 1;
+            }
+            seq.push(base);
+        }
+        qual.push(quality + 33);
+    }
+    edits
+}
+
+impl SimulateConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.coverage > 0.0 && self.coverage.is_finite()) {
+            return Err(format!("Invalid --coverage {}: expected a positive number", self.coverage));
+        }
+        if self.read_length == 0 {
+            return Err("Invalid --read-length 0: expected at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn from_reference(config: &SimulateConfig) -> Result<SimulateSummary, String> {
+    config.validate()?;
+    let contigs = load_reference(&config.reference)?;
+    let reference_bases: usize = contigs.iter().map(|(_, s)| s.len()).sum();
+    // Reads carry a few extra template bases so deletions don't run short
+    let template_length = config.read_length + config.read_length / 10 + 1;
+    let usable: Vec<(usize, usize)> = contigs.iter().enumerate()
+        .filter(|(_, (_, s))| s.len() >= template_length)
+        .map(|(i, (_, s))| (i, s.len() - template_length + 1))
+        .collect();
+    let positions: usize = usable.iter().map(|(_, n)| n).sum();
+    if positions == 0 {
+        return Err(format!("No contig in {} is long enough for {} bp reads", config.reference, config.read_length));
+    }
+
+    let target_reads = ((config.coverage * reference_bases as f64) / config.read_length as f64).ceil() as usize;
+    let mut rng = Seedable::new(config.seed);
+    let mut out = open_output(&config.output)?;
+    let mut summary = SimulateSummary { contigs: contigs.len(), reference_bases, ..Default::default() };
+    let (mut template, mut seq, mut qual) = (Vec::new(), Vec::new(), Vec::new());
+    let mut rejected = 0usize;
+
+    while summary.reads < target_reads {
+        // Uniform over start positions, so longer contigs get proportionally more reads
+        let mut offset = rng.below(positions);
+        let &(contig, _) = usable.iter().find(|(_, n)| {
+            if offset < *n { true } else { offset -= n; false }
+        }).ok_or("start position out of range")?;
+        let (name, sequence) = &contigs[contig];
+        let window = &sequence[offset..offset + template_length];
+        if window.iter().any(|b| !b"ACGT".contains(b)) {
+            rejected += 1;
+            if rejected > target_reads.max(1000) * 100 {
+                return Err(format!("Reference {} is almost entirely N; gave up finding read positions", config.reference));
+            }
+            continue;
+        }
+
+        let reverse = rng.chance(0.5);
+        template.clear();
+        if reverse {
+            // Read starts at the right end of the window on the minus strand
+            template.extend(window.iter().rev().map(|&b| complement_base(b)));
+        } else {
+            template.extend_from_slice(window);
+        }
+        let read_length = match &config.profile.learned {
+            Some(learned) if config.vary_length => learned.draw_length(&mut rng).min(config.read_length),
+            _ => config.read_length,
+        };
+        let edits = apply_errors(&template, read_length, &config.profile, &mut rng, &mut seq, &mut qual);
+        let (start, end) = if reverse {
+            (offset + template_length - read_length + 1, offset + template_length)
+        } else {
+            (offset + 1, offset + read_length)
+        };
+
+        summary.reads += 1;
+        summary.bases += seq.len();
+        summary.edits += edits;
+        writeln!(out, "@sim_{} pos={}:{}-{} strand={} edits={}", summary.reads, name, start, end, if reverse { '-' } else { '+' }, edits)
+            .and_then(|_| out.write_all(&seq))
+            .and_then(|_| out.write_all(b"\n+\n"))
+            .and_then(|_| out.write_all(&qual))
+            .and_then(|_| out.write_all(b"\n"))
+            .map_err(|e| format!("Failed to write {}: {}", config.output, e))?;
+    }
+    // Dropping the gzip encoder writes its trailer
+    out.flush().map_err(|e| format!("Failed to write {}: {}", config.output, e))?;
+    Ok(summary)
+}
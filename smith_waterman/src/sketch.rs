@@ -0,0 +1,75 @@
+// FracMinHash sketching shared by the k-mer based modes: the contamination
+// screen, analyze --kmer-spectrum, compare-assemblies' contig pairing and ani
+// Every k-mer of A/C/G/T bases is hashed together with its reverse complement
+// (rolling_hash::canonical), and only hashes under u64::MAX / scale are kept,
+// so a sketch holds about 1/scale of a sequence's distinct k-mers and two
+// sequences are sampled at the same k-mers. Shares of sampled k-mers (one
+// sketch's k-mers found in another) estimate the shares of all k-mers without
+// depending on the scale
+
+use std::collections::HashSet;
+
+pub const MAX_KMER: usize = 31;
+
+// u64::MAX / scale: hashes at or under it are kept
+pub fn max_hash(scale: u64) -> u64 {
+    u64::MAX / scale.max(1)
+}
+
+// Calls `f` with the hash of every canonical k-mer of `sequence` under max_hash
+pub fn for_each_sampled(sequence: &[u8], k: usize, max_hash: u64, mut f: impl FnMut(u64)) {
+    crate::rolling_hash::for_each_kmer(sequence, k, |_, forward, reverse| {
+        let hash = crate::rolling_hash::canonical(forward, reverse);
+        if hash <= max_hash {
+            f(hash);
+        }
+        true
+    });
+}
+
+// The distinct sampled k-mers of a set of sequences
+#[derive(Debug, Clone, Default)]
+pub struct Sketch {
+    pub k: usize,
+    pub scale: u64,
+    pub hashes: HashSet<u64>,
+}
+
+impl Sketch {
+    pub fn new(k: usize, scale: u64) -> Self {
+        Self { k, scale, hashes: HashSet::new() }
+    }
+
+    pub fn add(&mut self, sequence: &[u8]) {
+        let Self { k, scale, hashes } = self;
+        for_each_sampled(sequence, *k, max_hash(*scale), |hash| {
+            hashes.insert(hash);
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    // Sampled k-mers shared with `other`
+    pub fn shared(&self, other: &Sketch) -> usize {
+        let (small, large) = if self.len() <= other.len() { (self, other) } else { (other, self) };
+        small.hashes.iter().filter(|hash| large.hashes.contains(hash)).count()
+    }
+
+    // Share of this sketch's k-mers found in `other`
+    pub fn containment(&self, other: &Sketch) -> f64 {
+        if self.is_empty() { 0.0 } else { self.shared(other) as f64 / self.len() as f64 }
+    }
+
+    // Shared k-mers over the k-mers of either
+    pub fn jaccard(&self, other: &Sketch) -> f64 {
+        let shared = self.shared(other);
+        let union = self.len() + other.len() - shared;
+        if union == 0 { 0.0 } else { shared as f64 / union as f64 }
+    }
+}
@@ -1,10 +1,46 @@
 // OpenCL kernel for Smith-Waterman sequence alignment
 // Optimized for parallel processing of DNA sequences
 
-// Scoring parameters
+// Scoring parameters, set by the host when building the program (scoring.rs)
+#ifndef MATCH_SCORE
 #define MATCH_SCORE 2
+#endif
+#ifndef MISMATCH_PENALTY
 #define MISMATCH_PENALTY -1
+#endif
+#ifndef GAP_PENALTY
 #define GAP_PENALTY -2
+#endif
+
+// N handling, set by the host when building the program (n_policy.rs)
+#define N_POLICY_MISMATCH 0
+#define N_POLICY_ZERO 1
+#ifndef N_POLICY
+#define N_POLICY N_POLICY_MISMATCH
+#endif
+#define N_SCORE (N_POLICY == N_POLICY_ZERO ? 0 : MISMATCH_PENALTY)
+
+int is_n(uchar base) {
+    return base == 'N' || base == 'n';
+}
+
+// Score of one aligned pair; an N never matches
+int pair_score(uchar a, uchar b) {
+    if (is_n(a) || is_n(b)) {
+        return N_SCORE;
+    }
+    return (a == b) ? MATCH_SCORE : MISMATCH_PENALTY;
+}
+
+// High bit set in each byte of x that is zero
+uint zero_bytes(uint x) {
+    return ~(((x & 0x7f7f7f7fu) + 0x7f7f7f7fu) | x) & 0x80808080u;
+}
+
+// High bit set in each byte of four packed bases that is N or n
+uint n_bytes(uint bases) {
+    return zero_bytes(bases ^ 0x4e4e4e4eu) | zero_bytes(bases ^ 0x6e6e6e6eu);
+}
 
 // Smith-Waterman alignment kernel
 // Each work item processes a portion of the alignment matrix
@@ -39,12 +75,7 @@ __kernel void smith_waterman_align(
     for (uint i = start_pos + local_id; i < end_pos; i += group_size) {
         if (i < length) {
             // Simple scoring for this position
-            int score = 0;
-            if (seq1[i] == seq2[i]) {
-                score = MATCH_SCORE;
-            } else {
-                score = MISMATCH_PENALTY;
-            }
+            int score = pair_score(seq1[i], seq2[i]);
             
             // Update current score (simplified Smith-Waterman)
             current_score = max(current_score + score, 0);
@@ -111,7 +142,7 @@ __kernel void smith_waterman_detailed(
         
         // Compute new row
         for (uint j = local_id; j < len2; j += group_size) {
-            int match_score = (seq1[i] == seq2[j]) ? MATCH_SCORE : MISMATCH_PENALTY;
+            int match_score = pair_score(seq1[i], seq2[j]);
             
             int diag = (i > 0 && j > 0) ? prev_row_scores[j - 1] : 0;
             int left = (j > 0) ? row_scores[j - 1] : 0;
@@ -149,4 +180,391 @@ __kernel void smith_waterman_detailed(
     if (local_id == 0) {
         atomic_max(result, local_maxima[0]);
     }
-} 
\ No newline at end of file
+} 
+// Tiled variant of smith_waterman_align
+// Each work group stages 4 * group_size bases of both sequences in local memory
+// with one 4-byte vector load per work item, then every work item scores its
+// strided positions from the tile. Positions are visited in the same order as in
+// smith_waterman_align, so the scores are identical; only global memory traffic
+// changes (one wide coalesced load per item per tile instead of four byte loads)
+// Local buffers are sized by the host: tiles of 4 * group_size bytes, and
+// group_size ints for the reduction, so any work group size fits
+__kernel void smith_waterman_align_tiled(
+    __global const uchar* seq1,
+    __global const uchar* seq2,
+    __global int* result,
+    uint length,
+    __local uchar* tile1,
+    __local uchar* tile2,
+    __local int* local_scores
+) {
+    uint local_id = get_local_id(0);
+    uint group_size = get_local_size(0);
+    uint group_id = get_group_id(0);
+    
+    uint chunk_size = (length + get_num_groups(0) - 1) / get_num_groups(0);
+    uint start_pos = group_id * chunk_size;
+    uint end_pos = min(start_pos + chunk_size, length);
+    
+    // Uniform across the work group, so no barrier is skipped by only some items
+    if (start_pos >= length) {
+        return;
+    }
+    
+    uint tile_len = group_size * 4;
+    int max_score = 0;
+    int current_score = 0;
+    
+    for (uint tile_start = start_pos; tile_start < end_pos; tile_start += tile_len) {
+        // Stage this tile
+        uint base = tile_start + local_id * 4;
+        if (base + 4 <= end_pos) {
+            vstore4(vload4(0, seq1 + base), local_id, tile1);
+            vstore4(vload4(0, seq2 + base), local_id, tile2);
+        } else {
+            for (uint k = 0; k < 4 && base + k < end_pos; k++) {
+                tile1[local_id * 4 + k] = seq1[base + k];
+                tile2[local_id * 4 + k] = seq2[base + k];
+            }
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+        
+        // Score this work item's positions in the tile
+        uint tile_end = min(tile_len, end_pos - tile_start);
+        for (uint j = local_id; j < tile_end; j += group_size) {
+            int score = pair_score(tile1[j], tile2[j]);
+            current_score = max(current_score + score, 0);
+            max_score = max(max_score, current_score);
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+    
+    local_scores[local_id] = max_score;
+    barrier(CLK_LOCAL_MEM_FENCE);
+    
+    for (uint stride = group_size / 2; stride > 0; stride >>= 1) {
+        if (local_id < stride) {
+            local_scores[local_id] = max(local_scores[local_id], local_scores[local_id + stride]);
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+    
+    if (local_id == 0) {
+        atomic_max(result, local_scores[0]);
+    }
+}
+
+// Packed variant of smith_waterman_align (experimental)
+// Each work item stands in for four neighbouring work items of
+// smith_waterman_align: per step it loads the four bases those items would read
+// as one 32-bit word from each sequence, finds the mismatching byte lanes with
+// integer SIMD-within-a-register, and carries the four running scores in
+// registers. The host launches a quarter of the work items per group, and every
+// lane visits its positions in the same order, so scores are identical to
+// smith_waterman_align with group_size = 4 * local size
+// Running scores stay 32-bit: 8-bit lanes would overflow on long runs of matches
+__kernel void smith_waterman_align_packed(
+    __global const uchar* seq1,
+    __global const uchar* seq2,
+    __global int* result,
+    uint length,
+    __local int* local_scores
+) {
+    uint local_id = get_local_id(0);
+    uint local_size = get_local_size(0);
+    uint group_size = local_size * 4;
+    uint group_id = get_group_id(0);
+    
+    uint chunk_size = (length + get_num_groups(0) - 1) / get_num_groups(0);
+    uint start_pos = group_id * chunk_size;
+    uint end_pos = min(start_pos + chunk_size, length);
+    
+    if (start_pos >= length) {
+        return;
+    }
+    
+    int current[4] = {0, 0, 0, 0};
+    int best[4] = {0, 0, 0, 0};
+    
+    for (uint i = start_pos + local_id * 4; i < end_pos; i += group_size) {
+        uint lanes = min(4u, end_pos - i);
+        uint mismatched = 0;
+        uint ambiguous = 0;
+        if (lanes == 4) {
+            uint a = as_uint(vload4(0, seq1 + i));
+            uint b = as_uint(vload4(0, seq2 + i));
+            uint diff = a ^ b;
+            // High bit of each byte set where that byte of diff is non-zero
+            mismatched = (((diff & 0x7f7f7f7fu) + 0x7f7f7f7fu) | diff) & 0x80808080u;
+            ambiguous = n_bytes(a) | n_bytes(b);
+        } else {
+            for (uint k = 0; k < lanes; k++) {
+                if (seq1[i + k] != seq2[i + k]) {
+                    mismatched |= 0x80u << (8 * k);
+                }
+                if (is_n(seq1[i + k]) || is_n(seq2[i + k])) {
+                    ambiguous |= 0x80u << (8 * k);
+                }
+            }
+        }
+        for (uint k = 0; k < lanes; k++) {
+            int score = ((ambiguous >> (8 * k + 7)) & 1) ? N_SCORE
+                      : ((mismatched >> (8 * k + 7)) & 1) ? MISMATCH_PENALTY : MATCH_SCORE;
+            current[k] = max(current[k] + score, 0);
+            best[k] = max(best[k], current[k]);
+        }
+    }
+    
+    local_scores[local_id] = max(max(best[0], best[1]), max(best[2], best[3]));
+    barrier(CLK_LOCAL_MEM_FENCE);
+    
+    for (uint stride = local_size / 2; stride > 0; stride >>= 1) {
+        if (local_id < stride) {
+            local_scores[local_id] = max(local_scores[local_id], local_scores[local_id + stride]);
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+    
+    if (local_id == 0) {
+        atomic_max(result, local_scores[0]);
+    }
+}
+
+// All-diagonals scoring (ungapped.rs): ungapped local alignment of sequence sets
+// under a substitution matrix. Residues arrive as matrix rows, and each sequence set
+// comes with start offsets (one more than its sequences). Work item (d, p) scores
+// diagonal d of pair p = target * query_count + query; diagonal 0 starts at the
+// query's last residue and the first target residue. The pair's best diagonal
+// wins with atomic_max. With a threshold above 0 (score-only screening), a
+// diagonal stops once it can't reach it: its best so far is below, and so is its
+// running score plus max_score for each residue left. Bests below it aren't kept
+__kernel void smith_waterman_all_diagonals(
+    __global const uchar* queries,
+    __global const uint* query_offsets,
+    uint query_count,
+    __global const uchar* targets,
+    __global const uint* target_offsets,
+    __global const int* matrix,
+    uint alphabet_size,
+    __global int* results,
+    int threshold,
+    int max_score
+) {
+    uint diagonal = get_global_id(0);
+    uint pair = get_global_id(1);
+    uint query = pair % query_count;
+    uint target = pair / query_count;
+    
+    uint q_start = query_offsets[query];
+    uint q_len = query_offsets[query + 1] - q_start;
+    uint t_start = target_offsets[target];
+    uint t_len = target_offsets[target + 1] - t_start;
+    if (q_len == 0 || t_len == 0 || diagonal >= q_len + t_len - 1) {
+        return;
+    }
+    
+    uint i = (diagonal < q_len - 1) ? q_len - 1 - diagonal : 0;
+    uint j = (diagonal > q_len - 1) ? diagonal - (q_len - 1) : 0;
+    long left = min(q_len - i, t_len - j);
+    if (left * max_score < threshold) {
+        return;
+    }
+    int current = 0;
+    int best = 0;
+    for (; i < q_len && j < t_len; i++, j++) {
+        int score = matrix[queries[q_start + i] * alphabet_size + targets[t_start + j]];
+        current = max(current + score, 0);
+        best = max(best, current);
+        left--;
+        if (best < threshold && current + left * max_score < threshold) {
+            break;
+        }
+    }
+    if (best > 0 && best >= threshold) {
+        atomic_max(results + pair, best);
+    }
+}
+
+// smith_waterman_all_diagonals for a list of pairs: work item (d, p) scores
+// diagonal d of query p against target p, under matrix pair_matrix[p] of
+// matrices (alphabet_size squared scores each)
+__kernel void smith_waterman_paired_diagonals(
+    __global const uchar* queries,
+    __global const uint* query_offsets,
+    __global const uchar* targets,
+    __global const uint* target_offsets,
+    __global const int* matrices,
+    __global const uint* pair_matrix,
+    uint alphabet_size,
+    __global int* results
+) {
+    uint diagonal = get_global_id(0);
+    uint pair = get_global_id(1);
+    
+    uint q_start = query_offsets[pair];
+    uint q_len = query_offsets[pair + 1] - q_start;
+    uint t_start = target_offsets[pair];
+    uint t_len = target_offsets[pair + 1] - t_start;
+    if (q_len == 0 || t_len == 0 || diagonal >= q_len + t_len - 1) {
+        return;
+    }
+    
+    __global const int* matrix = matrices + pair_matrix[pair] * alphabet_size * alphabet_size;
+    uint i = (diagonal < q_len - 1) ? q_len - 1 - diagonal : 0;
+    uint j = (diagonal > q_len - 1) ? diagonal - (q_len - 1) : 0;
+    int current = 0;
+    int best = 0;
+    for (; i < q_len && j < t_len; i++, j++) {
+        current = max(current + matrix[queries[q_start + i] * alphabet_size + targets[t_start + j]], 0);
+        best = max(best, current);
+    }
+    if (best > 0) {
+        atomic_max(results + pair, best);
+    }
+}
+
+// One diagonal of one target against a position-specific scoring matrix:
+// profile[position * columns + residue], the last column scoring residues
+// outside the profile's alphabet. Writes the diagonal's best local run
+__kernel void smith_waterman_profile(
+    __global const int* profile,
+    uint profile_len,
+    uint columns,
+    __global const uchar* targets,
+    __global const uint* target_offsets,
+    __global int* results,
+    uint diagonals
+) {
+    uint diagonal = get_global_id(0);
+    uint target = get_global_id(1);
+    
+    uint t_start = target_offsets[target];
+    uint t_len = target_offsets[target + 1] - t_start;
+    if (t_len == 0 || diagonal >= profile_len + t_len - 1) {
+        return;
+    }
+    
+    uint i = (diagonal < profile_len - 1) ? profile_len - 1 - diagonal : 0;
+    uint j = (diagonal > profile_len - 1) ? diagonal - (profile_len - 1) : 0;
+    int current = 0;
+    int best = 0;
+    for (; i < profile_len && j < t_len; i++, j++) {
+        current = max(current + profile[i * columns + targets[t_start + j]], 0);
+        best = max(best, current);
+    }
+    results[target * diagonals + diagonal] = best;
+}
+
+// Edit distance of one pattern/text pair (edit_distance.rs). Bases are 4-bit
+// masks that match when they share a bit. semi_global lets the pattern start
+// and end anywhere in the text; otherwise both are aligned end to end.
+// Writes distance, start and end (0-based, exclusive) of the best placement
+#define MAX_PATTERN 64
+__kernel void edit_distance_batch(
+    __global const uchar* patterns,
+    __global const uint* pattern_offsets,
+    uint pattern_count,
+    __global const uchar* texts,
+    __global const uint* text_offsets,
+    uint semi_global,
+    __global uint* results
+) {
+    uint pattern = get_global_id(0);
+    uint text = get_global_id(1);
+    uint pair = text * pattern_count + pattern;
+    
+    uint p_start = pattern_offsets[pattern];
+    uint m = pattern_offsets[pattern + 1] - p_start;
+    uint t_start = text_offsets[text];
+    uint n = text_offsets[text + 1] - t_start;
+    
+    uint distance[MAX_PATTERN + 1];
+    uint start[MAX_PATTERN + 1];
+    for (uint i = 0; i <= m; i++) {
+        distance[i] = i;
+        start[i] = 0;
+    }
+    uint best = m;
+    uint best_start = 0;
+    uint best_end = 0;
+    for (uint j = 0; j < n; j++) {
+        uchar t = texts[t_start + j];
+        uint diagonal = distance[0];
+        uint diagonal_start = start[0];
+        if (semi_global) {
+            start[0] = j + 1;
+        } else {
+            distance[0] += 1;
+        }
+        for (uint i = 1; i <= m; i++) {
+            uint substitution = diagonal + ((patterns[p_start + i - 1] & t) == 0 ? 1 : 0);
+            uint up = distance[i] + 1;
+            uint up_start = start[i];
+            uint left = distance[i - 1] + 1;
+            uint left_start = start[i - 1];
+            diagonal = distance[i];
+            uint next_diagonal_start = start[i];
+            distance[i] = substitution;
+            start[i] = diagonal_start;
+            if (up < distance[i]) {
+                distance[i] = up;
+                start[i] = up_start;
+            }
+            if (left < distance[i]) {
+                distance[i] = left;
+                start[i] = left_start;
+            }
+            diagonal_start = next_diagonal_start;
+        }
+        if (semi_global && distance[m] < best) {
+            best = distance[m];
+            best_start = start[m];
+            best_end = j + 1;
+        }
+    }
+    if (!semi_global) {
+        best = distance[m];
+        best_start = 0;
+        best_end = n;
+    }
+    results[pair * 3] = best;
+    results[pair * 3 + 1] = best_start;
+    results[pair * 3 + 2] = best_end;
+}
+
+// Base translation (seq_transform.rs): every byte through a 256-entry table,
+// written in place or mirrored (reverse complement with the complement table)
+__kernel void translate_bases(
+    __global const uchar* input,
+    __global uchar* output,
+    __global const uchar* table,
+    uint length,
+    uint reverse
+) {
+    uint i = get_global_id(0);
+    if (i >= length) {
+        return;
+    }
+    uchar translated = table[input[i]];
+    output[reverse ? length - 1 - i : i] = translated;
+}
+
+// 2-bit packing (seq_transform.rs): four bases per byte, the first in the low
+// bits; codes come from a 256-entry table (A=0 C=1 G=2 T=3)
+__kernel void pack_2bit(
+    __global const uchar* input,
+    __global uchar* output,
+    __global const uchar* codes,
+    uint length
+) {
+    uint byte = get_global_id(0);
+    uint first = byte * 4;
+    if (first >= length) {
+        return;
+    }
+    uchar packed = 0;
+    for (uint j = 0; j < 4 && first + j < length; j++) {
+        packed |= codes[input[first + j]] << (j * 2);
+    }
+    output[byte] = packed;
+}
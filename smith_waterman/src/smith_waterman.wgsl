@@ -0,0 +1,73 @@
+// WGSL port of smith_waterman_align (smith_waterman.cl) for the wgpu backend
+// (Metal on macOS). Same scoring and launch geometry: each work group scores
+// one contiguous chunk, each work item a running local score over positions
+// strided by the work group size, and the result is the maximum over all
+
+// Set by the host when building the pipeline (wgpu_align.rs)
+// Match and mismatch scores (scoring.rs)
+override match_score: i32 = 2;
+override mismatch_penalty: i32 = -1;
+override group_size: u32 = 256u;
+// Score of a position with an N in either sequence (n_policy.rs)
+override n_score: i32 = -1;
+
+struct Params {
+    length: u32,
+    work_groups: u32,
+    // Work groups per row of the dispatch; more than the per-dimension
+    // limit are laid out over several rows
+    groups_x: u32,
+    pad: u32,
+}
+
+// Bases packed four to a word, little-endian
+@group(0) @binding(0) var<storage, read> seq1: array<u32>;
+@group(0) @binding(1) var<storage, read> seq2: array<u32>;
+@group(0) @binding(2) var<storage, read_write> result: atomic<i32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+var<workgroup> group_best: atomic<i32>;
+
+fn is_n(base: u32) -> bool {
+    return base == 78u || base == 110u;
+}
+
+// Score of one aligned pair; an N never matches
+fn pair_score(a: u32, b: u32) -> i32 {
+    if (is_n(a) || is_n(b)) {
+        return n_score;
+    }
+    if (a == b) {
+        return match_score;
+    }
+    return mismatch_penalty;
+}
+
+@compute @workgroup_size(group_size)
+fn smith_waterman_align(@builtin(workgroup_id) group: vec3<u32>,
+                        @builtin(local_invocation_index) local_id: u32) {
+    let group_id = group.y * params.groups_x + group.x;
+    var max_score = 0;
+
+    // No early return: every work item has to reach the barrier
+    if (group_id < params.work_groups) {
+        let chunk_size = (params.length + params.work_groups - 1u) / params.work_groups;
+        let start_pos = group_id * chunk_size;
+        let end_pos = min(start_pos + chunk_size, params.length);
+        var current_score = 0;
+        for (var i = start_pos + local_id; i < end_pos; i += group_size) {
+            let shift = (i & 3u) * 8u;
+            let a = (seq1[i >> 2u] >> shift) & 0xffu;
+            let b = (seq2[i >> 2u] >> shift) & 0xffu;
+            current_score = max(current_score + pair_score(a, b), 0);
+            max_score = max(max_score, current_score);
+        }
+    }
+
+    // Workgroup memory starts zeroed, and scores are never negative
+    atomicMax(&group_best, max_score);
+    workgroupBarrier();
+    if (local_id == 0u) {
+        atomicMax(&result, atomicLoad(&group_best));
+    }
+}
@@ -0,0 +1,278 @@
+// `soak`: align the same workload over and over for hours, to show the GPU
+// path doesn't leak or slow down over a run the length of a real one
+// Every launch through gpu_align_bytes creates its buffers on the shared OpenCL
+// context and drops them when it returns; a buffer, event or queue the driver
+// doesn't free only shows after hours, as GPU memory or file descriptors that
+// never come back, or as launches getting slower. The workload is a fixed set
+// of pairs, synthetic (--synthetic: lengths from 1 kb to 1 Mb, the second copy
+// with 5% substitutions, from the run seed) or the first chunks of a file
+// (--input, each against itself), aligned round robin so buffers of every size
+// are made and freed. Each pair's first score is its expected score: a launch
+// that scores differently fails the soak at once
+// Every `interval` a sample records GPU memory used (nvidia-smi, the whole
+// device), the process's resident memory, open file descriptors and threads
+// (/proc, Linux only; elsewhere these are missing) and the window's throughput,
+// and goes to the soak log as a JSON line. The first sample, after one interval
+// of warm-up, is the baseline. The soak fails as soon as any of them grows past
+// its limit over the baseline, or the mean throughput of the last DRIFT_WINDOWS
+// windows drops more than the allowed fraction below that of the first ones
+
+use crate::gpu::GpuDevice;
+use crate::seed::Seedable;
+use serde::Serialize;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_HOURS: f64 = 24.0;
+pub const DEFAULT_INTERVAL_S: u64 = 60;
+pub const DEFAULT_MAX_GPU_GROWTH_MB: f64 = 256.0;
+pub const DEFAULT_MAX_RSS_GROWTH_MB: f64 = 512.0;
+pub const DEFAULT_MAX_HANDLE_GROWTH: usize = 64;
+pub const DEFAULT_MAX_THROUGHPUT_DROP: f64 = 0.2;
+// Windows averaged at each end for throughput drift
+const DRIFT_WINDOWS: usize = 5;
+const SYNTHETIC_LENGTHS: [usize; 6] = [1_000, 4_097, 10_000, 65_537, 250_000, 1_000_000];
+const SYNTHETIC_SUBSTITUTION_RATE: f64 = 0.05;
+// Chunks of an --input file in the workload
+const INPUT_CHUNKS: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct SoakLimits {
+    pub max_gpu_growth_mb: f64,
+    pub max_rss_growth_mb: f64,
+    // File descriptors, and threads, each
+    pub max_handle_growth: usize,
+    // Fraction of the starting throughput
+    pub max_throughput_drop: f64,
+}
+
+impl Default for SoakLimits {
+    fn default() -> Self {
+        Self {
+            max_gpu_growth_mb: DEFAULT_MAX_GPU_GROWTH_MB,
+            max_rss_growth_mb: DEFAULT_MAX_RSS_GROWTH_MB,
+            max_handle_growth: DEFAULT_MAX_HANDLE_GROWTH,
+            max_throughput_drop: DEFAULT_MAX_THROUGHPUT_DROP,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SoakOptions {
+    pub duration: Duration,
+    pub interval: Duration,
+    pub limits: SoakLimits,
+}
+
+impl SoakOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.duration.is_zero() {
+            return Err("soak --hours must be more than 0".to_string());
+        }
+        if self.interval.is_zero() {
+            return Err("soak --interval must be at least 1 second".to_string());
+        }
+        if !(self.limits.max_throughput_drop > 0.0 && self.limits.max_throughput_drop < 1.0) {
+            return Err(format!("soak --max-throughput-drop must be between 0 and 1 (a fraction), not {}", self.limits.max_throughput_drop));
+        }
+        if self.limits.max_gpu_growth_mb < 0.0 || self.limits.max_rss_growth_mb < 0.0 {
+            return Err("soak memory growth limits can't be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Pairs aligned round robin for the whole soak
+pub struct Workload {
+    pub name: String,
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Workload {
+    pub fn synthetic() -> Self {
+        let mut rng = Seedable::stream("soak");
+        let pairs = SYNTHETIC_LENGTHS.iter().map(|&len| {
+            let seq1: Vec<u8> = (0..len).map(|_| rng.base()).collect();
+            let seq2 = seq1.iter().map(|&base| if rng.chance(SYNTHETIC_SUBSTITUTION_RATE) { rng.base() } else { base }).collect();
+            (seq1, seq2)
+        }).collect();
+        Self { name: format!("synthetic, {} pairs of 1 kb to 1 Mb", SYNTHETIC_LENGTHS.len()), pairs }
+    }
+
+    // The first chunks of `file`, each against itself
+    pub fn from_file(file: &str, chunk_size: crate::chunking::ChunkSize) -> Result<Self, String> {
+        let mut pairs = Vec::new();
+        crate::aligner::read_file_only(file, &Default::default(), chunk_size, crate::read_chunk::ReadNameMode::Discard, |chunk| {
+            pairs.push((chunk.bases().to_vec(), chunk.bases().to_vec()));
+            pairs.len() < INPUT_CHUNKS
+        })?;
+        if pairs.is_empty() {
+            return Err(format!("{} has no reads", file));
+        }
+        Ok(Self { name: format!("{}, {} chunk(s) of {}", crate::paths::file_name(file), pairs.len(), chunk_size), pairs })
+    }
+
+    fn bases(&self) -> usize {
+        self.pairs.iter().map(|(seq1, seq2)| seq1.len().min(seq2.len())).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoakSample {
+    pub elapsed_s: f64,
+    pub launches: u64,
+    // This window's
+    pub bases_per_s: f64,
+    pub gpu_memory_used_mb: Option<f64>,
+    pub rss_mb: Option<f64>,
+    pub open_fds: Option<usize>,
+    pub threads: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoakSummary {
+    pub samples: Vec<SoakSample>,
+    pub launches: u64,
+    // Why the soak stopped early, if it did
+    pub failure: Option<String>,
+}
+
+// Field `name` of /proc/self/status, in its own units
+#[cfg(target_os = "linux")]
+fn proc_status(name: &str) -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(name))?;
+    line[name.len()..].trim_start_matches(':').split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn process_handles() -> (Option<f64>, Option<usize>, Option<usize>) {
+    let rss_mb = proc_status("VmRSS").map(|kb| kb as f64 / 1024.0);
+    let fds = std::fs::read_dir("/proc/self/fd").ok().map(|dir| dir.count());
+    (rss_mb, fds, proc_status("Threads"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_handles() -> (Option<f64>, Option<usize>, Option<usize>) {
+    (None, None, None)
+}
+
+fn sample(start: Instant, launches: u64, window_bases: usize, window: Duration) -> SoakSample {
+    let (rss_mb, open_fds, threads) = process_handles();
+    SoakSample {
+        elapsed_s: start.elapsed().as_secs_f64(),
+        launches,
+        bases_per_s: window_bases as f64 / window.as_secs_f64().max(1e-9),
+        gpu_memory_used_mb: crate::metrics_sampler::gpu_memory_used_mb(),
+        rss_mb,
+        open_fds,
+        threads,
+    }
+}
+
+// The first limit `latest` breaks against `baseline`, with the throughput of
+// every window so far
+fn breach(baseline: &SoakSample, latest: &SoakSample, samples: &[SoakSample], limits: &SoakLimits) -> Option<String> {
+    let grown = |before: Option<f64>, after: Option<f64>| before.zip(after).map(|(before, after)| after - before);
+    if let Some(growth) = grown(baseline.gpu_memory_used_mb, latest.gpu_memory_used_mb).filter(|&growth| growth > limits.max_gpu_growth_mb) {
+        return Some(format!("GPU memory used grew {:.0} MB (limit {:.0} MB)", growth, limits.max_gpu_growth_mb));
+    }
+    if let Some(growth) = grown(baseline.rss_mb, latest.rss_mb).filter(|&growth| growth > limits.max_rss_growth_mb) {
+        return Some(format!("resident memory grew {:.0} MB (limit {:.0} MB)", growth, limits.max_rss_growth_mb));
+    }
+    let handles = |before: Option<usize>, after: Option<usize>| before.zip(after).map(|(before, after)| after.saturating_sub(before));
+    if let Some(growth) = handles(baseline.open_fds, latest.open_fds).filter(|&growth| growth > limits.max_handle_growth) {
+        return Some(format!("{} more file descriptors open (limit {})", growth, limits.max_handle_growth));
+    }
+    if let Some(growth) = handles(baseline.threads, latest.threads).filter(|&growth| growth > limits.max_handle_growth) {
+        return Some(format!("{} more threads (limit {})", growth, limits.max_handle_growth));
+    }
+    // The baseline window is warm-up, so drift starts from the one after it
+    let windows = &samples[1..];
+    if windows.len() >= 2 * DRIFT_WINDOWS {
+        let mean = |windows: &[SoakSample]| windows.iter().map(|sample| sample.bases_per_s).sum::<f64>() / windows.len() as f64;
+        let (first, last) = (mean(&windows[..DRIFT_WINDOWS]), mean(&windows[windows.len() - DRIFT_WINDOWS..]));
+        if first > 0.0 && last < first * (1.0 - limits.max_throughput_drop) {
+            return Some(format!("throughput fell {:.1}% from {:.1} to {:.1} Mbases/s (limit {:.0}%)", (1.0 - last / first) * 100.0,
+                                first / 1e6, last / 1e6, limits.max_throughput_drop * 100.0));
+        }
+    }
+    None
+}
+
+// Align `workload` on `device` (the CPU path without one) until the time is up
+// or a limit is broken, logging every sample to `log`; Err only when a launch
+// fails or scores differently
+pub fn run(workload: &Workload, options: &SoakOptions, device: Option<&GpuDevice>, log: &mut dyn Write) -> Result<SoakSummary, String> {
+    let align = |seq1: &[u8], seq2: &[u8]| match device {
+        Some(device) => crate::aligner::gpu_align_bytes(seq1, seq2, device),
+        None => Ok(crate::cpu_align::cpu_align_bytes(seq1, seq2)),
+    };
+    let mut expected: Vec<Option<i32>> = vec![None; workload.pairs.len()];
+    let mut summary = SoakSummary { samples: Vec::new(), launches: 0, failure: None };
+    let start = Instant::now();
+    let mut window_start = start;
+    let mut window_bases = 0;
+    while start.elapsed() < options.duration {
+        for (index, (seq1, seq2)) in workload.pairs.iter().enumerate() {
+            let score = align(seq1, seq2).map_err(|e| format!("launch {} failed after {:.0} s: {}", summary.launches + 1, start.elapsed().as_secs_f64(), e))?;
+            match expected[index] {
+                Some(first) if first != score => {
+                    return Err(format!("pair {} ({} bases) scored {} after {:.0} s, {} at first", index + 1, seq1.len(), score, start.elapsed().as_secs_f64(), first));
+                },
+                Some(_) => {},
+                None => expected[index] = Some(score),
+            }
+            summary.launches += 1;
+            window_bases += seq1.len().min(seq2.len());
+        }
+        if window_start.elapsed() < options.interval {
+            continue;
+        }
+        let latest = sample(start, summary.launches, window_bases, window_start.elapsed());
+        let line = serde_json::to_string(&latest).map_err(|e| format!("Failed to serialize soak sample: {}", e))?;
+        writeln!(log, "{}", line).and_then(|_| log.flush()).map_err(|e| format!("Failed to write the soak log: {}", e))?;
+        println!("  {:>7.0} s: {} launches, {:.1} Mbases/s, GPU memory {}, RSS {}, {} fds, {} threads", latest.elapsed_s, latest.launches,
+                 latest.bases_per_s / 1e6, mb(latest.gpu_memory_used_mb), mb(latest.rss_mb), count(latest.open_fds), count(latest.threads));
+        summary.samples.push(latest);
+        window_start = Instant::now();
+        window_bases = 0;
+        if let (Some(baseline), Some(latest)) = (summary.samples.first(), summary.samples.last()) {
+            if let Some(reason) = breach(baseline, latest, &summary.samples, &options.limits) {
+                summary.failure = Some(reason);
+                break;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn mb(value: Option<f64>) -> String {
+    value.map_or("n/a".to_string(), |mb| format!("{:.0} MB", mb))
+}
+
+fn count(value: Option<usize>) -> String {
+    value.map_or("n/a".to_string(), |count| count.to_string())
+}
+
+impl SoakSummary {
+    pub fn print(&self, workload: &Workload) {
+        let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) else {
+            println!("Soak ended before the first sample: {} launches", self.launches);
+            return;
+        };
+        println!("Soak: {} launches over {:.2} h on {} ({} bases per round)", self.launches, last.elapsed_s / 3600.0, workload.name, workload.bases());
+        let change = |before: Option<f64>, after: Option<f64>| match before.zip(after) {
+            Some((before, after)) => format!("{:.0} -> {:.0} MB ({:+.0})", before, after, after - before),
+            None => "n/a".to_string(),
+        };
+        let handles = |before: Option<usize>, after: Option<usize>| match before.zip(after) {
+            Some((before, after)) => format!("{} -> {} ({:+})", before, after, after as i64 - before as i64),
+            None => "n/a".to_string(),
+        };
+        println!("  GPU memory used: {}", change(first.gpu_memory_used_mb, last.gpu_memory_used_mb));
+        println!("  Resident memory: {}", change(first.rss_mb, last.rss_mb));
+        println!("  Open fds: {}, threads: {}", handles(first.open_fds, last.open_fds), handles(first.threads, last.threads));
+        println!("  Throughput: {:.1} -> {:.1} Mbases/s", first.bases_per_s / 1e6, last.bases_per_s / 1e6);
+    }
+}
@@ -0,0 +1,121 @@
+// --splice-aware: local alignment of a transcript against genomic sequence
+// The GPU kernels score ungapped diagonals, so this runs on the CPU. It is a
+// Smith-Waterman with the selected match/mismatch/gap scores (scoring.rs), plus
+// an intron state: skipping target bases costs `intron_open` once and
+// `intron_extend` per skipped base, instead of the gap penalty per base. Skips
+// shorter than MIN_INTRON bases stay ordinary gaps. Rows are the transcript
+// (seq1), columns the genomic sequence (seq2); memory is linear in the genomic
+// length, time is the product of both lengths
+
+use crate::n_policy::is_n;
+
+pub const MIN_INTRON: usize = 20;
+pub const DEFAULT_INTRON_OPEN: i32 = 20;
+pub const DEFAULT_INTRON_EXTEND: i32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpliceParams {
+    // Penalties, as positive numbers
+    pub intron_open: i32,
+    pub intron_extend: i32,
+}
+
+impl Default for SpliceParams {
+    fn default() -> Self {
+        Self { intron_open: DEFAULT_INTRON_OPEN, intron_extend: DEFAULT_INTRON_EXTEND }
+    }
+}
+
+impl SpliceParams {
+    pub fn new(intron_open: i32, intron_extend: i32) -> Result<Self, String> {
+        if intron_open < 0 || intron_extend < 0 {
+            return Err(format!("Invalid intron penalties open {} / extend {}: penalties can't be negative", intron_open, intron_extend));
+        }
+        Ok(Self { intron_open, intron_extend })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplicedAlignment {
+    pub score: i32,
+    // 1-based inclusive ends of the best local alignment
+    pub seq1_end: usize,
+    pub seq2_end: usize,
+    pub introns: usize,
+    pub intron_bases: usize,
+}
+
+// Score plus the introns on the path that reached it
+#[derive(Debug, Clone, Copy, Default)]
+struct Cell {
+    score: i32,
+    introns: usize,
+    intron_bases: usize,
+}
+
+impl Cell {
+    fn better(self, other: Cell) -> Cell {
+        if other.score > self.score { other } else { self }
+    }
+
+    fn plus(self, delta: i32) -> Cell {
+        Cell { score: self.score + delta, ..self }
+    }
+}
+
+fn pair_score(a: u8, b: u8) -> i32 {
+    if is_n(a) || is_n(b) {
+        crate::n_policy::selected().n_score()
+    } else if a.eq_ignore_ascii_case(&b) {
+        crate::scoring::selected().match_score
+    } else {
+        crate::scoring::selected().mismatch_penalty
+    }
+}
+
+pub fn align(seq1: &[u8], seq2: &[u8], params: SpliceParams) -> SplicedAlignment {
+    let m = seq2.len();
+    let gap_penalty = crate::scoring::selected().gap_penalty;
+    let mut prev = vec![Cell::default(); m + 1];
+    let mut cur = vec![Cell::default(); m + 1];
+    let mut intron = vec![Cell::default(); m + 1];
+    let mut best = SplicedAlignment::default();
+    let unreachable = Cell { score: i32::MIN / 2, ..Cell::default() };
+
+    for (i, &a) in seq1.iter().enumerate() {
+        cur[0] = Cell::default();
+        intron[0] = unreachable;
+        for j in 1..=m {
+            // Extend the intron ending at j-1, or open one spanning the last MIN_INTRON bases
+            let extended = intron[j - 1].plus(-params.intron_extend);
+            intron[j] = Cell { intron_bases: extended.intron_bases + 1, ..extended };
+            if j >= MIN_INTRON {
+                let from = cur[j - MIN_INTRON];
+                let opened = Cell {
+                    score: from.score - params.intron_open - params.intron_extend * MIN_INTRON as i32,
+                    introns: from.introns + 1,
+                    intron_bases: from.intron_bases + MIN_INTRON,
+                };
+                intron[j] = intron[j].better(opened);
+            }
+
+            let cell = Cell::default()
+                .better(prev[j - 1].plus(pair_score(a, seq2[j - 1])))
+                .better(cur[j - 1].plus(gap_penalty))
+                .better(prev[j].plus(gap_penalty))
+                .better(intron[j]);
+            cur[j] = cell;
+            if cell.score > best.score {
+                best = SplicedAlignment {
+                    score: cell.score,
+                    seq1_end: i + 1,
+                    seq2_end: j,
+                    introns: cell.introns,
+                    intron_bases: cell.intron_bases,
+                };
+            }
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    best
+}
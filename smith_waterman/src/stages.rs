@@ -0,0 +1,406 @@
+// Stages of a WGS file pass and the bounded channels between them
+//   reader   input bytes: fetch, read and decompress (async_input)
+//   parser   bytes into chunks of reads (async_input; BAM/CRAM and memory-mapped
+//            inputs parse as they read, so all of their time is the reader's)
+//   encoder  sanitizing, trimming, the screen, QC, read binning and masking
+//   GPU      launches and scores
+//   writer   per-read output, and handing spent chunks back to the parser
+// Each stage runs on its own thread and each channel holds a few items, so a
+// slow stage fills the channel in front of it and the stages upstream wait:
+// that time is counted as blocked, and time waiting on an empty channel as
+// starved. Whatever is left of a stage's time is busy, and the busiest stage is
+// the one holding the file up. Like file_timing, a run processes its files one
+// at a time, so the counters belong to its current file without being told
+// which; they are the run's (run_context.rs), so runs on other threads of the
+// process count their own
+// Channel depths come from .env: RAW_CHANNEL_DEPTH (reader to parser, in
+// 1 MiB blocks), INPUT_CHANNEL_DEPTH (parser to encoder), ENCODED_CHANNEL_DEPTH
+// (encoder to GPU) and SCORED_CHANNEL_DEPTH (GPU to writer); the chunk
+// channels shrink with --gpu-fraction
+// Each stage's current activity (busy, starved, blocked, done) and the time of
+// the last progress any stage made are kept as well, for the stall watchdog
+// (which watches the CLI's run)
+
+use crate::run_context;
+use serde::{Deserialize, Serialize};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Reader,
+    Parser,
+    Encoder,
+    Gpu,
+    Writer,
+}
+
+pub const STAGES: [Stage; 5] = [Stage::Reader, Stage::Parser, Stage::Encoder, Stage::Gpu, Stage::Writer];
+
+// Decoded input bytes per block on the reader-to-parser channel
+pub const RAW_BLOCK_BYTES: usize = 1024 * 1024;
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Reader => "reader",
+            Stage::Parser => "parser",
+            Stage::Encoder => "encoder",
+            Stage::Gpu => "gpu",
+            Stage::Writer => "writer",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    // Depth of the channel this stage fills
+    pub fn channel_depth(&self) -> usize {
+        let (var, default) = match self {
+            Stage::Reader => ("RAW_CHANNEL_DEPTH", 8),
+            Stage::Parser => return crate::async_input::get_channel_depth(),
+            Stage::Encoder => ("ENCODED_CHANNEL_DEPTH", 2),
+            Stage::Gpu => ("SCORED_CHANNEL_DEPTH", 4),
+            Stage::Writer => return 0,
+        };
+        let depth = std::env::var(var)
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|&v| v > 0)
+            .unwrap_or(default);
+        match self {
+            Stage::Reader => depth,
+            _ => crate::gpu_share::channel_depth(depth),
+        }
+    }
+}
+
+struct Meter {
+    items: AtomicU64,
+    bytes: AtomicU64,
+    wall_ns: AtomicU64,
+    starved_ns: AtomicU64,
+    blocked_ns: AtomicU64,
+}
+
+impl Meter {
+    const fn new() -> Self {
+        Self { items: AtomicU64::new(0), bytes: AtomicU64::new(0), wall_ns: AtomicU64::new(0), starved_ns: AtomicU64::new(0), blocked_ns: AtomicU64::new(0) }
+    }
+}
+
+// One run's stage counters and activities
+pub struct StageMeters {
+    meters: [Meter; 5],
+    states: [State; 5],
+    last_progress_ms: AtomicU64,
+}
+
+impl Default for StageMeters {
+    fn default() -> Self {
+        Self {
+            meters: [Meter::new(), Meter::new(), Meter::new(), Meter::new(), Meter::new()],
+            states: [State::new(), State::new(), State::new(), State::new(), State::new()],
+            last_progress_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+impl StageMeters {
+    fn meter(&self, stage: Stage) -> &Meter {
+        &self.meters[stage.index()]
+    }
+
+    fn state(&self, stage: Stage) -> &State {
+        &self.states[stage.index()]
+    }
+}
+
+// The meters of the run the current thread works for
+fn with_meters<T>(f: impl FnOnce(&StageMeters) -> T) -> T {
+    run_context::with_state(|state| f(&state.stages))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    Idle,
+    Busy,
+    Starved,
+    Blocked,
+    Done,
+}
+
+const ACTIVITIES: [Activity; 5] = [Activity::Idle, Activity::Busy, Activity::Starved, Activity::Blocked, Activity::Done];
+
+impl Activity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Activity::Idle => "idle",
+            Activity::Busy => "busy",
+            Activity::Starved => "starved",
+            Activity::Blocked => "blocked",
+            Activity::Done => "done",
+        }
+    }
+
+    // Running and not yet finished
+    pub fn is_active(&self) -> bool {
+        matches!(self, Activity::Busy | Activity::Starved | Activity::Blocked)
+    }
+}
+
+struct State {
+    activity: AtomicU8,
+    // Milliseconds since EPOCH the activity started
+    since_ms: AtomicU64,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self { activity: AtomicU8::new(Activity::Idle as u8), since_ms: AtomicU64::new(0) }
+    }
+}
+
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+fn now_ms() -> u64 {
+    EPOCH.elapsed().as_millis() as u64
+}
+
+fn progress(meters: &StageMeters) {
+    meters.last_progress_ms.store(now_ms(), Ordering::Relaxed);
+}
+
+pub fn set_activity(stage: Stage, activity: Activity) {
+    with_meters(|meters| {
+        let state = meters.state(stage);
+        state.activity.store(activity as u8, Ordering::Relaxed);
+        state.since_ms.store(now_ms(), Ordering::Relaxed);
+        progress(meters);
+    });
+}
+
+fn activity(stage: Stage) -> Activity {
+    with_meters(|meters| ACTIVITIES[meters.state(stage).activity.load(Ordering::Relaxed) as usize])
+}
+
+// Each stage's activity and how long it has been at it, in pipeline order
+pub fn activities() -> Vec<(Stage, Activity, Duration)> {
+    let now = now_ms();
+    STAGES.iter()
+        .map(|stage| {
+            let since_ms = with_meters(|meters| meters.state(*stage).since_ms.load(Ordering::Relaxed));
+            (*stage, activity(*stage), Duration::from_millis(now.saturating_sub(since_ms)))
+        })
+        .collect()
+}
+
+// Time since any stage last passed an item on, finished waiting or changed activity
+pub fn since_progress() -> Duration {
+    Duration::from_millis(now_ms().saturating_sub(with_meters(|meters| meters.last_progress_ms.load(Ordering::Relaxed))))
+}
+
+// Marks `stage` as waiting until dropped, then back to what it was doing
+pub struct Waiting {
+    stage: Stage,
+    was: Activity,
+}
+
+pub fn waiting(stage: Stage, activity: Activity) -> Waiting {
+    let was = self::activity(stage);
+    set_activity(stage, activity);
+    Waiting { stage, was }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        set_activity(self.stage, self.was);
+    }
+}
+
+pub fn start_file() {
+    with_meters(|meters| {
+        for meter in &meters.meters {
+            for counter in [&meter.items, &meter.bytes, &meter.wall_ns, &meter.starved_ns, &meter.blocked_ns] {
+                counter.store(0, Ordering::Relaxed);
+            }
+        }
+    });
+    for stage in STAGES {
+        set_activity(stage, Activity::Idle);
+    }
+}
+
+// An item of `bytes` passed downstream
+pub fn add_item(stage: Stage, bytes: usize) {
+    with_meters(|meters| {
+        meters.meter(stage).items.fetch_add(1, Ordering::Relaxed);
+        meters.meter(stage).bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        progress(meters);
+    });
+}
+
+// Time waiting for the stage upstream
+pub fn add_starved(stage: Stage, waited: Duration) {
+    with_meters(|meters| {
+        meters.meter(stage).starved_ns.fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+        progress(meters);
+    });
+}
+
+// Time waiting for room in the channel downstream
+pub fn add_blocked(stage: Stage, waited: Duration) {
+    with_meters(|meters| {
+        meters.meter(stage).blocked_ns.fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+        progress(meters);
+    });
+}
+
+// A stage's running time for the file; recorded when dropped, so early returns count
+pub struct StageClock {
+    stage: Stage,
+    started: Instant,
+}
+
+impl StageClock {
+    pub fn start(stage: Stage) -> Self {
+        set_activity(stage, Activity::Busy);
+        Self { stage, started: Instant::now() }
+    }
+}
+
+impl Drop for StageClock {
+    fn drop(&mut self) {
+        with_meters(|meters| meters.meter(self.stage).wall_ns.fetch_add(self.started.elapsed().as_nanos() as u64, Ordering::Relaxed));
+        set_activity(self.stage, Activity::Done);
+    }
+}
+
+pub struct StageSender<T> {
+    tx: SyncSender<T>,
+    queued: Arc<AtomicUsize>,
+    stage: Stage,
+}
+
+impl<T> StageSender<T> {
+    // Err with the item once the receiving stage has stopped
+    pub fn send(&self, item: T, bytes: usize) -> Result<(), T> {
+        let waited = Instant::now();
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let sent = {
+            let _waiting = waiting(self.stage, Activity::Blocked);
+            self.tx.send(item).map_err(|e| e.0)
+        };
+        add_blocked(self.stage, waited.elapsed());
+        match sent {
+            Ok(()) => add_item(self.stage, bytes),
+            Err(_) => {
+                self.queued.fetch_sub(1, Ordering::Relaxed);
+            },
+        }
+        sent
+    }
+}
+
+pub struct StageReceiver<T> {
+    rx: Receiver<T>,
+    queued: Arc<AtomicUsize>,
+    depth: usize,
+    stage: Stage,
+}
+
+impl<T> StageReceiver<T> {
+    // None once the sending stage has finished
+    pub fn recv(&self) -> Option<T> {
+        let waited = Instant::now();
+        let item = {
+            let _waiting = waiting(self.stage, Activity::Starved);
+            self.rx.recv().ok()
+        };
+        add_starved(self.stage, waited.elapsed());
+        if item.is_some() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        item
+    }
+
+    // Items waiting, for the dashboard's queue gauge
+    pub fn len(&self) -> usize {
+        self.queued.load(Ordering::Relaxed).min(self.depth)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.depth
+    }
+}
+
+// Channel from stage `from` to stage `to` holding `depth` items, usually from.channel_depth()
+pub fn channel<T>(from: Stage, to: Stage, depth: usize) -> (StageSender<T>, StageReceiver<T>) {
+    let depth = depth.max(1);
+    let (tx, rx) = std::sync::mpsc::sync_channel(depth);
+    let queued = Arc::new(AtomicUsize::new(0));
+    (StageSender { tx, queued: queued.clone(), stage: from }, StageReceiver { rx, queued, depth, stage: to })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StageStats {
+    pub stage: String,
+    pub items: u64,
+    pub bytes: u64,
+    pub wall_ms: f64,
+    pub busy_ms: f64,
+    pub starved_ms: f64,
+    pub blocked_ms: f64,
+}
+
+impl StageStats {
+    pub fn busy_fraction(&self) -> f64 {
+        if self.wall_ms > 0.0 { self.busy_ms / self.wall_ms } else { 0.0 }
+    }
+
+    // Bytes passed downstream per second of the stage's running time
+    pub fn bytes_per_second(&self) -> f64 {
+        if self.wall_ms > 0.0 { self.bytes as f64 / (self.wall_ms / 1000.0) } else { 0.0 }
+    }
+}
+
+// The current run's file's stages that ran, in pipeline order
+pub fn snapshot() -> Vec<StageStats> {
+    let ms = |counter: &AtomicU64| counter.load(Ordering::Relaxed) as f64 / 1e6;
+    with_meters(|meters| STAGES.iter()
+        .filter(|stage| meters.meter(**stage).wall_ns.load(Ordering::Relaxed) > 0)
+        .map(|stage| {
+            let meter = meters.meter(*stage);
+            let (wall_ms, starved_ms, blocked_ms) = (ms(&meter.wall_ns), ms(&meter.starved_ns), ms(&meter.blocked_ns));
+            StageStats {
+                stage: stage.name().to_string(),
+                items: meter.items.load(Ordering::Relaxed),
+                bytes: meter.bytes.load(Ordering::Relaxed),
+                wall_ms,
+                busy_ms: (wall_ms - starved_ms - blocked_ms).max(0.0),
+                starved_ms,
+                blocked_ms,
+            }
+        })
+        .collect())
+}
+
+// One line per file, e.g. "reader 41% busy 310 MB/s | ... ; bottleneck: gpu"
+pub fn summary(stats: &[StageStats]) -> String {
+    let stages: Vec<String> = stats.iter().map(|s| {
+        format!("{} {:.0}% busy, {:.0}% starved, {:.0}% blocked, {:.0} MB/s", s.stage, s.busy_fraction() * 100.0,
+                s.starved_ms / s.wall_ms.max(1e-9) * 100.0, s.blocked_ms / s.wall_ms.max(1e-9) * 100.0, s.bytes_per_second() / 1e6)
+    }).collect();
+    match stats.iter().max_by(|a, b| a.busy_fraction().total_cmp(&b.busy_fraction())) {
+        Some(bottleneck) => format!("{}; bottleneck: {}", stages.join(" | "), bottleneck.stage),
+        None => "none recorded".to_string(),
+    }
+}
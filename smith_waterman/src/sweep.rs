@@ -0,0 +1,353 @@
+// `sweep`: the WGS pipeline over a grid of settings, for picking the fastest
+// settings that still score well
+// Each --param names a setting and its values, as an inclusive range (1..10) or
+// a list (500,1000,5000). The grid is every combination, the last --param
+// varying fastest. Each point runs as its own `--full-wgs --gpu` process, as a
+// batch sample does (batch.rs), one at a time so points don't compete for the
+// GPU and their throughputs compare. Every point uses --output-root as its
+// --output-dir, so all of their benchmark results land in <root>/results under
+// the registry's run numbers, where bench list, bench export and run diff find
+// them. --subsample N runs each point on the first N input files (--num-files).
+// Arguments after -- go to every run
+// Settings:
+//   match                      --match-score
+//   mismatch, gap (gap_open)   --mismatch-penalty, --gap-penalty; given as costs
+//                              (positive) or as scores (0 or negative)
+//   chunk_reads                --chunk-size in reads
+//   chunk_size                 --chunk-size as given (e.g. 512KB, 16MB)
+//   kernel                     --kernel
+//   gpu_queues                 GPU_QUEUES
+// Each point's settings, status, run number, totals, identity-weighted score and
+// throughput go to sweep_results.tsv in the root. A finished point is
+// Pareto-optimal when no other is at least as fast and at least as
+// high-scoring with one of the two strictly better; the identity-weighted
+// score is the one compared, as it stays comparable when the scores are swept
+
+use crate::exit_codes::ExitKind;
+use crate::tools::benchmark::{self, BenchmarkResult};
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Runs a sweep may start
+pub const MAX_POINTS: usize = 256;
+pub const RESULTS_FILE: &str = "sweep_results.tsv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Match,
+    Mismatch,
+    Gap,
+    ChunkReads,
+    ChunkSize,
+    Kernel,
+    GpuQueues,
+}
+
+impl Setting {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "match" => Ok(Setting::Match),
+            "mismatch" => Ok(Setting::Mismatch),
+            "gap" | "gap_open" => Ok(Setting::Gap),
+            "chunk_reads" => Ok(Setting::ChunkReads),
+            "chunk_size" => Ok(Setting::ChunkSize),
+            "kernel" => Ok(Setting::Kernel),
+            "gpu_queues" => Ok(Setting::GpuQueues),
+            _ => Err(format!("Invalid --param setting '{}': expected match, mismatch, gap, chunk_reads, chunk_size, kernel or gpu_queues", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::Match => "match",
+            Setting::Mismatch => "mismatch",
+            Setting::Gap => "gap",
+            Setting::ChunkReads => "chunk_reads",
+            Setting::ChunkSize => "chunk_size",
+            Setting::Kernel => "kernel",
+            Setting::GpuQueues => "gpu_queues",
+        }
+    }
+
+    // The run flag the setting is passed as; None for .env settings
+    fn flag(&self) -> Option<&'static str> {
+        match self {
+            Setting::Match => Some("--match-score"),
+            Setting::Mismatch => Some("--mismatch-penalty"),
+            Setting::Gap => Some("--gap-penalty"),
+            Setting::ChunkReads | Setting::ChunkSize => Some("--chunk-size"),
+            Setting::Kernel => Some("--kernel"),
+            Setting::GpuQueues => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Setting::Match | Setting::Mismatch | Setting::Gap | Setting::ChunkReads | Setting::GpuQueues)
+    }
+
+    // `value` as the run takes it, or why it can't be used
+    fn normalize(&self, value: &str) -> Result<String, String> {
+        let invalid = |expected: &str| format!("Invalid --param {} value '{}': expected {}", self.name(), value, expected);
+        if !self.is_numeric() {
+            return match self {
+                Setting::ChunkSize => crate::chunking::ChunkSize::parse(value).map(|_| value.to_string()),
+                Setting::Kernel => crate::kernels::KernelVariant::parse(value).map(|kernel| kernel.name().to_string()),
+                _ => Ok(value.to_string()),
+            };
+        }
+        let number: i64 = value.parse().map_err(|_| invalid("a whole number"))?;
+        match self {
+            Setting::Match if number <= 0 => Err(invalid("a positive score")),
+            Setting::Mismatch | Setting::Gap => Ok((-number.abs()).to_string()),
+            Setting::ChunkReads if number <= 0 => Err(invalid("at least 1 read")),
+            Setting::ChunkReads => Ok(format!("{}reads", number)),
+            Setting::GpuQueues if number <= 0 => Err(invalid("at least 1 queue")),
+            _ => Ok(number.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub setting: Setting,
+    // As the run takes them
+    pub values: Vec<String>,
+}
+
+impl Param {
+    // name=values, the values a range (1..10) or a comma-separated list
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, values) = spec.split_once('=')
+            .ok_or_else(|| format!("Invalid --param '{}': expected setting=values, e.g. chunk_reads=500,1000,5000 or gap=1..10", spec))?;
+        let setting = Setting::parse(name)?;
+        let raw: Vec<String> = match values.split_once("..") {
+            Some((low, high)) if setting.is_numeric() => {
+                let bound = |value: &str| value.trim().parse::<i64>()
+                    .map_err(|_| format!("Invalid --param {} range '{}': expected whole numbers, e.g. 1..10", setting.name(), values));
+                let (low, high) = (bound(low)?, bound(high)?);
+                let (first, last) = (low.min(high), low.max(high));
+                if last - first >= MAX_POINTS as i64 {
+                    return Err(format!("--param {} range '{}' has more than {} values", setting.name(), values, MAX_POINTS));
+                }
+                (first..=last).map(|value| value.to_string()).collect()
+            },
+            Some(_) => return Err(format!("--param {} takes a list, not a range", setting.name())),
+            None => values.split(',').map(|value| value.trim().to_string()).filter(|value| !value.is_empty()).collect(),
+        };
+        if raw.is_empty() {
+            return Err(format!("--param {} has no values", setting.name()));
+        }
+        let mut values: Vec<String> = Vec::new();
+        for value in raw {
+            let value = setting.normalize(&value)?;
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        Ok(Self { setting, values })
+    }
+}
+
+// Every combination of the params' values, the last param varying fastest
+pub fn grid(params: &[Param]) -> Result<Vec<Vec<(Setting, String)>>, String> {
+    if params.is_empty() {
+        return Err("sweep needs at least one --param".to_string());
+    }
+    for (i, param) in params.iter().enumerate() {
+        if let Some(other) = params[..i].iter()
+            .find(|other| other.setting == param.setting || (other.setting.flag().is_some() && other.setting.flag() == param.setting.flag())) {
+            return Err(format!("--param {} and --param {} set the same thing; sweep one of them", other.setting.name(), param.setting.name()));
+        }
+    }
+    let points = params.iter().try_fold(1usize, |points, param| points.checked_mul(param.values.len()).filter(|&n| n <= MAX_POINTS));
+    if points.is_none() {
+        return Err(format!("The grid has more than {} points; sweep fewer values", MAX_POINTS));
+    }
+    let mut grid: Vec<Vec<(Setting, String)>> = vec![Vec::new()];
+    for param in params {
+        grid = grid.into_iter()
+            .flat_map(|point| param.values.iter().map(move |value| {
+                let mut point = point.clone();
+                point.push((param.setting, value.clone()));
+                point
+            }))
+            .collect();
+    }
+    Ok(grid)
+}
+
+// Forwarded arguments may not set what the grid sets
+pub fn check_forwarded(params: &[Param], forwarded: &[String]) -> Result<(), String> {
+    for param in params {
+        let Some(flag) = param.setting.flag() else { continue };
+        if forwarded.iter().any(|arg| arg == flag || arg.starts_with(&format!("{}=", flag))) {
+            return Err(format!("{} is swept by --param {}; don't pass it after --", flag, param.setting.name()));
+        }
+    }
+    for flag in ["--output-dir", "--num-files", "--full-wgs", "--gpu"] {
+        if forwarded.iter().any(|arg| arg == flag || arg.starts_with(&format!("{}=", flag))) {
+            return Err(format!("sweep sets {} for every run; don't pass it after --", flag));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct PointOutcome {
+    pub point: usize,
+    pub settings: Vec<(Setting, String)>,
+    pub status: ExitKind,
+    // The run's number and benchmark result, when it saved one
+    pub run: Option<(u64, BenchmarkResult)>,
+    pub pareto: bool,
+}
+
+impl PointOutcome {
+    fn finished(&self) -> Option<&BenchmarkResult> {
+        self.run.as_ref().map(|(_, result)| result)
+            .filter(|result| self.status.is_success() && !result.partial && result.total_bases > 0)
+    }
+
+    pub fn describe(&self) -> String {
+        let settings: Vec<String> = self.settings.iter().map(|(setting, value)| format!("{}={}", setting.name(), value)).collect();
+        settings.join(" ")
+    }
+}
+
+fn run_numbers(results_dir: &Path) -> Vec<u64> {
+    benchmark::load_runs(results_dir).map(|runs| runs.into_iter().map(|(number, _)| number).collect()).unwrap_or_default()
+}
+
+fn run_point(point: usize, settings: &[(Setting, String)], root: &Path, subsample: Option<usize>, forwarded: &[String]) -> Result<PointOutcome, String> {
+    let log_path = root.join("sweep_logs").join(format!("point_{}.log", point));
+    let log = File::create(&log_path).map_err(|e| format!("Failed to create {}: {}", log_path.display(), e))?;
+    let log_err = log.try_clone().map_err(|e| format!("Failed to open {}: {}", log_path.display(), e))?;
+    let exe = std::env::current_exe().map_err(|e| format!("Failed to locate rustseq_mini executable: {}", e))?;
+
+    let mut command = Command::new(exe);
+    command.args(["--full-wgs", "--gpu", "--output-dir"]).arg(root).args(forwarded);
+    if let Some(files) = subsample {
+        command.arg("--num-files").arg(files.to_string());
+    }
+    for (setting, value) in settings {
+        match setting.flag() {
+            Some(flag) => command.arg(format!("{}={}", flag, value)),
+            None => command.env("GPU_QUEUES", value),
+        };
+    }
+    command.stdout(Stdio::from(log)).stderr(Stdio::from(log_err));
+
+    let results_dir = root.join("results");
+    let before = run_numbers(&results_dir);
+    let outcome = PointOutcome { point, settings: settings.to_vec(), status: ExitKind::Failure, run: None, pareto: false };
+    println!("[point {}] {} (log: {})", point, outcome.describe(), log_path.display());
+    let started = std::time::Instant::now();
+    let status = command.status().map_err(|e| format!("Failed to start point {}: {}", point, e))?;
+    // The run this point registered is the newest result that wasn't there before
+    let run = benchmark::load_runs(&results_dir).unwrap_or_default().into_iter()
+        .filter(|(number, _)| !before.contains(number))
+        .max_by_key(|(number, _)| *number);
+    let outcome = PointOutcome { status: ExitKind::from_code(status.code()), run, ..outcome };
+    match outcome.finished() {
+        Some(result) => println!("[point {}] {:.1} Mbases/s, {:.2}% identity-weighted in {:.1} s", point,
+                                 result.throughput_bases_per_second / 1e6, result.normalized_score.identity_weighted * 100.0, started.elapsed().as_secs_f64()),
+        None => println!("[point {}] FAILED ({}) in {:.1} s", point, outcome.status.name(), started.elapsed().as_secs_f64()),
+    }
+    Ok(outcome)
+}
+
+// Mark the finished points no other finished point beats on both speed and score
+// A point whose run left chunks or files out of its score isn't compared
+fn mark_pareto(outcomes: &mut [PointOutcome]) {
+    let measures: Vec<Option<(f64, f64)>> = outcomes.iter()
+        .map(|o| o.finished().filter(|r| !r.is_partial).map(|r| (r.throughput_bases_per_second, r.normalized_score.identity_weighted)))
+        .collect();
+    for (outcome, measure) in outcomes.iter_mut().zip(&measures) {
+        let Some((speed, score)) = *measure else { continue };
+        outcome.pareto = !measures.iter().flatten()
+            .any(|&(s, q)| s >= speed && q >= score && (s > speed || q > score));
+    }
+}
+
+// Run every point of the grid, one at a time, with `root` as their output directory
+pub fn run_sweep(params: &[Param], root: &str, subsample: Option<usize>, forwarded: &[String]) -> Result<Vec<PointOutcome>, String> {
+    let grid = grid(params)?;
+    check_forwarded(params, forwarded)?;
+    if subsample == Some(0) {
+        return Err("--subsample must be at least 1 file".to_string());
+    }
+    let root = std::path::absolute(root).map_err(|e| format!("Invalid --output-root {}: {}", root, e))?;
+    create_dir_all(root.join("sweep_logs")).map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+    println!("Sweep: {} points, one at a time, output in {}", grid.len(), root.display());
+
+    let mut outcomes = Vec::with_capacity(grid.len());
+    for (i, settings) in grid.iter().enumerate() {
+        let outcome = run_point(i + 1, settings, &root, subsample, forwarded).unwrap_or_else(|e| {
+            println!("[point {}] FAILED: {}", i + 1, e);
+            PointOutcome { point: i + 1, settings: settings.clone(), status: ExitKind::Failure, run: None, pareto: false }
+        });
+        outcomes.push(outcome);
+    }
+    mark_pareto(&mut outcomes);
+    write_results(&results_path(&root), params, &outcomes)?;
+    Ok(outcomes)
+}
+
+pub fn results_path(root: &Path) -> PathBuf {
+    root.join(RESULTS_FILE)
+}
+
+fn write_results(path: &Path, params: &[Param], outcomes: &[PointOutcome]) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let settings: Vec<&str> = params.iter().map(|param| param.setting.name()).collect();
+    let mut written = writeln!(file, "point\t{}\tstatus\trun\treads\tbases\tscore\tscore_per_base\tidentity_weighted\ttime_s\tbases_per_s\tpareto",
+                               settings.join("\t"));
+    for outcome in outcomes {
+        let values: Vec<&str> = outcome.settings.iter().map(|(_, value)| value.as_str()).collect();
+        let run = match &outcome.run {
+            Some((number, r)) => format!("{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{:.1}\t{:.0}", number, r.total_reads, r.total_bases, r.total_score,
+                                         r.normalized_score.score_per_base, r.normalized_score.identity_weighted, r.total_time_seconds, r.throughput_bases_per_second),
+            None => ["."; 8].join("\t"),
+        };
+        written = written.and_then(|_| writeln!(file, "{}\t{}\t{}\t{}\t{}", outcome.point, values.join("\t"), outcome.status.name(), run,
+                                                if outcome.pareto { "yes" } else { "no" }));
+    }
+    written.map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn print_summary(outcomes: &[PointOutcome]) {
+    println!("\nSWEEP SUMMARY");
+    println!("=============");
+    println!("{:>5}  {:<8}  {:>5}  {:>10}  {:>9}  {:>8}  settings", "point", "status", "run", "Mbases/s", "identity", "time s");
+    for outcome in outcomes {
+        match (outcome.finished(), &outcome.run) {
+            (Some(result), Some((number, _))) => println!("{:>5}  {:<8}  {:>5}  {:>10.1}  {:>8.2}%  {:>8.1}  {}{}", outcome.point, outcome.status.name(), number,
+                                                          result.throughput_bases_per_second / 1e6, result.normalized_score.identity_weighted * 100.0,
+                                                          result.total_time_seconds, outcome.describe(), if outcome.pareto { "  *" } else { "" }),
+            _ => println!("{:>5}  {:<8}  {:>5}  {:>10}  {:>9}  {:>8}  {}", outcome.point, outcome.status.name(), ".", ".", ".", ".", outcome.describe()),
+        }
+    }
+    let finished = outcomes.iter().filter(|o| o.finished().is_some()).count();
+    println!("{} of {} points finished; * marks the Pareto-optimal ones (no other point is both faster and higher-scoring)", finished, outcomes.len());
+    let mut pareto: Vec<&PointOutcome> = outcomes.iter().filter(|o| o.pareto).collect();
+    pareto.sort_by(|a, b| {
+        let speed = |o: &PointOutcome| o.finished().map_or(0.0, |r| r.throughput_bases_per_second);
+        speed(b).total_cmp(&speed(a))
+    });
+    for outcome in pareto {
+        println!("  point {}: {}", outcome.point, outcome.describe());
+    }
+}
+
+// Success only if every point finished, a data error if none did
+pub fn exit_kind(outcomes: &[PointOutcome]) -> ExitKind {
+    if outcomes.iter().all(|o| o.status == ExitKind::Success && o.finished().is_some()) {
+        ExitKind::Success
+    } else if outcomes.iter().any(|o| o.finished().is_some()) {
+        ExitKind::PartialSuccess
+    } else {
+        ExitKind::DataError
+    }
+}
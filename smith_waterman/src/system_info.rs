@@ -193,39 +193,14 @@ impl SystemInfo {
         8.0
     }
     
-    /// Detect total system RAM
+    /// Detect total system RAM (sysinfo reads /proc/meminfo on Linux and the
+    /// OS memory APIs on Windows and macOS)
     fn detect_total_ram() -> Result<f64, String> {
-        // Try /proc/meminfo first
-        if let Ok(content) = std::fs::read_to_string("/proc/meminfo") {
-            for line in content.lines() {
-                if line.starts_with("MemTotal:") {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() >= 2 {
-                        if let Ok(kb) = parts[1].parse::<f64>() {
-                            return Ok(kb / (1024.0 * 1024.0)); // Convert KB to GB
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Try free command as fallback
-        let output = Command::new("free")
-            .args(&["-g"])
-            .output()
-            .map_err(|e| format!("Failed to run free command: {}", e))?;
-        
-        if output.status.success() {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            let lines: Vec<&str> = output_str.lines().collect();
-            if lines.len() >= 2 {
-                let parts: Vec<&str> = lines[1].split_whitespace().collect();
-                if parts.len() >= 2 {
-                    if let Ok(gb) = parts[1].parse::<f64>() {
-                        return Ok(gb);
-                    }
-                }
-            }
+        let mut system = sysinfo::System::new();
+        system.refresh_memory();
+        let bytes = system.total_memory();
+        if bytes > 0 {
+            return Ok(bytes as f64 / (1024.0 * 1024.0 * 1024.0));
         }
         
         // Default fallback
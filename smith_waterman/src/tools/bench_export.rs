@@ -0,0 +1,134 @@
+// `bench export`: stored benchmark runs flattened into a table for plotting
+// The run table has one row per run; the per-file table has one row per file
+// of each run, repeating the run columns that tell runs apart, so either loads
+// straight into pandas, R, or a spreadsheet
+
+use crate::tools::benchmark::BenchmarkResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(format!("Invalid export format '{}': expected csv", value)),
+        }
+    }
+}
+
+const RUN_COLUMNS: [&str; 30] = [
+    "run", "status", "version", "timestamp", "mode", "gpu_name", "chunk_size", "chunk_unit",
+    "parallel_files", "cpu_cores", "files", "reads", "bases", "score", "score_per_base",
+    "time_s", "reads_per_s", "bases_per_s", "gpu_utilization", "kernel_ms", "transfer_ms",
+    "latency_p50_ms", "latency_p95_ms", "latency_p99_ms", "instance_type", "gpu_energy_kwh",
+    "kwh_per_gbase", "cost_usd", "cost_per_gbase_usd", "excluded_fraction",
+];
+
+const FILE_COLUMNS: [&str; 11] = [
+    "file_index", "file", "completed", "reads", "bases", "wall_ms", "decompress_ms",
+    "parse_ms", "transfer_ms", "kernel_ms", "bases_per_s",
+];
+
+// Run columns repeated on every per-file row
+const FILE_RUN_COLUMNS: [&str; 6] = ["run", "status", "version", "timestamp", "mode", "gpu_name"];
+
+pub fn export(runs: &[(u64, BenchmarkResult)], format: ExportFormat, per_file: bool) -> String {
+    match format {
+        ExportFormat::Csv if per_file => file_csv(runs),
+        ExportFormat::Csv => run_csv(runs),
+    }
+}
+
+fn run_key(number: u64, result: &BenchmarkResult) -> Vec<String> {
+    vec![
+        number.to_string(),
+        result.status().to_string(),
+        result.version.clone(),
+        result.timestamp.to_rfc3339(),
+        result.mode.clone(),
+        result.system_info.gpu_name.clone(),
+    ]
+}
+
+fn run_csv(runs: &[(u64, BenchmarkResult)]) -> String {
+    let mut out = csv_row(RUN_COLUMNS.iter().map(|c| c.to_string()));
+    for (number, r) in runs {
+        let mut row = run_key(*number, r);
+        row.extend([
+            r.chunk_size.to_string(),
+            r.chunk_unit.clone(),
+            r.parallel_files.to_string(),
+            r.cpu_cores_used.to_string(),
+            r.files_processed.to_string(),
+            r.total_reads.to_string(),
+            r.total_bases.to_string(),
+            r.total_score.to_string(),
+            r.normalized_score.score_per_base.to_string(),
+            format!("{:.3}", r.total_time_seconds),
+            format!("{:.1}", r.throughput_reads_per_second),
+            format!("{:.1}", r.throughput_bases_per_second),
+            format!("{:.1}", r.gpu_utilization_avg),
+            format!("{:.3}", r.gpu_timing.kernel_ms),
+            format!("{:.3}", r.gpu_timing.transfer_ms),
+            format!("{:.3}", r.chunk_latency.p50_ms),
+            format!("{:.3}", r.chunk_latency.p95_ms),
+            format!("{:.3}", r.chunk_latency.p99_ms),
+            r.cost.instance_type.clone(),
+            optional(r.cost.gpu_energy_kwh),
+            optional(r.cost.kwh_per_gbase),
+            optional(r.cost.cost_usd),
+            optional(r.cost.cost_per_gbase_usd),
+            format!("{:.6}", r.excluded_fraction),
+        ]);
+        out.push_str(&csv_row(row));
+    }
+    out
+}
+
+// Runs saved before per-file timing was recorded have no rows here
+fn file_csv(runs: &[(u64, BenchmarkResult)]) -> String {
+    let header = FILE_RUN_COLUMNS.iter().chain(FILE_COLUMNS.iter()).map(|c| c.to_string());
+    let mut out = csv_row(header);
+    for (number, r) in runs {
+        for f in &r.files {
+            let mut row = run_key(*number, r);
+            row.extend([
+                f.index.to_string(),
+                f.file.clone(),
+                f.completed.to_string(),
+                f.reads.to_string(),
+                f.bases.to_string(),
+                format!("{:.3}", f.wall_ms),
+                format!("{:.3}", f.decompress_ms),
+                format!("{:.3}", f.parse_ms),
+                format!("{:.3}", f.transfer_ms),
+                format!("{:.3}", f.kernel_ms),
+                format!("{:.1}", f.bases_per_second()),
+            ]);
+            out.push_str(&csv_row(row));
+        }
+    }
+    out
+}
+
+// Empty for values the run didn't measure
+fn optional(value: Option<f64>) -> String {
+    value.map_or(String::new(), |v| format!("{:.6}", v))
+}
+
+fn csv_row(fields: impl IntoIterator<Item = String>) -> String {
+    let fields: Vec<String> = fields.into_iter().map(|f| csv_field(&f)).collect();
+    format!("{}\n", fields.join(","))
+}
+
+// Quote fields containing separators, quotes, or line breaks (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
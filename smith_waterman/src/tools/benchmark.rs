@@ -1,8 +1,19 @@
 use std::fs::{OpenOptions, create_dir_all};
-use std::io::{Write, BufRead};
+use std::io::Write;
+use std::path::Path;
 use std::time::Instant;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use crate::chunking::ChunkSize;
+use crate::chunk_latency::ChunkLatencySummary;
+use crate::file_timing::FileTiming;
+use crate::gpu_timing::GpuTimingSummary;
+use crate::metrics_sampler::MetricsSummary;
+use crate::read_binning::BinningSummary;
+use crate::adaptive_chunks::ChunkAdaptationSummary;
+use crate::run_cost::RunCost;
+use crate::run_registry::RunRecord;
+use crate::score_metrics::NormalizedScore;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CpuUtilizationSummary {
@@ -15,8 +26,14 @@ pub struct CpuUtilizationSummary {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BenchmarkResult {
+    // See schema.rs; 0 in results saved before it was recorded
+    #[serde(default)]
+    pub schema_version: u32,
     pub timestamp: DateTime<Utc>,
     pub run_id: String,
+    // Crate version that produced the run; empty in results saved before it was recorded
+    #[serde(default)]
+    pub version: String,
     pub mode: String, //single_file,full_wgs", etc.
     pub files_processed: usize,
     pub total_reads: usize,
@@ -26,11 +43,57 @@ pub struct BenchmarkResult {
     pub throughput_reads_per_second: f64,
     pub throughput_bases_per_second: f64,
     pub chunk_size: usize,
+    // "reads" or "bases"; empty in results saved before chunks could be sized in bases
+    #[serde(default)]
+    pub chunk_unit: String,
     pub gpu_utilization_avg: f64,
     pub gpu_memory_used_mb: f64,
     pub cpu_cores_used: usize,
     pub parallel_files: bool,
     pub system_info: SystemInfo,
+    #[serde(default)]
+    pub normalized_score: NormalizedScore,
+    #[serde(default)]
+    pub gpu_timing: GpuTimingSummary,
+    #[serde(default)]
+    pub chunk_latency: ChunkLatencySummary,
+    #[serde(default)]
+    pub binning: BinningSummary,
+    // Chunk size changes of an adaptive (--chunk-size auto) run
+    #[serde(default)]
+    pub chunk_adaptation: ChunkAdaptationSummary,
+    // True while the run is in progress; a result still partial after the run
+    // has gone means it crashed or was killed
+    #[serde(default)]
+    pub partial: bool,
+    // Share of the bases read that failed to align and were left out of the
+    // score (throughput still counts them), and whether any chunk or file was
+    // left out; unlike `partial`, this holds after the run has finished
+    #[serde(default)]
+    pub excluded_fraction: f64,
+    #[serde(default)]
+    pub is_partial: bool,
+    #[serde(default)]
+    pub chunks_completed: u64,
+    // One entry per file processed, in processing order
+    #[serde(default)]
+    pub files: Vec<FileTiming>,
+    // Estimated GPU energy and instance cost (--cost-per-hour)
+    #[serde(default)]
+    pub cost: RunCost,
+}
+
+impl BenchmarkResult {
+    // Aborted: never finished; partial: finished with data left out of it
+    pub fn status(&self) -> &'static str {
+        if self.partial {
+            "aborted"
+        } else if self.is_partial {
+            "partial"
+        } else {
+            "complete"
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -41,7 +104,9 @@ pub struct SystemInfo {
     pub total_ram_gb: f64,
 }
 
+// One run's benchmark; the run's RunContext owns it
 pub struct BenchmarkTracker {
+    run: RunRecord,
     start_time: Instant,
     results: Vec<BenchmarkResult>,
     current_run: Option<BenchmarkRun>,
@@ -54,22 +119,37 @@ struct BenchmarkRun {
     total_reads: usize,
     total_bases: usize,
     total_score: i32,
-    chunk_size: usize,
+    chunk_size: ChunkSize,
     parallel_files: bool,
+    chunks_completed: u64,
+    excluded_bases: usize,
+    is_partial: bool,
+}
+
+// Chunks between partial flushes of the run's results (BENCHMARK_FLUSH_CHUNKS, 0 = off)
+const DEFAULT_FLUSH_CHUNKS: u64 = 100;
+
+fn flush_interval() -> u64 {
+    std::env::var("BENCHMARK_FLUSH_CHUNKS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_FLUSH_CHUNKS)
 }
 
 impl BenchmarkTracker {
-    pub fn new() -> Self {
+    pub fn new(run: RunRecord) -> Self {
         Self {
+            run,
             start_time: Instant::now(),
             results: Vec::new(),
             current_run: None,
         }
     }
 
-    pub fn start_run(&mut self, mode: &str, chunk_size: usize, parallel_files: bool) {
-        let run_number = get_next_run_number();
-        let run_id = format!("run_{}", chrono::Utc::now().timestamp());
+    pub fn start_run(&mut self, mode: &str, chunk_size: ChunkSize, parallel_files: bool) {
+        let run_number = self.run.number;
+        let run_id = self.run.run_id.clone();
+        self.start_time = Instant::now();
         self.current_run = Some(BenchmarkRun {
             run_id: run_id.clone(),
             mode: mode.to_string(),
@@ -79,6 +159,9 @@ impl BenchmarkTracker {
             total_score: 0,
             chunk_size,
             parallel_files,
+            chunks_completed: 0,
+            excluded_bases: 0,
+            is_partial: false,
         });
         println!("Starting benchmark run #{}: {} (run_id: {})", run_number, mode, run_id);
     }
@@ -92,20 +175,65 @@ impl BenchmarkTracker {
         }
     }
 
+    pub fn set_excluded(&mut self, bases: usize, partial: bool) {
+        if let Some(run) = &mut self.current_run {
+            run.excluded_bases = bases;
+            run.is_partial = partial;
+        }
+    }
+
+    // Count a finished chunk, flushing the run so far to disk every few chunks
+    pub fn chunk_done(&mut self) {
+        let Some(run) = &mut self.current_run else { return };
+        run.chunks_completed += 1;
+        let interval = flush_interval();
+        if interval > 0 && run.chunks_completed % interval == 0 {
+            self.flush_partial();
+        }
+    }
+
+    // Write the in-progress run to its results file, marked partial
+    pub fn flush_partial(&self) {
+        if let Some(run) = &self.current_run {
+            self.write_run_file(&self.build_result(run, true), false);
+        }
+    }
+
     pub fn finish_run(&mut self) -> Option<BenchmarkResult> {
-        let duration = self.start_time.elapsed();
         let run = self.current_run.take()?;
+        let result = self.build_result(&run, false);
+        let run_id = run.run_id;
+
+        self.results.push(result.clone());
+        self.save_results();
         
-        let system_info = self.get_system_info();
-        let (gpu_util, gpu_memory) = self.get_gpu_stats();
+        println!("Benchmark run {} completed:", run_id);
+        println!("   Time: {:0.2}", result.total_time_seconds);
+        println!("   Throughput: {:.0} reads/s, {:.0} bases/s{}", 
+                result.throughput_reads_per_second, result.throughput_bases_per_second,
+                crate::wgs_report::partial_note(result.is_partial, result.excluded_fraction));
+        println!("   GPU utilization: {:0.1}", result.gpu_utilization_avg);
+        println!("   Score: {} ({})", result.total_score, result.normalized_score.summary());
+        crate::file_timing::print_files(&result.files);
         
-        // Clone run_id before moving it
-        let run_id = run.run_id.clone();
+        // Output system monitoring summary
+        self.output_monitoring_summary(&run_id);
         
-        let result = BenchmarkResult {
+        Some(result)
+    }
+
+    fn build_result(&self, run: &BenchmarkRun, partial: bool) -> BenchmarkResult {
+        let duration = self.start_time.elapsed();
+        let system_info = self.get_system_info();
+        let metrics = MetricsSummary::from_logs_dir(&self.run.logs_dir).ok().flatten();
+        let (gpu_util, gpu_memory) = Self::get_gpu_stats(metrics.as_ref());
+        
+        BenchmarkResult {
+            schema_version: crate::schema::BENCHMARK.version(),
             timestamp: chrono::Utc::now(),
-            run_id: run.run_id,
-            mode: run.mode,
+            run_id: run.run_id.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            mode: run.mode.clone(),
             files_processed: run.files_processed,
             total_reads: run.total_reads,
             total_bases: run.total_bases,
@@ -113,27 +241,25 @@ impl BenchmarkTracker {
             total_time_seconds: duration.as_secs_f64(),
             throughput_reads_per_second: run.total_reads as f64 / duration.as_secs_f64(),
             throughput_bases_per_second: run.total_bases as f64 / duration.as_secs_f64(),
-            chunk_size: run.chunk_size,
+            chunk_size: run.chunk_size.value(),
+            chunk_unit: run.chunk_size.unit().to_string(),
             gpu_utilization_avg: gpu_util,
             gpu_memory_used_mb: gpu_memory,
             cpu_cores_used: num_cpus::get(),
             parallel_files: run.parallel_files,
             system_info,
-        };
-
-        self.results.push(result.clone());
-        self.save_results();
-        
-        println!("Benchmark run {} completed:", run_id);
-        println!("   Time: {:0.2}", result.total_time_seconds);
-        println!("   Throughput: {:.0} reads/s, {:.0} bases/s", 
-                result.throughput_reads_per_second, result.throughput_bases_per_second);
-        println!("   GPU utilization: {:0.1}", result.gpu_utilization_avg);
-        
-        // Output system monitoring summary
-        self.output_monitoring_summary(&run_id);
-        
-        Some(result)
+            normalized_score: NormalizedScore::from_totals(run.total_score as i64, run.total_reads, run.total_bases),
+            gpu_timing: crate::gpu_timing::summary(),
+            chunk_latency: crate::chunk_latency::summary(),
+            binning: crate::read_binning::summary(),
+            chunk_adaptation: crate::adaptive_chunks::summary(),
+            partial,
+            excluded_fraction: if run.total_bases == 0 { 0.0 } else { run.excluded_bases as f64 / run.total_bases as f64 },
+            is_partial: run.is_partial,
+            chunks_completed: run.chunks_completed,
+            files: crate::file_timing::files(),
+            cost: RunCost::estimate(duration.as_secs_f64(), run.total_bases, metrics.as_ref()),
+        }
     }
 
     fn get_system_info(&self) -> SystemInfo {
@@ -156,43 +282,46 @@ impl BenchmarkTracker {
         }
     }
 
-    fn get_gpu_stats(&self) -> (f64, f64) {
-        // Simplified - could parse nvidia-smi output
-        // For now, return reasonable defaults
-        (25.0, 400.0) // 25% utilization, 400 memory
+    fn get_gpu_stats(metrics: Option<&MetricsSummary>) -> (f64, f64) {
+        // Average utilization and peak memory from the metrics sampler
+        match metrics {
+            Some(MetricsSummary { avg_gpu_util_percent: Some(util), max_gpu_memory_mb: Some(memory), .. }) => (*util, *memory),
+            // Fall back to reasonable defaults without GPU samples
+            _ => (25.0, 400.0), // 25% utilization, 400 memory
+        }
     }
 
-    fn save_results(&self) {
-        // Create benchmark_results directory if it doesn't exist
-        if let Err(e) = create_dir_all("benchmark_results") {
-            eprintln!("Failed to create benchmark_results directory: {}", e);
+    // Individual run result; written to a temporary file and renamed, so a crash
+    // mid-write leaves the previous flush intact
+    fn write_run_file(&self, result: &BenchmarkResult, announce: bool) {
+        // Create the results directory if it doesn't exist
+        let results_dir = crate::output_layout::results_dir();
+        if let Err(e) = create_dir_all(&results_dir) {
+            eprintln!("Failed to create {} directory: {}", results_dir.display(), e);
             return;
         }
         
-        // Get current run number
-        let run_number = get_next_run_number();
-        let filename = format!("benchmark_results/run_{}_benchmark_results.json", run_number);
+        let filename = results_dir.join(format!("run_{}_benchmark_results.json", self.run.number));
+        let temp = filename.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(result)
+            .expect("Failed to serialize benchmark result");
         
-        // Save individual run result
+        let written = std::fs::write(&temp, json.as_bytes()).and_then(|_| std::fs::rename(&temp, &filename));
+        match written {
+            Err(e) => eprintln!("Failed to write benchmark result: {}, {}", e, filename.display()),
+            Ok(()) if announce => println!("Benchmark results saved to: {}", filename.display()),
+            Ok(()) => {},
+        }
+        crate::remote_state::mirror(&filename);
+    }
+
+    fn save_results(&self) {
         if let Some(result) = self.results.last() {
-            let json = serde_json::to_string_pretty(result)
-                .expect("Failed to serialize benchmark result");
-            
-            if let Ok(mut file) = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(&filename) {
-                if let Err(e) = file.write_all(json.as_bytes()) {
-                    eprintln!("Failed to write benchmark result: {}, {}", e, filename);
-                } else {
-                    println!("Benchmark results saved to: {}", filename);
-                }
-            }
+            self.write_run_file(result, true);
         }
         
         // Also save to the legacy file for backward compatibility
-        let legacy_filename = "benchmark_results.json";
+        let legacy_filename = crate::output_layout::result_file("benchmark_results.json");
         let json = serde_json::to_string_pretty(&self.results)
             .expect("Failed to serialize benchmark results");
         
@@ -200,251 +329,92 @@ impl BenchmarkTracker {
             .create(true)
             .write(true)
             .truncate(true)
-            .open(legacy_filename) {
+            .open(&legacy_filename) {
             if let Err(e) = file.write_all(json.as_bytes()) {
-                eprintln!("Failed to write legacy benchmark results: {}, {}", e, legacy_filename);
+                eprintln!("Failed to write legacy benchmark results: {}, {}", e, legacy_filename.display());
             }
         }
+        crate::remote_state::mirror(&legacy_filename);
     }
 
     fn output_monitoring_summary(&self, run_id: &str) {
-        // Get current run number
-        let run_number = get_next_run_number();
-        let logs_dir = format!("logs/run_{}", run_number);
+        // Same run the system monitors logged under
+        let run = &self.run;
         
         println!("\nSYSTEM MONITORING SUMMARY");
         println!("=========================");
-        println!("Run #{}: {}", run_number, run_id);
-        println!("Logs directory: {}", logs_dir);
+        println!("Run #{}: {}", run.number, run_id);
+        println!("Logs directory: {}", run.logs_dir.display());
+        
+        let summary = match MetricsSummary::from_logs_dir(&run.logs_dir) {
+            Ok(Some(summary)) => summary,
+            Ok(None) => {
+                println!("No metrics samples recorded");
+                return;
+            },
+            Err(e) => {
+                println!("Metrics unavailable: {}", e);
+                return;
+            },
+        };
         
-        // Parse and display GPU utilization
-        let gpu_log_path = format!("{}/gpu_util.log", logs_dir);
-        if let Ok(max_util) = self.parse_gpu_log(&gpu_log_path) {
+        println!("Samples: {}", summary.samples);
+        if let Some(max_util) = summary.max_gpu_util_percent {
             println!("Max GPU Utilization: {:.1}%", max_util);
         }
-        
-        // Parse and display disk I/O
-        let disk_log_path = format!("{}/disk_io.log", logs_dir);
-        if let Ok(peak_read) = self.parse_disk_log(&disk_log_path) {
-            println!("Peak Disk Read: {:.1} MB/s", peak_read);
-        }
-        
-        // Parse and display memory/CPU
-        let mem_cpu_log_path = format!("{}/mem_cpu.log", logs_dir);
-        if let Ok((max_ram, cpu_summary)) = self.parse_mem_cpu_log(&mem_cpu_log_path) {
-            println!("Max RAM Usage: {:.1} GB", max_ram);
-            println!("CPU Utilization - Avg: {:.1}% user, {:.1}% system, {:.1}% idle", 
-                cpu_summary.avg_user_percent, cpu_summary.avg_system_percent, cpu_summary.avg_idle_percent);
-        }
-        
-        // Parse and display context switches
-        let context_switch_log_path = format!("{}/context_switch.log", logs_dir);
-        if let Ok(total_switches) = self.parse_context_switch_log(&context_switch_log_path) {
-            if total_switches > 0 {
-                println!("Total Context Switches: {}", total_switches);
-            }
+        println!("Peak Disk Read: {:.1} MB/s", summary.peak_disk_read_mbps);
+        println!("Max RAM Usage: {:.1} GB", summary.max_ram_gb);
+        println!("CPU Utilization - Avg: {:.1}% user, {:.1}% system, {:.1}% idle", 
+            summary.cpu.avg_user_percent, summary.cpu.avg_system_percent, summary.cpu.avg_idle_percent);
+        if let Some(watts) = summary.avg_gpu_power_watts {
+            println!("Avg GPU Power: {:.0} W", watts);
         }
-    }
-
-    fn parse_gpu_log(&self, log_path: &str) -> Result<f64, String> {
-        use std::fs::File;
-        use std::io::BufReader;
-        
-        let file = File::open(log_path)
-            .map_err(|e| format!("Failed to open GPU log: {}", e))?;
-        
-        let reader = BufReader::new(file);
-        let mut max_utilization: f64 = 0.0;
-        
-        for line in reader.lines() {
-            let line = line.map_err(|e| format!("Failed to read GPU log line: {}", e))?;
-            
-            // Skip header lines and empty lines
-            if line.trim().is_empty() || line.contains("Date") || line.contains("Time") {
-                continue;
-            }
-            
-            // Parse nvidia-smi dmon output format
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 3 {
-                if let Ok(util) = parts[2].parse::<f64>() {
-                    max_utilization = max_utilization.max(util);
-                }
-            }
-        }
-        
-        Ok(max_utilization)
-    }
-
-    fn parse_disk_log(&self, log_path: &str) -> Result<f64, String> {
-        use std::fs::File;
-        use std::io::BufReader;
-        
-        let file = File::open(log_path)
-            .map_err(|e| format!("Failed to open disk log: {}", e))?;
-        
-        let reader = BufReader::new(file);
-        let mut peak_read_mbps: f64 = 0.0;
-        
-        for line in reader.lines() {
-            let line = line.map_err(|e| format!("Failed to read disk log line: {}", e))?;
-            
-            // Skip header lines and empty lines
-            if line.trim().is_empty() || line.contains("Device") || line.contains("rrqm") {
-                continue;
-            }
-            
-            // Parse iostat output format
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 4 {
-                // Look for read KB/s and convert to MB/s
-                if let Ok(read_kbps) = parts[2].parse::<f64>() {
-                    let read_mbps = read_kbps / 1024.0;
-                    peak_read_mbps = peak_read_mbps.max(read_mbps);
-                }
-            }
-        }
-        
-        Ok(peak_read_mbps)
-    }
-
-    fn parse_mem_cpu_log(&self, log_path: &str) -> Result<(f64, CpuUtilizationSummary), String> {
-        use std::fs::File;
-        use std::io::BufReader;
-        
-        let file = File::open(log_path)
-            .map_err(|e| format!("Failed to open memory/CPU log: {}", e))?;
-        
-        let reader = BufReader::new(file);
-        let mut max_ram_gb: f64 = 0.0;
-        let mut cpu_samples = Vec::new();
-        
-        for line in reader.lines() {
-            let line = line.map_err(|e| format!("Failed to read memory/CPU log line: {}", e))?;
-            
-            // Skip header lines and empty lines
-            if line.trim().is_empty() || line.contains("procs") || line.contains("r") {
-                continue;
-            }
-            
-            // Parse vmstat output format
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 16 {
-                // Parse memory usage (free memory in KB)
-                if let Ok(free_kb) = parts[3].parse::<f64>() {
-                    // Estimate total RAM usage (assuming 32GB total for now)
-                    let used_kb = 32.0 * 1024.0 * 1024.0 - free_kb;
-                    let used_gb = used_kb / (1024.0 * 1024.0);
-                    max_ram_gb = max_ram_gb.max(used_gb);
-                }
-                
-                // Parse CPU utilization
-                if let Ok(user_percent) = parts[12].parse::<f64>() {
-                    if let Ok(system_percent) = parts[13].parse::<f64>() {
-                        if let Ok(idle_percent) = parts[14].parse::<f64>() {
-                            cpu_samples.push((user_percent, system_percent, idle_percent));
-                        }
-                    }
-                }
-            }
+        if summary.total_context_switches > 0 {
+            println!("Total Context Switches: {}", summary.total_context_switches);
         }
-        
-        // Calculate CPU utilization summary
-        let cpu_summary = if !cpu_samples.is_empty() {
-            let total_samples = cpu_samples.len() as f64;
-            let (avg_user, avg_system, avg_idle) = cpu_samples.iter()
-                .fold((0.0, 0.0, 0.0), |(u, s, i), &(user, system, idle)| {
-                    (u + user, s + system, i + idle)
-                });
-            
-            let max_user = cpu_samples.iter().map(|(u, _, _)| *u).fold(0.0, f64::max);
-            let max_system = cpu_samples.iter().map(|(_, s, _)| *s).fold(0.0, f64::max);
-            
-            CpuUtilizationSummary {
-                avg_user_percent: avg_user / total_samples,
-                avg_system_percent: avg_system / total_samples,
-                avg_idle_percent: avg_idle / total_samples,
-                max_user_percent: max_user,
-                max_system_percent: max_system,
-            }
-        } else {
-            CpuUtilizationSummary {
-                avg_user_percent: 0.0,
-                avg_system_percent: 0.0,
-                avg_idle_percent: 0.0,
-                max_user_percent: 0.0,
-                max_system_percent: 0.0,
-            }
-        };
-        
-        Ok((max_ram_gb, cpu_summary))
     }
+}
 
-    fn parse_context_switch_log(&self, log_path: &str) -> Result<u64, String> {
-        use std::fs::File;
-        use std::io::BufReader;
-        
-        let file = File::open(log_path);
-        if file.is_err() {
-            return Ok(0); // File doesn't exist, return 0
+// Every run_N_benchmark_results.json in `results_dir`, by run number
+pub fn load_runs(results_dir: &Path) -> Result<Vec<(u64, BenchmarkResult)>, String> {
+    let entries = std::fs::read_dir(results_dir)
+        .map_err(|e| format!("Failed to read {}: {}", results_dir.display(), e))?;
+    let mut runs = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(number) = name.strip_prefix("run_")
+            .and_then(|rest| rest.strip_suffix("_benchmark_results.json"))
+            .and_then(|n| n.parse::<u64>().ok()) else { continue };
+        let parsed = std::fs::read_to_string(entry.path()).map_err(|e| e.to_string())
+            .and_then(|json| crate::schema::parse::<BenchmarkResult>(&crate::schema::BENCHMARK, &json, &name));
+        match parsed {
+            Ok(result) => runs.push((number, result)),
+            Err(e) => eprintln!("Warning: Skipping {}: {}", entry.path().display(), e),
         }
-        
-        let file = file.unwrap();
-        let reader = BufReader::new(file);
-        let mut total_switches = 0u64;
-        
-        for line in reader.lines() {
-            let line = line.map_err(|e| format!("Failed to read context switch log line: {}", e))?;
-            
-            // Skip header lines and empty lines
-            if line.trim().is_empty() || line.contains("Linux") || line.contains("UID") {
-                continue;
-            }
-            
-            // Parse pidstat output format
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 4 {
-                if let Ok(switches) = parts[3].parse::<u64>() {
-                    total_switches += switches;
-                }
-            }
-        }
-        
-        Ok(total_switches)
     }
+    runs.sort_by_key(|(number, _)| *number);
+    Ok(runs)
 }
 
-// Global benchmark tracker
-use std::sync::Mutex;
-use once_cell::sync::Lazy;
-
-static BENCHMARK_TRACKER: Lazy<Mutex<BenchmarkTracker>> = Lazy::new(|| Mutex::new(BenchmarkTracker::new()));
-
-pub fn start_benchmark(mode: &str, chunk_size: usize, parallel_files: bool) {
-    if let Ok(mut tracker) = BENCHMARK_TRACKER.lock() {
-        tracker.start_run(mode, chunk_size, parallel_files);
+// `bench list`: one line per stored run; runs whose last flush was partial are
+// aborted, and finished runs that left data out of their totals are partial
+pub fn print_run_list(runs: &[(u64, BenchmarkResult)]) {
+    println!("{:>5}  {:<8}  {:<10}  {:>5}  {:>14}  {:>16}  {:>9}  {:>10}  {:>8}  updated",
+             "run", "status", "mode", "files", "reads", "bases", "time s", "Mbases/s", "chunks");
+    for (number, result) in runs {
+        println!("{:>5}  {:<8}  {:<10}  {:>5}  {:>14}  {:>16}  {:>9.1}  {:>10.1}  {:>8}  {}",
+                 number, result.status(), result.mode, result.files_processed,
+                 result.total_reads, result.total_bases, result.total_time_seconds,
+                 result.throughput_bases_per_second / 1e6, result.chunks_completed,
+                 result.timestamp.format("%Y-%m-%d %H:%M:%S"));
     }
-}
-
-pub fn get_next_run_number() -> u64 {
-    // Use a static counter to ensure unique run numbers
-    use std::sync::atomic::{AtomicU64, Ordering};
-    static RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
-    
-    // Increment and get the next run number
-    RUN_COUNTER.fetch_add(1, Ordering::SeqCst) + 1
-}
-
-pub fn update_benchmark_progress(files_processed: usize, reads: usize, bases: usize, score: i32) {
-    if let Ok(mut tracker) = BENCHMARK_TRACKER.lock() {
-        tracker.update_progress(files_processed, reads, bases, score);
+    let aborted = runs.iter().filter(|(_, r)| r.partial).count();
+    if aborted > 0 {
+        println!("{} of {} runs aborted (totals are as of their last flush)", aborted, runs.len());
+    }
+    let partial = runs.iter().filter(|(_, r)| !r.partial && r.is_partial).count();
+    if partial > 0 {
+        println!("{} of {} runs finished with chunks or files left out of their totals", partial, runs.len());
     }
 }
 
-pub fn finish_benchmark() -> Option<BenchmarkResult> {
-    if let Ok(mut tracker) = BENCHMARK_TRACKER.lock() {
-        tracker.finish_run()
-    } else {
-        None
-    }
-} 
\ No newline at end of file
@@ -0,0 +1,152 @@
+// Throughput ceilings of the two halves of a WGS run, to tell which one bounds
+// the end-to-end rate before tuning either of them
+//   bench io       reads and parses the input files as a WGS run would (the
+//                  same readers: BAM/CRAM, packed cache, memory map, async input
+//                  with parallel gzip) and drops the chunks unaligned
+//   bench compute  reads the first chunk of a file once and aligns it again and
+//                  again from memory, as WGS aligns each chunk (against itself),
+//                  on the GPU's launch queues or with --cpu the SIMD CPU path
+// Both are in bases per second at the run's chunk size. A run can go no faster
+// than the lower of the two: when it is I/O, compression, storage and the
+// reader thread are what to tune (parallel gzip, a packed cache, mmap input);
+// when it is compute, the kernel, queue count and chunk size are. `bench compute
+// --with-io` measures both on the same file and says which
+
+use crate::aligner::read_file_only;
+use crate::alignment_input::AlignmentInputOptions;
+use crate::chunking::ChunkSize;
+use crate::gpu::GpuContext;
+use crate::gpu_queues::{LaunchPool, QueueCount};
+use crate::{ReadChunk, ReadNameMode};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Io,
+    Compute,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Io => "I/O",
+            Side::Compute => "compute",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ceiling {
+    pub side: Side,
+    // Where it was measured: the files read, or the backend that aligned
+    pub source: String,
+    pub reads: usize,
+    pub bases: usize,
+    pub seconds: f64,
+}
+
+impl Ceiling {
+    pub fn bases_per_sec(&self) -> f64 {
+        if self.seconds > 0.0 { self.bases as f64 / self.seconds } else { 0.0 }
+    }
+
+    pub fn print(&self) {
+        let side = match self.side {
+            Side::Io => "I/O",
+            Side::Compute => "Compute",
+        };
+        println!("{} ceiling ({}): {} reads, {} bases in {:.2} s, {:.1} Mbases/s",
+                 side, self.source, self.reads, self.bases, self.seconds, self.bases_per_sec() / 1e6);
+    }
+}
+
+// Read every file through the WGS readers without aligning, timing each file
+pub fn measure_io(files: &[String], chunk_size: ChunkSize) -> Result<Ceiling, String> {
+    let input = AlignmentInputOptions::default();
+    let (mut reads, mut bases) = (0, 0);
+    let start = Instant::now();
+    for file in files {
+        let file_start = Instant::now();
+        let mut file_bases = 0;
+        let file_reads = read_file_only(file, &input, chunk_size, ReadNameMode::Discard, |chunk| {
+            file_bases += chunk.bases().len();
+            true
+        })?;
+        let seconds = file_start.elapsed().as_secs_f64();
+        println!("  {}: {} reads, {} bases in {:.2} s ({:.1} Mbases/s)", crate::paths::file_name(file), file_reads, file_bases, seconds,
+                 if seconds > 0.0 { file_bases as f64 / seconds / 1e6 } else { 0.0 });
+        reads += file_reads;
+        bases += file_bases;
+    }
+    let source = if files.len() == 1 { crate::paths::file_name(&files[0]).to_string() } else { format!("{} files", files.len()) };
+    Ok(Ceiling { side: Side::Io, source, reads, bases, seconds: start.elapsed().as_secs_f64() })
+}
+
+// The first chunk of `file`, kept in memory
+pub fn first_chunk(file: &str, chunk_size: ChunkSize) -> Result<ReadChunk, String> {
+    let mut first = None;
+    read_file_only(file, &AlignmentInputOptions::default(), chunk_size, ReadNameMode::Discard, |chunk| {
+        first = Some(chunk.clone());
+        false
+    })?;
+    first.filter(|chunk| !chunk.is_empty()).ok_or_else(|| format!("{} has no reads", file))
+}
+
+// Align `chunk` against itself `runs` times on `gpu`, or on the CPU without
+// one; every run must return the same score
+pub fn measure_compute(chunk: &ReadChunk, runs: usize, gpu: Option<&GpuContext>) -> Result<Ceiling, String> {
+    let bytes = chunk.bases();
+    let mut scores = Vec::with_capacity(runs);
+    let (source, seconds) = match gpu {
+        Some(gpu) => {
+            let mut launches = LaunchPool::<usize>::on(gpu, QueueCount::from_env()?)?;
+            // Warm-up launch so the program build isn't timed
+            let expected = crate::aligner::gpu_align_on(gpu, bytes, bytes)?;
+            scores.push(expected);
+            let start = Instant::now();
+            let mut finished = Vec::new();
+            for run in 0..runs {
+                finished.extend(launches.submit(run, bytes, bytes, gpu.info()));
+            }
+            finished.extend(launches.drain());
+            let seconds = start.elapsed().as_secs_f64();
+            for (_, score) in finished {
+                scores.push(score?);
+            }
+            (format!("OpenCL on {}, {} queue(s)", gpu.info().name, launches.queues()), seconds)
+        },
+        None => {
+            let level = crate::cpu_align::selected_level();
+            let start = Instant::now();
+            for _ in 0..runs {
+                scores.push(crate::cpu_align::cpu_align_bytes(bytes, bytes));
+            }
+            (format!("CPU, {}", level.name()), start.elapsed().as_secs_f64())
+        },
+    };
+    if let Some(&first) = scores.first() {
+        if let Some(&other) = scores.iter().find(|&&score| score != first) {
+            return Err(format!("the same chunk scored {} and then {}", first, other));
+        }
+    }
+    Ok(Ceiling { side: Side::Compute, source, reads: chunk.len() * runs, bases: bytes.len() * runs, seconds })
+}
+
+// Which side bounds the run, and by how much
+pub fn print_verdict(io: &Ceiling, compute: &Ceiling) {
+    let (io_rate, compute_rate) = (io.bases_per_sec(), compute.bases_per_sec());
+    if io_rate <= 0.0 || compute_rate <= 0.0 {
+        return;
+    }
+    let (bound, other, ratio) = if io_rate < compute_rate {
+        (io, compute, compute_rate / io_rate)
+    } else {
+        (compute, io, io_rate / compute_rate)
+    };
+    println!("Bound by {}: at most {:.1} Mbases/s end to end; {} could go {:.1}x faster",
+             bound.side.name(), bound.bases_per_sec() / 1e6, other.side.name(), ratio);
+    match bound.side {
+        Side::Io => println!("Tune the input side: DECOMPRESS_THREADS, a packed read cache (prepare), USE_MMAP_INPUT or faster storage"),
+        Side::Compute => println!("Tune the GPU side: --kernel, GPU_QUEUES, --chunk-size, or more devices"),
+    }
+}
@@ -0,0 +1,118 @@
+// Compares allocations of the old String-per-read chunker against the ReadChunk arena
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use flate2::read::MultiGzDecoder;
+
+use rustseq_mini::exit_codes::ExitKind;
+use rustseq_mini::input_format::{detect_path, Compression};
+use rustseq_mini::ReadChunk;
+
+// Counts every allocation made by the process
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn open(path: &str) -> Box<dyn BufRead> {
+    let detected = detect_path(path).expect("Failed to detect input format");
+    let file = File::open(path).expect("Failed to open file");
+    let inner: Box<dyn Read> = match detected.compression {
+        Compression::Gzip | Compression::Bgzf => Box::new(MultiGzDecoder::new(file)),
+        Compression::None => Box::new(file),
+        Compression::Zstd => panic!("zstd input is not supported by chunker_bench, decompress it first"),
+    };
+    Box::new(BufReader::new(inner))
+}
+
+// Old chunker: one String per read, concat() per chunk
+fn legacy_pass(path: &str, chunk_size: usize) -> usize {
+    let mut chunk: Vec<String> = Vec::with_capacity(chunk_size);
+    let mut bases = 0;
+    for (i, line) in open(path).lines().enumerate() {
+        let line = line.expect("Failed to read line");
+        if i % 4 == 1 {
+            chunk.push(line);
+            if chunk.len() >= chunk_size {
+                bases += chunk.concat().len();
+                chunk.clear();
+            }
+        }
+    }
+    bases + chunk.concat().len()
+}
+
+// New chunker: reused line buffer and arena
+fn arena_pass(path: &str, chunk_size: usize) -> usize {
+    let mut reader = open(path);
+    let mut chunk = ReadChunk::with_capacity(chunk_size);
+    let mut line = Vec::new();
+    let mut line_count = 0;
+    let mut bases = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).expect("Failed to read line") == 0 {
+            break;
+        }
+        line_count += 1;
+        if line_count % 4 == 2 {
+            chunk.push_read(&line);
+            if chunk.len() >= chunk_size {
+                bases += chunk.bases().len();
+                chunk.clear();
+            }
+        }
+    }
+    bases + chunk.bases().len()
+}
+
+fn measure(name: &str, pass: impl FnOnce() -> usize) {
+    let allocs_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes_before = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let bases = pass();
+    let elapsed = start.elapsed();
+    println!("{:>8}: {} bases, {} allocations, {:.1} MB allocated, {:.2} s",
+             name,
+             bases,
+             ALLOCATIONS.load(Ordering::Relaxed) - allocs_before,
+             (ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes_before) as f64 / (1024.0 * 1024.0),
+             elapsed.as_secs_f64());
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: chunker_bench <path/to/file.fastq[.gz]> [chunk_size_reads]");
+        ExitKind::ConfigError.exit();
+    }
+    let path = &args[1];
+    let chunk_size = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(10000);
+
+    measure("legacy", || legacy_pass(path, chunk_size));
+    measure("arena", || arena_pass(path, chunk_size));
+}
@@ -7,7 +7,8 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() != 2 {
         eprintln!("Usage: linecount <path/to/file.fastq.gz>");
-        std::process::exit(1);
+        // Configuration error, as in rustseq_mini::exit_codes
+        std::process::exit(2);
     }
     let path = &args[1];
     let file = File::open(path).expect("Failed to open file");
@@ -1,3 +1,3 @@
 pub mod benchmark;
-pub mod linecount;
-pub mod stdin_linecount;
\ No newline at end of file
+pub mod bench_export;
+pub mod ceilings;
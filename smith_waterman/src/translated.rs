@@ -0,0 +1,108 @@
+// `translated`: blastx-style screening of DNA reads against protein sequences
+// Each read is translated in all six frames (+1..+3 on the read, -1..-3 on its
+// reverse complement) with the standard genetic code; codons with anything but
+// A/C/G/T translate to X and stops to '*'. Every frame is aligned against every
+// target under BLOSUM62 (protein_matrix.rs). A target counts once per read,
+// with its best frame, and the read reports its best targets within the
+// HitLimits. Scoring is ungapped.rs: the best local run over all diagonals of
+// a pair, on the GPU or the CPU with the same results
+
+use crate::fastq::{FastqBatch, FastqChunks};
+use crate::hit_limits::HitLimits;
+use crate::ungapped::{self, ScoreMatrix, SequenceSet};
+use std::io::Write;
+
+// Reads translated and launched together
+const READS_PER_BATCH: usize = 4096;
+pub const DEFAULT_TOP_N: usize = 1;
+pub const DEFAULT_MIN_SCORE: i32 = 30;
+
+// Standard genetic code, codons in TCAG order
+const CODON_TABLE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+pub const FRAMES: [i8; 6] = [1, 2, 3, -1, -2, -3];
+
+fn codon_index(base: u8) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        b'T' | b'U' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+fn translate_codon(codon: &[u8]) -> u8 {
+    match (codon_index(codon[0]), codon_index(codon[1]), codon_index(codon[2])) {
+        (Some(a), Some(b), Some(c)) => CODON_TABLE[a * 16 + b * 4 + c],
+        _ => b'X',
+    }
+}
+
+// Protein for one frame: 1..=3 reads the sequence from offset frame-1, -1..=-3
+// reads its reverse complement the same way
+pub fn translate_frame(dna: &[u8], frame: i8) -> Vec<u8> {
+    let offset = (frame.unsigned_abs() as usize).saturating_sub(1);
+    if frame > 0 {
+        dna.get(offset..).unwrap_or(&[]).chunks_exact(3).map(translate_codon).collect()
+    } else {
+        let reverse: Vec<u8> = dna.iter().rev().map(|&b| crate::alignment_input::complement_base(b.to_ascii_uppercase())).collect();
+        reverse.get(offset..).unwrap_or(&[]).chunks_exact(3).map(translate_codon).collect()
+    }
+}
+
+pub fn six_frames(dna: &[u8]) -> [Vec<u8>; 6] {
+    FRAMES.map(|frame| translate_frame(dna, frame))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranslatedSummary {
+    pub reads: usize,
+    pub hits: usize,
+    pub reads_with_hits: usize,
+    pub targets: usize,
+}
+
+// Screen every read of `query` (FASTQ) against the proteins in `db` (FASTA) and
+// write each read's hits as TSV
+pub fn screen(query: &str, db: &str, limits: HitLimits, use_gpu: bool, out: &mut dyn Write) -> Result<TranslatedSummary, String> {
+    let proteins = crate::simulate::load_reference(db)?;
+    let matrix = ScoreMatrix::blosum62();
+    let mut targets = SequenceSet::new();
+    for (_, protein) in &proteins {
+        targets.push(protein, &matrix);
+    }
+    let mut summary = TranslatedSummary { targets: targets.len(), ..Default::default() };
+    let write_error = |e: std::io::Error| format!("Failed to write hits: {}", e);
+    writeln!(out, "#read\trank\ttarget\tframe\tscore").map_err(write_error)?;
+
+    let mut chunks = FastqChunks::open(query, READS_PER_BATCH)?;
+    let mut batch = FastqBatch::with_capacity(READS_PER_BATCH);
+    while chunks.next_into(&mut batch)? {
+        let mut frames = SequenceSet::new();
+        for record in batch.iter() {
+            for protein in six_frames(record.seq) {
+                frames.push(&protein, &matrix);
+            }
+        }
+        let scores = ungapped::scores(&frames, &targets, &matrix, use_gpu)?;
+        for (read, record) in batch.iter().enumerate() {
+            // (target, frame, score) with each target's best frame; ties go to the earlier frame
+            let mut hits: Vec<(usize, usize, i32)> = (0..targets.len()).map(|target| {
+                let row = target * frames.len() + read * FRAMES.len();
+                let frame = (0..FRAMES.len()).fold(0, |best, frame| if scores[row + frame] > scores[row + best] { frame } else { best });
+                (target, frame, scores[row + frame])
+            }).collect();
+            limits.select(&mut hits, |&(_, _, score)| score);
+            summary.reads += 1;
+            summary.hits += hits.len();
+            summary.reads_with_hits += usize::from(!hits.is_empty());
+            let name = record.id_str();
+            let name = name.split_whitespace().next().unwrap_or("");
+            for (rank, &(target, frame, score)) in hits.iter().enumerate() {
+                writeln!(out, "{}\t{}\t{}\t{:+}\t{}", name, rank + 1, proteins[target].0, FRAMES[frame], score).map_err(write_error)?;
+            }
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,367 @@
+// Live terminal dashboard for --full-wgs --tui
+// The aligner reports progress through the functions below; a render thread
+// redraws the dashboard four times a second. While it's up, the process's
+// stdout/stderr go to logs/run_N/console.log so the usual progress prints don't
+// scroll over it (Linux; elsewhere the prints are left on the terminal)
+
+use once_cell::sync::Lazy;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::{cursor, execute, terminal};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Gauge, List, ListItem, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+#[cfg(target_os = "linux")]
+use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+// Window for the current-throughput figure
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+const ERRORS_KEPT: usize = 5;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileStatus {
+    Pending,
+    Running,
+    Done,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+struct FileProgress {
+    name: String,
+    status: FileStatus,
+    reads: usize,
+    bases: usize,
+    chunks: usize,
+}
+
+struct DashboardState {
+    started: Instant,
+    files: Vec<FileProgress>,
+    expected_reads: Option<usize>,
+    // (time, total reads, total bases) samples for the throughput window
+    history: VecDeque<(Instant, usize, usize)>,
+    queue_depth: Option<(usize, usize)>,
+    errors: VecDeque<String>,
+}
+
+static STATE: Lazy<Mutex<DashboardState>> = Lazy::new(|| Mutex::new(DashboardState {
+    started: Instant::now(),
+    files: Vec::new(),
+    expected_reads: None,
+    history: VecDeque::new(),
+    queue_depth: None,
+    errors: VecDeque::new(),
+}));
+
+static RENDERER: Lazy<Mutex<Option<Renderer>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+fn update(f: impl FnOnce(&mut DashboardState)) {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Ok(mut state) = STATE.lock() {
+        f(&mut state);
+    }
+}
+
+// Progress hooks (no-ops unless the dashboard is running)
+
+pub fn set_files(files: &[String], expected_reads: Option<usize>) {
+    update(|state| {
+        state.started = Instant::now();
+        state.expected_reads = expected_reads;
+        state.files = files.iter().map(|f| FileProgress {
+            name: crate::paths::file_name(f).to_string(),
+            status: FileStatus::Pending,
+            reads: 0,
+            bases: 0,
+            chunks: 0,
+        }).collect();
+    });
+}
+
+fn set_status(index: usize, status: FileStatus) {
+    update(|state| {
+        if let Some(file) = state.files.get_mut(index) {
+            file.status = status;
+        }
+    });
+}
+
+pub fn file_started(index: usize) {
+    set_status(index, FileStatus::Running);
+}
+
+pub fn file_skipped(index: usize, reads: usize, bases: usize) {
+    update(|state| {
+        if let Some(file) = state.files.get_mut(index) {
+            file.status = FileStatus::Skipped;
+            file.reads = reads;
+            file.bases = bases;
+        }
+    });
+}
+
+pub fn file_finished(index: usize, ok: bool) {
+    set_status(index, if ok { FileStatus::Done } else { FileStatus::Failed });
+}
+
+pub fn chunk_done(index: usize, reads: usize, bases: usize) {
+    update(|state| {
+        if let Some(file) = state.files.get_mut(index) {
+            file.reads += reads;
+            file.bases += bases;
+            file.chunks += 1;
+        }
+        let now = Instant::now();
+        let totals = state.files.iter().fold((0, 0), |(r, b), f| (r + f.reads, b + f.bases));
+        state.history.push_back((now, totals.0, totals.1));
+        while state.history.front().is_some_and(|(t, _, _)| now.duration_since(*t) > THROUGHPUT_WINDOW) {
+            state.history.pop_front();
+        }
+    });
+}
+
+pub fn set_queue_depth(queued: usize, capacity: usize) {
+    update(|state| state.queue_depth = Some((queued, capacity)));
+}
+
+pub fn error(message: String) {
+    update(|state| {
+        state.errors.push_back(message);
+        while state.errors.len() > ERRORS_KEPT {
+            state.errors.pop_front();
+        }
+    });
+}
+
+fn format_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+fn render(frame: &mut Frame, state: &DashboardState) {
+    let elapsed = state.started.elapsed();
+    let total_reads: usize = state.files.iter().map(|f| f.reads).sum();
+    let total_bases: usize = state.files.iter().map(|f| f.bases).sum();
+    let files_done = state.files.iter()
+        .filter(|f| matches!(f.status, FileStatus::Done | FileStatus::Skipped | FileStatus::Failed))
+        .count();
+
+    // Progress by expected reads when configured, otherwise by files
+    let progress = match state.expected_reads {
+        Some(expected) if expected > 0 => total_reads as f64 / expected as f64,
+        _ if !state.files.is_empty() => files_done as f64 / state.files.len() as f64,
+        _ => 0.0,
+    }.clamp(0.0, 1.0);
+    let eta = if progress > 0.0 && progress < 1.0 {
+        format_duration(elapsed.mul_f64((1.0 - progress) / progress))
+    } else {
+        "--:--:--".to_string()
+    };
+
+    let (reads_per_s, bases_per_s) = match (state.history.front(), state.history.back()) {
+        (Some(first), Some(last)) if last.0 > first.0 => {
+            let secs = last.0.duration_since(first.0).as_secs_f64();
+            ((last.1 - first.1) as f64 / secs, (last.2 - first.2) as f64 / secs)
+        },
+        _ => (0.0, 0.0),
+    };
+
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(4),
+            Constraint::Min(5),
+            Constraint::Length(ERRORS_KEPT as u16 + 2),
+        ])
+        .split(frame.area());
+
+    let run = crate::run_registry::current();
+    let gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL)
+            .title(format!(" rustseq_mini run #{}  elapsed {}  ETA {} ", run.number, format_duration(elapsed), eta)))
+        .gauge_style(Style::default().fg(Color::Green))
+        .ratio(progress)
+        .label(format!("{:.1}%  ({}/{} files)", progress * 100.0, files_done, state.files.len()));
+    frame.render_widget(gauge, areas[0]);
+
+    let sample = crate::metrics_sampler::latest();
+    let gpu = match sample.as_ref().and_then(|s| Some((s.gpu_util_percent?, s.gpu_memory_used_mb?))) {
+        Some((util, memory)) => format!("GPU {:.0}% util, {:.0} MB", util, memory),
+        None => "GPU n/a".to_string(),
+    };
+    let cpu = sample.as_ref()
+        .map(|s| format!("CPU {:.0}% user {:.0}% sys, RAM {:.1} GB, disk {:.0} MB/s",
+                         s.cpu_user_percent, s.cpu_system_percent, s.ram_used_gb, s.disk_read_mbps))
+        .unwrap_or_default();
+    let queue = match state.queue_depth {
+        Some((queued, capacity)) => format!("input queue {}/{}", queued, capacity),
+        None => "input queue n/a".to_string(),
+    };
+    let stats = Paragraph::new(vec![
+        Line::from(format!("{:.0} reads/s, {:.1} Mbases/s   total {} reads, {} bases",
+                           reads_per_s, bases_per_s / 1e6, total_reads, total_bases)),
+        Line::from(format!("{}   {}   {}", gpu, queue, cpu)),
+    ]).block(Block::default().borders(Borders::ALL).title(" Throughput "));
+    frame.render_widget(stats, areas[1]);
+
+    let rows = state.files.iter().map(|f| {
+        let (label, color) = match f.status {
+            FileStatus::Pending => ("pending", Color::DarkGray),
+            FileStatus::Running => ("running", Color::Yellow),
+            FileStatus::Done => ("done", Color::Green),
+            FileStatus::Skipped => ("checkpointed", Color::Blue),
+            FileStatus::Failed => ("failed", Color::Red),
+        };
+        Row::new(vec![
+            Cell::from(f.name.clone()),
+            Cell::from(label).style(Style::default().fg(color)),
+            Cell::from(f.reads.to_string()),
+            Cell::from(f.bases.to_string()),
+            Cell::from(f.chunks.to_string()),
+        ])
+    });
+    let table = Table::new(rows, [
+            Constraint::Percentage(40),
+            Constraint::Length(13),
+            Constraint::Length(14),
+            Constraint::Length(16),
+            Constraint::Length(8),
+        ])
+        .header(Row::new(vec!["file", "status", "reads", "bases", "chunks"])
+            .style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(" Files "));
+    frame.render_widget(table, areas[2]);
+
+    let errors: Vec<ListItem> = state.errors.iter()
+        .map(|e| ListItem::new(e.as_str()).style(Style::default().fg(Color::Red)))
+        .collect();
+    frame.render_widget(List::new(errors).block(Block::default().borders(Borders::ALL).title(" Recent errors ")), areas[3]);
+}
+
+// Console output captured while the dashboard is up
+#[cfg(target_os = "linux")]
+struct Redirect {
+    saved_stdout: i32,
+    saved_stderr: i32,
+}
+
+#[cfg(target_os = "linux")]
+impl Redirect {
+    fn to_file(path: &std::path::Path) -> Result<(Self, File), String> {
+        use std::os::fd::{AsRawFd, FromRawFd};
+        let log = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let _ = std::io::stdout().flush();
+        unsafe {
+            let saved_stdout = libc::dup(1);
+            let saved_stderr = libc::dup(2);
+            let terminal_fd = libc::dup(1);
+            if saved_stdout < 0 || saved_stderr < 0 || terminal_fd < 0 {
+                return Err("Failed to duplicate stdout/stderr".to_string());
+            }
+            libc::dup2(log.as_raw_fd(), 1);
+            libc::dup2(log.as_raw_fd(), 2);
+            Ok((Self { saved_stdout, saved_stderr }, File::from_raw_fd(terminal_fd)))
+        }
+    }
+
+    fn restore(self) {
+        let _ = std::io::stdout().flush();
+        unsafe {
+            libc::dup2(self.saved_stdout, 1);
+            libc::dup2(self.saved_stderr, 2);
+            libc::close(self.saved_stdout);
+            libc::close(self.saved_stderr);
+        }
+    }
+}
+
+struct Renderer {
+    handle: JoinHandle<()>,
+    #[cfg(target_os = "linux")]
+    redirect: Redirect,
+}
+
+// Take over the terminal; `enable` alone only records that --tui was requested
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn start() -> Result<(), String> {
+    if !is_enabled() || RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    let (redirect, output) = {
+        let logs_dir = crate::run_registry::current().logs_dir.clone();
+        std::fs::create_dir_all(&logs_dir)
+            .map_err(|e| format!("Failed to create {}: {}", logs_dir.display(), e))?;
+        let console_log = logs_dir.join("console.log");
+        eprintln!("Dashboard running; console output goes to {}", console_log.display());
+        Redirect::to_file(&console_log)?
+    };
+    #[cfg(not(target_os = "linux"))]
+    let output = std::io::stdout();
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(output))
+        .map_err(|e| format!("Failed to open terminal: {}", e))?;
+    execute!(terminal.backend_mut(), terminal::EnterAlternateScreen, cursor::Hide)
+        .map_err(|e| format!("Failed to open terminal: {}", e))?;
+
+    let handle = std::thread::Builder::new()
+        .name("tui".to_string())
+        .spawn(move || {
+            while RUNNING.load(Ordering::SeqCst) {
+                if let Ok(state) = STATE.lock() {
+                    let _ = terminal.draw(|frame| render(frame, &state));
+                }
+                std::thread::sleep(REDRAW_INTERVAL);
+            }
+            let _ = execute!(terminal.backend_mut(), terminal::LeaveAlternateScreen, cursor::Show);
+        })
+        .map_err(|e| format!("Failed to start dashboard: {}", e))?;
+
+    if let Ok(mut renderer) = RENDERER.lock() {
+        *renderer = Some(Renderer {
+            handle,
+            #[cfg(target_os = "linux")]
+            redirect,
+        });
+    }
+    Ok(())
+}
+
+// Restore the terminal and console output (safe to call more than once)
+pub fn stop() {
+    if !RUNNING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let renderer = RENDERER.lock().ok().and_then(|mut r| r.take());
+    if let Some(renderer) = renderer {
+        let _ = renderer.handle.join();
+        #[cfg(target_os = "linux")]
+        renderer.redirect.restore();
+    }
+}
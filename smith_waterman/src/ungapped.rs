@@ -0,0 +1,330 @@
+// Ungapped local scores of every query against every target in two sequence sets
+// A pair's score is the best local run (the running sum reset at zero) over all
+// of its diagonals, under a substitution matrix. Sequences are encoded as matrix
+// rows first, so scoring is one table lookup. On the GPU
+// (smith_waterman_all_diagonals) each work item scores one diagonal of one pair;
+// the CPU path computes the same scores. Used by translated search and db search
+// `align pairs` scores query i against target i only, each pair under a matrix
+// of its own (smith_waterman_paired_diagonals)
+// Score-only screening (db search --min-score-early-exit): a diagonal stops as
+// soon as neither its best so far nor its running score plus the matrix's best
+// score for each residue left can reach the threshold, so diagonals (and pairs)
+// too short to reach it aren't scored at all. Pairs reaching the threshold get
+// their exact score and the rest score 0
+
+use crate::gpu::{GpuContext, GPU_MAX_WORK_GROUPS, GPU_WORK_GROUP_SIZE};
+use crate::n_policy::NPolicy;
+use crate::protein_matrix;
+use crate::scoring::Scoring;
+use ocl::{Buffer, Kernel, MemFlags};
+use rayon::prelude::*;
+
+// Row-major scores with the residue encoding that goes with them
+#[derive(Debug, Clone)]
+pub struct ScoreMatrix {
+    pub size: usize,
+    pub scores: Vec<i32>,
+    encode: fn(u8) -> u8,
+}
+
+// A C G T, then everything else (N)
+const NUCLEOTIDE_SIZE: usize = 5;
+
+pub fn encode_nucleotide(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => 0,
+        b'C' => 1,
+        b'G' => 2,
+        b'T' | b'U' => 3,
+        _ => 4,
+    }
+}
+
+impl ScoreMatrix {
+    pub fn blosum62() -> Self {
+        Self { size: protein_matrix::SIZE, scores: protein_matrix::flattened(), encode: protein_matrix::encode }
+    }
+
+    // The selected nucleotide scores; N pairs score as --n-policy says (skip counts as mismatch)
+    pub fn nucleotide() -> Self {
+        Self::nucleotide_with(crate::scoring::selected())
+    }
+
+    // Nucleotide scores with another match and mismatch, N as nucleotide() scores it
+    pub fn nucleotide_with(scoring: Scoring) -> Self {
+        let n_score = match crate::n_policy::selected() {
+            NPolicy::Zero => 0,
+            NPolicy::Mismatch | NPolicy::Skip => scoring.mismatch_penalty,
+        };
+        let scores = (0..NUCLEOTIDE_SIZE * NUCLEOTIDE_SIZE).map(|cell| {
+            let (a, b) = (cell / NUCLEOTIDE_SIZE, cell % NUCLEOTIDE_SIZE);
+            if a == NUCLEOTIDE_SIZE - 1 || b == NUCLEOTIDE_SIZE - 1 {
+                n_score
+            } else if a == b {
+                scoring.match_score
+            } else {
+                scoring.mismatch_penalty
+            }
+        }).collect();
+        Self { size: NUCLEOTIDE_SIZE, scores, encode: encode_nucleotide }
+    }
+
+    pub fn encode(&self, residue: u8) -> u8 {
+        (self.encode)(residue)
+    }
+
+    // Score of two encoded residues
+    pub fn score(&self, a: u8, b: u8) -> i32 {
+        self.scores[a as usize * self.size + b as usize]
+    }
+    // Best score any residue pair can get, bounding what is left of a diagonal
+    pub fn max_score(&self) -> i32 {
+        self.scores.iter().copied().max().unwrap_or(0).max(0)
+    }
+}
+
+// Encoded residues back to back, with start offsets (one more than sequences)
+#[derive(Debug, Default, Clone)]
+pub struct SequenceSet {
+    pub residues: Vec<u8>,
+    pub offsets: Vec<u32>,
+}
+
+impl SequenceSet {
+    pub fn new() -> Self {
+        Self { residues: Vec::new(), offsets: vec![0] }
+    }
+
+    pub fn push(&mut self, sequence: &[u8], matrix: &ScoreMatrix) {
+        self.residues.extend(sequence.iter().map(|&r| matrix.encode(r)));
+        self.offsets.push(self.residues.len() as u32);
+    }
+
+    pub fn push_encoded(&mut self, residues: &[u8]) {
+        self.residues.extend_from_slice(residues);
+        self.offsets.push(self.residues.len() as u32);
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> &[u8] {
+        &self.residues[self.offsets[index] as usize..self.offsets[index + 1] as usize]
+    }
+
+    pub(crate) fn longest(&self) -> usize {
+        self.offsets.windows(2).map(|w| (w[1] - w[0]) as usize).max().unwrap_or(0)
+    }
+
+    // Sequences first..last as a set of their own
+    pub(crate) fn slice(&self, first: usize, last: usize) -> SequenceSet {
+        let base = self.offsets[first];
+        SequenceSet {
+            residues: self.residues[base as usize..self.offsets[last] as usize].to_vec(),
+            offsets: self.offsets[first..=last].iter().map(|&o| o - base).collect(),
+        }
+    }
+}
+
+// Best ungapped local score over every diagonal of two encoded sequences
+pub fn pair_score(query: &[u8], target: &[u8], matrix: &ScoreMatrix) -> i32 {
+    screened_pair_score(query, target, matrix, 0)
+}
+
+// pair_score if it is at least `threshold`, 0 otherwise (0: no screening)
+pub fn screened_pair_score(query: &[u8], target: &[u8], matrix: &ScoreMatrix, threshold: i32) -> i32 {
+    if query.is_empty() || target.is_empty() {
+        return 0;
+    }
+    let (threshold, max_score) = (threshold as i64, matrix.max_score() as i64);
+    let mut best = 0;
+    for diagonal in 0..(query.len() + target.len()).saturating_sub(1) {
+        let i0 = (query.len() - 1).saturating_sub(diagonal);
+        let j0 = diagonal.saturating_sub(query.len() - 1);
+        let mut left = (query.len() - i0).min(target.len() - j0) as i64;
+        if left * max_score < threshold {
+            continue;
+        }
+        let mut current = 0;
+        for (&a, &b) in query[i0..].iter().zip(&target[j0..]) {
+            current = (current + matrix.score(a, b)).max(0);
+            best = best.max(current);
+            left -= 1;
+            if (best as i64) < threshold && current as i64 + left * max_score < threshold {
+                break;
+            }
+        }
+    }
+    if (best as i64) < threshold { 0 } else { best }
+}
+
+// Scores of every query against every target, indexed target * queries + query
+pub fn cpu_scores(queries: &SequenceSet, targets: &SequenceSet, matrix: &ScoreMatrix, threshold: i32) -> Vec<i32> {
+    (0..queries.len() * targets.len()).into_par_iter()
+        .map(|pair| screened_pair_score(queries.get(pair % queries.len()), targets.get(pair / queries.len()), matrix, threshold))
+        .collect()
+}
+
+// GPU counterpart of cpu_scores; targets are split so a launch stays within the
+// work item limit the alignment kernels use
+pub fn gpu_scores(queries: &SequenceSet, targets: &SequenceSet, matrix: &ScoreMatrix, threshold: i32) -> Result<Vec<i32>, String> {
+    let mut scores = Vec::with_capacity(queries.len() * targets.len());
+    if queries.residues.is_empty() || targets.residues.is_empty() {
+        // Nothing to launch; every pair scores 0
+        scores.resize(queries.len() * targets.len(), 0);
+        return Ok(scores);
+    }
+    let max_work_items = GPU_MAX_WORK_GROUPS * GPU_WORK_GROUP_SIZE;
+    let per_target = queries.len() * (queries.longest() + targets.longest()).max(1);
+    let targets_per_launch = (max_work_items / per_target).max(1);
+    let mut first = 0;
+    while first < targets.len() {
+        let last = (first + targets_per_launch).min(targets.len());
+        scores.extend(launch(queries, &targets.slice(first, last), matrix, threshold)?);
+        first = last;
+    }
+    Ok(scores)
+}
+
+// Read-only device copy of `data`
+fn upload<T: ocl::OclPrm>(queue: &ocl::Queue, data: &[T], what: &str) -> Result<Buffer<T>, String> {
+    Buffer::<T>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_only().copy_host_ptr())
+        .len(data.len())
+        .copy_host_slice(data)
+        .build()
+        .map_err(|e| format!("Failed to create {} buffer: {}", what, e))
+}
+
+fn launch(queries: &SequenceSet, targets: &SequenceSet, matrix: &ScoreMatrix, threshold: i32) -> Result<Vec<i32>, String> {
+    let gpu = GpuContext::shared()?;
+    let (queue, program) = (gpu.queue(), gpu.program()?);
+    let query_buffer = upload(queue, &queries.residues, "query")?;
+    let query_offsets = upload(queue, &queries.offsets, "query offset")?;
+    let target_buffer = upload(queue, &targets.residues, "target")?;
+    let target_offsets = upload(queue, &targets.offsets, "target offset")?;
+    let matrix_buffer = upload(queue, &matrix.scores, "matrix")?;
+    // The kernel folds into these with atomic_max, so they start at zero
+    let pairs = queries.len() * targets.len();
+    let result_buffer = Buffer::<i32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_write())
+        .len(pairs)
+        .fill_val(0)
+        .build()
+        .map_err(|e| format!("Failed to create result buffer: {}", e))?;
+
+    let diagonals = (queries.longest() + targets.longest()).saturating_sub(1).max(1);
+    let kernel = Kernel::builder()
+        .program(program)
+        .name("smith_waterman_all_diagonals")
+        .queue(queue.clone())
+        .global_work_size([diagonals, pairs])
+        .arg(&query_buffer)
+        .arg(&query_offsets)
+        .arg(queries.len() as u32)
+        .arg(&target_buffer)
+        .arg(&target_offsets)
+        .arg(&matrix_buffer)
+        .arg(matrix.size as u32)
+        .arg(&result_buffer)
+        .arg(threshold)
+        .arg(matrix.max_score())
+        .build()
+        .map_err(|e| format!("Failed to create all-diagonals kernel: {}", e))?;
+    unsafe {
+        kernel.enq().map_err(|e| format!("Failed to execute all-diagonals kernel: {}", e))?;
+    }
+    let mut scores = vec![0i32; pairs];
+    result_buffer.read(&mut scores).enq()
+        .map_err(|e| format!("Failed to read all-diagonals kernel result: {}", e))?;
+    Ok(scores)
+}
+
+// Scores on the GPU or the CPU
+pub fn scores(queries: &SequenceSet, targets: &SequenceSet, matrix: &ScoreMatrix, use_gpu: bool) -> Result<Vec<i32>, String> {
+    screened_scores(queries, targets, matrix, use_gpu, 0)
+}
+
+// Scores of the pairs reaching `threshold`, 0 for the rest (0: no screening)
+pub fn screened_scores(queries: &SequenceSet, targets: &SequenceSet, matrix: &ScoreMatrix, use_gpu: bool, threshold: i32) -> Result<Vec<i32>, String> {
+    if use_gpu {
+        gpu_scores(queries, targets, matrix, threshold)
+    } else {
+        Ok(cpu_scores(queries, targets, matrix, threshold))
+    }
+}
+
+// Scores of query i against target i, pair i under matrices[matrix_of[i]]; the
+// matrices share one alphabet and encoding
+pub fn paired_scores(queries: &SequenceSet, targets: &SequenceSet, matrices: &[ScoreMatrix], matrix_of: &[u32], use_gpu: bool) -> Result<Vec<i32>, String> {
+    if !use_gpu {
+        return Ok((0..queries.len()).into_par_iter()
+            .map(|pair| pair_score(queries.get(pair), targets.get(pair), &matrices[matrix_of[pair] as usize]))
+            .collect());
+    }
+    let mut scores = Vec::with_capacity(queries.len());
+    let max_work_items = GPU_MAX_WORK_GROUPS * GPU_WORK_GROUP_SIZE;
+    let pairs_per_launch = (max_work_items / (queries.longest() + targets.longest()).max(1)).max(1);
+    let mut first = 0;
+    while first < queries.len() {
+        let last = (first + pairs_per_launch).min(queries.len());
+        scores.extend(launch_paired(&queries.slice(first, last), &targets.slice(first, last), matrices, &matrix_of[first..last])?);
+        first = last;
+    }
+    Ok(scores)
+}
+
+fn launch_paired(queries: &SequenceSet, targets: &SequenceSet, matrices: &[ScoreMatrix], matrix_of: &[u32]) -> Result<Vec<i32>, String> {
+    let pairs = queries.len();
+    if queries.residues.is_empty() || targets.residues.is_empty() {
+        return Ok(vec![0; pairs]);
+    }
+    let gpu = GpuContext::shared()?;
+    let (queue, program) = (gpu.queue(), gpu.program()?);
+    let query_buffer = upload(queue, &queries.residues, "query")?;
+    let query_offsets = upload(queue, &queries.offsets, "query offset")?;
+    let target_buffer = upload(queue, &targets.residues, "target")?;
+    let target_offsets = upload(queue, &targets.offsets, "target offset")?;
+    let scores: Vec<i32> = matrices.iter().flat_map(|matrix| matrix.scores.iter().copied()).collect();
+    let matrix_buffer = upload(queue, &scores, "matrix")?;
+    let matrix_of_buffer = upload(queue, matrix_of, "pair matrix")?;
+    // The kernel folds into these with atomic_max, so they start at zero
+    let result_buffer = Buffer::<i32>::builder()
+        .queue(queue.clone())
+        .flags(MemFlags::new().read_write())
+        .len(pairs)
+        .fill_val(0)
+        .build()
+        .map_err(|e| format!("Failed to create result buffer: {}", e))?;
+
+    let diagonals = (queries.longest() + targets.longest()).saturating_sub(1).max(1);
+    let kernel = Kernel::builder()
+        .program(program)
+        .name("smith_waterman_paired_diagonals")
+        .queue(queue.clone())
+        .global_work_size([diagonals, pairs])
+        .arg(&query_buffer)
+        .arg(&query_offsets)
+        .arg(&target_buffer)
+        .arg(&target_offsets)
+        .arg(&matrix_buffer)
+        .arg(&matrix_of_buffer)
+        .arg(matrices[0].size as u32)
+        .arg(&result_buffer)
+        .build()
+        .map_err(|e| format!("Failed to create paired-diagonals kernel: {}", e))?;
+    unsafe {
+        kernel.enq().map_err(|e| format!("Failed to execute paired-diagonals kernel: {}", e))?;
+    }
+    let mut scores = vec![0i32; pairs];
+    result_buffer.read(&mut scores).enq()
+        .map_err(|e| format!("Failed to read paired-diagonals kernel result: {}", e))?;
+    Ok(scores)
+}
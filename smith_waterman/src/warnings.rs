@@ -0,0 +1,172 @@
+// Warnings of the run, kept for the end: a 10-hour run's warnings scroll away
+// long before it finishes, so each one is printed as it happens (to stderr,
+// "Warning: ...") and also recorded by category in the Registry of the run the
+// thread works for (run_context.rs). finish() prints a summary per category
+// (count and the first MAX_EXAMPLES messages) after the run's totals and
+// records it in manifest.json with --output-dir, so a run that "completed"
+// with hundreds of skipped chunks or a dead monitor says so where it will be
+// read
+// Categories:
+//   chunks       chunks that failed to align and were skipped
+//   input        parse anomalies and inputs read another way than planned
+//   checkpoints  checkpoints and run reports that couldn't be read or saved
+//   monitoring   system monitors, the dashboard, profiling and notifications
+//   throttling   the GPU shared or slowed down below what was asked for
+//   device       backends, CPU paths and NUMA placement not available
+//   storage      output files, remote state and the run registry
+
+use crate::run_context::{self, RunContext};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const MAX_EXAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Chunks,
+    Input,
+    Checkpoints,
+    Monitoring,
+    Throttling,
+    Device,
+    Storage,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Chunks => "chunks",
+            Category::Input => "input",
+            Category::Checkpoints => "checkpoints",
+            Category::Monitoring => "monitoring",
+            Category::Throttling => "throttling",
+            Category::Device => "device",
+            Category::Storage => "storage",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategorySummary {
+    pub category: Category,
+    pub count: u64,
+    // The first MAX_EXAMPLES messages, in the order they were raised
+    pub examples: Vec<String>,
+}
+
+// One run's warnings by category
+#[derive(Default)]
+pub struct Registry {
+    categories: Mutex<Vec<CategorySummary>>,
+}
+
+impl Registry {
+    fn record(&self, category: Category, message: String) {
+        let mut categories = self.categories.lock().unwrap_or_else(|e| e.into_inner());
+        let index = match categories.iter().position(|summary| summary.category == category) {
+            Some(index) => index,
+            None => {
+                categories.push(CategorySummary { category, count: 0, examples: Vec::new() });
+                categories.len() - 1
+            },
+        };
+        let summary = &mut categories[index];
+        summary.count += 1;
+        if summary.examples.len() < MAX_EXAMPLES {
+            summary.examples.push(message);
+        }
+    }
+
+    // Warnings so far, by category in Category order
+    pub(crate) fn summary(&self) -> Vec<CategorySummary> {
+        let mut summary = self.categories.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        summary.sort_by_key(|category| category.category);
+        summary
+    }
+}
+
+// Print a warning now and keep it for the run's summary
+pub fn warn(category: Category, message: impl Into<String>) {
+    let message = message.into();
+    eprintln!("Warning: {}", message);
+    run_context::with_state(|state| state.warnings.record(category, message));
+}
+
+// The warnings so far of the run the current thread works for
+pub fn summary() -> Vec<CategorySummary> {
+    run_context::with_state(|state| state.warnings.summary())
+}
+
+pub fn print_summary(summary: &[CategorySummary]) {
+    let total: u64 = summary.iter().map(|category| category.count).sum();
+    if total == 0 {
+        return;
+    }
+    println!("Warnings ({}):", total);
+    for category in summary {
+        println!("  {:<12} {:>6}", category.category.name(), category.count);
+        for example in &category.examples {
+            println!("      {}", example);
+        }
+        if category.count > category.examples.len() as u64 {
+            println!("      ... and {} more", category.count - category.examples.len() as u64);
+        }
+    }
+}
+
+// End of a run: print its summary and record it in manifest.json
+pub fn finish(run: &RunContext) {
+    let summary = run.warnings();
+    print_summary(&summary);
+    if let Err(e) = crate::output_layout::record_warnings(run.run().number, &summary) {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn each_run_keeps_its_own_warnings() {
+        let (first, second) = (RunContext::for_run(run_record(1)), RunContext::for_run(run_record(2)));
+        std::thread::scope(|scope| {
+            for (run, count) in [(&first, 7), (&second, 1)] {
+                scope.spawn(move || {
+                    let _entered = run.enter();
+                    for i in 0..count {
+                        warn(Category::Chunks, format!("chunk {} of run {}", i, run.run().number));
+                    }
+                });
+            }
+        });
+        let first_summary = first.warnings();
+        assert_eq!(first_summary.len(), 1);
+        assert_eq!(first_summary[0].count, 7);
+        assert_eq!(first_summary[0].examples.len(), MAX_EXAMPLES);
+        assert!(first_summary[0].examples.iter().all(|example| example.ends_with("of run 1")));
+        let second_summary = second.warnings();
+        assert_eq!(second_summary[0].count, 1);
+        assert_eq!(second_summary[0].examples, ["chunk 0 of run 2"]);
+
+        // Categories come back in Category order, whatever order they were raised in
+        let state = Arc::new(run_context::RunState::default());
+        let _entered = state.enter();
+        warn(Category::Storage, "disk full");
+        warn(Category::Input, "bad line");
+        let categories: Vec<Category> = summary().iter().map(|category| category.category).collect();
+        assert_eq!(categories, [Category::Input, Category::Storage]);
+    }
+
+    fn run_record(number: u64) -> crate::run_registry::RunRecord {
+        crate::run_registry::RunRecord {
+            number,
+            run_id: format!("run_{}", number),
+            started: chrono::Utc::now(),
+            command: Vec::new(),
+            logs_dir: std::env::temp_dir(),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// Stall watchdog for full WGS runs
+// Every stage reports progress as it passes items on and finishes waiting on
+// its channels (stages.rs). When a file's stages have made no progress at all
+// for STALL_TIMEOUT_S (default 300 s; 0 turns the watchdog off), the run is
+// stalled: a hung zcat or fetch, an NFS read that never returns, a kernel that
+// never completes. The stage at fault is the furthest downstream one that is
+// busy; the stages before it are blocked behind it and the ones after it are
+// starved. The watchdog then
+//   1. prints each stage's activity and how long it has been at it, and adds
+//      the event to stalls.json in the results directory
+//   2. if the stage is the reader and it has child processes (decompressor,
+//      remote fetcher), kills them. The file fails with the stall as its error
+//      and is retried once from the start (not with per-read output, whose rows
+//      for the file are already written); failing again counts towards
+//      quarantine like any other failure
+//   3. otherwise, or if the kill didn't get things moving, cancels the run: the
+//      file is checkpointed as incomplete and the run returns. If it hasn't
+//      within GRACE, the stuck stage is abandoned: the benchmark is flushed,
+//      the uploads to --remote-state finish and the process exits with 1
+// The watchdog is the CLI's: it watches the stages of the run of the thread
+// that starts it. The file a run is on and the child processes its reader may
+// kill are kept per run (run_context.rs), so runs on other threads of the
+// process neither replace them nor have theirs killed
+
+use crate::exit_codes::ExitKind;
+use crate::run_context::{self, RunState};
+use crate::run_control::CancellationToken;
+use crate::stages::{Activity, Stage};
+use crate::warnings::{self, Category};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const STALLS_FILE: &str = "stalls.json";
+pub const DEFAULT_STALL_TIMEOUT_S: u64 = 300;
+// Retries of a file after its reader was killed
+pub const STALL_RETRIES: u32 = 1;
+const GRACE: Duration = Duration::from_secs(30);
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// A stall that failed the current file
+#[derive(Debug, Clone)]
+pub struct Stall {
+    pub message: String,
+    // The stuck child processes were killed, so the file can be retried
+    pub retry: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct StageState {
+    stage: String,
+    activity: String,
+    for_s: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct StallEvent {
+    time: DateTime<Utc>,
+    file_path: String,
+    file_index: usize,
+    stage: Option<String>,
+    stalled_s: f64,
+    stages: Vec<StageState>,
+    action: String,
+}
+
+#[derive(Default)]
+struct CurrentFile {
+    index: usize,
+    path: String,
+    // Children were killed for this file; a second stall cancels the run
+    killed: bool,
+    stall: Option<Stall>,
+}
+
+struct Child {
+    id: u64,
+    stage: Stage,
+    pid: u32,
+    name: String,
+}
+
+// One run's current file and its reader's children
+#[derive(Default)]
+pub struct Watched {
+    file: Mutex<Option<CurrentFile>>,
+    children: Mutex<Vec<Child>>,
+}
+
+impl Watched {
+    fn file(&self) -> std::sync::MutexGuard<'_, Option<CurrentFile>> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn children(&self) -> std::sync::MutexGuard<'_, Vec<Child>> {
+        self.children.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+static EVENTS: Mutex<Vec<StallEvent>> = Mutex::new(Vec::new());
+static NEXT_CHILD: AtomicU64 = AtomicU64::new(0);
+
+// A child process of `stage` the watchdog may kill; forgotten when dropped
+pub struct WatchedChild {
+    id: u64,
+    run: Arc<RunState>,
+}
+
+pub fn watch_child(stage: Stage, pid: u32, name: &str) -> WatchedChild {
+    let id = NEXT_CHILD.fetch_add(1, Ordering::Relaxed);
+    let run = run_context::current();
+    run.watched.children().push(Child { id, stage, pid, name: name.to_string() });
+    WatchedChild { id, run }
+}
+
+impl Drop for WatchedChild {
+    fn drop(&mut self) {
+        self.run.watched.children().retain(|child| child.id != self.id);
+    }
+}
+
+// The current run's stages about to run are file `index`'s
+pub fn start_file(index: usize, path: &str) {
+    run_context::with_state(|state| {
+        *state.watched.file() = Some(CurrentFile { index, path: path.to_string(), ..Default::default() });
+    });
+}
+
+// The current run's file's stages have all stopped; the stall that failed it, if any
+pub fn finish_file() -> Option<Stall> {
+    run_context::with_state(|state| state.watched.file().take().and_then(|file| file.stall))
+}
+
+// STALL_TIMEOUT_S from .env; None when the watchdog is off
+pub fn timeout_from_env() -> Result<Option<Duration>, String> {
+    let seconds = match std::env::var("STALL_TIMEOUT_S") {
+        Ok(value) => value.trim().parse::<u64>()
+            .map_err(|_| format!("Invalid STALL_TIMEOUT_S '{}': expected seconds without progress (0 turns the watchdog off)", value))?,
+        Err(_) => DEFAULT_STALL_TIMEOUT_S,
+    };
+    Ok((seconds > 0).then(|| Duration::from_secs(seconds)))
+}
+
+// Watch the current run's stages for the rest of the process, cancelling
+// `cancel` on a stall that killing the reader's children can't clear
+pub fn watch(cancel: &CancellationToken) -> Result<(), String> {
+    let Some(timeout) = timeout_from_env()? else {
+        return Ok(());
+    };
+    let cancel = cancel.clone();
+    let run = run_context::current();
+    std::thread::Builder::new()
+        .name("stall-watchdog".to_string())
+        .spawn(move || {
+            let _entered = run.enter();
+            // Stall detection restarts after each action, so the next one gets a full timeout
+            let mut acted = Instant::now();
+            loop {
+                std::thread::sleep(CHECK_INTERVAL);
+                let stalled = crate::stages::since_progress().min(acted.elapsed());
+                if stalled < timeout || run.watched.file().is_none() {
+                    continue;
+                }
+                let states = crate::stages::activities();
+                if !states.iter().any(|(_, activity, _)| activity.is_active()) {
+                    continue;
+                }
+                if !on_stall(&run.watched, &states, stalled, &cancel) {
+                    return;
+                }
+                acted = Instant::now();
+            }
+        })
+        .map_err(|e| format!("Failed to start the stall watchdog: {}", e))?;
+    Ok(())
+}
+
+// Kill the stuck children or cancel the run; false once the run was cancelled
+fn on_stall(watched: &Watched, states: &[(Stage, Activity, Duration)], stalled: Duration, cancel: &CancellationToken) -> bool {
+    let stage = states.iter().rev().find(|(_, activity, _)| *activity == Activity::Busy).map(|(stage, _, _)| *stage);
+    let stage_name = stage.map_or("no stage", |stage| stage.name());
+    crate::tui::stop();
+    eprintln!("\nStalled: no progress for {} s (at fault: {})", stalled.as_secs(), stage_name);
+    for (stage, activity, since) in states {
+        eprintln!("    {:<8} {} for {:.0} s", stage.name(), activity.name(), since.as_secs_f64());
+    }
+
+    let mut file = watched.file();
+    let Some(current) = file.as_mut() else {
+        return true;
+    };
+    let killable = stage.is_some_and(|stage| stage == Stage::Reader) && !current.killed;
+    let mut killed = Vec::new();
+    if killable {
+        for child in watched.children().iter().filter(|child| Some(child.stage) == stage) {
+            if kill(child.pid) {
+                killed.push(format!("{} (pid {})", child.name, child.pid));
+            }
+        }
+    }
+    let message = format!("stalled: {} made no progress for {} s", stage_name, stalled.as_secs());
+    let action = if killed.is_empty() {
+        "cancelled the run".to_string()
+    } else {
+        format!("killed {}", killed.join(", "))
+    };
+    eprintln!("    Action: {}", action);
+    record(StallEvent {
+        time: Utc::now(),
+        file_path: current.path.clone(),
+        file_index: current.index,
+        stage: stage.map(|stage| stage.name().to_string()),
+        stalled_s: stalled.as_secs_f64(),
+        stages: states.iter()
+            .map(|(stage, activity, since)| StageState { stage: stage.name().to_string(), activity: activity.name().to_string(), for_s: since.as_secs_f64() })
+            .collect(),
+        action,
+    });
+
+    if !killed.is_empty() {
+        current.killed = true;
+        current.stall = Some(Stall { message, retry: true });
+        return true;
+    }
+    current.stall = Some(Stall { message: message.clone(), retry: false });
+    drop(file);
+    cancel.cancel();
+    let deadline = Instant::now() + GRACE;
+    while Instant::now() < deadline {
+        if watched.file().is_none() {
+            return false;
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    }
+    eprintln!("The {} stage didn't stop within {} s of cancelling; abandoning it", stage_name, GRACE.as_secs());
+    abandon(&format!("Run abandoned: {}", message));
+}
+
+fn kill(pid: u32) -> bool {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut system = sysinfo::System::new();
+    system.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
+    system.process(pid).is_some_and(|process| process.kill())
+}
+
+// Add the event to stalls.json in the results directory
+fn record(event: StallEvent) {
+    let mut events = EVENTS.lock().unwrap_or_else(|e| e.into_inner());
+    events.push(event);
+    let path = crate::output_layout::result_file(STALLS_FILE);
+    let written = serde_json::to_string_pretty(&*events)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => {
+            eprintln!("    Stalls: {}", path.display());
+            crate::remote_state::mirror(&path);
+        },
+        Err(e) => warnings::warn(Category::Storage, format!("Failed to write {}: {}", path.display(), e)),
+    }
+}
+
+// Flush, upload and exit while a stage is still stuck
+fn abandon(error: &str) -> ! {
+    crate::run_context::interrupt_cli();
+    let mut notification = crate::notify::RunNotification::new(crate::notify::RunEvent::Failed);
+    notification.error = Some(error.to_string());
+    crate::notify::send(&notification);
+    crate::remote_state::flush();
+    ExitKind::Failure.exit()
+}
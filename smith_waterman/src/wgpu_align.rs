@@ -0,0 +1,205 @@
+// wgpu backend: the basic alignment kernel as a WGSL compute shader
+// (smith_waterman.wgsl), on Metal on macOS and Vulkan/DX12 elsewhere
+// Built on macOS, where it needs nothing beyond the OS, and anywhere with
+// --features wgpu (build.rs sets the wgpu_backend cfg). Launch geometry and N
+// handling match the OpenCL kernel, so scores equal the OpenCL and CPU paths;
+// the tiled and packed variants, profiling events and multi-queue submission
+// are OpenCL-only. Used through Backend::Wgpu on the Aligner (engine.rs)
+
+use crate::gpu::{GPU_MAX_WORK_GROUPS, GPU_WORK_GROUP_SIZE};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = include_str!("smith_waterman.wgsl");
+const ENTRY_POINT: &str = "smith_waterman_align";
+
+// Clones share one device, queue and pipeline; safe to use from any thread
+#[derive(Clone)]
+pub struct WgpuContext {
+    inner: Arc<ContextInner>,
+}
+
+struct ContextInner {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    name: String,
+    backend: wgpu::Backend,
+    group_size: usize,
+    max_groups_per_dimension: usize,
+    max_binding_bytes: usize,
+}
+
+// Adapters that can run compute, in enumeration order (CPU emulators last)
+fn compute_adapters() -> Vec<wgpu::Adapter> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let mut adapters: Vec<wgpu::Adapter> = instance.enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .filter(|adapter| adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS))
+        .collect();
+    adapters.sort_by_key(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu);
+    adapters
+}
+
+// Names of the adapters WgpuContext::new can open, by index
+pub fn adapter_names() -> Vec<String> {
+    compute_adapters().iter().map(|adapter| {
+        let info = adapter.get_info();
+        format!("{} ({:?})", info.name, info.backend)
+    }).collect()
+}
+
+impl WgpuContext {
+    // Device, queue and pipeline on adapter `index` of adapter_names(); the
+    // pipeline is built with the N policy installed at the time
+    pub fn new(index: usize) -> Result<Self, String> {
+        let adapters = compute_adapters();
+        let count = adapters.len();
+        let adapter = adapters.into_iter().nth(index).ok_or_else(|| match count {
+            0 => "No wgpu adapter with compute support was found".to_string(),
+            _ => format!("wgpu adapter {} requested but only {} found", index, count),
+        })?;
+        let info = adapter.get_info();
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("rustseq_mini"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        })).map_err(|e| format!("Failed to open wgpu device {}: {}", info.name, e))?;
+
+        let device_max = limits.max_compute_invocations_per_workgroup.min(limits.max_compute_workgroup_size_x) as usize;
+        let group_size = crate::determinism::work_group_size(device_max, GPU_WORK_GROUP_SIZE);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("smith_waterman.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let constants = [
+            ("group_size", group_size as f64),
+            ("n_score", crate::n_policy::selected().n_score() as f64),
+            ("match_score", crate::scoring::selected().match_score as f64),
+            ("mismatch_penalty", crate::scoring::selected().mismatch_penalty as f64),
+        ];
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(ENTRY_POINT),
+            layout: None,
+            module: &module,
+            entry_point: Some(ENTRY_POINT),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                // The shader relies on its group maximum starting at 0
+                zero_initialize_workgroup_memory: true,
+            },
+            cache: None,
+        });
+
+        Ok(Self {
+            inner: Arc::new(ContextInner {
+                device,
+                queue,
+                pipeline,
+                name: info.name,
+                backend: info.backend,
+                group_size,
+                max_groups_per_dimension: limits.max_compute_workgroups_per_dimension as usize,
+                max_binding_bytes: limits.max_storage_buffer_binding_size as usize,
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    // e.g. "Apple M2 Pro (Metal)"
+    pub fn describe(&self) -> String {
+        format!("{} ({:?})", self.inner.name, self.inner.backend)
+    }
+
+    pub fn work_group_size(&self) -> usize {
+        self.inner.group_size
+    }
+
+    // Score of two sequences in one dispatch
+    pub fn align(&self, seq1: &[u8], seq2: &[u8]) -> Result<i32, String> {
+        let (seq1, seq2) = (crate::n_policy::prepare(seq1), crate::n_policy::prepare(seq2));
+        let len = seq1.len().min(seq2.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        let inner = &self.inner;
+        let padded = len.div_ceil(4) * 4;
+        if padded > inner.max_binding_bytes || len > GPU_MAX_WORK_GROUPS * inner.group_size {
+            return Err(format!("Sequence too large for {} ({} bytes, max {} per buffer)", inner.name, len, inner.max_binding_bytes));
+        }
+        let work_groups = len.div_ceil(inner.group_size).min(GPU_MAX_WORK_GROUPS);
+        let groups_x = work_groups.min(inner.max_groups_per_dimension);
+        let groups_y = work_groups.div_ceil(groups_x);
+
+        let upload = |label: &str, bases: &[u8]| {
+            let mut words = Vec::with_capacity(padded);
+            words.extend_from_slice(&bases[..len]);
+            words.resize(padded, 0);
+            inner.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: &words,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        };
+        let seq1_buffer = upload("seq1", &seq1);
+        let seq2_buffer = upload("seq2", &seq2);
+        let params: Vec<u8> = [len as u32, work_groups as u32, groups_x as u32, 0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let params_buffer = inner.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: &params,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        // New buffers are zeroed, which is the starting maximum
+        let result_buffer = inner.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("result"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = inner.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = inner.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &inner.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: seq1_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: seq2_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: result_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: params_buffer.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = inner.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(ENTRY_POINT), timestamp_writes: None });
+            pass.set_pipeline(&inner.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups_x as u32, groups_y as u32, 1);
+        }
+        encoder.copy_buffer_to_buffer(&result_buffer, 0, &readback, 0, 4);
+        inner.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback.slice(..).map_async(wgpu::MapMode::Read, move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        inner.device.poll(wgpu::PollType::Wait)
+            .map_err(|e| format!("wgpu device {} failed: {}", inner.name, e))?;
+        receiver.recv()
+            .map_err(|_| format!("wgpu device {} dropped the result", inner.name))?
+            .map_err(|e| format!("Failed to read the result from {}: {}", inner.name, e))?;
+        let bytes = readback.slice(..).get_mapped_range();
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
@@ -0,0 +1,135 @@
+// Everything a full WGS run needs, passed to process_full_wgs_dataset
+// The CLI fills this in from .env and the command line; library callers build it
+// directly, so the run doesn't depend on the process environment
+
+use crate::alignment_input::AlignmentInputOptions;
+use crate::chunking::{ChunkSize, DEFAULT_CHUNK_BASES};
+use crate::contamination::ScreenOptions;
+use crate::file_order::FileOrder;
+use crate::file_selection::FileSelection;
+use crate::gpu_queues::QueueCount;
+use crate::long_read_trim::TrimOptions;
+use crate::read_trim::ReadTrimOptions;
+use crate::long_reads::LongReadOptions;
+use crate::qc::QcOptions;
+use crate::read_binning::BinningPolicy;
+use crate::read_group::ReadGroup;
+use crate::read_output::ReadOutputOptions;
+use crate::sanitize::SanitizeOptions;
+use crate::wgs_summary::WgsExpectations;
+
+#[derive(Debug, Clone)]
+pub struct WgsConfig {
+    pub data_dir: String,
+    pub sample_id: String,
+    pub lanes: usize,
+    pub reads_per_lane: usize,
+    // Explicit inputs (FASTA or FASTQ, compressed or not) instead of the lane
+    // layout; names without a '/' are resolved against data_dir
+    pub input_files: Option<Vec<String>>,
+    pub chunk_size: ChunkSize,
+    // Kernel latency target in ms when the chunk size adapts (--chunk-size auto);
+    // chunk_size is then where each file starts
+    pub adaptive_chunks: Option<f64>,
+    // One read per chunk, each cut into tiles, with a score and CIGAR per read
+    pub long_reads: Option<LongReadOptions>,
+    // Reordering of reads by length within each chunk before launch
+    pub binning: BinningPolicy,
+    // Uppercasing, whitespace removal, and alphabet checks before anything else
+    pub sanitize: Option<SanitizeOptions>,
+    // Adapter, poly-A and telomere trimming of long reads after sanitizing
+    pub trim: Option<TrimOptions>,
+    // Head, tail and sliding-window quality trimming of short reads, after that
+    pub read_trim: Option<ReadTrimOptions>,
+    // SDUST masking of low-complexity runs before launch
+    pub mask_low_complexity: bool,
+    // Contamination screen of every chunk against a reference panel
+    pub screen: Option<ScreenOptions>,
+    // Duplicate estimation and other per-file statistics for qc_report.json
+    pub qc: Option<QcOptions>,
+    // Command queues launches are spread over (one when writing per-read output)
+    pub gpu_queues: QueueCount,
+    pub expectations: WgsExpectations,
+    pub alignment_input: AlignmentInputOptions,
+    // With this set, every read is also written out under its name
+    pub read_output: Option<ReadOutputOptions>,
+    // Sample, library and platform unit for the read output and manifest.json
+    pub read_group: Option<ReadGroup>,
+    pub selection: FileSelection,
+    // Order the selected files are processed in
+    pub order: FileOrder,
+    // Continue from the latest checkpoint instead of starting over (resume.rs)
+    pub resume: bool,
+    // Failed attempts after which a file is quarantined (quarantine.rs); 0 never
+    pub quarantine_after: u32,
+    // --memory-profile strict: allocations are tracked against this many bytes (memory_profile.rs)
+    pub memory_budget: Option<u64>,
+    // Checkpoints over checkpoint_store::COMPRESS_ABOVE are written zstd-compressed
+    pub compress_checkpoints: bool,
+    // Names of the per-file aggregates of chunk scores to report (aggregation.rs)
+    pub aggregates: Vec<String>,
+    // --deterministic: fixed kernel geometry, and a chunk that fails to align
+    // fails the run (determinism.rs)
+    pub deterministic: bool,
+}
+
+impl WgsConfig {
+    // Lane layout defaults: 8 lanes, R1 and R2 per lane
+    pub fn new(data_dir: &str, sample_id: &str) -> Self {
+        Self {
+            data_dir: data_dir.to_string(),
+            sample_id: sample_id.to_string(),
+            lanes: 8,
+            reads_per_lane: 2,
+            input_files: None,
+            chunk_size: ChunkSize::Bases(DEFAULT_CHUNK_BASES),
+            adaptive_chunks: None,
+            long_reads: None,
+            binning: BinningPolicy::Off,
+            sanitize: None,
+            trim: None,
+            read_trim: None,
+            mask_low_complexity: false,
+            screen: None,
+            qc: None,
+            gpu_queues: QueueCount::Auto,
+            expectations: WgsExpectations::default(),
+            alignment_input: AlignmentInputOptions::default(),
+            read_output: None,
+            read_group: None,
+            selection: FileSelection::default(),
+            order: FileOrder::AsListed,
+            resume: false,
+            quarantine_after: crate::quarantine::DEFAULT_QUARANTINE_AFTER,
+            memory_budget: None,
+            compress_checkpoints: false,
+            aggregates: Vec::new(),
+            deterministic: false,
+        }
+    }
+
+    // Chunks as read: one read each in long-read mode, chunk_size otherwise
+    pub fn launch_chunk_size(&self) -> ChunkSize {
+        if self.long_reads.is_some() { ChunkSize::Reads(1) } else { self.chunk_size }
+    }
+
+    // Input paths before the file selection is applied
+    pub fn discover_files(&self) -> Vec<String> {
+        match &self.input_files {
+            Some(files) => files.iter()
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+                .map(|f| if crate::paths::is_bare_name(f) { crate::paths::join(&self.data_dir, f) } else { f.to_string() })
+                .collect(),
+            None => {
+                let mut files = Vec::new();
+                for lane in 1..=self.lanes {
+                    for read in 1..=self.reads_per_lane {
+                        files.push(crate::paths::join(&self.data_dir, &format!("{}_L{:03}_R{}_001.fastq.gz", self.sample_id, lane, read)));
+                    }
+                }
+                files
+            },
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// WgsRunReport: what a WGS run returns and leaves behind
+// One FileReport per input file, in file order, with its path, counts, score
+// and how it ended: aligned in this run, taken from the checkpoint it resumed,
+// failed, or quarantined after failing too often (quarantine.rs), with the reason. The run's totals, the chunks skipped after
+// failing to align and every error are next to them. The report is saved as
+// results/run_N_wgs_report.json when the run ends, and also when a file fails
+// or the run is cancelled, so a failed run still says how far it got
+// A score that leaves out some of its input says so: each file and the totals
+// carry the reads and bases left out, their share of the bases and is_partial,
+// so a partial result is never taken for a complete one. A file's skipped
+// chunks are left out of it, and all of a failed or quarantined file; a
+// quarantined file's bases aren't known, so the totals count it in excluded_files
+
+use crate::score_metrics::NormalizedScore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Completed,
+    // Completed in the run this one resumed; counts come from the checkpoint
+    Resumed,
+    Failed,
+    // Failed on QUARANTINE_AFTER attempts; the run went on without it
+    Quarantined,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileReport {
+    pub index: usize,
+    pub path: String,
+    pub status: FileStatus,
+    pub score: i32,
+    pub total_reads: usize,
+    pub total_bases: usize,
+    pub chunks: usize,
+    // Chunks that failed to align and were left out of the score
+    pub skipped_chunks: usize,
+    pub processing_time_ms: f64,
+    pub gpu_device: String,
+    pub normalized: NormalizedScore,
+    pub error: Option<String>,
+    // Reads and bases left out of the score, and their share of the file's bases
+    #[serde(default)]
+    pub excluded_reads: usize,
+    #[serde(default)]
+    pub excluded_bases: usize,
+    #[serde(default)]
+    pub excluded_fraction: f64,
+    #[serde(default)]
+    pub is_partial: bool,
+    // --aggregate: each named aggregate of the file's chunk scores (aggregation.rs)
+    #[serde(default)]
+    pub aggregates: BTreeMap<String, f64>,
+}
+
+impl FileReport {
+    pub fn name(&self) -> &str {
+        crate::paths::file_name(&self.path)
+    }
+
+    // Counted in the totals: neither failed nor quarantined
+    pub fn aligned(&self) -> bool {
+        !matches!(self.status, FileStatus::Failed | FileStatus::Quarantined)
+    }
+
+    // Set what the score leaves out once the status is final: the given reads
+    // and bases of a file that aligned, all of one that didn't
+    pub fn exclude(&mut self, reads: usize, bases: usize) {
+        if self.aligned() {
+            (self.excluded_reads, self.excluded_bases) = (reads, bases);
+            self.excluded_fraction = share(bases, self.total_bases);
+            self.is_partial = bases > 0 || self.skipped_chunks > 0;
+        } else {
+            (self.excluded_reads, self.excluded_bases) = (self.total_reads, self.total_bases);
+            self.excluded_fraction = 1.0;
+            self.is_partial = true;
+        }
+    }
+
+    // ", partial: ..." when the score leaves out some of the file, else empty
+    pub fn partial_note(&self) -> String {
+        partial_note(self.is_partial, self.excluded_fraction)
+    }
+}
+
+fn share(count: usize, of: usize) -> f64 {
+    if of == 0 { 0.0 } else { count as f64 / of as f64 }
+}
+
+pub fn partial_note(is_partial: bool, excluded_fraction: f64) -> String {
+    if is_partial {
+        format!(", partial: {:.3}% of bases excluded", excluded_fraction * 100.0)
+    } else {
+        String::new()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunTotals {
+    pub files: usize,
+    pub completed_files: usize,
+    pub score: i64,
+    pub total_reads: usize,
+    pub total_bases: usize,
+    pub processing_time_ms: f64,
+    pub normalized: NormalizedScore,
+    // Left out of the score, over all files; see FileReport
+    #[serde(default)]
+    pub excluded_reads: usize,
+    #[serde(default)]
+    pub excluded_bases: usize,
+    #[serde(default)]
+    pub excluded_fraction: f64,
+    // Files failed or quarantined
+    #[serde(default)]
+    pub excluded_files: usize,
+    #[serde(default)]
+    pub is_partial: bool,
+}
+
+impl RunTotals {
+    pub fn partial_note(&self) -> String {
+        let files = if self.excluded_files > 0 { format!(", {} file(s) excluded", self.excluded_files) } else { String::new() };
+        partial_note(self.is_partial, self.excluded_fraction) + &files
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WgsRunReport {
+    // See schema.rs
+    #[serde(default)]
+    pub schema_version: u32,
+    pub run: u64,
+    pub files: Vec<FileReport>,
+    pub totals: RunTotals,
+    // Chunks skipped over the whole run
+    pub skipped: usize,
+    // File failures and skipped chunks, in the order they happened
+    pub errors: Vec<String>,
+}
+
+impl WgsRunReport {
+    pub fn new(run: u64) -> Self {
+        Self { schema_version: crate::schema::RUN_REPORT.version(), run, ..Default::default() }
+    }
+
+    pub fn add_file(&mut self, file: FileReport) {
+        self.skipped += file.skipped_chunks;
+        if let Some(error) = &file.error {
+            self.errors.push(format!("file {} ({}): {}", file.index + 1, file.name(), error));
+        }
+        self.files.push(file);
+        self.update_totals();
+    }
+
+    fn update_totals(&mut self) {
+        let mut totals = RunTotals { files: self.files.len(), ..Default::default() };
+        for file in &self.files {
+            if file.aligned() {
+                totals.completed_files += 1;
+            } else {
+                totals.excluded_files += 1;
+            }
+            totals.excluded_reads += file.excluded_reads;
+            totals.excluded_bases += file.excluded_bases;
+            totals.is_partial |= file.is_partial;
+            totals.score += file.score as i64;
+            totals.total_reads += file.total_reads;
+            totals.total_bases += file.total_bases;
+            totals.processing_time_ms += file.processing_time_ms;
+        }
+        totals.normalized = NormalizedScore::from_totals(totals.score, totals.total_reads, totals.total_bases);
+        totals.excluded_fraction = share(totals.excluded_bases, totals.total_bases);
+        self.totals = totals;
+    }
+
+    // Files that aligned, in this run or the one it resumed
+    pub fn completed(&self) -> impl Iterator<Item = &FileReport> {
+        self.files.iter().filter(|file| file.aligned())
+    }
+
+    pub fn path_for(run: u64) -> std::path::PathBuf {
+        crate::output_layout::result_file(&format!("run_{}_wgs_report.json", run))
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        crate::schema::parse(&crate::schema::RUN_REPORT, &json, &path.display().to_string())
+    }
+
+    pub fn save(&self) -> Result<std::path::PathBuf, String> {
+        let path = Self::path_for(self.run);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the run report: {}", e))?;
+        std::fs::write(&path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        crate::remote_state::mirror(&path);
+        Ok(path)
+    }
+}
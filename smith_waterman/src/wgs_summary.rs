@@ -0,0 +1,87 @@
+// Expected vs. observed dataset totals for WGS runs
+// Expectations come from .env; observed totals come from the streaming counters
+
+fn env_count(name: &str) -> Option<usize> {
+    std::env::var(name).ok().and_then(|v| v.trim().replace('_', "").parse::<f64>().ok()).map(|v| v as usize)
+}
+
+// Human-readable count, e.g. 415.2 million
+pub fn format_count(n: usize) -> String {
+    let n = n as f64;
+    if n >= 1e9 {
+        format!("{:.1} billion", n / 1e9)
+    } else if n >= 1e6 {
+        format!("{:.1} million", n / 1e6)
+    } else {
+        format!("{}", n)
+    }
+}
+
+fn delta(observed: usize, expected: usize) -> String {
+    if expected == 0 {
+        return "n/a".to_string();
+    }
+    format!("{:+.1}%", (observed as f64 - expected as f64) / expected as f64 * 100.0)
+}
+
+#[derive(Debug, Clone)]
+pub struct WgsExpectations {
+    pub reads: Option<usize>,
+    pub bases: Option<usize>,
+    // Haploid genome size used for coverage
+    pub genome_size: usize,
+}
+
+// Human genome size
+const DEFAULT_GENOME_SIZE: usize = 3_200_000_000;
+
+impl Default for WgsExpectations {
+    fn default() -> Self {
+        Self { reads: None, bases: None, genome_size: DEFAULT_GENOME_SIZE }
+    }
+}
+
+impl WgsExpectations {
+    // WGS_EXPECTED_READS and WGS_EXPECTED_BASES are optional (scientific notation allowed, e.g. 62e9)
+    pub fn from_env() -> Self {
+        Self {
+            reads: env_count("WGS_EXPECTED_READS"),
+            bases: env_count("WGS_EXPECTED_BASES"),
+            genome_size: env_count("WGS_GENOME_SIZE").filter(|&g| g > 0).unwrap_or(DEFAULT_GENOME_SIZE),
+        }
+    }
+
+    pub fn coverage(&self, bases: usize) -> f64 {
+        bases as f64 / self.genome_size as f64
+    }
+
+    pub fn print_expected(&self) {
+        match (self.reads, self.bases) {
+            (None, None) => println!("Expected totals: not configured (set WGS_EXPECTED_READS / WGS_EXPECTED_BASES)"),
+            (reads, bases) => {
+                if let Some(reads) = reads {
+                    println!("Expected total reads: ~{}", format_count(reads));
+                }
+                if let Some(bases) = bases {
+                    println!("Expected total base pairs: ~{}", format_count(bases));
+                    println!("Expected genome coverage: ~{:.1}x", self.coverage(bases));
+                }
+            },
+        }
+    }
+
+    pub fn print_observed(&self, reads: usize, bases: usize) {
+        match self.reads {
+            Some(expected) => println!("Total reads processed: {} (expected {}, {})", reads, expected, delta(reads, expected)),
+            None => println!("Total reads processed: {}", reads),
+        }
+        match self.bases {
+            Some(expected) => println!("Total base pairs: {} (expected {}, {})", bases, expected, delta(bases, expected)),
+            None => println!("Total base pairs: {}", bases),
+        }
+        match self.bases {
+            Some(expected) => println!("Genome coverage: {:.2}x (expected {:.2}x)", self.coverage(bases), self.coverage(expected)),
+            None => println!("Genome coverage: {:.2}x", self.coverage(bases)),
+        }
+    }
+}
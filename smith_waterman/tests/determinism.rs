@@ -0,0 +1,50 @@
+// --deterministic through the library: two WGS runs of the bundled data in one
+// process score every file the same and end with the same results digest,
+// each written to its own run's logs directory
+// The output layout is process-wide, so this is a test binary of its own,
+// apart from tests/examples.rs. It needs a GPU and is skipped without one, with
+// a note on stderr
+
+use rustseq_mini::chunking::ChunkSize;
+use rustseq_mini::wgs_config::WgsConfig;
+use rustseq_mini::{determinism, gpu, output_layout, Aligner, Backend, CancellationToken, FileStatus, RunContext};
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+
+#[test]
+fn deterministic_runs_match() {
+    if !gpu::is_gpu_available() {
+        eprintln!("skipped: no GPU");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("rustseq_mini_determinism_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    output_layout::install(&dir.to_string_lossy()).unwrap();
+
+    let chunk_size = ChunkSize::Reads(8);
+    let aligner = Aligner::builder().backend(Backend::Gpu).chunking(chunk_size).build().unwrap();
+    let mut config = WgsConfig::new(DATA, "determinism");
+    config.input_files = Some(vec!["reads_R1.fastq".to_string(), "reads_R2.fastq".to_string()]);
+    config.deterministic = true;
+
+    let (first_run, second_run) = (RunContext::register(), RunContext::register());
+    let first = aligner.run_wgs_in(&first_run, &config, &CancellationToken::new(), &mut |_| {}).unwrap();
+    let second = aligner.run_wgs_in(&second_run, &config, &CancellationToken::new(), &mut |_| {}).unwrap();
+    assert_eq!(first.files.len(), 2);
+    for (a, b) in first.files.iter().zip(&second.files) {
+        assert_eq!(a.status, FileStatus::Completed, "{}", a.name());
+        assert_eq!(b.status, FileStatus::Completed, "{}", b.name());
+        assert_eq!((a.score, a.total_reads, a.total_bases), (b.score, b.total_reads, b.total_bases), "{}", a.name());
+    }
+    let digest = determinism::results_digest(chunk_size, &first.files);
+    assert_eq!(digest, determinism::results_digest(chunk_size, &second.files));
+
+    for (run, report) in [(&first_run, &first), (&second_run, &second)] {
+        determinism::report(run, chunk_size, &report.files);
+        let written = std::fs::read_to_string(run.run().logs_dir.join(determinism::DIGEST_FILE)).unwrap();
+        assert_eq!(written.trim(), digest);
+    }
+    assert_ne!(first_run.run().logs_dir, second_run.run().logs_dir);
+    // The mode was the runs'; this thread never asked for it
+    assert!(!determinism::is_enabled());
+}
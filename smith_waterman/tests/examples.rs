@@ -0,0 +1,157 @@
+// The flows of the examples (examples/), on the bundled dataset in
+// examples/data, through the public API: pairwise alignment, k-mer counting and
+// a custom ChunkProcessor on the Pipeline, and a WGS run. cargo test also
+// builds the examples, so they keep compiling against the API they show
+// The GPU runs only where OpenCL has a GPU; elsewhere they are skipped with a
+// note on stderr, and the CPU backend is checked on its own
+// The dataset is 24 read pairs of 100 bases sampled from the two contigs of
+// reference.fa with a few substitutions each
+
+use rustseq_mini::chunking::ChunkSize;
+use rustseq_mini::kmer_hash::HashKind;
+use rustseq_mini::kmer_spectrum;
+use rustseq_mini::pipeline::{ChunkProcessor, Pipeline};
+use rustseq_mini::wgs_config::WgsConfig;
+use rustseq_mini::{cpu_align, gpu, Aligner, Backend, FileStatus, ReadChunk};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+const READS: usize = 24;
+const READ_LENGTH: usize = 100;
+
+fn data(name: &str) -> String {
+    format!("{}/{}", DATA, name)
+}
+
+fn read_files() -> Vec<String> {
+    vec![data("reads_R1.fastq"), data("reads_R2.fastq")]
+}
+
+// A fresh directory under the system temp directory for one test's outputs
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustseq_mini_{}_{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn aligner(backend: Backend) -> Aligner {
+    Aligner::builder().backend(backend).chunking(ChunkSize::Reads(8)).build().unwrap()
+}
+
+#[test]
+fn pairwise_cpu_scores_as_the_cpu_path() {
+    let aligner = aligner(Backend::Cpu);
+    let (seq1, seq2) = (b"ACGTTGCAACGGTACCTTAG", b"ACGTAGCAACGGTTCCTTAG");
+    assert_eq!(aligner.align(seq1, seq2).unwrap(), cpu_align::cpu_align_bytes(seq1, seq2));
+    let self_score = aligner.align(seq1, seq1).unwrap();
+    assert_eq!(self_score, cpu_align::cpu_align_bytes(seq1, seq1));
+    assert!(self_score > 0);
+    assert_eq!(aligner.align(b"", seq2).unwrap(), 0);
+}
+
+#[test]
+fn pairwise_files_score_each_contig() {
+    let result = aligner(Backend::Cpu).align_files(&data("reads_R1.fastq"), &data("reference.fa")).unwrap();
+    let contigs: Vec<&str> = result.contigs.iter().map(|contig| contig.contig.as_str()).collect();
+    assert_eq!(contigs, ["chr_a", "chr_b"]);
+    assert_eq!(result.contigs.iter().map(|contig| contig.length).collect::<Vec<_>>(), [1200, 800]);
+    assert_eq!(result.contigs.iter().map(|contig| contig.score).sum::<i64>(), result.score as i64);
+    assert!(result.score > 0);
+}
+
+#[test]
+fn pairwise_gpu_matches_cpu() {
+    if !gpu::is_gpu_available() {
+        eprintln!("skipped: no GPU");
+        return;
+    }
+    let (reads, reference) = (data("reads_R1.fastq"), data("reference.fa"));
+    let cpu = aligner(Backend::Cpu).align_files(&reads, &reference).unwrap();
+    let gpu = aligner(Backend::Gpu).align_files(&reads, &reference).unwrap();
+    assert_eq!(gpu.score, cpu.score);
+    assert_eq!(gpu.contigs.iter().map(|contig| contig.score).collect::<Vec<_>>(), cpu.contigs.iter().map(|contig| contig.score).collect::<Vec<_>>());
+}
+
+#[test]
+fn kmer_count_counts_every_kmer() {
+    let k = 21;
+    let output = scratch_dir("kmer_count").join("spectrum.tsv");
+    let mut pipeline = Pipeline::new(ChunkSize::Reads(10));
+    pipeline.register(kmer_spectrum::processor(HashKind::default(), k, 1, &output).unwrap());
+    pipeline.run(&read_files()).unwrap();
+
+    // #file, multiplicity, distinct k-mers: multiplicities times k-mers add up to every k-mer of the file
+    let tsv = std::fs::read_to_string(&output).unwrap();
+    for file in read_files() {
+        let kmers: u64 = tsv.lines().skip(1)
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .filter(|fields| fields[0] == file)
+            .map(|fields| fields[1].parse::<u64>().unwrap() * fields[2].parse::<u64>().unwrap())
+            .sum();
+        assert_eq!(kmers, (READS * (READ_LENGTH - k + 1)) as u64, "{}", file);
+    }
+}
+
+// Reads and bases of each finished file, kept where the test can see them
+// once the pipeline owns the processor
+#[derive(Default)]
+struct ReadCounter {
+    current: (usize, usize),
+    files: Arc<Mutex<Vec<(usize, usize)>>>,
+}
+
+impl ChunkProcessor for ReadCounter {
+    fn name(&self) -> &str {
+        "Read counter"
+    }
+
+    fn start_file(&mut self, _file: &str) -> Result<(), String> {
+        self.current = (0, 0);
+        Ok(())
+    }
+
+    fn process(&mut self, chunk: &ReadChunk) -> Result<(), String> {
+        self.current.0 += chunk.len();
+        self.current.1 += chunk.bases().len();
+        Ok(())
+    }
+
+    fn finish_file(&mut self, _file: &str) -> Result<String, String> {
+        self.files.lock().unwrap().push(self.current);
+        Ok(format!("{} reads, {} bases", self.current.0, self.current.1))
+    }
+}
+
+#[test]
+fn custom_processor_sees_every_read() {
+    let counter = ReadCounter::default();
+    let files = counter.files.clone();
+    let output = scratch_dir("custom_processor").join("spectrum.tsv");
+    let mut pipeline = Pipeline::new(ChunkSize::Reads(5));
+    pipeline.register(Box::new(counter))
+        .register(kmer_spectrum::processor(HashKind::default(), 15, 1, &output).unwrap());
+    pipeline.run(&read_files()).unwrap();
+    assert_eq!(*files.lock().unwrap(), vec![(READS, READS * READ_LENGTH); 2]);
+    assert!(output.exists());
+}
+
+#[test]
+fn wgs_streaming_completes_each_file() {
+    if !gpu::is_gpu_available() {
+        eprintln!("skipped: no GPU");
+        return;
+    }
+    rustseq_mini::output_layout::install(&scratch_dir("wgs_streaming").to_string_lossy()).unwrap();
+    let mut config = WgsConfig::new(DATA, "example");
+    config.input_files = Some(vec!["reads_R1.fastq".to_string(), "reads_R2.fastq".to_string()]);
+    config.aggregates = vec!["max".to_string()];
+    let report = aligner(Backend::Gpu).run_wgs(&config).unwrap();
+    assert_eq!(report.files.len(), 2);
+    for file in &report.files {
+        assert_eq!(file.status, FileStatus::Completed, "{}", file.name());
+        assert_eq!((file.total_reads, file.total_bases), (READS, READS * READ_LENGTH));
+        assert!(file.aggregates["max"] <= file.score as f64);
+    }
+}
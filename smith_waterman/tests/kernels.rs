@@ -0,0 +1,45 @@
+// The kernels against the CPU path at the lengths around tile and work group
+// edges that --verify-kernel checks. The GPU case runs only where OpenCL has a
+// GPU and is skipped elsewhere, with a note on stderr; the CPU path at the
+// launch geometry is checked on its own
+
+use rustseq_mini::aligner::launch_geometry;
+use rustseq_mini::cpu_align::{self, SimdLevel};
+use rustseq_mini::gpu::{self, GpuDevice, GPU_WORK_GROUP_SIZE};
+use rustseq_mini::kernels::{self, KernelVariant};
+use rustseq_mini::seed::Seedable;
+
+// A random sequence and a copy of it with every 7th base redrawn
+fn pair(len: usize, seed: u64) -> (Vec<u8>, Vec<u8>) {
+    let mut rng = Seedable::new(seed);
+    let seq1: Vec<u8> = (0..len).map(|_| rng.base()).collect();
+    let seq2 = seq1.iter().enumerate().map(|(i, &base)| if i % 7 == 0 { rng.base() } else { base }).collect();
+    (seq1, seq2)
+}
+
+#[test]
+fn tiled_kernel_matches_cpu() {
+    if !gpu::is_gpu_available() {
+        eprintln!("skipped: no GPU");
+        return;
+    }
+    let device = gpu::get_gpu_devices().into_iter().next().expect("a GPU device");
+    let checked = kernels::verify_against_cpu(KernelVariant::Tiled, &device).unwrap();
+    assert!(checked > 0);
+}
+
+#[test]
+fn cpu_path_at_launch_geometry() {
+    // A device that takes the preferred work group size whole
+    let device = GpuDevice { name: "test".to_string(), memory_gb: 0.0, max_work_group_size: GPU_WORK_GROUP_SIZE };
+    for (case, len) in [1023, 4097, 65_537].into_iter().enumerate() {
+        let (seq1, seq2) = pair(len, case as u64 + 1);
+        let (work_groups, group_size) = launch_geometry(len, &device);
+        let expected = cpu_align::cpu_align_bytes(&seq1, &seq2);
+        assert_eq!(cpu_align::align_with(&seq1, &seq2, work_groups, group_size, SimdLevel::Scalar), expected, "length {}", len);
+        for level in SimdLevel::supported() {
+            assert_eq!(cpu_align::align_with(&seq1, &seq2, work_groups, group_size, level), expected, "length {} at {}", len, level.name());
+        }
+        assert!(expected > 0, "length {}", len);
+    }
+}
@@ -0,0 +1,44 @@
+// --output-dir with spaces and non-ASCII characters: the layout is created
+// under it and the manifest's paths and file names come back whole
+// The layout is process-wide (first install wins), so this is a test binary of
+// its own
+
+use rustseq_mini::{output_layout, paths};
+
+#[test]
+fn non_ascii_output_dir_round_trips() {
+    let dir = std::env::temp_dir().join(format!("rustseq_mini é サンプル {}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    output_layout::install(&dir.to_string_lossy()).unwrap();
+    assert_eq!(output_layout::get(), Some(dir.as_path()));
+
+    let input = paths::join("runs/NA12878 été", "reads é 1.fastq.gz");
+    output_layout::record_file_order(1, "input", std::slice::from_ref(&input)).unwrap();
+    output_layout::record_file_order(2, "size", &[]).unwrap();
+
+    let manifest = output_layout::load_manifest(&dir.join("manifest.json")).unwrap();
+    assert_eq!(manifest.results_dir, dir.join("results"));
+    assert_eq!(manifest.checkpoints_dir, dir.join("checkpoints"));
+    assert_eq!(manifest.logs_dir, dir.join("logs"));
+    for sub in [&manifest.results_dir, &manifest.checkpoints_dir, &manifest.logs_dir] {
+        assert!(sub.is_dir(), "{}", sub.display());
+    }
+    // Each run's order is kept under its number; the latest is also at the top
+    assert_eq!(manifest.file_order.unwrap().order, "size");
+    assert_eq!(manifest.runs.iter().map(|entry| entry.run).collect::<Vec<_>>(), [1, 2]);
+    let files = manifest.runs[0].file_order.clone().unwrap().files;
+    assert_eq!(files, [input]);
+    assert_eq!(paths::file_name(&files[0]), "reads é 1.fastq.gz");
+
+    assert_eq!(output_layout::result_file("benchmark_results.json"), dir.join("results").join("benchmark_results.json"));
+    assert_eq!(output_layout::checkpoint_file("wgs_checkpoint.json"), dir.join("checkpoints").join("wgs_checkpoint.json"));
+
+    // Another directory is refused and gets no manifest; the same one again is fine
+    let other = std::env::temp_dir().join(format!("rustseq_mini_other_{}", std::process::id()));
+    assert!(output_layout::install(&other.to_string_lossy()).is_err());
+    assert!(!other.join("manifest.json").exists());
+    assert_eq!(output_layout::get(), Some(dir.as_path()));
+    output_layout::install(&dir.to_string_lossy()).unwrap();
+    assert!(!dir.join("manifest.json.tmp").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
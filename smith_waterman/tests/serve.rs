@@ -0,0 +1,135 @@
+// `serve` over TCP: requests and replies are JSON lines, bad requests get an
+// error, and each job runs as a run of its own. On the CPU backend a job fails
+// (WGS needs the GPU) after claiming its run; with a GPU, two jobs of the
+// bundled data run side by side through the scheduler and both complete.
+// Searches from several clients at once go through the server's QueryBatcher
+// and each client gets its own hits
+// Jobs claim runs under the output layout, which is process-wide, so this is a
+// test binary of its own
+
+use rustseq_mini::gpu_scheduler::GpuScheduler;
+use rustseq_mini::hit_limits::HitLimits;
+use rustseq_mini::query_batcher::QueryBatcher;
+use rustseq_mini::sequence_db::{SearchOptions, SequenceDb};
+use rustseq_mini::serve::{JobState, Server};
+use rustseq_mini::wgs_config::WgsConfig;
+use rustseq_mini::{gpu, output_layout, Aligner, Backend};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/data");
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(server: &Arc<Server>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = server.clone();
+        std::thread::spawn(move || server.serve(listener));
+        let writer = TcpStream::connect(address).unwrap();
+        Self { reader: BufReader::new(writer.try_clone().unwrap()), writer }
+    }
+
+    fn send(&mut self, request: &str) -> Value {
+        writeln!(self.writer, "{}", request).unwrap();
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+fn job(name: &str, priority: u32) -> String {
+    json!({"op": "submit", "name": name, "data_dir": DATA, "input_files": ["reads_R1.fastq", "reads_R2.fastq"], "priority": priority}).to_string()
+}
+
+#[test]
+fn jobs_run_as_runs_of_their_own() {
+    let dir = std::env::temp_dir().join(format!("rustseq_mini_serve_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    output_layout::install(&dir.to_string_lossy()).unwrap();
+
+    let aligner = Aligner::builder().backend(Backend::Cpu).build().unwrap();
+    let server = Server::new(aligner, WgsConfig::new(DATA, "serve"), GpuScheduler::new(2), 1);
+    let mut client = Client::connect(&server);
+
+    assert_eq!(client.send("not json")["ok"], false);
+    assert_eq!(client.send(r#"{"op": "resume", "job": 1}"#)["ok"], false);
+    let rejected = client.send(&job("zero", 0));
+    assert_eq!(rejected["ok"], false);
+    assert!(rejected["error"].as_str().unwrap().contains("priority"), "{}", rejected);
+
+    let submitted = client.send(&job("cpu", 1));
+    assert_eq!(submitted["ok"], true, "{}", submitted);
+    let id = submitted["job"].as_u64().unwrap();
+    let finished = server.wait(id).unwrap();
+    assert_eq!(finished.state, JobState::Failed);
+    assert!(finished.error.as_deref().unwrap().contains("GPU backend"), "{:?}", finished.error);
+    assert!(finished.run.is_some());
+
+    let status = client.send(&json!({"op": "status", "job": id}).to_string());
+    assert_eq!(status["jobs"][0]["state"], "failed", "{}", status);
+    assert_eq!(status["jobs"][0]["run"], finished.run.unwrap());
+    assert_eq!(client.send(&json!({"op": "cancel", "job": id}).to_string())["ok"], false);
+    assert_eq!(client.send(r#"{"op": "status", "job": 99}"#)["ok"], false);
+    assert_eq!(client.send(r#"{"op": "jobs"}"#)["jobs"].as_array().unwrap().len(), 1);
+    let search = client.send(r#"{"op": "search", "sequence": "ACGT"}"#);
+    assert!(search["error"].as_str().unwrap().contains("--db"), "{}", search);
+
+    if !gpu::is_gpu_available() {
+        eprintln!("skipped the GPU jobs: no GPU");
+        let _ = std::fs::remove_dir_all(&dir);
+        return;
+    }
+    let aligner = Aligner::builder().backend(Backend::Gpu).build().unwrap();
+    let server = Server::new(aligner, WgsConfig::new(DATA, "serve"), GpuScheduler::new(1), 2);
+    let mut client = Client::connect(&server);
+    let ids: Vec<u64> = [("high", 2), ("low", 1)].iter()
+        .map(|(name, priority)| client.send(&job(name, *priority))["job"].as_u64().unwrap())
+        .collect();
+    let statuses: Vec<_> = ids.iter().map(|id| server.wait(*id).unwrap()).collect();
+    for status in &statuses {
+        assert_eq!(status.state, JobState::Completed, "{:?}", status.error);
+        assert_eq!((status.files_done, status.total_files), (2, 2));
+        assert!(status.launches > 0);
+    }
+    assert_ne!(statuses[0].run, statuses[1].run);
+    assert_eq!(statuses[0].score, statuses[1].score);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn searches_share_launches() {
+    let reference = format!("{}/reference.fa", DATA);
+    let db = Arc::new(SequenceDb::build(&reference, 11).unwrap());
+    let query = std::fs::read_to_string(&reference).unwrap().lines().nth(1).unwrap()[..40].to_string();
+    let options = SearchOptions { min_shared_kmers: 0, limits: HitLimits::new(1, 1).unwrap(), use_gpu: false, early_exit: 0 };
+    let batcher = QueryBatcher::new(Arc::clone(&db), options, 16, Duration::from_millis(200)).unwrap();
+    let aligner = Aligner::builder().backend(Backend::Cpu).build().unwrap();
+    let server = Server::with_search(aligner, WgsConfig::new(DATA, "serve"), GpuScheduler::new(1), 1, db, batcher);
+
+    let clients: Vec<_> = (0..4).map(|_| {
+        let (server, query) = (Arc::clone(&server), query.clone());
+        std::thread::spawn(move || Client::connect(&server).send(&json!({"op": "search", "sequence": query}).to_string()))
+    }).collect();
+    for client in clients {
+        let reply = client.join().unwrap();
+        assert_eq!(reply["ok"], true, "{}", reply);
+        let hits = reply["hits"].as_array().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["target"], "chr_a");
+        assert!(hits[0]["score"].as_i64().unwrap() >= 40);
+    }
+    let stats = server.search_stats().unwrap();
+    assert_eq!(stats.queries, 4);
+    assert!(stats.launches < 4, "{:?}", stats);
+
+    let mut client = Client::connect(&server);
+    assert_eq!(client.send(r#"{"op": "search", "sequence": " "}"#)["ok"], false);
+}